@@ -0,0 +1,92 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant}
+};
+
+// Maximum users remembered per chat
+const MAX_USERS_PER_CHAT: usize = 200;
+// Users that didn't talk since this duration are not considered active anymore
+const ACTIVITY_WINDOW: Duration = Duration::from_secs(60 * 60 * 24);
+// Messages remembered per user, enough for the strictest rain requirement
+const MAX_MESSAGES_PER_USER: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct ActiveUser {
+    pub id: u64,
+    pub name: String,
+    last_seen: Instant,
+    // Time of its latest messages, most recent first
+    messages: VecDeque<Instant>
+}
+
+impl ActiveUser {
+    // Messages written by the user in the activity window
+    pub fn message_count(&self) -> u32 {
+        self.messages.iter()
+            .take_while(|sent| sent.elapsed() < ACTIVITY_WINDOW)
+            .count() as u32
+    }
+}
+
+// Track the most recently active users of each chat
+// Telegram has no API to list the members of a group,
+// so we remember who wrote a message in it instead
+pub struct ActivityTracker {
+    // Users of each chat, most recent first
+    chats: Mutex<HashMap<i64, VecDeque<ActiveUser>>>
+}
+
+impl ActivityTracker {
+    pub fn new() -> Self {
+        Self {
+            chats: Mutex::new(HashMap::new())
+        }
+    }
+
+    // Record a message sent by a user in a chat
+    // Returns true if the user wasn't remembered in this chat yet
+    pub fn record(&self, chat_id: i64, user_id: u64, name: String) -> bool {
+        let mut chats = self.chats.lock().expect("activity tracker lock");
+        let users = chats.entry(chat_id).or_default();
+        let now = Instant::now();
+        let (mut messages, new) = match users.iter().position(|user| user.id == user_id) {
+            Some(index) => (users.remove(index).map(|user| user.messages).unwrap_or_default(), false),
+            None => (VecDeque::new(), true)
+        };
+        messages.push_front(now);
+        messages.truncate(MAX_MESSAGES_PER_USER);
+
+        users.push_front(ActiveUser {
+            id: user_id,
+            name,
+            last_seen: now,
+            messages
+        });
+        users.truncate(MAX_USERS_PER_CHAT);
+
+        new
+    }
+
+    // Messages written by a user in the chat during the activity window
+    pub fn message_count(&self, chat_id: i64, user_id: u64) -> u32 {
+        let chats = self.chats.lock().expect("activity tracker lock");
+        chats.get(&chat_id)
+            .and_then(|users| users.iter().find(|user| user.id == user_id))
+            .map_or(0, ActiveUser::message_count)
+    }
+
+    // Get up to `count` users that were active recently in the chat, most recent first
+    pub fn recent_users(&self, chat_id: i64, count: usize, exclude: u64) -> Vec<ActiveUser> {
+        let chats = self.chats.lock().expect("activity tracker lock");
+        chats.get(&chat_id)
+            .map(|users| users.iter()
+                .take_while(|user| user.last_seen.elapsed() < ACTIVITY_WINDOW)
+                .filter(|user| user.id != exclude)
+                .take(count)
+                .cloned()
+                .collect()
+            )
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,529 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::{bail, Result};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path,
+        Query,
+        Request,
+        State
+    },
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json,
+    Router
+};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::TcpListener,
+    sync::broadcast::{self, error::RecvError}
+};
+use xelis_common::crypto::{hash, Hash};
+
+use crate::{
+    amount::{parse_amount, Amount},
+    service::{parse_matrix_user, parse_slack_user, BotEvent, Movement, ServiceError, StatsPeriod, UserApplication, WalletService}
+};
+
+// Journal entries answered when no limit is given, and at most
+const DEFAULT_LEDGER_LIMIT: usize = 100;
+const MAX_LEDGER_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct AdminApiConfig {
+    pub address: SocketAddr,
+    // Expected in the Authorization header as a bearer token
+    pub token: String
+}
+
+// Error answered as {"error": "..."}
+struct ApiError {
+    status: StatusCode,
+    message: String
+}
+
+impl ApiError {
+    fn bad_request(message: impl ToString) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message: message.to_string()
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        error!("Error in the admin API: {:?}", e);
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: "Internal error".to_string()
+        }
+    }
+}
+
+impl From<ServiceError> for ApiError {
+    fn from(e: ServiceError) -> Self {
+        match e {
+            ServiceError::Any(e) => e.into(),
+            ServiceError::WalletError(e) => anyhow::Error::from(e).into(),
+            e => Self::bad_request(e)
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(ErrorBody { error: self.message })).into_response()
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+struct ApiState {
+    service: WalletService,
+    // Only the hash of the token is kept, so comparing it leaks nothing about the token
+    token_hash: [u8; 32]
+}
+
+// User of the API paths: /users/{platform}/{id}
+// Matrix and Slack users are given by their user ID on the platform or the ID the bot knows them by
+fn parse_user(platform: &str, id: &str) -> Result<UserApplication, ApiError> {
+    let invalid = || ApiError::bad_request(format!("Invalid user ID '{}'", id));
+    match platform {
+        "discord" => Ok(UserApplication::Discord(id.parse().map_err(|_| invalid())?)),
+        "telegram" => Ok(UserApplication::Telegram(id.parse().map_err(|_| invalid())?)),
+        "matrix" => parse_matrix_user(id).ok_or_else(invalid),
+        "slack" => parse_slack_user(id).ok_or_else(invalid),
+        "twitch" => Ok(UserApplication::Twitch(id.parse().map_err(|_| invalid())?)),
+        _ => Err(ApiError::bad_request(format!("Unknown platform '{}', expected discord, telegram, matrix, slack or twitch", platform)))
+    }
+}
+
+fn platform_name(user: &UserApplication) -> (&'static str, u64) {
+    match user {
+        UserApplication::Discord(id) => ("discord", *id),
+        UserApplication::Telegram(id) => ("telegram", *id),
+        UserApplication::Matrix(id) => ("matrix", *id),
+        UserApplication::Slack(id) => ("slack", *id),
+        UserApplication::Twitch(id) => ("twitch", *id)
+    }
+}
+
+// Balance changes require the instance to process them
+fn check_leader(state: &ApiState) -> Result<(), ApiError> {
+    if state.service.is_leader() {
+        Ok(())
+    } else {
+        Err(ApiError {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: "This instance is on standby, send the request to the leader".to_string()
+        })
+    }
+}
+
+// Every route requires "Authorization: Bearer <token>"
+// Browsers can't set headers on a WebSocket, so the token can also be given as ?token=
+async fn authorize(State(state): State<Arc<ApiState>>, request: Request, next: Next) -> Response {
+    let token = request.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| request.uri().query()
+            .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("token="))));
+
+    match token {
+        Some(token) if *hash(token.as_bytes()).as_bytes() == state.token_hash => next.run(request).await,
+        _ => {
+            warn!("Unauthorized request to the admin API: {} {}", request.method(), request.uri().path());
+            ApiError {
+                status: StatusCode::UNAUTHORIZED,
+                message: "Missing or invalid token".to_string()
+            }.into_response()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BalanceBody {
+    asset: String,
+    name: String,
+    decimals: u8,
+    // Atomic units
+    amount: u64
+}
+
+#[derive(Serialize)]
+struct UserBody {
+    platform: &'static str,
+    id: u64,
+    frozen: bool,
+    balances: Vec<BalanceBody>
+}
+
+async fn user_body(state: &ApiState, user: &UserApplication) -> Result<UserBody, ApiError> {
+    let (platform, id) = platform_name(user);
+    let balances = state.service.get_balances_for_user(user).await?
+        .into_iter()
+        .map(|balance| BalanceBody {
+            asset: balance.asset.to_string(),
+            name: balance.name,
+            decimals: balance.decimals,
+            amount: balance.amount
+        })
+        .collect();
+
+    Ok(UserBody {
+        platform,
+        id,
+        frozen: state.service.is_frozen(user).await,
+        balances
+    })
+}
+
+// GET /users/{platform}/{id}
+async fn get_user(State(state): State<Arc<ApiState>>, Path((platform, id)): Path<(String, String)>) -> ApiResult<UserBody> {
+    let user = parse_user(&platform, &id)?;
+    Ok(Json(user_body(&state, &user).await?))
+}
+
+#[derive(Serialize)]
+struct UserBalanceBody {
+    platform: &'static str,
+    id: u64,
+    // XEL in atomic units
+    balance: u64
+}
+
+// GET /users, XEL balance of every user, highest first
+async fn get_users(State(state): State<Arc<ApiState>>) -> ApiResult<Vec<UserBalanceBody>> {
+    let users = state.service.get_users_balances().await?
+        .into_iter()
+        .map(|(user, balance)| {
+            let (platform, id) = platform_name(&user);
+            UserBalanceBody { platform, id, balance }
+        })
+        .collect();
+
+    Ok(Json(users))
+}
+
+#[derive(Deserialize)]
+struct AdjustmentRequest {
+    // XEL, with decimals
+    amount: String,
+    // Kept in the journal with the entry
+    reason: String
+}
+
+// POST /users/{platform}/{id}/credit and /users/{platform}/{id}/debit
+async fn adjust_balance(state: &ApiState, platform: &str, id: &str, request: AdjustmentRequest, credit: bool) -> ApiResult<UserBody> {
+    check_leader(state)?;
+    let user = parse_user(platform, id)?;
+    let amount = parse_amount(&request.amount).and_then(Amount::exact).map_err(ApiError::bad_request)?;
+
+    info!("Admin API {} {} atomic XEL of {:?}: {}", if credit { "credits" } else { "debits" }, amount, user, request.reason.trim());
+    if credit {
+        state.service.add_balance(&user, amount, &request.reason).await?;
+    } else {
+        state.service.remove_balance(&user, amount, &request.reason).await?;
+    }
+
+    Ok(Json(user_body(state, &user).await?))
+}
+
+async fn credit_user(State(state): State<Arc<ApiState>>, Path((platform, id)): Path<(String, String)>, Json(request): Json<AdjustmentRequest>) -> ApiResult<UserBody> {
+    adjust_balance(&state, &platform, &id, request, true).await
+}
+
+async fn debit_user(State(state): State<Arc<ApiState>>, Path((platform, id)): Path<(String, String)>, Json(request): Json<AdjustmentRequest>) -> ApiResult<UserBody> {
+    adjust_balance(&state, &platform, &id, request, false).await
+}
+
+#[derive(Deserialize)]
+struct LedgerQuery {
+    // Only the entries of this user, both are required together
+    platform: Option<String>,
+    id: Option<String>,
+    // Id of the first entry, to page through the journal
+    from: Option<u64>,
+    limit: Option<usize>
+}
+
+#[derive(Serialize)]
+struct MovementBody {
+    id: u64,
+    timestamp: u64,
+    platform: &'static str,
+    user: u64,
+    kind: &'static str,
+    asset: String,
+    credit: bool,
+    // Atomic units
+    amount: u64,
+    before: u64,
+    after: u64,
+    note: Option<String>
+}
+
+impl MovementBody {
+    fn new(user: &UserApplication, movement: Movement) -> Self {
+        let (platform, id) = platform_name(user);
+        Self {
+            id: movement.id,
+            timestamp: movement.timestamp,
+            platform,
+            user: id,
+            kind: movement.kind.name(),
+            asset: movement.asset.to_string(),
+            credit: movement.is_credit(),
+            amount: movement.amount(),
+            before: movement.before,
+            after: movement.after,
+            note: movement.note
+        }
+    }
+}
+
+// GET /ledger?platform=&id=&from=&limit=, journal entries in order
+async fn get_ledger(State(state): State<Arc<ApiState>>, Query(query): Query<LedgerQuery>) -> ApiResult<Vec<MovementBody>> {
+    let user = match (query.platform.as_deref(), query.id.as_deref()) {
+        (Some(platform), Some(id)) => Some(parse_user(platform, id)?),
+        (None, None) => None,
+        _ => return Err(ApiError::bad_request("platform and id must be given together"))
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LEDGER_LIMIT).min(MAX_LEDGER_LIMIT);
+
+    let movements = state.service.get_movements(user.as_ref(), query.from.unwrap_or(0), limit).await?
+        .into_iter()
+        .map(|(user, movement)| MovementBody::new(&user, movement))
+        .collect();
+
+    Ok(Json(movements))
+}
+
+#[derive(Serialize)]
+struct UserRef {
+    platform: &'static str,
+    id: u64
+}
+
+impl UserRef {
+    fn new(user: &UserApplication) -> Self {
+        let (platform, id) = platform_name(user);
+        Self { platform, id }
+    }
+}
+
+#[derive(Serialize)]
+struct EventBody {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    timestamp: u64,
+    user: UserRef,
+    recipient: Option<UserRef>,
+    asset: String,
+    // Atomic units
+    amount: u64,
+    tx_hash: Option<String>
+}
+
+impl EventBody {
+    fn new(event: &BotEvent) -> Self {
+        Self {
+            kind: event.kind.name(),
+            timestamp: event.timestamp,
+            user: UserRef::new(&event.user),
+            recipient: event.recipient.as_ref().map(UserRef::new),
+            asset: event.asset.to_string(),
+            amount: event.amount,
+            tx_hash: event.tx_hash.as_ref().map(Hash::to_string)
+        }
+    }
+}
+
+// GET /events, WebSocket streaming the activity of the bot as it happens
+async fn events(State(state): State<Arc<ApiState>>, upgrade: WebSocketUpgrade) -> Response {
+    // Subscribed before the upgrade, so no event is missed in between
+    let receiver = state.service.subscribe_bot_events();
+    upgrade.on_upgrade(move |socket| stream_events(socket, receiver))
+}
+
+// Send each event as a JSON text message until the client leaves
+async fn stream_events(mut socket: WebSocket, mut receiver: broadcast::Receiver<BotEvent>) {
+    debug!("Client subscribed to the bot events");
+    loop {
+        let event = tokio::select! {
+            event = receiver.recv() => event,
+            message = socket.recv() => match message {
+                // Pings are answered by the socket, anything else the client sends is ignored
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue
+            }
+        };
+
+        let event = match event {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("A subscriber of the bot events is too slow, {} events were dropped", missed);
+                continue;
+            },
+            Err(RecvError::Closed) => break
+        };
+
+        let body = match serde_json::to_string(&EventBody::new(&event)) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Couldn't encode the bot event {:?}: {}", event, e);
+                continue;
+            }
+        };
+
+        if socket.send(Message::text(body)).await.is_err() {
+            break;
+        }
+    }
+
+    debug!("Client unsubscribed from the bot events");
+}
+
+#[derive(Deserialize)]
+struct WithdrawalsRequest {
+    locked: bool
+}
+
+#[derive(Serialize)]
+struct WithdrawalsBody {
+    locked: bool
+}
+
+// GET /withdrawals
+async fn get_withdrawals(State(state): State<Arc<ApiState>>) -> ApiResult<WithdrawalsBody> {
+    Ok(Json(WithdrawalsBody { locked: state.service.is_withdraw_locked() }))
+}
+
+// POST /withdrawals, lock or unlock the withdrawals of all users
+async fn set_withdrawals(State(state): State<Arc<ApiState>>, Json(request): Json<WithdrawalsRequest>) -> ApiResult<WithdrawalsBody> {
+    check_leader(&state)?;
+    info!("Admin API {} the withdrawals", if request.locked { "locks" } else { "unlocks" });
+    state.service.set_withdraw_locked(request.locked);
+
+    Ok(Json(WithdrawalsBody { locked: request.locked }))
+}
+
+#[derive(Deserialize)]
+struct StatsQuery {
+    // day, week or all, all by default
+    period: Option<String>
+}
+
+#[derive(Serialize)]
+struct StatsBody {
+    online: bool,
+    leader: bool,
+    topoheight: u64,
+    withdrawals_locked: bool,
+    users: usize,
+    // XEL in atomic units
+    wallet_balance: u64,
+    total_owed: u64,
+    // Wallet balance over what is owed, null while nothing is owed
+    solvency_ratio: Option<f64>,
+    unconfirmed: usize,
+    pending_notifications: usize,
+    stuck_withdrawals: usize,
+    period: String,
+    // Tips of the period across all communities
+    tips: u64,
+    tipped: u64,
+    tippers: usize
+}
+
+// GET /stats?period=
+async fn get_stats(State(state): State<Arc<ApiState>>, Query(query): Query<StatsQuery>) -> ApiResult<StatsBody> {
+    let period_name = query.period.unwrap_or_else(|| "all".to_string());
+    let period = match period_name.as_str() {
+        "day" => StatsPeriod::current_day(),
+        "week" => StatsPeriod::current_week(),
+        "all" => StatsPeriod::AllTime,
+        _ => return Err(ApiError::bad_request(format!("Unknown period '{}', expected day, week or all", period_name)))
+    };
+
+    let service = &state.service;
+    let solvency = service.get_solvency().await?;
+    let queues = service.get_queue_status();
+    let stats = service.get_tip_stats(period, None).await?;
+
+    Ok(Json(StatsBody {
+        online: service.is_wallet_online().await,
+        leader: service.is_leader(),
+        topoheight: service.get_wallet_topoheight().await?,
+        withdrawals_locked: service.is_withdraw_locked(),
+        users: service.get_users_count().await?,
+        wallet_balance: solvency.assets,
+        total_owed: solvency.liabilities,
+        solvency_ratio: solvency.ratio(),
+        unconfirmed: queues.unconfirmed,
+        pending_notifications: queues.notifications,
+        stuck_withdrawals: queues.stuck_withdrawals,
+        period: period_name,
+        tips: stats.values().map(|stats| stats.tips).sum(),
+        tipped: stats.values().map(|stats| stats.tipped).sum(),
+        tippers: stats.values().filter(|stats| stats.tips > 0).count()
+    }))
+}
+
+// HTTP API for the operators, to build dashboards without the interactive prompt
+// Every route is under /api and answers JSON, amounts are in atomic units
+pub struct AdminApi {
+    config: AdminApiConfig
+}
+
+impl AdminApi {
+    pub fn new(config: AdminApiConfig) -> Self {
+        Self { config }
+    }
+
+    // Bind the address, then answer the requests in the background
+    pub async fn start(self, service: WalletService) -> Result<()> {
+        if self.config.token.trim().is_empty() {
+            bail!("The admin API token can't be empty");
+        }
+
+        let listener = TcpListener::bind(self.config.address).await?;
+        info!("Admin API listening on {}", self.config.address);
+
+        let state = Arc::new(ApiState {
+            service,
+            token_hash: *hash(self.config.token.as_bytes()).as_bytes()
+        });
+        let routes = Router::new()
+            .route("/users", get(get_users))
+            .route("/users/{platform}/{id}", get(get_user))
+            .route("/users/{platform}/{id}/credit", post(credit_user))
+            .route("/users/{platform}/{id}/debit", post(debit_user))
+            .route("/ledger", get(get_ledger))
+            .route("/withdrawals", get(get_withdrawals).post(set_withdrawals))
+            .route("/stats", get(get_stats))
+            .route("/events", get(events))
+            .route_layer(middleware::from_fn_with_state(Arc::clone(&state), authorize))
+            .with_state(state);
+        let router = Router::new().nest("/api", routes);
+
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                warn!("Admin API stopped: {}", e);
+            }
+        });
+
+        Ok(())
+    }
+}
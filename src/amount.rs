@@ -0,0 +1,189 @@
+use thiserror::Error;
+use xelis_common::config::COIN_DECIMALS;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AmountError {
+    #[error("No amount provided")]
+    Empty,
+    #[error("Invalid character '{}' in amount", .0)]
+    InvalidCharacter(char),
+    #[error("Amount has more than one decimal separator")]
+    MultipleSeparators,
+    #[error("Amount has a ',', use '.' as decimal separator")]
+    AmbiguousSeparator,
+    #[error("{asset} only supports up to {decimals} decimals")]
+    TooPrecise {
+        asset: String,
+        decimals: u8
+    },
+    #[error("Amount is too big")]
+    Overflow,
+}
+
+// Amount parsed from a user input
+// If the input had more decimals than supported, the value is rounded
+// and the user must confirm it before it's used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Amount {
+    // Value in atomic units
+    pub value: u64,
+    // Was the input rounded to fit the atomic precision
+    pub rounded: bool,
+    // Precision the input was parsed with
+    pub decimals: u8
+}
+
+impl Amount {
+    // Get the value of a XEL amount only if no rounding happened
+    pub fn exact(self) -> Result<u64, AmountError> {
+        self.exact_for("XELIS")
+    }
+
+    // Same as `exact` for an amount of another asset, named in the error
+    pub fn exact_for(self, asset: &str) -> Result<u64, AmountError> {
+        if self.rounded {
+            return Err(AmountError::TooPrecise {
+                asset: asset.to_string(),
+                decimals: self.decimals
+            });
+        }
+
+        Ok(self.value)
+    }
+}
+
+// Keywords accepted instead of an amount, relative to the balance of the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmountKeyword {
+    // "all" or "max"
+    All,
+    Half
+}
+
+impl AmountKeyword {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "all" | "max" => Some(AmountKeyword::All),
+            "half" => Some(AmountKeyword::Half),
+            _ => None
+        }
+    }
+
+    // Part of the available amount requested
+    pub fn of(&self, available: u64) -> u64 {
+        match self {
+            AmountKeyword::All => available,
+            AmountKeyword::Half => available / 2
+        }
+    }
+}
+
+// Decimal digits added by a multiplier suffix, such as "k" in "1.5k"
+fn suffix_shift(suffix: char) -> Option<usize> {
+    match suffix.to_ascii_lowercase() {
+        'k' => Some(3),
+        'm' => Some(6),
+        _ => None
+    }
+}
+
+// Check if a unit following an amount is a multiplier suffix
+pub fn is_amount_suffix(unit: &str) -> bool {
+    let mut chars = unit.chars();
+    chars.next().and_then(suffix_shift).is_some() && chars.next().is_none()
+}
+
+// Parse a user input such as "1.5", "0.00000001" or "2.5k" directly into atomic units
+// No floating point is involved: extra decimals are rounded half up
+// and reported through `Amount::rounded`
+pub fn parse_amount(input: &str) -> Result<Amount, AmountError> {
+    parse_amount_with_decimals(input, COIN_DECIMALS)
+}
+
+// Same as `parse_amount` for an asset with its own precision
+pub fn parse_amount_with_decimals(input: &str, decimals: u8) -> Result<Amount, AmountError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(AmountError::Empty);
+    }
+
+    // "k" and "m" move the decimal separator to the right
+    let (input, shift) = match input.chars().last().and_then(suffix_shift) {
+        Some(shift) => (input[..input.len() - 1].trim_end(), shift),
+        None => (input, 0)
+    };
+
+    // "1,000" would be read as 1 by some and 1000 by others, so only "." is accepted
+    if input.contains(',') {
+        return Err(AmountError::AmbiguousSeparator);
+    }
+
+    let mut parts = input.split('.');
+    let integer = parts.next().unwrap_or_default();
+    let fraction_digits = parts.next().unwrap_or_default();
+    if parts.next().is_some() {
+        return Err(AmountError::MultipleSeparators);
+    }
+
+    if integer.is_empty() && fraction_digits.is_empty() {
+        return Err(AmountError::Empty);
+    }
+
+    if let Some(c) = integer.chars().chain(fraction_digits.chars()).find(|c| !c.is_ascii_digit()) {
+        return Err(AmountError::InvalidCharacter(c));
+    }
+
+    let (moved, fraction_digits) = fraction_digits.split_at(fraction_digits.len().min(shift));
+    let integer = format!("{}{}{}", integer, moved, "0".repeat(shift - moved.len()));
+
+    let mut value: u64 = 0;
+    for c in integer.chars() {
+        value = value.checked_mul(10)
+            .and_then(|v| v.checked_add(c as u64 - '0' as u64))
+            .ok_or(AmountError::Overflow)?;
+    }
+    let unit = 10u64.checked_pow(decimals as u32).ok_or(AmountError::Overflow)?;
+    value = value.checked_mul(unit).ok_or(AmountError::Overflow)?;
+
+    // Pad the decimals to the atomic precision
+    let (kept, dropped) = fraction_digits.split_at(fraction_digits.len().min(decimals as usize));
+    let mut fraction: u64 = 0;
+    for i in 0..decimals as usize {
+        let digit = kept.as_bytes().get(i).map(|b| (b - b'0') as u64).unwrap_or(0);
+        fraction = fraction * 10 + digit;
+    }
+
+    // Round half up using the first digit that doesn't fit
+    let rounded = dropped.bytes().any(|b| b != b'0');
+    if dropped.as_bytes().first().is_some_and(|b| *b >= b'5') {
+        fraction += 1;
+    }
+
+    let value = value.checked_add(fraction).ok_or(AmountError::Overflow)?;
+    Ok(Amount { value, rounded, decimals })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_decimals_and_suffixes() {
+        assert_eq!(parse_amount_with_decimals("1.5", 8).unwrap(), Amount { value: 150_000_000, rounded: false, decimals: 8 });
+        assert_eq!(parse_amount_with_decimals("2.5k", 2).unwrap().value, 250_000);
+        assert_eq!(parse_amount_with_decimals("0.005", 2).unwrap(), Amount { value: 1, rounded: true, decimals: 2 });
+    }
+
+    #[test]
+    fn rejects_comma_separator() {
+        assert_eq!(parse_amount_with_decimals("1,000", 8), Err(AmountError::AmbiguousSeparator));
+        assert_eq!(parse_amount_with_decimals("1,5", 8), Err(AmountError::AmbiguousSeparator));
+    }
+
+    #[test]
+    fn too_precise_names_the_asset() {
+        let error = parse_amount_with_decimals("0.005", 2).and_then(|amount| amount.exact_for("USDT")).unwrap_err();
+        assert_eq!(error, AmountError::TooPrecise { asset: "USDT".to_string(), decimals: 2 });
+        assert_eq!(error.to_string(), "USDT only supports up to 2 decimals");
+    }
+}
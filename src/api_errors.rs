@@ -0,0 +1,312 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant}
+};
+
+use log::{info, warn};
+use matrix_sdk::{Error as MatrixError, HttpError as MatrixHttpError};
+use poise::serenity_prelude::{HttpError, SerenityError};
+use teloxide::{ApiError, RequestError};
+
+use crate::{slack_api::SlackApiError, twitch_api::TwitchApiError};
+
+// Errors of a platform are counted over this window to detect a spike
+const SPIKE_WINDOW: Duration = Duration::from_secs(60);
+// Errors of a platform within the window that start the backoff
+const SPIKE_THRESHOLD: usize = 30;
+// How long the notifications stay reduced after the last spike
+const BACKOFF_DURATION: Duration = Duration::from_secs(5 * 60);
+
+// Platform whose API answered a request with an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Discord,
+    Telegram,
+    Matrix,
+    Slack,
+    Twitch
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Platform::Discord => write!(f, "Discord"),
+            Platform::Telegram => write!(f, "Telegram"),
+            Platform::Matrix => write!(f, "Matrix"),
+            Platform::Slack => write!(f, "Slack"),
+            Platform::Twitch => write!(f, "Twitch")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiErrorKind {
+    // Too many requests, the platform asked us to wait
+    RateLimited,
+    // The bot is blocked, kicked or lacks the permission
+    Forbidden,
+    // The user or chat doesn't exist anymore
+    UnknownUser,
+    // The platform couldn't be reached
+    Network,
+    Other
+}
+
+impl ApiErrorKind {
+    pub const ALL: [ApiErrorKind; 5] = [ApiErrorKind::RateLimited, ApiErrorKind::Forbidden, ApiErrorKind::UnknownUser, ApiErrorKind::Network, ApiErrorKind::Other];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApiErrorKind::RateLimited => "rate limited",
+            ApiErrorKind::Forbidden => "forbidden",
+            ApiErrorKind::UnknownUser => "unknown user",
+            ApiErrorKind::Network => "network",
+            ApiErrorKind::Other => "other"
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            ApiErrorKind::RateLimited => 0,
+            ApiErrorKind::Forbidden => 1,
+            ApiErrorKind::UnknownUser => 2,
+            ApiErrorKind::Network => 3,
+            ApiErrorKind::Other => 4
+        }
+    }
+
+    pub fn from_telegram(error: &RequestError) -> Self {
+        match error {
+            RequestError::RetryAfter(_) => ApiErrorKind::RateLimited,
+            RequestError::Network(_) | RequestError::Io(_) => ApiErrorKind::Network,
+            RequestError::Api(ApiError::ChatNotFound | ApiError::UserNotFound | ApiError::UserDeactivated) => ApiErrorKind::UnknownUser,
+            // Telegram prefixes its permission errors, most of them have no variant
+            RequestError::Api(e) => {
+                let description = e.to_string();
+                if description.starts_with("Forbidden") || description.starts_with("Unauthorized") {
+                    ApiErrorKind::Forbidden
+                } else {
+                    ApiErrorKind::Other
+                }
+            },
+            _ => ApiErrorKind::Other
+        }
+    }
+
+    pub fn from_discord(error: &SerenityError) -> Self {
+        match error {
+            SerenityError::Http(HttpError::Request(_)) | SerenityError::Io(_) | SerenityError::Gateway(_) | SerenityError::Tungstenite(_) => ApiErrorKind::Network,
+            SerenityError::Http(e) => match e.status_code().map(|status| status.as_u16()) {
+                Some(429) => ApiErrorKind::RateLimited,
+                Some(401 | 403) => ApiErrorKind::Forbidden,
+                Some(404) => ApiErrorKind::UnknownUser,
+                _ => ApiErrorKind::Other
+            },
+            _ => ApiErrorKind::Other
+        }
+    }
+
+    pub fn from_matrix(error: &MatrixHttpError) -> Self {
+        match error.as_client_api_error().map(|e| e.status_code.as_u16()) {
+            Some(429) => ApiErrorKind::RateLimited,
+            Some(401 | 403) => ApiErrorKind::Forbidden,
+            Some(404) => ApiErrorKind::UnknownUser,
+            Some(_) => ApiErrorKind::Other,
+            // The homeserver didn't answer
+            None => ApiErrorKind::Network
+        }
+    }
+
+    pub fn from_slack(error: &SlackApiError) -> Self {
+        match error {
+            SlackApiError::Network(_) => ApiErrorKind::Network,
+            SlackApiError::Status(status) => match status.as_u16() {
+                429 => ApiErrorKind::RateLimited,
+                401 | 403 => ApiErrorKind::Forbidden,
+                404 => ApiErrorKind::UnknownUser,
+                _ => ApiErrorKind::Other
+            },
+            // Slack answers most errors with a code in the body
+            SlackApiError::Api(code) => match code.as_str() {
+                "ratelimited" => ApiErrorKind::RateLimited,
+                "invalid_auth" | "not_authed" | "token_revoked" | "account_inactive" | "missing_scope" => ApiErrorKind::Forbidden,
+                "user_not_found" | "channel_not_found" => ApiErrorKind::UnknownUser,
+                _ => ApiErrorKind::Other
+            }
+        }
+    }
+
+    pub fn from_twitch(error: &TwitchApiError) -> Self {
+        match error {
+            TwitchApiError::Network(_) => ApiErrorKind::Network,
+            // 403 is answered to the whispers of a user who blocks them
+            TwitchApiError::Status(status, _) => match status.as_u16() {
+                429 => ApiErrorKind::RateLimited,
+                401 | 403 => ApiErrorKind::Forbidden,
+                404 => ApiErrorKind::UnknownUser,
+                _ => ApiErrorKind::Other
+            }
+        }
+    }
+}
+
+// Classify an error returned by the client of a platform, None if it comes from elsewhere
+fn classify(error: &anyhow::Error) -> Option<(Platform, ApiErrorKind)> {
+    error.chain().find_map(|cause| {
+        if let Some(e) = cause.downcast_ref::<RequestError>() {
+            Some((Platform::Telegram, ApiErrorKind::from_telegram(e)))
+        } else if let Some(e) = cause.downcast_ref::<MatrixHttpError>() {
+            Some((Platform::Matrix, ApiErrorKind::from_matrix(e)))
+        } else if let Some(e) = cause.downcast_ref::<MatrixError>() {
+            let kind = match e {
+                MatrixError::Http(e) => ApiErrorKind::from_matrix(e),
+                _ => ApiErrorKind::Other
+            };
+            Some((Platform::Matrix, kind))
+        } else if let Some(e) = cause.downcast_ref::<SlackApiError>() {
+            Some((Platform::Slack, ApiErrorKind::from_slack(e)))
+        } else if let Some(e) = cause.downcast_ref::<TwitchApiError>() {
+            Some((Platform::Twitch, ApiErrorKind::from_twitch(e)))
+        } else {
+            cause.downcast_ref::<SerenityError>().map(|e| (Platform::Discord, ApiErrorKind::from_discord(e)))
+        }
+    })
+}
+
+// API errors of a platform, as reported in the status
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApiErrorStats {
+    // Errors since startup, by kind
+    totals: [u64; ApiErrorKind::ALL.len()],
+    // Errors within the spike window
+    pub recent: usize,
+    // Notifications are reduced until the errors calm down
+    pub backing_off: bool
+}
+
+impl ApiErrorStats {
+    pub fn total(&self, kind: ApiErrorKind) -> u64 {
+        self.totals[kind.index()]
+    }
+}
+
+impl fmt::Display for ApiErrorStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let totals = ApiErrorKind::ALL.iter()
+            .filter(|kind| self.total(**kind) > 0)
+            .map(|kind| format!("{} {}", self.total(*kind), kind.name()))
+            .collect::<Vec<_>>();
+
+        if totals.is_empty() {
+            write!(f, "none")?;
+        } else {
+            write!(f, "{}", totals.join(", "))?;
+        }
+
+        write!(f, " ({} in the last minute)", self.recent)?;
+        if self.backing_off {
+            write!(f, ", backing off")?;
+        }
+
+        Ok(())
+    }
+}
+
+// Error counters of a platform
+#[derive(Default)]
+struct PlatformErrors {
+    totals: [u64; ApiErrorKind::ALL.len()],
+    // When the errors within the spike window happened
+    recent: VecDeque<Instant>,
+    // Non essential notifications are reduced until this instant
+    backoff_until: Option<Instant>
+}
+
+impl PlatformErrors {
+    // Forget the errors that left the spike window and the backoff once over
+    fn prune(&mut self, platform: Platform, now: Instant) {
+        while self.recent.front().is_some_and(|at| now.duration_since(*at) > SPIKE_WINDOW) {
+            self.recent.pop_front();
+        }
+
+        if self.backoff_until.is_some_and(|until| until <= now) {
+            info!("{} API errors calmed down, notifications are back to normal", platform);
+            self.backoff_until = None;
+        }
+    }
+}
+
+// Count the errors answered by the Discord, Telegram, Matrix, Slack and Twitch APIs
+// When they spike, e.g. during a large rain, the bot sends fewer notifications
+// so it stays within the platform limits
+pub struct ApiErrorMonitor {
+    discord: Mutex<PlatformErrors>,
+    telegram: Mutex<PlatformErrors>,
+    matrix: Mutex<PlatformErrors>,
+    slack: Mutex<PlatformErrors>,
+    twitch: Mutex<PlatformErrors>
+}
+
+impl ApiErrorMonitor {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            discord: Mutex::new(PlatformErrors::default()),
+            telegram: Mutex::new(PlatformErrors::default()),
+            matrix: Mutex::new(PlatformErrors::default()),
+            slack: Mutex::new(PlatformErrors::default()),
+            twitch: Mutex::new(PlatformErrors::default())
+        })
+    }
+
+    fn errors(&self, platform: Platform) -> &Mutex<PlatformErrors> {
+        match platform {
+            Platform::Discord => &self.discord,
+            Platform::Telegram => &self.telegram,
+            Platform::Matrix => &self.matrix,
+            Platform::Slack => &self.slack,
+            Platform::Twitch => &self.twitch
+        }
+    }
+
+    // Count an error answered by a platform, starting the backoff if they spike
+    pub fn record(&self, platform: Platform, kind: ApiErrorKind) {
+        let now = Instant::now();
+        let mut errors = self.errors(platform).lock().expect("api errors lock");
+        errors.prune(platform, now);
+        errors.totals[kind.index()] += 1;
+        errors.recent.push_back(now);
+
+        if errors.recent.len() >= SPIKE_THRESHOLD {
+            if errors.backoff_until.is_none() {
+                warn!("{} API errors are spiking ({} in the last minute), reducing notifications", platform, errors.recent.len());
+            }
+            errors.backoff_until = Some(now + BACKOFF_DURATION);
+        }
+    }
+
+    // Count an error if it was answered by a platform, other errors are ignored
+    pub fn record_error(&self, error: &anyhow::Error) {
+        if let Some((platform, kind)) = classify(error) {
+            self.record(platform, kind);
+        }
+    }
+
+    // Check if the notifications on a platform must be reduced
+    pub fn is_backing_off(&self, platform: Platform) -> bool {
+        let mut errors = self.errors(platform).lock().expect("api errors lock");
+        errors.prune(platform, Instant::now());
+        errors.backoff_until.is_some()
+    }
+
+    pub fn get_stats(&self, platform: Platform) -> ApiErrorStats {
+        let mut errors = self.errors(platform).lock().expect("api errors lock");
+        errors.prune(platform, Instant::now());
+        ApiErrorStats {
+            totals: errors.totals,
+            recent: errors.recent.len(),
+            backing_off: errors.backoff_until.is_some()
+        }
+    }
+}
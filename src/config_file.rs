@@ -0,0 +1,139 @@
+use std::{ffi::OsString, fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use clap::{parser::ValueSource, ArgAction, ArgMatches, Command};
+use toml::{Table, Value};
+
+// Options of the command line that make no sense in the file
+const SKIPPED_OPTIONS: [&str; 4] = ["config_file", "generate_config", "help", "version"];
+
+// Flag of an option, written with = so a value starting with - isn't read as a flag
+fn flag(long: &str, value: &str) -> OsString {
+    format!("--{}={}", long, value).into()
+}
+
+// Text of a single value, the CLI parses it as it would from the command line
+fn value_text(key: &str, value: &Value) -> Result<String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        Value::Boolean(value) => Ok(value.to_string()),
+        _ => bail!("Invalid value for '{}' in the config file, expected a string, a number or a boolean", key)
+    }
+}
+
+// Whether an option was given on the command line or in the environment
+fn is_given(matches: &ArgMatches, id: &str) -> bool {
+    matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable))
+}
+
+// Turn the options of a TOML file into command line flags
+// The options already given on the command line or in the environment are skipped, so they take precedence
+pub fn file_args(command: &Command, matches: &ArgMatches, path: &Path) -> Result<Vec<OsString>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read the config file {}", path.display()))?;
+    let table: Table = toml::from_str(&content)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+    let mut args = Vec::new();
+    for (key, value) in table.iter() {
+        // Keys are the names of the flags, with _ or -
+        let id = key.replace('-', "_");
+        let arg = command.get_arguments()
+            .find(|arg| arg.get_id() == id.as_str() && !SKIPPED_OPTIONS.contains(&id.as_str()));
+        let Some((arg, long)) = arg.and_then(|arg| arg.get_long().map(|long| (arg, long))) else {
+            bail!("Unknown option '{}' in the config file", key);
+        };
+
+        // An option given as a flag or an environment variable, or conflicting with one, is skipped
+        let conflicts = command.get_arg_conflicts_with(arg);
+        if is_given(matches, &id) || conflicts.iter().any(|other| is_given(matches, other.get_id().as_str())) {
+            continue;
+        }
+
+        match (value, arg.get_action()) {
+            (Value::Boolean(enabled), ArgAction::SetTrue) => {
+                if *enabled {
+                    args.push(format!("--{}", long).into());
+                }
+            },
+            (Value::Array(values), _) => {
+                for value in values {
+                    args.push(flag(long, &value_text(key, value)?));
+                }
+            },
+            (value, _) => args.push(flag(long, &value_text(key, value)?))
+        }
+    }
+
+    Ok(args)
+}
+
+// Write a config file listing every option with its description, commented out with its default value
+// An existing file is never overwritten
+pub fn write_template(command: &Command, path: &Path) -> Result<()> {
+    if path.exists() {
+        bail!("{} already exists, remove it first or choose another --config-file", path.display());
+    }
+
+    let mut template = String::from("# Configuration of the XELIS tip bot\n# Uncomment an option to set it, the flags given on the command line take precedence\n");
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        if SKIPPED_OPTIONS.contains(&id) || arg.get_long().is_none() {
+            continue;
+        }
+
+        template.push('\n');
+        if let Some(help) = arg.get_long_help().or(arg.get_help()) {
+            for line in help.to_string().lines() {
+                template.push_str(format!("# {}", line).trim_end());
+                template.push('\n');
+            }
+        }
+
+        if arg.is_required_set() {
+            template.push_str("# Required\n");
+        }
+
+        if let Some(env) = arg.get_env() {
+            template.push_str(&format!("# Or the {} environment variable\n", env.to_string_lossy()));
+        }
+
+        let defaults: Vec<String> = arg.get_default_values().iter()
+            .map(|value| value.to_string_lossy().into_owned())
+            .collect();
+        let value = match arg.get_action() {
+            ArgAction::SetTrue => "false".to_string(),
+            ArgAction::Append => Value::Array(defaults.into_iter().map(Value::String).collect()).to_string(),
+            _ => match defaults.into_iter().next() {
+                // Numbers are written as is, anything else as a string
+                Some(default) if default.parse::<f64>().is_ok() => default,
+                Some(default) => Value::String(default).to_string(),
+                None => "\"\"".to_string()
+            }
+        };
+        template.push_str(&format!("# {} = {}\n", id, value));
+    }
+
+    fs::write(path, template).with_context(|| format!("Couldn't write the config file {}", path.display()))
+}
+
+// Secret given as is or in a file, such as a Docker or systemd secret
+// The trailing line break most files end with isn't part of the secret
+pub fn read_secret(value: Option<String>, file: Option<&Path>) -> Result<String> {
+    let secret = match (value, file) {
+        (Some(value), _) => value,
+        (None, Some(file)) => fs::read_to_string(file)
+            .with_context(|| format!("Couldn't read {}", file.display()))?
+            .trim_end_matches(['\r', '\n'])
+            .to_string(),
+        (None, None) => bail!("No value nor file given")
+    };
+
+    if secret.is_empty() {
+        bail!("The secret is empty");
+    }
+
+    Ok(secret)
+}
@@ -0,0 +1,58 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex
+    },
+    time::{Duration, Instant}
+};
+
+// How long a user has to answer a confirmation
+pub const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Actions waiting for a confirmation from the user
+// Each action is identified by an id that is sent back by the platform (button callback)
+pub struct PendingConfirmations<T> {
+    next_id: AtomicU64,
+    actions: Mutex<HashMap<u64, (Instant, T)>>
+}
+
+impl<T> PendingConfirmations<T> {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            actions: Mutex::new(HashMap::new())
+        }
+    }
+
+    // Store an action and returns its id
+    // Expired actions are dropped at the same time
+    pub fn insert(&self, action: T) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let mut actions = self.actions.lock().expect("pending confirmations lock");
+        actions.retain(|_, (created_at, _)| created_at.elapsed() < CONFIRMATION_TIMEOUT);
+        actions.insert(id, (Instant::now(), action));
+
+        id
+    }
+
+    // Store an action under a chosen id, such as the id of its user,
+    // replacing the action already stored with it
+    pub fn insert_with_id(&self, id: u64, action: T) {
+        let mut actions = self.actions.lock().expect("pending confirmations lock");
+        actions.retain(|_, (created_at, _)| created_at.elapsed() < CONFIRMATION_TIMEOUT);
+        actions.insert(id, (Instant::now(), action));
+    }
+
+    // Take the action back if it has not expired and matches the predicate
+    // The action is kept if the predicate fails, so only its owner can consume it
+    pub fn take_if(&self, id: u64, predicate: impl FnOnce(&T) -> bool) -> Option<T> {
+        let mut actions = self.actions.lock().expect("pending confirmations lock");
+        let (created_at, action) = actions.get(&id)?;
+        if created_at.elapsed() >= CONFIRMATION_TIMEOUT || !predicate(action) {
+            return None;
+        }
+
+        actions.remove(&id).map(|(_, action)| action)
+    }
+}
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use clap::ValueEnum;
+use log::{debug, warn};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+
+use crate::service::{Movement, UserApplication, WalletService};
+
+// Maximum journal entries sent in a single request
+const EXPORT_BATCH_SIZE: usize = 500;
+// Measurement of the exported entries with the InfluxDB format
+const INFLUX_MEASUREMENT: &str = "ledger";
+
+// Encoding of the exported journal entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    // InfluxDB line protocol, timestamps in seconds
+    Influx,
+    // One JSON object per line
+    Json
+}
+
+#[derive(Debug, Clone)]
+pub struct ExporterConfig {
+    // Endpoint receiving the entries with a POST request
+    pub url: String,
+    // Value of the Authorization header, if any
+    pub authorization: Option<String>,
+    pub format: ExportFormat,
+    // How often the new entries are exported
+    pub interval: Duration
+}
+
+fn platform(user: &UserApplication) -> (&'static str, u64) {
+    match user {
+        UserApplication::Telegram(id) => ("telegram", *id),
+        UserApplication::Discord(id) => ("discord", *id),
+        UserApplication::Matrix(id) => ("matrix", *id),
+        UserApplication::Slack(id) => ("slack", *id),
+        UserApplication::Twitch(id) => ("twitch", *id)
+    }
+}
+
+// Encode a journal entry as an InfluxDB line
+// Tags only contain names, numbers and hashes so they don't need escaping
+fn influx_line(user: &UserApplication, movement: &Movement) -> String {
+    let (platform, id) = platform(user);
+    format!(
+        "{},platform={},user={},kind={},asset={},direction={} id={}u,amount={}u,before={}u,after={}u {}",
+        INFLUX_MEASUREMENT,
+        platform,
+        id,
+        movement.kind.name(),
+        movement.asset,
+        if movement.is_credit() { "credit" } else { "debit" },
+        movement.id,
+        movement.amount(),
+        movement.before,
+        movement.after,
+        movement.timestamp
+    )
+}
+
+// Encode a journal entry as a JSON object
+fn json_line(user: &UserApplication, movement: &Movement) -> String {
+    let (platform, id) = platform(user);
+    format!(
+        "{{\"id\":{},\"timestamp\":{},\"platform\":\"{}\",\"user\":\"{}\",\"kind\":\"{}\",\"asset\":\"{}\",\"credit\":{},\"amount\":{},\"before\":{},\"after\":{}}}",
+        movement.id,
+        movement.timestamp,
+        platform,
+        id,
+        movement.kind.name(),
+        movement.asset,
+        movement.is_credit(),
+        movement.amount(),
+        movement.before,
+        movement.after
+    )
+}
+
+// Stream the balance journal to an external time-series database
+// Entries are sent in order and at least once: the export position is saved
+// in the wallet storage only once the endpoint accepted them
+pub struct LedgerExporter {
+    client: reqwest::Client,
+    config: ExporterConfig
+}
+
+impl LedgerExporter {
+    pub fn new(config: ExporterConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config
+        }
+    }
+
+    // Export the new entries periodically in the background
+    pub fn start(self, service: WalletService) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(self.config.interval).await;
+                if !service.is_leader() {
+                    continue;
+                }
+
+                if let Err(e) = self.export(&service).await {
+                    warn!("Couldn't export the ledger: {}", e);
+                }
+            }
+        });
+    }
+
+    // Send every entry not exported yet, batch by batch
+    async fn export(&self, service: &WalletService) -> anyhow::Result<()> {
+        loop {
+            let movements = service.get_unexported_movements(EXPORT_BATCH_SIZE).await?;
+            let Some((_, last)) = movements.last() else {
+                return Ok(());
+            };
+            let last = last.id;
+
+            self.send(&movements).await?;
+            service.set_movements_exported(last).await?;
+            debug!("Exported {} ledger entries up to {}", movements.len(), last);
+
+            if movements.len() < EXPORT_BATCH_SIZE {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn send(&self, movements: &[(UserApplication, Movement)]) -> Result<(), reqwest::Error> {
+        let (encode, content_type): (fn(&UserApplication, &Movement) -> String, _) = match self.config.format {
+            ExportFormat::Influx => (influx_line, "text/plain; charset=utf-8"),
+            ExportFormat::Json => (json_line, "application/x-ndjson")
+        };
+
+        let body = movements.iter()
+            .map(|(user, movement)| encode(user, movement))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut request = self.client.post(&self.config.url)
+            .header(CONTENT_TYPE, content_type)
+            .body(body);
+
+        if let Some(authorization) = self.config.authorization.as_deref() {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+
+        request.send().await?.error_for_status()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+use std::{net::SocketAddr, sync::Arc, time::{Duration, Instant}};
+
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::{header::CONTENT_TYPE, HeaderName, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router
+};
+use log::{error, info, warn};
+use tokio::net::TcpListener;
+
+use crate::service::{Health, WalletService};
+
+// Blocks the wallet can be behind the daemon and still be ready
+pub const DEFAULT_MAX_SYNC_LAG: u64 = 30;
+// Time without any event after which the event loop is considered stalled
+// A new stable topoheight comes with every block, far more often than this
+pub const DEFAULT_MAX_EVENT_AGE: u64 = 5 * 60;
+
+#[derive(Debug, Clone)]
+pub struct HealthConfig {
+    pub address: SocketAddr,
+    pub max_sync_lag: u64,
+    pub max_event_age: Duration
+}
+
+// State of the event loop, as seen by the probes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventLoop {
+    Alive,
+    Stalled,
+    // The instance isn't the leader, the event loop doesn't run
+    Standby,
+    // The wallet is offline, the event loop waits for the daemon
+    Offline
+}
+
+impl EventLoop {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Alive => "alive",
+            Self::Stalled => "stalled",
+            Self::Standby => "standby",
+            Self::Offline => "offline"
+        }
+    }
+}
+
+// The event loop of an online leader handled no event for too long
+pub fn is_stalled(health: &Health, max_event_age: Duration, started_at: Instant) -> bool {
+    if !health.leader || !health.online {
+        return false;
+    }
+
+    // Before the first event, the bot is given the same time to get one
+    let idle = health.last_event.unwrap_or_else(|| started_at.elapsed());
+    idle > max_event_age
+}
+
+struct HealthState {
+    service: WalletService,
+    config: HealthConfig,
+    started_at: Instant
+}
+
+impl HealthState {
+    fn event_loop(&self, health: &Health) -> EventLoop {
+        if !health.leader {
+            return EventLoop::Standby;
+        }
+
+        if !health.online {
+            return EventLoop::Offline;
+        }
+
+        if is_stalled(health, self.config.max_event_age, self.started_at) {
+            EventLoop::Stalled
+        } else {
+            EventLoop::Alive
+        }
+    }
+
+    fn is_synced(&self, health: &Health) -> bool {
+        health.sync_lag().is_some_and(|lag| lag <= self.config.max_sync_lag)
+    }
+}
+
+fn option_json(value: Option<u64>) -> String {
+    value.map_or_else(|| "null".to_string(), |value| value.to_string())
+}
+
+// Encode the health as a JSON object
+fn health_json(status: &str, health: &Health, event_loop: EventLoop) -> String {
+    format!(
+        "{{\"status\":\"{}\",\"online\":{},\"leader\":{},\"wallet_topoheight\":{},\"daemon_topoheight\":{},\"sync_lag\":{},\"event_loop\":\"{}\",\"last_event_seconds\":{}}}",
+        status,
+        health.online,
+        health.leader,
+        health.wallet_topoheight,
+        option_json(health.daemon_topoheight),
+        option_json(health.sync_lag()),
+        event_loop.name(),
+        option_json(health.last_event.map(|elapsed| elapsed.as_secs()))
+    )
+}
+
+type HealthResponse = (StatusCode, [(HeaderName, &'static str); 1], String);
+
+fn respond(ok: bool, health: &Health, event_loop: EventLoop) -> HealthResponse {
+    let (code, status) = if ok {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+
+    (code, [(CONTENT_TYPE, "application/json")], health_json(status, health, event_loop))
+}
+
+fn respond_error(e: anyhow::Error) -> HealthResponse {
+    error!("Couldn't check the health: {:?}", e);
+    (StatusCode::INTERNAL_SERVER_ERROR, [(CONTENT_TYPE, "application/json")], "{\"status\":\"error\"}".to_string())
+}
+
+// Liveness: fails only when the event loop is stalled, restarting the bot is the only fix
+// A daemon that can't be reached is reported but doesn't fail it, a restart wouldn't help
+async fn health(State(state): State<Arc<HealthState>>) -> impl IntoResponse {
+    match state.service.get_health().await {
+        Ok(health) => {
+            let event_loop = state.event_loop(&health);
+            respond(event_loop != EventLoop::Stalled, &health, event_loop)
+        },
+        Err(e) => respond_error(e)
+    }
+}
+
+// Readiness: the wallet is online, synced with the daemon and its event loop runs
+async fn ready(State(state): State<Arc<HealthState>>) -> impl IntoResponse {
+    match state.service.get_health().await {
+        Ok(health) => {
+            let event_loop = state.event_loop(&health);
+            let ready = state.is_synced(&health) && event_loop != EventLoop::Stalled;
+            respond(ready, &health, event_loop)
+        },
+        Err(e) => respond_error(e)
+    }
+}
+
+// HTTP endpoints for the Docker and Kubernetes probes and the uptime monitors
+// GET /health is the liveness and GET /ready the readiness, both answer the same JSON
+pub struct HealthServer {
+    config: HealthConfig
+}
+
+impl HealthServer {
+    pub fn new(config: HealthConfig) -> Self {
+        Self { config }
+    }
+
+    // Bind the address, then answer the probes in the background
+    pub async fn start(self, service: WalletService) -> Result<()> {
+        let listener = TcpListener::bind(self.config.address).await?;
+        info!("Health endpoint listening on {}", self.config.address);
+
+        let state = Arc::new(HealthState {
+            service,
+            config: self.config,
+            started_at: Instant::now()
+        });
+        let router = Router::new()
+            .route("/health", get(health))
+            .route("/ready", get(ready))
+            .with_state(state);
+
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                warn!("Health endpoint stopped: {}", e);
+            }
+        });
+
+        Ok(())
+    }
+}
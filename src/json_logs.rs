@@ -0,0 +1,214 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    sync::Mutex
+};
+
+use anyhow::Result;
+use chrono::{Local, SecondsFormat, Utc};
+use clap::ValueEnum;
+use log::{
+    kv::{self, Key, Value, VisitSource, VisitValue},
+    LevelFilter,
+    Log,
+    Metadata,
+    Record
+};
+use serde_json::{Map, Number};
+use xelis_common::prompt::ModuleConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    // Formatted by the prompt, with colors in the terminal
+    Text,
+    // One JSON object per line, for Loki or ELK
+    Json
+}
+
+// Where the log file is written, as the prompt does
+#[derive(Debug, Clone)]
+pub struct LogFileConfig {
+    // Directory ending with a /
+    pub path: String,
+    pub filename: String,
+    // Prefix the filename with the date, a new file is started every day
+    pub date_based: bool,
+    pub level: LevelFilter
+}
+
+// Open log file and the date it was opened for
+struct OpenLogFile {
+    date: String,
+    file: File
+}
+
+// Logger writing each record as a JSON object on a line, in the terminal and the log file
+// The key-values of a record, such as the user or the TX hash of an event, are fields of the object
+pub struct JsonLogger {
+    level: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+    file: Option<(LogFileConfig, Mutex<Option<OpenLogFile>>)>
+}
+
+// Collect the key-values of a record into the JSON object
+struct FieldsVisitor<'a>(&'a mut Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for FieldsVisitor<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let mut field = FieldVisitor(serde_json::Value::Null);
+        value.visit(&mut field)?;
+        // The fields of the record itself are kept
+        self.0.entry(key.as_str()).or_insert(field.0);
+        Ok(())
+    }
+}
+
+// Keep numbers and booleans as is, anything else is written as its text
+struct FieldVisitor(serde_json::Value);
+
+impl VisitValue<'_> for FieldVisitor {
+    fn visit_any(&mut self, value: Value) -> Result<(), kv::Error> {
+        self.0 = serde_json::Value::String(value.to_string());
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), kv::Error> {
+        self.0 = serde_json::Value::Null;
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), kv::Error> {
+        self.0 = Number::from_f64(value).map_or(serde_json::Value::Null, serde_json::Value::Number);
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_str(&mut self, value: &str) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+}
+
+impl JsonLogger {
+    // The modules keep the levels given to the prompt with --logs-modules
+    pub fn new(level: LevelFilter, modules: Vec<ModuleConfig>, file: Option<LogFileConfig>) -> Self {
+        Self {
+            level,
+            modules: modules.into_iter().map(|module| (module.module, module.level.into())).collect(),
+            file: file.map(|file| (file, Mutex::new(None)))
+        }
+    }
+
+    // Install the logger for the whole process, instead of the one of the prompt
+    pub fn init(self) -> Result<()> {
+        let mut max_level = self.level;
+        if let Some((file, _)) = self.file.as_ref() {
+            fs::create_dir_all(&file.path)?;
+            max_level = max_level.max(file.level);
+        }
+
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+
+    // Level of the most specific module configured for a target, if any
+    fn module_level(&self, target: &str) -> Option<LevelFilter> {
+        self.modules.iter()
+            .filter(|(module, _)| target == module.as_str() || target.strip_prefix(module.as_str()).is_some_and(|rest| rest.starts_with("::")))
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+    }
+
+    fn format(&self, record: &Record) -> String {
+        let mut fields = Map::new();
+        fields.insert("timestamp".to_string(), Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).into());
+        fields.insert("level".to_string(), record.level().as_str().to_lowercase().into());
+        fields.insert("target".to_string(), record.target().into());
+        fields.insert("message".to_string(), record.args().to_string().into());
+
+        // A field that can't be read is left out, the message is still logged
+        let _ = record.key_values().visit(&mut FieldsVisitor(&mut fields));
+
+        let mut line = serde_json::Value::Object(fields).to_string();
+        line.push('\n');
+        line
+    }
+
+    // Append a line to the log file of the day, opening it if needed
+    fn write_file(&self, config: &LogFileConfig, open: &Mutex<Option<OpenLogFile>>, line: &str) -> io::Result<()> {
+        let mut open = open.lock().map_err(|_| io::Error::other("log file lock poisoned"))?;
+        let date = if config.date_based {
+            Local::now().format("%Y-%m-%d").to_string()
+        } else {
+            String::new()
+        };
+
+        if open.as_ref().is_none_or(|open| open.date != date) {
+            let filename = if config.date_based {
+                format!("{}{}.{}", config.path, date, config.filename)
+            } else {
+                format!("{}{}", config.path, config.filename)
+            };
+
+            let file = OpenOptions::new().create(true).append(true).open(filename)?;
+            *open = Some(OpenLogFile { date, file });
+        }
+
+        match open.as_mut() {
+            Some(open) => open.file.write_all(line.as_bytes()),
+            None => Ok(())
+        }
+    }
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let file_level = self.file.as_ref().map_or(LevelFilter::Off, |(file, _)| file.level);
+        self.module_level(metadata.target()).is_none_or(|level| metadata.level() <= level)
+            && metadata.level() <= self.level.max(file_level)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = self.format(record);
+        // A logger has nowhere to report its own errors, a line that can't be written is lost
+        if record.level() <= self.level {
+            let _ = io::stdout().lock().write_all(line.as_bytes());
+        }
+
+        if let Some((config, open)) = self.file.as_ref() {
+            if record.level() <= config.level {
+                let _ = self.write_file(config, open, &line);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        let _ = io::stdout().flush();
+        if let Some((_, open)) = self.file.as_ref() {
+            if let Ok(mut open) = open.lock() {
+                if let Some(open) = open.as_mut() {
+                    let _ = open.file.flush();
+                }
+            }
+        }
+    }
+}
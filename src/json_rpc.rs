@@ -0,0 +1,342 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::{bail, Result};
+use axum::{
+    extract::{Request, State},
+    http::{header::{AUTHORIZATION, WWW_AUTHENTICATE}, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json,
+    Router
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::{debug, error, info, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use xelis_common::crypto::hash;
+
+use crate::service::{AssetBalance, OperationId, ServiceError, UserApplication, WalletService};
+
+// Same codes as the JSON-RPC server of the XELIS wallet
+const PARSE_ERROR: i16 = -32700;
+const INVALID_REQUEST: i16 = -32600;
+const METHOD_NOT_FOUND: i16 = -32601;
+const INVALID_PARAMS: i16 = -32602;
+const INTERNAL_ERROR: i16 = -32603;
+// Refused by the service, such as not enough funds, the message tells why
+const SERVICE_ERROR: i16 = -32000;
+// The instance is on standby and can't move funds
+const STANDBY_ERROR: i16 = -32001;
+
+// Requests answered at most in a single batch
+const MAX_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct JsonRpcConfig {
+    pub address: SocketAddr,
+    // Credentials of the HTTP basic authentication
+    pub username: String,
+    pub password: String
+}
+
+struct RpcError {
+    code: i16,
+    message: String
+}
+
+impl RpcError {
+    fn new(code: i16, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string()
+        }
+    }
+
+    fn invalid_params(message: impl ToString) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        error!("Error in the JSON-RPC server: {:?}", e);
+        Self::new(INTERNAL_ERROR, "Internal error")
+    }
+}
+
+impl From<ServiceError> for RpcError {
+    fn from(e: ServiceError) -> Self {
+        match e {
+            ServiceError::Any(e) => e.into(),
+            ServiceError::WalletError(e) => anyhow::Error::from(e).into(),
+            e => Self::new(SERVICE_ERROR, e)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Option<Value>
+}
+
+struct RpcState {
+    service: WalletService,
+    // Only the hash of the expected Authorization header is kept
+    credentials_hash: [u8; 32]
+}
+
+fn basic_credentials(username: &str, password: &str) -> String {
+    format!("Basic {}", STANDARD.encode(format!("{}:{}", username, password)))
+}
+
+// Every request requires the credentials, as the wallet RPC does
+async fn authorize(State(state): State<Arc<RpcState>>, request: Request, next: Next) -> Response {
+    let authorized = request.headers()
+        .get(AUTHORIZATION)
+        .is_some_and(|value| *hash(value.as_bytes()).as_bytes() == state.credentials_hash);
+
+    if authorized {
+        next.run(request).await
+    } else {
+        warn!("Unauthorized request to the JSON-RPC server");
+        (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, "Basic realm=\"json_rpc\"")]).into_response()
+    }
+}
+
+// User of the params: {"platform": "discord", "id": 1234}
+#[derive(Deserialize)]
+struct UserParams {
+    platform: String,
+    id: u64
+}
+
+impl UserParams {
+    fn to_user(&self) -> Result<UserApplication, RpcError> {
+        match self.platform.as_str() {
+            "discord" => Ok(UserApplication::Discord(self.id)),
+            "telegram" => Ok(UserApplication::Telegram(self.id)),
+            "matrix" => Ok(UserApplication::Matrix(self.id)),
+            "slack" => Ok(UserApplication::Slack(self.id)),
+            "twitch" => Ok(UserApplication::Twitch(self.id)),
+            platform => Err(RpcError::invalid_params(format!("Unknown platform '{}', expected discord, telegram, matrix, slack or twitch", platform)))
+        }
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Option<Value>) -> Result<T, RpcError> {
+    serde_json::from_value(params.unwrap_or(Value::Null)).map_err(RpcError::invalid_params)
+}
+
+// Funds are only moved by the leader
+fn check_leader(state: &RpcState) -> Result<(), RpcError> {
+    if state.service.is_leader() {
+        Ok(())
+    } else {
+        Err(RpcError::new(STANDBY_ERROR, "This instance is on standby, send the request to the leader"))
+    }
+}
+
+#[derive(Serialize)]
+struct BalanceResult {
+    asset: String,
+    name: String,
+    decimals: u8,
+    // Atomic units
+    amount: u64
+}
+
+impl From<AssetBalance> for BalanceResult {
+    fn from(balance: AssetBalance) -> Self {
+        Self {
+            asset: balance.asset.to_string(),
+            name: balance.name,
+            decimals: balance.decimals,
+            amount: balance.amount
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct GetUserBalanceParams {
+    user: UserParams,
+    // Name or hash, XEL if not set
+    asset: Option<String>
+}
+
+#[derive(Deserialize)]
+struct GetUserParams {
+    user: UserParams
+}
+
+#[derive(Deserialize)]
+struct InternalTransferParams {
+    from: UserParams,
+    to: UserParams,
+    // Name or hash, XEL if not set
+    asset: Option<String>,
+    // Atomic units
+    amount: u64,
+    // Chosen by the caller, a transfer sent again with the same one is executed only once
+    operation_id: Option<String>
+}
+
+#[derive(Deserialize)]
+struct RequestWithdrawParams {
+    user: UserParams,
+    address: String,
+    // Name or hash, XEL if not set
+    asset: Option<String>,
+    // Atomic units
+    amount: u64,
+    // Required when the user protected the withdrawals
+    pin: Option<String>,
+    // Chosen by the caller, a withdrawal sent again with the same one is broadcast only once
+    operation_id: Option<String>
+}
+
+async fn execute(state: &RpcState, method: &str, params: Option<Value>) -> Result<Value, RpcError> {
+    let service = &state.service;
+    let value = match method {
+        "get_version" => json!(env!("CARGO_PKG_VERSION")),
+        "get_user_balance" => {
+            let params: GetUserBalanceParams = parse_params(params)?;
+            let balance = service.find_balance_for_user(&params.user.to_user()?, params.asset.as_deref()).await?;
+            json!(BalanceResult::from(balance))
+        },
+        "get_user_balances" => {
+            let params: GetUserParams = parse_params(params)?;
+            let balances: Vec<BalanceResult> = service.get_balances_for_user(&params.user.to_user()?).await?
+                .into_iter()
+                .map(BalanceResult::from)
+                .collect();
+            json!(balances)
+        },
+        "get_deposit_address" => {
+            let params: GetUserParams = parse_params(params)?;
+            json!(service.get_address_for_user(&params.user.to_user()?).to_string())
+        },
+        "internal_transfer" => {
+            check_leader(state)?;
+            let params: InternalTransferParams = parse_params(params)?;
+            let from = params.from.to_user()?;
+            let to = params.to.to_user()?;
+            let asset = service.find_balance_for_user(&from, params.asset.as_deref()).await?.asset;
+            let operation = params.operation_id.as_deref().map(OperationId::rpc);
+
+            info!("JSON-RPC transfer of {} atomic units of {} from {:?} to {:?}", params.amount, asset, from, to);
+            service.transfer(&from, &to, &asset, params.amount, None, operation.as_ref()).await?;
+            json!(true)
+        },
+        "request_withdraw" => {
+            check_leader(state)?;
+            let params: RequestWithdrawParams = parse_params(params)?;
+            let user = params.user.to_user()?;
+            let address = service.parse_address(&params.address)?;
+            let asset = service.find_balance_for_user(&user, params.asset.as_deref()).await?.asset;
+            let operation = params.operation_id.as_deref().map(OperationId::rpc);
+
+            info!("JSON-RPC withdrawal of {} atomic units of {} for {:?} to {}", params.amount, asset, user, address);
+            let tx_hash = service.withdraw(&user, address, &asset, params.amount, params.pin.as_deref(), operation.as_ref()).await?;
+            json!(tx_hash.to_string())
+        },
+        _ => return Err(RpcError::new(METHOD_NOT_FOUND, format!("Method '{}' not found", method)))
+    };
+
+    Ok(value)
+}
+
+// Answer a single request, the ID is null when it couldn't be read
+async fn handle_request(state: &RpcState, request: Value) -> Value {
+    let request: RpcRequest = match serde_json::from_value(request) {
+        Ok(request) => request,
+        Err(e) => return error_response(Value::Null, RpcError::new(INVALID_REQUEST, e))
+    };
+
+    let id = request.id.unwrap_or(Value::Null);
+    if request.jsonrpc != "2.0" {
+        return error_response(id, RpcError::new(INVALID_REQUEST, "Expected jsonrpc 2.0"));
+    }
+
+    debug!("JSON-RPC request {}", request.method);
+    match execute(state, &request.method, request.params).await {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => error_response(id, e)
+    }
+}
+
+fn error_response(id: Value, e: RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": e.code, "message": e.message } })
+}
+
+// POST /json_rpc, a request or a batch of requests answered in order
+async fn json_rpc(State(state): State<Arc<RpcState>>, body: String) -> Json<Value> {
+    let request = match serde_json::from_str::<Value>(&body) {
+        Ok(request) => request,
+        Err(e) => return Json(error_response(Value::Null, RpcError::new(PARSE_ERROR, e)))
+    };
+
+    match request {
+        Value::Array(requests) => {
+            if requests.is_empty() || requests.len() > MAX_BATCH_SIZE {
+                let e = RpcError::new(INVALID_REQUEST, format!("A batch holds 1 to {} requests", MAX_BATCH_SIZE));
+                return Json(error_response(Value::Null, e));
+            }
+
+            // In order, so a batch can't race its own transfers
+            let mut responses = Vec::with_capacity(requests.len());
+            for request in requests {
+                responses.push(handle_request(&state, request).await);
+            }
+
+            Json(Value::Array(responses))
+        },
+        request => Json(handle_request(&state, request).await)
+    }
+}
+
+// JSON-RPC 2.0 server in the style of the XELIS wallet, for other services to drive the ledger
+// Amounts are in atomic units, users are given as {"platform", "id"}
+pub struct JsonRpcServer {
+    config: JsonRpcConfig
+}
+
+impl JsonRpcServer {
+    pub fn new(config: JsonRpcConfig) -> Self {
+        Self { config }
+    }
+
+    // Bind the address, then answer the requests in the background
+    pub async fn start(self, service: WalletService) -> Result<()> {
+        if self.config.username.is_empty() || self.config.password.is_empty() {
+            bail!("The JSON-RPC username and password can't be empty");
+        }
+
+        let listener = TcpListener::bind(self.config.address).await?;
+        info!("JSON-RPC server listening on {}", self.config.address);
+
+        let credentials = basic_credentials(&self.config.username, &self.config.password);
+        let state = Arc::new(RpcState {
+            service,
+            credentials_hash: *hash(credentials.as_bytes()).as_bytes()
+        });
+        let router = Router::new()
+            .route("/json_rpc", post(json_rpc))
+            .route_layer(middleware::from_fn_with_state(Arc::clone(&state), authorize))
+            .with_state(state);
+
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                warn!("JSON-RPC server stopped: {}", e);
+            }
+        });
+
+        Ok(())
+    }
+}
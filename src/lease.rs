@@ -0,0 +1,121 @@
+use std::{fs, io::ErrorKind, path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use xelis_common::time::get_current_time_in_millis;
+
+use crate::service::WalletService;
+
+// Wait after taking the lease before trusting it, in case another instance took it at the same time
+const SETTLE_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct LeaseConfig {
+    // File shared by all the instances, on storage they can all reach
+    pub path: PathBuf,
+    // Name of this instance in the lease
+    pub instance: String,
+    // Time without renewal after which another instance takes over
+    pub ttl: Duration
+}
+
+// Instance holding the lease and when it expires, in milliseconds
+struct Lease {
+    holder: String,
+    expires_at: u64
+}
+
+// Lease file content: the holder and the expiry separated by a space
+fn parse_lease(content: &str) -> Option<Lease> {
+    let (holder, expires_at) = content.trim().rsplit_once(' ')?;
+    Some(Lease {
+        holder: holder.to_string(),
+        expires_at: expires_at.parse().ok()?
+    })
+}
+
+// Only one instance is the leader at a time: it holds a lease it renews
+// well before it expires, and the other instances wait on standby to take it over
+// A leader that can't renew its lease stops, as another instance may already lead
+pub struct LeaderElection {
+    config: LeaseConfig
+}
+
+impl LeaderElection {
+    pub fn new(config: LeaseConfig) -> Self {
+        Self { config }
+    }
+
+    // The lease is a few bytes, reading and writing it doesn't block for long
+    fn read(&self) -> Result<Option<Lease>> {
+        match fs::read_to_string(&self.config.path) {
+            Ok(content) => Ok(parse_lease(&content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    // Replace the lease at once, so no instance reads a partial one
+    fn write(&self, expires_at: u64) -> Result<()> {
+        let suffix: String = self.config.instance.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+        let tmp = self.config.path.with_extension(format!("{}.tmp", suffix));
+        fs::write(&tmp, format!("{} {}", self.config.instance, expires_at))?;
+        fs::rename(&tmp, &self.config.path)?;
+
+        Ok(())
+    }
+
+    // Take or renew the lease, false if another instance holds it
+    async fn try_acquire(&self, renewing: bool) -> Result<bool> {
+        let now = get_current_time_in_millis();
+        if let Some(lease) = self.read()? {
+            if lease.holder != self.config.instance && lease.expires_at > now {
+                return Ok(false);
+            }
+        }
+
+        self.write(now + self.config.ttl.as_millis() as u64)?;
+        if !renewing {
+            tokio::time::sleep(SETTLE_DELAY).await;
+        }
+
+        Ok(self.read()?.is_some_and(|lease| lease.holder == self.config.instance))
+    }
+
+    // Wait for the lease, then keep it until it's lost
+    // Only returns once this instance can't be sure to lead anymore
+    pub async fn run(self, service: WalletService) -> Result<()> {
+        let renew_interval = self.config.ttl / 3;
+        loop {
+            match self.try_acquire(false).await {
+                Ok(true) => break,
+                Ok(false) => {},
+                Err(e) => warn!("Couldn't read the lease {}: {}", self.config.path.display(), e)
+            }
+
+            tokio::time::sleep(renew_interval).await;
+        }
+
+        info!("Instance {} is now the leader", self.config.instance);
+        service.set_leader(true);
+
+        let mut renewed_at = get_current_time_in_millis();
+        let error = loop {
+            tokio::time::sleep(renew_interval).await;
+            match self.try_acquire(true).await {
+                Ok(true) => renewed_at = get_current_time_in_millis(),
+                Ok(false) => break anyhow!("Instance {} lost the lease to another instance", self.config.instance),
+                Err(e) => warn!("Couldn't renew the lease {}: {}", self.config.path.display(), e)
+            }
+
+            if get_current_time_in_millis().saturating_sub(renewed_at) >= self.config.ttl.as_millis() as u64 {
+                break anyhow!("Instance {} couldn't renew its lease in time", self.config.instance);
+            }
+        };
+
+        // Nothing is processed anymore while the instance stops
+        service.set_leader(false);
+
+        Err(error)
+    }
+}
@@ -1,37 +1,164 @@
+mod activity;
+mod admin_api;
+mod amount;
+mod api_errors;
+mod config_file;
+mod confirmation;
+mod exporter;
+mod health;
+mod json_logs;
+mod json_rpc;
+mod lease;
+mod matrix;
+mod matrix_message;
+mod migration;
+mod platform;
+mod price;
 mod service;
+mod slack;
+mod slack_api;
+mod slack_message;
+mod systemd;
 mod telegram_message;
+mod telegram_notifier;
+mod twitch;
+mod twitch_api;
+mod twitch_irc;
+mod twitch_message;
 
-use std::{sync::Arc, time::Duration};
-use telegram_message::{InlineCode, TelegramMessage};
+use std::{ffi::OsString, net::SocketAddr, path::{Path, PathBuf}, sync::Arc, time::Duration};
+use activity::{ActiveUser, ActivityTracker};
+use admin_api::{AdminApi, AdminApiConfig};
+use amount::{is_amount_suffix, parse_amount, parse_amount_with_decimals, Amount, AmountError, AmountKeyword};
+use api_errors::{ApiErrorKind, ApiErrorMonitor, Platform};
+use config_file::read_secret;
+use confirmation::{PendingConfirmations, CONFIRMATION_TIMEOUT};
+use exporter::{ExportFormat, ExporterConfig, LedgerExporter};
+use health::{HealthConfig, HealthServer, DEFAULT_MAX_EVENT_AGE, DEFAULT_MAX_SYNC_LAG};
+use json_logs::{JsonLogger, LogFileConfig, LogFormat};
+use json_rpc::{JsonRpcConfig, JsonRpcServer};
+use lease::{LeaderElection, LeaseConfig};
+use matrix::{MatrixBot, MatrixConfig};
+use migration::{balances_csv, parse_balances_csv, BalanceImport};
+use platform::{DiscordPlatform, TelegramPlatform};
+use price::{format_fiat, is_xelis_unit, split_unit, Conversion, MarketData, PriceError, PriceFeed, DEFAULT_CURRENCY, DEFAULT_PRICE_API};
+use slack::{SlackConfig, SlackServer};
+use systemd::{notify_ready, notify_status, notify_stopping, SystemdWatchdog, WatchdogConfig};
+use telegram_message::{escape_html, InlineCode, Spoiler, TelegramMessage};
+use telegram_notifier::TelegramNotifier;
 use thiserror::Error;
-use anyhow::{Error, Result};
-use clap::Parser;
+use twitch::{TwitchBot, TwitchConfig};
+use anyhow::{bail, Context as _, Error, Result};
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use clap::{ArgMatches, CommandFactory, Parser};
 use poise::{
     serenity_prelude::{
         GatewayIntents,
         ClientBuilder,
+        Http,
         CreateEmbed,
         CreateEmbedFooter,
+        CreateActionRow,
+        CreateAttachment,
+        CreateButton,
+        CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+        CreateInteractionResponseFollowup,
+        ButtonStyle,
+        ChannelId,
+        ChannelType,
+        ComponentInteraction,
+        ComponentInteractionCollector,
+        ComponentInteractionDataKind,
+        CreateMessage,
+        CreateSelectMenu,
+        CreateSelectMenuKind,
+        CreateSelectMenuOption,
+        EditMessage,
+        Guild,
+        GuildChannel,
+        MessageId,
+        Context as SerenityContext,
+        FullEvent,
+        GetMessages,
+        Interaction,
+        AutocompleteChoice,
+        Member,
+        ReactionType,
+        Role,
         User,
         Colour
     },
+    ChoiceParameter,
     CreateReply
 };
 use service::{
+    default_explorer_url,
+    discord_id,
+    parse_delay,
+    parse_matrix_user,
+    parse_slack_user,
+    telegram_channel,
+    Alert,
+    AssetBalance,
+    AuditRecord,
+    BudgetOverrun,
+    Challenge,
+    ChallengeResult,
+    CommandRule,
+    Community,
+    Exemption,
+    FaucetConfig,
+    Feature,
+    ForgottenUser,
+    Game,
+    GoalUpdate,
+    LeaderboardKind,
+    LotteryConfig,
+    LotteryDraw,
+    LotteryRound,
+    Movement,
+    OperationId,
+    OpsChannels,
+    Outcome,
+    QueueStatus,
+    RateLimitConfig,
+    RecipientFilter,
+    Residual,
+    ReminderConfig,
+    RuntimeSettings,
+    SavedAddress,
+    ServiceError,
+    Setting,
+    Statement,
+    StatsPeriod,
+    Strictness,
     UserApplication,
     WalletService,
-    WalletServiceImpl
+    WalletServiceImpl,
+    WhitelistStatus,
+    CHALLENGE_TIMEOUT,
+    DEFAULT_SOLVENCY_BUFFER,
+    MAX_GIVEAWAY_CLAIMS,
+    MAX_NOTE_LENGTH,
+    MAX_NOTIFICATION_ATTEMPTS,
+    MAX_TICKETS_PER_PURCHASE,
+    PRACTICE_BALANCE,
+    PRACTICE_WITHDRAW_FEE
 };
 use teloxide::{
     dispatching::{HandlerExt, UpdateFilterExt},
     prelude::{dptree, Dispatcher, Requester},
-    types::{Message, Update},
-    utils::command::BotCommands,
+    payloads::{AnswerCallbackQuerySetters, SendMessageSetters},
+    types::{CallbackQuery, ChatId, ChatMemberUpdated, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, Message, MessageEntityKind, Recipient, ReplyParameters, ThreadId, Update, UserId},
+    utils::{command::{BotCommands, ParseError}, html::user_mention},
     Bot
 };
 use xelis_common::{
     async_handler,
-    crypto::Address,
+    config::{COIN_VALUE, XELIS_ASSET},
+    api::DataValue,
+    crypto::{hash, Address, Hash},
     network::Network,
     prompt::{
         argument::{Arg, ArgType, ArgumentManager},
@@ -45,20 +172,73 @@ use xelis_common::{
         ModuleConfig,
         Prompt,
         PromptError,
+        ShareablePrompt,
         default_logs_datetime_format,
     },
-    utils::{format_xelis, from_xelis, detect_available_parallelism}
+    time::get_current_time_in_seconds,
+    utils::{format_coin, format_xelis, detect_available_parallelism}
 };
 use xelis_wallet::config::DEFAULT_DAEMON_ADDRESS;
-use log::{debug, error};
+use log::{debug, error, info, warn};
 
 // Context type for poise with our data type
 type Context<'a> = poise::Context<'a, WalletService, Error>;
+// Context of the slash and context menu commands only, required by the modals
+type ApplicationContext<'a> = poise::ApplicationContext<'a, WalletService, Error>;
+
+// Telegram commands waiting for a confirmation from their author
+type TelegramConfirmations = Arc<PendingConfirmations<(Message, TelegramCommand)>>;
+// Telegram withdrawals waiting for the PIN of their author, by user
+type TelegramPinRequests = Arc<PendingConfirmations<TelegramPendingWithdraw>>;
+// Telegram rains waiting for their author to confirm the recipients
+type TelegramRainPreviews = Arc<PendingConfirmations<TelegramPendingRain>>;
+// Recently active users of each Telegram group
+type TelegramActivity = Arc<ActivityTracker>;
+type TelegramNotifications = Arc<TelegramNotifier>;
 
 // Icon URL for thumbnail
 const ICON: &str = "https://github.com/xelis-project/xelis-assets/raw/master/icons/png/square/green_background_black_logo.png?raw=true";
 // Color of the embed
 const COLOR: u32 = 196559;
+// How many messages are scanned to find active users for a rain
+const RAIN_HISTORY_LIMIT: u8 = 100;
+// Maximum duration of a giveaway in minutes
+const MAX_GIVEAWAY_DURATION: u32 = 7 * 24 * 60;
+// Prefix of the button ids used to claim a giveaway
+const GIVEAWAY_BUTTON_PREFIX: &str = "giveaway:";
+// Latest movements shown by the user CLI command
+const CLI_USER_MOVEMENTS: usize = 20;
+// Latest records shown by the audit_log CLI command
+const CLI_AUDIT_RECORDS: usize = 20;
+// Users displayed on each page of the list_balances CLI command
+const CLI_BALANCES_PAGE_SIZE: usize = 20;
+// Users displayed on each page of the leaderboard
+const LEADERBOARD_PAGE_SIZE: usize = 10;
+// How long the leaderboard pages can be browsed
+const LEADERBOARD_TIMEOUT: Duration = Duration::from_secs(120);
+// How long an admin has to answer each step of the setup wizard
+const SETUP_WIZARD_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+// How long a user has to submit the amount of a tip from the context menu
+const TIP_MODAL_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+// Minimum tips proposed by the setup wizard
+const SETUP_MIN_TIPS: [u64; 4] = [0, COIN_VALUE / 1000, COIN_VALUE / 100, COIN_VALUE / 10];
+// Prefix of the callback data of the Telegram setup wizard
+const SETUP_WIZARD_PREFIX: &str = "setup:";
+// Prefix of the tip me buttons, followed by the user to tip and the suggested amount if any
+const TIPME_BUTTON_PREFIX: &str = "tipme:";
+// Prefix of the tip button of a Telegram reminder, followed by the user to tip and the amount
+const REMINDER_TIP_PREFIX: &str = "remindtip:";
+// Buttons of a challenge, followed by its id
+const CHALLENGE_ACCEPT_PREFIX: &str = "accept:";
+const CHALLENGE_DECLINE_PREFIX: &str = "decline:";
+// Buttons of a Telegram rain preview, followed by its id
+const RAIN_CONFIRM_PREFIX: &str = "rain:";
+const RAIN_CANCEL_PREFIX: &str = "cancelrain:";
+// Warning shown with the deposit address once the liability cap is reached
+const DEPOSITS_PAUSED_TITLE: &str = "Deposits are temporarily paused";
+const DEPOSITS_PAUSED_MESSAGE: &str = "New XEL deposits will be sent back to the sender minus the network fee";
+// Discord commands an instance on standby can answer, they don't change the wallet state
+const DISCORD_READ_ONLY_COMMANDS: [&str; 6] = ["status", "balance", "deposit", "leaderboard", "convert", "price"];
 
 #[derive(Debug, Error)]
 pub enum TelegramError {
@@ -66,34 +246,115 @@ pub enum TelegramError {
     NoUser
 }
 
+#[derive(Debug, Error)]
+pub enum DiscordError {
+    #[error("This command can only be used in a server")]
+    NoGuild
+}
+
 #[derive(Parser)]
 #[clap(version = "1.0.0", about = "XELIS Tip Bot")]
 #[command(styles = xelis_common::get_cli_styles())]
 pub struct Config {
+    /// TOML file to read the options from, named as the flags such as discord_token = "..."
+    /// The flags given on the command line take precedence over the file
+    #[clap(long, env = "XELIS_TIPBOT_CONFIG_FILE")]
+    config_file: Option<PathBuf>,
+    /// Write a template of the file given to --config-file with every option, then exit
+    #[clap(long)]
+    generate_config: bool,
     /// Network selected for wallet
     #[clap(long, value_enum, default_value_t = Network::Mainnet)]
     network: Network,
     /// Password for wallet
-    #[clap(short, long)]
-    password: String,
+    #[clap(short, long, env = "XELIS_TIPBOT_PASSWORD", hide_env_values = true, required_unless_present = "password_file", conflicts_with = "password_file")]
+    password: Option<String>,
+    /// File holding the password for wallet, such as a Docker or systemd secret
+    #[clap(long, env = "XELIS_TIPBOT_PASSWORD_FILE", conflicts_with = "password")]
+    password_file: Option<PathBuf>,
     /// Name for the wallet
     #[clap(short, long)]
     wallet_name: String,
-    /// Daemon address for wallet
-    #[clap(short, long, default_value_t = String::from(DEFAULT_DAEMON_ADDRESS))]
-    daemon_address: String,
+    /// Daemon address for wallet, repeat it or separate them by commas to add fallback daemons
+    /// The wallet switches to the next one when the daemon it's connected to can't be reached
+    #[clap(short, long, value_delimiter = ',', default_value = DEFAULT_DAEMON_ADDRESS)]
+    daemon_address: Vec<String>,
     /// Discord bot token
-    #[clap(long)]
-    discord_token: String,
+    #[clap(long, env = "XELIS_TIPBOT_DISCORD_TOKEN", hide_env_values = true, required_unless_present = "discord_token_file", conflicts_with = "discord_token_file")]
+    discord_token: Option<String>,
+    /// File holding the Discord bot token, such as a Docker or systemd secret
+    #[clap(long, env = "XELIS_TIPBOT_DISCORD_TOKEN_FILE", conflicts_with = "discord_token")]
+    discord_token_file: Option<PathBuf>,
+    /// Discord user IDs allowed to use the /admin commands, separated by commas
+    /// They are also alerted by DM when a withdrawal doesn't show up on-chain
+    /// The owners of the Discord application are always allowed
+    #[clap(long, value_delimiter = ',')]
+    discord_operators: Vec<u64>,
     /// Telegram bot token
-    #[clap(long)]
-    telegram_token: String,
+    #[clap(long, env = "XELIS_TIPBOT_TELEGRAM_TOKEN", hide_env_values = true, required_unless_present = "telegram_token_file", conflicts_with = "telegram_token_file")]
+    telegram_token: Option<String>,
+    /// File holding the Telegram bot token, such as a Docker or systemd secret
+    #[clap(long, env = "XELIS_TIPBOT_TELEGRAM_TOKEN_FILE", conflicts_with = "telegram_token")]
+    telegram_token_file: Option<PathBuf>,
+    /// URL of the Matrix homeserver of the bot account, such as https://matrix.org
+    /// The Matrix frontend is disabled if not set, it requires --matrix-user and a password
+    #[clap(long, requires = "matrix_user")]
+    matrix_homeserver: Option<String>,
+    /// Matrix user ID of the bot account, such as @tipbot:matrix.org
+    #[clap(long, requires = "matrix_homeserver")]
+    matrix_user: Option<String>,
+    /// Password of the Matrix bot account
+    #[clap(long, env = "XELIS_TIPBOT_MATRIX_PASSWORD", hide_env_values = true, conflicts_with = "matrix_password_file")]
+    matrix_password: Option<String>,
+    /// File holding the password of the Matrix bot account, such as a Docker or systemd secret
+    #[clap(long, env = "XELIS_TIPBOT_MATRIX_PASSWORD_FILE", conflicts_with = "matrix_password")]
+    matrix_password_file: Option<PathBuf>,
+    /// Address receiving the slash commands, events and installations of the Slack app, such as 127.0.0.1:8083
+    /// The Slack frontend is disabled if not set, it requires the client ID, client secret, signing secret and public URL of the app
+    #[clap(long, requires_all = ["slack_client_id", "slack_public_url"])]
+    slack_bind_address: Option<SocketAddr>,
+    /// Client ID of the Slack app, shown in its basic information
+    #[clap(long, requires = "slack_bind_address")]
+    slack_client_id: Option<String>,
+    /// URL at which Slack reaches --slack-bind-address, such as https://tipbot.example.com
+    /// The app is installed in a workspace from {url}/slack/install
+    #[clap(long, requires = "slack_bind_address")]
+    slack_public_url: Option<String>,
+    /// Client secret of the Slack app
+    #[clap(long, env = "XELIS_TIPBOT_SLACK_CLIENT_SECRET", hide_env_values = true, conflicts_with = "slack_client_secret_file")]
+    slack_client_secret: Option<String>,
+    /// File holding the client secret of the Slack app, such as a Docker or systemd secret
+    #[clap(long, env = "XELIS_TIPBOT_SLACK_CLIENT_SECRET_FILE", conflicts_with = "slack_client_secret")]
+    slack_client_secret_file: Option<PathBuf>,
+    /// Signing secret of the Slack app, it authenticates the requests sent by Slack
+    #[clap(long, env = "XELIS_TIPBOT_SLACK_SIGNING_SECRET", hide_env_values = true, conflicts_with = "slack_signing_secret_file")]
+    slack_signing_secret: Option<String>,
+    /// File holding the signing secret of the Slack app, such as a Docker or systemd secret
+    #[clap(long, env = "XELIS_TIPBOT_SLACK_SIGNING_SECRET_FILE", conflicts_with = "slack_signing_secret")]
+    slack_signing_secret_file: Option<PathBuf>,
+    /// Client ID of the Twitch application the bot token was created for
+    /// The Twitch frontend is disabled if not set, it requires --twitch-channels and a token
+    #[clap(long, requires = "twitch_channels")]
+    twitch_client_id: Option<String>,
+    /// User access token of the Twitch bot account, with the chat:read, chat:edit and user:manage:whispers scopes
+    #[clap(long, env = "XELIS_TIPBOT_TWITCH_TOKEN", hide_env_values = true, conflicts_with = "twitch_token_file")]
+    twitch_token: Option<String>,
+    /// File holding the token of the Twitch bot account, such as a Docker or systemd secret
+    #[clap(long, env = "XELIS_TIPBOT_TWITCH_TOKEN_FILE", conflicts_with = "twitch_token")]
+    twitch_token_file: Option<PathBuf>,
+    /// Logins of the streamers whose chat the bot joins, separated by commas
+    #[clap(long, value_delimiter = ',', requires = "twitch_client_id")]
+    twitch_channels: Vec<String>,
     /// Set log level
     #[clap(long, value_enum, default_value_t = LogLevel::Info)]
     log_level: LogLevel,
     /// Set file log level
     #[clap(long, value_enum, default_value_t = LogLevel::Info)]
     file_log_level: LogLevel,
+    /// Format of the log lines, json writes one object per line with the fields of the events
+    /// The interactive prompt and its CLI commands are disabled in json
+    #[clap(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
     /// Disable the log file
     #[clap(long)]
     disable_file_logging: bool,
@@ -145,6 +406,139 @@ pub struct Config {
     /// Concurrency configuration for Network Handler
     #[clap(long, default_value_t = detect_available_parallelism())]
     pub network_concurrency: usize,
+    /// Amount in XEL given on each faucet claim
+    /// The faucet is disabled if not set
+    #[clap(long)]
+    faucet_amount: Option<String>,
+    /// Minimum interval in seconds between two faucet claims of the same user
+    #[clap(long, default_value_t = 86400)]
+    faucet_interval: u64,
+    /// Price in XEL of a lottery ticket
+    /// The lottery is disabled if not set
+    #[clap(long)]
+    lottery_ticket_price: Option<String>,
+    /// Duration in seconds of a lottery round
+    #[clap(long, default_value_t = 7 * 24 * 60 * 60)]
+    lottery_interval: u64,
+    /// Time in seconds without any command after which a user holding a balance is reminded in DM
+    /// The reminders are disabled if not set
+    #[clap(long)]
+    reminder_inactivity: Option<u64>,
+    /// How often in seconds the dormant balances are checked
+    #[clap(long, default_value_t = 24 * 60 * 60)]
+    reminder_interval: u64,
+    /// Maximum XEL owed to all the users
+    /// Once reached, new XEL deposits are returned to their sender minus the fee
+    #[clap(long)]
+    max_liability: Option<String>,
+    /// XEL the bot can owe over its wallet balance before the withdrawals are locked, 1 XEL by default
+    /// The solvency is checked every minute and the operators are alerted when it's lost
+    #[clap(long)]
+    solvency_buffer: Option<String>,
+    /// XEL balance of the wallet under which the operators are alerted in DM
+    /// They are also alerted when the wallet goes offline or the event loop keeps restarting
+    #[clap(long)]
+    low_balance_alert: Option<String>,
+    /// Discord channel ID where the errors, failed withdrawals and panics are posted for the operators
+    /// The operator alerts are posted there too
+    #[clap(long)]
+    ops_discord_channel: Option<u64>,
+    /// Telegram chat ID where the errors, failed withdrawals and panics are sent for the operators
+    /// The operator alerts are sent there too
+    #[clap(long, allow_hyphen_values = true)]
+    ops_telegram_chat: Option<i64>,
+    /// Minimum amount in XEL of a tip, rains included
+    /// Communities can only configure a higher minimum
+    #[clap(long)]
+    min_tip: Option<String>,
+    /// Minimum amount in XEL of a withdrawal
+    #[clap(long)]
+    min_withdraw: Option<String>,
+    /// Maximum amount in XEL a user can withdraw over a rolling 24 hours
+    /// Users exempted with the add_exemption CLI command are not limited
+    #[clap(long)]
+    max_daily_withdraw: Option<String>,
+    /// Hours before an address saved by a user with the withdrawal whitelist on can be used,
+    /// and before the whitelist of a user is actually turned off
+    #[clap(long, default_value_t = 24)]
+    whitelist_delay: u64,
+    /// Commands a user can send in a row before being rate limited
+    /// Each command has its own limit, 0 disables the rate limiting
+    #[clap(long, default_value_t = 5)]
+    rate_limit_burst: u32,
+    /// Time in seconds needed to get back one command once rate limited
+    #[clap(long, default_value_t = 3)]
+    rate_limit_interval: u64,
+    /// API used to fetch the XELIS price
+    /// {currency} is replaced by the requested currency code
+    #[clap(long, default_value_t = String::from(DEFAULT_PRICE_API))]
+    price_api: String,
+    /// Fiat currencies shown by the price command, separated by commas
+    #[clap(long, value_delimiter = ',', default_value = DEFAULT_CURRENCY)]
+    price_currencies: Vec<String>,
+    /// URL receiving the balance journal, such as the write API of InfluxDB
+    /// The export is disabled if not set
+    #[clap(long)]
+    export_url: Option<String>,
+    /// Value of the Authorization header of the export requests, such as "Token <token>"
+    #[clap(long)]
+    export_authorization: Option<String>,
+    /// Format of the exported journal entries
+    #[clap(long, value_enum, default_value_t = ExportFormat::Influx)]
+    export_format: ExportFormat,
+    /// How often in seconds the new journal entries are exported
+    #[clap(long, default_value_t = 10)]
+    export_interval: u64,
+    /// CSV file of balances migrated from another tip bot, credited at startup
+    /// It needs the platform, user_id and amount columns, a file is never imported twice
+    #[clap(long)]
+    migrate_from: Option<PathBuf>,
+    /// Checksum of the migrated file as printed by export_balances or b3sum
+    #[clap(long)]
+    migrate_checksum: Option<String>,
+    /// Lease file shared by the instances of the bot, only the instance holding it processes
+    /// deposits and withdrawals while the others serve read-only commands and wait to take over
+    #[clap(long)]
+    lease_file: Option<PathBuf>,
+    /// Seconds without renewal after which the lease can be taken by another instance
+    #[clap(long, default_value_t = 15)]
+    lease_ttl: u64,
+    /// Address of the health endpoints, such as 0.0.0.0:8080
+    /// GET /health fails when the event loop is stalled, GET /ready when the wallet isn't synced with the daemon
+    /// The endpoints are disabled if not set
+    #[clap(long)]
+    health_bind_address: Option<SocketAddr>,
+    /// Blocks the wallet can be behind the daemon and still be ready
+    #[clap(long, default_value_t = DEFAULT_MAX_SYNC_LAG)]
+    health_max_sync_lag: u64,
+    /// Seconds without any event after which the event loop is considered stalled
+    /// The systemd watchdog isn't pinged anymore once it is, when WatchdogSec= is set
+    #[clap(long, default_value_t = DEFAULT_MAX_EVENT_AGE)]
+    health_max_event_age: u64,
+    /// Address of the REST admin API, such as 127.0.0.1:8081
+    /// The API is disabled if not set, it requires --admin-api-token
+    #[clap(long, requires = "admin_api_token")]
+    admin_api_bind_address: Option<SocketAddr>,
+    /// Token expected in the Authorization header of the admin API requests, as "Bearer <token>"
+    #[clap(long, env = "XELIS_TIPBOT_ADMIN_API_TOKEN", hide_env_values = true)]
+    admin_api_token: Option<String>,
+    /// Address of the JSON-RPC server, such as 127.0.0.1:8082
+    /// Other services can read balances, transfer and withdraw through it, it requires --rpc-username and --rpc-password
+    #[clap(long, requires_all = ["rpc_username", "rpc_password"])]
+    rpc_bind_address: Option<SocketAddr>,
+    /// Username of the HTTP basic authentication of the JSON-RPC server
+    #[clap(long, env = "XELIS_TIPBOT_RPC_USERNAME")]
+    rpc_username: Option<String>,
+    /// Password of the HTTP basic authentication of the JSON-RPC server
+    #[clap(long, env = "XELIS_TIPBOT_RPC_PASSWORD", hide_env_values = true)]
+    rpc_password: Option<String>,
+    /// Name of this instance in the lease, the process ID by default
+    #[clap(long)]
+    instance_id: Option<String>,
+    /// Page of a TX in the block explorer, linked when a withdrawal is confirmed
+    /// {hash} is replaced by the TX hash, the official explorer of the network by default
+    #[clap(long)]
+    explorer_url: Option<String>,
 }
 
 #[derive(BotCommands, Clone)]
@@ -158,21 +552,91 @@ pub enum TelegramCommand {
     Status,
     #[command(description = "display your balance.")]
     Balance,
+    #[command(description = "display your movements between two dates: <from> <to> [csv], dates as YYYY-MM-DD.")]
+    Statement { args: String },
     #[command(description = "display your deposit address.")]
     Deposit,
-    #[command(description = "withdraw from your balance.", parse_with = "split")]
-    Withdraw { address: String, amount: f64 },
-    #[command(description = "tip the user to which you reply")]
-    Tip { amount: f64 },
+    #[command(description = "find your balance in the latest proof of reserves.")]
+    Reserves,
+    #[command(description = "withdraw from your balance: <address|label> <amount> [asset].")]
+    Withdraw { args: String },
+    #[command(description = "your saved withdrawal addresses: [add <label> <address>|remove <label>|list].")]
+    Address { args: String },
+    #[command(description = "tip the user to which you reply: <amount> [asset].")]
+    Tip { amount: String },
+    #[command(description = "tip a user by mention: <@username> <amount> [asset].", parse_with = split_mention)]
+    TipUser { username: String, amount: String },
+    #[command(description = "post a button others can press to tip you: [amount].")]
+    TipMe { amount: String },
+    #[command(description = "split an amount between the last active users of the group.", parse_with = "split")]
+    Rain { amount: String, count: u8 },
+    #[command(description = "start a giveaway: amount, duration in minutes and max claims.", parse_with = "split")]
+    Giveaway { amount: String, duration: u32, max_claims: u16 },
+    #[command(description = "mirror the announcements of the group in a channel (admins only): <channel> [spoiler] or off.")]
+    Announcements { args: String },
+    #[command(description = "show the monthly tipping goal of the group, or set it (admins only): <target> [reward] [message] or off.")]
+    Goal { args: String },
+    #[command(description = "claim free XELIS from the faucet.")]
+    Faucet,
+    #[command(description = "show the top tippers: [day|week|all] [tippers|receivers].")]
+    Leaderboard { args: String },
+    #[command(description = "challenge the user to which you reply to a coinflip, the winner takes both stakes.")]
+    Coinflip { amount: String },
+    #[command(description = "start a dice duel, reply to a user to challenge only them.")]
+    Dice { amount: String },
+    #[command(description = "convert between XEL and a fiat currency: <amount> <xel|currency> [currency].")]
+    Convert { args: String },
+    #[command(description = "show the XELIS price with its 24h change and volume: [currency].")]
+    Price { args: String },
+    #[command(description = "show the running lottery round, or buy tickets with: buy <count>.")]
+    Lottery { args: String },
+    #[command(description = "learn the commands with a sandbox balance: [balance|tip <amount>|withdraw <address> <amount>|reset].")]
+    Practice { args: String },
+    #[command(description = "reminders sent when your balance is left unused: [on|off].")]
+    Reminders { args: String },
+    #[command(description = "get a reminder in DM later, or send one to a user: <@username|me> in <delay> about <memo> [amount], delay as 30m, 12h, 3d or 2w.")]
+    Remind { args: String },
+    #[command(description = "delete all your data once your balances are withdrawn to an address or label, or donated to the faucet: <address|label|donate> [pin].")]
+    Forgetme { args: String },
+    #[command(description = "your personal settings: budget [daily|weekly <amount|off>], whitelist [on|off], pin <new pin|off> [current pin], or the settings of the group (admins only): [tips public|private] [mintip <amount|off>] [bot on|off] [recipients off|low|medium|high].")]
+    Settings { args: String },
 }
 
 impl TelegramCommand {
     pub fn allow_public(&self) -> bool {
         match self {
             TelegramCommand::Tip { amount: _ } => true,
+            TelegramCommand::TipUser { username: _, amount: _ } => true,
+            TelegramCommand::TipMe { amount: _ } => true,
+            TelegramCommand::Rain { amount: _, count: _ } => true,
+            TelegramCommand::Giveaway { amount: _, duration: _, max_claims: _ } => true,
+            TelegramCommand::Announcements { args: _ } => true,
+            TelegramCommand::Goal { args: _ } => true,
+            TelegramCommand::Leaderboard { args: _ } => true,
+            TelegramCommand::Coinflip { amount: _ } => true,
+            TelegramCommand::Dice { amount: _ } => true,
+            TelegramCommand::Convert { args: _ } => true,
+            TelegramCommand::Price { args: _ } => true,
+            TelegramCommand::Lottery { args: _ } => true,
+            TelegramCommand::Practice { args: _ } => true,
+            TelegramCommand::Remind { args: _ } => true,
+            TelegramCommand::Settings { args: _ } => true,
             _ => false
         }
     }
+
+    // Feature of the command that can be disabled by a group
+    pub fn feature(&self) -> Option<Feature> {
+        match self {
+            TelegramCommand::Rain { amount: _, count: _ } => Some(Feature::Rain),
+            TelegramCommand::Giveaway { amount: _, duration: _, max_claims: _ } => Some(Feature::Giveaway),
+            TelegramCommand::Coinflip { amount: _ } => Some(Feature::Games),
+            TelegramCommand::Dice { amount: _ } => Some(Feature::Games),
+            TelegramCommand::Lottery { args: _ } => Some(Feature::Lottery),
+            TelegramCommand::Faucet => Some(Feature::Faucet),
+            _ => None
+        }
+    }
 }
 
 #[tokio::main]
@@ -183,18 +647,191 @@ async fn main() -> Result<()> {
 
     xelis_common::config::init();
 
-    let mut config = Config::parse();
+    let Some(mut config) = load_config()? else {
+        return Ok(());
+    };
+
+    // Logs are configured first so every startup phase is reported
+    config.logs_modules.push(ModuleConfig { module: "serenity".to_string(), level: LogLevel::Warn });
+    // The JSON logger replaces the one of the prompt, so the bot runs without it
+    let prompt = match config.log_format {
+        LogFormat::Text => Some(Prompt::new(
+            config.log_level,
+            &config.logs_path,
+            &config.filename_log,
+            config.disable_file_logging,
+            config.disable_file_log_date_based,
+            config.disable_log_color,
+            config.auto_compress_logs,
+            !config.disable_interactive_mode,
+            config.logs_modules,
+            config.file_log_level,
+            true,
+            config.datetime_format,
+        ).context("Couldn't configure the logs")?),
+        LogFormat::Json => {
+            let file = (!config.disable_file_logging).then(|| LogFileConfig {
+                path: config.logs_path.clone(),
+                filename: config.filename_log.clone(),
+                date_based: !config.disable_file_log_date_based,
+                level: config.file_log_level.into()
+            });
+            JsonLogger::new(config.log_level.into(), config.logs_modules, file).init()
+                .context("Couldn't configure the logs")?;
+            None
+        }
+    };
+
+    // Secrets given as files are read once at startup
+    let password = read_secret(config.password, config.password_file.as_deref())
+        .context("Couldn't read the wallet password, check --password-file")?;
+    let discord_token = read_secret(config.discord_token, config.discord_token_file.as_deref())
+        .context("Couldn't read the Discord token, check --discord-token-file")?;
+    let telegram_token = read_secret(config.telegram_token, config.telegram_token_file.as_deref())
+        .context("Couldn't read the Telegram token, check --telegram-token-file")?;
+    let matrix_config = match (config.matrix_homeserver, config.matrix_user) {
+        (Some(homeserver), Some(user)) => Some(MatrixConfig {
+            homeserver,
+            user,
+            password: read_secret(config.matrix_password, config.matrix_password_file.as_deref())
+                .context("Couldn't read the Matrix password, check --matrix-password-file")?
+        }),
+        _ => None
+    };
+    let slack_config = match (config.slack_bind_address, config.slack_client_id, config.slack_public_url) {
+        (Some(address), Some(client_id), Some(public_url)) => Some(SlackConfig {
+            address,
+            client_id,
+            client_secret: read_secret(config.slack_client_secret, config.slack_client_secret_file.as_deref())
+                .context("Couldn't read the Slack client secret, check --slack-client-secret-file")?,
+            signing_secret: read_secret(config.slack_signing_secret, config.slack_signing_secret_file.as_deref())
+                .context("Couldn't read the Slack signing secret, check --slack-signing-secret-file")?,
+            public_url: public_url.trim_end_matches('/').to_string()
+        }),
+        _ => None
+    };
+    let twitch_config = match config.twitch_client_id {
+        Some(client_id) => Some(TwitchConfig {
+            client_id,
+            token: read_secret(config.twitch_token, config.twitch_token_file.as_deref())
+                .context("Couldn't read the Twitch token, check --twitch-token-file")?,
+            channels: config.twitch_channels
+        }),
+        None => None
+    };
+
+    let faucet_config = match config.faucet_amount.as_deref() {
+        Some(amount) => Some(FaucetConfig {
+            amount: parse_amount(amount).and_then(Amount::exact)?,
+            interval: Duration::from_secs(config.faucet_interval)
+        }),
+        None => None
+    };
+
+    let lottery_config = match config.lottery_ticket_price.as_deref() {
+        Some(price) => Some(LotteryConfig {
+            ticket_price: parse_amount(price).and_then(Amount::exact)?,
+            interval: Duration::from_secs(config.lottery_interval)
+        }),
+        None => None
+    };
+
+    let runtime = runtime_settings(&config)?;
+
+    let operators = config.discord_operators.iter()
+        .map(|id| discord_id(*id))
+        .collect::<Result<_, _>>()?;
+
+    let reminder_config = config.reminder_inactivity.map(|inactivity| ReminderConfig {
+        inactivity: Duration::from_secs(inactivity),
+        interval: Duration::from_secs(config.reminder_interval)
+    });
+
+    // Telegram notifications are created before the service to report their queue
+    let bot = Bot::new(telegram_token);
+    let api_errors = ApiErrorMonitor::new();
+    let notifier: TelegramNotifications = TelegramNotifier::new(bot.clone(), api_errors.clone());
 
-    // Init wallet service
+    // Phase 1: open the wallet and connect it to the daemon
+    info!("Opening wallet {}", config.wallet_name);
+    notify_status("Opening the wallet and connecting to the daemon");
     let service = WalletServiceImpl::new(
         &config.wallet_name,
-        &config.password,
+        &password,
         config.daemon_address,
         config.network,
         config.n_decryption_threads,
-        config.network_concurrency
+        config.network_concurrency,
+        faucet_config,
+        lottery_config,
+        reminder_config,
+        runtime,
+        config.discord_operators.clone(),
+        notifier.clone(),
+        api_errors,
+        PriceFeed::new(config.price_api, config.price_currencies)
     ).await?;
 
+    // With leader election, the instance stays on standby until it holds the lease
+    let leader_election = config.lease_file.map(|path| LeaderElection::new(LeaseConfig {
+        path,
+        instance: config.instance_id.unwrap_or_else(|| format!("pid-{}", std::process::id())),
+        ttl: Duration::from_secs(config.lease_ttl)
+    }));
+    if leader_election.is_some() {
+        service.set_leader(false);
+    }
+
+    // Phase 2: check the stored data before the bot serves anyone
+    info!("Checking storage");
+    notify_status("Checking storage");
+    service.check_storage().await?;
+
+    // Balances migrated from another tip bot are credited before anyone can spend them
+    if let Some(path) = config.migrate_from {
+        migrate_balances(&service, &path, config.migrate_checksum.as_deref()).await?;
+    }
+
+    // Phase 3: check the platform tokens, then build their clients
+    info!("Checking platform tokens");
+    notify_status("Checking platform tokens");
+    check_platform_tokens(&discord_token, &bot).await?;
+
+    // Matrix users are sent their notifications once the client is set
+    let matrix_bot = match matrix_config {
+        Some(matrix_config) => {
+            let matrix_bot = MatrixBot::login(matrix_config).await?;
+            service.set_matrix_client(matrix_bot.client());
+            Some(matrix_bot)
+        },
+        None => None
+    };
+
+    // Slack users are sent their notifications through the Web API of the server
+    let slack_server = slack_config.map(SlackServer::new);
+    if let Some(slack_server) = slack_server.as_ref() {
+        service.set_slack_api(slack_server.api());
+    }
+
+    // Twitch users are whispered their notifications through the Helix API
+    let twitch_bot = match twitch_config {
+        Some(twitch_config) => {
+            let twitch_bot = TwitchBot::login(twitch_config).await?;
+            service.set_twitch_api(twitch_bot.api());
+            Some(twitch_bot)
+        },
+        None => None
+    };
+
+    if let Some(url) = config.export_url {
+        LedgerExporter::new(ExporterConfig {
+            url,
+            authorization: config.export_authorization,
+            format: config.export_format,
+            interval: Duration::from_secs(config.export_interval)
+        }).start(Arc::clone(&service));
+    }
+
     // Init discord bot
     let mut discord_client = {
         let intents = GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT;
@@ -204,7 +841,35 @@ async fn main() -> Result<()> {
             let service = service.clone();
             poise::Framework::builder()
                 .options(poise::FrameworkOptions {
-                    commands: vec![status(), balance(), deposit(), withdraw(), tip()],
+                    commands: vec![status(), balance(), deposit(), reserves(), withdraw(), address(), tip(), tip_user(), tipme(), rain(), giveaway(), goal(), top_tipper_reward(), recipient_filters(), admin(), faucet(), leaderboard(), coinflip(), dice(), convert(), price(), lottery(), practice(), statement(), reminders(), remind(), settings(), forgetme()],
+                    event_handler: |ctx, event, framework, data| {
+                        Box::pin(discord_event_handler(ctx, event, framework, data))
+                    },
+                    owners: operators,
+                    command_check: Some(|ctx| Box::pin(discord_command_check(ctx))),
+                    on_error: |error| Box::pin(async move {
+                        // Users refused by the command check were already answered
+                        if let poise::FrameworkError::CommandCheckFailed { error: None, .. } = error {
+                            return;
+                        }
+
+                        if let poise::FrameworkError::Command { error: e, ctx, .. } = &error {
+                            ctx.data().api_errors().record_error(e);
+                        }
+
+                        if let Err(e) = poise::builtins::on_error(error).await {
+                            error!("Error while handling error: {}", e);
+                        }
+                    }),
+                    pre_command: |ctx| Box::pin(async move {
+                        if !ctx.data().is_leader() {
+                            return;
+                        }
+
+                        if let Err(e) = ctx.data().record_user_activity(&UserApplication::Discord(ctx.author().id.into())).await {
+                            warn!("Couldn't record activity of {}: {}", ctx.author().id, e);
+                        }
+                    }),
                     ..Default::default()
                 })
                 .setup(|ctx, _ready, framework| {
@@ -217,66 +882,158 @@ async fn main() -> Result<()> {
         };
     
         // Create the client using token and intents
-        ClientBuilder::new(config.discord_token, intents)
+        ClientBuilder::new(discord_token, intents)
             .framework(framework)
             .await?
     };
 
+    // Panics are logged by the default hook, then forwarded to the ops channels
+    {
+        let service = service.clone();
+        let http = discord_client.http.clone();
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+            error!("Panic: {}", info);
+            if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                let service = service.clone();
+                let http = http.clone();
+                let alert = Alert::new("Panic", info.to_string(), Colour::RED);
+                handle.spawn(async move {
+                    service.report_to_ops(&http, &alert).await;
+                });
+            }
+        }));
+    }
+
+    // Phase 4: the event loop starts once every platform can send its notifications
+    info!("Starting wallet service");
+    notify_status("Starting wallet service");
+    Arc::clone(&service).start(discord_client.http.clone(), bot.clone()).await?;
+    #[cfg(unix)]
+    reload_on_hangup(Arc::clone(&service))?;
+
+    // Without WatchdogSec= in the unit, nothing is pinged
+    SystemdWatchdog::new(WatchdogConfig {
+        max_event_age: Duration::from_secs(config.health_max_event_age)
+    }).start(Arc::clone(&service));
+
+    if let Some(address) = config.health_bind_address {
+        HealthServer::new(HealthConfig {
+            address,
+            max_sync_lag: config.health_max_sync_lag,
+            max_event_age: Duration::from_secs(config.health_max_event_age)
+        }).start(Arc::clone(&service)).await?;
+    }
+
+    if let (Some(address), Some(token)) = (config.admin_api_bind_address, config.admin_api_token) {
+        AdminApi::new(AdminApiConfig {
+            address,
+            token
+        }).start(Arc::clone(&service)).await?;
+    }
+
+    if let (Some(address), Some(username), Some(password)) = (config.rpc_bind_address, config.rpc_username, config.rpc_password) {
+        JsonRpcServer::new(JsonRpcConfig {
+            address,
+            username,
+            password
+        }).start(Arc::clone(&service)).await?;
+    }
+
+    if let Some(slack_server) = slack_server {
+        slack_server.start(Arc::clone(&service)).await?;
+    }
+
     // Telegram bot
-    let (telegram_client, bot) = {
-        let bot = Bot::new(config.telegram_token);
-        let instance = bot.clone();
+    let telegram_client = {
+        let bot = bot.clone();
         let service = service.clone();
-        let handle = tokio::spawn(async move {
-            let handler = Update::filter_message()
+        tokio::spawn(async move {
+            let confirmations: TelegramConfirmations = Arc::new(PendingConfirmations::new());
+            let activity: TelegramActivity = Arc::new(ActivityTracker::new());
+            let pins: TelegramPinRequests = Arc::new(PendingConfirmations::new());
+            let rains: TelegramRainPreviews = Arc::new(PendingConfirmations::new());
+            let handler = dptree::entry()
                 .branch(
-                    dptree::entry()
+                    Update::filter_message()
+                        .inspect_async(register_telegram_chat)
+                        .inspect_async(record_telegram_activity)
+                        .inspect_async(record_telegram_usernames)
                         .filter_command::<TelegramCommand>()
                         .endpoint(telegram_handler)
+                )
+                .branch(
+                    Update::filter_message()
+                        .filter(|msg: Message| msg.chat.is_private())
+                        .inspect_async(register_telegram_chat)
+                        .endpoint(telegram_pin_handler)
+                )
+                .branch(
+                    Update::filter_callback_query()
+                        .endpoint(telegram_callback_handler)
+                )
+                .branch(
+                    Update::filter_my_chat_member()
+                        .endpoint(telegram_my_chat_member_handler)
                 );
     
+            // Telegram only lets one instance receive the updates
+            while !service.is_leader() {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+
+            // Failed updates are logged as usual and counted with the Telegram API errors
+            let error_service = Arc::clone(&service);
+            let error_handler = move |e: Error| {
+                let service = Arc::clone(&error_service);
+                async move {
+                    service.api_errors().record_error(&e);
+                    error!("An error has occurred in the dispatcher: {:?}", e);
+                }
+            };
+
             Dispatcher::builder(bot, handler)
-                .dependencies(dptree::deps![service])
+                .dependencies(dptree::deps![service, confirmations, pins, rains, activity, notifier])
+                .error_handler(Arc::new(error_handler))
                 .enable_ctrlc_handler()
                 .build()
                 .dispatch().await
-        });
-
-        (handle, instance)
+        })
     };
 
-    // start the service
-    Arc::clone(&service).start(discord_client.http.clone(), bot).await?;
-
-    config.logs_modules.push(ModuleConfig { module: "serenity".to_string(), level: LogLevel::Warn });
-    let prompt = Prompt::new(
-        config.log_level,
-        &config.logs_path,
-        &config.filename_log,
-        config.disable_file_logging,
-        config.disable_file_log_date_based,
-        config.disable_log_color,
-        config.auto_compress_logs,
-        !config.disable_interactive_mode,
-        config.logs_modules,
-        config.file_log_level,
-        true,
-        config.datetime_format,
-    )?;
-
-    let command_manager = CommandManager::new(prompt.clone());
-    command_manager.store_in_context(service)?;
+    // Matrix bot
+    let matrix_client = matrix_bot.map(|matrix_bot| matrix_bot.start(Arc::clone(&service)));
+    let matrix_client = async move {
+        match matrix_client {
+            Some(client) => client.await,
+            None => std::future::pending().await
+        }
+    };
 
-    command_manager.register_default_commands()?;
-    command_manager.add_command(Command::new("rescan", "Rescan the wallet", CommandHandler::Async(async_handler!(rescan))))?;
-    command_manager.add_command(Command::new("clear_balances", "Clear all balances", CommandHandler::Async(async_handler!(clear_balances))))?;
-    command_manager.add_command(Command::with_required_arguments("add_balance_discord", "Add balance to a discord user", vec![Arg::new("user_id", ArgType::Number), Arg::new("amount", ArgType::String)], CommandHandler::Async(async_handler!(add_balance_discord))))?;
-    command_manager.add_command(Command::with_required_arguments("remove_balance_discord", "Remove balance from a discord user", vec![Arg::new("user_id", ArgType::Number), Arg::new("amount", ArgType::String)], CommandHandler::Async(async_handler!(remove_balance_discord))))?;
+    // Twitch bot
+    let twitch_client = twitch_bot.map(|twitch_bot| twitch_bot.start(Arc::clone(&service)));
+    let twitch_client = async move {
+        match twitch_client {
+            Some(client) => client.await,
+            None => std::future::pending().await
+        }
+    };
 
-    command_manager.add_command(Command::with_required_arguments("withdraw", "Withdraw an amount to an address", vec![Arg::new("address", ArgType::String), Arg::new("amount", ArgType::String)], CommandHandler::Async(async_handler!(withdraw_cmd))))?;
-    command_manager.add_command(Command::with_required_arguments("withdraw_all", "Withdraw the whole balance to an address", vec![Arg::new("address", ArgType::String)], CommandHandler::Async(async_handler!(withdraw_all))))?;
+    let lease_service = Arc::clone(&service);
+    let command_manager = match prompt.clone() {
+        Some(prompt) => Some(build_command_manager(prompt, service)?),
+        None => None
+    };
+    info!("Tip bot is ready");
+    notify_ready();
 
-    command_manager.display_commands()?;
+    let leader_election = async move {
+        match leader_election {
+            Some(election) => election.run(lease_service).await,
+            None => std::future::pending().await
+        }
+    };
 
     tokio::select! {
         // start listening for events by starting a single shard
@@ -288,458 +1045,6062 @@ async fn main() -> Result<()> {
         _ = telegram_client => {
             error!("Telegram client stopped");
         },
-        res = prompt.start(Duration::from_millis(1000), Box::new(async_handler!(prompt_message_builder)), Some(&command_manager)) => {
+        _ = matrix_client => {
+            error!("Matrix client stopped");
+        },
+        _ = twitch_client => {
+            error!("Twitch client stopped");
+        },
+        res = leader_election => {
+            if let Err(e) = res {
+                error!("Stopping as this instance isn't the leader anymore: {:?}", e);
+            }
+        },
+        res = run_prompt(prompt, command_manager) => {
             if let Err(e) = res {
                 error!("An error occurred while running the prompt: {:?}", e);
             }
         }
     };
 
+    notify_stopping();
     Ok(())
 }
 
-// Default prompt message builder
-async fn prompt_message_builder(_: &Prompt, _: Option<&CommandManager>) -> Result<String, PromptError> {
-    Ok("XELIS Tip Bot >>".to_string())
+// Register the CLI commands of the prompt
+fn build_command_manager(prompt: ShareablePrompt, service: WalletService) -> Result<CommandManager> {
+    let command_manager = CommandManager::new(prompt);
+    command_manager.store_in_context(service)?;
+
+    command_manager.register_default_commands()?;
+    command_manager.add_command(Command::new("rescan", "Rescan the wallet", CommandHandler::Async(async_handler!(rescan))))?;
+    command_manager.add_command(Command::new("queues", "Show the depth of the processing queues", CommandHandler::Async(async_handler!(queues))))?;
+    command_manager.add_command(Command::new("verify_storage", "Check that every stored entry can be decoded and quarantine the corrupt ones", CommandHandler::Async(async_handler!(verify_storage))))?;
+    command_manager.add_command(Command::new("ledger", "Show the balances derived from the ledger and the stored ones that don't match it", CommandHandler::Async(async_handler!(ledger))))?;
+    command_manager.add_command(Command::new("audit", "Recompute the balances from the ledger and the deposits and repair the stored ones", CommandHandler::Async(async_handler!(audit))))?;
+    command_manager.add_command(Command::new("clear_balances", "Clear all balances", CommandHandler::Async(async_handler!(clear_balances))))?;
+    command_manager.add_command(Command::with_required_arguments("credit", "Credit XEL to a user, asking for the reason", vec![Arg::new("platform", ArgType::String), Arg::new("id", ArgType::Number), Arg::new("amount", ArgType::String)], CommandHandler::Async(async_handler!(credit))))?;
+    command_manager.add_command(Command::with_required_arguments("debit", "Debit XEL from a user, asking for the reason", vec![Arg::new("platform", ArgType::String), Arg::new("id", ArgType::Number), Arg::new("amount", ArgType::String)], CommandHandler::Async(async_handler!(debit))))?;
+
+    command_manager.add_command(Command::with_required_arguments("add_exemption", "Exempt a user or a Discord role from cooldowns and limits", vec![Arg::new("kind", ArgType::String), Arg::new("id", ArgType::Number)], CommandHandler::Async(async_handler!(add_exemption))))?;
+    command_manager.add_command(Command::with_required_arguments("remove_exemption", "Remove an exemption", vec![Arg::new("kind", ArgType::String), Arg::new("id", ArgType::Number)], CommandHandler::Async(async_handler!(remove_exemption))))?;
+    command_manager.add_command(Command::new("failed_withdrawals", "List the withdrawals rolled back after the daemon rejected their TX", CommandHandler::Async(async_handler!(failed_withdrawals))))?;
+    command_manager.add_command(Command::new("notifications", "List the DM notifications waiting to be sent again", CommandHandler::Async(async_handler!(notifications))))?;
+    command_manager.add_command(Command::new("flush_notifications", "Send every queued DM notification again now, the undeliverable ones included", CommandHandler::Async(async_handler!(flush_notifications))))?;
+    command_manager.add_command(Command::new("drop_notifications", "Delete the undeliverable DM notifications", CommandHandler::Async(async_handler!(drop_notifications))))?;
+    command_manager.add_command(Command::new("list_exemptions", "List the users and roles exempted from cooldowns and limits", CommandHandler::Async(async_handler!(list_exemptions))))?;
+    command_manager.add_command(Command::with_required_arguments("freeze", "Block the transfers and withdrawals of a user", vec![Arg::new("platform", ArgType::String), Arg::new("id", ArgType::Number)], CommandHandler::Async(async_handler!(freeze))))?;
+    command_manager.add_command(Command::with_required_arguments("unfreeze", "Allow a frozen user to transfer and withdraw again", vec![Arg::new("platform", ArgType::String), Arg::new("id", ArgType::Number)], CommandHandler::Async(async_handler!(unfreeze))))?;
+    command_manager.add_command(Command::new("list_frozen", "List the frozen users", CommandHandler::Async(async_handler!(list_frozen))))?;
+    command_manager.add_command(Command::new("reload", "Read the config file again and apply the limits, cooldowns, ops channels and explorer URL", CommandHandler::Async(async_handler!(reload))))?;
+    command_manager.add_command(Command::with_optional_arguments("list_balances", "List the XEL balances of the users, highest first", vec![Arg::new("page", ArgType::Number)], CommandHandler::Async(async_handler!(list_balances))))?;
+    command_manager.add_command(Command::with_required_arguments("export_balances", "Write the balances of the users with their last activity to a CSV file", vec![Arg::new("path", ArgType::String)], CommandHandler::Async(async_handler!(export_balances))))?;
+    command_manager.add_command(Command::with_required_arguments("import_balances", "Credit the balances of a CSV file migrated from another tip bot", vec![Arg::new("path", ArgType::String)], CommandHandler::Async(async_handler!(import_balances))))?;
+    command_manager.add_command(Command::with_optional_arguments("audit_log", "Verify the signed log of the balance changes and show its latest records, of a user if set", vec![Arg::new("platform", ArgType::String), Arg::new("id", ArgType::Number)], CommandHandler::Async(async_handler!(audit_log))))?;
+    command_manager.add_command(Command::with_required_arguments("user", "Show the balances, deposits and latest movements of a user", vec![Arg::new("platform", ArgType::String), Arg::new("id", ArgType::Number)], CommandHandler::Async(async_handler!(user_lookup))))?;
+    command_manager.add_command(Command::new("lottery_draw", "Draw the running lottery round now", CommandHandler::Async(async_handler!(lottery_draw))))?;
+    command_manager.add_command(Command::new("lottery_refund", "Refund all tickets of the running lottery round and start a new one", CommandHandler::Async(async_handler!(lottery_refund))))?;
+    command_manager.add_command(Command::with_required_arguments("add_faucet_balance", "Add balance to the faucet", vec![Arg::new("amount", ArgType::String)], CommandHandler::Async(async_handler!(add_faucet_balance))))?;
+    command_manager.add_command(Command::with_required_arguments("withdraw", "Withdraw an amount to an address", vec![Arg::new("address", ArgType::String), Arg::new("amount", ArgType::String)], CommandHandler::Async(async_handler!(withdraw_cmd))))?;
+    command_manager.add_command(Command::with_required_arguments("proof_of_reserves", "Take a signed proof of reserves and write it to a JSON file to publish", vec![Arg::new("path", ArgType::String)], CommandHandler::Async(async_handler!(proof_of_reserves))))?;
+    command_manager.add_command(Command::new("unknown_deposits", "List the deposits that couldn't be credited to any user", CommandHandler::Async(async_handler!(unknown_deposits))))?;
+    command_manager.add_command(Command::with_required_arguments("refund_deposit", "Send the transfers of an unknown deposit back to its sender", vec![Arg::new("tx_hash", ArgType::String)], CommandHandler::Async(async_handler!(refund_deposit))))?;
+    command_manager.add_command(Command::with_required_arguments("return_deposit", "Send a deposit back to the address it came from", vec![Arg::new("tx_hash", ArgType::String)], CommandHandler::Async(async_handler!(return_deposit))))?;
+    command_manager.add_command(Command::with_required_arguments("withdraw_all", "Withdraw the whole balance to an address", vec![Arg::new("address", ArgType::String)], CommandHandler::Async(async_handler!(withdraw_all))))?;
+
+    command_manager.display_commands()?;
+
+    Ok(command_manager)
 }
 
-// Rescan CLI command
-async fn rescan(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
-    let context = manager.get_context().lock()?;
-    let service: &WalletService = context.get()?;
-    if let Err(e) = service.rescan().await {
-        manager.error(format!("An error occurred while rescanning the wallet: {}", e.to_string()));
-    } else {
-        manager.message("Wallet has been rescanned");
+// Run the prompt until it stops, without it the bot runs until Ctrl+C
+async fn run_prompt(prompt: Option<ShareablePrompt>, command_manager: Option<CommandManager>) -> Result<(), PromptError> {
+    let (Some(prompt), Some(command_manager)) = (prompt, command_manager) else {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Couldn't listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+
+        return Ok(());
+    };
+
+    prompt.start(Duration::from_millis(1000), Box::new(async_handler!(prompt_message_builder)), Some(&command_manager)).await
+}
+
+// Parse the command line over the options of the config file
+// None when only a config template was asked
+fn load_config() -> Result<Option<Config>> {
+    // First pass for the config file and the flags given, the ones the file may hold aren't required yet
+    let matches = Config::command().ignore_errors(true).get_matches();
+    if matches.get_flag("generate_config") {
+        let Some(path) = matches.get_one::<PathBuf>("config_file") else {
+            bail!("--generate-config requires --config-file");
+        };
+
+        config_file::write_template(&Config::command(), path)?;
+        println!("Config template written to {}", path.display());
+        return Ok(None);
     }
 
+    let args = config_args(&matches)?;
+    Ok(Some(Config::try_parse_from(args).unwrap_or_else(|e| e.exit())))
+}
+
+// Parse the command line and the config file again, the file may have changed since the start
+fn reload_config() -> Result<Config> {
+    let matches = Config::command().ignore_errors(true).try_get_matches()?;
+    Ok(Config::try_parse_from(config_args(&matches)?)?)
+}
+
+// Arguments of the command line, preceded by the options of the config file if any
+fn config_args(matches: &ArgMatches) -> Result<Vec<OsString>> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let Some(path) = matches.get_one::<PathBuf>("config_file") else {
+        return Ok(args);
+    };
+
+    // The file options are placed before the command line ones, which they never repeat
+    let file_args = config_file::file_args(&Config::command(), matches, path)?;
+    let mut args = args.into_iter();
+    Ok(args.next().into_iter()
+        .chain(file_args)
+        .chain(args)
+        .collect())
+}
+
+// Settings that can change while the bot runs, read at startup and by a reload
+fn runtime_settings(config: &Config) -> Result<RuntimeSettings> {
+    let exact_amount = |amount: Option<&str>| amount.map(|amount| parse_amount(amount).and_then(Amount::exact)).transpose();
+
+    Ok(RuntimeSettings {
+        max_liability: exact_amount(config.max_liability.as_deref())?,
+        solvency_buffer: exact_amount(config.solvency_buffer.as_deref())?.unwrap_or(DEFAULT_SOLVENCY_BUFFER),
+        low_balance_alert: exact_amount(config.low_balance_alert.as_deref())?,
+        min_tip: exact_amount(config.min_tip.as_deref())?.unwrap_or(0),
+        min_withdraw: exact_amount(config.min_withdraw.as_deref())?.unwrap_or(0),
+        max_daily_withdraw: exact_amount(config.max_daily_withdraw.as_deref())?,
+        whitelist_delay: config.whitelist_delay * 60 * 60,
+        rate_limit: (config.rate_limit_burst > 0).then(|| RateLimitConfig {
+            burst: config.rate_limit_burst,
+            refill: Duration::from_secs(config.rate_limit_interval)
+        }),
+        ops_channels: OpsChannels {
+            discord: config.ops_discord_channel.map(discord_id).transpose()?,
+            telegram: config.ops_telegram_chat.map(ChatId)
+        },
+        explorer_url: config.explorer_url.clone().or_else(|| default_explorer_url(config.network).map(str::to_string))
+    })
+}
+
+// Read the configuration again and apply the settings that can change while running
+// The other options are only read at startup
+fn reload_runtime_settings(service: &WalletService) -> Result<Vec<&'static str>> {
+    let config = reload_config()?;
+    Ok(service.reload_settings(runtime_settings(&config)?))
+}
+
+// Reload the settings on SIGHUP, as the reload command does
+#[cfg(unix)]
+fn reload_on_hangup(service: WalletService) -> Result<()> {
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("SIGHUP received, reloading the settings");
+            match reload_runtime_settings(&service) {
+                Ok(changes) if changes.is_empty() => info!("No setting changed"),
+                // The changes are logged by the service
+                Ok(_) => {},
+                Err(e) => error!("Couldn't reload the settings: {:#}", e)
+            }
+        }
+    });
+
     Ok(())
 }
 
-// Clear all balances
-async fn clear_balances(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
-    let context = manager.get_context().lock()?;
-    let service: &WalletService = context.get()?;
-    if let Err(e) = service.clear_balances().await {
-        manager.error(format!("An error occurred while clearing the balances: {}", e.to_string()));
-    } else {
-        manager.message("Balances have been cleared");
-    }
+// Check the platform tokens before anything is started, so a wrong token fails fast
+async fn check_platform_tokens(discord_token: &str, bot: &Bot) -> Result<()> {
+    let discord = Http::new(discord_token).get_current_user().await
+        .context("Couldn't log in to Discord, check --discord-token")?;
+    info!("Logged in to Discord as {}", discord.name);
+
+    let telegram = bot.get_me().await
+        .context("Couldn't log in to Telegram, check --telegram-token")?;
+    info!("Logged in to Telegram as @{}", telegram.username());
 
     Ok(())
 }
 
-// Add balance to discord user
-async fn add_balance_discord(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
-    let context = manager.get_context().lock()?;
-    let service: &WalletService = context.get()?;
-    let user_id = args.get_value("user_id")?.to_number()?;
-    let amount = args.get_value("amount")?.to_string_value()?;
-    let amount = from_xelis(amount).ok_or_else(|| CommandError::InvalidArgument("amount".to_string()))?;
+// Prompt message builder, refreshed each tick with the live wallet metrics
+async fn prompt_message_builder(_: &Prompt, command_manager: Option<&CommandManager>) -> Result<String, PromptError> {
+    let service = command_manager.and_then(|manager| {
+        let context = manager.get_context().lock().ok()?;
+        context.get::<WalletService>().ok().cloned()
+    });
 
-    if let Err(e) = service.add_balance(&UserApplication::Discord(user_id.into()), amount).await {
-        manager.error(format!("An error occurred while adding balance: {}", e.to_string()));
-    } else {
-        manager.message("Balance has been added");
+    let Some(service) = service else {
+        return Ok("XELIS Tip Bot >>".to_string());
+    };
+
+    let topoheight = match service.get_wallet_topoheight().await {
+        Ok(topoheight) => topoheight.to_string(),
+        Err(_) => "?".to_string()
+    };
+    let status = if service.is_wallet_online().await { "Online" } else { "Offline" };
+    let users = match service.get_users_count().await {
+        Ok(users) => users.to_string(),
+        Err(_) => "?".to_string()
+    };
+
+    Ok(format!(
+        "XELIS Tip Bot | TopoHeight: {} | {} | Pending withdrawals: {} | Users: {} >>",
+        topoheight,
+        status,
+        service.get_pending_withdrawals_count(),
+        users
+    ))
+}
+
+// Time since the last event handled by the service
+fn format_last_event(queues: &QueueStatus) -> String {
+    match queues.last_event {
+        Some(elapsed) => format!("{}s ago", elapsed.as_secs()),
+        None => "never".to_string()
     }
+}
+
+// Show the depth of the processing queues
+async fn queues(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let queues = service.get_queue_status();
+    manager.message(format!("Unconfirmed TXs: {}", queues.unconfirmed));
+    manager.message(format!("Pending notifications: {}", queues.notifications));
+    manager.message(format!("Stuck withdrawals: {}", queues.stuck_withdrawals));
+    manager.message(format!("Last event: {}", format_last_event(&queues)));
+    manager.message(format!("Discord API errors: {}", queues.discord_errors));
+    manager.message(format!("Telegram API errors: {}", queues.telegram_errors));
+    manager.message(format!("Matrix API errors: {}", queues.matrix_errors));
+    manager.message(format!("Slack API errors: {}", queues.slack_errors));
+    manager.message(format!("Twitch API errors: {}", queues.twitch_errors));
 
     Ok(())
 }
 
-async fn remove_balance_discord(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+// Rescan CLI command
+async fn rescan(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
     let context = manager.get_context().lock()?;
     let service: &WalletService = context.get()?;
-    let user_id = args.get_value("user_id")?.to_number()?;
-    let amount = args.get_value("amount")?.to_string_value()?;
-    let amount = from_xelis(amount).ok_or_else(|| CommandError::InvalidArgument("amount".to_string()))?;
-
-    if let Err(e) = service.remove_balance(&UserApplication::Discord(user_id.into()), amount).await {
-        manager.error(format!("An error occurred while removing balance: {}", e.to_string()));
+    if let Err(e) = service.rescan().await {
+        manager.error(format!("An error occurred while rescanning the wallet: {}", e.to_string()));
     } else {
-        manager.message("Balance has been removed");
+        manager.message("Wallet has been rescanned");
     }
 
     Ok(())
 }
 
-// Withdraw all balance
-async fn withdraw_all(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+// Decode every entry stored by the bot to catch corruption before it spreads to the balances
+// Corrupt entries are only moved to the quarantine once confirmed
+async fn verify_storage(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let report = {
+        let context = manager.get_context().lock()?;
+        let service: &WalletService = context.get()?;
+        service.verify_storage().await
+    };
+
+    if report.quarantined > 0 {
+        manager.warn(format!("{} entries were quarantined by previous checks", report.quarantined));
+    }
+
+    if report.corrupt.is_empty() {
+        manager.message(format!("All {} entries are valid", report.entries));
+        return Ok(());
+    }
+
+    manager.error(format!("{} of {} entries are corrupt:", report.corrupt.len(), report.entries));
+    for entry in report.corrupt.iter() {
+        match &entry.key {
+            Some(key) => manager.error(format!("- {} {:?}: {}", entry.tree, key, entry.reason)),
+            None => manager.error(format!("- {}: {}", entry.tree, entry.reason))
+        };
+    }
+
+    let movable = report.corrupt.iter().filter(|entry| entry.can_be_quarantined()).count();
+    if movable == 0 {
+        manager.warn("None of them can be read to be quarantined, restore the wallet from a backup");
+        return Ok(());
+    }
+
+    let answer = manager.get_prompt()
+        .read_input(format!("Move {} corrupt entries to the quarantine? (y/N): ", movable), false).await
+        .context("Error while reading the answer")?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        manager.message("Nothing was quarantined");
+        return Ok(());
+    }
+
     let context = manager.get_context().lock()?;
     let service: &WalletService = context.get()?;
-    let address = args.get_value("address")?.to_string_value()?;
+    match service.quarantine_corrupt_entries().await {
+        Ok(entries) => manager.message(format!("{} entries were quarantined", entries.len())),
+        Err(e) => manager.error(format!("An error occurred while quarantining the entries: {}", e.to_string()))
+    };
 
-    let address = Address::from_string(&address).map_err(|e| CommandError::InvalidArgument(e.to_string()))?;
+    Ok(())
+}
 
-    if let Err(e) = service.withdraw_all(address).await {
-        manager.error(format!("An error occurred while withdrawing all: {}", e.to_string()));
+// Signed amount of XEL of a ledger account
+fn format_ledger_balance(balance: i128) -> String {
+    let amount = format_xelis(u64::try_from(balance.unsigned_abs()).unwrap_or(u64::MAX));
+    if balance < 0 {
+        format!("-{} XEL", amount)
     } else {
-        manager.message("All balance has been withdrawn");
+        format!("{} XEL", amount)
     }
-
-    Ok(())
 }
 
-// Withdraw all balance
-async fn withdraw_cmd(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+// Show the XEL of every account derived from the ledger and the stored balances that don't match it
+async fn ledger(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
     let context = manager.get_context().lock()?;
     let service: &WalletService = context.get()?;
-    let address = args.get_value("address")?.to_string_value()?;
+    let report = service.verify_ledger().await.context("Error while verifying the ledger")?;
 
-    let amount = args.get_value("amount")?.to_string_value()?;
-    let amount = from_xelis(amount).ok_or_else(|| CommandError::InvalidArgument("amount".to_string()))?;
+    manager.message(format!("{} entries", report.entries));
+    manager.message(format!("- Users: {}", format_ledger_balance(report.users)));
+    for (account, balance) in report.accounts.iter() {
+        manager.message(format!("- {}{}: {}", account.name(), if account.is_external() { " (external)" } else { "" }, format_ledger_balance(*balance)));
+    }
+    manager.message(format!("Owed by the bot: {}", format_ledger_balance(report.liabilities())));
 
-    let address = Address::from_string(&address).map_err(|e| CommandError::InvalidArgument(e.to_string()))?;
+    if report.discrepancies.is_empty() {
+        manager.message("Every stored balance matches the ledger");
+        return Ok(());
+    }
 
-    if let Err(e) = service.withdraw_to(address, amount).await {
-        manager.error(format!("An error occurred while withdrawing all: {}", e.to_string()));
-    } else {
-        manager.message("Amount has been withdrawn");
+    manager.error(format!("{} stored balances don't match the ledger:", report.discrepancies.len()));
+    for discrepancy in report.discrepancies.iter() {
+        // Other assets are shown in atomic units
+        if discrepancy.asset == XELIS_ASSET {
+            manager.error(format!("- {:?}: {} XEL stored, {} in the ledger", discrepancy.account, format_xelis(discrepancy.stored), format_ledger_balance(discrepancy.derived)));
+        } else {
+            manager.error(format!("- {:?} in {}: {} stored, {} in the ledger", discrepancy.account, discrepancy.asset, discrepancy.stored, discrepancy.derived));
+        }
     }
 
     Ok(())
 }
 
+// Recompute the balance of every user from the ledger and its deposits
+// The stored balances that don't match the ledger are set back to it once confirmed
+async fn audit(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let mismatches = {
+        let context = manager.get_context().lock()?;
+        let service: &WalletService = context.get()?;
+        service.reconcile_balances().await.context("Error while reconciling the balances")?
+    };
 
-/// See the status of the wallet
-#[poise::command(slash_command, broadcast_typing)]
-async fn status(ctx: Context<'_>) -> Result<(), Error> {
-    // Retrieve balance for user
-    let service = ctx.data();
-    let balance = service.get_wallet_balance().await?;
-    let total_balance = service.get_total_users_balance().await?;
-    let topoheight = service.get_wallet_topoheight().await?;
-    let network = service.network();
-    let online = service.is_wallet_online().await;
+    if mismatches.is_empty() {
+        manager.message("Every balance matches the ledger and the deposits of its user");
+        return Ok(());
+    }
 
-    let embed = CreateEmbed::default()
-        .title("Status")
-        .field("Wallet Balance", format_xelis(balance), false)
-        .field("Total Users Balance", format_xelis(total_balance), false)
-        .field("Synced TopoHeight", topoheight.to_string(), false)
-        .field("Network", network.to_string(), false)
-        .field("Is Online", online.to_string(), false)
-        .thumbnail(ICON)
-        .colour(COLOR);
-    let mut reply = CreateReply::default()
-        .embed(embed);
+    manager.error(format!("{} balances don't match:", mismatches.len()));
+    for balance in mismatches.iter() {
+        // Other assets are shown in atomic units
+        if balance.asset == XELIS_ASSET {
+            manager.error(format!(
+                "- {:?}: {} XEL stored, {} in the ledger, {} XEL of deposits, {} XEL credited",
+                balance.user,
+                format_xelis(balance.stored),
+                format_ledger_balance(balance.ledger),
+                format_xelis(balance.deposits),
+                format_xelis(balance.credited)
+            ));
+        } else {
+            manager.error(format!(
+                "- {:?} in {}: {} stored, {} in the ledger, {} of deposits, {} credited",
+                balance.user,
+                balance.asset,
+                balance.stored,
+                balance.ledger,
+                balance.deposits,
+                balance.credited
+            ));
+        }
+    }
 
-    // Set reply to ephemeral if command was not used in DM
+    if mismatches.iter().any(|balance| balance.is_deposits_mismatch()) {
+        manager.warn("Deposits that don't match their credits are not repaired, check them with the user command and fix them with credit or debit");
+    }
+
+    let repairable = mismatches.iter().filter(|balance| balance.is_repairable()).count();
+    if repairable == 0 {
+        return Ok(());
+    }
+
+    let answer = manager.get_prompt()
+        .read_input(format!("Set {} stored balances to the ledger? (y/N): ", repairable), false).await
+        .context("Error while reading the answer")?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        manager.message("Nothing was repaired");
+        return Ok(());
+    }
+
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    match service.repair_balances().await {
+        Ok(repaired) => manager.message(format!("{} balances were repaired", repaired.len())),
+        Err(e) => manager.error(format!("An error occurred while repairing the balances: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// Clear all balances
+async fn clear_balances(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    if let Err(e) = service.clear_balances().await {
+        manager.error(format!("An error occurred while clearing the balances: {}", e.to_string()));
+    } else {
+        manager.message("Balances have been cleared");
+    }
+
+    Ok(())
+}
+
+// Credit XEL to a user, the reason is asked for the journal
+async fn credit(manager: &CommandManager, args: ArgumentManager) -> Result<(), CommandError> {
+    adjust_balance(manager, args, true).await
+}
+
+// Debit XEL from a user, the reason is asked for the journal
+async fn debit(manager: &CommandManager, args: ArgumentManager) -> Result<(), CommandError> {
+    adjust_balance(manager, args, false).await
+}
+
+async fn adjust_balance(manager: &CommandManager, mut args: ArgumentManager, credit: bool) -> Result<(), CommandError> {
+    let platform = args.get_value("platform")?.to_string_value()?;
+    let id = args.get_value("id")?.to_number()?;
+    let amount = args.get_value("amount")?.to_string_value()?;
+    let user = parse_user(&platform, id)?;
+    let amount = parse_amount(&amount).and_then(Amount::exact).map_err(|e| CommandError::InvalidArgument(e.to_string()))?;
+
+    // The reason can contain spaces, it's asked apart from the arguments
+    let reason = manager.get_prompt()
+        .read_input(format!("Reason (up to {} characters): ", MAX_NOTE_LENGTH), false).await
+        .context("Error while reading the reason")?;
+
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let result = if credit {
+        service.add_balance(&user, amount, &reason).await
+    } else {
+        service.remove_balance(&user, amount, &reason).await
+    };
+
+    match result {
+        Ok(()) => manager.message(format!("{} {} XEL {} {:?}", if credit { "Credited" } else { "Debited" }, format_xelis(amount), if credit { "to" } else { "from" }, user)),
+        Err(e) => manager.error(format!("An error occurred while updating the balance: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// Parse an exemption from its kind (telegram, discord, matrix, slack, twitch or role) and id
+fn parse_exemption(kind: &str, id: u64) -> Result<Exemption, CommandError> {
+    match kind {
+        "telegram" => Ok(Exemption::User(UserApplication::Telegram(id))),
+        "discord" => Ok(Exemption::User(UserApplication::Discord(id))),
+        "matrix" => Ok(Exemption::User(UserApplication::Matrix(id))),
+        "slack" => Ok(Exemption::User(UserApplication::Slack(id))),
+        "twitch" => Ok(Exemption::User(UserApplication::Twitch(id))),
+        "role" => Ok(Exemption::DiscordRole(id)),
+        _ => Err(CommandError::InvalidArgument("kind must be telegram, discord, matrix, slack, twitch or role".to_string()))
+    }
+}
+
+// Exempt a user or a role from cooldowns and limits
+async fn add_exemption(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let kind = args.get_value("kind")?.to_string_value()?;
+    let id = args.get_value("id")?.to_number()?;
+    let exemption = parse_exemption(&kind, id)?;
+
+    if let Err(e) = service.add_exemption(exemption).await {
+        manager.error(format!("An error occurred while adding exemption: {}", e.to_string()));
+    } else {
+        manager.message(format!("{} is now exempted", exemption));
+    }
+
+    Ok(())
+}
+
+// Remove an exemption
+async fn remove_exemption(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let kind = args.get_value("kind")?.to_string_value()?;
+    let id = args.get_value("id")?.to_number()?;
+    let exemption = parse_exemption(&kind, id)?;
+
+    match service.remove_exemption(exemption).await {
+        Ok(true) => manager.message(format!("{} is no longer exempted", exemption)),
+        Ok(false) => manager.warn(format!("{} was not exempted", exemption)),
+        Err(e) => manager.error(format!("An error occurred while removing exemption: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// List all exemptions
+async fn list_exemptions(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+
+    match service.get_exemptions().await {
+        Ok(exemptions) if exemptions.is_empty() => manager.message("No exemptions"),
+        Ok(exemptions) => for exemption in exemptions {
+            manager.message(exemption.to_string());
+        },
+        Err(e) => manager.error(format!("An error occurred while listing exemptions: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// Parse a user from its platform (telegram, discord, matrix, slack or twitch) and id
+// Matrix and Slack users are given by the ID the bot knows them by
+fn parse_user(platform: &str, id: u64) -> Result<UserApplication, CommandError> {
+    match platform {
+        "telegram" => Ok(UserApplication::Telegram(id)),
+        "discord" => Ok(UserApplication::Discord(id)),
+        "matrix" => Ok(UserApplication::Matrix(id)),
+        "slack" => Ok(UserApplication::Slack(id)),
+        "twitch" => Ok(UserApplication::Twitch(id)),
+        _ => Err(CommandError::InvalidArgument("platform must be telegram, discord, matrix, slack or twitch".to_string()))
+    }
+}
+
+// Block the transfers and withdrawals of a user
+async fn freeze(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let platform = args.get_value("platform")?.to_string_value()?;
+    let id = args.get_value("id")?.to_number()?;
+    let user = parse_user(&platform, id)?;
+
+    match service.set_frozen(&user, true).await {
+        Ok(true) => manager.message(format!("{:?} is now frozen", user)),
+        Ok(false) => manager.warn(format!("{:?} was already frozen", user)),
+        Err(e) => manager.error(format!("An error occurred while freezing user: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// Allow a frozen user to transfer and withdraw again
+async fn unfreeze(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let platform = args.get_value("platform")?.to_string_value()?;
+    let id = args.get_value("id")?.to_number()?;
+    let user = parse_user(&platform, id)?;
+
+    match service.set_frozen(&user, false).await {
+        Ok(true) => manager.message(format!("{:?} is no longer frozen", user)),
+        Ok(false) => manager.warn(format!("{:?} was not frozen", user)),
+        Err(e) => manager.error(format!("An error occurred while unfreezing user: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// List all frozen users
+async fn list_frozen(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+
+    match service.get_frozen_users().await {
+        Ok(users) if users.is_empty() => manager.message("No frozen users"),
+        Ok(users) => for user in users {
+            manager.message(format!("{:?}", user));
+        },
+        Err(e) => manager.error(format!("An error occurred while listing frozen users: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// Read the config file again and apply the settings that can change while running
+async fn reload(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+
+    match reload_runtime_settings(service) {
+        Ok(changes) if changes.is_empty() => manager.message("No setting changed"),
+        Ok(changes) => manager.message(format!("Settings reloaded, changed: {}", changes.join(", "))),
+        Err(e) => manager.error(format!("Couldn't reload the settings: {:#}", e))
+    };
+
+    Ok(())
+}
+
+// List the XEL balances of the users by page, highest first
+// The sum is compared against the wallet balance to spot a deficit
+async fn list_balances(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let page = if args.has_argument("page") {
+        args.get_value("page")?.to_number()? as usize
+    } else {
+        1
+    };
+
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let balances = service.get_users_balances().await.context("Error while loading the balances")?;
+    if balances.is_empty() {
+        manager.message("No balances");
+        return Ok(());
+    }
+
+    let pages = balances.len().div_ceil(CLI_BALANCES_PAGE_SIZE);
+    if page == 0 || page > pages {
+        return Err(CommandError::InvalidArgument(format!("page must be between 1 and {}", pages)));
+    }
+
+    manager.message(format!("Page {}/{} ({} users):", page, pages, balances.len()));
+    let start = (page - 1) * CLI_BALANCES_PAGE_SIZE;
+    for (i, (user, balance)) in balances.iter().enumerate().skip(start).take(CLI_BALANCES_PAGE_SIZE) {
+        manager.message(format!("{}. {:?}: {} XEL", i + 1, user, format_xelis(*balance)));
+    }
+
+    // The faucet and the running escrows are owed too, they're part of the total
+    let users_balance: u64 = balances.iter().map(|(_, balance)| balance).sum();
+    let total_owed = service.get_total_users_balance().await.context("Error while computing the total owed")?;
+    let wallet_balance = service.get_wallet_balance().await.context("Error while loading the wallet balance")?;
+    let summary = format!(
+        "Users: {} XEL | Total owed: {} XEL | Wallet: {} XEL",
+        format_xelis(users_balance),
+        format_xelis(total_owed),
+        format_xelis(wallet_balance)
+    );
+
+    if wallet_balance >= total_owed {
+        manager.message(format!("{} | Surplus: {} XEL", summary, format_xelis(wallet_balance - total_owed)));
+    } else {
+        manager.warn(format!("{} | Deficit: {} XEL", summary, format_xelis(total_owed - wallet_balance)));
+    }
+
+    Ok(())
+}
+
+// Take a proof of reserves and write its published part to a file
+async fn proof_of_reserves(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let path = args.get_value("path")?.to_string_value()?;
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let proof = service.create_reserve_proof().await.context("Error while taking the proof of reserves")?;
+
+    std::fs::write(&path, proof.to_json()).with_context(|| format!("Couldn't write the proof of reserves to {}", path))?;
+    let snapshot = &proof.snapshot;
+    manager.message(format!("Proof of reserves {} written to {} with {} leaves", snapshot.id, path, snapshot.leaves.len()));
+    manager.message(format!("Wallet: {} XEL | Owed: {} XEL | Merkle root: {}", format_xelis(snapshot.assets), format_xelis(snapshot.liabilities), snapshot.root));
+
+    Ok(())
+}
+
+// Write the balances of the users to a CSV file
+async fn export_balances(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let path = args.get_value("path")?.to_string_value()?;
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let balances = service.get_balances_with_activity().await.context("Error while loading the balances")?;
+
+    // The checksum lets the import verify the file wasn't altered in between
+    let csv = balances_csv(&balances);
+    std::fs::write(&path, &csv).with_context(|| format!("Couldn't write the balances to {}", path))?;
+    manager.message(format!("Exported {} balances to {} (checksum {})", balances.len(), path, hash(csv.as_bytes())));
+
+    Ok(())
+}
+
+// Note of the journal entries of an import, the checksum tells which file they came from
+fn import_note(import: &BalanceImport) -> String {
+    format!("Balance migrated from another tip bot, file checksum {}", import.checksum)
+}
+
+// Credit the balances of a CSV file, such as the export of another tip bot
+// The whole file is validated and the import confirmed before anything is credited
+async fn import_balances(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let path = args.get_value("path")?.to_string_value()?;
+    let content = std::fs::read(&path).with_context(|| format!("Couldn't read {}", path))?;
+
+    let checksum = manager.get_prompt()
+        .read_input("Checksum of the file, as printed by export_balances or b3sum (empty to skip): ".to_string(), false).await
+        .context("Error while reading the checksum")?;
+    let expected = match checksum.trim() {
+        "" => None,
+        checksum => Some(Hash::from_hex(checksum).map_err(|e| CommandError::InvalidArgument(e.to_string()))?)
+    };
+
+    let import = match parse_balances_csv(&content, expected.as_ref()) {
+        Ok(import) => import,
+        Err(e) => {
+            manager.error(format!("{} can't be imported: {}", path, e));
+            return Ok(());
+        }
+    };
+
+    let Some(total) = import.total() else {
+        manager.error("The total of the balances is too big");
+        return Ok(());
+    };
+
+    if expected.is_none() {
+        manager.warn(format!("The file isn't verified without its checksum, it is {}", import.checksum));
+    }
+
+    {
+        let context = manager.get_context().lock()?;
+        let service: &WalletService = context.get()?;
+        if service.is_imported(&import.checksum).await {
+            manager.error(format!("{} was already imported", path));
+            return Ok(());
+        }
+
+        // The migrated funds must be deposited to the wallet of the bot
+        let total_owed = service.get_total_users_balance().await.context("Error while computing the total owed")?;
+        let wallet_balance = service.get_wallet_balance().await.context("Error while loading the wallet balance")?;
+        let owed = total_owed.saturating_add(total);
+        if wallet_balance < owed {
+            manager.warn(format!("{} XEL will be owed but the wallet holds {} XEL, deposit the migrated funds", format_xelis(owed), format_xelis(wallet_balance)));
+        }
+    }
+
+    manager.message(format!("{} users to credit with {} XEL, {} rows skipped (zero balances or other assets)", import.balances.len(), format_xelis(total), import.skipped));
+    let answer = manager.get_prompt()
+        .read_input(format!("Credit {} XEL to {} users? (y/N): ", format_xelis(total), import.balances.len()), false).await
+        .context("Error while reading the answer")?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        manager.message("Nothing was imported");
+        return Ok(());
+    }
+
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    match service.import_balances(&import.checksum, &import.balances, &import_note(&import)).await {
+        Ok(()) => manager.message(format!("Imported {} XEL to {} users", format_xelis(total), import.balances.len())),
+        Err(e) => manager.error(format!("An error occurred while importing the balances: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// Import the balances of the --migrate-from file at startup, a file already imported is skipped
+async fn migrate_balances(service: &WalletService, path: &Path, checksum: Option<&str>) -> Result<()> {
+    let expected = checksum.map(Hash::from_hex)
+        .transpose()
+        .map_err(|e| Error::msg(format!("Invalid migration checksum: {}", e)))?;
+    let content = std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    let import = parse_balances_csv(&content, expected.as_ref()).with_context(|| format!("{} can't be imported", path.display()))?;
+    if service.is_imported(&import.checksum).await {
+        info!("{} was already imported", path.display());
+        return Ok(());
+    }
+
+    let total = import.total().context("The total of the migrated balances is too big")?;
+    if expected.is_none() {
+        warn!("Importing {} without --migrate-checksum, its checksum is {}", path.display(), import.checksum);
+    }
+
+    service.import_balances(&import.checksum, &import.balances, &import_note(&import)).await?;
+    info!("Migrated {} XEL to {} users from {}, {} rows skipped", format_xelis(total), import.balances.len(), path.display(), import.skipped);
+
+    Ok(())
+}
+
+// Amounts of other assets than XEL are shown in atomic units
+fn format_audit_record(record: &AuditRecord) -> String {
+    let entry = &record.entry;
+    let (amount, after) = if entry.asset == XELIS_ASSET {
+        (format!("{} XEL", format_xelis(entry.amount())), format!("{} XEL", format_xelis(entry.after)))
+    } else {
+        (format!("{} {}", entry.amount(), entry.asset), entry.after.to_string())
+    };
+
+    format!(
+        "- #{} {} {} {}{}, balance {}, user {}, journal #{}",
+        entry.sequence,
+        format_timestamp(entry.timestamp),
+        entry.kind.name(),
+        if entry.is_credit() { "+" } else { "-" },
+        amount,
+        after,
+        entry.subject,
+        entry.movement
+    )
+}
+
+// Verify the audit log and show its latest records, of a single user if set
+async fn audit_log(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let user = match (args.has_argument("platform"), args.has_argument("id")) {
+        (true, true) => {
+            let platform = args.get_value("platform")?.to_string_value()?;
+            let id = args.get_value("id")?.to_number()?;
+            Some(parse_user(&platform, id)?)
+        },
+        (false, false) => None,
+        _ => return Err(CommandError::InvalidArgument("both the platform and the id of the user are required".to_string()))
+    };
+
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let verification = service.verify_audit_log().await.context("Error while verifying the audit log")?;
+    if verification.broken.is_empty() {
+        manager.message(format!("All {} records of the audit log are verified", verification.records));
+    } else {
+        manager.error(format!("{} of {} records of the audit log are broken:", verification.broken.len(), verification.records));
+        for (sequence, reason) in verification.broken.iter() {
+            manager.error(format!("- #{}: {}", sequence, reason));
+        }
+    }
+
+    let records = service.get_audit_records(user.as_ref(), CLI_AUDIT_RECORDS).await.context("Error while loading the audit log")?;
+    if records.is_empty() {
+        manager.message("No records");
+        return Ok(());
+    }
+
+    manager.message(format!("Latest {} records:", records.len()));
+    for record in records.iter() {
+        manager.message(format_audit_record(record));
+    }
+
+    Ok(())
+}
+
+// Show everything needed to answer a support request of a user
+async fn user_lookup(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let platform = args.get_value("platform")?.to_string_value()?;
+    let id = args.get_value("id")?.to_number()?;
+    let user = parse_user(&platform, id)?;
+
+    match service.get_balances_for_user(&user).await {
+        Ok(balances) => {
+            let balances = balances.iter()
+                .map(|balance| balance.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            manager.message(format!("Balances of {:?}: {}", user, balances));
+        },
+        Err(e) => manager.error(format!("An error occurred while loading the balances: {}", e.to_string()))
+    };
+
+    if service.is_frozen(&user).await {
+        manager.warn("User is frozen");
+    }
+
+    if service.is_exempt(&user, &[]).await {
+        manager.message("User is exempted from cooldowns and limits");
+    }
+
+    match service.get_deposits(&user).await {
+        Ok(deposits) if deposits.is_empty() => manager.message("No deposits"),
+        Ok(deposits) => {
+            manager.message(format!("{} deposits:", deposits.len()));
+            for deposit in deposits {
+                let amounts = deposit.amounts.iter()
+                    .map(|amount| amount.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                manager.message(format!(
+                    "- TX {} at topoheight {}: {}{}",
+                    deposit.tx_hash,
+                    deposit.topoheight,
+                    amounts,
+                    if deposit.returned { " (returned)" } else { "" }
+                ));
+            }
+        },
+        Err(e) => manager.error(format!("An error occurred while loading the deposits: {}", e.to_string()))
+    };
+
+    match service.get_recent_movements(&user, CLI_USER_MOVEMENTS).await {
+        Ok(movements) if movements.is_empty() => manager.message("No movements"),
+        Ok(movements) => {
+            manager.message(format!("Latest {} movements:", movements.len()));
+            for (movement, balance) in movements {
+                manager.message(format!(
+                    "- #{} {} {} {}{}, balance {}{}",
+                    movement.id,
+                    format_timestamp(movement.timestamp),
+                    movement.kind.name(),
+                    if movement.is_credit() { "+" } else { "-" },
+                    balance.with_amount(movement.amount()),
+                    balance,
+                    movement.note.as_ref().map(|note| format!(" ({})", note)).unwrap_or_default()
+                ));
+            }
+        },
+        Err(e) => manager.error(format!("An error occurred while loading the movements: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// List the withdrawals that were rolled back
+async fn failed_withdrawals(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+
+    match service.get_failed_withdrawals().await {
+        Ok(failed) if failed.is_empty() => manager.message("No failed withdrawals"),
+        Ok(failed) => for withdrawal in failed {
+            let amount = if withdrawal.asset == XELIS_ASSET {
+                format!("{} XEL", format_xelis(withdrawal.amount))
+            } else {
+                format!("{} of asset {}", withdrawal.amount, withdrawal.asset)
+            };
+
+            let user = withdrawal.user.map(|user| format!("{:?}", user)).unwrap_or_else(|| "wallet".to_string());
+            manager.message(format!("TX {} of {}: {} (fee {} XEL) rejected at {}: {}", withdrawal.tx_hash, user, amount, format_xelis(withdrawal.fee), format_timestamp(withdrawal.failed_at), withdrawal.reason));
+        },
+        Err(e) => manager.error(format!("An error occurred while listing failed withdrawals: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// List the deposits stranded in the wallet
+async fn unknown_deposits(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+
+    match service.get_unknown_deposits().await {
+        Ok(deposits) if deposits.is_empty() => manager.message("No unknown deposits"),
+        Ok(deposits) => for deposit in deposits {
+            let transfers = deposit.transfers.iter()
+                .map(|transfer| {
+                    let amount = if transfer.asset == XELIS_ASSET {
+                        format!("{} XEL", format_xelis(transfer.amount))
+                    } else {
+                        format!("{} of asset {}", transfer.amount, transfer.asset)
+                    };
+
+                    format!("{} ({})", amount, transfer.reason)
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let line = format!("TX {} from {} at topoheight {}, detected at {}: {}", deposit.tx_hash, deposit.from, deposit.topoheight, format_timestamp(deposit.detected_at), transfers);
+            match deposit.resolved_at {
+                Some(resolved_at) => {
+                    let refunds = deposit.refunds.iter().map(|hash| hash.to_string()).collect::<Vec<_>>().join(", ");
+                    manager.message(format!("{} (refunded at {} in {})", line, format_timestamp(resolved_at), refunds));
+                },
+                None => manager.warn(line)
+            }
+        },
+        Err(e) => manager.error(format!("An error occurred while listing unknown deposits: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// List the DM notifications waiting to be sent again
+async fn notifications(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+
+    match service.get_queued_notifications().await {
+        Ok(notifications) if notifications.is_empty() => manager.message("No queued notifications"),
+        Ok(notifications) => for (notification, deferred) in notifications {
+            let line = format!("#{} {} for {:?}, queued at {}, {} attempts: {}", notification.id, notification.title, notification.user, format_timestamp(notification.created_at), notification.attempts, notification.last_error);
+            if notification.is_undeliverable() {
+                manager.error(format!("{} (undeliverable)", line));
+            } else if deferred {
+                manager.message(format!("{} (waiting for the user to start the bot)", line));
+            } else {
+                manager.message(format!("{} (next attempt at {})", line, format_timestamp(notification.next_attempt)));
+            }
+        },
+        Err(e) => manager.error(format!("An error occurred while listing the notifications: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// Reschedule every queued notification for the next retry
+async fn flush_notifications(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+
+    match service.flush_notifications().await {
+        Ok(count) => manager.message(format!("{} notifications will be sent again in a few seconds", count)),
+        Err(e) => manager.error(format!("An error occurred while flushing the notifications: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// Delete the notifications that failed too many times
+async fn drop_notifications(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let answer = manager.get_prompt()
+        .read_input(format!("Delete the notifications undeliverable after {} attempts? (y/N): ", MAX_NOTIFICATION_ATTEMPTS), false).await
+        .context("Error while reading the answer")?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        manager.message("Nothing was deleted");
+        return Ok(());
+    }
+
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    match service.drop_undeliverable_notifications().await {
+        Ok(count) => manager.message(format!("{} notifications were deleted", count)),
+        Err(e) => manager.error(format!("An error occurred while deleting the notifications: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// Draw the running lottery round
+async fn lottery_draw(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+
+    if let Err(e) = service.force_lottery_draw().await {
+        manager.error(format!("An error occurred while drawing the lottery: {}", e.to_string()));
+    } else {
+        manager.message("Lottery round will be drawn in a few seconds");
+    }
+
+    Ok(())
+}
+
+// Refund the running lottery round
+async fn lottery_refund(manager: &CommandManager, _: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+
+    match service.refund_lottery().await {
+        Ok(refunded) => manager.message(format!("{} XEL refunded, a new round has started", format_xelis(refunded))),
+        Err(e) => manager.error(format!("An error occurred while refunding the lottery: {}", e.to_string()))
+    };
+
+    Ok(())
+}
+
+// Add balance to the faucet
+async fn add_faucet_balance(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let amount = args.get_value("amount")?.to_string_value()?;
+    let amount = parse_amount(&amount).and_then(Amount::exact).map_err(|e| CommandError::InvalidArgument(e.to_string()))?;
+
+    if let Err(e) = service.add_faucet_balance(amount).await {
+        manager.error(format!("An error occurred while adding faucet balance: {}", e.to_string()));
+    } else {
+        manager.message(format!("Faucet balance is now {} XEL", format_xelis(service.get_faucet_balance().await)));
+    }
+
+    Ok(())
+}
+
+// Send a misdirected or disputed deposit back to its sender
+async fn refund_deposit(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let tx_hash = args.get_value("tx_hash")?.to_string_value()?;
+    let tx_hash = Hash::from_hex(&tx_hash).map_err(|e| CommandError::InvalidArgument(e.to_string()))?;
+
+    match service.refund_deposit(&tx_hash).await {
+        Ok(refunded) => for (amount, hash) in refunded {
+            manager.message(format!("Refunded {} of unknown deposit {} in TX {}", amount, tx_hash, hash));
+        },
+        Err(e) => manager.error(format!("An error occurred while refunding deposit {}: {}", tx_hash, e.to_string()))
+    };
+
+    Ok(())
+}
+
+async fn return_deposit(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let tx_hash = args.get_value("tx_hash")?.to_string_value()?;
+    let tx_hash = Hash::from_hex(&tx_hash).map_err(|e| CommandError::InvalidArgument(e.to_string()))?;
+
+    match service.return_deposit(&tx_hash).await {
+        Ok(returned) => for (amount, hash) in returned {
+            manager.message(format!("Returned {} of TX {} in TX {}", amount, tx_hash, hash));
+        },
+        Err(e) => manager.error(format!("An error occurred while returning deposit {}: {}", tx_hash, e.to_string()))
+    };
+
+    Ok(())
+}
+
+// Withdraw all balance
+async fn withdraw_all(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let address = args.get_value("address")?.to_string_value()?;
+
+    let address = Address::from_string(&address).map_err(|e| CommandError::InvalidArgument(e.to_string()))?;
+
+    if let Err(e) = service.withdraw_all(address).await {
+        manager.error(format!("An error occurred while withdrawing all: {}", e.to_string()));
+    } else {
+        manager.message("All balance has been withdrawn");
+    }
+
+    Ok(())
+}
+
+// Withdraw all balance
+async fn withdraw_cmd(manager: &CommandManager, mut args: ArgumentManager) -> Result<(), CommandError> {
+    let context = manager.get_context().lock()?;
+    let service: &WalletService = context.get()?;
+    let address = args.get_value("address")?.to_string_value()?;
+
+    let amount = args.get_value("amount")?.to_string_value()?;
+    let amount = parse_amount(&amount).and_then(Amount::exact).map_err(|e| CommandError::InvalidArgument(e.to_string()))?;
+
+    let address = Address::from_string(&address).map_err(|e| CommandError::InvalidArgument(e.to_string()))?;
+
+    if let Err(e) = service.withdraw_to(address, amount).await {
+        manager.error(format!("An error occurred while withdrawing all: {}", e.to_string()));
+    } else {
+        manager.message("Amount has been withdrawn");
+    }
+
+    Ok(())
+}
+
+// Ask the user to confirm an amount that was rounded to the precision of its asset
+// Returns false if the user cancelled or didn't answer in time
+async fn confirm_rounded_amount(ctx: Context<'_>, title: &str, amount: &AssetBalance) -> Result<bool, Error> {
+    let embed = CreateEmbed::default()
+        .title(title)
+        .description(format!("{} only supports up to {} decimals, your amount has been rounded", amount.name, amount.decimals))
+        .field("Rounded amount", amount.to_string(), false)
+        .thumbnail(ICON)
+        .colour(COLOR);
+
+    confirm_amount(ctx, title, embed).await
+}
+
+// Ask the user to confirm an amount typed in a command if needed
+// Amounts converted from fiat show the rate used
+async fn confirm_command_amount(ctx: Context<'_>, title: &str, amount: &CommandAmount) -> Result<bool, Error> {
+    if let Some(conversion) = amount.conversion.as_ref() {
+        let embed = CreateEmbed::default()
+            .title(title)
+            .description(format!("{} {} has been converted to XEL at the current rate", format_fiat(conversion.fiat), conversion.currency))
+            .field("Amount", amount.amount.to_string(), false)
+            .field("Rates", conversion_rates(conversion), false)
+            .thumbnail(ICON)
+            .colour(COLOR);
+
+        return confirm_amount(ctx, title, embed).await;
+    }
+
+    if amount.rounded {
+        return confirm_rounded_amount(ctx, title, &amount.amount).await;
+    }
+
+    Ok(true)
+}
+
+// What was already tipped over the budget a tip goes over
+fn budget_overrun_text(overrun: &BudgetOverrun) -> String {
+    format!("You already tipped {} XEL of your {} budget of {} XEL", format_xelis(overrun.spent), overrun.period, format_xelis(overrun.limit))
+}
+
+// Ask the user to confirm a tip going over one of their budgets
+// Returns false if the user didn't confirm it
+async fn confirm_tip_budget(ctx: Context<'_>, title: &str, amount: &AssetBalance) -> Result<bool, Error> {
+    if amount.asset != XELIS_ASSET {
+        return Ok(true);
+    }
+
+    let Some(overrun) = ctx.data().check_tip_budget(&UserApplication::Discord(ctx.author().id.into()), amount.amount).await else {
+        return Ok(true);
+    };
+
+    let embed = CreateEmbed::default()
+        .title(title)
+        .description(format!("This tip goes over your {} budget, confirm to tip anyway", overrun.period))
+        .field("Budget", budget_overrun_text(&overrun), false)
+        .field("Amount", amount.to_string(), false)
+        .thumbnail(ICON)
+        .colour(COLOR);
+
+    confirm_amount(ctx, title, embed).await
+}
+
+// Show an embed with confirm and cancel buttons to the user
+// Returns false if the user cancelled or didn't answer in time
+async fn confirm_amount(ctx: Context<'_>, title: &str, embed: CreateEmbed) -> Result<bool, Error> {
+    let (reply, interaction, confirm_id) = ask_confirmation(ctx, embed).await?;
+    match interaction {
+        Some(interaction) => {
+            // Remove the buttons, the embed is kept as is
+            interaction.create_response(ctx, CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new().components(Vec::new())
+            )).await?;
+
+            Ok(interaction.data.custom_id == confirm_id)
+        },
+        None => confirmation_timed_out(ctx, reply, title).await
+    }
+}
+
+// Send the embed with the confirm and cancel buttons, and wait for one of them to be pressed
+// Returns the reply, the button interaction if any and the id of the confirm button
+async fn ask_confirmation(ctx: Context<'_>, embed: CreateEmbed) -> Result<(poise::ReplyHandle<'_>, Option<ComponentInteraction>, String), Error> {
+    let confirm_id = format!("{}-confirm", ctx.id());
+    let cancel_id = format!("{}-cancel", ctx.id());
+
+    let reply = ctx.send(CreateReply::default()
+        .ephemeral(true)
+        .embed(embed)
+        .components(vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(&confirm_id).label("Confirm").style(ButtonStyle::Success),
+            CreateButton::new(&cancel_id).label("Cancel").style(ButtonStyle::Danger)
+        ])])
+    ).await?;
+
+    let interaction = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .custom_ids(vec![confirm_id.clone(), cancel_id])
+        .timeout(CONFIRMATION_TIMEOUT)
+        .await;
+
+    Ok((reply, interaction, confirm_id))
+}
+
+// Withdrawal PIN asked once its user confirmed a withdrawal
+#[derive(Debug, poise::Modal)]
+#[name = "Withdrawal PIN"]
+struct PinModal {
+    #[name = "PIN"]
+    #[placeholder = "Your withdrawal PIN"]
+    #[min_length = 4]
+    #[max_length = 12]
+    pin: String
+}
+
+// Let the user review an amount, then ask its withdrawal PIN in a modal
+// Returns the PIN, None if the user cancelled or didn't answer in time
+async fn confirm_amount_with_pin(ctx: Context<'_>, title: &str, embed: CreateEmbed) -> Result<Option<String>, Error> {
+    let (reply, interaction, confirm_id) = ask_confirmation(ctx, embed.clone()).await?;
+    let Some(interaction) = interaction else {
+        confirmation_timed_out(ctx, reply, title).await?;
+        return Ok(None);
+    };
+
+    if interaction.data.custom_id != confirm_id {
+        interaction.create_response(ctx, CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new().components(Vec::new())
+        )).await?;
+        return Ok(None);
+    }
+
+    let modal = poise::execute_modal_on_component_interaction::<PinModal>(ModalContext(ctx.serenity_context()), interaction, None, Some(CONFIRMATION_TIMEOUT)).await?;
+
+    // The buttons are removed whether the PIN was given or not
+    reply.edit(ctx, CreateReply::default().embed(embed).components(Vec::new())).await?;
+
+    Ok(modal.map(|modal| modal.pin))
+}
+
+// Tell the user the confirmation expired and remove its buttons
+async fn confirmation_timed_out(ctx: Context<'_>, reply: poise::ReplyHandle<'_>, title: &str) -> Result<bool, Error> {
+    reply.edit(ctx, CreateReply::default()
+        .embed(
+            CreateEmbed::default()
+                .title(title)
+                .description("Confirmation timed out")
+                .thumbnail(ICON)
+                .colour(Colour::RED)
+        )
+        .components(Vec::new())
+    ).await?;
+
+    Ok(false)
+}
+
+/// See the status of the wallet
+#[poise::command(slash_command, broadcast_typing)]
+async fn status(ctx: Context<'_>) -> Result<(), Error> {
+    // Retrieve balance for user
+    let service = ctx.data();
+    let solvency = service.get_solvency().await?;
+    let topoheight = service.get_wallet_topoheight().await?;
+    let network = service.network();
+    let online = service.is_wallet_online().await;
+
+    let embed = CreateEmbed::default()
+        .title("Status")
+        .field("Wallet Balance", format_xelis(solvency.assets), false)
+        .field("Total Users Balance", format_xelis(solvency.liabilities), false)
+        .field("Solvency Ratio", solvency.to_string(), false)
+        .field("Synced TopoHeight", topoheight.to_string(), false)
+        .field("Network", network.to_string(), false)
+        .field("Is Online", online.to_string(), false)
+        .thumbnail(ICON)
+        .colour(COLOR);
+
+    // Owners of the application also see the processing queues
+    let embed = if ctx.framework().options().owners.contains(&ctx.author().id) {
+        let queues = service.get_queue_status();
+        embed.field("Unconfirmed TXs", queues.unconfirmed.to_string(), true)
+            .field("Pending Notifications", queues.notifications.to_string(), true)
+            .field("Last Event", format_last_event(&queues), true)
+            .field("Discord API Errors", queues.discord_errors.to_string(), false)
+            .field("Telegram API Errors", queues.telegram_errors.to_string(), false)
+            .field("Matrix API Errors", queues.matrix_errors.to_string(), false)
+            .field("Slack API Errors", queues.slack_errors.to_string(), false)
+            .field("Twitch API Errors", queues.twitch_errors.to_string(), false)
+    } else {
+        embed
+    };
+
+    let mut reply = CreateReply::default()
+        .embed(embed);
+
+    // Set reply to ephemeral if command was not used in DM
+    if ctx.channel_id().to_channel(ctx.http()).await?.private().is_none() {
+        reply = reply.ephemeral(true);
+    }
+
+    // Send reply
+    ctx.send(reply).await?;
+
+    Ok(())
+}
+
+/// Show your current balance
+#[poise::command(slash_command, broadcast_typing)]
+async fn balance(ctx: Context<'_>) -> Result<(), Error> {
+    platform::balance(ctx.data(), &DiscordPlatform::new(ctx)).await
+}
+
+#[derive(poise::ChoiceParameter, Clone, Copy)]
+enum StatementFormat {
+    #[name = "Text"]
+    Text,
+    #[name = "CSV file"]
+    Csv
+}
+
+// Parse an inclusive range of dates (YYYY-MM-DD) into timestamps in seconds
+fn parse_statement_range(from: &str, to: &str) -> Result<(u64, u64), String> {
+    let parse = |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date));
+
+    let from = parse(from)?.and_time(NaiveTime::MIN).and_utc().timestamp();
+    // The last day is included
+    let to = parse(to)?.and_time(NaiveTime::MIN).and_utc().timestamp() + 24 * 60 * 60 - 1;
+    if from > to {
+        return Err("The start date must be before the end date".to_string());
+    }
+
+    Ok((from.max(0) as u64, to.max(0) as u64))
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|date| date.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+fn format_movement(movement: &Movement) -> String {
+    format!(
+        "{} {} {}{} XEL, balance {} XEL",
+        format_timestamp(movement.timestamp),
+        movement.kind.name(),
+        if movement.is_credit() { "+" } else { "-" },
+        format_xelis(movement.amount()),
+        format_xelis(movement.after)
+    )
+}
+
+// Export the movements of a statement for spreadsheets
+fn statement_csv(statement: &Statement) -> String {
+    let mut csv = String::from("id,date,kind,amount,balance_before,balance_after\n");
+    for movement in statement.movements.iter() {
+        csv.push_str(&format!(
+            "{},{},{},{}{},{},{}\n",
+            movement.id,
+            format_timestamp(movement.timestamp),
+            movement.kind.name(),
+            if movement.is_credit() { "" } else { "-" },
+            format_xelis(movement.amount()),
+            format_xelis(movement.before),
+            format_xelis(movement.after)
+        ));
+    }
+
+    csv
+}
+
+/// Show your balance and movements over a date range
+#[poise::command(slash_command, broadcast_typing)]
+async fn statement(
+    ctx: Context<'_>,
+    #[description = "First day, YYYY-MM-DD"] from: String,
+    #[description = "Last day included, YYYY-MM-DD"] to: String,
+    #[description = "Show the movements as text or as a CSV file, text by default"] format: Option<StatementFormat>
+) -> Result<(), Error> {
+    let (from, to) = match parse_statement_range(&from, &to) {
+        Ok(range) => range,
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title("Statement")
+                    .field("An error occured while building the statement", e, false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let statement = ctx.data().get_statement(&UserApplication::Discord(ctx.author().id.into()), from, to).await?;
+    let mut embed = CreateEmbed::default()
+        .title("Statement")
+        .description(format!("From <t:{}:d> to <t:{}:d>", from, to))
+        .field("Opening balance", format!("{} XEL", format_xelis(statement.opening)), true)
+        .field("Credits", format!("+{} XEL", format_xelis(statement.total_credits())), true)
+        .field("Debits", format!("-{} XEL", format_xelis(statement.total_debits())), true)
+        .field("Closing balance", format!("{} XEL", format_xelis(statement.closing)), true)
+        .field("Movements", statement.movements.len().to_string(), true)
+        .field("Journal", if statement.verified { "Verified" } else { "Mismatch, please contact the team" }, true)
+        .thumbnail(ICON)
+        .colour(if statement.verified { Colour::new(COLOR) } else { Colour::RED });
+
+    let mut reply = CreateReply::default().ephemeral(true);
+    match format.unwrap_or(StatementFormat::Text) {
+        StatementFormat::Text => {
+            // Embed fields are limited to 1024 characters
+            let mut lines = String::new();
+            for (i, movement) in statement.movements.iter().enumerate() {
+                let line = format_movement(movement);
+                if lines.len() + line.len() + 1 > 1000 {
+                    lines.push_str(&format!("... and {} more, use the CSV format to see all of them", statement.movements.len() - i));
+                    break;
+                }
+                lines.push_str(&line);
+                lines.push('\n');
+            }
+
+            if !lines.is_empty() {
+                embed = embed.field("Details", lines, false);
+            }
+        },
+        StatementFormat::Csv => {
+            reply = reply.attachment(CreateAttachment::bytes(statement_csv(&statement).into_bytes(), "statement.csv"));
+        }
+    };
+
+    ctx.send(reply.embed(embed)).await?;
+
+    Ok(())
+}
+
+/// Enable or disable the reminders sent when your balance is left unused
+#[poise::command(slash_command, broadcast_typing)]
+async fn reminders(ctx: Context<'_>, #[description = "Receive the reminders, shows the current choice if not set"] enabled: Option<bool>) -> Result<(), Error> {
+    let service = ctx.data();
+    let user = UserApplication::Discord(ctx.author().id.into());
+    if let Some(enabled) = enabled {
+        service.set_reminders_enabled(&user, enabled).await?;
+    }
+
+    let description = if service.are_reminders_enabled(&user).await {
+        "You will be reminded in DM when your balance is left unused for a long time"
+    } else {
+        "You won't be reminded of your unused balance"
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(
+        CreateEmbed::default()
+            .title("Reminders")
+            .description(description)
+            .thumbnail(ICON)
+            .colour(COLOR)
+    )).await?;
+
+    Ok(())
+}
+
+/// Schedule a reminder sent in DM to a user, or to yourself, once the delay is over
+#[poise::command(slash_command, broadcast_typing)]
+async fn remind(
+    ctx: Context<'_>,
+    #[description = "User to remind, you can pick yourself"] user: User,
+    #[rename = "in"] #[description = "Delay such as 30m, 12h, 3d or 2w"] delay: String,
+    #[description = "What the reminder is about"] about: String,
+    #[description = "XEL the user can tip you from the reminder"] amount: Option<String>
+) -> Result<(), Error> {
+    let service = ctx.data();
+    let author = UserApplication::Discord(ctx.author().id.into());
+    let amount = amount.as_deref()
+        .map(|amount| parse_amount(amount).and_then(Amount::exact))
+        .transpose()
+        .map_err(|e| e.to_string());
+
+    let result = match (parse_delay(&delay), amount) {
+        _ if user.bot => Err("You can't remind a bot".to_string()),
+        (Ok(delay), Ok(amount)) => service.schedule_reminder(&author, &ctx.author().name, &UserApplication::Discord(user.id.into()), delay, &about, amount).await
+            .map_err(|e| e.to_string()),
+        (Err(e), _) => Err(e.to_string()),
+        (_, Err(e)) => Err(e)
+    };
+
+    let embed = match result {
+        Ok(reminder) => {
+            let target = if user.id == ctx.author().id {
+                "You".to_string()
+            } else {
+                user.to_string()
+            };
+
+            let mut embed = CreateEmbed::default()
+                .title("Reminder")
+                .description(format!("{} will be reminded in DM <t:{}:R>", target, reminder.due_at))
+                .field("About", reminder.memo, false)
+                .thumbnail(ICON)
+                .colour(COLOR);
+
+            if let Some(amount) = reminder.amount {
+                embed = embed.field("Amount", format!("{} XEL", format_xelis(amount)), true);
+            }
+
+            embed
+        },
+        Err(e) => CreateEmbed::default()
+            .title("Reminder")
+            .field("An error occured", e, false)
+            .thumbnail(ICON)
+            .colour(Colour::RED)
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed)).await?;
+
+    Ok(())
+}
+
+// Destination of the balances of a user asking to be forgotten, an address, a label or donate
+async fn parse_residual(service: &WalletService, user: &UserApplication, destination: &str) -> Result<Residual, ServiceError> {
+    if destination.trim().eq_ignore_ascii_case("donate") {
+        return Ok(Residual::Donate);
+    }
+
+    service.resolve_address(user, destination).await.map(Residual::Withdraw)
+}
+
+// What happens to the balances of a user asking to be forgotten
+fn residual_text(residual: &Residual) -> String {
+    match residual {
+        Residual::Withdraw(to) => format!("Withdrawn to {}, XEL too small to be withdrawn is donated to the faucet", to),
+        Residual::Donate => "XEL is donated to the faucet, other assets are left to the bot".to_string()
+    }
+}
+
+// Balances that are withdrawn or donated before the data of a user is deleted
+async fn forgotten_balances_text(service: &WalletService, user: &UserApplication) -> Result<String, Error> {
+    let balances = service.get_balances_for_user(user).await?
+        .into_iter()
+        .filter(|balance| balance.amount > 0)
+        .map(|balance| balance.to_string())
+        .collect::<Vec<_>>();
+
+    Ok(if balances.is_empty() { "Nothing".to_string() } else { balances.join("\n") })
+}
+
+// Withdrawals and donation made while forgetting a user
+fn forgotten_user_text(forgotten: &ForgottenUser) -> Vec<String> {
+    let mut lines: Vec<String> = forgotten.withdrawn.iter()
+        .map(|(amount, hash)| format!("Withdrawn {} in TX {}", amount, hash))
+        .collect();
+
+    if forgotten.donated > 0 {
+        lines.push(format!("Donated {} XEL to the faucet", format_xelis(forgotten.donated)));
+    }
+
+    lines
+}
+
+/// Delete all your data, once your balances are withdrawn or donated
+#[poise::command(slash_command, broadcast_typing)]
+async fn forgetme(
+    ctx: Context<'_>,
+    #[description = "Address or label to withdraw your balances to, or donate to give them to the faucet"] #[autocomplete = "autocomplete_address"] balances: String
+) -> Result<(), Error> {
+    let service = ctx.data();
+    let user = UserApplication::Discord(ctx.author().id.into());
+    let residual = match parse_residual(service, &user, &balances).await {
+        Ok(residual) => residual,
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title("Forget Me")
+                    .field("An error occured while deleting your data", e.to_string(), false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let preview = CreateEmbed::default()
+        .title("Forget Me")
+        .description("Your balances, deposit history, statements, stats, address book and settings will be deleted, this can't be undone")
+        .field("Balances", forgotten_balances_text(service, &user).await?, false)
+        .field("What happens to them", residual_text(&residual), false)
+        .thumbnail(ICON)
+        .colour(COLOR);
+
+    let pin = if service.has_withdraw_pin(&user).await {
+        match confirm_amount_with_pin(ctx, "Forget Me", preview).await? {
+            Some(pin) => Some(pin),
+            None => return Ok(())
+        }
+    } else {
+        if !confirm_amount(ctx, "Forget Me", preview).await? {
+            return Ok(());
+        }
+
+        None
+    };
+
+    let embed = match service.forget_user(&user, residual, pin.as_deref()).await {
+        Ok(forgotten) => {
+            let mut embed = CreateEmbed::default()
+                .title("Forget Me")
+                .description("Your data has been deleted")
+                .colour(COLOR);
+
+            let lines = forgotten_user_text(&forgotten);
+            if !lines.is_empty() {
+                embed = embed.field("Balances", lines.join("\n"), false);
+            }
+
+            embed
+        },
+        Err(e) => CreateEmbed::default()
+            .title("Forget Me")
+            .field("An error occured while deleting your data", e.to_string(), false)
+            .colour(Colour::RED)
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed.thumbnail(ICON))).await?;
+
+    Ok(())
+}
+
+// Parse a budget limit, "off" removes it
+fn parse_budget_limit(input: &str) -> Result<Option<u64>, AmountError> {
+    if input.trim().eq_ignore_ascii_case("off") {
+        return Ok(None);
+    }
+
+    parse_amount(input).and_then(Amount::exact).map(Some)
+}
+
+// Limit of a budget with what was already tipped over its period
+fn budget_limit_text(limit: Option<u64>, spent: u64) -> String {
+    match limit {
+        Some(limit) => format!("{} / {} XEL", format_xelis(spent), format_xelis(limit)),
+        None => format!("Not set, {} XEL tipped", format_xelis(spent))
+    }
+}
+
+/// Your personal settings, and the commands allowed in the server for its admins
+#[poise::command(slash_command, subcommands("settings_budget", "settings_whitelist", "settings_pin", "settings_commands", "settings_disable", "settings_enable", "settings_restrict"))]
+async fn settings(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Limit what you tip per day or week, going over it asks for a confirmation
+#[poise::command(slash_command, rename = "budget")]
+async fn settings_budget(
+    ctx: Context<'_>,
+    #[description = "XEL you want to tip per day at most, off to remove it"] daily: Option<String>,
+    #[description = "XEL you want to tip per week at most, off to remove it"] weekly: Option<String>
+) -> Result<(), Error> {
+    let service = ctx.data();
+    let user = UserApplication::Discord(ctx.author().id.into());
+    let mut budget = service.get_tip_budget(&user).await;
+    for (limit, input) in [(&mut budget.daily, daily.as_deref()), (&mut budget.weekly, weekly.as_deref())] {
+        let Some(input) = input else {
+            continue;
+        };
+
+        match parse_budget_limit(input) {
+            Ok(value) => *limit = value,
+            Err(e) => {
+                ctx.send(CreateReply::default().ephemeral(true).embed(
+                    CreateEmbed::default()
+                        .title("Budget")
+                        .field("An error occured while setting your budget", e.to_string(), false)
+                        .thumbnail(ICON)
+                        .colour(Colour::RED)
+                )).await?;
+                return Ok(());
+            }
+        }
+    }
+
+    service.set_tip_budget(&user, budget).await?;
+
+    let (daily, weekly) = service.get_tip_budget_spending(&user).await;
+    ctx.send(CreateReply::default().ephemeral(true).embed(
+        CreateEmbed::default()
+            .title("Budget")
+            .description("Tips and rains going over your budget must be confirmed")
+            .field("Today", budget_limit_text(budget.daily, daily), true)
+            .field("This week", budget_limit_text(budget.weekly, weekly), true)
+            .thumbnail(ICON)
+            .colour(COLOR)
+    )).await?;
+
+    Ok(())
+}
+
+// Whitelist status shown to its user
+fn whitelist_status_text(status: WhitelistStatus) -> String {
+    match status {
+        WhitelistStatus::Disabled => "Off, you can withdraw to any address".to_string(),
+        WhitelistStatus::Enabled => "On, you can only withdraw to the addresses of your address book".to_string(),
+        WhitelistStatus::Disabling(at) => format!("Turning off on {}, until then you can only withdraw to your address book", format_timestamp(at))
+    }
+}
+
+// Delay applied to the new addresses and to turning the whitelist off
+fn whitelist_delay_text(service: &WalletService) -> String {
+    format!("New addresses can be used {} hours after being saved, and turning the whitelist off takes as long", service.get_whitelist_delay() / 3600)
+}
+
+// When a saved address can be used, None if it already can
+fn saved_address_pending(service: &WalletService, saved: &SavedAddress, status: WhitelistStatus) -> Option<String> {
+    let usable_at = saved.added_at + service.get_whitelist_delay();
+    (status != WhitelistStatus::Disabled && usable_at > get_current_time_in_seconds())
+        .then(|| format!("Usable for withdrawals on {}", format_timestamp(usable_at)))
+}
+
+/// Only allow withdrawals to the addresses saved in your address book for a while
+#[poise::command(slash_command, rename = "whitelist")]
+async fn settings_whitelist(
+    ctx: Context<'_>,
+    #[description = "Turn the whitelist on or off, shows the current status if not set"] enabled: Option<bool>
+) -> Result<(), Error> {
+    let service = ctx.data();
+    let user = UserApplication::Discord(ctx.author().id.into());
+    let status = match enabled {
+        Some(enabled) => service.set_whitelist_enabled(&user, enabled).await?,
+        None => service.get_whitelist_status(&user).await
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(
+        CreateEmbed::default()
+            .title("Whitelist")
+            .description(whitelist_status_text(status))
+            .field("Delay", whitelist_delay_text(service), false)
+            .thumbnail(ICON)
+            .colour(COLOR)
+    )).await?;
+
+    Ok(())
+}
+
+/// Require a PIN to confirm your withdrawals
+#[poise::command(slash_command, rename = "pin")]
+async fn settings_pin(
+    ctx: Context<'_>,
+    #[description = "New PIN of 4 to 12 digits, off to remove it"] pin: String,
+    #[description = "Current PIN, required to change or remove it"] current: Option<String>
+) -> Result<(), Error> {
+    let user = UserApplication::Discord(ctx.author().id.into());
+    let new_pin = Some(pin.trim()).filter(|pin| !pin.eq_ignore_ascii_case("off"));
+    let embed = match ctx.data().set_withdraw_pin(&user, current.as_deref(), new_pin).await {
+        Ok(()) => CreateEmbed::default()
+            .title("PIN")
+            .description(if new_pin.is_some() { "Your withdrawals now require your PIN" } else { "Your withdrawals don't require a PIN anymore" })
+            .colour(COLOR),
+        Err(e) => CreateEmbed::default()
+            .title("PIN")
+            .field("An error occured while setting your PIN", e.to_string(), false)
+            .colour(Colour::RED)
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed.thumbnail(ICON))).await?;
+
+    Ok(())
+}
+
+// Commands a server can disable or restrict to some channels
+// /settings is left out so the admins can always change them back
+fn configurable_commands<'a>(ctx: Context<'a>) -> impl Iterator<Item = &'a str> {
+    ctx.framework().options().commands.iter()
+        .map(|command| command.name.as_str())
+        .filter(|name| *name != "settings")
+}
+
+// Suggest the commands a server can configure
+async fn autocomplete_command(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let partial = partial.trim_start_matches('/').to_lowercase();
+    configurable_commands(ctx)
+        .filter(|name| name.starts_with(&partial))
+        .map(|name| AutocompleteChoice::new(format!("/{}", name), name))
+        // Discord doesn't show more than 25 choices
+        .take(25)
+        .collect()
+}
+
+// Check that a command can be configured, otherwise tell the admin
+async fn check_configurable_command(ctx: Context<'_>, command: &str) -> Result<bool, Error> {
+    if !configurable_commands(ctx).any(|name| name == command) {
+        ctx.send(CreateReply::default().ephemeral(true).embed(
+            CreateEmbed::default()
+                .title("Commands")
+                .field("An error occured while configuring the command", format!("/{} can't be configured", command), false)
+                .thumbnail(ICON)
+                .colour(Colour::RED)
+        )).await?;
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+// Where a command can be used in the server
+fn command_rule_text(rule: Option<&CommandRule>) -> String {
+    match rule {
+        None => "Everywhere".to_string(),
+        Some(CommandRule::Disabled) => "Disabled".to_string(),
+        Some(CommandRule::Channels(channels)) => channels.iter()
+            .map(|channel| format!("<#{}>", channel))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+// Show where a command can be used after its rule changed
+async fn send_command_rule(ctx: Context<'_>, command: &str, rule: Option<&CommandRule>) -> Result<(), Error> {
+    ctx.send(CreateReply::default().ephemeral(true).embed(
+        CreateEmbed::default()
+            .title("Commands")
+            .field(format!("/{} can be used", command), command_rule_text(rule), false)
+            .thumbnail(ICON)
+            .colour(COLOR)
+    )).await?;
+
+    Ok(())
+}
+
+/// Show the commands disabled or restricted to some channels in this server
+#[poise::command(slash_command, rename = "commands", guild_only, required_permissions = "MANAGE_GUILD")]
+async fn settings_commands(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or(DiscordError::NoGuild)?;
+    let rules = ctx.data().get_command_rules(&Community::Discord(guild_id.get())).await?;
+
+    let mut embed = CreateEmbed::default()
+        .title("Commands")
+        .thumbnail(ICON)
+        .colour(COLOR);
+
+    if rules.is_empty() {
+        embed = embed.description("All the commands can be used everywhere");
+    }
+
+    // Embeds are limited to 25 fields
+    for (command, rule) in rules.iter().take(25) {
+        embed = embed.field(format!("/{}", command), command_rule_text(Some(rule)), false);
+    }
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed)).await?;
+
+    Ok(())
+}
+
+/// Disable a command in this server
+#[poise::command(slash_command, rename = "disable", guild_only, required_permissions = "MANAGE_GUILD")]
+async fn settings_disable(
+    ctx: Context<'_>,
+    #[description = "Command to disable"] #[autocomplete = "autocomplete_command"] command: String
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or(DiscordError::NoGuild)?;
+    if !check_configurable_command(ctx, &command).await? {
+        return Ok(());
+    }
+
+    let rule = CommandRule::Disabled;
+    ctx.data().set_command_rule(&Community::Discord(guild_id.get()), &command, Some(rule.clone())).await?;
+    send_command_rule(ctx, &command, Some(&rule)).await
+}
+
+/// Allow a command everywhere in this server again
+#[poise::command(slash_command, rename = "enable", guild_only, required_permissions = "MANAGE_GUILD")]
+async fn settings_enable(
+    ctx: Context<'_>,
+    #[description = "Command to enable"] #[autocomplete = "autocomplete_command"] command: String
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or(DiscordError::NoGuild)?;
+    if !check_configurable_command(ctx, &command).await? {
+        return Ok(());
+    }
+
+    ctx.data().set_command_rule(&Community::Discord(guild_id.get()), &command, None).await?;
+    send_command_rule(ctx, &command, None).await
+}
+
+/// Allow a command only in a channel, or remove the channel if it was already allowed
+#[poise::command(slash_command, rename = "restrict", guild_only, required_permissions = "MANAGE_GUILD")]
+async fn settings_restrict(
+    ctx: Context<'_>,
+    #[description = "Command to restrict"] #[autocomplete = "autocomplete_command"] command: String,
+    #[description = "Channel where the command can be used"] channel: GuildChannel
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or(DiscordError::NoGuild)?;
+    if !check_configurable_command(ctx, &command).await? {
+        return Ok(());
+    }
+
+    match ctx.data().toggle_command_channel(&Community::Discord(guild_id.get()), &command, channel.id.get()).await {
+        Ok(rule) => send_command_rule(ctx, &command, rule.as_ref()).await,
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title("Commands")
+                    .field("An error occured while configuring the command", e.to_string(), false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+            )).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Find your balance in the latest proof of reserves
+#[poise::command(slash_command, broadcast_typing)]
+async fn reserves(ctx: Context<'_>) -> Result<(), Error> {
+    let service = ctx.data();
+    let embed = match service.get_reserve_leaf(&UserApplication::Discord(ctx.author().id.into())).await? {
+        Some(leaf) => CreateEmbed::default()
+            .title("Proof of Reserves")
+            .description(format!("Your XEL balance is leaf #{} of the proof of reserves {}, taken at {}. Keep your salt private, it's what links the leaf to you", leaf.index, leaf.proof, format_timestamp(leaf.timestamp)))
+            .field("Balance", format_xelis(leaf.balance), false)
+            .field("Salt", leaf.salt.to_string(), false)
+            .field("Leaf", leaf.leaf.to_string(), false)
+            .field("Merkle Root", leaf.root.to_string(), false)
+            .footer(CreateEmbedFooter::new("Leaf is blake3(salt || balance), the balance in atomic units as 8 big endian bytes"))
+            .thumbnail(ICON)
+            .colour(COLOR),
+        None => CreateEmbed::default()
+            .title("Proof of Reserves")
+            .description("You held no XEL at the time of the latest proof of reserves, or none was published yet")
+            .thumbnail(ICON)
+            .colour(COLOR)
+    };
+
+    // The salt must stay private
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true)).await?;
+
+    Ok(())
+}
+
+/// Show your deposit address
+#[poise::command(slash_command, broadcast_typing)]
+async fn deposit(ctx: Context<'_>) -> Result<(), Error> {
+    // Retrieve address for user
+    let service = ctx.data();
+    let address = service.get_address_for_user(&UserApplication::Discord(ctx.author().id.into()));
+
+    let mut embed = CreateEmbed::default()
+        .title("Deposit")
+        .field("Your deposit address is", address.to_string(), true)
+        .footer(CreateEmbedFooter::new("Please do not send any other coins than XELIS to this address"))
+        .thumbnail(ICON)
+        .colour(COLOR);
+
+    if service.are_deposits_paused().await {
+        embed = embed.field(DEPOSITS_PAUSED_TITLE, DEPOSITS_PAUSED_MESSAGE, false)
+            .colour(Colour::ORANGE);
+    }
+
+    let mut reply = CreateReply::default()
+        .embed(embed);
+
+    // Set reply to ephemeral if command was not used in DM
     if ctx.channel_id().to_channel(ctx.http()).await?.private().is_none() {
         reply = reply.ephemeral(true);
     }
 
-    // Send reply
-    ctx.send(reply).await?;
+    // Send reply
+    ctx.send(reply).await?;
+
+    Ok(())
+}
+
+// Amount typed by a user in a command
+struct CommandAmount {
+    amount: AssetBalance,
+    // Rounded to the precision of the asset
+    rounded: bool,
+    // Set when the amount was typed in a fiat currency and converted to XEL
+    conversion: Option<Conversion>,
+    // Set when the amount was typed as a part of the balance, such as "all"
+    keyword: Option<AmountKeyword>
+}
+
+// Parse an amount of an asset held by the user, XEL when no asset is given
+// XEL amounts can also be typed in a fiat currency, such as "5usd"
+// or relative to the balance with "all", "max" and "half"
+async fn parse_asset_amount(service: &WalletService, user: &UserApplication, asset: Option<&str>, amount: &str) -> Result<CommandAmount, String> {
+    let balance = service.find_balance_for_user(user, asset).await.map_err(|e| e.to_string())?;
+    if let Some(keyword) = AmountKeyword::parse(amount) {
+        return Ok(CommandAmount {
+            amount: balance.with_amount(keyword.of(balance.amount)),
+            rounded: false,
+            conversion: None,
+            keyword: Some(keyword)
+        });
+    }
+
+    let amount = match split_unit(amount) {
+        Some((value, unit)) if is_xelis_unit(unit) => value,
+        Some((value, unit)) if !is_amount_suffix(unit) => {
+            if balance.asset != XELIS_ASSET {
+                return Err("Fiat amounts can only be used with XEL".to_string());
+            }
+
+            let conversion = service.prices().convert(value, unit, None).await.map_err(|e| e.to_string())?;
+            return Ok(CommandAmount {
+                amount: balance.with_amount(conversion.xel),
+                rounded: false,
+                conversion: Some(conversion),
+                keyword: None
+            });
+        },
+        _ => amount
+    };
+
+    let amount = parse_amount_with_decimals(amount, balance.decimals).map_err(|e| e.to_string())?;
+    Ok(CommandAmount {
+        amount: balance.with_amount(amount.value),
+        rounded: amount.rounded,
+        conversion: None,
+        keyword: None
+    })
+}
+
+// Parse the amount of a withdrawal
+// Keywords are relative to the largest amount that can be withdrawn once the fee is paid
+// The minimum withdrawal is checked before anything is shown to the user
+async fn parse_withdraw_amount(service: &WalletService, user: &UserApplication, to: &Address, asset: Option<&str>, amount: &str) -> Result<CommandAmount, String> {
+    let mut amount = parse_asset_amount(service, user, asset, amount).await?;
+    if let Some(keyword) = amount.keyword {
+        let max = service.get_max_withdraw(user, to, &amount.amount.asset).await.map_err(|e| e.to_string())?;
+        amount.amount.amount = keyword.of(max);
+    }
+
+    service.check_min_withdraw(&amount.amount.asset, amount.amount.amount).map_err(|e| e.to_string())?;
+
+    Ok(amount)
+}
+
+// Suggest the assets held by the user, with their balance as label
+async fn autocomplete_asset(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let balances = match ctx.data().get_balances_for_user(&UserApplication::Discord(ctx.author().id.into())).await {
+        Ok(balances) => balances,
+        Err(e) => {
+            debug!("Couldn't load balances for autocomplete: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let partial = partial.to_lowercase();
+    balances.into_iter()
+        .filter(|balance| balance.name.to_lowercase().starts_with(&partial) || balance.asset.to_string().starts_with(&partial))
+        .map(|balance| AutocompleteChoice::new(balance.to_string(), balance.asset.to_string()))
+        // Discord doesn't show more than 25 choices
+        .take(25)
+        .collect()
+}
+
+// Suggest the labels of the address book of the user, with their address as label
+async fn autocomplete_address(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let addresses = match ctx.data().get_addresses(&UserApplication::Discord(ctx.author().id.into())).await {
+        Ok(addresses) => addresses,
+        Err(e) => {
+            debug!("Couldn't load address book for autocomplete: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let partial = partial.to_lowercase();
+    addresses.into_iter()
+        .filter(|saved| saved.label.starts_with(&partial))
+        .map(|saved| AutocompleteChoice::new(format!("{} ({})", saved.label, saved.address), saved.label))
+        // Discord doesn't show more than 25 choices
+        .take(25)
+        .collect()
+}
+
+/// Withdraw from your balance
+#[poise::command(slash_command, broadcast_typing)]
+async fn withdraw(
+    ctx: Context<'_>,
+    #[description = "Address, or label of your address book"] #[autocomplete = "autocomplete_address"] address: String,
+    amount: String,
+    #[description = "Asset to withdraw, XEL by default"] #[autocomplete = "autocomplete_asset"] asset: Option<String>
+) -> Result<(), Error> {
+    let service = ctx.data();
+    let ephemeral = ctx.channel_id().to_channel(ctx.http()).await?.private().is_none();
+    let user = UserApplication::Discord(ctx.author().id.into());
+
+    // Parse the address, or find it in the address book
+    let to = match service.resolve_address(&user, &address).await {
+        Ok(address) => address,
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(ephemeral).embed(
+                CreateEmbed::default()
+                    .title("Withdraw")
+                    .field("An error occured while withdrawing", e.to_string(), false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    // Parse amount in the precision of the chosen asset
+    let amount = match parse_withdraw_amount(service, &user, &to, asset.as_deref(), &amount).await {
+        Ok(amount) => amount,
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(ephemeral).embed(
+                CreateEmbed::default()
+                    .title("Withdraw")
+                    .field("An error occured while withdrawing", e, false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let CommandAmount { amount, rounded, conversion, .. } = amount;
+    let fee = match service.estimate_withdraw_fee(&to, &amount.asset, amount.amount).await {
+        Ok(fee) => fee,
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(ephemeral).embed(
+                CreateEmbed::default()
+                    .title("Withdraw")
+                    .field("An error occured while withdrawing", e.to_string(), false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    // Nothing is broadcasted before the user reviewed the withdrawal
+    let mut preview = CreateEmbed::default()
+        .title("Withdraw")
+        .description("Please review your withdrawal, it can't be reverted once sent")
+        .field("Destination", to.to_string(), false)
+        .field("Amount", amount.to_string(), true)
+        .field("Estimated fee", format!("{} XEL", format_xelis(fee)), true)
+        .thumbnail(ICON)
+        .colour(COLOR);
+
+    if rounded {
+        preview = preview.field("Rounded", format!("{} only supports up to {} decimals, your amount has been rounded", amount.name, amount.decimals), false);
+    }
+
+    if let Some(conversion) = conversion.as_ref() {
+        preview = conversion_fields(preview, conversion);
+    }
+
+    let pin = if service.has_withdraw_pin(&user).await {
+        match confirm_amount_with_pin(ctx, "Withdraw", preview).await? {
+            Some(pin) => Some(pin),
+            None => return Ok(())
+        }
+    } else {
+        if !confirm_amount(ctx, "Withdraw", preview).await? {
+            return Ok(());
+        }
+
+        None
+    };
+
+    platform::withdraw(service, &DiscordPlatform::new(ctx), to, &amount, conversion.as_ref(), pin.as_deref()).await
+}
+
+/// Save the addresses you withdraw to under a label
+#[poise::command(slash_command, subcommands("address_add", "address_remove", "address_list"))]
+async fn address(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Save an address under a label, to withdraw to it with /withdraw <label>
+#[poise::command(slash_command, rename = "add")]
+async fn address_add(
+    ctx: Context<'_>,
+    #[description = "Label of the address, such as exchange"] label: String,
+    #[description = "Address to save"] address: String
+) -> Result<(), Error> {
+    let user = UserApplication::Discord(ctx.author().id.into());
+    let embed = match ctx.data().add_address(&user, &label, &address).await {
+        Ok(label) => CreateEmbed::default()
+            .title("Address Book")
+            .field(format!("Saved as {}", label), address, false)
+            .colour(COLOR),
+        Err(e) => CreateEmbed::default()
+            .title("Address Book")
+            .field("An error occured while saving the address", e.to_string(), false)
+            .colour(Colour::RED)
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed.thumbnail(ICON))).await?;
+
+    Ok(())
+}
+
+/// Remove an address from your address book
+#[poise::command(slash_command, rename = "remove")]
+async fn address_remove(
+    ctx: Context<'_>,
+    #[description = "Label of the address"] #[autocomplete = "autocomplete_address"] label: String
+) -> Result<(), Error> {
+    let user = UserApplication::Discord(ctx.author().id.into());
+    let embed = if ctx.data().remove_address(&user, &label).await? {
+        CreateEmbed::default()
+            .title("Address Book")
+            .description(format!("{} has been removed", label))
+            .colour(COLOR)
+    } else {
+        CreateEmbed::default()
+            .title("Address Book")
+            .field("An error occured while removing the address", format!("No address is saved as {}", label), false)
+            .colour(Colour::RED)
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed.thumbnail(ICON))).await?;
+
+    Ok(())
+}
+
+/// Show your saved addresses
+#[poise::command(slash_command, rename = "list")]
+async fn address_list(ctx: Context<'_>) -> Result<(), Error> {
+    let service = ctx.data();
+    let user = UserApplication::Discord(ctx.author().id.into());
+    let addresses = service.get_addresses(&user).await?;
+    let status = service.get_whitelist_status(&user).await;
+    let mut embed = CreateEmbed::default()
+        .title("Address Book")
+        .thumbnail(ICON)
+        .colour(COLOR);
+
+    if addresses.is_empty() {
+        embed = embed.description("No address saved yet, add one with /address add");
+    }
+
+    for saved in addresses.iter() {
+        let value = match saved_address_pending(service, saved, status) {
+            Some(pending) => format!("{}\n{}", saved.address, pending),
+            None => saved.address.to_string()
+        };
+        embed = embed.field(&saved.label, value, false);
+    }
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed)).await?;
+
+    Ok(())
+}
+
+/// Tip a user with XELIS
+#[poise::command(slash_command, broadcast_typing)]
+async fn tip(
+    ctx: Context<'_>,
+    #[description = "User to tip"] user: User,
+    #[description = "Amount to tip"] amount: String,
+    #[description = "Asset to tip, XEL by default"] #[autocomplete = "autocomplete_asset"] asset: Option<String>
+) -> Result<(), Error> {
+    discord_tip(ctx, &user, &amount, asset.as_deref()).await
+}
+
+// Amount asked when tipping from the context menu of a user
+#[derive(Debug, poise::Modal)]
+#[name = "Tip with XELIS"]
+struct TipModal {
+    #[name = "Amount"]
+    #[placeholder = "1.5 or 5usd"]
+    amount: String,
+    #[name = "Asset"]
+    #[placeholder = "XEL"]
+    asset: Option<String>
+}
+
+/// Tip a user with XELIS from its context menu
+#[poise::command(context_menu_command = "Tip with XELIS")]
+async fn tip_user(ctx: ApplicationContext<'_>, user: User) -> Result<(), Error> {
+    let Some(modal) = poise::execute_modal(ctx, None::<TipModal>, Some(TIP_MODAL_TIMEOUT)).await? else {
+        return Ok(());
+    };
+
+    let asset = modal.asset.as_deref().map(str::trim).filter(|asset| !asset.is_empty());
+    discord_tip(ctx.into(), &user, &modal.amount, asset).await
+}
+
+// Tip a Discord user, shared by the slash and the context menu commands
+async fn discord_tip(ctx: Context<'_>, user: &User, amount: &str, asset: Option<&str>) -> Result<(), Error> {
+    let service = ctx.data();
+    let author = UserApplication::Discord(ctx.author().id.into());
+
+    // Parse amount in the precision of the chosen asset
+    let amount = match parse_asset_amount(service, &author, asset, amount.trim()).await {
+        Ok(amount) => amount,
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title("Tip")
+                    .field("An error occured while tipping", e, false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    // Let the user confirm the amount if it was rounded or converted
+    if !confirm_command_amount(ctx, "Tip", &amount).await? {
+        return Ok(());
+    }
+
+    if !confirm_tip_budget(ctx, "Tip", &amount.amount).await? {
+        return Ok(());
+    }
+    let CommandAmount { amount, conversion, .. } = amount;
+
+    platform::tip(service, &DiscordPlatform::new(ctx), &UserApplication::Discord(user.id.into()), &user.to_string(), &amount, conversion.as_ref()).await
+}
+
+/// Post a button that others can press to tip you
+#[poise::command(slash_command, guild_only)]
+async fn tipme(
+    ctx: Context<'_>,
+    #[description = "Suggested amount in XEL, asked to the tipper if empty"] amount: Option<String>
+) -> Result<(), Error> {
+    let amount = match amount.as_deref().map(|amount| parse_amount(amount).and_then(Amount::exact)).transpose() {
+        Ok(amount) => amount,
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title("Tip me")
+                    .field("An error occured", e.to_string(), false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let (custom_id, label) = match amount {
+        Some(amount) => (format!("{}{}:{}", TIPME_BUTTON_PREFIX, ctx.author().id, amount), format!("Tip {} XEL", format_xelis(amount))),
+        None => (format!("{}{}", TIPME_BUTTON_PREFIX, ctx.author().id), "Tip".to_string())
+    };
+
+    ctx.send(CreateReply::default()
+        .embed(
+            CreateEmbed::default()
+                .title("Tip me")
+                .description(format!("{} accepts tips in XELIS, press the button to tip them", ctx.author()))
+                .thumbnail(ICON)
+                .colour(COLOR)
+        )
+        .components(vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(custom_id).label(label).style(ButtonStyle::Success)
+        ])])
+    ).await?;
+
+    Ok(())
+}
+
+// Serenity context outside of a command, as expected by the poise modals
+struct ModalContext<'a>(&'a SerenityContext);
+
+impl AsRef<SerenityContext> for ModalContext<'_> {
+    fn as_ref(&self) -> &SerenityContext {
+        self.0
+    }
+}
+
+// Tip the author of a /tipme message once the user that pressed its button confirmed
+// Without a suggested amount, it's asked in the same modal as the context menu
+async fn discord_tipme(ctx: &SerenityContext, service: &WalletService, component: &ComponentInteraction, data: &str) -> Result<(), Error> {
+    let mut parts = data.split(':');
+    let Some(to) = parts.next().and_then(|id| id.parse::<u64>().ok()) else {
+        return Ok(());
+    };
+    let suggested = parts.next().and_then(|amount| amount.parse::<u64>().ok());
+
+    if to == component.user.id.get() {
+        component.create_response(ctx, CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content("You can't tip yourself")
+                .ephemeral(true)
+        )).await?;
+        return Ok(());
+    }
+
+    let author = UserApplication::Discord(component.user.id.into());
+    let amount = match suggested {
+        Some(amount) => {
+            component.defer_ephemeral(ctx).await?;
+            Ok(AssetBalance::xelis(amount))
+        },
+        None => {
+            let Some(modal) = poise::execute_modal_on_component_interaction::<TipModal>(ModalContext(ctx), component.clone(), None, Some(TIP_MODAL_TIMEOUT)).await? else {
+                return Ok(());
+            };
+
+            let asset = modal.asset.as_deref().map(str::trim).filter(|asset| !asset.is_empty());
+            parse_asset_amount(service, &author, asset, modal.amount.trim()).await.map(|amount| amount.amount)
+        }
+    };
+
+    let amount = match amount {
+        Ok(amount) => amount,
+        Err(e) => {
+            component.create_followup(ctx, CreateInteractionResponseFollowup::new().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title("Tip")
+                    .field("An error occured while tipping", e, false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+            )).await?;
+            return Ok(());
+        }
+    };
+
+    let mut embed = CreateEmbed::default()
+        .title("Tip")
+        .description(format!("Tip {} to <@{}>?", amount, to))
+        .thumbnail(ICON)
+        .colour(COLOR);
+
+    // Budgets only apply to XEL
+    if amount.asset == XELIS_ASSET {
+        if let Some(overrun) = service.check_tip_budget(&author, amount.amount).await {
+            embed = embed.field(format!("This tip goes over your {} budget", overrun.period), budget_overrun_text(&overrun), false);
+        }
+    }
+
+    let confirm_id = format!("{}-confirm", component.id);
+    let cancel_id = format!("{}-cancel", component.id);
+    let confirmation = component.create_followup(ctx, CreateInteractionResponseFollowup::new()
+        .ephemeral(true)
+        .embed(embed)
+        .components(vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(&confirm_id).label("Confirm").style(ButtonStyle::Success),
+            CreateButton::new(&cancel_id).label("Cancel").style(ButtonStyle::Danger)
+        ])])
+    ).await?;
+
+    let interaction = ComponentInteractionCollector::new(ctx)
+        .author_id(component.user.id)
+        .custom_ids(vec![confirm_id.clone(), cancel_id])
+        .timeout(CONFIRMATION_TIMEOUT)
+        .await;
+
+    let Some(interaction) = interaction else {
+        component.edit_followup(ctx, confirmation.id, CreateInteractionResponseFollowup::new()
+            .embed(
+                CreateEmbed::default()
+                    .title("Tip")
+                    .description("Confirmation timed out")
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+            )
+            .components(Vec::new())
+        ).await?;
+        return Ok(());
+    };
+
+    // Remove the buttons, the embed is kept as is
+    interaction.create_response(ctx, CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new().components(Vec::new())
+    )).await?;
+
+    if interaction.data.custom_id != confirm_id {
+        return Ok(());
+    }
+
+    let community = component.guild_id.map(|id| Community::Discord(id.get()));
+    // One tip per button press, even if the confirmation is clicked twice
+    let operation = OperationId::discord("tipme", component.id.get());
+    match service.transfer(&author, &UserApplication::Discord(to), &amount.asset, amount.amount, community, Some(&operation)).await {
+        Ok(()) => {
+            let embed = CreateEmbed::default()
+                .title("Tip")
+                .description(format!("{} have tipped {} to <@{}>", component.user, amount, to))
+                .thumbnail(ICON)
+                .colour(COLOR);
+
+            component.channel_id.send_message(ctx, CreateMessage::default().embed(embed.clone())).await?;
+            if let Some(guild_id) = component.guild_id {
+                mirror_discord_announcement_in(ctx, service, guild_id.get(), component.channel_id, embed).await;
+            }
+        },
+        Err(e) => {
+            component.create_followup(ctx, CreateInteractionResponseFollowup::new().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title("Tip")
+                    .field("An error occured while tipping", e.to_string(), false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+            )).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Split XELIS between the most recently active users of the channel
+#[poise::command(slash_command, guild_only, broadcast_typing)]
+async fn rain(
+    ctx: Context<'_>,
+    #[description = "Total amount to split"] amount: String,
+    #[description = "How many active users receive a share"] #[min = 1] #[max = 50] users: u8
+) -> Result<(), Error> {
+    if !check_discord_feature(ctx, Feature::Rain).await? {
+        return Ok(());
+    }
+
+    let amount = match parse_amount(&amount) {
+        Ok(amount) => amount,
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title("Rain")
+                    .field("An error occured while raining", e.to_string(), false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    // Let the user confirm the amount if it was rounded
+    if amount.rounded && !confirm_rounded_amount(ctx, "Rain", &AssetBalance::xelis(amount.value)).await? {
+        return Ok(());
+    }
+
+    if !confirm_tip_budget(ctx, "Rain", &AssetBalance::xelis(amount.value)).await? {
+        return Ok(());
+    }
+    let amount = amount.value;
+
+    // Collect the latest non-bot authors of the channel, most recent first
+    // Authors rejected by the recipient filter of the server are skipped
+    let guild_id = ctx.guild_id().ok_or(DiscordError::NoGuild)?;
+    let filter = ctx.data().get_recipient_filter(&Community::Discord(guild_id.get())).await;
+    let author = ctx.author().id;
+    let messages = ctx.channel_id().messages(ctx.http(), GetMessages::new().limit(RAIN_HISTORY_LIMIT)).await?;
+    let mut recipients: Vec<User> = Vec::new();
+    let mut filtered: Vec<User> = Vec::new();
+    for message in messages {
+        if message.author.bot
+            || message.author.id == author
+            || recipients.iter().chain(filtered.iter()).any(|u| u.id == message.author.id) {
+            continue;
+        }
+
+        if !filter.is_empty() {
+            let member = if filter.needs_member() {
+                guild_id.member(ctx, message.author.id).await.ok()
+            } else {
+                None
+            };
+
+            if !discord_recipient_allowed(&filter, &message.author, member.as_ref()) {
+                filtered.push(message.author);
+                continue;
+            }
+        }
+
+        recipients.push(message.author);
+        if recipients.len() >= users as usize {
+            break;
+        }
+    }
+
+    let share = amount / recipients.len().max(1) as u64;
+    let transfers: Vec<(UserApplication, u64)> = recipients.iter()
+        .map(|user| (UserApplication::Discord(user.id.into()), share))
+        .collect();
+
+    // Show who receives what before anything is debited, the rain goes to these exact users
+    if !recipients.is_empty() && share > 0 {
+        let mentions = recipients.iter()
+            .map(|user| user.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut preview = CreateEmbed::default()
+            .title("Rain")
+            .description(format!("Confirm to make it rain on these {} users\n{}", recipients.len(), mentions))
+            .field("Each user receives", format!("{} XEL", format_xelis(share)), true)
+            .field("Total", format!("{} XEL", format_xelis(share * recipients.len() as u64)), true)
+            .thumbnail(ICON)
+            .colour(COLOR);
+
+        if !filtered.is_empty() {
+            preview = preview.field("Skipped", format!("{} users not allowed by the recipient filters of the server", filtered.len()), false);
+        }
+
+        if !confirm_amount(ctx, "Rain", preview).await? {
+            return Ok(());
+        }
+    }
+
+    // Debit the author and credit every recipient at once
+    let service = ctx.data();
+    let community = ctx.guild_id().map(|id| Community::Discord(id.get()));
+    let operation = OperationId::discord("rain", ctx.id());
+    match service.transfer_many(&UserApplication::Discord(author.into()), &transfers, community, Some(&operation)).await {
+        Ok(total) => {
+            let mentions = recipients.iter()
+                .map(|user| user.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let embed = CreateEmbed::default()
+                .title("Rain")
+                .description(format!("{} made it rain {} XEL on {} users", ctx.author(), format_xelis(total), recipients.len()))
+                .field("Each user received", format!("{} XEL", format_xelis(share)), false)
+                .field("Recipients", mentions, false)
+                .thumbnail(ICON)
+                .colour(COLOR);
+
+            ctx.send(CreateReply::default().embed(embed.clone())).await?;
+            mirror_discord_announcement(ctx, embed).await;
+        },
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title("Rain")
+                    .field("An error occured while raining", e.to_string(), false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Start a giveaway, users click the button to claim an equal share
+#[poise::command(slash_command, guild_only, broadcast_typing)]
+async fn giveaway(
+    ctx: Context<'_>,
+    #[description = "Total amount to give away"] amount: String,
+    #[description = "Duration in minutes"] #[min = 1] #[max = 10080] duration: u32,
+    #[description = "Maximum users that can claim"] #[min = 1] #[max = 100] max_claims: u16
+) -> Result<(), Error> {
+    if !check_discord_feature(ctx, Feature::Giveaway).await? {
+        return Ok(());
+    }
+
+    let amount = match parse_amount(&amount) {
+        Ok(amount) => amount,
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title("Giveaway")
+                    .field("An error occured while starting the giveaway", e.to_string(), false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    // Let the user confirm the amount if it was rounded
+    if amount.rounded && !confirm_rounded_amount(ctx, "Giveaway", &AssetBalance::xelis(amount.value)).await? {
+        return Ok(());
+    }
+    let amount = amount.value;
+
+    let duration = Duration::from_secs(duration.min(MAX_GIVEAWAY_DURATION) as u64 * 60);
+    if amount / max_claims as u64 > 0 && !confirm_amount(ctx, "Giveaway", giveaway_preview(amount, max_claims, duration)).await? {
+        return Ok(());
+    }
+
+    let service = ctx.data();
+    match service.create_giveaway(&UserApplication::Discord(ctx.author().id.into()), amount, max_claims, duration, ctx.channel_id().get()).await {
+        Ok(giveaway) => {
+            ctx.send(CreateReply::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("Giveaway")
+                        .description(format!("{} is giving away {} XEL", ctx.author(), format_xelis(giveaway.amount)))
+                        .field("Max claims", giveaway.max_claims.to_string(), true)
+                        .field("Ends", format!("<t:{}:R>", giveaway.expire_at), true)
+                        .thumbnail(ICON)
+                        .colour(COLOR)
+                )
+                .components(vec![CreateActionRow::Buttons(vec![
+                    CreateButton::new(format!("{}{}", GIVEAWAY_BUTTON_PREFIX, giveaway.id)).label("Claim").style(ButtonStyle::Success)
+                ])])
+            ).await?;
+        },
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title("Giveaway")
+                    .field("An error occured while starting the giveaway", e.to_string(), false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+        }
+    };
+
+    Ok(())
+}
+
+// Payout of a giveaway shown to its host before the amount is escrowed
+fn giveaway_preview(amount: u64, max_claims: u16, duration: Duration) -> CreateEmbed {
+    CreateEmbed::default()
+        .title("Giveaway")
+        .description(format!("Confirm to escrow {} XEL for up to {} users", format_xelis(amount), max_claims))
+        .field("Each user receives at least", format!("{} XEL", format_xelis(amount / max_claims as u64)), true)
+        .field("Ends in", format!("{} minutes", duration.as_secs() / 60), true)
+        .field("Payout", "The amount is split equally between the claimants when the giveaway ends, the rest is refunded", false)
+        .thumbnail(ICON)
+        .colour(COLOR)
+}
+
+// Check that a feature is enabled in the guild, otherwise let the author know
+async fn check_discord_feature(ctx: Context<'_>, feature: Feature) -> Result<bool, Error> {
+    let community = ctx.guild_id().map(|id| Community::Discord(id.get()));
+    if let Err(e) = ctx.data().check_feature(community.as_ref(), feature).await {
+        ctx.send(CreateReply::default().ephemeral(true).embed(
+            CreateEmbed::default()
+                .title(feature.name())
+                .description(e.to_string())
+                .thumbnail(ICON)
+                .colour(Colour::RED)
+            )
+        ).await?;
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+// Roles of the author in the guild, none in DMs
+async fn discord_author_roles(ctx: Context<'_>) -> Vec<u64> {
+    match ctx.author_member().await {
+        Some(member) => member.roles.iter().map(|role| role.get()).collect(),
+        None => Vec::new()
+    }
+}
+
+// Refuse the command if the server doesn't allow it here or if the author is sending too many of them
+async fn discord_command_check(ctx: Context<'_>) -> Result<bool, Error> {
+    let service = ctx.data();
+    if !service.is_leader() && !DISCORD_READ_ONLY_COMMANDS.contains(&discord_root_command(ctx)) {
+        discord_check_failed(ctx, "Standby", ServiceError::NotLeader.to_string()).await?;
+        return Ok(false);
+    }
+
+    if let Some(guild_id) = ctx.guild_id() {
+        let community = Community::Discord(guild_id.get());
+        if let Err(e) = service.check_command_rule(&community, discord_root_command(ctx), ctx.channel_id().get()).await {
+            discord_check_failed(ctx, "Unavailable command", e.to_string()).await?;
+            return Ok(false);
+        }
+    }
+
+    let user = UserApplication::Discord(ctx.author().id.into());
+    let roles = discord_author_roles(ctx).await;
+    if let Some(limited) = service.check_rate_limit(&user, &roles, &ctx.command().qualified_name).await {
+        discord_check_failed(ctx, "Slow down", limited.to_string()).await?;
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+// Slash commands must always be answered, but only privately
+async fn discord_check_failed(ctx: Context<'_>, title: &str, reason: String) -> Result<(), Error> {
+    ctx.send(CreateReply::default()
+        .embed(CreateEmbed::default()
+            .title(title)
+            .description(reason)
+            .color(COLOR)
+            .thumbnail(ICON)
+        )
+        .ephemeral(true)
+    ).await?;
+
+    Ok(())
+}
+
+// Top level command used, so the rules of a command apply to its subcommands
+fn discord_root_command<'a>(ctx: Context<'a>) -> &'a str {
+    ctx.parent_commands().first().copied().unwrap_or(ctx.command()).name.as_str()
+}
+
+// Check if the author is exempted by the operator, directly or through one of its roles
+async fn is_discord_user_exempt(ctx: Context<'_>, user: &UserApplication) -> bool {
+    let roles = discord_author_roles(ctx).await;
+
+    ctx.data().is_exempt(user, &roles).await
+}
+
+/// Claim free XELIS from the faucet
+#[poise::command(slash_command, broadcast_typing)]
+async fn faucet(ctx: Context<'_>) -> Result<(), Error> {
+    if !check_discord_feature(ctx, Feature::Faucet).await? {
+        return Ok(());
+    }
+
+    let service = ctx.data();
+    let user = UserApplication::Discord(ctx.author().id.into());
+    let exempt = is_discord_user_exempt(ctx, &user).await;
+    let embed = match service.claim_faucet(&user, exempt).await {
+        Ok(amount) => CreateEmbed::default()
+            .title("Faucet")
+            .description(format!("You received {} XEL", format_xelis(amount)))
+            .thumbnail(ICON)
+            .colour(COLOR),
+        Err(e) => CreateEmbed::default()
+            .title("Faucet")
+            .field("An error occured while claiming the faucet", e.to_string(), false)
+            .thumbnail(ICON)
+            .colour(Colour::RED)
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed)).await?;
+
+    Ok(())
+}
+
+#[derive(poise::ChoiceParameter, Clone, Copy)]
+enum LeaderboardPeriod {
+    #[name = "Today"]
+    Day,
+    #[name = "This week"]
+    Week,
+    #[name = "All time"]
+    AllTime
+}
+
+impl LeaderboardPeriod {
+    fn stats_period(&self) -> StatsPeriod {
+        match self {
+            LeaderboardPeriod::Day => StatsPeriod::current_day(),
+            LeaderboardPeriod::Week => StatsPeriod::current_week(),
+            LeaderboardPeriod::AllTime => StatsPeriod::AllTime
+        }
+    }
+}
+
+#[derive(poise::ChoiceParameter, Clone, Copy)]
+enum LeaderboardType {
+    #[name = "Top tippers"]
+    Tippers,
+    #[name = "Top receivers"]
+    Receivers
+}
+
+// Build the embed of a leaderboard page with its navigation buttons
+fn leaderboard_page(title: &str, leaderboard: &[(UserApplication, u64)], page: usize, previous_id: &str, next_id: &str) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let pages = leaderboard.len().div_ceil(LEADERBOARD_PAGE_SIZE).max(1);
+    let start = page * LEADERBOARD_PAGE_SIZE;
+    let lines = leaderboard.iter()
+        .enumerate()
+        .skip(start)
+        .take(LEADERBOARD_PAGE_SIZE)
+        .map(|(i, (user, amount))| {
+            let user = match user {
+                UserApplication::Discord(id) => format!("<@{}>", id),
+                UserApplication::Telegram(id) => format!("Telegram user {}", id),
+                UserApplication::Matrix(id) => format!("Matrix user {}", id),
+                UserApplication::Slack(id) => format!("Slack user {}", id),
+                UserApplication::Twitch(id) => format!("Twitch user {}", id)
+            };
+            format!("**{}.** {} - {} XEL", i + 1, user, format_xelis(*amount))
+        })
+        .collect::<Vec<_>>();
+
+    let embed = CreateEmbed::default()
+        .title(title)
+        .description(if lines.is_empty() { "No tips yet for this period".to_string() } else { lines.join("\n") })
+        .footer(CreateEmbedFooter::new(format!("Page {}/{}", page + 1, pages)))
+        .thumbnail(ICON)
+        .colour(COLOR);
+
+    let components = if pages > 1 {
+        vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(previous_id).label("Previous").style(ButtonStyle::Secondary).disabled(page == 0),
+            CreateButton::new(next_id).label("Next").style(ButtonStyle::Secondary).disabled(page + 1 >= pages)
+        ])]
+    } else {
+        Vec::new()
+    };
+
+    (embed, components)
+}
+
+/// Show the top tippers or receivers
+#[poise::command(slash_command, broadcast_typing)]
+async fn leaderboard(
+    ctx: Context<'_>,
+    #[description = "Period of the leaderboard, this week by default"] period: Option<LeaderboardPeriod>,
+    #[description = "Rank the tippers or the receivers, tippers by default"] kind: Option<LeaderboardType>
+) -> Result<(), Error> {
+    let period = period.unwrap_or(LeaderboardPeriod::Week);
+    let kind = kind.unwrap_or(LeaderboardType::Tippers);
+    let title = format!("Leaderboard - {} - {}", kind.name(), period.name());
+
+    // In a guild only the tips done in it are ranked
+    let community = ctx.guild_id().map(|id| Community::Discord(id.get()));
+    let service = ctx.data();
+    let leaderboard = service.get_leaderboard(period.stats_period(), community, match kind {
+        LeaderboardType::Tippers => LeaderboardKind::Tippers,
+        LeaderboardType::Receivers => LeaderboardKind::Receivers
+    }).await?;
+
+    let previous_id = format!("{}-previous", ctx.id());
+    let next_id = format!("{}-next", ctx.id());
+    let mut page = 0;
+
+    let (embed, components) = leaderboard_page(&title, &leaderboard, page, &previous_id, &next_id);
+    if components.is_empty() {
+        ctx.send(CreateReply::default().embed(embed)).await?;
+        return Ok(());
+    }
+
+    let reply = ctx.send(CreateReply::default().embed(embed).components(components)).await?;
+    while let Some(interaction) = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .custom_ids(vec![previous_id.clone(), next_id.clone()])
+        .timeout(LEADERBOARD_TIMEOUT)
+        .await
+    {
+        if interaction.data.custom_id == previous_id {
+            page = page.saturating_sub(1);
+        } else {
+            page += 1;
+        }
+
+        let (embed, components) = leaderboard_page(&title, &leaderboard, page, &previous_id, &next_id);
+        interaction.create_response(ctx, CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new().embed(embed).components(components)
+        )).await?;
+    }
+
+    // Browsing is over, keep the last page without its buttons
+    let (embed, _) = leaderboard_page(&title, &leaderboard, page, &previous_id, &next_id);
+    reply.edit(ctx, CreateReply::default().embed(embed).components(Vec::new())).await?;
+
+    Ok(())
+}
+
+// Display name of a game
+fn game_name(game: Game) -> &'static str {
+    match game {
+        Game::Coinflip => "Coinflip",
+        Game::Dice => "Dice"
+    }
+}
+
+// Describe what happened during a game
+fn outcome_text(outcome: &Outcome) -> String {
+    match outcome {
+        Outcome::Coinflip { heads } => if *heads { "Heads" } else { "Tails" }.to_string(),
+        Outcome::Dice { challenger, opponent, rerolls } => {
+            let mut text = format!("Challenger rolled {}, opponent rolled {}", challenger, opponent);
+            if *rerolls > 0 {
+                text.push_str(&format!(" after {} tie(s)", rerolls));
+            }
+            text
+        }
+    }
+}
+
+// Embed showing the seed of a challenge so players can verify its outcome
+fn challenge_embed(challenge: &Challenge) -> CreateEmbed {
+    CreateEmbed::default()
+        .title(game_name(challenge.game))
+        .field("Stake", format!("{} XEL each", format_xelis(challenge.amount)), true)
+        .field("Seed commitment", format!("`{}`", challenge.commitment()), false)
+        .footer(CreateEmbedFooter::new("Outcome is blake3(seed || id), the seed is revealed once the game ends"))
+        .thumbnail(ICON)
+        .colour(COLOR)
+}
+
+// Embed of a settled challenge
+fn challenge_result_embed(result: &ChallengeResult) -> CreateEmbed {
+    let winner = match result.winner {
+        UserApplication::Discord(id) => format!("<@{}>", id),
+        UserApplication::Telegram(id) => format!("Telegram user {}", id),
+        UserApplication::Matrix(id) => format!("Matrix user {}", id),
+        UserApplication::Slack(id) => format!("Slack user {}", id),
+        UserApplication::Twitch(id) => format!("Twitch user {}", id)
+    };
+
+    challenge_embed(&result.challenge)
+        .description(format!("{} won {} XEL", winner, format_xelis(result.prize)))
+        .field("Result", outcome_text(&result.outcome), true)
+        .field("Seed", format!("`{}`", result.challenge.seed()), false)
+        .field("Id", result.challenge.id.to_string(), true)
+}
+
+// Escrow the stake of the author and post the challenge with its buttons
+async fn start_challenge(ctx: Context<'_>, game: Game, user: Option<User>, amount: String) -> Result<(), Error> {
+    if !check_discord_feature(ctx, Feature::Games).await? {
+        return Ok(());
+    }
+
+    let title = game_name(game);
+    let amount = match parse_amount(&amount) {
+        Ok(amount) => amount,
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title(title)
+                    .field("An error occured while creating the challenge", e.to_string(), false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    // Let the user confirm the amount if it was rounded
+    if amount.rounded && !confirm_rounded_amount(ctx, title, &AssetBalance::xelis(amount.value)).await? {
+        return Ok(());
+    }
+    let amount = amount.value;
+
+    if user.as_ref().is_some_and(|user| user.bot) {
+        ctx.send(CreateReply::default().ephemeral(true).embed(
+            CreateEmbed::default()
+                .title(title)
+                .field("An error occured while creating the challenge", "Bots can't be challenged", false)
+                .thumbnail(ICON)
+                .colour(Colour::RED)
+            )
+        ).await?;
+        return Ok(());
+    }
+
+    let service = ctx.data();
+    let opponent = user.as_ref().map(|user| UserApplication::Discord(user.id.into()));
+    match service.create_challenge(game, &UserApplication::Discord(ctx.author().id.into()), opponent.as_ref(), amount, ctx.channel_id().get()).await {
+        Ok(challenge) => {
+            let opponent = user.as_ref().map(|user| user.to_string()).unwrap_or_else(|| "anyone".to_string());
+            let description = match game {
+                Game::Coinflip => format!("{} challenged {} to a coinflip, {} is heads", ctx.author(), opponent, ctx.author()),
+                Game::Dice => format!("{} challenged {} to a dice duel, the highest roll wins", ctx.author(), opponent)
+            };
+
+            let mut reply = CreateReply::default()
+                .embed(
+                    challenge_embed(&challenge)
+                        .description(description)
+                        .field("Expires", format!("<t:{}:R>", challenge.expire_at), true)
+                )
+                .components(vec![CreateActionRow::Buttons(vec![
+                    CreateButton::new(format!("{}{}", CHALLENGE_ACCEPT_PREFIX, challenge.id)).label("Accept").style(ButtonStyle::Success),
+                    CreateButton::new(format!("{}{}", CHALLENGE_DECLINE_PREFIX, challenge.id)).label("Decline").style(ButtonStyle::Danger)
+                ])]);
+
+            // Ping the challenged user
+            if let Some(user) = user {
+                reply = reply.content(user.to_string());
+            }
+
+            ctx.send(reply).await?;
+        },
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title(title)
+                    .field("An error occured while creating the challenge", e.to_string(), false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Challenge a user to a coinflip, the winner takes both stakes
+#[poise::command(slash_command, guild_only, broadcast_typing)]
+async fn coinflip(
+    ctx: Context<'_>,
+    #[description = "User to challenge"] user: User,
+    #[description = "Amount staked by each player"] amount: String
+) -> Result<(), Error> {
+    start_challenge(ctx, Game::Coinflip, Some(user), amount).await
+}
+
+/// Start a dice duel, the highest roll takes both stakes
+#[poise::command(slash_command, guild_only, broadcast_typing)]
+async fn dice(
+    ctx: Context<'_>,
+    #[description = "Amount staked by each player"] amount: String,
+    #[description = "User to challenge, anyone can accept if empty"] user: Option<User>
+) -> Result<(), Error> {
+    start_challenge(ctx, Game::Dice, user, amount).await
+}
+
+/// Convert an amount between XEL and a fiat currency
+#[poise::command(slash_command, broadcast_typing)]
+async fn convert(
+    ctx: Context<'_>,
+    #[description = "Amount to convert"] amount: String,
+    #[description = "Unit of the amount: XEL or a fiat currency code like USD"] unit: String,
+    #[description = "Fiat currency when converting from XEL, USD by default"] currency: Option<String>
+) -> Result<(), Error> {
+    let service = ctx.data();
+    let embed = match service.prices().convert(&amount, &unit, currency.as_deref()).await {
+        Ok(conversion) => CreateEmbed::default()
+            .title("Convert")
+            .field("XEL", format!("{} XEL", format_xelis(conversion.xel)), true)
+            .field(&conversion.currency, format!("{} {}", format_fiat(conversion.fiat), conversion.currency), true)
+            .field("Atomic units", conversion.xel.to_string(), false)
+            .field("Rates", conversion_rates(&conversion), false)
+            .thumbnail(ICON)
+            .colour(COLOR),
+        Err(e) => CreateEmbed::default()
+            .title("Convert")
+            .field("An error occured while converting", e.to_string(), false)
+            .thumbnail(ICON)
+            .colour(Colour::RED)
+    };
+
+    ctx.send(CreateReply::default().embed(embed)).await?;
+
+    Ok(())
+}
+
+// Fetch the market data in the requested currency, or in all the configured ones
+async fn fetch_markets(service: &WalletService, currency: Option<&str>) -> Vec<(String, Result<MarketData, PriceError>)> {
+    let currencies = match currency {
+        Some(currency) => vec![currency.to_string()],
+        None => service.prices().currencies().to_vec()
+    };
+
+    let mut markets = Vec::with_capacity(currencies.len());
+    for currency in currencies {
+        let market = service.prices().get_market(&currency).await;
+        markets.push((currency.to_uppercase(), market));
+    }
+
+    markets
+}
+
+// Price, 24h change and volume of XELIS in a currency
+fn market_summary(currency: &str, market: &MarketData) -> String {
+    let mut summary = format!("1 XEL = {} {}", format_fiat(market.price), currency);
+    if let Some(change) = market.change_24h {
+        summary.push_str(&format!("\n24h change: {:+.2}%", change));
+    }
+
+    if let Some(volume) = market.volume_24h {
+        summary.push_str(&format!("\n24h volume: {:.0} {}", volume, currency));
+    }
+
+    summary
+}
+
+/// Show the XELIS price with its 24h change and volume
+#[poise::command(slash_command, broadcast_typing)]
+async fn price(ctx: Context<'_>, #[description = "Fiat currency code like USD, the configured ones by default"] currency: Option<String>) -> Result<(), Error> {
+    let mut embed = CreateEmbed::default()
+        .title("Price")
+        .thumbnail(ICON)
+        .colour(COLOR);
+
+    for (currency, market) in fetch_markets(ctx.data(), currency.as_deref()).await {
+        embed = match market {
+            Ok(market) => embed.field(&currency, market_summary(&currency, &market), true),
+            Err(e) => embed.field(&currency, e.to_string(), true).colour(Colour::RED)
+        };
+    }
+
+    ctx.send(CreateReply::default().embed(embed)).await?;
+
+    Ok(())
+}
+
+// Fiat value of an amount and the rate used to convert it
+fn conversion_fields(embed: CreateEmbed, conversion: &Conversion) -> CreateEmbed {
+    embed.field("Value", format!("{} {}", format_fiat(conversion.fiat), conversion.currency), true)
+        .field("Rate", format!("1 XEL = {} {}", format_fiat(conversion.price), conversion.currency), true)
+}
+
+// Exchange rate in both directions
+fn conversion_rates(conversion: &Conversion) -> String {
+    format!(
+        "1 XEL = {} {}\n1 {} = {} XEL",
+        format_fiat(conversion.price),
+        conversion.currency,
+        conversion.currency,
+        format_fiat(1.0 / conversion.price)
+    )
+}
+
+// Embed of a lottery round with the tickets of the user
+fn lottery_embed(round: &LotteryRound, pot: u64, tickets: u32, last_draw: Option<LotteryDraw>) -> CreateEmbed {
+    let mut embed = CreateEmbed::default()
+        .title(format!("Lottery round #{}", round.id))
+        .field("Pot", format!("{} XEL", format_xelis(pot)), true)
+        .field("Ticket price", format!("{} XEL", format_xelis(round.ticket_price)), true)
+        .field("Tickets sold", round.total_tickets().to_string(), true)
+        .field("Your tickets", tickets.to_string(), true)
+        .field("Draw", format!("<t:{}:R>", round.draw_at), true)
+        .field("Seed commitment", format!("`{}`", round.commitment()), false)
+        .footer(CreateEmbedFooter::new("Winning ticket is blake3(seed || round) modulo the tickets sold"))
+        .thumbnail(ICON)
+        .colour(COLOR);
+
+    if let Some(draw) = last_draw {
+        let winner = match draw.winner {
+            Some(UserApplication::Discord(id)) => format!("<@{}> won {} XEL", id, format_xelis(draw.pot)),
+            Some(UserApplication::Telegram(id)) => format!("Telegram user {} won {} XEL", id, format_xelis(draw.pot)),
+            Some(UserApplication::Matrix(id)) => format!("Matrix user {} won {} XEL", id, format_xelis(draw.pot)),
+            Some(UserApplication::Slack(id)) => format!("Slack user {} won {} XEL", id, format_xelis(draw.pot)),
+            Some(UserApplication::Twitch(id)) => format!("Twitch user {} won {} XEL", id, format_xelis(draw.pot)),
+            None => "No tickets were sold".to_string()
+        };
+        embed = embed.field(format!("Round #{}", draw.round), format!("{}\nSeed: `{}`", winner, draw.seed), false);
+    }
+
+    embed
+}
+
+/// Play the lottery, the whole pot goes to a random ticket
+#[poise::command(slash_command, subcommands("lottery_info", "lottery_buy"))]
+async fn lottery(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show the running lottery round
+#[poise::command(slash_command, rename = "info", broadcast_typing)]
+async fn lottery_info(ctx: Context<'_>) -> Result<(), Error> {
+    if !check_discord_feature(ctx, Feature::Lottery).await? {
+        return Ok(());
+    }
+
+    let service = ctx.data();
+    let embed = match service.get_lottery().await {
+        Ok((round, pot)) => {
+            let tickets = round.tickets_of(&UserApplication::Discord(ctx.author().id.into()));
+            lottery_embed(&round, pot, tickets, service.get_last_lottery_draw().await)
+        },
+        Err(e) => CreateEmbed::default()
+            .title("Lottery")
+            .field("An error occured while loading the lottery", e.to_string(), false)
+            .thumbnail(ICON)
+            .colour(Colour::RED)
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed)).await?;
+
+    Ok(())
+}
+
+/// Buy tickets for the running lottery round
+#[poise::command(slash_command, rename = "buy", broadcast_typing)]
+async fn lottery_buy(
+    ctx: Context<'_>,
+    #[description = "Number of tickets to buy"] #[min = 1] #[max = 1000] count: u32
+) -> Result<(), Error> {
+    if !check_discord_feature(ctx, Feature::Lottery).await? {
+        return Ok(());
+    }
+
+    let service = ctx.data();
+    let embed = match service.buy_lottery_tickets(&UserApplication::Discord(ctx.author().id.into()), count).await {
+        Ok((round, tickets)) => {
+            let (_, pot) = service.get_lottery().await?;
+            lottery_embed(&round, pot, tickets, None)
+                .description(format!("You bought {} tickets", count))
+        },
+        Err(e) => CreateEmbed::default()
+            .title("Lottery")
+            .field("An error occured while buying tickets", e.to_string(), false)
+            .thumbnail(ICON)
+            .colour(Colour::RED)
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed)).await?;
+
+    Ok(())
+}
+
+// Target and reward of a goal, the reward is optional
+fn parse_goal_amounts(target: &str, reward: Option<&str>) -> Result<(u64, u64), AmountError> {
+    let target = parse_amount(target).and_then(Amount::exact)?;
+    let reward = match reward {
+        Some(reward) => parse_amount(reward).and_then(Amount::exact)?,
+        None => 0
+    };
+
+    Ok((target, reward))
+}
+
+fn goal_error_embed(title: &str, error: impl ToString) -> CreateEmbed {
+    CreateEmbed::default()
+        .title("Tipping goal")
+        .field(title, error.to_string(), false)
+        .thumbnail(ICON)
+        .colour(Colour::RED)
+}
+
+/// Tip together toward a monthly goal of the server
+#[poise::command(slash_command, guild_only, subcommands("goal_show", "goal_set", "goal_cancel"))]
+async fn goal(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show the progress of the tipping goal
+#[poise::command(slash_command, rename = "show", guild_only)]
+async fn goal_show(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or(DiscordError::NoGuild)?;
+    let embed = match ctx.data().get_goal(&Community::Discord(guild_id.get())).await {
+        Some(goal) => goal.embed(GoalUpdate::Progress),
+        None => goal_error_embed("No goal", "No goal is running in this server")
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed)).await?;
+
+    Ok(())
+}
+
+/// Set the tipping goal of this month, the reward is split between the tippers
+#[poise::command(slash_command, rename = "set", guild_only, required_permissions = "MANAGE_GUILD")]
+async fn goal_set(
+    ctx: Context<'_>,
+    #[description = "XEL to tip in the server before the end of the month"] target: String,
+    #[description = "XEL from your balance split between the tippers once reached"] reward: Option<String>,
+    #[description = "Message announced once the goal is reached"] #[max_length = 200] message: Option<String>
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or(DiscordError::NoGuild)?;
+    let community = Community::Discord(guild_id.get());
+
+    let (target, reward) = match parse_goal_amounts(&target, reward.as_deref()) {
+        Ok(amounts) => amounts,
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(true).embed(goal_error_embed("An error occured while setting the goal", e))).await?;
+            return Ok(());
+        }
+    };
+
+    let service = ctx.data();
+    match service.set_goal(&community, &UserApplication::Discord(ctx.author().id.into()), target, reward, message, ctx.channel_id().get()).await {
+        Ok(goal) => {
+            let reply = ctx.send(CreateReply::default().embed(goal.embed(GoalUpdate::Progress))).await?;
+            let message = reply.message().await?;
+            service.set_goal_message(&community, message.id.get()).await?;
+        },
+        Err(e) => {
+            ctx.send(CreateReply::default().ephemeral(true).embed(goal_error_embed("An error occured while setting the goal", e))).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Cancel the tipping goal, its reward goes back to who set it
+#[poise::command(slash_command, rename = "cancel", guild_only, required_permissions = "MANAGE_GUILD")]
+async fn goal_cancel(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or(DiscordError::NoGuild)?;
+    let embed = match ctx.data().cancel_goal(&Community::Discord(guild_id.get())).await {
+        Ok(goal) => CreateEmbed::default()
+            .title("Tipping goal")
+            .description("The goal was cancelled")
+            .field("Tipped", format!("{} / {} XEL", format_xelis(goal.tipped), format_xelis(goal.target)), true)
+            .field("Refunded", format!("{} XEL", format_xelis(goal.reward)), true)
+            .thumbnail(ICON)
+            .colour(COLOR),
+        Err(e) => goal_error_embed("An error occured while cancelling the goal", e)
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed)).await?;
+
+    Ok(())
+}
+
+// Embed of the practice mode, labeled so it can't be mistaken for a real balance
+fn practice_embed() -> CreateEmbed {
+    CreateEmbed::default()
+        .title("Practice")
+        .footer(CreateEmbedFooter::new("Practice mode: sandbox balance, nothing is sent on chain"))
+        .thumbnail(ICON)
+        .colour(COLOR)
+}
+
+fn practice_error_embed(error: impl ToString) -> CreateEmbed {
+    practice_embed()
+        .field("An error occured", error.to_string(), false)
+        .colour(Colour::RED)
+}
+
+/// Learn the commands with a sandbox balance, no real XEL is moved
+#[poise::command(slash_command, subcommands("practice_balance", "practice_tip", "practice_withdraw", "practice_reset"))]
+async fn practice(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show your sandbox balance
+#[poise::command(slash_command, rename = "balance")]
+async fn practice_balance(ctx: Context<'_>) -> Result<(), Error> {
+    let balance = ctx.data().get_practice_balance(&UserApplication::Discord(ctx.author().id.into())).await;
+    ctx.send(CreateReply::default().ephemeral(true).embed(
+        practice_embed()
+            .field("Your sandbox balance is", format!("{} XEL", format_xelis(balance)), false)
+    )).await?;
+
+    Ok(())
+}
+
+/// Tip a user with your sandbox balance
+#[poise::command(slash_command, rename = "tip")]
+async fn practice_tip(ctx: Context<'_>, #[description = "User to tip"] user: User, #[description = "Amount to tip"] amount: String) -> Result<(), Error> {
+    let service = ctx.data();
+    let res = match parse_amount(&amount) {
+        Ok(amount) => service.practice_tip(&UserApplication::Discord(ctx.author().id.into()), &UserApplication::Discord(user.id.into()), amount.value).await
+            .map(|balance| (amount.value, balance))
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string())
+    };
+
+    let embed = match res {
+        Ok((amount, balance)) => practice_embed()
+            .description(format!("You have tipped {} XEL to {}", format_xelis(amount), user))
+            .field("Your sandbox balance is", format!("{} XEL", format_xelis(balance)), false),
+        Err(e) => practice_error_embed(e)
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed)).await?;
+
+    Ok(())
+}
+
+/// Simulate a withdrawal of your sandbox balance
+#[poise::command(slash_command, rename = "withdraw")]
+async fn practice_withdraw(ctx: Context<'_>, address: String, amount: String) -> Result<(), Error> {
+    let service = ctx.data();
+    // Same checks as a real withdrawal so the errors can be learned too
+    let res = match (Address::from_string(&address), parse_amount(&amount)) {
+        (Err(e), _) => Err(e.to_string()),
+        (Ok(to), _) if to.is_mainnet() != service.network().is_mainnet() => Err("Invalid network".to_string()),
+        (_, Err(e)) => Err(e.to_string()),
+        (Ok(_), Ok(amount)) => service.practice_withdraw(&UserApplication::Discord(ctx.author().id.into()), amount.value).await
+            .map(|balance| (amount.value, balance))
+            .map_err(|e| e.to_string())
+    };
+
+    let embed = match res {
+        Ok((amount, balance)) => practice_embed()
+            .description(format!("You would have withdrawn {} XEL", format_xelis(amount)))
+            .field("Fee", format!("{} XEL", format_xelis(PRACTICE_WITHDRAW_FEE)), true)
+            .field("Your sandbox balance is", format!("{} XEL", format_xelis(balance)), true),
+        Err(e) => practice_error_embed(e)
+    };
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed)).await?;
+
+    Ok(())
+}
+
+/// Start over with a fresh sandbox balance
+#[poise::command(slash_command, rename = "reset")]
+async fn practice_reset(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.data().reset_practice(&UserApplication::Discord(ctx.author().id.into())).await?;
+    ctx.send(CreateReply::default().ephemeral(true).embed(
+        practice_embed()
+            .field("Your sandbox balance is", format!("{} XEL", format_xelis(PRACTICE_BALANCE)), false)
+    )).await?;
+
+    Ok(())
+}
+
+// Handler for discord gateway events
+// Used for buttons that must keep working after the command returned
+async fn discord_event_handler(ctx: &SerenityContext, event: &FullEvent, _: poise::FrameworkContext<'_, WalletService, Error>, service: &WalletService) -> Result<(), Error> {
+    // Buttons and reactions change the wallet state, they are left to the leader
+    if !service.is_leader() {
+        return Ok(());
+    }
+
+    match event {
+        FullEvent::InteractionCreate { interaction: Interaction::Component(component) } => {
+            if let Some(id) = component.data.custom_id.strip_prefix(GIVEAWAY_BUTTON_PREFIX).and_then(|id| id.parse().ok()) {
+                let filter = match component.guild_id {
+                    Some(guild_id) => service.get_recipient_filter(&Community::Discord(guild_id.get())).await,
+                    None => RecipientFilter::default()
+                };
+
+                let content = if !discord_recipient_allowed(&filter, &component.user, component.member.as_deref()) {
+                    recipient_filter_text(&filter)
+                } else {
+                    match service.claim_giveaway(id, &UserApplication::Discord(component.user.id.into())).await {
+                        Ok(giveaway) => format!("You joined the giveaway ({}/{} claims)", giveaway.claims.len(), giveaway.max_claims),
+                        Err(e) => format!("An error occured while claiming: {}", e)
+                    }
+                };
+
+                component.create_response(ctx, CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content(content)
+                        .ephemeral(true)
+                )).await?;
+            } else if let Some(id) = component.data.custom_id.strip_prefix(CHALLENGE_ACCEPT_PREFIX).and_then(|id| id.parse().ok()) {
+                let response = match service.accept_challenge(id, &UserApplication::Discord(component.user.id.into())).await {
+                    // Replace the challenge by its result
+                    Ok(result) => CreateInteractionResponse::UpdateMessage(
+                        CreateInteractionResponseMessage::new()
+                            .embed(challenge_result_embed(&result))
+                            .components(Vec::new())
+                    ),
+                    Err(e) => CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content(format!("An error occured while accepting the challenge: {}", e))
+                            .ephemeral(true)
+                    )
+                };
+
+                component.create_response(ctx, response).await?;
+            } else if let Some(id) = component.data.custom_id.strip_prefix(CHALLENGE_DECLINE_PREFIX).and_then(|id| id.parse().ok()) {
+                let response = match service.cancel_challenge(id, &UserApplication::Discord(component.user.id.into())).await {
+                    Ok(challenge) => CreateInteractionResponse::UpdateMessage(
+                        CreateInteractionResponseMessage::new()
+                            .embed(
+                                challenge_embed(&challenge)
+                                    .description(format!("Challenge cancelled by {}, stake refunded", component.user))
+                                    .field("Seed", format!("`{}`", challenge.seed()), false)
+                            )
+                            .components(Vec::new())
+                    ),
+                    Err(e) => CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content(format!("An error occured while declining the challenge: {}", e))
+                            .ephemeral(true)
+                    )
+                };
+
+                component.create_response(ctx, response).await?;
+            } else if let Some(data) = component.data.custom_id.strip_prefix(TIPME_BUTTON_PREFIX) {
+                // The confirmation waits for the tipper, other events must not be blocked
+                let (ctx, service, component, data) = (ctx.clone(), service.clone(), component.clone(), data.to_string());
+                tokio::spawn(async move {
+                    if let Err(e) = discord_tipme(&ctx, &service, &component, &data).await {
+                        warn!("Couldn't tip from the button of {}: {}", component.user.id, e);
+                    }
+                });
+            }
+        },
+        // Only guilds joined while running are new, not the ones loaded on startup
+        FullEvent::GuildCreate { guild, is_new: Some(true) } => {
+            let (ctx, service, guild) = (ctx.clone(), service.clone(), guild.clone());
+            tokio::spawn(async move {
+                if let Err(e) = discord_setup_wizard(&ctx, &service, &guild).await {
+                    warn!("Couldn't run the setup wizard of guild {}: {}", guild.id, e);
+                }
+            });
+        },
+        FullEvent::Message { new_message } => {
+            // React on the messages of the top tipper of the week
+            if let Some(guild_id) = new_message.guild_id.filter(|_| !new_message.author.bot) {
+                if let Some(emoji) = service.get_top_tipper_emoji(guild_id.get(), new_message.author.id.get()).await {
+                    match ReactionType::try_from(emoji.as_str()) {
+                        Ok(reaction) => {
+                            new_message.react(ctx, reaction).await?;
+                        },
+                        Err(e) => debug!("Invalid top tipper emoji {} in guild {}: {}", emoji, guild_id, e)
+                    }
+                }
+            }
+        },
+        _ => {}
+    }
+
+    Ok(())
+}
+
+// Label of a minimum tip proposed by the setup wizard
+fn min_tip_label(amount: u64) -> String {
+    if amount == 0 {
+        "No minimum".to_string()
+    } else {
+        format!("{} XEL", format_xelis(amount))
+    }
+}
+
+// Names of the features enabled in a community
+fn enabled_features(disabled: u64) -> String {
+    let features = Feature::ALL.iter()
+        .filter(|feature| disabled & feature.bit() == 0)
+        .map(|feature| feature.name())
+        .collect::<Vec<_>>();
+
+    if features.is_empty() {
+        "None".to_string()
+    } else {
+        features.join(", ")
+    }
+}
+
+fn setup_embed(guild: &Guild, step: &str) -> CreateEmbed {
+    CreateEmbed::default()
+        .title(format!("Setup - {}", guild.name))
+        .footer(CreateEmbedFooter::new(step))
+        .thumbnail(ICON)
+        .colour(COLOR)
+}
+
+// Wait for the owner to answer the current step of the wizard
+async fn next_setup_interaction(ctx: &SerenityContext, guild: &Guild, message_id: MessageId) -> Option<ComponentInteraction> {
+    ComponentInteractionCollector::new(ctx)
+        .author_id(guild.owner_id)
+        .message_id(message_id)
+        .timeout(SETUP_WIZARD_TIMEOUT)
+        .await
+}
+
+// Guide the owner of a new guild through its configuration in DM
+// Each step is saved when answered, unanswered steps keep their default value
+async fn discord_setup_wizard(ctx: &SerenityContext, service: &WalletService, guild: &Guild) -> Result<(), Error> {
+    let community = Community::Discord(guild.id.get());
+    let dm = guild.owner_id.create_dm_channel(ctx).await?;
+
+    // Select menus are limited to 25 options
+    let mut channels = guild.channels.values()
+        .filter(|channel| channel.kind == ChannelType::Text)
+        .collect::<Vec<_>>();
+    channels.sort_by_key(|channel| channel.position);
+
+    let mut options = vec![CreateSelectMenuOption::new("No announcements", "none")];
+    options.extend(channels.iter().take(24).map(|channel| CreateSelectMenuOption::new(format!("#{}", channel.name), channel.id.to_string())));
+
+    let message = dm.send_message(ctx, CreateMessage::default()
+        .embed(
+            setup_embed(guild, "Step 1/3")
+                .description("Thanks for adding the XELIS Tip Bot! Let's configure it for your server.")
+                .field("Announcements", "Choose a channel where the tips and rains of the server are also announced", false)
+        )
+        .components(vec![CreateActionRow::SelectMenu(
+            CreateSelectMenu::new("setup-channel", CreateSelectMenuKind::String { options })
+                .placeholder("Announcement channel")
+        )])
+    ).await?;
+
+    let Some(interaction) = next_setup_interaction(ctx, guild, message.id).await else {
+        return expire_setup_wizard(ctx, guild, dm.id, message.id).await;
+    };
+
+    let channel = match &interaction.data.kind {
+        ComponentInteractionDataKind::StringSelect { values } => values.first().and_then(|value| value.parse::<u64>().ok()),
+        _ => None
+    };
+    service.set_setting(&community, Setting::AnnouncementChannel, channel.map(DataValue::U64)).await?;
+
+    let buttons = SETUP_MIN_TIPS.iter()
+        .map(|amount| CreateButton::new(format!("setup-min-{}", amount)).label(min_tip_label(*amount)).style(ButtonStyle::Secondary))
+        .collect();
+
+    interaction.create_response(ctx, CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new()
+            .embed(
+                setup_embed(guild, "Step 2/3")
+                    .field("Minimum tip", "Tips and rain shares below this amount will be refused in the server", false)
+            )
+            .components(vec![CreateActionRow::Buttons(buttons)])
+    )).await?;
+
+    let Some(interaction) = next_setup_interaction(ctx, guild, message.id).await else {
+        return expire_setup_wizard(ctx, guild, dm.id, message.id).await;
+    };
+
+    let min_tip = interaction.data.custom_id.strip_prefix("setup-min-")
+        .and_then(|amount| amount.parse::<u64>().ok())
+        .unwrap_or(0);
+    service.set_setting(&community, Setting::MinTip, Some(DataValue::U64(min_tip)).filter(|_| min_tip > 0)).await?;
+
+    let mut disabled = service.get_disabled_features(&community).await;
+    let options = Feature::ALL.iter()
+        .map(|feature| CreateSelectMenuOption::new(feature.name(), feature.name()).default_selection(disabled & feature.bit() == 0))
+        .collect();
+
+    interaction.create_response(ctx, CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new()
+            .embed(
+                setup_embed(guild, "Step 3/3")
+                    .field("Features", "Select the features available in the server, then save", false)
+            )
+            .components(vec![
+                CreateActionRow::SelectMenu(
+                    CreateSelectMenu::new("setup-features", CreateSelectMenuKind::String { options })
+                        .min_values(0)
+                        .max_values(Feature::ALL.len() as u8)
+                        .placeholder("Enabled features")
+                ),
+                CreateActionRow::Buttons(vec![
+                    CreateButton::new("setup-save").label("Save").style(ButtonStyle::Success)
+                ])
+            ])
+    )).await?;
+
+    // The selection is only saved once the owner clicks on the save button
+    loop {
+        let Some(interaction) = next_setup_interaction(ctx, guild, message.id).await else {
+            return expire_setup_wizard(ctx, guild, dm.id, message.id).await;
+        };
+
+        if let ComponentInteractionDataKind::StringSelect { values } = &interaction.data.kind {
+            disabled = Feature::ALL.iter()
+                .filter(|feature| !values.iter().any(|value| value == feature.name()))
+                .fold(0, |disabled, feature| disabled | feature.bit());
+
+            interaction.create_response(ctx, CreateInteractionResponse::Acknowledge).await?;
+            continue;
+        }
+
+        service.set_setting(&community, Setting::DisabledFeatures, Some(DataValue::U64(disabled)).filter(|_| disabled != 0)).await?;
+
+        interaction.create_response(ctx, CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .embed(
+                    setup_embed(guild, "Done")
+                        .description("The bot is ready to be used in your server")
+                        .field("Announcements", channel.map(|id| format!("<#{}>", id)).unwrap_or_else(|| "Disabled".to_string()), true)
+                        .field("Minimum tip", min_tip_label(min_tip), true)
+                        .field("Features", enabled_features(disabled), false)
+                )
+                .components(Vec::new())
+        )).await?;
+
+        return Ok(());
+    }
+}
+
+// The owner didn't answer in time, remove the components of the wizard
+async fn expire_setup_wizard(ctx: &SerenityContext, guild: &Guild, channel_id: ChannelId, message_id: MessageId) -> Result<(), Error> {
+    channel_id.edit_message(ctx, message_id, EditMessage::new()
+        .embed(
+            setup_embed(guild, "Expired")
+                .description("The setup has expired, the remaining settings keep their default value")
+        )
+        .components(Vec::new())
+    ).await?;
+
+    Ok(())
+}
+
+// Mirror an announcement of a guild in its announcement channel, if configured
+// Failures are only logged as the announcement was already made in the channel
+// Nothing is mirrored while the Discord API errors spike
+async fn mirror_discord_announcement(ctx: Context<'_>, embed: CreateEmbed) {
+    let Some(guild_id) = ctx.guild_id() else {
+        return;
+    };
+
+    mirror_discord_announcement_in(ctx.serenity_context(), ctx.data(), guild_id.get(), ctx.channel_id(), embed).await;
+}
+
+// Same as `mirror_discord_announcement` outside of a command
+async fn mirror_discord_announcement_in(ctx: &SerenityContext, service: &WalletService, guild_id: u64, channel_id: ChannelId, embed: CreateEmbed) {
+    let Some(DataValue::U64(channel)) = service.get_setting(&Community::Discord(guild_id), Setting::AnnouncementChannel).await else {
+        return;
+    };
+
+    if channel == channel_id.get() {
+        return;
+    }
+
+    if service.api_errors().is_backing_off(Platform::Discord) {
+        debug!("Not mirroring announcement of guild {}, Discord API errors are spiking", guild_id);
+        return;
+    }
+
+    let channel = match discord_id::<ChannelId>(channel) {
+        Ok(channel) => channel,
+        Err(e) => {
+            warn!("Invalid announcement channel of guild {}: {}", guild_id, e);
+            return;
+        }
+    };
+
+    if let Err(e) = channel.send_message(ctx, CreateMessage::default().embed(embed)).await {
+        service.api_errors().record(Platform::Discord, ApiErrorKind::from_discord(&e));
+        warn!("Couldn't mirror announcement of guild {}: {}", guild_id, e);
+    }
+}
+
+/// Reward the top tipper of each week with a role and/or an emoji reaction
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", default_member_permissions = "MANAGE_GUILD")]
+async fn top_tipper_reward(
+    ctx: Context<'_>,
+    #[description = "Role given to the top tipper, leave empty to disable"] role: Option<Role>,
+    #[description = "Emoji added on the messages of the top tipper, leave empty to disable"] emoji: Option<String>
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or(DiscordError::NoGuild)?;
+    let community = Community::Discord(guild_id.get());
+
+    if let Some(emoji) = emoji.as_deref() {
+        if let Err(e) = ReactionType::try_from(emoji) {
+            ctx.send(CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title("Top Tipper Reward")
+                    .field("Invalid emoji", e.to_string(), false)
+                    .thumbnail(ICON)
+                    .colour(Colour::RED)
+                )
+            ).await?;
+            return Ok(());
+        }
+    }
+
+    let service = ctx.data();
+    service.set_setting(&community, Setting::TopTipperRole, role.as_ref().map(|role| DataValue::U64(role.id.get()))).await?;
+    service.set_setting(&community, Setting::TopTipperEmoji, emoji.clone().map(DataValue::String)).await?;
+
+    ctx.send(CreateReply::default().ephemeral(true).embed(
+        CreateEmbed::default()
+            .title("Top Tipper Reward")
+            .description("The top tipper of the previous week is updated every hour")
+            .field("Role", role.map(|role| role.to_string()).unwrap_or_else(|| "Disabled".to_string()), true)
+            .field("Emoji", emoji.unwrap_or_else(|| "Disabled".to_string()), true)
+            .thumbnail(ICON)
+            .colour(COLOR)
+        )
+    ).await?;
+
+    Ok(())
+}
+
+// Check a Discord user against the recipient filter of a server
+// Without its member, a user only passes a filter that doesn't need it
+fn discord_recipient_allowed(filter: &RecipientFilter, user: &User, member: Option<&Member>) -> bool {
+    let roles: Vec<u64> = member.map(|member| member.roles.iter().map(|role| role.get()).collect()).unwrap_or_default();
+    let created_at = user.id.created_at().unix_timestamp().max(0) as u64;
+    let joined_at = member.and_then(|member| member.joined_at).map(|joined_at| joined_at.unix_timestamp().max(0) as u64);
+
+    filter.accepts(&roles, created_at, joined_at)
+}
+
+// Requirements of a recipient filter, as told to a user that doesn't meet them
+fn recipient_filter_text(filter: &RecipientFilter) -> String {
+    let mut requirements = Vec::new();
+    if let Some(role) = filter.role {
+        requirements.push(format!("have the <@&{}> role", role));
+    }
+
+    if filter.min_account_age > 0 {
+        requirements.push(format!("have a Discord account older than {} days", filter.min_account_age));
+    }
+
+    if filter.min_member_age > 0 {
+        requirements.push(format!("be a member of this server for {} days", filter.min_member_age));
+    }
+
+    format!("To receive rains and claim giveaways in this server, you must {}", requirements.join(" and "))
+}
+
+/// Only let users with a role or old enough accounts receive rains and claim giveaways
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", default_member_permissions = "MANAGE_GUILD")]
+async fn recipient_filters(
+    ctx: Context<'_>,
+    #[description = "Role required, leave empty to disable"] role: Option<Role>,
+    #[description = "Minimum age of the Discord account in days, leave empty to disable"] #[min = 1] #[max = 3650] account_age: Option<u32>,
+    #[description = "Minimum days since joining the server, leave empty to disable"] #[min = 1] #[max = 3650] member_age: Option<u32>
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or(DiscordError::NoGuild)?;
+    let filter = RecipientFilter {
+        role: role.as_ref().map(|role| role.id.get()),
+        min_account_age: account_age.unwrap_or(0) as u64,
+        min_member_age: member_age.unwrap_or(0) as u64
+    };
+    ctx.data().set_recipient_filter(&Community::Discord(guild_id.get()), filter).await?;
+
+    let days = |days: Option<u32>| days.map(|days| format!("{} days", days)).unwrap_or_else(|| "Disabled".to_string());
+    ctx.send(CreateReply::default().ephemeral(true).embed(
+        CreateEmbed::default()
+            .title("Recipient Filters")
+            .description("Rains skip the users that don't pass these filters, and they can't claim giveaways")
+            .field("Role", role.map(|role| role.to_string()).unwrap_or_else(|| "Disabled".to_string()), true)
+            .field("Account Age", days(account_age), true)
+            .field("Member Since", days(member_age), true)
+            .thumbnail(ICON)
+            .colour(COLOR)
+        )
+    ).await?;
+
+    Ok(())
+}
+
+#[derive(poise::ChoiceParameter, Clone, Copy)]
+enum AdminPlatform {
+    Discord,
+    Telegram,
+    Matrix,
+    Slack,
+    Twitch
+}
+
+// User targeted by an operator, IDs are passed as text as they don't fit in a Discord integer
+// Matrix and Slack users can also be given by their user ID on the platform
+fn parse_admin_user(platform: AdminPlatform, id: &str) -> Result<UserApplication, String> {
+    let invalid = || format!("Invalid user ID '{}'", id);
+    match platform {
+        AdminPlatform::Discord => Ok(UserApplication::Discord(id.trim().parse().map_err(|_| invalid())?)),
+        AdminPlatform::Telegram => Ok(UserApplication::Telegram(id.trim().parse().map_err(|_| invalid())?)),
+        AdminPlatform::Matrix => parse_matrix_user(id).ok_or_else(invalid),
+        AdminPlatform::Slack => parse_slack_user(id).ok_or_else(invalid),
+        AdminPlatform::Twitch => Ok(UserApplication::Twitch(id.trim().parse().map_err(|_| invalid())?))
+    }
+}
+
+// Answer an operator privately
+async fn send_admin_reply(ctx: Context<'_>, embed: CreateEmbed) -> Result<(), Error> {
+    ctx.send(CreateReply::default().ephemeral(true).embed(embed.thumbnail(ICON))).await?;
+    Ok(())
+}
+
+/// Manage the bot, only for its operators
+#[poise::command(slash_command, owners_only, hide_in_help, subcommands("admin_status", "admin_balance", "admin_add_balance", "admin_remove_balance", "admin_withdrawals", "admin_freeze", "admin_unfreeze"))]
+async fn admin(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show the status of the wallet and of the processing queues
+#[poise::command(slash_command, rename = "status", owners_only)]
+async fn admin_status(ctx: Context<'_>) -> Result<(), Error> {
+    let service = ctx.data();
+    let solvency = service.get_solvency().await?;
+    let topoheight = service.get_wallet_topoheight().await?;
+    let online = service.is_wallet_online().await;
+    let queues = service.get_queue_status();
+
+    send_admin_reply(ctx, CreateEmbed::default()
+        .title("Admin")
+        .field("Wallet Balance", format_xelis(solvency.assets), true)
+        .field("Total Users Balance", format_xelis(solvency.liabilities), true)
+        .field("Solvency Ratio", solvency.to_string(), true)
+        .field("Users", service.get_users_count().await?.to_string(), true)
+        .field("Synced TopoHeight", topoheight.to_string(), true)
+        .field("Network", service.network().to_string(), true)
+        .field("Is Online", online.to_string(), true)
+        .field("Withdrawals", if service.is_withdraw_locked() { "Locked" } else { "Open" }, true)
+        .field("Unconfirmed TXs", queues.unconfirmed.to_string(), true)
+        .field("Pending Notifications", queues.notifications.to_string(), true)
+        .field("Stuck Withdrawals", queues.stuck_withdrawals.to_string(), true)
+        .field("Last Event", format_last_event(&queues), true)
+        .field("Discord API Errors", queues.discord_errors.to_string(), false)
+        .field("Telegram API Errors", queues.telegram_errors.to_string(), false)
+        .field("Matrix API Errors", queues.matrix_errors.to_string(), false)
+        .field("Slack API Errors", queues.slack_errors.to_string(), false)
+        .field("Twitch API Errors", queues.twitch_errors.to_string(), false)
+        .colour(COLOR)
+    ).await
+}
+
+/// Show the balance of a user
+#[poise::command(slash_command, rename = "balance", owners_only)]
+async fn admin_balance(
+    ctx: Context<'_>,
+    #[description = "Platform of the user"] platform: AdminPlatform,
+    #[description = "ID of the user on its platform"] id: String
+) -> Result<(), Error> {
+    let user = match parse_admin_user(platform, &id) {
+        Ok(user) => user,
+        Err(e) => return send_admin_reply(ctx, CreateEmbed::default().title("Admin").description(e).colour(Colour::RED)).await
+    };
+
+    let balances = ctx.data().get_balances_for_user(&user).await?;
+    let balances = balances.iter()
+        .map(|balance| balance.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    send_admin_reply(ctx, CreateEmbed::default()
+        .title("Admin")
+        .field(format!("Balance of {} user {}", platform.name(), id.trim()), balances, false)
+        .colour(COLOR)
+    ).await
+}
+
+/// Credit XEL to a user, recorded as an admin movement in its statement
+#[poise::command(slash_command, rename = "add_balance", owners_only)]
+async fn admin_add_balance(
+    ctx: Context<'_>,
+    #[description = "Platform of the user"] platform: AdminPlatform,
+    #[description = "ID of the user on its platform"] id: String,
+    #[description = "Amount of XEL to credit"] amount: String,
+    #[description = "Why the balance is changed, kept in the journal"] #[max_length = 200] reason: String
+) -> Result<(), Error> {
+    admin_adjust_balance(ctx, platform, &id, &amount, &reason, true).await
+}
+
+/// Debit XEL from a user, recorded as an admin movement in its statement
+#[poise::command(slash_command, rename = "remove_balance", owners_only)]
+async fn admin_remove_balance(
+    ctx: Context<'_>,
+    #[description = "Platform of the user"] platform: AdminPlatform,
+    #[description = "ID of the user on its platform"] id: String,
+    #[description = "Amount of XEL to debit"] amount: String,
+    #[description = "Why the balance is changed, kept in the journal"] #[max_length = 200] reason: String
+) -> Result<(), Error> {
+    admin_adjust_balance(ctx, platform, &id, &amount, &reason, false).await
+}
+
+async fn admin_adjust_balance(ctx: Context<'_>, platform: AdminPlatform, id: &str, amount: &str, reason: &str, credit: bool) -> Result<(), Error> {
+    let service = ctx.data();
+    let result = match (parse_admin_user(platform, id), parse_amount(amount).and_then(Amount::exact)) {
+        (Err(e), _) => Err(e),
+        (_, Err(e)) => Err(e.to_string()),
+        (Ok(user), Ok(amount)) => {
+            info!("Operator {} {} {} XEL of {:?}", ctx.author().id, if credit { "credits" } else { "debits" }, format_xelis(amount), user);
+            let result = if credit {
+                service.add_balance(&user, amount, reason).await
+            } else {
+                service.remove_balance(&user, amount, reason).await
+            };
+
+            result.map(|_| (user, amount)).map_err(|e| e.to_string())
+        }
+    };
+
+    let embed = match result {
+        Ok((user, amount)) => {
+            let balances = service.get_balances_for_user(&user).await?;
+            let balances = balances.iter()
+                .map(|balance| balance.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            CreateEmbed::default()
+                .title("Admin")
+                .field(if credit { "Credited" } else { "Debited" }, format!("{} XEL", format_xelis(amount)), false)
+                .field("Reason", reason.trim(), false)
+                .field("New balance", balances, false)
+                .colour(COLOR)
+        },
+        Err(e) => CreateEmbed::default()
+            .title("Admin")
+            .field("An error occured while updating the balance", e, false)
+            .colour(Colour::RED)
+    };
+
+    send_admin_reply(ctx, embed).await
+}
+
+/// Lock or unlock the withdrawals of all users
+#[poise::command(slash_command, rename = "withdrawals", owners_only)]
+async fn admin_withdrawals(
+    ctx: Context<'_>,
+    #[description = "True to lock the withdrawals, false to allow them again"] locked: bool
+) -> Result<(), Error> {
+    info!("Operator {} {} the withdrawals", ctx.author().id, if locked { "locks" } else { "unlocks" });
+    ctx.data().set_withdraw_locked(locked);
+
+    send_admin_reply(ctx, CreateEmbed::default()
+        .title("Admin")
+        .field("Withdrawals", if locked { "Locked" } else { "Open" }, false)
+        .colour(if locked { Colour::ORANGE } else { COLOR })
+    ).await
+}
+
+/// Block the transfers and withdrawals of a user
+#[poise::command(slash_command, rename = "freeze", owners_only)]
+async fn admin_freeze(
+    ctx: Context<'_>,
+    #[description = "Platform of the user"] platform: AdminPlatform,
+    #[description = "ID of the user on its platform"] id: String
+) -> Result<(), Error> {
+    admin_set_frozen(ctx, platform, &id, true).await
+}
+
+/// Allow a frozen user to transfer and withdraw again
+#[poise::command(slash_command, rename = "unfreeze", owners_only)]
+async fn admin_unfreeze(
+    ctx: Context<'_>,
+    #[description = "Platform of the user"] platform: AdminPlatform,
+    #[description = "ID of the user on its platform"] id: String
+) -> Result<(), Error> {
+    admin_set_frozen(ctx, platform, &id, false).await
+}
+
+async fn admin_set_frozen(ctx: Context<'_>, platform: AdminPlatform, id: &str, frozen: bool) -> Result<(), Error> {
+    let user = match parse_admin_user(platform, id) {
+        Ok(user) => user,
+        Err(e) => return send_admin_reply(ctx, CreateEmbed::default().title("Admin").description(e).colour(Colour::RED)).await
+    };
+
+    info!("Operator {} {} {:?}", ctx.author().id, if frozen { "freezes" } else { "unfreezes" }, user);
+    let changed = ctx.data().set_frozen(&user, frozen).await?;
+    let status = match (frozen, changed) {
+        (true, true) => "Frozen, transfers and withdrawals are blocked",
+        (true, false) => "Already frozen",
+        (false, true) => "Unfrozen, transfers and withdrawals are allowed again",
+        (false, false) => "Not frozen"
+    };
+
+    send_admin_reply(ctx, CreateEmbed::default()
+        .title("Admin")
+        .field(format!("{} user {}", platform.name(), id.trim()), status, false)
+        .colour(if frozen { Colour::ORANGE } else { COLOR })
+    ).await
+}
+
+// Handler for telegram bot
+async fn telegram_handler(bot: Bot, msg: Message, cmd: TelegramCommand, state: WalletService, confirmations: TelegramConfirmations, pins: TelegramPinRequests, rains: TelegramRainPreviews, activity: TelegramActivity, notifier: TelegramNotifications) -> Result<(), Error> {
+    if !cmd.allow_public() && !msg.chat.is_private() {
+        let from = msg.from.ok_or(TelegramError::NoUser)?;
+        bot.send_message(from.id, "You can only use this command in private").await?;
+        return Ok(());
+    }
+
+    // A group that disabled the bot can only enable it again
+    if let Some(community) = telegram_community(&msg) {
+        if !matches!(cmd, TelegramCommand::Settings { args: _ }) && state.is_setting_enabled(&community, Setting::BotDisabled).await {
+            return Ok(());
+        }
+    }
+
+    if let Some(from) = msg.from.as_ref() {
+        let user = UserApplication::Telegram(from.id.0);
+        // Commands are limited by name, as written by the user
+        let command = msg.text()
+            .and_then(|text| text.split_whitespace().next())
+            .and_then(|command| command.split('@').next())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        if let Some(limited) = state.check_rate_limit(&user, &[], &command).await {
+            // Answer a flood only once, in private
+            if !limited.notified {
+                bot.send_message(from.id, limited.to_string()).await?;
+            }
+            return Ok(());
+        }
+
+        if let Err(e) = state.record_user_activity(&user).await {
+            warn!("Couldn't record activity of {}: {}", from.id, e);
+        }
+    }
+
+    let thread_id = msg.thread_id.filter(|_| msg.is_topic_message);
+    if let Some(feature) = cmd.feature() {
+        if let Err(e) = state.check_feature(telegram_community(&msg).as_ref(), feature).await {
+            TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                .title(feature.name())
+                .line(e.to_string())
+                .send().await?;
+            return Ok(());
+        }
+    }
+
+    match cmd {
+        TelegramCommand::Start => {
+            // The private chat of the user was registered with its first message
+            TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                .title("Welcome")
+                .field("Welcome to the XELIS Tip Bot!", "You can use /help to see the available commands", false)
+                .send().await?;
+        }
+        TelegramCommand::Help => {
+            bot.send_message(msg.chat.id, TelegramCommand::descriptions().to_string()).await?;
+        },
+        TelegramCommand::Status => {
+            let solvency = state.get_solvency().await?;
+            let topoheight = state.get_wallet_topoheight().await?;
+            let network = state.network();
+            let online = state.is_wallet_online().await;
+
+            TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                .title("Status")
+                .field("Wallet Balance", format_xelis(solvency.assets), false)
+                .field("Total Users Balance", format_xelis(solvency.liabilities), false)
+                .field("Solvency Ratio", solvency.to_string(), false)
+                .field("Synced TopoHeight", topoheight.to_string(), false)
+                .field("Network", network.to_string(), false)
+                .field("Is Online", online.to_string(), false)
+                .send().await?;
+        },
+        TelegramCommand::Balance => {
+            platform::balance(&state, &TelegramPlatform::new(&bot, &state, &msg, thread_id)?).await?;
+        },
+        TelegramCommand::Reminders { args } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let user = UserApplication::Telegram(from.id.0);
+            match args.trim().to_lowercase().as_str() {
+                "on" => state.set_reminders_enabled(&user, true).await?,
+                "off" => state.set_reminders_enabled(&user, false).await?,
+                "" => {},
+                _ => {
+                    bot.send_message(msg.chat.id, "Usage: /reminders [on|off]").await?;
+                    return Ok(());
+                }
+            }
+
+            let line = if state.are_reminders_enabled(&user).await {
+                "You will be reminded when your balance is left unused for a long time"
+            } else {
+                "You won't be reminded of your unused balance"
+            };
+
+            TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                .title("Reminders")
+                .line(line)
+                .send().await?;
+        },
+        TelegramCommand::Remind { args } => {
+            telegram_remind(&bot, &msg, &state, &args).await?;
+        },
+        TelegramCommand::Settings { args } => {
+            if !msg.chat.is_private() {
+                return telegram_group_settings(&bot, &msg, &state, thread_id, &args).await;
+            }
+
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let user = UserApplication::Telegram(from.id.0);
+            let mut args = args.split_whitespace();
+            match args.next() {
+                Some("budget") => {},
+                Some("whitelist") => {
+                    let status = match args.next() {
+                        Some("on") => state.set_whitelist_enabled(&user, true).await?,
+                        Some("off") => state.set_whitelist_enabled(&user, false).await?,
+                        None => state.get_whitelist_status(&user).await,
+                        Some(_) => {
+                            bot.send_message(msg.chat.id, "Usage: /settings whitelist [on|off]").await?;
+                            return Ok(());
+                        }
+                    };
+
+                    TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                        .title("Whitelist")
+                        .line(whitelist_status_text(status))
+                        .field("Delay", whitelist_delay_text(&state), false)
+                        .send().await?;
+                    return Ok(());
+                },
+                Some("pin") => {
+                    let (Some(pin), current) = (args.next(), args.next()) else {
+                        bot.send_message(msg.chat.id, "Usage: /settings pin <new pin|off> [current pin]").await?;
+                        return Ok(());
+                    };
+
+                    // The PINs shouldn't stay in the chat history
+                    if let Err(e) = bot.delete_message(msg.chat.id, msg.id).await {
+                        debug!("Couldn't delete the PIN message of {}: {}", from.id, e);
+                    }
+
+                    let new_pin = Some(pin).filter(|pin| !pin.eq_ignore_ascii_case("off"));
+                    match state.set_withdraw_pin(&user, current, new_pin).await {
+                        Ok(()) => {
+                            TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                                .title("PIN")
+                                .line(if new_pin.is_some() { "Your withdrawals now require your PIN" } else { "Your withdrawals don't require a PIN anymore" })
+                                .send().await?;
+                        },
+                        Err(e) => {
+                            bot.send_message(msg.chat.id, format!("An error occured while setting your PIN: {}", e)).await?;
+                        }
+                    };
+                    return Ok(());
+                },
+                _ => {
+                    bot.send_message(msg.chat.id, "Usage: /settings budget [daily|weekly <amount|off>], /settings whitelist [on|off] or /settings pin <new pin|off> [current pin]").await?;
+                    return Ok(());
+                }
+            }
+
+            let mut budget = state.get_tip_budget(&user).await;
+            match (args.next(), args.next()) {
+                (None, _) => {},
+                (Some(period @ ("daily" | "weekly")), Some(limit)) => {
+                    let limit = match parse_budget_limit(limit) {
+                        Ok(limit) => limit,
+                        Err(e) => {
+                            bot.send_message(msg.chat.id, format!("An error occured while setting your budget: {}", e)).await?;
+                            return Ok(());
+                        }
+                    };
+
+                    if period == "daily" {
+                        budget.daily = limit;
+                    } else {
+                        budget.weekly = limit;
+                    }
+                    state.set_tip_budget(&user, budget).await?;
+                },
+                _ => {
+                    bot.send_message(msg.chat.id, "Usage: /settings budget [daily|weekly <amount|off>]").await?;
+                    return Ok(());
+                }
+            };
+
+            let (daily, weekly) = state.get_tip_budget_spending(&user).await;
+            TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                .title("Budget")
+                .line("Tips and rains going over your budget must be confirmed")
+                .field("Today", budget_limit_text(budget.daily, daily), true)
+                .field("This week", budget_limit_text(budget.weekly, weekly), true)
+                .send().await?;
+        },
+        TelegramCommand::Statement { args } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let mut args = args.split_whitespace();
+            let (Some(start), Some(end)) = (args.next(), args.next()) else {
+                bot.send_message(msg.chat.id, "Usage: /statement <from> <to> [csv], dates as YYYY-MM-DD").await?;
+                return Ok(());
+            };
+            let csv = args.next() == Some("csv");
+
+            let (start, end) = match parse_statement_range(start, end) {
+                Ok(range) => range,
+                Err(e) => {
+                    bot.send_message(msg.chat.id, format!("An error occured while building the statement: {}", e)).await?;
+                    return Ok(());
+                }
+            };
+
+            let statement = state.get_statement(&UserApplication::Telegram(from.id.0), start, end).await?;
+            let mut message = TelegramMessage::new(&bot, msg.chat.id, thread_id);
+            message.title("Statement")
+                .field("From", format_timestamp(start), true)
+                .field("To", format_timestamp(end), true)
+                .field("Opening balance", format!("{} XEL", format_xelis(statement.opening)), true)
+                .field("Credits", format!("+{} XEL", format_xelis(statement.total_credits())), true)
+                .field("Debits", format!("-{} XEL", format_xelis(statement.total_debits())), true)
+                .field("Closing balance", format!("{} XEL", format_xelis(statement.closing)), true)
+                .field("Journal", if statement.verified { "Verified" } else { "Mismatch, please contact the team" }, true);
+
+            // Telegram messages are limited to 4096 characters
+            if !csv && !statement.movements.is_empty() {
+                let mut lines = String::new();
+                for (i, movement) in statement.movements.iter().enumerate() {
+                    let line = format_movement(movement);
+                    if lines.len() + line.len() + 1 > 3000 {
+                        lines.push_str(&format!("... and {} more, add csv to the command to see all of them", statement.movements.len() - i));
+                        break;
+                    }
+                    lines.push_str(&line);
+                    lines.push('\n');
+                }
+                message.field("Details", lines, false);
+            }
+
+            message.send().await?;
+
+            if csv {
+                bot.send_document(msg.chat.id, InputFile::memory(statement_csv(&statement).into_bytes()).file_name("statement.csv")).await?;
+            }
+        },
+        TelegramCommand::Reserves => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let mut message = TelegramMessage::new(&bot, msg.chat.id, thread_id);
+            message.title("Proof of Reserves");
+            match state.get_reserve_leaf(&UserApplication::Telegram(from.id.0)).await? {
+                Some(leaf) => {
+                    message.line(format!("Your XEL balance is leaf #{} of the proof of reserves {}, taken at {}. Keep your salt private, it's what links the leaf to you", leaf.index, leaf.proof, format_timestamp(leaf.timestamp)))
+                        .field("Balance", format_xelis(leaf.balance), false)
+                        .field("Salt", InlineCode::new(&leaf.salt.to_string()), false)
+                        .field("Leaf", InlineCode::new(&leaf.leaf.to_string()), false)
+                        .field("Merkle Root", InlineCode::new(&leaf.root.to_string()), false)
+                        .line("Leaf is blake3(salt || balance), the balance in atomic units as 8 big endian bytes");
+                },
+                None => {
+                    message.line("You held no XEL at the time of the latest proof of reserves, or none was published yet");
+                }
+            }
+
+            message.send().await?;
+        },
+        TelegramCommand::Deposit => {
+            let from = msg.from.ok_or(TelegramError::NoUser)?;
+            let address = state.get_address_for_user(&UserApplication::Telegram(from.id.0));
+
+            let mut message = TelegramMessage::new(&bot, msg.chat.id, thread_id);
+            message.title("Deposit")
+                .field("Your deposit address is", InlineCode::new(&address.to_string()), false)
+                .field("Please do not send any other coins than XELIS to this address", "", false);
+
+            if state.are_deposits_paused().await {
+                message.field(DEPOSITS_PAUSED_TITLE, DEPOSITS_PAUSED_MESSAGE, false);
+            }
+
+            message.send().await?;
+        },
+        TelegramCommand::Withdraw { args } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let mut args = args.split_whitespace();
+            let (Some(address), Some(amount)) = (args.next(), args.next()) else {
+                bot.send_message(msg.chat.id, "Usage: /withdraw <address|label> <amount> [asset]").await?;
+                return Ok(());
+            };
+            let asset = args.next();
+
+            let user = UserApplication::Telegram(from.id.0);
+            let to = match state.resolve_address(&user, address).await {
+                Ok(address) => address,
+                Err(e) => {
+                    bot.send_message(msg.chat.id, format!("An error occured while withdrawing: {}", e)).await?;
+                    return Ok(());
+                }
+            };
+
+            let amount = match parse_withdraw_amount(&state, &user, &to, asset, amount).await {
+                Ok(amount) => {
+                    // Let the user confirm the amount if it was rounded or converted
+                    if amount.rounded || amount.conversion.is_some() {
+                        let cmd = TelegramCommand::Withdraw { args: format!("{} {}", address, command_amount_args(&amount.amount)) };
+                        confirm_telegram_command_amount(&bot, &msg, &confirmations, "Withdraw", &amount, cmd).await?;
+                        return Ok(());
+                    }
+
+                    amount.amount
+                },
+                Err(e) => {
+                    bot.send_message(msg.chat.id, format!("An error occured while withdrawing: {}", e)).await?;
+                    return Ok(());
+                }
+            };
+
+            // The PIN is asked in the next message, so it's not part of the command
+            if state.has_withdraw_pin(&user).await {
+                pins.insert_with_id(from.id.0, TelegramPendingWithdraw { to, amount });
+                bot.send_message(msg.chat.id, format!("Send your withdrawal PIN in the next {} seconds to confirm", CONFIRMATION_TIMEOUT.as_secs())).await?;
+                return Ok(());
+            }
+
+            telegram_send_withdraw(&bot, &msg, &state, to, &amount, None).await?;
+        },
+        TelegramCommand::Forgetme { args } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let user = UserApplication::Telegram(from.id.0);
+            let mut parts = args.split_whitespace();
+            let Some(destination) = parts.next() else {
+                bot.send_message(msg.chat.id, "Usage: /forgetme <address|label|donate> [pin]").await?;
+                return Ok(());
+            };
+
+            // The PIN shouldn't stay in the chat history
+            if parts.next().is_some() {
+                if let Err(e) = bot.delete_message(msg.chat.id, msg.id).await {
+                    debug!("Couldn't delete the PIN message of {}: {}", from.id, e);
+                }
+            }
+
+            let residual = match parse_residual(&state, &user, destination).await {
+                Ok(residual) => residual,
+                Err(e) => {
+                    bot.send_message(msg.chat.id, format!("An error occured while deleting your data: {}", e)).await?;
+                    return Ok(());
+                }
+            };
+
+            let balances = forgotten_balances_text(&state, &user).await?;
+            let id = confirmations.insert((msg.clone(), TelegramCommand::Forgetme { args: args.clone() }));
+            TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                .title("Forget Me")
+                .line("Your balances, deposit history, statements, stats, address book and settings will be deleted, this can't be undone")
+                .field("Balances", escape_html(&balances), false)
+                .field("What happens to them", residual_text(&residual), false)
+                .buttons(InlineKeyboardMarkup::new(vec![vec![
+                    InlineKeyboardButton::callback("Delete my data", format!("forget:{}", id)),
+                    InlineKeyboardButton::callback("Cancel", format!("cancel:{}", id))
+                ]]))
+                .send().await?;
+        },
+        TelegramCommand::Address { args } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let user = UserApplication::Telegram(from.id.0);
+            let mut args = args.split_whitespace();
+            match (args.next(), args.next(), args.next()) {
+                (Some("add"), Some(label), Some(address)) => match state.add_address(&user, label, address).await {
+                    Ok(label) => {
+                        TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                            .title("Address Book")
+                            .field(&format!("Saved as {}", label), InlineCode::new(address), false)
+                            .send().await?;
+                    },
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, format!("An error occured while saving the address: {}", e)).await?;
+                    }
+                },
+                (Some("remove"), Some(label), None) => {
+                    let line = if state.remove_address(&user, label).await? {
+                        format!("{} has been removed", label)
+                    } else {
+                        format!("No address is saved as {}", label)
+                    };
+
+                    TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                        .title("Address Book")
+                        .line(escape_html(&line))
+                        .send().await?;
+                },
+                (None | Some("list"), None, None) => {
+                    let addresses = state.get_addresses(&user).await?;
+                    let status = state.get_whitelist_status(&user).await;
+                    let mut message = TelegramMessage::new(&bot, msg.chat.id, thread_id);
+                    message.title("Address Book");
+                    if addresses.is_empty() {
+                        message.line("No address saved yet, add one with /address add");
+                    }
+
+                    for saved in addresses.iter() {
+                        let address = InlineCode::new(&saved.address.to_string()).to_string();
+                        let value = match saved_address_pending(&state, saved, status) {
+                            Some(pending) => format!("{}\n{}", address, pending),
+                            None => address
+                        };
+                        message.field(&saved.label, value, false);
+                    }
+
+                    message.send().await?;
+                },
+                _ => {
+                    bot.send_message(msg.chat.id, "Usage: /address [add <label> <address>|remove <label>|list]").await?;
+                }
+            }
+        },
+        TelegramCommand::Tip { amount } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let dm = from.id;
+            let user = UserApplication::Telegram(from.id.0);
+            let mut args = amount.split_whitespace();
+            let (amount, asset) = (args.next().unwrap_or_default(), args.next());
+            let amount = match parse_asset_amount(&state, &user, asset, amount).await {
+                Ok(amount) => {
+                    // Let the user confirm the amount if it was rounded or converted
+                    if amount.rounded || amount.conversion.is_some() {
+                        let cmd = TelegramCommand::Tip { amount: command_amount_args(&amount.amount) };
+                        confirm_telegram_command_amount(&bot, &msg, &confirmations, "Tip", &amount, cmd).await?;
+                        return Ok(());
+                    }
+
+                    let cmd = TelegramCommand::Tip { amount: command_amount_args(&amount.amount) };
+                    if confirm_telegram_tip_budget(&bot, &msg, &state, &confirmations, "Tip", &amount.amount, cmd).await? {
+                        return Ok(());
+                    }
+
+                    amount.amount
+                },
+                Err(e) => {
+                    debug!("Invalid amount: {}", e);
+                    bot.send_message(dm, format!("An error occured while tipping: {}", e)).await?;
+                    return Ok(());
+                }
+            };
+
+            let to = msg.reply_to_message().and_then(|m| m.from.as_ref()).ok_or(TelegramError::NoUser)?;
+
+            if to.is_bot || to.is_anonymous() || to.is_channel() {
+                debug!("Invalid user");
+                bot.send_message(dm, "An error occured while tipping: Invalid user").await?;
+                return Ok(());
+            }
+
+            let name = to.username.as_ref().unwrap_or(&to.first_name).clone();
+            telegram_tip(&bot, &state, &notifier, &msg, thread_id, to.id, name, amount).await?;
+        },
+        TelegramCommand::TipUser { username, amount } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let dm = from.id;
+            let Some((to, name, amount)) = resolve_telegram_mention(&state, &msg, &username, &amount).await else {
+                debug!("Unknown user {}", username);
+                bot.send_message(dm, format!("An error occured while tipping: Unknown user {}, they must have written in a group with the bot first", username)).await?;
+                return Ok(());
+            };
+
+            let user = UserApplication::Telegram(from.id.0);
+            let mut args = amount.split_whitespace();
+            let (amount, asset) = (args.next().unwrap_or_default(), args.next());
+            let amount = match parse_asset_amount(&state, &user, asset, amount).await {
+                Ok(amount) => {
+                    // Let the user confirm the amount if it was rounded or converted
+                    if amount.rounded || amount.conversion.is_some() {
+                        let cmd = TelegramCommand::TipUser { username, amount: command_amount_args(&amount.amount) };
+                        confirm_telegram_command_amount(&bot, &msg, &confirmations, "Tip", &amount, cmd).await?;
+                        return Ok(());
+                    }
+
+                    let cmd = TelegramCommand::TipUser { username, amount: command_amount_args(&amount.amount) };
+                    if confirm_telegram_tip_budget(&bot, &msg, &state, &confirmations, "Tip", &amount.amount, cmd).await? {
+                        return Ok(());
+                    }
+
+                    amount.amount
+                },
+                Err(e) => {
+                    debug!("Invalid amount: {}", e);
+                    bot.send_message(dm, format!("An error occured while tipping: {}", e)).await?;
+                    return Ok(());
+                }
+            };
+
+            telegram_tip(&bot, &state, &notifier, &msg, thread_id, to, name, amount).await?;
+        },
+        TelegramCommand::TipMe { amount } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            if msg.chat.is_private() {
+                bot.send_message(from.id, "You can only use this command in a group").await?;
+                return Ok(());
+            }
+
+            let name = escape_html(from.username.as_ref().unwrap_or(&from.first_name));
+            let mut message = TelegramMessage::new(&bot, msg.chat.id, thread_id);
+            message.title("Tip me")
+                .line(format!("{} accepts tips in XELIS", name));
+
+            if amount.trim().is_empty() {
+                // Without an amount, there is nothing to confirm from a button
+                match from.username.as_ref() {
+                    Some(username) => message.line(format!("Use /tipuser @{} &lt;amount&gt; to tip them", escape_html(username))),
+                    None => message.line("Reply to one of their messages with /tip &lt;amount&gt; to tip them")
+                };
+            } else {
+                let amount = match parse_amount(&amount).and_then(Amount::exact) {
+                    Ok(amount) => amount,
+                    Err(e) => {
+                        bot.send_message(from.id, format!("An error occured: {}", e)).await?;
+                        return Ok(());
+                    }
+                };
+
+                message.buttons(InlineKeyboardMarkup::new(vec![vec![
+                    InlineKeyboardButton::callback(format!("Tip {} XEL", format_xelis(amount)), format!("{}{}:{}", TIPME_BUTTON_PREFIX, from.id, amount))
+                ]]));
+            }
+
+            // The button tips the author of the message it replies to
+            message.send()
+                .reply_parameters(ReplyParameters::new(msg.id))
+                .await?;
+        },
+        TelegramCommand::Rain { amount, count } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let dm = from.id;
+            if msg.chat.is_private() {
+                bot.send_message(dm, "You can only use this command in a group").await?;
+                return Ok(());
+            }
+
+            let amount = match parse_amount(&amount) {
+                Ok(amount) => amount,
+                Err(e) => {
+                    debug!("Invalid amount: {}", e);
+                    bot.send_message(dm, format!("An error occured while raining: {}", e)).await?;
+                    return Ok(());
+                }
+            };
+
+            // Let the user confirm the amount if it was rounded
+            if amount.rounded {
+                let cmd = TelegramCommand::Rain { amount: format_xelis(amount.value), count };
+                confirm_telegram_rounded_amount(&bot, &msg, &confirmations, "Rain", &AssetBalance::xelis(amount.value), cmd).await?;
+                return Ok(());
+            }
+
+            let cmd = TelegramCommand::Rain { amount: format_xelis(amount.value), count };
+            if confirm_telegram_tip_budget(&bot, &msg, &state, &confirmations, "Rain", &AssetBalance::xelis(amount.value), cmd).await? {
+                return Ok(());
+            }
+            let amount = amount.value;
+
+            // Users that don't meet the strictness of the group are skipped
+            let community = Community::Telegram(msg.chat.id.0);
+            let strictness = state.get_recipient_strictness(&community).await;
+            let mut recipients = Vec::new();
+            let mut skipped = 0;
+            for user in activity.recent_users(msg.chat.id.0, usize::MAX, from.id.0) {
+                if recipients.len() >= count as usize {
+                    break;
+                }
+
+                if state.is_eligible_recipient(&community, &UserApplication::Telegram(user.id), strictness, user.message_count()).await {
+                    recipients.push(user);
+                } else {
+                    skipped += 1;
+                }
+            }
+
+            let share = amount / recipients.len().max(1) as u64;
+            if recipients.is_empty() || share == 0 {
+                // Nothing to preview, the service tells why the rain can't happen
+                telegram_send_rain(&bot, &msg, &state, &recipients, share).await?;
+                return Ok(());
+            }
+
+            // Show who receives what before anything is debited, the rain goes to these exact users
+            let names = recipients.iter()
+                .map(|user| escape_html(&user.name))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let total = share * recipients.len() as u64;
+            let id = rains.insert(TelegramPendingRain { msg: msg.clone(), recipients, share });
+
+            let mut preview = TelegramMessage::new(&bot, dm, None);
+            preview.title("Rain")
+                .line("Confirm to make it rain on these users")
+                .field("Recipients", names, false)
+                .field("Each user receives", format!("{} XEL", format_xelis(share)), true)
+                .field("Total", format!("{} XEL", format_xelis(total)), true);
+
+            if skipped > 0 {
+                preview.field("Skipped", format!("{} users below the {} strictness of the group", skipped, strictness.name()), false);
+            }
+
+            preview
+                .buttons(InlineKeyboardMarkup::new(vec![vec![
+                    InlineKeyboardButton::callback("Confirm", format!("{}{}", RAIN_CONFIRM_PREFIX, id)),
+                    InlineKeyboardButton::callback("Cancel", format!("{}{}", RAIN_CANCEL_PREFIX, id))
+                ]]))
+                .send().await?;
+        },
+        TelegramCommand::Giveaway { amount, duration, max_claims } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let dm = from.id;
+            if msg.chat.is_private() {
+                bot.send_message(dm, "You can only use this command in a group").await?;
+                return Ok(());
+            }
+
+            if max_claims == 0 || max_claims > MAX_GIVEAWAY_CLAIMS || duration == 0 || duration > MAX_GIVEAWAY_DURATION {
+                bot.send_message(dm, format!("A giveaway lasts up to {} minutes with up to {} claims", MAX_GIVEAWAY_DURATION, MAX_GIVEAWAY_CLAIMS)).await?;
+                return Ok(());
+            }
+
+            let amount = match parse_amount(&amount) {
+                Ok(amount) => amount,
+                Err(e) => {
+                    bot.send_message(dm, format!("An error occured while starting the giveaway: {}", e)).await?;
+                    return Ok(());
+                }
+            };
+
+            // Let the user confirm the amount if it was rounded
+            if amount.rounded {
+                let cmd = TelegramCommand::Giveaway { amount: format_xelis(amount.value), duration, max_claims };
+                confirm_telegram_rounded_amount(&bot, &msg, &confirmations, "Giveaway", &AssetBalance::xelis(amount.value), cmd).await?;
+                return Ok(());
+            }
+            let amount = amount.value;
+
+            // Show the payout before the amount is escrowed
+            if amount / max_claims as u64 == 0 {
+                telegram_start_giveaway(&bot, &msg, &state, amount, duration, max_claims).await?;
+                return Ok(());
+            }
+
+            let id = confirmations.insert((msg.clone(), TelegramCommand::Giveaway { amount: format_xelis(amount), duration, max_claims }));
+            TelegramMessage::new(&bot, dm, None)
+                .title("Giveaway")
+                .line(format!("Confirm to escrow {} XEL for up to {} users", format_xelis(amount), max_claims))
+                .field("Each user receives at least", format!("{} XEL", format_xelis(amount / max_claims as u64)), true)
+                .field("Ends in", format!("{} minutes", duration), true)
+                .line("The amount is split equally between the claimants when the giveaway ends, the rest is refunded")
+                .buttons(InlineKeyboardMarkup::new(vec![vec![
+                    InlineKeyboardButton::callback("Confirm", format!("startgiveaway:{}", id)),
+                    InlineKeyboardButton::callback("Cancel", format!("cancel:{}", id))
+                ]]))
+                .send().await?;
+        },
+        TelegramCommand::Coinflip { amount } => {
+            telegram_challenge(&bot, &msg, &state, &confirmations, thread_id, Game::Coinflip, amount).await?;
+        },
+        TelegramCommand::Dice { amount } => {
+            telegram_challenge(&bot, &msg, &state, &confirmations, thread_id, Game::Dice, amount).await?;
+        },
+        TelegramCommand::Convert { args } => {
+            let mut args = args.split_whitespace();
+            let (Some(amount), Some(unit)) = (args.next(), args.next()) else {
+                bot.send_message(msg.chat.id, "Usage: /convert <amount> <xel|currency> [currency]").await?;
+                return Ok(());
+            };
+
+            match state.prices().convert(amount, unit, args.next()).await {
+                Ok(conversion) => {
+                    TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                        .title("Convert")
+                        .field("XEL", format!("{} XEL", format_xelis(conversion.xel)), true)
+                        .field(&conversion.currency, format!("{} {}", format_fiat(conversion.fiat), conversion.currency), true)
+                        .field("Atomic units", InlineCode::new(&conversion.xel.to_string()), false)
+                        .field("Rates", conversion_rates(&conversion), false)
+                        .send().await?;
+                },
+                Err(e) => {
+                    bot.send_message(msg.chat.id, format!("An error occured while converting: {}", e)).await?;
+                }
+            };
+        },
+        TelegramCommand::Price { args } => {
+            let currency = args.split_whitespace().next();
+            let mut message = TelegramMessage::new(&bot, msg.chat.id, thread_id);
+            message.title("Price");
+            for (currency, market) in fetch_markets(&state, currency).await {
+                match market {
+                    Ok(market) => message.field(&escape_html(&currency), market_summary(&escape_html(&currency), &market), false),
+                    Err(e) => message.field(&escape_html(&currency), escape_html(&e.to_string()), false)
+                };
+            }
+
+            message.send().await?;
+        },
+        TelegramCommand::Practice { args } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let user = UserApplication::Telegram(from.id.0);
+            let mut message = TelegramMessage::new(&bot, msg.chat.id, thread_id);
+            message.title("Practice");
+
+            let mut args = args.split_whitespace();
+            let res = match args.next().unwrap_or("balance") {
+                "balance" => Ok(state.get_practice_balance(&user).await),
+                "tip" => match (args.next().map(parse_amount), msg.reply_to_message().and_then(|m| m.from.as_ref())) {
+                    (Some(Ok(amount)), Some(to)) if !to.is_bot => {
+                        let res = state.practice_tip(&user, &UserApplication::Telegram(to.id.0), amount.value).await;
+                        if res.is_ok() {
+                            message.line(format!("You have tipped {} XEL to {}", format_xelis(amount.value), escape_html(to.username.as_ref().unwrap_or(&to.first_name))));
+                        }
+                        res.map_err(|e| e.to_string())
+                    },
+                    (Some(Err(e)), _) => Err(e.to_string()),
+                    _ => Err("Reply to a user with /practice tip <amount>".to_string())
+                },
+                "withdraw" => match (args.next().map(Address::from_string), args.next().map(parse_amount)) {
+                    (Some(Ok(to)), _) if to.is_mainnet() != state.network().is_mainnet() => Err("Invalid network".to_string()),
+                    (Some(Ok(_)), Some(Ok(amount))) => {
+                        let res = state.practice_withdraw(&user, amount.value).await;
+                        if res.is_ok() {
+                            message.line(format!("You would have withdrawn {} XEL", format_xelis(amount.value)))
+                                .field("Fee", format!("{} XEL", format_xelis(PRACTICE_WITHDRAW_FEE)), true);
+                        }
+                        res.map_err(|e| e.to_string())
+                    },
+                    (Some(Err(e)), _) => Err(e.to_string()),
+                    (_, Some(Err(e))) => Err(e.to_string()),
+                    _ => Err("Usage: /practice withdraw <address> <amount>".to_string())
+                },
+                "reset" => state.reset_practice(&user).await
+                    .map(|_| PRACTICE_BALANCE)
+                    .map_err(|e| e.to_string()),
+                _ => Err("Usage: /practice [balance|tip <amount>|withdraw <address> <amount>|reset]".to_string())
+            };
+
+            match res {
+                Ok(balance) => message.field("Your sandbox balance is", format!("{} XEL", format_xelis(balance)), true),
+                Err(e) => message.field("An error occured", escape_html(&e), false)
+            };
+
+            message.line("<i>Practice mode: sandbox balance, nothing is sent on chain</i>")
+                .send().await?;
+        },
+        TelegramCommand::Lottery { args } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let user = UserApplication::Telegram(from.id.0);
+            let mut args = args.split_whitespace();
+            let res = match (args.next(), args.next().map(|count| count.parse::<u32>())) {
+                (None, _) => state.get_lottery().await.map(|(round, pot)| {
+                    let tickets = round.tickets_of(&user);
+                    (round, pot, tickets)
+                }),
+                (Some("buy"), Some(Ok(count))) => match state.buy_lottery_tickets(&user, count).await {
+                    Ok((round, tickets)) => state.get_lottery().await.map(|(_, pot)| (round, pot, tickets)),
+                    Err(e) => Err(e)
+                },
+                _ => {
+                    bot.send_message(msg.chat.id, format!("Usage: /lottery or /lottery buy <count> (up to {} tickets)", MAX_TICKETS_PER_PURCHASE)).await?;
+                    return Ok(());
+                }
+            };
+
+            match res {
+                Ok((round, pot, tickets)) => {
+                    let mut message = TelegramMessage::new(&bot, msg.chat.id, thread_id);
+                    message.title(&format!("Lottery round #{}", round.id))
+                        .field("Pot", format!("{} XEL", format_xelis(pot)), true)
+                        .field("Ticket price", format!("{} XEL", format_xelis(round.ticket_price)), true)
+                        .field("Tickets sold", round.total_tickets().to_string(), true)
+                        .field("Your tickets", tickets.to_string(), true)
+                        .field("Draw in", format!("{} minutes", round.draw_at.saturating_sub(get_current_time_in_seconds()).div_ceil(60)), true)
+                        .field("Seed commitment", InlineCode::new(&round.commitment().to_string()), false);
+
+                    if let Some(draw) = state.get_last_lottery_draw().await {
+                        let winner = match draw.winner {
+                            Some(UserApplication::Telegram(id)) => format!("{} won {} XEL", user_mention(UserId(id), &id.to_string()), format_xelis(draw.pot)),
+                            Some(UserApplication::Discord(id)) => format!("Discord user {} won {} XEL", id, format_xelis(draw.pot)),
+                            Some(UserApplication::Matrix(id)) => format!("Matrix user {} won {} XEL", id, format_xelis(draw.pot)),
+                            Some(UserApplication::Slack(id)) => format!("Slack user {} won {} XEL", id, format_xelis(draw.pot)),
+                            Some(UserApplication::Twitch(id)) => format!("Twitch user {} won {} XEL", id, format_xelis(draw.pot)),
+                            None => "No tickets were sold".to_string()
+                        };
+                        message.field(&format!("Round #{}", draw.round), format!("{}\nSeed: {}", winner, InlineCode::new(&draw.seed.to_string()).to_string()), false);
+                    }
+
+                    message.send().await?;
+                },
+                Err(e) => {
+                    bot.send_message(msg.chat.id, format!("An error occured with the lottery: {}", e)).await?;
+                }
+            };
+        },
+        TelegramCommand::Faucet => {
+            let from = msg.from.ok_or(TelegramError::NoUser)?;
+            let user = UserApplication::Telegram(from.id.0);
+            let exempt = state.is_exempt(&user, &[]).await;
+            match state.claim_faucet(&user, exempt).await {
+                Ok(amount) => {
+                    TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                        .title("Faucet")
+                        .field("You received", format!("{} XEL", format_xelis(amount)), false)
+                        .send().await?;
+                },
+                Err(e) => {
+                    bot.send_message(msg.chat.id, format!("An error occured while claiming the faucet: {}", e)).await?;
+                }
+            };
+        },
+        TelegramCommand::Leaderboard { args } => {
+            let mut period = LeaderboardPeriod::Week;
+            let mut kind = LeaderboardType::Tippers;
+            for arg in args.split_whitespace() {
+                match arg.to_lowercase().as_str() {
+                    "day" | "today" => period = LeaderboardPeriod::Day,
+                    "week" => period = LeaderboardPeriod::Week,
+                    "all" => period = LeaderboardPeriod::AllTime,
+                    "tippers" => kind = LeaderboardType::Tippers,
+                    "receivers" => kind = LeaderboardType::Receivers,
+                    _ => {
+                        bot.send_message(msg.chat.id, "Usage: /leaderboard [day|week|all] [tippers|receivers]").await?;
+                        return Ok(());
+                    }
+                }
+            }
+
+            // In a group only the tips done in it are ranked
+            let leaderboard = state.get_leaderboard(period.stats_period(), telegram_community(&msg), match kind {
+                LeaderboardType::Tippers => LeaderboardKind::Tippers,
+                LeaderboardType::Receivers => LeaderboardKind::Receivers
+            }).await?;
+
+            let lines = leaderboard.iter()
+                .take(LEADERBOARD_PAGE_SIZE)
+                .enumerate()
+                .map(|(i, (user, amount))| {
+                    let user = match user {
+                        UserApplication::Telegram(id) => format!("<a href=\"tg://user?id={}\">{}</a>", id, id),
+                        UserApplication::Discord(id) => format!("Discord user {}", id),
+                        UserApplication::Matrix(id) => format!("Matrix user {}", id),
+                        UserApplication::Slack(id) => format!("Slack user {}", id),
+                        UserApplication::Twitch(id) => format!("Twitch user {}", id)
+                    };
+                    format!("{}. {} - {} XEL", i + 1, user, format_xelis(*amount))
+                })
+                .collect::<Vec<_>>();
+
+            TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                .title(&format!("Leaderboard - {} - {}", kind.name(), period.name()))
+                .field("Ranking", if lines.is_empty() { "No tips yet for this period".to_string() } else { lines.join("\n") }, false)
+                .send().await?;
+        },
+        TelegramCommand::Announcements { args } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let Some(community) = telegram_community(&msg) else {
+                bot.send_message(from.id, "You can only use this command in a group").await?;
+                return Ok(());
+            };
+
+            if !bot.get_chat_member(msg.chat.id, from.id).await?.is_privileged() {
+                bot.send_message(from.id, "Only the administrators of the group can use this command").await?;
+                return Ok(());
+            }
+
+            let mut args = args.split_whitespace();
+            let (channel, spoiler) = match (args.next(), args.next()) {
+                (None, _) | (Some("off"), _) => (None, false),
+                (Some(channel), spoiler) => (Some(channel.to_string()), spoiler == Some("spoiler"))
+            };
+
+            if let Some(channel) = channel.as_deref() {
+                if parse_telegram_channel(channel).is_none() {
+                    bot.send_message(msg.chat.id, "Invalid channel, use its @username or its id").await?;
+                    return Ok(());
+                }
+            }
+
+            state.set_setting(&community, Setting::AnnouncementChannel, channel.clone().map(DataValue::String)).await?;
+            state.set_setting(&community, Setting::AnnouncementSpoiler, Some(DataValue::Bool(spoiler))).await?;
+
+            TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                .title("Announcements")
+                .field("Channel", channel.unwrap_or_else(|| "Disabled".to_string()), true)
+                .field("Hidden amounts", spoiler.to_string(), true)
+                .send().await?;
+        },
+        TelegramCommand::Goal { args } => {
+            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+            let Some(community) = telegram_community(&msg) else {
+                bot.send_message(from.id, "You can only use this command in a group").await?;
+                return Ok(());
+            };
+
+            // The celebration message is everything after the reward
+            let (target, args) = args.trim().split_once(char::is_whitespace).unwrap_or((args.trim(), ""));
+            let (reward, celebration) = args.trim().split_once(char::is_whitespace).unwrap_or((args.trim(), ""));
+            if target.is_empty() {
+                let mut message = TelegramMessage::new(&bot, msg.chat.id, thread_id);
+                match state.get_goal(&community).await {
+                    Some(goal) => goal.write_telegram_message(&mut message, GoalUpdate::Progress),
+                    None => {
+                        message.title("Tipping goal").line("No goal is running in this group");
+                    }
+                };
+                message.send().await?;
+                return Ok(());
+            }
+
+            if !bot.get_chat_member(msg.chat.id, from.id).await?.is_privileged() {
+                bot.send_message(from.id, "Only the administrators of the group can use this command").await?;
+                return Ok(());
+            }
+
+            if target == "off" {
+                match state.cancel_goal(&community).await {
+                    Ok(goal) => {
+                        TelegramMessage::new(&bot, msg.chat.id, thread_id)
+                            .title("Tipping goal")
+                            .line("The goal was cancelled")
+                            .field("Tipped", format!("{} / {} XEL", format_xelis(goal.tipped), format_xelis(goal.target)), true)
+                            .field("Refunded", format!("{} XEL", format_xelis(goal.reward)), true)
+                            .send().await?;
+                    },
+                    Err(e) => {
+                        bot.send_message(from.id, format!("An error occured while cancelling the goal: {}", e)).await?;
+                    }
+                };
+                return Ok(());
+            }
+
+            let reward = Some(reward).filter(|reward| !reward.is_empty());
+            let (target, reward) = match parse_goal_amounts(target, reward) {
+                Ok(amounts) => amounts,
+                Err(e) => {
+                    bot.send_message(from.id, format!("An error occured while setting the goal: {}", e)).await?;
+                    return Ok(());
+                }
+            };
+            let celebration = Some(celebration.trim().to_string()).filter(|message| !message.is_empty());
+
+            match state.set_goal(&community, &UserApplication::Telegram(from.id.0), target, reward, celebration, telegram_channel(msg.chat.id)).await {
+                Ok(goal) => {
+                    let mut message = TelegramMessage::new(&bot, msg.chat.id, thread_id);
+                    goal.write_telegram_message(&mut message, GoalUpdate::Progress);
+                    let sent = message.send().await?;
+                    state.set_goal_message(&community, sent.id.0 as u64).await?;
+                },
+                Err(e) => {
+                    bot.send_message(from.id, format!("An error occured while setting the goal: {}", e)).await?;
+                }
+            };
+        }
+    }
+
+    Ok(())
+}
+
+// Escrow the stake of the author and post the challenge with its buttons
+// The user to which the message replies is the opponent
+async fn telegram_challenge(bot: &Bot, msg: &Message, state: &WalletService, confirmations: &TelegramConfirmations, thread_id: Option<ThreadId>, game: Game, amount: String) -> Result<(), Error> {
+    let title = game_name(game);
+    let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+    let dm = from.id;
+    if msg.chat.is_private() {
+        bot.send_message(dm, "You can only use this command in a group").await?;
+        return Ok(());
+    }
+
+    let amount = match parse_amount(&amount) {
+        Ok(amount) => amount,
+        Err(e) => {
+            bot.send_message(dm, format!("An error occured while creating the challenge: {}", e)).await?;
+            return Ok(());
+        }
+    };
+
+    // Let the user confirm the amount if it was rounded
+    if amount.rounded {
+        let rounded = format_xelis(amount.value);
+        let cmd = match game {
+            Game::Coinflip => TelegramCommand::Coinflip { amount: rounded },
+            Game::Dice => TelegramCommand::Dice { amount: rounded }
+        };
+        confirm_telegram_rounded_amount(bot, msg, confirmations, title, &AssetBalance::xelis(amount.value), cmd).await?;
+        return Ok(());
+    }
+    let amount = amount.value;
+
+    let to = msg.reply_to_message().and_then(|m| m.from.as_ref()).filter(|to| !to.is_bot && !to.is_anonymous() && !to.is_channel());
+    // A coinflip needs an opponent, a dice duel can be accepted by anyone
+    if to.is_none() && game == Game::Coinflip {
+        bot.send_message(dm, "Reply to a message of the user you want to challenge").await?;
+        return Ok(());
+    }
+
+    let opponent = to.map(|to| UserApplication::Telegram(to.id.0));
+    match state.create_challenge(game, &UserApplication::Telegram(from.id.0), opponent.as_ref(), amount, telegram_channel(msg.chat.id)).await {
+        Ok(challenge) => {
+            let author = user_mention(from.id, &from.full_name());
+            let opponent = to.map(|to| user_mention(to.id, &to.full_name())).unwrap_or_else(|| "anyone".to_string());
+            let description = match game {
+                Game::Coinflip => format!("{} challenged {} to a coinflip, {} is heads", author, opponent, escape_html(&from.full_name())),
+                Game::Dice => format!("{} challenged {} to a dice duel, the highest roll wins", author, opponent)
+            };
+
+            TelegramMessage::new(bot, msg.chat.id, thread_id)
+                .title(title)
+                .line(description)
+                .field("Stake", format!("{} XEL each", format_xelis(challenge.amount)), true)
+                .field("Expires in", format!("{} minutes", CHALLENGE_TIMEOUT.as_secs() / 60), true)
+                .field("Seed commitment", InlineCode::new(&challenge.commitment().to_string()), false)
+                .buttons(InlineKeyboardMarkup::new(vec![vec![
+                    InlineKeyboardButton::callback("Accept", format!("{}{}", CHALLENGE_ACCEPT_PREFIX, challenge.id)),
+                    InlineKeyboardButton::callback("Decline", format!("{}{}", CHALLENGE_DECLINE_PREFIX, challenge.id))
+                ]]))
+                .send().await?;
+        },
+        Err(e) => {
+            bot.send_message(dm, format!("An error occured while creating the challenge: {}", e)).await?;
+        }
+    };
 
     Ok(())
 }
 
-/// Show your current balance
-#[poise::command(slash_command, broadcast_typing)]
-async fn balance(ctx: Context<'_>) -> Result<(), Error> {
-    // Retrieve balance for user
-    let service = ctx.data();
-    let balance = service.get_balance_for_user(&UserApplication::Discord(ctx.author().id.into())).await;
+// Community of a telegram message, None in private chats
+fn telegram_community(msg: &Message) -> Option<Community> {
+    if msg.chat.is_private() {
+        return None;
+    }
 
-    let embed = CreateEmbed::default()
-        .title("Balance")
-        .field("Your balance is", format_xelis(balance), false)
-        .thumbnail(ICON)
-        .colour(COLOR);
-    let mut reply = CreateReply::default()
-        .embed(embed);
+    Some(Community::Telegram(msg.chat.id.0))
+}
 
-    // Set reply to ephemeral if command was not used in DM
-    if ctx.channel_id().to_channel(ctx.http()).await?.private().is_none() {
-        reply = reply.ephemeral(true);
+// Parse a channel given as @username or as chat id
+fn parse_telegram_channel(channel: &str) -> Option<Recipient> {
+    if channel.starts_with('@') && channel.len() > 1 {
+        return Some(Recipient::ChannelUsername(channel.to_string()));
     }
 
-    // Send reply
-    ctx.send(reply).await?;
+    channel.parse().ok().map(|id| Recipient::Id(ChatId(id)))
+}
+
+// Mirror an announcement of a group in its linked channel, if configured
+// Failures are only logged as the announcement was already made in the group
+async fn mirror_telegram_announcement(bot: &Bot, state: &WalletService, msg: &Message, title: &str, to: String, amount: &AssetBalance) {
+    let Some(community) = telegram_community(msg) else {
+        return;
+    };
+
+    let Some(DataValue::String(channel)) = state.get_setting(&community, Setting::AnnouncementChannel).await else {
+        return;
+    };
+
+    let Some(channel) = parse_telegram_channel(&channel) else {
+        return;
+    };
+
+    let spoiler = matches!(state.get_setting(&community, Setting::AnnouncementSpoiler).await, Some(DataValue::Bool(true)));
+    let from = msg.from.as_ref().map(|user| user.username.as_ref().unwrap_or(&user.first_name).clone()).unwrap_or_default();
+    let amount = escape_html(&amount.to_string());
+    let amount = if spoiler { Spoiler::new(&amount).to_string() } else { amount };
+
+    let res = TelegramMessage::new(bot, channel, None)
+        .title(title)
+        .field("Group", escape_html(msg.chat.title().unwrap_or_default()), true)
+        .field("From", escape_html(&from), true)
+        .field("To", escape_html(&to), true)
+        .field("Amount", amount, true)
+        .send().await;
+
+    if let Err(e) = res {
+        warn!("Couldn't mirror announcement of chat {}: {}", msg.chat.id, e);
+    }
+}
+
+// Build the setup wizard of a group from its current settings
+// Each button saves its setting directly, the wizard can be left at any time
+async fn telegram_setup_wizard<'a>(bot: &'a Bot, state: &WalletService, to: ChatId, chat_id: ChatId, title: &str, done: bool) -> TelegramMessage<'a> {
+    let community = Community::Telegram(chat_id.0);
+    let min_tip = state.get_min_tip(&community).await;
+    let disabled = state.get_disabled_features(&community).await;
+
+    let mut message = TelegramMessage::new(bot, to, None);
+    message.title(&format!("Setup - {}", escape_html(title)))
+        .field("Minimum tip", min_tip_label(min_tip), true)
+        .field("Features", enabled_features(disabled), true);
+
+    if done {
+        message.line("Setup saved. Use /announcements in the group to mirror its tips in a channel.");
+        return message;
+    }
+
+    message.line("Choose the minimum tip and the features available in the group, then click on Done.");
+
+    let mut buttons = vec![SETUP_MIN_TIPS.iter()
+        .map(|amount| {
+            let label = min_tip_label(*amount);
+            let label = if *amount == min_tip { format!("✅ {}", label) } else { label };
+            InlineKeyboardButton::callback(label, format!("{}{}:min:{}", SETUP_WIZARD_PREFIX, chat_id, amount))
+        })
+        .collect::<Vec<_>>()];
+
+    for feature in Feature::ALL {
+        let label = if disabled & feature.bit() == 0 { format!("✅ {}", feature.name()) } else { format!("❌ {}", feature.name()) };
+        buttons.push(vec![InlineKeyboardButton::callback(label, format!("{}{}:feature:{}", SETUP_WIZARD_PREFIX, chat_id, feature.bit()))]);
+    }
+
+    buttons.push(vec![InlineKeyboardButton::callback("Done", format!("{}{}:done", SETUP_WIZARD_PREFIX, chat_id))]);
+    message.buttons(InlineKeyboardMarkup::new(buttons));
+
+    message
+}
+
+// Send the setup wizard to the admin who added the bot in a group
+async fn telegram_my_chat_member_handler(bot: Bot, update: ChatMemberUpdated, state: WalletService) -> Result<(), Error> {
+    let added = !update.old_chat_member.is_present() && update.new_chat_member.is_present();
+    if !added || !(update.chat.is_group() || update.chat.is_supergroup()) {
+        return Ok(());
+    }
+
+    let title = update.chat.title().unwrap_or_default();
+    let wizard = telegram_setup_wizard(&bot, &state, update.from.id.into(), update.chat.id, title, false).await;
+    if let Err(e) = wizard.send().await {
+        // The admin never started a conversation with the bot, use the group instead
+        // Only the admins of the group can click on the buttons
+        debug!("Couldn't send the setup wizard to {}: {}", update.from.id, e);
+        telegram_setup_wizard(&bot, &state, update.chat.id, update.chat.id, title, false).await
+            .send().await?;
+    }
+
+    Ok(())
+}
+
+// Apply a button of the setup wizard
+async fn telegram_setup_callback(bot: &Bot, q: &CallbackQuery, state: &WalletService, data: &str) -> Result<(), Error> {
+    let mut parts = data.split(':');
+    let (Some(chat_id), Some(action)) = (parts.next().and_then(|id| id.parse().ok()).map(ChatId), parts.next()) else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
+    };
+    let value = parts.next().and_then(|value| value.parse::<u64>().ok());
+
+    // Settings can only be changed by the admins of the group
+    let is_admin = bot.get_chat_member(chat_id, q.from.id).await
+        .is_ok_and(|member| member.is_privileged());
+    if !is_admin {
+        bot.answer_callback_query(q.id.clone())
+            .text("Only the admins of the group can configure the bot")
+            .show_alert(true)
+            .await?;
+        return Ok(());
+    }
+
+    let community = Community::Telegram(chat_id.0);
+    match (action, value) {
+        ("min", Some(min_tip)) => {
+            state.set_setting(&community, Setting::MinTip, Some(DataValue::U64(min_tip)).filter(|_| min_tip > 0)).await?;
+        },
+        ("feature", Some(bit)) if Feature::ALL.iter().any(|feature| feature.bit() == bit) => {
+            let disabled = state.get_disabled_features(&community).await ^ bit;
+            state.set_setting(&community, Setting::DisabledFeatures, Some(DataValue::U64(disabled)).filter(|_| disabled != 0)).await?;
+        },
+        ("done", _) => {},
+        _ => {
+            bot.answer_callback_query(q.id.clone()).await?;
+            return Ok(());
+        }
+    };
+
+    bot.answer_callback_query(q.id.clone()).await?;
+    let Some(message) = q.regular_message() else {
+        return Ok(());
+    };
+
+    let title = match bot.get_chat(chat_id).await {
+        Ok(chat) => chat.title().unwrap_or_default().to_string(),
+        Err(_) => chat_id.to_string()
+    };
+
+    // Telegram refuses the edit when the same button is clicked twice
+    let res = telegram_setup_wizard(bot, state, message.chat.id, chat_id, &title, action == "done").await
+        .edit(message.id)
+        .await;
+    if let Err(e) = res {
+        debug!("Couldn't update the setup wizard of chat {}: {}", chat_id, e);
+    }
+
+    Ok(())
+}
+
+// Remember the private chat of a user writing to the bot, such as with /start
+// Telegram bots can only send DMs to these users, so the notifications deferred until then are delivered
+async fn register_telegram_chat(bot: Bot, msg: Message, state: WalletService) {
+    if !msg.chat.is_private() {
+        return;
+    }
+
+    let Some(from) = msg.from.as_ref().filter(|user| !user.is_bot) else {
+        return;
+    };
+
+    match state.record_telegram_chat(from.id.0, msg.chat.id).await {
+        Ok(true) => if let Err(e) = state.deliver_deferred_notifications(&bot, from.id.0).await {
+            warn!("Couldn't deliver the deferred notifications of {}: {}", from.id, e);
+        },
+        Ok(false) => {},
+        Err(e) => warn!("Couldn't record the private chat of {}: {}", from.id, e)
+    }
+}
+
+// Remember the author of each group message for the rain command
+// The first message of a user in the group is stored for the recipient strictness
+async fn record_telegram_activity(msg: Message, state: WalletService, activity: TelegramActivity) {
+    if msg.chat.is_private() {
+        return;
+    }
+
+    let Some(from) = msg.from.as_ref().filter(|user| !user.is_bot) else {
+        return;
+    };
+
+    if activity.record(msg.chat.id.0, from.id.0, from.username.as_ref().unwrap_or(&from.first_name).clone()) {
+        if let Err(e) = state.record_first_seen(&Community::Telegram(msg.chat.id.0), &UserApplication::Telegram(from.id.0)).await {
+            warn!("Couldn't record first message of {} in {}: {}", from.id, msg.chat.id, e);
+        }
+    }
+}
+
+// Remember the username of each user seen by the bot for the tips by mention
+async fn record_telegram_usernames(msg: Message, state: WalletService) {
+    for user in msg.mentioned_users().filter(|user| !user.is_bot) {
+        if let Some(username) = user.username.as_ref() {
+            if let Err(e) = state.record_telegram_username(username, user.id.0).await {
+                warn!("Couldn't record username of {}: {}", user.id, e);
+            }
+        }
+    }
+}
+
+// Split the arguments of /tipuser between the mention and the amount with its optional asset
+fn split_mention(args: String) -> Result<(String, String), ParseError> {
+    let mut args = args.trim().splitn(2, char::is_whitespace);
+    match (args.next().filter(|username| !username.is_empty()), args.next()) {
+        (Some(username), Some(amount)) => Ok((username.to_string(), amount.trim().to_string())),
+        (username, _) => Err(ParseError::TooFewArguments {
+            expected: 2,
+            found: username.iter().count(),
+            message: "Expected a user and an amount".to_string()
+        })
+    }
+}
+
+// Find the user mentioned in /tipuser and the arguments left after its mention
+// Text mentions carry the user but display its name, which may contain spaces
+// Usernames are resolved with the users already seen by the bot
+async fn resolve_telegram_mention(state: &WalletService, msg: &Message, username: &str, args: &str) -> Option<(UserId, String, String)> {
+    let text = format!("{} {}", username, args);
+    for entity in msg.parse_entities().unwrap_or_default() {
+        if let MessageEntityKind::TextMention { user } = entity.kind() {
+            if let Some(args) = text.strip_prefix(entity.text()) {
+                if user.is_bot {
+                    return None;
+                }
+
+                let name = user.username.as_ref().unwrap_or(&user.first_name).clone();
+                return Some((user.id, name, args.trim().to_string()));
+            }
+        }
+    }
+
+    let id = state.get_telegram_user_by_username(username).await?;
+    Some((UserId(id), username.trim_start_matches('@').to_string(), args.to_string()))
+}
+
+// Tip a Telegram user and announce it in the chat of the command
+async fn telegram_tip(bot: &Bot, state: &WalletService, notifier: &TelegramNotifications, msg: &Message, thread_id: Option<ThreadId>, to: UserId, name: String, amount: AssetBalance) -> Result<(), Error> {
+    let platform = TelegramPlatform::new(bot, state, msg, thread_id)?.notifier(notifier);
+    platform::tip(state, &platform, &UserApplication::Telegram(to.0), &format!("{} ({})", name, to), &amount, None).await
+}
+
+// Split the arguments of /remind following its user: in <delay> about <memo> [amount]
+// The last word is the amount if it parses as one
+fn split_remind_args(args: &str) -> Option<(String, String, Option<u64>)> {
+    let mut words = args.split_whitespace().peekable();
+    words.next_if_eq(&"in");
+    let delay = words.next()?.to_string();
+    words.next_if_eq(&"about");
+
+    let mut memo = words.collect::<Vec<_>>();
+    let amount = match memo.as_slice() {
+        [_, .., last] => parse_amount(last).and_then(Amount::exact).ok(),
+        _ => None
+    };
+    if amount.is_some() {
+        memo.pop();
+    }
+
+    Some((delay, memo.join(" "), amount))
+}
+
+// Schedule a reminder sent in DM to the mentioned user, or to its author with "me"
+// The author is answered in private so the group is not spammed
+async fn telegram_remind(bot: &Bot, msg: &Message, state: &WalletService, args: &str) -> Result<(), Error> {
+    let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+    let dm = from.id;
+    let usage = "Usage: /remind <@username|me> in <delay> about <memo> [amount]";
+    let (username, args) = match args.trim().split_once(char::is_whitespace) {
+        Some((username, args)) => (username, args.trim()),
+        None => {
+            bot.send_message(dm, usage).await?;
+            return Ok(());
+        }
+    };
+
+    let (to, name, args) = if username.eq_ignore_ascii_case("me") {
+        (from.id, "You".to_string(), args.to_string())
+    } else {
+        let Some((to, name, args)) = resolve_telegram_mention(state, msg, username, args).await else {
+            bot.send_message(dm, format!("Unknown user {}, they must have written in a group with the bot first", username)).await?;
+            return Ok(());
+        };
+        (to, name, args)
+    };
+
+    let Some((delay, memo, amount)) = split_remind_args(&args) else {
+        bot.send_message(dm, usage).await?;
+        return Ok(());
+    };
+
+    let creator_name = match from.username.as_ref() {
+        Some(username) => format!("@{}", username),
+        None => from.full_name()
+    };
+    let user = UserApplication::Telegram(from.id.0);
+    let result = match parse_delay(&delay) {
+        Ok(duration) => state.schedule_reminder(&user, &creator_name, &UserApplication::Telegram(to.0), duration, &memo, amount).await,
+        Err(e) => Err(e)
+    };
+
+    match result {
+        Ok(reminder) => {
+            let mut message = TelegramMessage::new(bot, dm, None);
+            message.title("Reminder")
+                .line(format!("{} will be reminded in DM in {}", escape_html(&name), escape_html(&delay)))
+                .field("About", escape_html(&reminder.memo), false);
+            if let Some(amount) = reminder.amount {
+                message.field("Amount", format!("{} XEL", format_xelis(amount)), true);
+            }
+
+            message.send().await?;
+        },
+        Err(e) => {
+            debug!("Couldn't schedule a reminder: {}", e);
+            bot.send_message(dm, format!("An error occured while scheduling the reminder: {}", e)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Show or change the settings of a Telegram group, only its admins can use it
+async fn telegram_group_settings(bot: &Bot, msg: &Message, state: &WalletService, thread_id: Option<ThreadId>, args: &str) -> Result<(), Error> {
+    let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+    let community = Community::Telegram(msg.chat.id.0);
+    let mut args = args.split_whitespace();
+    let usage = "Usage: /settings [tips public|private] [mintip <amount|off>] [bot on|off] [recipients off|low|medium|high]";
+
+    if let Some(setting @ ("budget" | "whitelist" | "pin")) = args.clone().next() {
+        // Only possible if the bot is an admin of the group
+        if setting == "pin" {
+            if let Err(e) = bot.delete_message(msg.chat.id, msg.id).await {
+                debug!("Couldn't delete the PIN message of {}: {}", from.id, e);
+            }
+        }
+
+        bot.send_message(from.id, format!("Your {} can only be configured in private", setting)).await?;
+        return Ok(());
+    }
+
+    let admins = bot.get_chat_administrators(msg.chat.id).await?;
+    if !admins.iter().any(|admin| admin.user.id == from.id) {
+        bot.send_message(from.id, "Only the admins of the group can use /settings in it").await?;
+        return Ok(());
+    }
+
+    match (args.next(), args.next()) {
+        (None, _) => {},
+        (Some("tips"), Some(mode @ ("public" | "private"))) => {
+            state.set_setting(&community, Setting::PrivateTips, Some(DataValue::Bool(true)).filter(|_| mode == "private")).await?;
+        },
+        (Some("mintip"), Some(amount)) => {
+            let min_tip = match amount {
+                "off" => Ok(0),
+                amount => parse_amount(amount).and_then(Amount::exact)
+            };
+
+            match min_tip {
+                Ok(min_tip) => state.set_setting(&community, Setting::MinTip, Some(DataValue::U64(min_tip)).filter(|_| min_tip > 0)).await?,
+                Err(e) => {
+                    bot.send_message(from.id, format!("An error occured while setting the minimum tip: {}", e)).await?;
+                    return Ok(());
+                }
+            }
+        },
+        (Some("bot"), Some(mode @ ("on" | "off"))) => {
+            state.set_setting(&community, Setting::BotDisabled, Some(DataValue::Bool(true)).filter(|_| mode == "off")).await?;
+        },
+        (Some("recipients"), Some(level)) => match Strictness::from_name(level) {
+            Some(strictness) => state.set_recipient_strictness(&community, strictness).await?,
+            None => {
+                bot.send_message(msg.chat.id, usage).await?;
+                return Ok(());
+            }
+        },
+        _ => {
+            bot.send_message(msg.chat.id, usage).await?;
+            return Ok(());
+        }
+    }
+
+    let min_tip = state.get_min_tip(&community).await;
+    TelegramMessage::new(bot, msg.chat.id, thread_id)
+        .title("Group settings")
+        .field("Tips", if state.is_setting_enabled(&community, Setting::PrivateTips).await { "Confirmed in private" } else { "Announced in the group" }, true)
+        .field("Minimum tip", if min_tip > 0 { format!("{} XEL", format_xelis(min_tip)) } else { "None".to_string() }, true)
+        .field("Bot", if state.is_setting_enabled(&community, Setting::BotDisabled).await { "Disabled" } else { "Enabled" }, true)
+        .field("Rain and giveaway recipients", strictness_description(state.get_recipient_strictness(&community).await), false)
+        .send().await?;
+
+    Ok(())
+}
+
+// Requirements of a strictness level, as shown in the group settings
+fn strictness_description(strictness: Strictness) -> String {
+    match strictness {
+        Strictness::Off => "Everyone".to_string(),
+        strictness => format!(
+            "{}: seen in the group for {} days and {} messages over the last 24 hours",
+            strictness.name(),
+            strictness.min_member_days(),
+            strictness.min_messages()
+        )
+    }
+}
+
+// Why a user can't claim a giveaway in a group
+fn telegram_strictness_text(strictness: Strictness) -> String {
+    format!(
+        "To receive rains and claim giveaways in this group, you must have written in it for {} days and sent {} messages over the last 24 hours",
+        strictness.min_member_days(),
+        strictness.min_messages()
+    )
+}
+
+// Arguments of an amount for a command executed again after a confirmation
+// Other assets than XEL are passed by hash as their name may not be unique
+fn command_amount_args(amount: &AssetBalance) -> String {
+    if amount.asset == XELIS_ASSET {
+        format_xelis(amount.amount)
+    } else {
+        format!("{} {}", format_coin(amount.amount, amount.decimals), amount.asset)
+    }
+}
+
+// Ask the author of a message to confirm a rounded amount
+// The command is executed again with the rounded amount once confirmed
+async fn confirm_telegram_rounded_amount(bot: &Bot, msg: &Message, confirmations: &TelegramConfirmations, title: &str, amount: &AssetBalance, cmd: TelegramCommand) -> Result<(), Error> {
+    let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+    let id = confirmations.insert((msg.clone(), cmd));
+
+    TelegramMessage::new(bot, from.id, None)
+        .title(title)
+        .field(&format!("{} only supports up to", escape_html(&amount.name)), format!("{} decimals", amount.decimals), true)
+        .field("Your amount has been rounded to", escape_html(&amount.to_string()), false)
+        .buttons(telegram_confirmation_buttons(id))
+        .send().await?;
+
+    Ok(())
+}
+
+// Ask the author of a message to confirm an amount typed in a command
+// Amounts converted from fiat show the rate used, the command is executed again in XEL
+async fn confirm_telegram_command_amount(bot: &Bot, msg: &Message, confirmations: &TelegramConfirmations, title: &str, amount: &CommandAmount, cmd: TelegramCommand) -> Result<(), Error> {
+    let Some(conversion) = amount.conversion.as_ref() else {
+        return confirm_telegram_rounded_amount(bot, msg, confirmations, title, &amount.amount, cmd).await;
+    };
+
+    let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+    let id = confirmations.insert((msg.clone(), cmd));
+
+    TelegramMessage::new(bot, from.id, None)
+        .title(title)
+        .field("Converted from", format!("{} {}", format_fiat(conversion.fiat), conversion.currency), true)
+        .field("Amount", escape_html(&amount.amount.to_string()), true)
+        .field("Rates", conversion_rates(conversion), false)
+        .buttons(telegram_confirmation_buttons(id))
+        .send().await?;
+
+    Ok(())
+}
+
+// Ask the author of a message to confirm a tip going over one of their budgets
+// Returns true if the confirmation was asked, the command is executed again once confirmed
+async fn confirm_telegram_tip_budget(bot: &Bot, msg: &Message, state: &WalletService, confirmations: &TelegramConfirmations, title: &str, amount: &AssetBalance, cmd: TelegramCommand) -> Result<bool, Error> {
+    if amount.asset != XELIS_ASSET {
+        return Ok(false);
+    }
+
+    let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+    let Some(overrun) = state.check_tip_budget(&UserApplication::Telegram(from.id.0), amount.amount).await else {
+        return Ok(false);
+    };
+
+    let id = confirmations.insert((msg.clone(), cmd));
+    TelegramMessage::new(bot, from.id, None)
+        .title(title)
+        .line(format!("This tip goes over your {} budget", overrun.period))
+        .field("Budget", budget_overrun_text(&overrun), false)
+        .field("Amount", escape_html(&amount.to_string()), false)
+        .buttons(InlineKeyboardMarkup::new(vec![vec![
+            InlineKeyboardButton::callback("Tip anyway", format!("overbudget:{}", id)),
+            InlineKeyboardButton::callback("Cancel", format!("cancel:{}", id))
+        ]]))
+        .send().await?;
+
+    Ok(true)
+}
 
-    Ok(())
+// Buttons answering a pending confirmation
+fn telegram_confirmation_buttons(id: u64) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback("Confirm", format!("confirm:{}", id)),
+        InlineKeyboardButton::callback("Cancel", format!("cancel:{}", id))
+    ]])
 }
 
-/// Show your deposit address
-#[poise::command(slash_command, broadcast_typing)]
-async fn deposit(ctx: Context<'_>) -> Result<(), Error> {
-    // Retrieve address for user
-    let service = ctx.data();
-    let address = service.get_address_for_user(&UserApplication::Discord(ctx.author().id.into()));
+// Ask the user that pressed a tip me button to confirm the tip in private
+// Once confirmed, it runs as a /tip replying to the /tipme command of the poster
+async fn telegram_tipme_callback(bot: &Bot, q: &CallbackQuery, confirmations: &TelegramConfirmations, data: &str) -> Result<(), Error> {
+    let Some((to, amount)) = data.split_once(':').and_then(|(to, amount)| Some((to.parse::<u64>().ok()?, amount.parse::<u64>().ok()?))) else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
+    };
 
-    let embed = CreateEmbed::default()
-        .title("Deposit")
-        .field("Your deposit address is", address.to_string(), true)
-        .footer(CreateEmbedFooter::new("Please do not send any other coins than XELIS to this address"))
-        .thumbnail(ICON)
-        .colour(COLOR);
+    let Some(mut msg) = q.regular_message().cloned() else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
+    };
 
-    let mut reply = CreateReply::default()
-        .embed(embed);
+    let Some(poster) = msg.reply_to_message().and_then(|m| m.from.clone()).filter(|user| user.id.0 == to) else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
+    };
 
-    // Set reply to ephemeral if command was not used in DM
-    if ctx.channel_id().to_channel(ctx.http()).await?.private().is_none() {
-        reply = reply.ephemeral(true);
+    if poster.id == q.from.id {
+        bot.answer_callback_query(q.id.clone())
+            .text("You can't tip yourself")
+            .show_alert(true)
+            .await?;
+        return Ok(());
     }
 
-    // Send reply
-    ctx.send(reply).await?;
+    msg.from = Some(q.from.clone());
+    let amount = AssetBalance::xelis(amount);
+    let id = confirmations.insert((msg, TelegramCommand::Tip { amount: command_amount_args(&amount) }));
+
+    let res = TelegramMessage::new(bot, q.from.id, None)
+        .title("Tip")
+        .field("Amount", escape_html(&amount.to_string()), true)
+        .field("To", escape_html(poster.username.as_ref().unwrap_or(&poster.first_name)), true)
+        .buttons(telegram_confirmation_buttons(id))
+        .send().await;
+
+    // The bot can only write to users that started a conversation with it
+    let text = match res {
+        Ok(_) => "Confirm the tip in your private chat with the bot",
+        Err(e) => {
+            debug!("Couldn't ask {} to confirm a tip: {}", q.from.id, e);
+            "Start a private chat with the bot, then press the button again"
+        }
+    };
+
+    bot.answer_callback_query(q.id.clone())
+        .text(text)
+        .show_alert(true)
+        .await?;
 
     Ok(())
 }
 
-/// Withdraw from your balance
-#[poise::command(slash_command, broadcast_typing)]
-async fn withdraw(ctx: Context<'_>, address: String, amount: f64) -> Result<(), Error> {
-    let service = ctx.data();
-    let ephemeral = ctx.channel_id().to_channel(ctx.http()).await?.private().is_none();
+// Tip the creator of a reminder from the button of the reminder
+// The button is in the private chat of the reminded user, whose reminder already shows the amount and the recipient
+async fn telegram_reminder_tip_callback(bot: &Bot, q: &CallbackQuery, state: &WalletService, notifier: &TelegramNotifications, data: &str) -> Result<(), Error> {
+    let Some((to, amount)) = data.split_once(':').and_then(|(to, amount)| Some((to.parse::<u64>().ok()?, amount.parse::<u64>().ok()?))) else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
+    };
 
-    // Parse address in correct format
-    let to = match Address::from_string(&address) {
-        Ok(address) => address,
-        Err(e) => {
-            ctx.send(CreateReply::default().ephemeral(ephemeral).embed(
-                CreateEmbed::default()
-                    .title("Withdraw")
-                    .field("An error occured while withdrawing", e.to_string(), false)
-                    .thumbnail(ICON)
-                    .colour(Colour::RED)
-                )
-            ).await?;
-            return Ok(());
-        }    
+    let Some(mut msg) = q.regular_message().cloned().filter(|msg| msg.chat.is_private()) else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
     };
 
-    // Verify the address is in good network
-    if to.is_mainnet() != service.network().is_mainnet() {
-        ctx.send(CreateReply::default().ephemeral(ephemeral).embed(
-            CreateEmbed::default()
-                .title("Withdraw")
-                .field("An error occured while withdrawing", "Invalid network", false)
-                .thumbnail(ICON)
-                .colour(Colour::RED)
-            )
-        ).await?;
+    let user = UserApplication::Telegram(q.from.id.0);
+    if let Some(overrun) = state.check_tip_budget(&user, amount).await {
+        bot.answer_callback_query(q.id.clone())
+            .text(format!("This tip goes over your {} budget. {}", overrun.period, budget_overrun_text(&overrun)))
+            .show_alert(true)
+            .await?;
         return Ok(());
     }
 
-    // Parse amount in correct format
-    let amount = match from_xelis(amount.to_string()) {
-        Some(amount) => amount,
-        None => {
-            ctx.send(CreateReply::default().ephemeral(true).embed(
-                CreateEmbed::default()
-                    .title("Tip")
-                    .field("An error occured while tipping", "Invalid amount", false)
-                    .thumbnail(ICON)
-                    .colour(Colour::RED)
-                )
-            ).await?;
-            return Ok(());
-        }
+    bot.answer_callback_query(q.id.clone()).await?;
+    // The reminder can only be paid once
+    bot.edit_message_reply_markup(msg.chat.id, msg.id).await?;
+
+    let to = UserId(to);
+    let name = match bot.get_chat(to).await {
+        Ok(chat) => chat.username().or(chat.first_name()).map(str::to_string).unwrap_or_else(|| to.to_string()),
+        Err(_) => to.to_string()
     };
 
-    match service.withdraw(&UserApplication::Discord(ctx.author().id.into()), to, amount).await {
-        Ok(hash) => {
-            ctx.send(CreateReply::default().ephemeral(ephemeral).embed(
-                CreateEmbed::default()
-                    .title("Withdraw")
-                    .description(format!("You have withdrawn {} XEL", format_xelis(amount)))
-                    .field("Transaction", hash.to_string(), false)
-                    .thumbnail(ICON)
-                    .colour(COLOR)
-                )
-            ).await?;
+    msg.from = Some(q.from.clone());
+    telegram_tip(bot, state, notifier, &msg, None, to, name, AssetBalance::xelis(amount)).await
+}
+
+// Rain of a Telegram user waiting for a confirmation, with the recipients it was shown
+struct TelegramPendingRain {
+    msg: Message,
+    recipients: Vec<ActiveUser>,
+    share: u64
+}
+
+// Credit every recipient of a rain at once and announce it in the group
+async fn telegram_send_rain(bot: &Bot, msg: &Message, state: &WalletService, recipients: &[ActiveUser], share: u64) -> Result<(), Error> {
+    let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+    let thread_id = msg.thread_id.filter(|_| msg.is_topic_message);
+    let transfers: Vec<(UserApplication, u64)> = recipients.iter()
+        .map(|user| (UserApplication::Telegram(user.id), share))
+        .collect();
+
+    let operation = OperationId::telegram("rain", msg.chat.id, msg.id);
+    match state.transfer_many(&UserApplication::Telegram(from.id.0), &transfers, telegram_community(msg), Some(&operation)).await {
+        Ok(total) => {
+            debug!("Rained {} XEL on {} users (chat id: {}, thread: {:?})", format_xelis(total), recipients.len(), msg.chat.id, thread_id);
+            let names = recipients.iter()
+                .map(|user| escape_html(&user.name))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            TelegramMessage::new(bot, msg.chat.id, thread_id)
+                .title("Rain")
+                .field("You made it rain", format!("{} XEL", format_xelis(total)), false)
+                .field("Each user received", format!("{} XEL", format_xelis(share)), false)
+                .field("Recipients", names, false)
+                .send().await?;
+
+            mirror_telegram_announcement(bot, state, msg, "Rain", format!("{} users", recipients.len()), &AssetBalance::xelis(total)).await;
         },
         Err(e) => {
-            ctx.send(CreateReply::default().ephemeral(ephemeral).embed(
-                CreateEmbed::default()
-                    .title("Withdraw")
-                    .field("An error occured while withdrawing", e.to_string(), false)
-                    .thumbnail(ICON)
-                    .colour(Colour::RED)
-                )
-            ).await?;
+            debug!("An error occured while raining: {}", e);
+            bot.send_message(from.id, format!("An error occured while raining: {}", e)).await?;
         }
     };
 
     Ok(())
 }
 
-/// Tip a user with XELIS
-#[poise::command(slash_command, broadcast_typing)]
-async fn tip(ctx: Context<'_>, #[description = "User to tip"] user: User, #[description = "Amount to tip"] amount: f64) -> Result<(), Error> {
-    let amount = match from_xelis(amount.to_string()) {
-        Some(amount) => amount,
-        None => {
-            ctx.send(CreateReply::default().ephemeral(true).embed(
-                CreateEmbed::default()
-                    .title("Tip")
-                    .field("An error occured while tipping", "Invalid amount", false)
-                    .thumbnail(ICON)
-                    .colour(Colour::RED)
-                )
-            ).await?;
-            return Ok(());
+// Escrow the amount of a giveaway and post its claim button in the group
+async fn telegram_start_giveaway(bot: &Bot, msg: &Message, state: &WalletService, amount: u64, duration: u32, max_claims: u16) -> Result<(), Error> {
+    let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+    let thread_id = msg.thread_id.filter(|_| msg.is_topic_message);
+    let duration = Duration::from_secs(duration as u64 * 60);
+    match state.create_giveaway(&UserApplication::Telegram(from.id.0), amount, max_claims, duration, telegram_channel(msg.chat.id)).await {
+        Ok(giveaway) => {
+            TelegramMessage::new(bot, msg.chat.id, thread_id)
+                .title("Giveaway")
+                .field("Giving away", format!("{} XEL", format_xelis(giveaway.amount)), true)
+                .field("Max claims", giveaway.max_claims.to_string(), true)
+                .field("Ends in", format!("{} minutes", duration.as_secs() / 60), true)
+                .buttons(InlineKeyboardMarkup::new(vec![vec![
+                    InlineKeyboardButton::callback("Claim", format!("{}{}", GIVEAWAY_BUTTON_PREFIX, giveaway.id))
+                ]]))
+                .send().await?;
+
+            let to = format!("Giveaway of up to {} users", giveaway.max_claims);
+            mirror_telegram_announcement(bot, state, msg, "Giveaway", to, &AssetBalance::xelis(giveaway.amount)).await;
+        },
+        Err(e) => {
+            bot.send_message(from.id, format!("An error occured while starting the giveaway: {}", e)).await?;
         }
     };
 
-    // Retrieve address for user
-    let service = ctx.data();
+    Ok(())
+}
 
-    match service.transfer(&UserApplication::Discord(ctx.author().id.into()), &UserApplication::Discord(user.id.into()), amount).await {
-        Ok(_) => {
-            ctx.send(CreateReply::default().embed(
-                CreateEmbed::default()
-                    .title("Tip")
-                    .description(format!("{} have tipped {} XEL to {}", ctx.author(), format_xelis(amount), user))
-                    .thumbnail(ICON)
-                    .colour(COLOR)
-                )
-            ).await?;
+// Delete the data of a Telegram user once it confirmed its /forgetme
+async fn telegram_forget_user(bot: &Bot, msg: &Message, state: &WalletService, args: &str) -> Result<(), Error> {
+    let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+    let user = UserApplication::Telegram(from.id.0);
+    let mut args = args.split_whitespace();
+    let (destination, pin) = (args.next().unwrap_or_default(), args.next());
+
+    let res = match parse_residual(state, &user, destination).await {
+        Ok(residual) => state.forget_user(&user, residual, pin).await,
+        Err(e) => Err(e)
+    };
+
+    match res {
+        Ok(forgotten) => {
+            let mut reply = TelegramMessage::new(bot, msg.chat.id, None);
+            reply.title("Forget Me")
+                .line("Your data has been deleted");
+            for line in forgotten_user_text(&forgotten) {
+                reply.line(escape_html(&line));
+            }
+            reply.send().await?;
         },
         Err(e) => {
-            ctx.send(CreateReply::default().ephemeral(true).embed(
-                CreateEmbed::default()
-                    .title("Tip")
-                    .field("An error occured while tipping", e.to_string(), false)
-                    .thumbnail(ICON)
-                    .colour(Colour::RED)
-                )
-            ).await?;
+            bot.send_message(msg.chat.id, format!("An error occured while deleting your data: {}", e)).await?;
         }
     };
 
     Ok(())
 }
 
-// Handler for telegram bot
-async fn telegram_handler(bot: Bot, msg: Message, cmd: TelegramCommand, state: WalletService) -> Result<(), Error> {
-    if !cmd.allow_public() && !msg.chat.is_private() {
-        let from = msg.from.ok_or(TelegramError::NoUser)?;
-        bot.send_message(from.id, "You can only use this command in private").await?;
+// Withdrawal of a Telegram user waiting for its PIN
+struct TelegramPendingWithdraw {
+    to: Address,
+    amount: AssetBalance
+}
+
+// Send a Telegram withdrawal and tell its user how it went
+async fn telegram_send_withdraw(bot: &Bot, msg: &Message, state: &WalletService, to: Address, amount: &AssetBalance, pin: Option<&str>) -> Result<(), Error> {
+    platform::withdraw(state, &TelegramPlatform::new(bot, state, msg, msg.thread_id)?, to, amount, None, pin).await
+}
+
+// Private messages that aren't commands, they answer the PIN asked by a withdrawal
+async fn telegram_pin_handler(bot: Bot, msg: Message, state: WalletService, pins: TelegramPinRequests) -> Result<(), Error> {
+    let Some(from) = msg.from.as_ref() else {
+        return Ok(());
+    };
+
+    let Some(pending) = pins.take_if(from.id.0, |_| true) else {
         return Ok(());
+    };
+
+    // The PIN shouldn't stay in the chat history
+    if let Err(e) = bot.delete_message(msg.chat.id, msg.id).await {
+        debug!("Couldn't delete the PIN message of {}: {}", from.id, e);
     }
 
-    let thread_id = msg.thread_id.filter(|_| msg.is_topic_message);
-    match cmd {
-        TelegramCommand::Start => {
-            TelegramMessage::new(&bot, msg.chat.id, thread_id)
-                .title("Welcome")
-                .field("Welcome to the XELIS Tip Bot!", "You can use /help to see the available commands", false)
-                .send().await?;
+    telegram_send_withdraw(&bot, &msg, &state, pending.to, &pending.amount, Some(msg.text().unwrap_or_default())).await
+}
+
+// Handler for telegram inline buttons
+async fn telegram_callback_handler(bot: Bot, q: CallbackQuery, state: WalletService, confirmations: TelegramConfirmations, pins: TelegramPinRequests, rains: TelegramRainPreviews, activity: TelegramActivity, notifier: TelegramNotifications) -> Result<(), Error> {
+    // Setup wizard buttons carry the group id and the setting to change
+    if let Some(data) = q.data.as_deref().and_then(|data| data.strip_prefix(SETUP_WIZARD_PREFIX)) {
+        return telegram_setup_callback(&bot, &q, &state, data).await;
+    }
+
+    if let Some(data) = q.data.as_deref().and_then(|data| data.strip_prefix(TIPME_BUTTON_PREFIX)) {
+        return telegram_tipme_callback(&bot, &q, &confirmations, data).await;
+    }
+
+    if let Some(data) = q.data.as_deref().and_then(|data| data.strip_prefix(REMINDER_TIP_PREFIX)) {
+        return telegram_reminder_tip_callback(&bot, &q, &state, &notifier, data).await;
+    }
+
+    let Some((action, id)) = q.data.as_deref().and_then(|data| data.split_once(':')) else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
+    };
+    let Ok(id) = id.parse::<u64>() else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
+    };
+
+    // Giveaway claims are answered with a popup so the group is not spammed
+    if action == GIVEAWAY_BUTTON_PREFIX.trim_end_matches(':') {
+        // The group may require some history from the users claiming
+        let user = UserApplication::Telegram(q.from.id.0);
+        let mut refused = None;
+        if let Some(chat_id) = q.message.as_ref().map(|message| message.chat().id) {
+            let community = Community::Telegram(chat_id.0);
+            let strictness = state.get_recipient_strictness(&community).await;
+            if !state.is_eligible_recipient(&community, &user, strictness, activity.message_count(chat_id.0, q.from.id.0)).await {
+                refused = Some(strictness);
+            }
         }
-        TelegramCommand::Help => {
-            bot.send_message(msg.chat.id, TelegramCommand::descriptions().to_string()).await?;
-        },
-        TelegramCommand::Status => {
-            let balance = state.get_wallet_balance().await?;
-            let total_balance = state.get_total_users_balance().await?;
-            let topoheight = state.get_wallet_topoheight().await?;
-            let network = state.network();
-            let online = state.is_wallet_online().await;
 
-            TelegramMessage::new(&bot, msg.chat.id, thread_id)
-                .title("Status")
-                .field("Wallet Balance", format_xelis(balance), false)
-                .field("Total Users Balance", format_xelis(total_balance), false)
-                .field("Synced TopoHeight", topoheight.to_string(), false)
-                .field("Network", network.to_string(), false)
-                .field("Is Online", online.to_string(), false)
-                .send().await?;
-        },
-        TelegramCommand::Balance => {
-            let from = msg.from.ok_or(TelegramError::NoUser)?;
-            let balance = state.get_balance_for_user(&UserApplication::Telegram(from.id.0)).await;
+        let text = match refused {
+            Some(strictness) => telegram_strictness_text(strictness),
+            None => match state.claim_giveaway(id, &user).await {
+                Ok(giveaway) => format!("You joined the giveaway ({}/{} claims)", giveaway.claims.len(), giveaway.max_claims),
+                Err(e) => format!("An error occured while claiming: {}", e)
+            }
+        };
 
-            TelegramMessage::new(&bot, msg.chat.id, thread_id)
-                .title("Balance")
-                .field("Your balance is", format_xelis(balance), false)
-                .send().await?;
-        },
-        TelegramCommand::Deposit => {
-            let from = msg.from.ok_or(TelegramError::NoUser)?;
-            let address = state.get_address_for_user(&UserApplication::Telegram(from.id.0));
+        bot.answer_callback_query(q.id.clone())
+            .text(text)
+            .show_alert(true)
+            .await?;
+        return Ok(());
+    }
 
-            TelegramMessage::new(&bot, msg.chat.id, thread_id)
-                .title("Deposit")
-                .field("Your deposit address is", InlineCode::new(&address.to_string()), false)
-                .field("Please do not send any other coins than XELIS to this address", "", false)
-                .send().await?;
-        },
-        TelegramCommand::Withdraw { address, amount } => {
-            let from = msg.from.ok_or(TelegramError::NoUser)?;
-            let to = match Address::from_string(&address) {
-                Ok(address) => address,
-                Err(e) => {
-                    bot.send_message(msg.chat.id, format!("An error occured while withdrawing: {}", e)).await?;
-                    return Ok(());
-                }    
-            };
+    // Challenges are settled in the group, errors are shown in a popup
+    if action == CHALLENGE_ACCEPT_PREFIX.trim_end_matches(':') || action == CHALLENGE_DECLINE_PREFIX.trim_end_matches(':') {
+        let user = UserApplication::Telegram(q.from.id.0);
+        let res = if action == CHALLENGE_ACCEPT_PREFIX.trim_end_matches(':') {
+            state.accept_challenge(id, &user).await.map(|result| (result.challenge.clone(), Some(result)))
+        } else {
+            state.cancel_challenge(id, &user).await.map(|challenge| (challenge, None))
+        };
 
-            if to.is_mainnet() != state.network().is_mainnet() {
-                bot.send_message(msg.chat.id, "An error occured while withdrawing: Invalid network").await?;
+        let (challenge, result) = match res {
+            Ok(res) => res,
+            Err(e) => {
+                bot.answer_callback_query(q.id.clone())
+                    .text(e.to_string())
+                    .show_alert(true)
+                    .await?;
                 return Ok(());
             }
+        };
 
-            let amount = match from_xelis(amount.to_string()) {
-                Some(amount) => amount,
-                None => {
-                    bot.send_message(msg.chat.id, "An error occured while withdrawing: Invalid amount").await?;
-                    return Ok(());
-                }
-            };
+        bot.answer_callback_query(q.id.clone()).await?;
+        let Some(message) = q.regular_message() else {
+            return Ok(());
+        };
+        bot.edit_message_reply_markup(message.chat.id, message.id).await?;
 
-            match state.withdraw(&UserApplication::Telegram(from.id.0), to, amount).await {
-                Ok(hash) => {
-                    TelegramMessage::new(&bot, msg.chat.id, msg.thread_id)
-                        .title("Withdraw")
-                        .field("You have withdrawn", format!("{} XEL", format_xelis(amount)), false)
-                        .field("Transaction", InlineCode::new(&hash.to_string()), false)
-                        .send().await?;
-                },
-                Err(e) => {
-                    bot.send_message(msg.chat.id, format!("An error occured while withdrawing: {}", e)).await?;
-                }
-            };
-        },
-        TelegramCommand::Tip { amount } => {
-            let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
-            let dm = from.id;
-            let amount = match from_xelis(amount.to_string()) {
-                Some(amount) => amount,
-                None => {
-                    debug!("Invalid amount");
-                    bot.send_message(dm, "An error occured while tipping: Invalid amount").await?;
+        let thread_id = message.thread_id.filter(|_| message.is_topic_message);
+        let mut reply = TelegramMessage::new(&bot, message.chat.id, thread_id);
+        reply.title(game_name(challenge.game));
+        match result {
+            Some(result) => {
+                let UserApplication::Telegram(winner) = result.winner else {
                     return Ok(());
-                }
-            };
-
-            let to = msg.reply_to_message().and_then(|m| m.from.as_ref()).ok_or(TelegramError::NoUser)?;
+                };
+                let winner = UserId(winner);
+                let name = match bot.get_chat_member(message.chat.id, winner).await {
+                    Ok(member) => member.user.full_name(),
+                    Err(_) => winner.to_string()
+                };
 
-            if to.is_bot || to.is_anonymous() || to.is_channel() {
-                debug!("Invalid user");
-                bot.send_message(dm, "An error occured while tipping: Invalid user").await?;
-                return Ok(());
+                reply.line(format!("{} won {} XEL", user_mention(winner, &name), format_xelis(result.prize)))
+                    .field("Result", outcome_text(&result.outcome), true);
+            },
+            None => {
+                reply.line(format!("Challenge cancelled by {}, stake refunded", user_mention(q.from.id, &q.from.full_name())));
             }
+        };
 
-            match state.transfer(&UserApplication::Telegram(from.id.0), &UserApplication::Telegram(to.id.0), amount).await {
-                Ok(()) => {
-                    
-                    debug!("Tipped {} XEL to {} (chat id: {}, thread: {:?})", format_xelis(amount), to.id, msg.chat.id, thread_id);
-                    TelegramMessage::new(&bot, msg.chat.id, thread_id)
-                        .title("Tip")
-                        .field("You have tipped", format!("{} XEL", format_xelis(amount)), false)
-                        .field("To", format!("{} ({})", to.username.as_ref().unwrap_or(&to.first_name), to.id), false)
-                        .send().await?;
-                },
-                Err(e) => {
-                    debug!("An error occured while tipping: {}", e);
-                    bot.send_message(dm, format!("An error occured while tipping: {}", e)).await?;
-                }
-            };
+        reply.field("Seed", InlineCode::new(&challenge.seed().to_string()), false)
+            .field("Id", challenge.id.to_string(), true)
+            .send().await?;
+
+        return Ok(());
+    }
+
+    bot.answer_callback_query(q.id.clone()).await?;
+
+    // Rain previews keep their recipients, only the author can confirm them
+    if action == RAIN_CONFIRM_PREFIX.trim_end_matches(':') || action == RAIN_CANCEL_PREFIX.trim_end_matches(':') {
+        let Some(pending) = rains.take_if(id, |pending| pending.msg.from.as_ref().is_some_and(|user| user.id == q.from.id)) else {
+            bot.send_message(q.from.id, "This confirmation has expired").await?;
+            return Ok(());
+        };
+
+        if let Some(message) = &q.message {
+            bot.edit_message_reply_markup(message.chat().id, message.id()).await?;
         }
+
+        if action == RAIN_CANCEL_PREFIX.trim_end_matches(':') {
+            bot.send_message(q.from.id, "Command has been cancelled").await?;
+            return Ok(());
+        }
+
+        return telegram_send_rain(&bot, &pending.msg, &state, &pending.recipients, pending.share).await;
     }
 
-    Ok(())
-}
\ No newline at end of file
+    // Only the author of the command can confirm it
+    let pending = confirmations.take_if(id, |(msg, _)| msg.from.as_ref().is_some_and(|user| user.id == q.from.id));
+    let Some((msg, cmd)) = pending else {
+        bot.send_message(q.from.id, "This confirmation has expired").await?;
+        return Ok(());
+    };
+
+    // Remove the buttons from the confirmation message
+    if let Some(message) = &q.message {
+        bot.edit_message_reply_markup(message.chat().id, message.id()).await?;
+    }
+
+    match action {
+        "confirm" => telegram_handler(bot, msg, cmd, state, confirmations, pins, rains, activity, notifier).await,
+        "overbudget" => {
+            state.approve_budget_overrun(&UserApplication::Telegram(q.from.id.0));
+            telegram_handler(bot, msg, cmd, state, confirmations, pins, rains, activity, notifier).await
+        },
+        "forget" => match cmd {
+            TelegramCommand::Forgetme { args } => telegram_forget_user(&bot, &msg, &state, &args).await,
+            _ => Ok(())
+        },
+        "startgiveaway" => match cmd {
+            TelegramCommand::Giveaway { amount, duration, max_claims } => telegram_start_giveaway(&bot, &msg, &state, parse_amount(&amount)?.value, duration, max_claims).await,
+            _ => Ok(())
+        },
+        _ => {
+            bot.send_message(q.from.id, "Command has been cancelled").await?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::time::Duration;
+
+use anyhow::{Context as _, Error, Result};
+use log::{debug, error, info, warn};
+use matrix_sdk::{
+    config::SyncSettings,
+    event_handler::Ctx,
+    ruma::{
+        events::room::{
+            member::StrippedRoomMemberEvent,
+            message::{MessageType, OriginalSyncRoomMessageEvent}
+        },
+        UserId
+    },
+    Client,
+    Room,
+    RoomState
+};
+use tokio::task::JoinHandle;
+
+use crate::{
+    command_amount_args,
+    parse_asset_amount,
+    parse_withdraw_amount,
+    platform::{self, ChatPlatform, MatrixPlatform, Reply},
+    service::{UserApplication, WalletService},
+    DEPOSITS_PAUSED_MESSAGE,
+    DEPOSITS_PAUSED_TITLE
+};
+
+// Commands answered on Matrix, they start with a ! as Matrix has no bot commands
+const MATRIX_COMMANDS: [(&str, &str); 6] = [
+    ("!help", "Show the available commands"),
+    ("!balance", "Show your balance"),
+    ("!deposit", "Show your deposit address"),
+    ("!tip <@user:server> <amount> [asset]", "Tip a Matrix user"),
+    ("!withdraw <address|label> <amount> [asset]", "Withdraw to an address"),
+    ("!reminders [on|off]", "Enable or disable the reminders of your unused balance")
+];
+
+// Wait before syncing again once the homeserver couldn't be reached
+const SYNC_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct MatrixConfig {
+    // URL of the homeserver, such as https://matrix.org
+    pub homeserver: String,
+    // Matrix user ID or localpart of the bot account
+    pub user: String,
+    pub password: String
+}
+
+pub struct MatrixBot {
+    client: Client
+}
+
+impl MatrixBot {
+    // Log in at startup, so wrong credentials stop the bot like a wrong token
+    pub async fn login(config: MatrixConfig) -> Result<Self> {
+        let client = Client::builder()
+            .homeserver_url(&config.homeserver)
+            .build().await
+            .context("Couldn't reach the Matrix homeserver, check --matrix-homeserver")?;
+
+        client.matrix_auth()
+            .login_username(&config.user, &config.password)
+            .initial_device_display_name("XELIS Tip Bot")
+            .await
+            .context("Couldn't log in to Matrix, check --matrix-user and --matrix-password")?;
+
+        info!("Logged in to Matrix as {}", config.user);
+        Ok(Self { client })
+    }
+
+    // Used by the service to send the DMs
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+
+    // Answer the commands of the rooms the bot is in
+    pub fn start(self, service: WalletService) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            // Like Telegram, only the leader answers the commands
+            while !service.is_leader() {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+
+            // The messages sent while the bot was offline are skipped
+            let mut token = loop {
+                match self.client.sync_once(SyncSettings::default()).await {
+                    Ok(response) => break response.next_batch,
+                    Err(e) => {
+                        let e = Error::from(e);
+                        service.api_errors().record_error(&e);
+                        warn!("Couldn't sync with the Matrix homeserver: {:#}", e);
+                        tokio::time::sleep(SYNC_RETRY_DELAY).await;
+                    }
+                }
+            };
+
+            self.client.add_event_handler_context(service.clone());
+            self.client.add_event_handler(on_invite);
+            self.client.add_event_handler(on_message);
+
+            loop {
+                let settings = SyncSettings::default().token(token.clone());
+                match self.client.sync(settings).await {
+                    Ok(()) => break,
+                    Err(e) => {
+                        let e = Error::from(e);
+                        service.api_errors().record_error(&e);
+                        error!("The Matrix sync stopped: {:#}", e);
+                        tokio::time::sleep(SYNC_RETRY_DELAY).await;
+                    }
+                }
+
+                // Resume from the last sync, the events already handled aren't received again
+                if let Some(next) = self.client.sync_token().await {
+                    token = next;
+                }
+            }
+        })
+    }
+}
+
+// The bot joins the rooms it's invited to, including the DMs
+async fn on_invite(event: StrippedRoomMemberEvent, client: Client, room: Room) {
+    if client.user_id() != Some(&*event.state_key) || room.state() != RoomState::Invited {
+        return;
+    }
+
+    debug!("Joining the Matrix room {} on the invite of {}", room.room_id(), event.sender);
+    if let Err(e) = room.join().await {
+        warn!("Couldn't join the Matrix room {}: {}", room.room_id(), e);
+    }
+}
+
+async fn on_message(event: OriginalSyncRoomMessageEvent, room: Room, client: Client, service: Ctx<WalletService>) {
+    if room.state() != RoomState::Joined || client.user_id() == Some(&*event.sender) {
+        return;
+    }
+
+    let MessageType::Text(text) = &event.content.msgtype else {
+        return;
+    };
+
+    if let Err(e) = handle_command(&service, &room, &event, &text.body).await {
+        service.api_errors().record_error(&e);
+        error!("Error while answering the Matrix command of {}: {:?}", event.sender, e);
+    }
+}
+
+async fn handle_command(service: &WalletService, room: &Room, event: &OriginalSyncRoomMessageEvent, text: &str) -> Result<(), Error> {
+    let mut args = text.split_whitespace();
+    let Some(command) = args.next().filter(|command| command.starts_with('!')).map(str::to_lowercase) else {
+        return Ok(());
+    };
+
+    if !MATRIX_COMMANDS.iter().any(|(usage, _)| usage.split(' ').next() == Some(command.as_str())) {
+        return Ok(());
+    }
+
+    // Known before the user gets a balance, so it can be sent DMs
+    let user = service.record_matrix_user(&event.sender).await?;
+    let platform = MatrixPlatform::new(service, room, &event.sender, &event.event_id);
+    if let Some(limited) = service.check_rate_limit(&user, &[], &command).await {
+        // Answer a flood only once, in private
+        if !limited.notified {
+            platform.send(Reply::error("Rate limit", "Please wait", limited)).await?;
+        }
+        return Ok(());
+    }
+
+    if let Err(e) = service.record_user_activity(&user).await {
+        warn!("Couldn't record activity of {}: {}", event.sender, e);
+    }
+
+    match command.as_str() {
+        "!help" => {
+            let mut reply = Reply::new("Help");
+            for (usage, description) in MATRIX_COMMANDS {
+                reply = reply.field(usage, description, false);
+            }
+            platform.send(reply).await
+        },
+        "!balance" => platform::balance(service, &platform).await,
+        "!deposit" => {
+            let address = service.get_address_for_user(&user);
+            let mut reply = Reply::new("Deposit")
+                .description("Please do not send any other coins than XELIS to this address")
+                .code_field("Your deposit address is", address.to_string());
+
+            if service.are_deposits_paused().await {
+                reply = reply.field(DEPOSITS_PAUSED_TITLE, DEPOSITS_PAUSED_MESSAGE, false);
+            }
+
+            platform.send(reply.private()).await
+        },
+        "!tip" => {
+            let (Some(to), Some(amount)) = (args.next(), args.next()) else {
+                return platform.send(Reply::error("Tip", "Usage", "!tip <@user:server> <amount> [asset]")).await;
+            };
+
+            let to = match UserId::parse(to) {
+                Ok(to) => to,
+                Err(_) => return platform.send(Reply::error("Tip", "An error occured while tipping", format!("{} isn't a Matrix user ID, such as @alice:matrix.org", to))).await
+            };
+
+            let amount = match parse_asset_amount(service, &user, args.next(), amount).await {
+                Ok(amount) if amount.rounded || amount.conversion.is_some() => {
+                    let command = format!("!tip {} {}", to, command_amount_args(&amount.amount));
+                    return platform.send(platform::confirm_amount("Tip", &amount.amount, &command)).await;
+                },
+                Ok(amount) => amount.amount,
+                Err(e) => return platform.send(Reply::error("Tip", "An error occured while tipping", e)).await
+            };
+
+            let recipient = match service.record_matrix_user(&to).await {
+                Ok(recipient) => recipient,
+                Err(e) => return platform.send(Reply::error("Tip", "An error occured while tipping", e)).await
+            };
+
+            platform::tip(service, &platform, &recipient, to.as_str(), &amount, None).await
+        },
+        "!withdraw" => {
+            let (Some(address), Some(amount)) = (args.next(), args.next()) else {
+                return platform.send(Reply::error("Withdraw", "Usage", "!withdraw <address|label> <amount> [asset]")).await;
+            };
+
+            let to = match service.resolve_address(&user, address).await {
+                Ok(to) => to,
+                Err(e) => return platform.send(Reply::error("Withdraw", "An error occured while withdrawing", e)).await
+            };
+
+            let amount = match parse_withdraw_amount(service, &user, &to, args.next(), amount).await {
+                Ok(amount) if amount.rounded || amount.conversion.is_some() => {
+                    let command = format!("!withdraw {} {}", address, command_amount_args(&amount.amount));
+                    return platform.send(platform::confirm_amount("Withdraw", &amount.amount, &command)).await;
+                },
+                Ok(amount) => amount.amount,
+                Err(e) => return platform.send(Reply::error("Withdraw", "An error occured while withdrawing", e)).await
+            };
+
+            platform::withdraw(service, &platform, to, &amount, None, None).await
+        },
+        "!reminders" => reminders(service, &platform, &user, args.next()).await,
+        _ => Ok(())
+    }
+}
+
+async fn reminders(service: &WalletService, platform: &MatrixPlatform<'_>, user: &UserApplication, enabled: Option<&str>) -> Result<(), Error> {
+    match enabled.map(str::to_lowercase).as_deref() {
+        Some("on") => service.set_reminders_enabled(user, true).await?,
+        Some("off") => service.set_reminders_enabled(user, false).await?,
+        None => {},
+        Some(_) => return platform.send(Reply::error("Reminders", "Usage", "!reminders [on|off]")).await
+    }
+
+    let description = if service.are_reminders_enabled(user).await {
+        "You will be reminded when your balance is left unused for a long time"
+    } else {
+        "You won't be reminded of your unused balance"
+    };
+
+    platform.send(Reply::new("Reminders").description(description)).await
+}
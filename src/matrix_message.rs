@@ -0,0 +1,55 @@
+use matrix_sdk::ruma::events::room::message::RoomMessageEventContent;
+
+use crate::telegram_message::escape_html;
+
+// Message sent on Matrix, with a plain body for the clients that don't render HTML
+// Its texts are plain, they're escaped in the HTML body
+#[derive(Default)]
+pub struct MatrixMessage {
+    title: Option<String>,
+    // Plain and HTML version of each line
+    lines: Vec<(String, String)>
+}
+
+impl MatrixMessage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, text: &str) -> Self {
+        self.title = Some(text.to_string());
+        self
+    }
+
+    pub fn line(mut self, text: &str) -> Self {
+        self.lines.push((text.to_string(), escape_html(text)));
+        self
+    }
+
+    pub fn field(mut self, name: &str, value: &str) -> Self {
+        self.lines.push((format!("{}: {}", name, value), format!("<strong>{}</strong><br>{}", escape_html(name), escape_html(value))));
+        self
+    }
+
+    // Value shown in a monospace font, such as an address
+    pub fn code_field(mut self, name: &str, value: &str) -> Self {
+        self.lines.push((format!("{}: {}", name, value), format!("<strong>{}</strong><br><code>{}</code>", escape_html(name), escape_html(value))));
+        self
+    }
+
+    pub fn into_content(self) -> RoomMessageEventContent {
+        let mut plain = Vec::new();
+        let mut html = Vec::new();
+        if let Some(title) = self.title {
+            html.push(format!("<h4>{}</h4>", escape_html(&title)));
+            plain.push(title);
+        }
+
+        for (text, formatted) in self.lines {
+            plain.push(text);
+            html.push(format!("<p>{}</p>", formatted));
+        }
+
+        RoomMessageEventContent::text_html(plain.join("\n\n"), html.join(""))
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+use xelis_common::{
+    config::XELIS_ASSET,
+    crypto::{hash, Hash},
+    utils::format_coin
+};
+
+use crate::{
+    amount::{parse_amount, Amount},
+    service::{parse_matrix_user, parse_slack_user, AssetBalance, UserApplication}
+};
+
+// Columns read by the import, other columns are ignored
+const PLATFORM_COLUMN: &str = "platform";
+const USER_COLUMN: &str = "user_id";
+// The export names the amount column balance
+const AMOUNT_COLUMNS: [&str; 2] = ["amount", "balance"];
+// Optional, rows of other assets than XEL are skipped
+const ASSET_COLUMN: &str = "asset";
+
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error("Checksum mismatch, expected {} but the file has {}", .0, .1)]
+    ChecksumMismatch(Hash, Hash),
+    #[error("The file has no header")]
+    Empty,
+    #[error("Missing column {}", .0)]
+    MissingColumn(&'static str),
+    #[error("Line {}: {}", .0, .1)]
+    InvalidRow(usize, String),
+    #[error("Line {}: {:?} is already on line {}", .0, .1, .2)]
+    Duplicate(usize, UserApplication, usize)
+}
+
+// Balances read from a CSV file, validated but not credited yet
+#[derive(Debug, Clone)]
+pub struct BalanceImport {
+    // Hash of the file, an import is refused if a file with the same checksum was already imported
+    pub checksum: Hash,
+    pub balances: Vec<(UserApplication, u64)>,
+    // Rows of a zero balance or of another asset than XEL
+    pub skipped: usize
+}
+
+impl BalanceImport {
+    // Total XEL credited by the import, None if it overflows
+    pub fn total(&self) -> Option<u64> {
+        self.balances.iter().try_fold(0u64, |total, (_, amount)| total.checked_add(*amount))
+    }
+}
+
+// Quote a CSV field if it contains a separator, a quote or a line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Export the balances of the users for accounting or a migration
+// The last activity is a timestamp in seconds, empty if the user was never seen
+pub fn balances_csv(balances: &[(UserApplication, AssetBalance, Option<u64>)]) -> String {
+    let mut csv = String::from("platform,user_id,asset,asset_name,balance,last_activity\n");
+    for (user, balance, last_seen) in balances {
+        let (platform, id) = match user {
+            UserApplication::Telegram(id) => ("telegram", id),
+            UserApplication::Discord(id) => ("discord", id),
+            UserApplication::Matrix(id) => ("matrix", id),
+            UserApplication::Slack(id) => ("slack", id),
+            UserApplication::Twitch(id) => ("twitch", id)
+        };
+
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            platform,
+            id,
+            balance.asset,
+            csv_field(&balance.name),
+            format_coin(balance.amount, balance.decimals),
+            last_seen.map(|timestamp| timestamp.to_string()).unwrap_or_default()
+        ));
+    }
+
+    csv
+}
+
+// Split a CSV line in its fields, a quoted field can contain commas and doubled quotes
+// Line breaks inside a field are not supported
+fn split_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.next_if_eq(&'"').is_some() {
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            },
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c)
+        }
+    }
+
+    if quoted {
+        return Err("unterminated quoted field".to_string());
+    }
+    fields.push(field);
+
+    Ok(fields)
+}
+
+fn parse_user(platform: &str, id: &str) -> Result<UserApplication, String> {
+    let invalid = || format!("invalid user id {}", id);
+    match platform.to_lowercase().as_str() {
+        "telegram" => Ok(UserApplication::Telegram(id.parse().map_err(|_| invalid())?)),
+        "discord" => Ok(UserApplication::Discord(id.parse().map_err(|_| invalid())?)),
+        "matrix" => parse_matrix_user(id).ok_or_else(invalid),
+        "slack" => parse_slack_user(id).ok_or_else(invalid),
+        "twitch" => Ok(UserApplication::Twitch(id.parse().map_err(|_| invalid())?)),
+        _ => Err(format!("platform must be telegram, discord, matrix, slack or twitch, not {}", platform))
+    }
+}
+
+// Read the balances to import from a CSV file with a header
+// The platform, user_id and amount (or balance) columns are required, so the files
+// written by the export can be imported as is
+// Nothing is returned if a single row is invalid or a user appears twice
+pub fn parse_balances_csv(content: &[u8], expected: Option<&Hash>) -> Result<BalanceImport, MigrationError> {
+    let checksum = hash(content);
+    if let Some(expected) = expected {
+        if *expected != checksum {
+            return Err(MigrationError::ChecksumMismatch(expected.clone(), checksum));
+        }
+    }
+
+    let content = String::from_utf8_lossy(content);
+    let mut lines = content.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+
+    let (_, header) = lines.next().ok_or(MigrationError::Empty)?;
+    let header = split_csv_line(header.trim_start_matches('\u{feff}'))
+        .map_err(|e| MigrationError::InvalidRow(1, e))?;
+    let column = |name: &str| header.iter().position(|column| column.trim().eq_ignore_ascii_case(name));
+
+    let platform_column = column(PLATFORM_COLUMN).ok_or(MigrationError::MissingColumn(PLATFORM_COLUMN))?;
+    let user_column = column(USER_COLUMN).ok_or(MigrationError::MissingColumn(USER_COLUMN))?;
+    let amount_column = AMOUNT_COLUMNS.iter()
+        .find_map(|name| column(name))
+        .ok_or(MigrationError::MissingColumn(AMOUNT_COLUMNS[0]))?;
+    let asset_column = column(ASSET_COLUMN);
+
+    let xelis_asset = XELIS_ASSET.to_string();
+    let mut seen: HashMap<UserApplication, usize> = HashMap::new();
+    let mut balances = Vec::new();
+    let mut skipped = 0;
+    for (line, row) in lines {
+        let fields = split_csv_line(row).map_err(|e| MigrationError::InvalidRow(line, e))?;
+        let field = |index: usize| fields.get(index).map(|field| field.trim()).unwrap_or_default();
+
+        let user = parse_user(field(platform_column), field(user_column))
+            .map_err(|e| MigrationError::InvalidRow(line, e))?;
+        let amount = parse_amount(field(amount_column))
+            .and_then(Amount::exact)
+            .map_err(|e| MigrationError::InvalidRow(line, e.to_string()))?;
+
+        // A user can hold several assets, only its XEL row counts as a duplicate
+        let asset = asset_column.map(field).unwrap_or_default();
+        if !asset.is_empty() && asset != xelis_asset && !asset.eq_ignore_ascii_case("xel") {
+            skipped += 1;
+            continue;
+        }
+
+        if let Some(first) = seen.insert(user, line) {
+            return Err(MigrationError::Duplicate(line, user, first));
+        }
+
+        if amount == 0 {
+            skipped += 1;
+            continue;
+        }
+
+        balances.push((user, amount));
+    }
+
+    Ok(BalanceImport {
+        checksum,
+        balances,
+        skipped
+    })
+}
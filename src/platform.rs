@@ -0,0 +1,592 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use log::{debug, warn};
+use matrix_sdk::{
+    ruma::{EventId, UserId},
+    Room
+};
+use poise::{serenity_prelude::{Colour, CreateEmbed}, CreateReply};
+use serde_json::json;
+use teloxide::{
+    types::{Message, Recipient, ThreadId, User},
+    Bot
+};
+use xelis_common::crypto::Address;
+
+use crate::{
+    matrix_message::MatrixMessage,
+    mirror_discord_announcement,
+    mirror_telegram_announcement,
+    price::{format_fiat, Conversion},
+    service::{matrix_id, slack_community, slack_id, AssetBalance, Community, OperationId, ServiceError, Setting, UserApplication, WalletService},
+    slack::SlashCommand,
+    slack_api::SlackApi,
+    slack_message::SlackMessage,
+    telegram_message::{escape_html, InlineCode, TelegramMessage},
+    twitch_api::TwitchApi,
+    twitch_irc::{ChatMessage, TwitchChat},
+    twitch_message::TwitchMessage,
+    Context,
+    TelegramError,
+    TelegramNotifications,
+    COLOR,
+    ICON
+};
+
+// Outcome of a reply, shown as the colour of the embed on Discord
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyKind {
+    Success,
+    // Accepted but not done yet, such as a queued withdrawal
+    Pending,
+    Error
+}
+
+#[derive(Debug, Clone)]
+struct ReplyField {
+    name: String,
+    value: String,
+    inline: bool,
+    // Shown in a monospace font, such as a TX hash
+    code: bool
+}
+
+// Transfer mirrored in the announcement channel of the community
+#[derive(Debug, Clone)]
+struct Announcement {
+    to: String,
+    amount: AssetBalance
+}
+
+// Answer of a command, built once and rendered by each platform
+// Its texts are plain, they're escaped when sent on Telegram
+#[derive(Debug, Clone)]
+pub struct Reply {
+    title: String,
+    description: Option<String>,
+    fields: Vec<ReplyField>,
+    kind: ReplyKind,
+    // Only shown to the author of the command
+    private: bool,
+    announcement: Option<Announcement>
+}
+
+impl Reply {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            description: None,
+            fields: Vec::new(),
+            kind: ReplyKind::Success,
+            private: false,
+            announcement: None
+        }
+    }
+
+    // Accepted but not done yet, the description tells what happens next
+    pub fn pending(title: &str, description: impl Into<String>) -> Self {
+        let mut reply = Self::new(title).description(description);
+        reply.kind = ReplyKind::Pending;
+        reply
+    }
+
+    // Errors are always private, the others in the channel don't need to see them
+    pub fn error(title: &str, context: &str, error: impl ToString) -> Self {
+        let mut reply = Self::new(title).field(context, error.to_string(), false).private();
+        reply.kind = ReplyKind::Error;
+        reply
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn field(mut self, name: &str, value: impl Into<String>, inline: bool) -> Self {
+        self.fields.push(ReplyField { name: name.to_string(), value: value.into(), inline, code: false });
+        self
+    }
+
+    pub fn code_field(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.fields.push(ReplyField { name: name.to_string(), value: value.into(), inline: false, code: true });
+        self
+    }
+
+    // Value of an amount typed in a fiat currency
+    pub fn conversion(self, conversion: Option<&Conversion>) -> Self {
+        match conversion {
+            Some(conversion) => self.field("Value", format!("{} {}", format_fiat(conversion.fiat), conversion.currency), true)
+                .field("Rate", format!("1 XEL = {} {}", format_fiat(conversion.price), conversion.currency), true),
+            None => self
+        }
+    }
+
+    pub fn private(mut self) -> Self {
+        self.private = true;
+        self
+    }
+
+    pub fn private_if(self, private: bool) -> Self {
+        if private { self.private() } else { self }
+    }
+
+    pub fn announce(mut self, to: impl Into<String>, amount: &AssetBalance) -> Self {
+        self.announcement = Some(Announcement { to: to.into(), amount: amount.clone() });
+        self
+    }
+
+    fn to_embed(&self) -> CreateEmbed {
+        let colour = match self.kind {
+            ReplyKind::Success => Colour::from(COLOR),
+            ReplyKind::Pending => Colour::ORANGE,
+            ReplyKind::Error => Colour::RED
+        };
+
+        let mut embed = CreateEmbed::default()
+            .title(self.title.as_str())
+            .thumbnail(ICON)
+            .colour(colour);
+
+        if let Some(description) = self.description.as_ref() {
+            embed = embed.description(description.as_str());
+        }
+
+        for field in self.fields.iter() {
+            let value = if field.code { format!("`{}`", field.value) } else { field.value.clone() };
+            embed = embed.field(field.name.as_str(), value, field.inline);
+        }
+
+        embed
+    }
+
+    fn telegram_value(field: &ReplyField) -> String {
+        let value = escape_html(&field.value);
+        if field.code { InlineCode::new(&value).to_string() } else { value }
+    }
+
+    fn to_telegram<'a>(&self, bot: &'a Bot, chat_id: impl Into<Recipient>, thread_id: Option<ThreadId>) -> TelegramMessage<'a> {
+        let mut message = TelegramMessage::new(bot, chat_id, thread_id);
+        message.title(&escape_html(&self.title));
+        if let Some(description) = self.description.as_ref() {
+            message.line(escape_html(description));
+        }
+
+        for field in self.fields.iter() {
+            message.field(&escape_html(&field.name), Self::telegram_value(field), field.inline);
+        }
+
+        message
+    }
+
+    // Body of the Telegram message, the title is sent apart
+    fn to_telegram_line(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(description) = self.description.as_ref() {
+            lines.push(escape_html(description));
+        }
+
+        for field in self.fields.iter() {
+            lines.push(format!("<strong>{}</strong> {}", escape_html(&field.name), Self::telegram_value(field)));
+        }
+
+        lines.join("\n")
+    }
+
+    fn to_matrix(&self) -> MatrixMessage {
+        let mut message = MatrixMessage::new().title(&self.title);
+        if let Some(description) = self.description.as_ref() {
+            message = message.line(description);
+        }
+
+        for field in self.fields.iter() {
+            message = if field.code {
+                message.code_field(&field.name, &field.value)
+            } else {
+                message.field(&field.name, &field.value)
+            };
+        }
+
+        message
+    }
+
+    fn to_twitch(&self) -> TwitchMessage {
+        let mut message = TwitchMessage::new().title(&self.title);
+        if let Some(description) = self.description.as_ref() {
+            message = message.line(description);
+        }
+
+        for field in self.fields.iter() {
+            message = message.field(&field.name, &field.value);
+        }
+
+        message
+    }
+
+    fn to_slack(&self) -> SlackMessage {
+        let mut message = SlackMessage::new().title(&self.title);
+        if let Some(description) = self.description.as_ref() {
+            message = message.line(description);
+        }
+
+        for field in self.fields.iter() {
+            message = if field.code {
+                message.code_field(&field.name, &field.value)
+            } else {
+                message.field(&field.name, &field.value)
+            };
+        }
+
+        message
+    }
+}
+
+// Where a command was used, each platform only has to tell who used it and how to answer
+// The commands themselves are written once below, after the platform parsed and confirmed their arguments
+#[async_trait]
+pub trait ChatPlatform: Send + Sync {
+    // Author of the command
+    fn author(&self) -> UserApplication;
+
+    // How the author is shown to the others, a mention when the platform has them
+    fn author_name(&self) -> String;
+
+    // Group the command was used in, None in private
+    fn community(&self) -> Option<Community>;
+
+    // Same for every delivery of the command, so it's executed once
+    fn operation(&self, command: &str) -> OperationId;
+
+    // Answer the command, in its channel or only to its author if the reply is private
+    async fn send(&self, reply: Reply) -> Result<(), Error>;
+}
+
+pub struct DiscordPlatform<'a> {
+    ctx: Context<'a>
+}
+
+impl<'a> DiscordPlatform<'a> {
+    pub fn new(ctx: Context<'a>) -> Self {
+        Self { ctx }
+    }
+}
+
+#[async_trait]
+impl ChatPlatform for DiscordPlatform<'_> {
+    fn author(&self) -> UserApplication {
+        UserApplication::Discord(self.ctx.author().id.into())
+    }
+
+    fn author_name(&self) -> String {
+        self.ctx.author().to_string()
+    }
+
+    fn community(&self) -> Option<Community> {
+        self.ctx.guild_id().map(|id| Community::Discord(id.get()))
+    }
+
+    // Discord delivers the same interaction again if it didn't get an answer in time
+    fn operation(&self, command: &str) -> OperationId {
+        OperationId::discord(command, self.ctx.id())
+    }
+
+    // Private replies are ephemeral, in DM they're private anyway
+    async fn send(&self, reply: Reply) -> Result<(), Error> {
+        let embed = reply.to_embed();
+        let ephemeral = reply.private && self.ctx.guild_id().is_some();
+        self.ctx.send(CreateReply::default().ephemeral(ephemeral).embed(embed.clone())).await?;
+
+        if reply.announcement.is_some() {
+            mirror_discord_announcement(self.ctx, embed).await;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct TelegramPlatform<'a> {
+    bot: &'a Bot,
+    state: &'a WalletService,
+    msg: &'a Message,
+    from: &'a User,
+    thread_id: Option<ThreadId>,
+    // Busy groups may rate limit us, the notifier batches the public replies if needed
+    notifier: Option<&'a TelegramNotifications>
+}
+
+impl<'a> TelegramPlatform<'a> {
+    pub fn new(bot: &'a Bot, state: &'a WalletService, msg: &'a Message, thread_id: Option<ThreadId>) -> Result<Self, TelegramError> {
+        let from = msg.from.as_ref().ok_or(TelegramError::NoUser)?;
+        Ok(Self {
+            bot,
+            state,
+            msg,
+            from,
+            thread_id,
+            notifier: None
+        })
+    }
+
+    pub fn notifier(mut self, notifier: &'a TelegramNotifications) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+}
+
+#[async_trait]
+impl ChatPlatform for TelegramPlatform<'_> {
+    fn author(&self) -> UserApplication {
+        UserApplication::Telegram(self.from.id.0)
+    }
+
+    fn author_name(&self) -> String {
+        self.from.username.as_ref().unwrap_or(&self.from.first_name).clone()
+    }
+
+    fn community(&self) -> Option<Community> {
+        if self.msg.chat.is_private() {
+            return None;
+        }
+
+        Some(Community::Telegram(self.msg.chat.id.0))
+    }
+
+    // Telegram delivers the same update again if the bot didn't acknowledge it
+    fn operation(&self, command: &str) -> OperationId {
+        OperationId::telegram(command, self.msg.chat.id, self.msg.id)
+    }
+
+    // Private replies of a group command are sent in DM, Telegram has no ephemeral messages
+    async fn send(&self, reply: Reply) -> Result<(), Error> {
+        if reply.private && !self.msg.chat.is_private() {
+            // The author may never have started a conversation with the bot
+            if let Err(e) = reply.to_telegram(self.bot, self.from.id, None).send().await {
+                warn!("Couldn't send the {} reply to {} in private: {}", reply.title, self.from.id, e);
+            }
+        } else {
+            match self.notifier.filter(|_| reply.kind == ReplyKind::Success) {
+                Some(notifier) => notifier.notify(self.msg.chat.id, self.thread_id, &escape_html(&reply.title), reply.to_telegram_line()).await,
+                None => {
+                    reply.to_telegram(self.bot, self.msg.chat.id, self.thread_id).send().await?;
+                }
+            }
+        }
+
+        if let Some(announcement) = reply.announcement {
+            mirror_telegram_announcement(self.bot, self.state, self.msg, &reply.title, announcement.to, &announcement.amount).await;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct MatrixPlatform<'a> {
+    service: &'a WalletService,
+    room: &'a Room,
+    sender: &'a UserId,
+    event_id: &'a EventId
+}
+
+impl<'a> MatrixPlatform<'a> {
+    pub fn new(service: &'a WalletService, room: &'a Room, sender: &'a UserId, event_id: &'a EventId) -> Self {
+        Self { service, room, sender, event_id }
+    }
+}
+
+#[async_trait]
+impl ChatPlatform for MatrixPlatform<'_> {
+    fn author(&self) -> UserApplication {
+        UserApplication::Matrix(matrix_id(self.sender))
+    }
+
+    fn author_name(&self) -> String {
+        self.sender.to_string()
+    }
+
+    // Matrix rooms aren't communities, their settings can't be changed
+    fn community(&self) -> Option<Community> {
+        None
+    }
+
+    // The homeserver may send the same event again after a sync is interrupted
+    fn operation(&self, command: &str) -> OperationId {
+        OperationId::matrix(command, self.event_id.as_str())
+    }
+
+    // Private replies of a command used in a room with others are sent in the DM room of the author
+    async fn send(&self, reply: Reply) -> Result<(), Error> {
+        let content = reply.to_matrix().into_content();
+        if reply.private && self.room.joined_members_count() > 2 {
+            match self.service.get_matrix_dm_room(self.sender).await {
+                Ok(room) => {
+                    room.send(content).await?;
+                },
+                Err(e) => warn!("Couldn't send the {} reply to {} in private: {}", reply.title, self.sender, e)
+            }
+        } else {
+            self.room.send(content).await?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct SlackPlatform<'a> {
+    api: &'a SlackApi,
+    command: &'a SlashCommand
+}
+
+impl<'a> SlackPlatform<'a> {
+    pub fn new(api: &'a SlackApi, command: &'a SlashCommand) -> Self {
+        Self { api, command }
+    }
+}
+
+#[async_trait]
+impl ChatPlatform for SlackPlatform<'_> {
+    fn author(&self) -> UserApplication {
+        UserApplication::Slack(slack_id(&self.command.user_id))
+    }
+
+    // Mentions would be escaped with the rest of the text
+    fn author_name(&self) -> String {
+        format!("@{}", self.command.user_name)
+    }
+
+    // DM channels start with a D, the settings of the workspace apply in its other channels
+    fn community(&self) -> Option<Community> {
+        if self.command.channel_id.starts_with('D') {
+            return None;
+        }
+
+        Some(slack_community(&self.command.team_id))
+    }
+
+    // Each use of a slash command has its own trigger
+    fn operation(&self, command: &str) -> OperationId {
+        OperationId::slack(command, &self.command.trigger_id)
+    }
+
+    // Private replies are ephemeral, only the author sees them in the channel
+    async fn send(&self, reply: Reply) -> Result<(), Error> {
+        let response_type = if reply.private { "ephemeral" } else { "in_channel" };
+        let text = reply.to_slack().into_text();
+        self.api.respond(&self.command.response_url, &json!({ "response_type": response_type, "text": text })).await?;
+
+        Ok(())
+    }
+}
+
+pub struct TwitchPlatform<'a> {
+    api: &'a TwitchApi,
+    chat: &'a TwitchChat,
+    message: &'a ChatMessage
+}
+
+impl<'a> TwitchPlatform<'a> {
+    pub fn new(api: &'a TwitchApi, chat: &'a TwitchChat, message: &'a ChatMessage) -> Self {
+        Self { api, chat, message }
+    }
+}
+
+#[async_trait]
+impl ChatPlatform for TwitchPlatform<'_> {
+    fn author(&self) -> UserApplication {
+        UserApplication::Twitch(self.message.user_id)
+    }
+
+    fn author_name(&self) -> String {
+        format!("@{}", self.message.login)
+    }
+
+    // Each channel is a community, tips are counted in the stats of the streamer
+    fn community(&self) -> Option<Community> {
+        Some(Community::Twitch(self.message.room_id))
+    }
+
+    // Twitch gives each chat message a unique ID
+    fn operation(&self, command: &str) -> OperationId {
+        OperationId::twitch(command, &self.message.id)
+    }
+
+    // Private replies are whispered, the chat has no ephemeral messages
+    async fn send(&self, reply: Reply) -> Result<(), Error> {
+        let text = reply.to_twitch().into_text();
+        if !reply.private {
+            self.chat.reply(self.message, &text);
+            return Ok(());
+        }
+
+        // Users may only accept the whispers of the accounts they follow
+        if let Err(e) = self.api.whisper(self.message.user_id, &text).await {
+            warn!("Couldn't whisper the {} reply to {}: {}", reply.title, self.message.login, e);
+            self.chat.reply(self.message, "I couldn't whisper you, please allow whispers from strangers in your Twitch settings");
+        }
+
+        Ok(())
+    }
+}
+
+// Matrix, Slack and Twitch have no buttons, a rounded or converted amount is confirmed by sending the command again with it
+pub fn confirm_amount(title: &str, amount: &AssetBalance, command: &str) -> Reply {
+    Reply::pending(title, "The amount was rounded or converted, send the command again with this amount to confirm")
+        .field("Amount", amount.to_string(), false)
+        .code_field("Command", command)
+        .private()
+}
+
+// Balances of the author, only shown to them
+pub async fn balance(service: &WalletService, platform: &dyn ChatPlatform) -> Result<(), Error> {
+    let balances = service.get_balances_for_user(&platform.author()).await?;
+    let balances = balances.iter()
+        .map(|balance| balance.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    platform.send(Reply::new("Balance").field("Your balance is", balances, false).private()).await
+}
+
+// Move an amount, already confirmed by the author, to another user of the same platform
+// The community may ask for the tips to be confirmed in private
+pub async fn tip(service: &WalletService, platform: &dyn ChatPlatform, to: &UserApplication, to_name: &str, amount: &AssetBalance, conversion: Option<&Conversion>) -> Result<(), Error> {
+    let community = platform.community();
+    let operation = platform.operation("tip");
+    let reply = match service.transfer(&platform.author(), to, &amount.asset, amount.amount, community, Some(&operation)).await {
+        Ok(()) => {
+            debug!("Tipped {} to {:?} in {:?}", amount, to, community);
+            let private = match community.as_ref() {
+                Some(community) => service.is_setting_enabled(community, Setting::PrivateTips).await,
+                None => false
+            };
+
+            Reply::new("Tip")
+                .description(format!("{} tipped {} to {}", platform.author_name(), amount, to_name))
+                .conversion(conversion)
+                .private_if(private)
+                .announce(to_name, amount)
+        },
+        Err(e) => {
+            debug!("An error occured while tipping: {}", e);
+            Reply::error("Tip", "An error occured while tipping", e)
+        }
+    };
+
+    platform.send(reply).await
+}
+
+// Send an amount, already reviewed by the author, to an address outside of the bot
+// The reply is private as it shows the address and the TX of the author
+pub async fn withdraw(service: &WalletService, platform: &dyn ChatPlatform, to: Address, amount: &AssetBalance, conversion: Option<&Conversion>, pin: Option<&str>) -> Result<(), Error> {
+    let operation = platform.operation("withdraw");
+    let reply = match service.withdraw(&platform.author(), to, &amount.asset, amount.amount, pin, Some(&operation)).await {
+        Ok(hash) => Reply::new("Withdraw")
+            .description(format!("You have withdrawn {}", amount))
+            .code_field("Transaction", hash.to_string())
+            .conversion(conversion)
+            .private(),
+        // Sent once the bot is synced, the author is told in DM
+        Err(e @ ServiceError::WithdrawalQueued) => Reply::pending("Withdraw", e.to_string()).private(),
+        Err(e) => Reply::error("Withdraw", "An error occured while withdrawing", e)
+    };
+
+    platform.send(reply).await
+}
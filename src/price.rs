@@ -0,0 +1,192 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant}
+};
+
+use log::debug;
+use thiserror::Error;
+use xelis_common::config::COIN_VALUE;
+
+use crate::amount::{parse_amount, AmountError};
+
+// Default API used to fetch the XELIS price
+// The 24h change and volume are optional in the response
+pub const DEFAULT_PRICE_API: &str = "https://api.coingecko.com/api/v3/simple/price?ids=xelis&vs_currencies={currency}&include_24hr_change=true&include_24hr_vol=true";
+// How long a fetched price is reused before asking the API again
+const PRICE_CACHE_DURATION: Duration = Duration::from_secs(60);
+// Id of XELIS in the API response
+const PRICE_API_ID: &str = "xelis";
+// Fiat currency used when none is provided
+pub const DEFAULT_CURRENCY: &str = "usd";
+// Suffixes of the market data fields in the API response
+const CHANGE_24H_SUFFIX: &str = "_24h_change";
+const VOLUME_24H_SUFFIX: &str = "_24h_vol";
+
+#[derive(Debug, Error)]
+pub enum PriceError {
+    #[error("Unknown currency '{}'", .0)]
+    UnknownCurrency(String),
+    #[error("Price feed is unavailable")]
+    Unavailable(#[from] reqwest::Error),
+    #[error("Price is too low to convert")]
+    InvalidPrice,
+    #[error("Amount is too big")]
+    Overflow,
+    #[error(transparent)]
+    InvalidAmount(#[from] AmountError)
+}
+
+// Result of a conversion between XEL and a fiat currency
+#[derive(Debug, Clone)]
+pub struct Conversion {
+    // Amount in atomic units
+    pub xel: u64,
+    pub fiat: f64,
+    // Uppercase currency code
+    pub currency: String,
+    // Price of 1 XEL
+    pub price: f64
+}
+
+// Split an amount typed with its unit, such as "5usd" or "2.5 eur"
+// Returns None when the input has no unit
+pub fn split_unit(input: &str) -> Option<(&str, &str)> {
+    let input = input.trim();
+    let index = input.find(|c: char| c.is_ascii_alphabetic()).filter(|index| *index > 0)?;
+    let (amount, unit) = input.split_at(index);
+
+    Some((amount.trim(), unit))
+}
+
+// Check if a unit is XEL itself rather than a fiat currency
+pub fn is_xelis_unit(unit: &str) -> bool {
+    matches!(unit.to_lowercase().as_str(), "xel" | "xelis")
+}
+
+// Market data of XELIS in a fiat currency
+#[derive(Debug, Clone, Copy)]
+pub struct MarketData {
+    // Price of 1 XEL
+    pub price: f64,
+    // Price change over the last 24 hours in percent
+    pub change_24h: Option<f64>,
+    // Traded volume over the last 24 hours in the currency
+    pub volume_24h: Option<f64>
+}
+
+// Format a fiat value, small values keep more decimals
+pub fn format_fiat(value: f64) -> String {
+    if value.abs() >= 1.0 {
+        format!("{:.2}", value)
+    } else {
+        format!("{:.6}", value)
+    }
+}
+
+// Fetch the price of XELIS in fiat currencies
+// Prices are cached for a short time so busy chats don't hit the API on each command
+pub struct PriceFeed {
+    client: reqwest::Client,
+    // URL with a {currency} placeholder
+    api: String,
+    // Currencies shown by default with the price
+    currencies: Vec<String>,
+    cache: Mutex<HashMap<String, (Instant, MarketData)>>
+}
+
+impl PriceFeed {
+    pub fn new(api: String, currencies: Vec<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api,
+            currencies,
+            cache: Mutex::new(HashMap::new())
+        }
+    }
+
+    // Currencies configured by the operator
+    pub fn currencies(&self) -> &[String] {
+        &self.currencies
+    }
+
+    // Get the market data of XELIS in the requested currency
+    pub async fn get_market(&self, currency: &str) -> Result<MarketData, PriceError> {
+        let currency = currency.to_lowercase();
+        if let Some((fetched_at, market)) = self.cache.lock().expect("price cache lock").get(&currency) {
+            if fetched_at.elapsed() < PRICE_CACHE_DURATION {
+                return Ok(*market);
+            }
+        }
+
+        debug!("Fetching XELIS price in {}", currency);
+        let url = self.api.replace("{currency}", &currency);
+        let response: HashMap<String, HashMap<String, Option<f64>>> = self.client.get(url)
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+
+        let fields = response.get(PRICE_API_ID)
+            .ok_or_else(|| PriceError::UnknownCurrency(currency.to_uppercase()))?;
+        let price = fields.get(&currency)
+            .copied()
+            .flatten()
+            .ok_or_else(|| PriceError::UnknownCurrency(currency.to_uppercase()))?;
+
+        if !price.is_finite() || price <= 0.0 {
+            return Err(PriceError::InvalidPrice);
+        }
+
+        let market = MarketData {
+            price,
+            change_24h: fields.get(&format!("{}{}", currency, CHANGE_24H_SUFFIX)).copied().flatten(),
+            volume_24h: fields.get(&format!("{}{}", currency, VOLUME_24H_SUFFIX)).copied().flatten()
+        };
+        self.cache.lock().expect("price cache lock").insert(currency, (Instant::now(), market));
+
+        Ok(market)
+    }
+
+    // Get the price of 1 XEL in the requested currency
+    pub async fn get_price(&self, currency: &str) -> Result<f64, PriceError> {
+        Ok(self.get_market(currency).await?.price)
+    }
+
+    // Convert an amount given either in XEL or in a fiat currency
+    // When the amount is in XEL, it's converted to the fiat currency provided or USD
+    pub async fn convert(&self, amount: &str, unit: &str, fiat: Option<&str>) -> Result<Conversion, PriceError> {
+        // Fiat amounts are parsed with the same precision as XEL
+        let value = parse_amount(amount)?.value;
+        let (xel, fiat, currency) = if is_xelis_unit(unit) {
+            let currency = fiat.unwrap_or(DEFAULT_CURRENCY);
+            (value, self.to_fiat(value, currency).await?, currency)
+        } else {
+            let fiat = value as f64 / COIN_VALUE as f64;
+            (self.from_fiat(fiat, unit).await?, fiat, unit)
+        };
+
+        Ok(Conversion {
+            xel,
+            fiat,
+            currency: currency.to_uppercase(),
+            price: self.get_price(currency).await?
+        })
+    }
+
+    // Convert an amount in atomic units to the requested currency
+    pub async fn to_fiat(&self, amount: u64, currency: &str) -> Result<f64, PriceError> {
+        let price = self.get_price(currency).await?;
+        Ok(amount as f64 / COIN_VALUE as f64 * price)
+    }
+
+    // Convert a fiat amount to atomic units
+    pub async fn from_fiat(&self, amount: f64, currency: &str) -> Result<u64, PriceError> {
+        let price = self.get_price(currency).await?;
+        let value = (amount / price * COIN_VALUE as f64).round();
+        if !value.is_finite() || value >= u64::MAX as f64 {
+            return Err(PriceError::Overflow);
+        }
+
+        Ok(value as u64)
+    }
+}
@@ -1,16 +1,21 @@
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
     path::Path,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+        Mutex,
+        OnceLock,
+        RwLock
     },
     time::Duration
 };
 
-use anyhow::Result;
-use poise::serenity_prelude::{Http, CreateMessage, CreateEmbed};
-use teloxide::{types::ChatId, Bot};
+use anyhow::{Context, Result};
+use matrix_sdk::Client as MatrixClient;
+use poise::serenity_prelude::{ChannelId, Colour, Http, CreateMessage, CreateEmbed};
+use teloxide::Bot;
 use thiserror::Error;
 use xelis_common::{
     api::{
@@ -18,21 +23,20 @@ use xelis_common::{
         DataElement,
         DataValue
     },
-    config::XELIS_ASSET,
+    config::{COIN_DECIMALS, XELIS_ASSET},
     crypto::{
         ecdlp::NoOpProgressTableGenerationReportFunction,
         Address,
-        Hash,
-        Hashable
+        Hash
     },
     network::Network,
     serializer::{Reader, ReaderError, Serializer, Writer},
+    time::{get_current_time_in_millis, get_current_time_in_seconds},
     transaction::builder::{
-        FeeBuilder,
         TransactionTypeBuilder,
         TransferBuilder
     },
-    utils::format_xelis
+    utils::{format_coin, format_xelis}
 };
 use xelis_wallet::{
     error::WalletError,
@@ -42,39 +46,235 @@ use xelis_wallet::{
 };
 use log::{debug, error, info, warn};
 
-use crate::{telegram_message::TelegramMessage, COLOR, ICON};
+use crate::{
+    api_errors::{ApiErrorMonitor, ApiErrorStats, Platform},
+    price::PriceFeed,
+    slack_api::SlackApi,
+    telegram_message::TelegramMessage,
+    telegram_notifier::TelegramNotifier,
+    twitch_api::TwitchApi,
+    COLOR,
+    ICON
+};
+
+mod acl;
+mod address_book;
+mod alerts;
+mod audit;
+mod backend;
+mod batch;
+mod budget;
+mod command_rules;
+mod confirmations;
+mod connection;
+mod cooldown;
+mod deposits;
+mod events;
+mod failover;
+mod faucet;
+mod forget;
+mod freeze;
+mod games;
+mod goals;
+mod health;
+mod ids;
+mod imports;
+mod integrity;
+mod journal;
+mod ledger;
+mod lottery;
+mod matrix;
+mod membership;
+// Wallet of the unit tests, also built with the mock-wallet feature
+#[cfg(any(test, feature = "mock-wallet"))]
+mod mock;
+mod outbox;
+mod operations;
+mod ops;
+mod pin;
+mod practice;
+mod reconciliation;
+mod reminders;
+mod reorg;
+mod runtime;
+mod reserves;
+mod scheduler;
+mod settings;
+mod slack;
+mod solvency;
+mod stats;
+mod submission;
+mod twitch;
+mod unconfirmed;
+mod unknown_deposits;
+mod unsent;
+mod usernames;
+mod watchdog;
+mod whitelist;
+mod withdrawals;
+
+pub use acl::Exemption;
+pub use address_book::SavedAddress;
+pub use audit::AuditRecord;
+pub use backend::{DaemonEvent, WalletBackend};
+pub use budget::{BudgetOverrun, TipBudget};
+pub use command_rules::CommandRule;
+pub use confirmations::default_explorer_url;
+pub use cooldown::{RateLimitConfig, RateLimited};
+pub use deposits::Deposit;
+pub use events::{BotEvent, BotEventKind};
+pub use faucet::FaucetConfig;
+pub use forget::{ForgottenUser, Residual};
+pub use games::{Challenge, ChallengeResult, Game, Outcome, CHALLENGE_TIMEOUT};
+pub use goals::{Goal, GoalUpdate, MAX_GOAL_MESSAGE_LENGTH};
+pub use health::Health;
+pub use ids::{discord_id, telegram_channel, telegram_chat};
+pub use journal::{Movement, MovementKind, Statement, MAX_NOTE_LENGTH};
+pub use lottery::{LotteryConfig, LotteryDraw, LotteryRound, MAX_TICKETS_PER_PURCHASE};
+pub use matrix::{matrix_id, parse_matrix_user};
+pub use membership::Strictness;
+#[cfg(feature = "mock-wallet")]
+pub use mock::MockWallet;
+pub use operations::OperationId;
+pub use ops::{Alert, OpsChannels};
+pub use outbox::MAX_NOTIFICATION_ATTEMPTS;
+pub use practice::{PRACTICE_BALANCE, PRACTICE_WITHDRAW_FEE};
+pub use reminders::ReminderConfig;
+pub use runtime::RuntimeSettings;
+pub use scheduler::parse_delay;
+pub use settings::{Feature, RecipientFilter, Setting};
+pub use slack::{parse_slack_user, slack_community, slack_id, SlackWorkspace};
+pub use solvency::DEFAULT_SOLVENCY_BUFFER;
+pub use stats::{LeaderboardKind, StatsPeriod, TipStats};
+pub use unsent::FailedWithdrawal;
+pub use whitelist::WhitelistStatus;
+
+use alerts::{AlertState, ALERTS_INTERVAL};
+use batch::BalanceUpdate;
+use connection::Connection;
+use events::EVENTS_CAPACITY;
+use failover::DaemonEndpoints;
+use outbox::OUTBOX_RETRY_INTERVAL;
+use scheduler::{MAX_REMINDER_DELAY, SCHEDULER_INTERVAL};
+use solvency::SOLVENCY_INTERVAL;
+use unknown_deposits::UnknownTransfer;
+use unsent::{Debit, UNSENT_RETRY_INTERVAL};
+use watchdog::{PendingWithdrawal, WATCHDOG_INTERVAL};
 
 const BALANCES_TREE: &str = "balances";
 const HISTORY_TREE: &str = "history";
+const GIVEAWAYS_TREE: &str = "giveaways";
+// Balances of the accounts owned by the bot itself
+const INTERNAL_BALANCES_TREE: &str = "internal_balances";
+
+// Maximum claims allowed in a single giveaway
+pub const MAX_GIVEAWAY_CLAIMS: u16 = 100;
+// How often expired giveaways are settled
+const GIVEAWAY_SETTLEMENT_INTERVAL: Duration = Duration::from_secs(5);
+// How often the weekly top tipper of each guild is checked
+const TOP_TIPPER_UPDATE_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UserApplication {
     Telegram(u64),
-    Discord(u64)
+    Discord(u64),
+    // Hash of the Matrix user ID, see `matrix_id`
+    Matrix(u64),
+    // Hash of the Slack user ID, see `slack_id`
+    Slack(u64),
+    // Twitch user IDs are numeric
+    Twitch(u64)
+}
+
+// Wire format of a user, embedded in the integrated deposit addresses and used in the storage keys
+// Every encoding ever written must stay decodable, as old addresses can still be used
+// - v0: the platform tag followed by the platform id as u64, always written for Telegram and Discord
+//   so their addresses and storage keys never change
+// - v1: VERSIONED_FORMAT, the format version, the platform tag and the platform id
+//   Platforms added later are written with it and can define their own id
+const TELEGRAM_TAG: u8 = 0;
+const DISCORD_TAG: u8 = 1;
+// Only written in v1
+const MATRIX_TAG: u8 = 2;
+const SLACK_TAG: u8 = 3;
+const TWITCH_TAG: u8 = 4;
+// Never used as a platform tag, announces an explicit version byte
+const VERSIONED_FORMAT: u8 = 0xFF;
+// Latest version of the wire format that can be decoded
+const USER_APPLICATION_VERSION: u8 = 1;
+
+impl UserApplication {
+    // Read a user once its platform tag is known, v0 only knows Telegram and Discord
+    fn read_platform(version: u8, tag: u8, reader: &mut Reader) -> Result<Self, ReaderError> {
+        let user = match tag {
+            TELEGRAM_TAG => UserApplication::Telegram(reader.read_u64()?),
+            DISCORD_TAG => UserApplication::Discord(reader.read_u64()?),
+            MATRIX_TAG if version >= 1 => UserApplication::Matrix(reader.read_u64()?),
+            SLACK_TAG if version >= 1 => UserApplication::Slack(reader.read_u64()?),
+            TWITCH_TAG if version >= 1 => UserApplication::Twitch(reader.read_u64()?),
+            _ => return Err(ReaderError::InvalidValue)
+        };
+
+        Ok(user)
+    }
+
+    // Platform the user is on, to check if its API errors are spiking
+    pub fn platform(&self) -> Platform {
+        match self {
+            UserApplication::Discord(_) => Platform::Discord,
+            UserApplication::Telegram(_) => Platform::Telegram,
+            UserApplication::Matrix(_) => Platform::Matrix,
+            UserApplication::Slack(_) => Platform::Slack,
+            UserApplication::Twitch(_) => Platform::Twitch
+        }
+    }
 }
 
 impl Serializer for UserApplication {
     fn write(&self, writer: &mut Writer) {
         match self {
             UserApplication::Telegram(id) => {
-                writer.write_u8(0);
+                writer.write_u8(TELEGRAM_TAG);
                 id.write(writer);
             },
             UserApplication::Discord(id) => {
-                writer.write_u8(1);
+                writer.write_u8(DISCORD_TAG);
+                id.write(writer);
+            },
+            UserApplication::Matrix(id) => {
+                writer.write_u8(VERSIONED_FORMAT);
+                writer.write_u8(USER_APPLICATION_VERSION);
+                writer.write_u8(MATRIX_TAG);
+                id.write(writer);
+            },
+            UserApplication::Slack(id) => {
+                writer.write_u8(VERSIONED_FORMAT);
+                writer.write_u8(USER_APPLICATION_VERSION);
+                writer.write_u8(SLACK_TAG);
+                id.write(writer);
+            },
+            UserApplication::Twitch(id) => {
+                writer.write_u8(VERSIONED_FORMAT);
+                writer.write_u8(USER_APPLICATION_VERSION);
+                writer.write_u8(TWITCH_TAG);
                 id.write(writer);
             }
         }
     }
 
     fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
-        let id = match reader.read_u8()? {
-            0 => UserApplication::Telegram(reader.read_u64()?),
-            1 => UserApplication::Discord(reader.read_u64()?),
-            _ => return Err(ReaderError::InvalidValue)
-        };
+        match reader.read_u8()? {
+            VERSIONED_FORMAT => {
+                let version = reader.read_u8()?;
+                if version == 0 || version > USER_APPLICATION_VERSION {
+                    return Err(ReaderError::InvalidValue);
+                }
 
-        Ok(id)
+                let tag = reader.read_u8()?;
+                Self::read_platform(version, tag, reader)
+            },
+            tag => Self::read_platform(0, tag, reader)
+        }
     }
 }
 
@@ -90,153 +290,941 @@ impl Into<DataElement> for &UserApplication {
     }
 }
 
+// A Discord guild, a Telegram group, a Slack workspace or a Twitch channel in which the bot is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Community {
+    Telegram(i64),
+    Discord(u64),
+    // Hash of the team ID, see `slack_community`
+    Slack(u64),
+    // User ID of the streamer
+    Twitch(u64)
+}
+
+impl Serializer for Community {
+    fn write(&self, writer: &mut Writer) {
+        match self {
+            Community::Telegram(id) => {
+                writer.write_u8(0);
+                (*id as u64).write(writer);
+            },
+            Community::Discord(id) => {
+                writer.write_u8(1);
+                id.write(writer);
+            },
+            Community::Slack(id) => {
+                writer.write_u8(2);
+                id.write(writer);
+            },
+            Community::Twitch(id) => {
+                writer.write_u8(3);
+                id.write(writer);
+            }
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let id = match reader.read_u8()? {
+            0 => Community::Telegram(reader.read_u64()? as i64),
+            1 => Community::Discord(reader.read_u64()?),
+            2 => Community::Slack(reader.read_u64()?),
+            3 => Community::Twitch(reader.read_u64()?),
+            _ => return Err(ReaderError::InvalidValue)
+        };
+
+        Ok(id)
+    }
+}
+
+// Storage key of a user balance, one entry per asset
+#[derive(Debug, Clone)]
+struct BalanceKey {
+    user: UserApplication,
+    asset: Hash
+}
+
+impl Serializer for BalanceKey {
+    fn write(&self, writer: &mut Writer) {
+        self.user.write(writer);
+        self.asset.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let user = UserApplication::read(reader)?;
+        let asset = Hash::read(reader)?;
+
+        Ok(Self {
+            user,
+            asset
+        })
+    }
+}
+
+// Balance of a user in one asset, with what's needed to display it
+#[derive(Debug, Clone)]
+pub struct AssetBalance {
+    pub asset: Hash,
+    pub name: String,
+    pub decimals: u8,
+    pub amount: u64
+}
+
+impl AssetBalance {
+    pub fn xelis(amount: u64) -> Self {
+        Self {
+            asset: XELIS_ASSET,
+            name: "XEL".to_string(),
+            decimals: COIN_DECIMALS,
+            amount
+        }
+    }
+
+    // Same asset with another amount
+    pub fn with_amount(&self, amount: u64) -> Self {
+        Self {
+            amount,
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for AssetBalance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", format_coin(self.amount, self.decimals), self.name)
+    }
+}
+
+// Depth of the processing queues, to spot a backlog early
+#[derive(Debug, Clone, Copy)]
+pub struct QueueStatus {
+    // Incoming TXs waiting for the stable topoheight
+    pub unconfirmed: usize,
+    // Telegram notifications waiting for their chat to be writable again
+    pub notifications: usize,
+    // Withdrawals still not on-chain after all their broadcasts
+    pub stuck_withdrawals: usize,
+    // Time since the event loop handled its last event, if any
+    pub last_event: Option<Duration>,
+    // Errors answered by the platform APIs
+    pub discord_errors: ApiErrorStats,
+    pub telegram_errors: ApiErrorStats,
+    pub matrix_errors: ApiErrorStats,
+    pub slack_errors: ApiErrorStats,
+    pub twitch_errors: ApiErrorStats
+}
+
+// A giveaway escrows the host funds until it is settled
+// Once the timer expires or all claims are used,
+// the amount is split equally between the claimants
+#[derive(Debug, Clone)]
+pub struct Giveaway {
+    pub id: u64,
+    pub host: UserApplication,
+    pub amount: u64,
+    pub max_claims: u16,
+    // Timestamp in seconds
+    pub expire_at: u64,
+    // Discord channel or Telegram chat where the giveaway was started
+    pub channel_id: u64,
+    pub claims: Vec<UserApplication>
+}
+
+impl Giveaway {
+    pub fn is_full(&self) -> bool {
+        self.claims.len() >= self.max_claims as usize
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.expire_at
+    }
+}
+
+impl Serializer for Giveaway {
+    fn write(&self, writer: &mut Writer) {
+        self.id.write(writer);
+        self.host.write(writer);
+        self.amount.write(writer);
+        self.max_claims.write(writer);
+        self.expire_at.write(writer);
+        self.channel_id.write(writer);
+        (self.claims.len() as u16).write(writer);
+        for claim in self.claims.iter() {
+            claim.write(writer);
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let id = reader.read_u64()?;
+        let host = UserApplication::read(reader)?;
+        let amount = reader.read_u64()?;
+        let max_claims = reader.read_u16()?;
+        let expire_at = reader.read_u64()?;
+        let channel_id = reader.read_u64()?;
+        let len = reader.read_u16()?;
+        let mut claims = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            claims.push(UserApplication::read(reader)?);
+        }
+
+        Ok(Self {
+            id,
+            host,
+            amount,
+            max_claims,
+            expire_at,
+            channel_id,
+            claims
+        })
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ServiceError {
     #[error("Cannot transfer 0 XEL")]
     Zero,
     #[error("You can't tip yourself")]
     SelfTip,
+    #[error("No users to send XEL to")]
+    NoRecipients,
+    #[error("Amount is too big")]
+    Overflow,
+    #[error("Amount is too small to be split between {} users", .0)]
+    AmountTooSmall(u16),
+    #[error("Giveaway not found or already ended")]
+    GiveawayNotFound,
+    #[error("You already claimed this giveaway")]
+    AlreadyClaimed,
+    #[error("Giveaway has no claims left")]
+    GiveawayFull,
+    #[error("You can't claim your own giveaway")]
+    SelfClaim,
+    #[error("Challenge not found or already ended")]
+    ChallengeNotFound,
+    #[error("You can't challenge yourself")]
+    SelfChallenge,
+    #[error("This challenge is not for you")]
+    NotChallenged,
+    #[error("{} is disabled here", .0)]
+    FeatureDisabled(&'static str),
+    #[error("/{} is disabled in this server", .0)]
+    CommandDisabled(String),
+    #[error("/{} can't be used in this channel", .0)]
+    CommandRestricted(String),
+    #[error("A command can only be restricted to {} channels", .0)]
+    TooManyCommandChannels(usize),
+    #[error("Invalid address: {}", .0)]
+    InvalidAddress(String),
+    #[error("Invalid network")]
+    InvalidNetwork,
+    #[error("A label is up to {} letters, digits, '-' or '_'", .0)]
+    InvalidAddressLabel(usize),
+    #[error("Your address book is limited to {} addresses, remove one first", .0)]
+    AddressBookFull(usize),
+    #[error("'{}' is neither an address nor a label of your address book", .0)]
+    UnknownAddressLabel(String),
+    #[error("Your withdrawals are limited to your address book, save this address with /address add first")]
+    AddressNotWhitelisted,
+    #[error("A PIN is {} to {} digits", .0, .1)]
+    InvalidPin(usize, usize),
+    #[error("Your withdrawals are protected by a PIN, it's required to continue")]
+    PinRequired,
+    #[error("Wrong PIN, {} attempts left before your withdrawals are locked", .0)]
+    WrongPin(u8),
+    #[error("Too many wrong PINs, your withdrawals are locked for {} minutes", .0.div_ceil(60))]
+    PinLocked(u64),
+    #[error("This address was saved recently, you can withdraw to it in {} hours", .0.div_ceil(3600))]
+    AddressNotYetWhitelisted(u64),
+    #[error("Amount is below the minimum tip of {} XEL", format_xelis(*.0))]
+    BelowMinTip(u64),
+    #[error("Amount is below the minimum withdrawal of {} XEL", format_xelis(*.0))]
+    BelowMinWithdraw(u64),
+    #[error("Withdrawals are limited to {} XEL per 24 hours, you can still withdraw {} XEL", format_xelis(*.0), format_xelis(*.1))]
+    WithdrawLimit(u64, u64),
+    #[error("A goal is already running here, cancel it first")]
+    GoalAlreadySet,
+    #[error("No goal is running here")]
+    GoalNotFound,
+    #[error("Celebration message is limited to {} characters", MAX_GOAL_MESSAGE_LENGTH)]
+    GoalMessageTooLong,
+    #[error("Invalid delay, use a number followed by m, h, d or w such as 3d")]
+    InvalidDelay,
+    #[error("A reminder can be scheduled up to {} days ahead", MAX_REMINDER_DELAY.as_secs() / (24 * 60 * 60))]
+    ReminderTooFar,
+    #[error("A memo of up to {} characters is required", .0)]
+    InvalidReminderMemo(usize),
+    #[error("You can have up to {} pending reminders", .0)]
+    TooManyReminders(usize),
+    #[error("Lottery is disabled")]
+    LotteryDisabled,
+    #[error("You can buy up to {} tickets at once", .0)]
+    TooManyTickets(u32),
+    #[error("Faucet is disabled")]
+    FaucetDisabled,
+    #[error("Faucet is empty, come back later")]
+    FaucetEmpty,
+    #[error("You can claim the faucet again in {} minutes", .0.div_ceil(60))]
+    FaucetCooldown(u64),
     #[error("Not enough funds to transfer {} XEL", format_xelis(*.0))]
     NotEnoughFunds(u64),
+    #[error("Not enough funds to transfer {}", .0)]
+    NotEnoughAssetFunds(AssetBalance),
+    #[error("You don't have any balance in {}", .0)]
+    UnknownAsset(String),
     #[error("Not enough funds to pay {} XEL of fee", format_xelis(*.0))]
     NotEnoughFundsForFee(u64),
+    #[error("Your account is frozen, contact the operator of the bot")]
+    AccountFrozen,
+    #[error("A reason of up to {} characters is required", .0)]
+    InvalidNote(usize),
+    #[error("You have a giveaway, challenge, goal or lottery ticket running, wait for it to end first")]
+    EscrowRunning,
+    #[error("{} XEL is over your withdrawal limit, withdraw it tomorrow or donate it", format_xelis(*.0))]
+    ResidualBalance(u64),
+    #[error("A file with the same checksum was already imported")]
+    AlreadyImported,
+    #[error("This command was already executed")]
+    AlreadyProcessed,
+    #[error("Deposit not found")]
+    DepositNotFound,
+    #[error("Deposit was already returned")]
+    DepositAlreadyReturned,
+    #[error("Deposit was already spent by its user")]
+    DepositSpent,
     #[error("Service is already running")]
     AlreadyRunning,
     #[error("Withdraw is locked")]
     WithdrawLocked,
+    #[error("Another transaction of the bot is still being processed, try again in a moment")]
+    TransactionConflict,
     #[error(transparent)]
     Any(#[from] anyhow::Error),
     #[error(transparent)]
     WalletError(#[from] WalletError),
     #[error("Wallet is offline")]
     WalletOffline,
+    #[error("The bot is syncing with the network, your withdrawal is queued and you'll get a DM once it's sent")]
+    WithdrawalQueued,
+    #[error("The bot is syncing with the network, try again later")]
+    Syncing,
+    #[error("This instance is on standby, try again in a moment")]
+    NotLeader,
 }
 
 pub type WalletService = Arc<WalletServiceImpl>;
 
 pub struct WalletServiceImpl {
-    wallet: Arc<Wallet>,
+    wallet: Arc<dyn WalletBackend>,
+    // Daemons the wallet switches between when the one it's connected to can't be reached
+    daemons: DaemonEndpoints,
+    // Withdrawals are queued until the event loop is subscribed and the wallet synced
+    connection: Mutex<Connection>,
     running: AtomicBool,
     locked: AtomicBool,
+    // This instance holds the lease, always true without leader election
+    leader: AtomicBool,
+    faucet: Option<FaucetConfig>,
+    lottery: Option<LotteryConfig>,
+    reminders: Option<ReminderConfig>,
+    // Limits, cooldowns and operator settings, replaced when the configuration is reloaded
+    runtime: RwLock<Arc<RuntimeSettings>>,
+    // The last solvency check found a deficit over the buffer
+    insolvent: AtomicBool,
+    alerts: Mutex<AlertState>,
+    // Users that confirmed their next tip can go over their budget
+    budget_overruns: Mutex<HashSet<UserApplication>>,
+    cooldowns: Mutex<cooldown::Cooldowns>,
+    // TXs are built and broadcast one at a time, in the order they were requested
+    submission_queue: tokio::sync::Mutex<()>,
+    // TXs sent by the wallet that aren't confirmed yet
+    pending_withdrawals: Mutex<HashMap<Hash, PendingWithdrawal>>,
+    // Discord users alerted when a withdrawal goes missing
+    operators: Vec<u64>,
+    // Size of the unconfirmed TXs queue of the event loop
+    unconfirmed_count: AtomicUsize,
+    // Timestamp in milliseconds of the last event handled, 0 if none yet
+    last_event: AtomicU64,
+    // Activity published to the subscribers of the event stream
+    events: tokio::sync::broadcast::Sender<BotEvent>,
+    notifier: Arc<TelegramNotifier>,
+    // Logged in to the homeserver when Matrix is enabled, to send DMs to its users
+    matrix: OnceLock<MatrixClient>,
+    // Client of the Slack Web API when Slack is enabled, each workspace has its own token
+    slack: OnceLock<SlackApi>,
+    // Client of the Helix API when Twitch is enabled, to whisper its users
+    twitch: OnceLock<TwitchApi>,
+    // Errors answered by the platform APIs, non essential notifications are reduced when they spike
+    api_errors: Arc<ApiErrorMonitor>,
+    prices: PriceFeed,
+}
+
+// Transfer of a user withdrawal, without any extra data
+fn withdraw_builder(to: &Address, asset: &Hash, amount: u64) -> TransactionTypeBuilder {
+    TransactionTypeBuilder::Transfers(vec![TransferBuilder {
+        amount,
+        asset: asset.clone(),
+        destination: to.clone(),
+        extra_data: None,
+        encrypt_extra_data: true,
+    }])
 }
 
 impl WalletServiceImpl {
     // Create a new wallet service
-    pub async fn new(name: &str, password: &str, daemon_address: String, network: Network, n_threads_decryption: usize, network_concurrency: usize) -> Result<WalletService> {
+    pub async fn new(name: &str, password: &str, daemon_addresses: Vec<String>, network: Network, n_threads_decryption: usize, network_concurrency: usize, faucet: Option<FaucetConfig>, lottery: Option<LotteryConfig>, reminders: Option<ReminderConfig>, runtime: RuntimeSettings, operators: Vec<u64>, notifier: Arc<TelegramNotifier>, api_errors: Arc<ApiErrorMonitor>, prices: PriceFeed) -> Result<WalletService> {
         let precomputed_tables = precomputed_tables::read_or_generate_precomputed_tables(None, precomputed_tables::L1_FULL, NoOpProgressTableGenerationReportFunction, true).await?;
 
-        let wallet = if Path::new(&name).is_dir() {
-            Wallet::open(name, password, network, precomputed_tables, n_threads_decryption, network_concurrency)?
+        let wallet: Arc<dyn WalletBackend> = if Path::new(&name).is_dir() {
+            Wallet::open(name, password, network, precomputed_tables, n_threads_decryption, network_concurrency)
+                .with_context(|| format!("Couldn't open the wallet {}, check --password", name))?
         } else {
-            Wallet::create(name, password, None, network, precomputed_tables, n_threads_decryption, network_concurrency).await?
+            Wallet::create(name, password, None, network, precomputed_tables, n_threads_decryption, network_concurrency).await
+                .with_context(|| format!("Couldn't create the wallet {}", name))?
         };
 
-        wallet.set_online_mode(&daemon_address, true).await?;
+        Self::with_backend(wallet, daemon_addresses, faucet, lottery, reminders, runtime, operators, notifier, api_errors, prices).await
+    }
+
+    // Create the service on any wallet, such as the mock of the unit tests
+    pub async fn with_backend(wallet: Arc<dyn WalletBackend>, daemon_addresses: Vec<String>, faucet: Option<FaucetConfig>, lottery: Option<LotteryConfig>, reminders: Option<ReminderConfig>, runtime: RuntimeSettings, operators: Vec<u64>, notifier: Arc<TelegramNotifier>, api_errors: Arc<ApiErrorMonitor>, prices: PriceFeed) -> Result<WalletService> {
+        let daemons = DaemonEndpoints::connect(wallet.as_ref(), daemon_addresses).await?;
 
         let service = Arc::new(Self {
             wallet,
+            daemons,
+            connection: Mutex::new(Connection::default()),
             running: AtomicBool::new(false),
-            locked: AtomicBool::new(false)
+            locked: AtomicBool::new(false),
+            leader: AtomicBool::new(true),
+            faucet,
+            lottery,
+            reminders,
+            runtime: RwLock::new(Arc::new(runtime)),
+            insolvent: AtomicBool::new(false),
+            alerts: Mutex::new(AlertState::default()),
+            budget_overruns: Mutex::new(HashSet::new()),
+            cooldowns: Mutex::new(HashMap::new()),
+            submission_queue: tokio::sync::Mutex::new(()),
+            pending_withdrawals: Mutex::new(HashMap::new()),
+            operators,
+            unconfirmed_count: AtomicUsize::new(0),
+            last_event: AtomicU64::new(0),
+            events: tokio::sync::broadcast::channel(EVENTS_CAPACITY).0,
+            notifier,
+            matrix: OnceLock::new(),
+            slack: OnceLock::new(),
+            twitch: OnceLock::new(),
+            api_errors,
+            prices
         });
 
         Ok(service)
     }
 
+    // Verify the stored data can be read before anything uses it
+    pub async fn check_storage(&self) -> Result<()> {
+        self.migrate_balances().await.context("Couldn't migrate the balances")?;
+        self.recover_balance_batch().await.context("Couldn't complete the interrupted balance updates")?;
+        self.check_ledger().await.context("Couldn't verify the ledger")?;
+
+        Ok(())
+    }
+
+    // Balances used to be stored by user only, move them to the XEL entry of the user
+    async fn migrate_balances(&self) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let mut migrated = 0;
+        for key in storage.get_custom_tree_keys(&BALANCES_TREE.to_string(), &None, None, None)? {
+            if key.as_type::<BalanceKey>().is_ok() {
+                continue;
+            }
+
+            let user: UserApplication = key.as_type()?;
+            let balance = storage.get_custom_data(BALANCES_TREE, &key)?.to_value()?.to_u64()?;
+            storage.delete_custom_data(BALANCES_TREE, &key)?;
+            let key = BalanceKey { user, asset: XELIS_ASSET };
+            storage.set_custom_data(BALANCES_TREE, &DataValue::Blob(key.to_bytes()), &balance.into())?;
+            migrated += 1;
+        }
+
+        if migrated > 0 {
+            info!("Migrated {} balances to the multi-asset format", migrated);
+        }
+
+        Ok(())
+    }
+
+    // Get the price feed of XELIS
+    pub fn prices(&self) -> &PriceFeed {
+        &self.prices
+    }
+
     // Start the service to scan all incoming TXs
     pub async fn start(self: WalletService, http: Arc<Http>, bot: Bot) -> Result<(), ServiceError> {
         if self.running.swap(true, Ordering::SeqCst) {
             return Err(ServiceError::AlreadyRunning);
         }
 
+        {
+            let service = Arc::clone(&self);
+            let http = Arc::clone(&http);
+            let bot = bot.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(GIVEAWAY_SETTLEMENT_INTERVAL).await;
+                    if !service.is_leader() {
+                        continue;
+                    }
+
+                    match service.settle_giveaways().await {
+                        Ok(settled) => for (giveaway, share) in settled {
+                            if let Err(e) = service.announce_giveaway_result(&http, &bot, &giveaway, share).await {
+                                service.api_errors.record_error(&e);
+                                error!("Error while announcing giveaway {} result: {:?}", giveaway.id, e);
+                            }
+                        },
+                        Err(e) => error!("Error while settling giveaways: {:?}", e)
+                    }
+
+                    if let Err(e) = service.update_goals(&http, &bot).await {
+                        error!("Error while updating goals: {:?}", e);
+                    }
+
+                    if let Err(e) = service.expire_challenges().await {
+                        error!("Error while expiring challenges: {:?}", e);
+                    }
+
+                    if let Err(e) = service.prune_operations().await {
+                        error!("Error while pruning the processed operations: {:?}", e);
+                    }
+
+                    match service.draw_lottery_if_due().await {
+                        Ok(Some(draw)) => if let Err(e) = service.notify_lottery_winner(&http, &bot, &draw).await {
+                            error!("Error while notifying lottery round {} winner: {:?}", draw.round, e);
+                        },
+                        Ok(None) => {},
+                        Err(e) => error!("Error while drawing the lottery: {:?}", e)
+                    }
+                }
+            });
+        }
+
+        {
+            let service = Arc::clone(&self);
+            let http = Arc::clone(&http);
+            tokio::spawn(async move {
+                loop {
+                    if service.is_leader() {
+                        if let Err(e) = service.update_top_tippers(&http).await {
+                            error!("Error while updating top tippers: {:?}", e);
+                        }
+                    }
+
+                    tokio::time::sleep(TOP_TIPPER_UPDATE_INTERVAL).await;
+                }
+            });
+        }
+
+        {
+            let service = Arc::clone(&self);
+            let http = Arc::clone(&http);
+            let bot = bot.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(OUTBOX_RETRY_INTERVAL).await;
+                    if !service.is_leader() {
+                        continue;
+                    }
+
+                    if let Err(e) = service.retry_notifications(&http, &bot).await {
+                        error!("Error while sending the queued notifications: {:?}", e);
+                    }
+                }
+            });
+        }
+
+        {
+            let service = Arc::clone(&self);
+            let http = Arc::clone(&http);
+            let bot = bot.clone();
+            tokio::spawn(async move {
+                // The TXs left by a crash are broadcast right away
+                loop {
+                    if service.is_leader() {
+                        if let Err(e) = service.retry_unsent_transactions(&http).await {
+                            error!("Error while broadcasting the unsent TXs: {:?}", e);
+                        }
+
+                        if let Err(e) = service.send_queued_withdrawals(&http, &bot).await {
+                            error!("Error while sending the queued withdrawals: {:?}", e);
+                        }
+                    }
+
+                    tokio::time::sleep(UNSENT_RETRY_INTERVAL).await;
+                }
+            });
+        }
+
+        {
+            let service = Arc::clone(&self);
+            let http = Arc::clone(&http);
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(WATCHDOG_INTERVAL).await;
+                    if !service.is_leader() {
+                        continue;
+                    }
+
+                    if let Err(e) = service.watch_pending_withdrawals(&http).await {
+                        error!("Error while watching the pending withdrawals: {:?}", e);
+                    }
+                }
+            });
+        }
+
+        {
+            let service = Arc::clone(&self);
+            let http = Arc::clone(&http);
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(SOLVENCY_INTERVAL).await;
+                    if !service.is_leader() {
+                        continue;
+                    }
+
+                    if let Err(e) = service.check_solvency(&http).await {
+                        error!("Error while checking the solvency: {:?}", e);
+                    }
+                }
+            });
+        }
+
+        {
+            let service = Arc::clone(&self);
+            let http = Arc::clone(&http);
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(ALERTS_INTERVAL).await;
+                    if !service.is_leader() {
+                        continue;
+                    }
+
+                    if let Err(e) = service.check_alerts(&http).await {
+                        error!("Error while checking the alerts: {:?}", e);
+                    }
+                }
+            });
+        }
+
+        {
+            let service = Arc::clone(&self);
+            let http = Arc::clone(&http);
+            let bot = bot.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(SCHEDULER_INTERVAL).await;
+                    if !service.is_leader() {
+                        continue;
+                    }
+
+                    if let Err(e) = service.send_due_reminders(&http, &bot).await {
+                        error!("Error while sending the due reminders: {:?}", e);
+                    }
+                }
+            });
+        }
+
+        if let Some(config) = self.reminders {
+            let service = Arc::clone(&self);
+            let http = Arc::clone(&http);
+            let bot = bot.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(config.interval).await;
+                    if !service.is_leader() {
+                        continue;
+                    }
+
+                    if let Err(e) = service.send_dormant_reminders(&config, &http, &bot).await {
+                        error!("Error while reminding dormant users: {:?}", e);
+                    }
+                }
+            });
+        }
+
         tokio::spawn(async move {
             loop {
+                // Deposits are only processed by the leader
+                if !self.is_leader() {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+
                 info!("Starting event loop");
                 if let Err(e) = self.event_loop(&http, &bot).await {
                     error!("Error in event loop: {:?}", e);
+                    // The operators are already alerted while the wallet is offline
+                    if !matches!(e.downcast_ref::<ServiceError>(), Some(ServiceError::WalletOffline)) {
+                        let alert = Alert::new("Event Loop Error", "The event loop stopped and is restarting", Colour::RED)
+                            .field("Error", format!("{:#}", e), false);
+                        self.report_to_ops(&http, &alert).await;
+                    }
+                    self.record_event_loop_restart(&http, &e).await;
                 }
 
-                tokio::time::sleep(Duration::from_secs(5)).await;
+                // Subscribed again when the loop restarts, to another daemon if the current one is lost
+                let delay = self.handle_disconnect(&http).await;
+                tokio::time::sleep(delay).await;
             }
         });
 
         Ok(())
     }
 
-    // Notify a discord user of a deposit
-    async fn notify_discord_deposit(&self, http: &Http, user_id: u64, amount: u64, transaction_hash: &Hash) -> Result<()> {
-        let user = http.get_user(user_id.try_into()?).await?;
-        let channel = user.create_dm_channel(&http).await?;
+    // Publish the result of a giveaway where it was started
+    async fn announce_giveaway_result(&self, http: &Http, bot: &Bot, giveaway: &Giveaway, share: u64) -> Result<()> {
+        let winners = giveaway.claims.len();
+        match giveaway.host {
+            UserApplication::Discord(_) => {
+                let mentions = giveaway.claims.iter()
+                    .filter_map(|claim| match claim {
+                        UserApplication::Discord(id) => Some(format!("<@{}>", id)),
+                        _ => None
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                let mut embed = CreateEmbed::default()
+                    .title("Giveaway ended")
+                    .description(format!("{} users claimed the giveaway of {} XEL", winners, format_xelis(giveaway.amount)))
+                    .thumbnail(ICON)
+                    .colour(COLOR);
+
+                if winners > 0 {
+                    embed = embed.field("Each user received", format!("{} XEL", format_xelis(share)), false)
+                        .field("Winners", mentions, false);
+                }
 
-        let embed = CreateEmbed::default()
-            .title("Deposit")
-            .description(format!("You received {} XEL", format_xelis(amount)))
-            .field("Transaction", transaction_hash.to_string(), false)
-            .thumbnail(ICON)
-            .colour(COLOR);
+                discord_id::<ChannelId>(giveaway.channel_id)?.send_message(http, CreateMessage::default().embed(embed)).await?;
+            },
+            UserApplication::Telegram(_) => {
+                let mut message = TelegramMessage::new(&bot, telegram_chat(giveaway.channel_id), None);
+                message.title("Giveaway ended")
+                    .field("Users that claimed the giveaway", winners.to_string(), true)
+                    .field("Total", format!("{} XEL", format_xelis(giveaway.amount)), true);
+
+                if winners > 0 {
+                    message.field("Each user received", format!("{} XEL", format_xelis(share)), true);
+                }
+
+                message.send().await?;
+            },
+            // Giveaways are only started on Discord and Telegram
+            UserApplication::Matrix(_) | UserApplication::Slack(_) | UserApplication::Twitch(_) => {}
+        }
 
-        channel.send_message(&http, CreateMessage::default().embed(embed)).await?;
         Ok(())
     }
 
-    // Notify a telegram user of a deposit
-    async fn notify_telegram_deposit(&self, bot: &Bot, user_id: u64, amount: u64, transaction_hash: &Hash) -> Result<()> {
-        TelegramMessage::new(&bot, ChatId(user_id as i64), None)
-            .title("Deposit")
-            .field("You received", format!("{} XEL", format_xelis(amount)), false)
-            .field("Transaction", transaction_hash.to_string(), false)
-            .send().await?;
+    // Notify the winner of a lottery round in DM
+    async fn notify_lottery_winner(&self, http: &Http, bot: &Bot, draw: &LotteryDraw) -> Result<()> {
+        let Some(winner) = draw.winner else {
+            return Ok(());
+        };
 
-        Ok(())
+        let fields = vec![
+            ("You won the lottery round".to_string(), format!("#{}", draw.round)),
+            ("You received".to_string(), format!("{} XEL", format_xelis(draw.pot))),
+            ("Winning ticket".to_string(), format!("{}/{}", draw.winning_ticket + 1, draw.total_tickets)),
+            ("Seed".to_string(), draw.seed.to_string())
+        ];
+        self.notify_user(http, bot, &winner, "Lottery", fields).await
+    }
+
+    // Check if a XEL deposit would bring the users balances over the liability cap
+    async fn exceeds_max_liability(&self, amount: u64) -> Result<bool> {
+        let Some(max_liability) = self.runtime().max_liability else {
+            return Ok(false);
+        };
+
+        let total = self.get_total_users_balance().await?;
+        Ok(total.saturating_add(amount) > max_liability)
+    }
+
+    // Check if new deposits are currently returned to their sender
+    pub async fn are_deposits_paused(&self) -> bool {
+        let Some(max_liability) = self.runtime().max_liability else {
+            return false;
+        };
+
+        match self.get_total_users_balance().await {
+            Ok(total) => total >= max_liability,
+            Err(e) => {
+                warn!("Couldn't compute the total liability: {}", e);
+                false
+            }
+        }
     }
 
     // Handle a confirmed transaction
     // This function is called when a transaction is in stable topoheight
     async fn handle_confirmed_transaction(&self, transaction: &TransactionEntry, http: &Http, bot: &Bot) -> Result<()> {
         match &transaction.entry {
-            EntryType::Incoming { from: _, transfers } => {
+            EntryType::Incoming { from, transfers } => {
+                // A TX can contain a transfer for each asset, they are all credited at once
+                let tx_key = transaction.hash.clone().into();
+                {
+                    let storage = self.wallet.get_storage().read().await;
+                    if storage.has_custom_data(HISTORY_TREE, &tx_key)? {
+                        // Already processed this TX
+                        info!("Already processed TX: {}", transaction.hash);
+                        return Ok(());
+                    }
+
+                    if storage.has_custom_data(deposits::RETURNED_DEPOSITS_TREE, &tx_key)? {
+                        info!("TX {} was returned to its sender", transaction.hash);
+                        return Ok(());
+                    }
+                }
+
+                // Transfers that don't name a user are kept for the operators
+                let mut unknown = Vec::new();
+                // Transfers to credit, they are all written before the TX is marked as processed
+                let mut credits = Vec::new();
+
                 // Check if there is any transfer that is for us
-                for transfer in transfers.iter().filter(|t| t.asset == XELIS_ASSET) {
-                    if let Some(data) = &transfer.extra_data {
-                        if let Some(user_id) = data.data().and_then(|v| v.as_value().and_then(|v| v.as_type::<UserApplication>()).ok()) {
-                            let amount = transfer.amount;
-                            {
-                                let mut storage = self.wallet.get_storage().write().await;
-                                let tx_key = transaction.hash.clone().into();
-                                if storage.has_custom_data(HISTORY_TREE, &tx_key)? {
-                                    // Already processed this TX
-                                    info!("Already processed TX: {}", transaction.hash);
-                                    continue;
-                                }
+                for transfer in transfers.iter() {
+                    let Some(data) = &transfer.extra_data else {
+                        unknown.push(UnknownTransfer { asset: transfer.asset.clone(), amount: transfer.amount, reason: "No extra data".to_string() });
+                        continue;
+                    };
+
+                    let Some(user_id) = data.data().and_then(|v| v.as_value().and_then(|v| v.as_type::<UserApplication>()).ok()) else {
+                        warn!("Invalid user application data: {:?}", data);
+                        unknown.push(UnknownTransfer { asset: transfer.asset.clone(), amount: transfer.amount, reason: "Invalid extra data".to_string() });
+                        continue;
+                    };
+                    let amount = transfer.amount;
+
+                    // Reprocessing a deposit of a forgotten user would bring its account back
+                    {
+                        let storage = self.wallet.get_storage().read().await;
+                        if self.is_forgotten_deposit(&storage, &user_id, transaction.topoheight) {
+                            info!("TX {} was credited to a user that asked to be forgotten", transaction.hash);
+                            continue;
+                        }
+                    }
+
+                    // A transfer sent back before the TX was processed again is never credited nor sent back twice
+                    let returned = {
+                        let storage = self.wallet.get_storage().read().await;
+                        self.get_returned_transfer(&storage, &transaction.hash, &transfer.asset)?
+                    };
+                    match returned {
+                        Some(true) => {
+                            info!("Transfer of TX {} in {} was already returned to its sender", transaction.hash, transfer.asset);
+                            continue;
+                        },
+                        Some(false) => {
+                            error!("The bot stopped while returning a transfer of TX {}, check if {} received it", transaction.hash, from);
+                            let alert = Alert::new("Deposit Return Interrupted", format!("The bot stopped while returning a transfer of TX {} over the liability cap, credit the user if {} never received it", transaction.hash, from), Colour::RED)
+                                .field("User", format!("{:?}", user_id), false);
+                            self.report_to_ops(http, &alert).await;
+                            continue;
+                        },
+                        None => {}
+                    }
+
+                    // Over the liability cap, XEL deposits go back to the sender
+                    if transfer.asset == XELIS_ASSET && self.exceeds_max_liability(amount).await? {
+                        match self.return_transfer(&transaction.hash, from.clone(), &transfer.asset, amount).await {
+                            Ok((returned, hash)) => {
+                                warn!("Liability cap reached, returned {} XEL of TX {} to {} in TX {}", format_xelis(returned), transaction.hash, from, hash);
 
-                                info!("Processing TX: {}", transaction.hash);
-                                // Calculate new balance
-                                let balance = self.get_balance_internal(&storage, &user_id);
-                                let new_balance = balance + amount;
-                                // Update balance
-                                storage.set_custom_data(BALANCES_TREE, &(&user_id).into(), &new_balance.into())?;
+                                // Never credited, so it's not part of the deposits of the user
+                                let returned = AssetBalance::xelis(returned);
+                                let fields = vec![
+                                    ("Deposits are temporarily paused, this amount has been sent back to the sender".to_string(), returned.to_string()),
+                                    ("Transaction".to_string(), hash.to_string())
+                                ];
+                                self.notify_user(http, bot, &user_id, "Deposit returned", fields).await?;
 
-                                // Store the TX hash in the history
-                                storage.set_custom_data(HISTORY_TREE, &tx_key, &(&user_id).into())?;
+                                continue;
+                            },
+                            // The deposit is credited as usual if it can't be returned
+                            Err(e) => {
+                                error!("Couldn't return TX {} over the liability cap: {}", transaction.hash, e);
+                                let alert = Alert::new("Deposit Not Returned", format!("TX {} is over the liability cap but couldn't be returned, it's credited as usual", transaction.hash), Colour::RED)
+                                    .field("Error", e.to_string(), false);
+                                self.report_to_ops(http, &alert).await;
                             }
+                        }
+                    }
 
-                            info!("User {:?} received {} XEL in TX {}", user_id, format_xelis(amount), transaction.hash);
-                            // Notify user
-                            match user_id {
-                                UserApplication::Telegram(user_id) => {
-                                    if let Err(e) = self.notify_telegram_deposit(&bot, user_id, amount, &transaction.hash).await {
-                                        error!("Error while notifying user of deposit: {:?}", e);
-                                    }
-                                },
-                                UserApplication::Discord(user_id) => {
-                                    if let Err(e) = self.notify_discord_deposit(&http, user_id, amount, &transaction.hash).await {
-                                        error!("Error while notifying user of deposit: {:?}", e);
-                                    }
-                                }
+                    credits.push((user_id, transfer));
+                }
+
+                // A failure or a crash leaves the TX either fully credited or not credited at all
+                if let Some((owner, _)) = credits.first() {
+                    let mut storage = self.wallet.get_storage().write().await;
+                    info!("Processing TX: {}", transaction.hash);
+                    let mut updates: Vec<BalanceUpdate> = Vec::with_capacity(credits.len());
+                    for (user_id, transfer) in credits.iter() {
+                        // Transfers of the same asset to the same user add up
+                        match updates.iter_mut().find(|update| update.user == *user_id && update.asset == transfer.asset) {
+                            Some(update) => update.after = update.after.checked_add(transfer.amount).context("Deposit credit overflow")?,
+                            None => {
+                                let before = self.get_asset_balance_internal(&storage, user_id, &transfer.asset);
+                                let after = before.checked_add(transfer.amount).context("Deposit credit overflow")?;
+                                updates.push(BalanceUpdate { user: *user_id, asset: transfer.asset.clone(), before, after });
                             }
-                        } else {
-                            warn!("Invalid user application data: {:?}", data);
                         }
                     }
+                    // The TX hash is stored in the history with the balances
+                    self.apply_deposit_updates(&mut storage, &transaction.hash, owner, updates)?;
+                }
+
+                for (user_id, transfer) in credits {
+                    let amount = {
+                        let storage = self.wallet.get_storage().read().await;
+                        self.get_asset_balance_display(&storage, &transfer.asset, transfer.amount).await
+                    };
+
+                    info!(
+                        event = BotEventKind::DepositCredited.name(), user:? = user_id, asset:% = transfer.asset, amount = transfer.amount, tx_hash:% = transaction.hash;
+                        "User {:?} received {} in TX {}", user_id, amount, transaction.hash
+                    );
+                    self.publish_event(BotEvent::new(BotEventKind::DepositCredited, user_id, transfer.asset.clone(), transfer.amount).with_tx(transaction.hash.clone()));
+                    // Notify user, the notification is sent again later if it fails
+                    let fields = vec![
+                        ("You received".to_string(), amount.to_string()),
+                        ("Transaction".to_string(), transaction.hash.to_string())
+                    ];
+                    self.notify_user(http, bot, &user_id, "Deposit", fields).await?;
+                }
+
+                if !unknown.is_empty() {
+                    self.record_unknown_deposit(http, &transaction.hash, from, transaction.topoheight, unknown).await?;
+                }
+            },
+            EntryType::Outgoing { .. } => {
+                // A TX broadcast before a crash may still be waiting to be sent again
+                let sent = {
+                    let mut storage = self.wallet.get_storage().write().await;
+                    self.finalize_unsent_transaction(&mut storage, &transaction.hash)?;
+                    self.take_sent_withdrawal(&mut storage, &transaction.hash)?
+                };
+
+                if let Some(sent) = sent {
+                    info!(
+                        event = BotEventKind::WithdrawalConfirmed.name(), user:? = sent.user, asset:% = sent.asset, amount = sent.amount, tx_hash:% = transaction.hash;
+                        "Withdrawal of {:?} confirmed in TX {}", sent.user, transaction.hash
+                    );
+                    self.publish_event(BotEvent::new(BotEventKind::WithdrawalConfirmed, sent.user, sent.asset.clone(), sent.amount).with_tx(transaction.hash.clone()));
+                    self.notify_withdrawal_confirmed(http, bot, &transaction.hash, &sent).await;
+                }
+
+                if self.pending_withdrawals.lock().expect("pending withdrawals lock").remove(&transaction.hash).is_some() {
+                    debug!("Withdrawal TX {} is confirmed", transaction.hash);
                 }
             },
             _ => {}
@@ -248,43 +1236,64 @@ impl WalletServiceImpl {
     // this function is called one time at WalletService creation,
     // and is notified by the wallet of any new transaction
     async fn event_loop(self: &WalletService, http: &Arc<Http>, bot: &Bot) -> Result<()> {
-        // Get all unconfirmed transactions
-        let mut unconfirmed_transactions: VecDeque<TransactionEntry> = VecDeque::new();
+        // Get all unconfirmed transactions, including the ones left by the previous run
+        let mut unconfirmed_transactions: VecDeque<TransactionEntry> = self.load_unconfirmed_transactions().await?;
 
         // Receiver for wallet events
         let mut receiver = self.wallet.subscribe_events().await;
 
-        // Receiver for stable topoheight changes
-        let mut stable_topoheight_receiver = {
-            let lock = self.wallet.get_network_handler();
-            let network_handler = lock.lock().await;
-
-            if let Some(network_handler) = network_handler.as_ref() {
-                network_handler.get_api().on_stable_topoheight_changed_event().await?
-            } else {
-                return Err(ServiceError::WalletOffline.into());
-            }
+        // Receiver for stable topoheight changes and TXs orphaned by a reorg
+        let Some(mut daemon_receiver) = self.wallet.subscribe_daemon_events().await? else {
+            return Err(ServiceError::WalletOffline.into());
         };
+        let daemon_topoheight = self.wallet.get_daemon_topoheight().await?.ok_or(ServiceError::WalletOffline)?;
+
+        // Withdrawals stay queued until the wallet caught up with the daemon
+        self.connection_established(daemon_topoheight).await?;
 
         // Handle events
         loop {
+            self.unconfirmed_count.store(unconfirmed_transactions.len(), Ordering::SeqCst);
             tokio::select! {
-                res = stable_topoheight_receiver.next() => {
-                    let event = res?;
+                res = daemon_receiver.recv() => {
+                    // Closed when the connection to the daemon is lost
+                    let event = res.ok_or(ServiceError::WalletOffline)??;
+                    self.last_event.store(get_current_time_in_millis(), Ordering::SeqCst);
+                    match event {
+                        DaemonEvent::StableTopoHeight(stable_topoheight) => {
+                            self.update_sync_state(stable_topoheight).await?;
+
+                            // Handle all transactions that are now confirmed
+                            while let Some(transaction) = unconfirmed_transactions.pop_front() {
+                                if transaction.topoheight <= stable_topoheight {
+                                    // Kept in storage until handled, an error retries it when the loop restarts
+                                    self.handle_confirmed_transaction(&transaction, http, bot).await?;
+                                    self.remove_unconfirmed_transaction(&transaction.hash).await?;
+                                } else {
+                                    info!("Re-adding TX to unconfirmed transactions: {}", transaction.hash);
+                                    unconfirmed_transactions.push_front(transaction);
+                                    break;
+                                }
+                            }
+                        },
+                        DaemonEvent::TransactionOrphaned(tx_hash) => {
+                            // Not credited yet, it's only dropped from the queue
+                            if let Some(index) = unconfirmed_transactions.iter().position(|t| t.hash == tx_hash) {
+                                warn!("Unconfirmed TX {} was orphaned", tx_hash);
+                                unconfirmed_transactions.remove(index);
+                                self.remove_unconfirmed_transaction(&tx_hash).await?;
+                                continue;
+                            }
 
-                    // Handle all transactions that are now confirmed
-                    while let Some(transaction) = unconfirmed_transactions.pop_front() {
-                        if transaction.topoheight <= event.new_stable_topoheight {
-                            self.handle_confirmed_transaction(&transaction, http, bot).await?;
-                        } else {
-                            info!("Re-adding TX to unconfirmed transactions: {}", transaction.hash);
-                            unconfirmed_transactions.push_front(transaction);
-                            break;
+                            for reversal in self.revert_orphaned_deposit(http, &tx_hash).await? {
+                                self.notify_deposit_reversal(http, bot, &reversal).await;
+                            }
                         }
                     }
                 },
                 res = receiver.recv() => {
                     let event = res?;
+                    self.last_event.store(get_current_time_in_millis(), Ordering::SeqCst);
                     match event {
                         Event::NewTransaction(transaction) => {
                             info!("New transaction: {}", transaction.hash);
@@ -293,8 +1302,15 @@ impl WalletServiceImpl {
                                 continue;
                             }
 
+                            if let EntryType::Outgoing { .. } = transaction.entry {
+                                self.mark_withdrawal_seen(&transaction.hash);
+                            }
+
+                            self.persist_unconfirmed_transaction(&transaction).await?;
                             unconfirmed_transactions.push_back(transaction);
                         }
+                        // The daemon was lost, the loop restarts on another one
+                        Event::Offline => return Err(ServiceError::WalletOffline.into()),
                         Event::Rescan { start_topoheight: _ } => {
                             warn!("Rescan event received, this should not happen");
                             self.locked.store(true, Ordering::SeqCst);
@@ -306,9 +1322,15 @@ impl WalletServiceImpl {
         }
     }
 
-    // Get the balance for a user based on its id
+    // Get the XEL balance for a user based on its id
     fn get_balance_internal(&self, storage: &EncryptedStorage, user: &UserApplication) -> u64 {
-        let balance = match storage.get_custom_data(BALANCES_TREE, &DataValue::Blob(user.to_bytes())) {
+        self.get_asset_balance_internal(storage, user, &XELIS_ASSET)
+    }
+
+    // Get the balance of an asset for a user based on its id
+    fn get_asset_balance_internal(&self, storage: &EncryptedStorage, user: &UserApplication, asset: &Hash) -> u64 {
+        let key = BalanceKey { user: *user, asset: asset.clone() };
+        let balance = match storage.get_custom_data(BALANCES_TREE, &DataValue::Blob(key.to_bytes())) {
             Ok(balance) => balance,
             Err(_) => return 0
         };
@@ -316,31 +1338,163 @@ impl WalletServiceImpl {
         balance
     }
 
-    // Get the balance for a user based on its id
-    pub async fn get_balance_for_user(&self, user: &UserApplication) -> u64 {
+    // Set the XEL balance of a user
+    fn set_balance_internal(&self, storage: &mut EncryptedStorage, user: &UserApplication, balance: u64, kind: MovementKind) -> Result<()> {
+        self.set_asset_balance_internal(storage, user, &XELIS_ASSET, balance, kind)
+    }
+
+    // Set the balance of an asset for a user
+    // The change is recorded in the journal with its reason, and posted to the ledger
+    fn set_asset_balance_internal(&self, storage: &mut EncryptedStorage, user: &UserApplication, asset: &Hash, balance: u64, kind: MovementKind) -> Result<()> {
+        let before = self.get_asset_balance_internal(storage, user, asset);
+        let key = BalanceKey { user: *user, asset: asset.clone() };
+        storage.set_custom_data(BALANCES_TREE, &DataValue::Blob(key.to_bytes()), &balance.into())?;
+        self.record_movement(storage, user, asset, kind, before, balance)?;
+        self.post_balance_change(storage, user, asset, kind, before, balance)
+    }
+
+    // Attach the name and decimals of an asset to an amount
+    // Assets unknown to the wallet are displayed in atomic units with their hash
+    async fn get_asset_balance_display(&self, storage: &EncryptedStorage, asset: &Hash, amount: u64) -> AssetBalance {
+        if *asset == XELIS_ASSET {
+            return AssetBalance::xelis(amount);
+        }
+
+        let (name, decimals) = match storage.get_asset(asset).await {
+            Ok(data) => (data.get_name().to_string(), data.get_decimals()),
+            Err(_) => (asset.to_string(), 0)
+        };
+
+        AssetBalance {
+            asset: asset.clone(),
+            name,
+            decimals,
+            amount
+        }
+    }
+
+    // Get the balances of a user in every asset, XEL is always first
+    pub async fn get_balances_for_user(&self, user: &UserApplication) -> Result<Vec<AssetBalance>> {
         let storage = self.wallet.get_storage().read().await;
-        self.get_balance_internal(&storage, user)
+        let mut balances = vec![self.get_asset_balance_display(&storage, &XELIS_ASSET, self.get_balance_internal(&storage, user)).await];
+        for key in storage.get_custom_tree_keys(&BALANCES_TREE.to_string(), &None, None, None)? {
+            let key: BalanceKey = key.as_type()?;
+            if key.user != *user || key.asset == XELIS_ASSET {
+                continue;
+            }
+
+            let amount = self.get_asset_balance_internal(&storage, user, &key.asset);
+            if amount > 0 {
+                balances.push(self.get_asset_balance_display(&storage, &key.asset, amount).await);
+            }
+        }
+
+        Ok(balances)
+    }
+
+    // Find the balance of a user in an asset from its name or hash
+    // XEL is used when no asset is given
+    pub async fn find_balance_for_user(&self, user: &UserApplication, asset: Option<&str>) -> Result<AssetBalance, ServiceError> {
+        let balances = self.get_balances_for_user(user).await?;
+        let Some(asset) = asset.map(str::trim).filter(|asset| !asset.is_empty()) else {
+            return Ok(balances.into_iter().next().unwrap_or_else(|| AssetBalance::xelis(0)));
+        };
+
+        balances.into_iter()
+            .find(|balance| balance.name.eq_ignore_ascii_case(asset) || balance.asset.to_string() == asset)
+            .ok_or_else(|| ServiceError::UnknownAsset(asset.to_string()))
     }
 
     // Get the total balance for all users
     pub async fn get_total_users_balance(&self) -> Result<u64> {
         let storage = self.wallet.get_storage().read().await;
+        self.get_total_users_balance_internal(&storage)
+    }
+
+    fn get_total_users_balance_internal(&self, storage: &EncryptedStorage) -> Result<u64> {
         let mut total = 0;
         for key in storage.get_custom_tree_keys(&BALANCES_TREE.to_string(), &None, None, None)? {
-            let user_id = key.to_type()?;
-            debug!("Getting balance for discord key: {:?}", user_id);
-            let balance: u64 = self.get_balance_internal(&storage, &user_id);
+            let key: BalanceKey = key.as_type()?;
+            // Only XEL is compared against the wallet balance
+            if key.asset != XELIS_ASSET {
+                continue;
+            }
+
+            debug!("Getting balance for key: {:?}", key.user);
+            let balance: u64 = self.get_balance_internal(storage, &key.user);
             total += balance;
         }
 
+        // Accounts owned by the bot are funded from the wallet too
+        for key in storage.get_custom_tree_keys(&INTERNAL_BALANCES_TREE.to_string(), &None, None, None)? {
+            total += storage.get_custom_data(INTERNAL_BALANCES_TREE, &key)?.to_value()?.to_u64()?;
+        }
+
+        // Funds escrowed are still owed to users
+        total += self.get_escrowed_internal(storage)?;
+
+        Ok(total)
+    }
+
+    // Get the XEL escrowed in the running giveaways, goals and challenges
+    fn get_escrowed_internal(&self, storage: &EncryptedStorage) -> Result<u64> {
+        let mut total = 0;
+        for key in storage.get_custom_tree_keys(&GIVEAWAYS_TREE.to_string(), &None, None, None)? {
+            let giveaway = self.get_giveaway_internal(storage, &key)?;
+            total += giveaway.amount;
+        }
+
+        // Rewards of the running goals
+        for key in storage.get_custom_tree_keys(&goals::GOALS_TREE.to_string(), &None, None, None)? {
+            let goal: Goal = storage.get_custom_data(goals::GOALS_TREE, &key)?.to_value()?.to_type()?;
+            total += goal.reward;
+        }
+
+        // Stakes of the pending challenges
+        for key in storage.get_custom_tree_keys(&games::GAMES_TREE.to_string(), &None, None, None)? {
+            let challenge = self.get_challenge_internal(storage, &key)?;
+            total += challenge.amount;
+        }
+
         Ok(total)
     }
 
+    // Count the users holding a balance entry, in any asset
+    pub async fn get_users_count(&self) -> Result<usize> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut users = HashSet::new();
+        for key in storage.get_custom_tree_keys(&BALANCES_TREE.to_string(), &None, None, None)? {
+            let key: BalanceKey = key.as_type()?;
+            users.insert(key.user);
+        }
+
+        Ok(users.len())
+    }
+
+    // Get the XEL balance of every user holding some, highest first
+    pub async fn get_users_balances(&self) -> Result<Vec<(UserApplication, u64)>> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut balances = Vec::new();
+        for key in storage.get_custom_tree_keys(&BALANCES_TREE.to_string(), &None, None, None)? {
+            let balance_key: BalanceKey = key.as_type()?;
+            if balance_key.asset != XELIS_ASSET {
+                continue;
+            }
+
+            let balance = storage.get_custom_data(BALANCES_TREE, &key)?.to_value()?.to_u64()?;
+            if balance > 0 {
+                balances.push((balance_key.user, balance));
+            }
+        }
+
+        balances.sort_by(|a, b| b.1.cmp(&a.1));
+
+        Ok(balances)
+    }
+
     // Get the balance for the service
     pub async fn get_wallet_balance(&self) -> Result<u64> {
-        let storage = self.wallet.get_storage().read().await;
-        let balance = storage.get_plaintext_balance_for(&XELIS_ASSET).await.unwrap_or(0);
-        Ok(balance)
+        Ok(self.wallet.get_balance(&XELIS_ASSET).await)
     }
 
     // Get the current wallet topoheight
@@ -355,8 +1509,11 @@ impl WalletServiceImpl {
         self.wallet.get_address_with(DataElement::Value(DataValue::Blob(user.to_bytes())))
     }
 
-    // Transfer XEL from one user to another
-    pub async fn transfer(&self, from: &UserApplication, to: &UserApplication, amount: u64) -> Result<(), ServiceError> {
+    // Transfer an asset from one user to another
+    // The community is where the tip happened, if any, for statistics
+    // The minimum tip and the statistics only apply to XEL
+    // A transfer with an operation ID is executed only once
+    pub async fn transfer(&self, from: &UserApplication, to: &UserApplication, asset: &Hash, amount: u64, community: Option<Community>, operation: Option<&OperationId>) -> Result<(), ServiceError> {
         if amount == 0 {
             return Err(ServiceError::Zero);
         }
@@ -365,73 +1522,359 @@ impl WalletServiceImpl {
             return Err(ServiceError::SelfTip);
         }
 
+        let is_xelis = *asset == XELIS_ASSET;
+        if is_xelis {
+            self.check_min_tip(community.as_ref(), amount).await?;
+        }
+
         let mut storage = self.wallet.get_storage().write().await;
-        let from_balance = self.get_balance_internal(&storage, &from);
+        self.check_operation(&storage, operation)?;
+        self.check_not_frozen(&storage, from)?;
+
+        let from_balance = self.get_asset_balance_internal(&storage, from, asset);
         if amount > from_balance {
+            if is_xelis {
+                return Err(ServiceError::NotEnoughFunds(amount));
+            }
+
+            return Err(ServiceError::NotEnoughAssetFunds(self.get_asset_balance_display(&storage, asset, amount).await));
+        }
+
+        let to_balance = self.get_asset_balance_internal(&storage, to, asset);
+        let updates = vec![
+            BalanceUpdate { user: *from, asset: asset.clone(), before: from_balance, after: from_balance - amount },
+            BalanceUpdate { user: *to, asset: asset.clone(), before: to_balance, after: to_balance.checked_add(amount).ok_or(ServiceError::Overflow)? }
+        ];
+
+        // Update balances, both or none
+        self.apply_balance_updates(&mut storage, updates, MovementKind::Tip)?;
+        self.mark_operation_processed(&mut storage, operation)?;
+
+        if is_xelis {
+            self.record_tip_stats(&mut storage, from, to, amount, community)?;
+            self.record_goal_progress(&mut storage, community, from, amount)?;
+        }
+
+        info!(
+            event = BotEventKind::Tip.name(), user:? = from, recipient:? = to, asset:% = asset, amount = amount;
+            "{:?} tipped {} atomic units of {} to {:?}", from, amount, asset, to
+        );
+        self.publish_event(BotEvent::new(BotEventKind::Tip, *from, asset.clone(), amount).with_recipient(*to));
+
+        Ok(())
+    }
+
+    // Transfer XEL from one user to many others
+    // All balances are updated under the same storage lock,
+    // either every recipient is credited or none
+    // A transfer with an operation ID is executed only once
+    pub async fn transfer_many(&self, from: &UserApplication, transfers: &[(UserApplication, u64)], community: Option<Community>, operation: Option<&OperationId>) -> Result<u64, ServiceError> {
+        if transfers.is_empty() {
+            return Err(ServiceError::NoRecipients);
+        }
+
+        // Compute the total to debit and merge duplicated recipients
+        let mut total: u64 = 0;
+        let mut credits: HashMap<&UserApplication, u64> = HashMap::new();
+        for (to, amount) in transfers {
+            if *amount == 0 {
+                return Err(ServiceError::Zero);
+            }
+
+            if from == to {
+                return Err(ServiceError::SelfTip);
+            }
+
+            total = total.checked_add(*amount).ok_or(ServiceError::Overflow)?;
+            *credits.entry(to).or_insert(0) += amount;
+        }
+
+        // Each recipient must receive at least the minimum tip
+        if let Some(min) = credits.values().min() {
+            self.check_min_tip(community.as_ref(), *min).await?;
+        }
+
+        let mut storage = self.wallet.get_storage().write().await;
+        self.check_operation(&storage, operation)?;
+        self.check_not_frozen(&storage, from)?;
+
+        let from_balance = self.get_balance_internal(&storage, from);
+        if total > from_balance {
+            return Err(ServiceError::NotEnoughFunds(total));
+        }
+
+        // Compute every new balance before writing any of them
+        let mut updates = vec![BalanceUpdate { user: *from, asset: XELIS_ASSET, before: from_balance, after: from_balance - total }];
+        for (to, amount) in credits.iter() {
+            let to_balance = self.get_balance_internal(&storage, to);
+            let balance = to_balance.checked_add(*amount).ok_or(ServiceError::Overflow)?;
+            updates.push(BalanceUpdate { user: **to, asset: XELIS_ASSET, before: to_balance, after: balance });
+        }
+
+        // Update balances, the recipients are all credited or none
+        self.apply_balance_updates(&mut storage, updates, MovementKind::Rain)?;
+        self.mark_operation_processed(&mut storage, operation)?;
+        for (to, amount) in credits.iter() {
+            self.record_tip_stats(&mut storage, from, to, *amount, community)?;
+        }
+        self.record_goal_progress(&mut storage, community, from, total)?;
+
+        info!(
+            event = BotEventKind::Rain.name(), user:? = from, amount = total, recipients = credits.len();
+            "{:?} rained {} XEL on {} users", from, format_xelis(total), credits.len()
+        );
+        for (to, amount) in credits {
+            self.publish_event(BotEvent::new(BotEventKind::Rain, *from, XELIS_ASSET, amount).with_recipient(*to));
+        }
+
+        Ok(total)
+    }
+
+    // Start a new giveaway, the amount is escrowed from the host balance
+    pub async fn create_giveaway(&self, host: &UserApplication, amount: u64, max_claims: u16, duration: Duration, channel_id: u64) -> Result<Giveaway, ServiceError> {
+        if amount == 0 {
+            return Err(ServiceError::Zero);
+        }
+
+        if max_claims == 0 || max_claims > MAX_GIVEAWAY_CLAIMS {
+            return Err(ServiceError::NoRecipients);
+        }
+
+        if amount / (max_claims as u64) == 0 {
+            return Err(ServiceError::AmountTooSmall(max_claims));
+        }
+
+        let mut storage = self.wallet.get_storage().write().await;
+        self.check_not_frozen(&storage, host)?;
+        let balance = self.get_balance_internal(&storage, host);
+        if amount > balance {
             return Err(ServiceError::NotEnoughFunds(amount));
         }
 
-        let to_balance = self.get_balance_internal(&storage, to);
+        // Find an unused id
+        let mut id = get_current_time_in_millis();
+        while storage.has_custom_data(GIVEAWAYS_TREE, &DataValue::U64(id))? {
+            id += 1;
+        }
 
-        // Update balances
-        storage.set_custom_data(BALANCES_TREE, &from.into(), &(from_balance - amount).into())?;
-        storage.set_custom_data(BALANCES_TREE, &to.into(), &(to_balance + amount).into())?;
+        let giveaway = Giveaway {
+            id,
+            host: *host,
+            amount,
+            max_claims,
+            expire_at: get_current_time_in_seconds() + duration.as_secs(),
+            channel_id,
+            claims: Vec::new()
+        };
+
+        info!("{:?} started giveaway {} of {} XEL", host, id, format_xelis(amount));
+        self.set_balance_internal(&mut storage, host, balance - amount, MovementKind::Giveaway)?;
+        storage.set_custom_data(GIVEAWAYS_TREE, &DataValue::U64(id), &DataValue::Blob(giveaway.to_bytes()).into())?;
+
+        Ok(giveaway)
+    }
+
+    // Load a giveaway from its storage key
+    fn get_giveaway_internal(&self, storage: &EncryptedStorage, key: &DataValue) -> Result<Giveaway> {
+        let giveaway = storage.get_custom_data(GIVEAWAYS_TREE, key)?.to_value()?.to_type()?;
+        Ok(giveaway)
+    }
+
+    // Register a user in a running giveaway
+    pub async fn claim_giveaway(&self, id: u64, user: &UserApplication) -> Result<Giveaway, ServiceError> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let key = DataValue::U64(id);
+        let mut giveaway = match self.get_giveaway_internal(&storage, &key) {
+            Ok(giveaway) => giveaway,
+            Err(_) => return Err(ServiceError::GiveawayNotFound)
+        };
+
+        if giveaway.is_expired(get_current_time_in_seconds()) {
+            return Err(ServiceError::GiveawayNotFound);
+        }
+
+        if giveaway.host == *user {
+            return Err(ServiceError::SelfClaim);
+        }
+
+        if giveaway.claims.contains(user) {
+            return Err(ServiceError::AlreadyClaimed);
+        }
+
+        if giveaway.is_full() {
+            return Err(ServiceError::GiveawayFull);
+        }
+
+        giveaway.claims.push(*user);
+        storage.set_custom_data(GIVEAWAYS_TREE, &key, &DataValue::Blob(giveaway.to_bytes()).into())?;
+
+        Ok(giveaway)
+    }
+
+    // Settle all giveaways that are expired or full
+    // Returns each settled giveaway with the share received by every claimant
+    pub async fn settle_giveaways(&self) -> Result<Vec<(Giveaway, u64)>> {
+        let now = get_current_time_in_seconds();
+        let mut storage = self.wallet.get_storage().write().await;
+        let mut settled = Vec::new();
+        for key in storage.get_custom_tree_keys(&GIVEAWAYS_TREE.to_string(), &None, None, None)? {
+            // One undecodable giveaway must not keep the others from being settled
+            let giveaway = match self.get_giveaway_internal(&storage, &key) {
+                Ok(giveaway) => giveaway,
+                Err(e) => {
+                    error!("Skipping giveaway {:?} that can't be read: {:#}", key, e);
+                    continue;
+                }
+            };
+            if !giveaway.is_expired(now) && !giveaway.is_full() {
+                continue;
+            }
+
+            // Split the pot, the remaining dust goes back to the host
+            let share = giveaway.amount.checked_div(giveaway.claims.len() as u64).unwrap_or(0);
+            let refund = giveaway.amount - share * giveaway.claims.len() as u64;
+            let mut credits: HashMap<UserApplication, u64> = giveaway.claims.iter().map(|claim| (*claim, share)).collect();
+            if refund > 0 {
+                *credits.entry(giveaway.host).or_insert(0) += refund;
+            }
+
+            // The giveaway stays pending if its claimants can't all be credited
+            let mut updates = Vec::with_capacity(credits.len());
+            for (user, amount) in credits {
+                let balance = self.get_balance_internal(&storage, &user);
+                updates.push(BalanceUpdate { user, asset: XELIS_ASSET, before: balance, after: balance.checked_add(amount).context("Giveaway credit overflow")? });
+            }
+            self.apply_balance_updates(&mut storage, updates, MovementKind::Giveaway)?;
+
+            info!("Giveaway {} settled: {} claims of {} XEL, {} XEL refunded", giveaway.id, giveaway.claims.len(), format_xelis(share), format_xelis(refund));
+            storage.delete_custom_data(GIVEAWAYS_TREE, &key)?;
+            settled.push((giveaway, share));
+        }
+
+        Ok(settled)
+    }
+
+    // Estimate the fee paid in XEL to withdraw an amount, nothing is sent
+    pub async fn estimate_withdraw_fee(&self, to: &Address, asset: &Hash, amount: u64) -> Result<u64, ServiceError> {
+        let fee = self.wallet.estimate_fees(withdraw_builder(to, asset, amount)).await?;
+        Ok(fee)
+    }
+
+    // Check that a withdrawal reaches the minimum, only XEL has one
+    pub fn check_min_withdraw(&self, asset: &Hash, amount: u64) -> Result<(), ServiceError> {
+        let min_withdraw = self.runtime().min_withdraw;
+        if *asset == XELIS_ASSET && amount < min_withdraw {
+            return Err(ServiceError::BelowMinWithdraw(min_withdraw));
+        }
 
         Ok(())
     }
 
-    // Withdraw XEL from the service to an address
-    pub async fn withdraw(&self, user: &UserApplication, to: Address, amount: u64) -> Result<Hash, ServiceError> {
+    // Largest amount of an asset a user can withdraw
+    // The fee is kept aside when withdrawing XEL, other assets can be fully withdrawn
+    // XEL is also capped by the withdrawal limit of the user
+    pub async fn get_max_withdraw(&self, user: &UserApplication, to: &Address, asset: &Hash) -> Result<u64, ServiceError> {
+        let balance = {
+            let storage = self.wallet.get_storage().read().await;
+            self.get_asset_balance_internal(&storage, user, asset)
+        };
+
+        if *asset != XELIS_ASSET {
+            return Ok(balance);
+        }
+
+        let fee = self.estimate_withdraw_fee(to, asset, balance).await?;
+        let max = balance.checked_sub(fee)
+            .filter(|amount| *amount > 0)
+            .ok_or(ServiceError::NotEnoughFundsForFee(fee))?;
+
+        // Stay within the withdrawal limit of the user
+        match (self.runtime().max_daily_withdraw, self.get_remaining_withdraw_limit(user).await) {
+            (Some(limit), Some(0)) => Err(ServiceError::WithdrawLimit(limit, 0)),
+            (_, Some(remaining)) => Ok(max.min(remaining)),
+            _ => Ok(max)
+        }
+    }
+
+    // Withdraw an asset from the service to an address
+    // The fee is always paid from the XEL balance of the user
+    // Users with a withdrawal PIN must give it
+    // A withdrawal with an operation ID is broadcast only once
+    pub async fn withdraw(&self, user: &UserApplication, to: Address, asset: &Hash, amount: u64, pin: Option<&str>, operation: Option<&OperationId>) -> Result<Hash, ServiceError> {
         if amount == 0 {
             return Err(ServiceError::Zero);
         }
 
+        self.check_min_withdraw(asset, amount)?;
+
         if self.locked.load(Ordering::SeqCst) {
             return Err(ServiceError::WithdrawLocked);
         }
 
-        let builder = TransactionTypeBuilder::Transfers(vec![TransferBuilder {
-                amount,
-                asset: XELIS_ASSET,
-                destination: to.clone(),
-                extra_data: None,
-                encrypt_extra_data: true,
+        self.verify_withdraw_pin(user, pin).await?;
+
+        // Exemptions are checked before locking the storage
+        let limited = *asset == XELIS_ASSET && self.runtime().max_daily_withdraw.is_some() && !self.is_exempt(user, &[]).await;
+
+        // A TX can't be built on the state of a wallet that isn't synced
+        if !self.is_synced() {
+            self.queue_withdrawal(user, &to, asset, amount, limited, operation).await?;
+            return Err(ServiceError::WithdrawalQueued);
+        }
+
+        self.send_withdrawal(user, to, asset, amount, limited, operation).await
+    }
+
+    // Build and broadcast a withdrawal, once the PIN and the lock are checked
+    async fn send_withdrawal(&self, user: &UserApplication, to: Address, asset: &Hash, amount: u64, limited: bool, operation: Option<&OperationId>) -> Result<Hash, ServiceError> {
+        let is_xelis = *asset == XELIS_ASSET;
+        let _queue = self.submission_queue.lock().await;
+        let builder = withdraw_builder(&to, asset, amount);
+        let fee = self.wallet.estimate_fees(builder.clone()).await?;
+
+        let storage = self.wallet.get_storage().write().await;
+        {
+            self.check_operation(&storage, operation)?;
+            self.check_not_frozen(&storage, user)?;
+
+            if limited {
+                self.check_withdraw_limit(&storage, user, amount)?;
             }
-        ]);
 
-        let fee = self.wallet.estimate_fees(builder.clone(), Default::default(), Default::default()).await?;
+            self.check_withdraw_whitelist(&storage, user, &to)?;
 
-        let mut storage = self.wallet.get_storage().write().await;
-        let (balance, fee, mut state, transaction) = {
-            let balance = self.get_balance_internal(&storage, user);
+            let balance = self.get_asset_balance_internal(&storage, user, asset);
             if amount > balance {
-                return Err(ServiceError::NotEnoughFunds(amount));
+                if is_xelis {
+                    return Err(ServiceError::NotEnoughFunds(amount));
+                }
+
+                return Err(ServiceError::NotEnoughAssetFunds(self.get_asset_balance_display(&storage, asset, amount).await));
             }
 
             // Verify if he has enough with fees included
-            if fee + amount > balance {
+            let xelis_balance = self.get_balance_internal(&storage, user);
+            let required = if is_xelis { fee + amount } else { fee };
+            if required > xelis_balance {
                 return Err(ServiceError::NotEnoughFundsForFee(fee));
             }
+        }
 
-            let (transaction, state) = self.wallet.create_transaction_with_storage(
-                &storage,
-                builder,
-                FeeBuilder::Fixed(fee),
-                Default::default(),
-                None
-            ).await?;
-
-            (balance, fee, state, transaction)
-        };
-
-        self.wallet.submit_transaction(&transaction).await?;
-
-        let tx_hash = transaction.hash();
-        info!("Withdrawing {} XEL to {} in TX {} from {:?}", format_xelis(amount), to, tx_hash, user);
+        // The balances are debited before the TX is broadcast and given back if it's rejected
+        let debit = Debit { user: Some(*user), asset: asset.clone(), amount };
+        let (tx_hash, mut storage) = self.broadcast_transaction(storage, builder, fee, debit).await?;
+        let display = self.get_asset_balance_display(&storage, asset, amount).await;
+        info!(
+            event = BotEventKind::WithdrawalBroadcast.name(), user:? = user, asset:% = asset, amount = amount, address:% = to, tx_hash:% = tx_hash;
+            "Withdrawing {} to {} in TX {} from {:?}", display, to, tx_hash, user
+        );
+        self.mark_operation_processed(&mut storage, operation)?;
+
+        if is_xelis {
+            self.record_withdrawal(&mut storage, user, amount)?;
+        }
 
-        // Update balance
-        storage.set_custom_data(BALANCES_TREE, &user.into(), &(balance - (fee + amount)).into())?;
-        state.apply_changes(&mut storage).await?;
+        self.publish_event(BotEvent::new(BotEventKind::WithdrawalBroadcast, *user, asset.clone(), amount).with_tx(tx_hash.clone()));
 
         Ok(tx_hash)
     }
@@ -441,82 +1884,157 @@ impl WalletServiceImpl {
     pub async fn clear_balances(&self) -> Result<(), ServiceError> {
         warn!("Clearing balances tree");
         let mut storage = self.wallet.get_storage().write().await;
+        // Keep a trace of the cleared balances in the journal
+        for key in storage.get_custom_tree_keys(&BALANCES_TREE.to_string(), &None, None, None)? {
+            let key: BalanceKey = key.as_type().context("Invalid balance key")?;
+            self.set_asset_balance_internal(&mut storage, &key.user, &key.asset, 0, MovementKind::Admin)?;
+        }
         storage.clear_custom_tree(BALANCES_TREE)?;
 
         Ok(())
     }
 
+    // Lock or unlock the withdrawals of all users
+    pub fn set_withdraw_locked(&self, locked: bool) {
+        warn!("Withdrawals {} by an operator", if locked { "locked" } else { "unlocked" });
+        self.locked.store(locked, Ordering::SeqCst);
+    }
+
+    // Withdrawals are locked by an operator or after an unexpected rescan
+    pub fn is_withdraw_locked(&self) -> bool {
+        self.locked.load(Ordering::SeqCst)
+    }
+
+    // Let this instance process deposits and withdrawals, or keep it on standby
+    pub fn set_leader(&self, leader: bool) {
+        self.leader.store(leader, Ordering::SeqCst);
+    }
+
+    // Only the leader changes the wallet state, the other instances serve read-only commands
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::SeqCst)
+    }
+
     // Add balance to a user
-    pub async fn add_balance(&self, user: &UserApplication, amount: u64) -> Result<(), ServiceError> {
-        warn!("Adding {} XEL to {:?}", format_xelis(amount), user);
+    // Credit XEL to a user by hand, the reason is kept with the journal entry
+    pub async fn add_balance(&self, user: &UserApplication, amount: u64, reason: &str) -> Result<(), ServiceError> {
+        let reason = journal::check_note(reason)?;
+        if amount == 0 {
+            return Err(ServiceError::Zero);
+        }
+
+        warn!("Adding {} XEL to {:?}: {}", format_xelis(amount), user, reason);
         let mut storage = self.wallet.get_storage().write().await;
         let balance = self.get_balance_internal(&storage, user);
-        storage.set_custom_data(BALANCES_TREE, &user.into(), &(balance + amount).into())?;
+        let new_balance = balance.checked_add(amount).ok_or(ServiceError::Overflow)?;
+        let id = self.get_next_movement_id(&storage);
+        self.set_balance_internal(&mut storage, user, new_balance, MovementKind::Admin)?;
+        self.record_movement_note(&mut storage, id, reason)?;
 
         Ok(())
     }
 
-    pub async fn remove_balance(&self, user: &UserApplication, amount: u64) -> Result<(), ServiceError> {
-        warn!("Removing {} XEL from {:?}", format_xelis(amount), user);
+    // Debit XEL from a user by hand, the reason is kept with the journal entry
+    pub async fn remove_balance(&self, user: &UserApplication, amount: u64, reason: &str) -> Result<(), ServiceError> {
+        let reason = journal::check_note(reason)?;
+        if amount == 0 {
+            return Err(ServiceError::Zero);
+        }
+
+        warn!("Removing {} XEL from {:?}: {}", format_xelis(amount), user, reason);
         let mut storage = self.wallet.get_storage().write().await;
         let balance = self.get_balance_internal(&storage, user);
         if amount > balance {
             return Err(ServiceError::NotEnoughFunds(amount));
         }
-        storage.set_custom_data(BALANCES_TREE, &user.into(), &(balance - amount).into())?;
+        let id = self.get_next_movement_id(&storage);
+        self.set_balance_internal(&mut storage, user, balance - amount, MovementKind::Admin)?;
+        self.record_movement_note(&mut storage, id, reason)?;
 
         Ok(())
     }
 
     // Withdraw all XEL from the service to an address
     pub async fn withdraw_all(&self, to: Address) -> Result<(), ServiceError> {
-        let amount = {
-            let storage = self.wallet.get_storage().read().await;
-            storage.get_plaintext_balance_for(&XELIS_ASSET).await.unwrap_or(0)
-        };
-
+        let amount = self.wallet.get_balance(&XELIS_ASSET).await;
         self.withdraw_to(to, amount).await
     }
 
     // Withdraw XEL from the service to an address
     pub async fn withdraw_to(&self, to: Address, amount: u64) -> Result<(), ServiceError> {
-        let fee = self.wallet.estimate_fees(
-            TransactionTypeBuilder::Transfers(vec![TransferBuilder {
-                amount,
-                asset: XELIS_ASSET,
-                destination: to.clone(),
-                extra_data: None,
-                encrypt_extra_data: true,
-            }]),
-            Default::default(),
-            Default::default()
-        ).await?;
+        if !self.is_synced() {
+            return Err(ServiceError::Syncing);
+        }
 
-        let mut storage = self.wallet.get_storage().write().await;
-        let (transaction, mut state) = self.wallet.create_transaction_with_storage(
-            &storage,
-            TransactionTypeBuilder::Transfers(vec![TransferBuilder {
-                amount: amount - fee,
-                asset: XELIS_ASSET,
-                destination: to.clone(),
-                extra_data: None,
-                encrypt_extra_data: true,
-            }]),
-            FeeBuilder::Fixed(fee),
-            Default::default(),
-            None
-        ).await?;
-
-        self.wallet.submit_transaction(&transaction).await?;
-
-        let tx_hash = transaction.hash();
-        info!("Withdrawing {} XEL to {} in TX {}", format_xelis(amount - fee), to, tx_hash);
+        let _queue = self.submission_queue.lock().await;
+        let fee = self.wallet.estimate_fees(withdraw_builder(&to, &XELIS_ASSET, amount)).await?;
 
-        state.apply_changes(&mut storage).await?;
+        let storage = self.wallet.get_storage().write().await;
+        let debit = Debit { user: None, asset: XELIS_ASSET, amount: amount - fee };
+        let (tx_hash, _) = self.broadcast_transaction(storage, withdraw_builder(&to, &XELIS_ASSET, amount - fee), fee, debit).await?;
+        info!("Withdrawing {} XEL to {} in TX {}", format_xelis(amount - fee), to, tx_hash);
 
         Ok(())
     }
 
+    // Send funds received by the wallet back to an address
+    // The fee is taken from the amount, returns the amount sent and the TX hash
+    pub async fn send_back(&self, to: Address, asset: &Hash, amount: u64) -> Result<(u64, Hash), ServiceError> {
+        let builder = |amount| TransactionTypeBuilder::Transfers(vec![TransferBuilder {
+            amount,
+            asset: asset.clone(),
+            destination: to.clone(),
+            extra_data: None,
+            encrypt_extra_data: true,
+        }]);
+
+        let _queue = self.submission_queue.lock().await;
+        let fee = self.wallet.estimate_fees(builder(amount)).await?;
+        // Only XEL can pay for its own fee
+        let returned = if *asset == XELIS_ASSET {
+            amount.checked_sub(fee).filter(|returned| *returned > 0).ok_or(ServiceError::NotEnoughFundsForFee(fee))?
+        } else {
+            amount
+        };
+
+        let storage = self.wallet.get_storage().write().await;
+        // Deposits returned from a user balance are given back by return_deposit on errors only
+        let debit = Debit { user: None, asset: asset.clone(), amount: returned };
+        let (tx_hash, _) = self.broadcast_transaction(storage, builder(returned), fee, debit).await?;
+
+        Ok((returned, tx_hash))
+    }
+
+    // Count the TXs sent by the wallet that aren't confirmed yet
+    pub fn get_pending_withdrawals_count(&self) -> usize {
+        self.pending_withdrawals.lock().expect("pending withdrawals lock").len()
+    }
+
+    // Get the depth of the processing queues
+    pub fn get_queue_status(&self) -> QueueStatus {
+        let last_event = match self.last_event.load(Ordering::SeqCst) {
+            0 => None,
+            timestamp => Some(Duration::from_millis(get_current_time_in_millis().saturating_sub(timestamp)))
+        };
+
+        QueueStatus {
+            unconfirmed: self.unconfirmed_count.load(Ordering::SeqCst),
+            notifications: self.notifier.pending_count(),
+            stuck_withdrawals: self.get_stuck_withdrawals_count(),
+            last_event,
+            discord_errors: self.api_errors.get_stats(Platform::Discord),
+            telegram_errors: self.api_errors.get_stats(Platform::Telegram),
+            matrix_errors: self.api_errors.get_stats(Platform::Matrix),
+            slack_errors: self.api_errors.get_stats(Platform::Slack),
+            twitch_errors: self.api_errors.get_stats(Platform::Twitch)
+        }
+    }
+
+    // Errors answered by the platform APIs
+    pub fn api_errors(&self) -> &ApiErrorMonitor {
+        &self.api_errors
+    }
+
     // Get the network of the wallet
     pub fn network(&self) -> &Network {
         self.wallet.get_network()
@@ -529,7 +2047,78 @@ impl WalletServiceImpl {
 
     // Rescan the wallet
     pub async fn rescan(&self) -> Result<(), ServiceError> {
-        self.wallet.rescan(0, true).await?;
+        self.wallet.rescan().await?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every encoding ever written must decode to the same user forever
+    // The ids are written as 8 big endian bytes
+    const ID: u64 = 0x0102_0304_0506_0708;
+
+    // Decode a known encoding, then check how the user is written today
+    fn check_vector(bytes: &[u8], expected: UserApplication, written: &[u8]) {
+        let user = UserApplication::from_bytes(bytes).expect("known encoding must decode");
+        assert_eq!(user, expected);
+        assert_eq!(user.to_bytes(), written);
+    }
+
+    #[test]
+    fn telegram_v0() {
+        let bytes = [TELEGRAM_TAG, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+        check_vector(&bytes, UserApplication::Telegram(ID), &bytes);
+    }
+
+    #[test]
+    fn discord_v0() {
+        let bytes = [DISCORD_TAG, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x39];
+        check_vector(&bytes, UserApplication::Discord(12345), &bytes);
+    }
+
+    // Telegram and Discord users are still written in v0
+    #[test]
+    fn telegram_v1() {
+        check_vector(
+            &[VERSIONED_FORMAT, 1, TELEGRAM_TAG, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08],
+            UserApplication::Telegram(ID),
+            &[TELEGRAM_TAG, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]
+        );
+    }
+
+    #[test]
+    fn discord_v1() {
+        check_vector(
+            &[VERSIONED_FORMAT, 1, DISCORD_TAG, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08],
+            UserApplication::Discord(ID),
+            &[DISCORD_TAG, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]
+        );
+    }
+
+    #[test]
+    fn matrix_v1() {
+        let bytes = [VERSIONED_FORMAT, 1, MATRIX_TAG, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+        check_vector(&bytes, UserApplication::Matrix(ID), &bytes);
+    }
+
+    #[test]
+    fn slack_v1() {
+        let bytes = [VERSIONED_FORMAT, 1, SLACK_TAG, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+        check_vector(&bytes, UserApplication::Slack(ID), &bytes);
+    }
+
+    #[test]
+    fn twitch_v1() {
+        let bytes = [VERSIONED_FORMAT, 1, TWITCH_TAG, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+        check_vector(&bytes, UserApplication::Twitch(ID), &bytes);
+    }
+
+    // Platforms added after v0 can't be read from a v0 encoding
+    #[test]
+    fn matrix_v0_is_refused() {
+        assert!(UserApplication::from_bytes(&[MATRIX_TAG, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]).is_err());
+    }
+}
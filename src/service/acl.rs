@@ -0,0 +1,109 @@
+use std::fmt;
+
+use anyhow::Result;
+use log::info;
+use xelis_common::{
+    api::{DataElement, DataValue},
+    serializer::{Reader, ReaderError, Serializer, Writer}
+};
+
+use super::{UserApplication, WalletServiceImpl};
+
+// Users and roles exempted by the operator from the anti-abuse limits
+pub(super) const EXEMPTIONS_TREE: &str = "exemptions";
+
+// Entry of the operator ACL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exemption {
+    User(UserApplication),
+    // Discord role id, role ids are unique across guilds
+    DiscordRole(u64)
+}
+
+impl fmt::Display for Exemption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Exemption::User(UserApplication::Telegram(id)) => write!(f, "Telegram user {}", id),
+            Exemption::User(UserApplication::Discord(id)) => write!(f, "Discord user {}", id),
+            Exemption::User(UserApplication::Matrix(id)) => write!(f, "Matrix user {}", id),
+            Exemption::User(UserApplication::Slack(id)) => write!(f, "Slack user {}", id),
+            Exemption::User(UserApplication::Twitch(id)) => write!(f, "Twitch user {}", id),
+            Exemption::DiscordRole(id) => write!(f, "Discord role {}", id)
+        }
+    }
+}
+
+impl Serializer for Exemption {
+    fn write(&self, writer: &mut Writer) {
+        match self {
+            Exemption::User(user) => {
+                writer.write_u8(0);
+                user.write(writer);
+            },
+            Exemption::DiscordRole(id) => {
+                writer.write_u8(1);
+                id.write(writer);
+            }
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let exemption = match reader.read_u8()? {
+            0 => Exemption::User(UserApplication::read(reader)?),
+            1 => Exemption::DiscordRole(reader.read_u64()?),
+            _ => return Err(ReaderError::InvalidValue)
+        };
+
+        Ok(exemption)
+    }
+}
+
+impl WalletServiceImpl {
+    // Exempt a user or a role from the cooldowns and limits
+    pub async fn add_exemption(&self, exemption: Exemption) -> Result<()> {
+        info!("Adding exemption for {}", exemption);
+        let mut storage = self.wallet.get_storage().write().await;
+        storage.set_custom_data(EXEMPTIONS_TREE, &DataValue::Blob(exemption.to_bytes()), &DataElement::Value(DataValue::Bool(true)))?;
+
+        Ok(())
+    }
+
+    // Remove an exemption, returns false if it didn't exist
+    pub async fn remove_exemption(&self, exemption: Exemption) -> Result<bool> {
+        info!("Removing exemption for {}", exemption);
+        let mut storage = self.wallet.get_storage().write().await;
+        let key = DataValue::Blob(exemption.to_bytes());
+        if !storage.has_custom_data(EXEMPTIONS_TREE, &key)? {
+            return Ok(false);
+        }
+
+        storage.delete_custom_data(EXEMPTIONS_TREE, &key)?;
+        Ok(true)
+    }
+
+    // List all the exemptions
+    pub async fn get_exemptions(&self) -> Result<Vec<Exemption>> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut exemptions = Vec::new();
+        for key in storage.get_custom_tree_keys(&EXEMPTIONS_TREE.to_string(), &None, None, None)? {
+            exemptions.push(key.as_type()?);
+        }
+
+        Ok(exemptions)
+    }
+
+    // Check if a user is exempted, directly or through one of its Discord roles
+    pub async fn is_exempt(&self, user: &UserApplication, roles: &[u64]) -> bool {
+        let storage = self.wallet.get_storage().read().await;
+        let exemptions = std::iter::once(Exemption::User(*user))
+            .chain(roles.iter().map(|role| Exemption::DiscordRole(*role)));
+
+        for exemption in exemptions {
+            if storage.has_custom_data(EXEMPTIONS_TREE, &DataValue::Blob(exemption.to_bytes())).unwrap_or(false) {
+                return true;
+            }
+        }
+
+        false
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::{anyhow, Result};
+use xelis_common::{
+    api::DataValue,
+    crypto::Address,
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{ServiceError, UserApplication, WalletServiceImpl};
+
+// Labelled withdrawal addresses saved by each user
+pub(super) const ADDRESS_BOOK_TREE: &str = "address_book";
+
+// Addresses a user can save, Discord doesn't suggest more than 25 labels
+pub const MAX_ADDRESSES: usize = 25;
+// Characters of a label
+pub const MAX_ADDRESS_LABEL_LENGTH: usize = 32;
+
+// Storage key of a saved address
+pub(super) struct AddressBookKey {
+    user: UserApplication,
+    label: String
+}
+
+impl Serializer for AddressBookKey {
+    fn write(&self, writer: &mut Writer) {
+        self.user.write(writer);
+        self.label.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let user = UserApplication::read(reader)?;
+        let label = String::read(reader)?;
+
+        Ok(Self {
+            user,
+            label
+        })
+    }
+}
+
+// Address saved under a label, as stored
+pub(super) struct AddressEntry {
+    address: String,
+    // Timestamp in seconds, withdrawal whitelists only accept it after a delay
+    added_at: u64
+}
+
+impl Serializer for AddressEntry {
+    fn write(&self, writer: &mut Writer) {
+        self.address.write(writer);
+        self.added_at.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let address = String::read(reader)?;
+        let added_at = reader.read_u64()?;
+
+        Ok(Self {
+            address,
+            added_at
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SavedAddress {
+    pub label: String,
+    pub address: Address,
+    pub added_at: u64
+}
+
+// Labels are case insensitive and can't be mistaken for an address
+fn normalize_label(label: &str) -> Result<String, ServiceError> {
+    let label = label.trim().to_lowercase();
+    if label.is_empty()
+        || label.len() > MAX_ADDRESS_LABEL_LENGTH
+        || !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(ServiceError::InvalidAddressLabel(MAX_ADDRESS_LABEL_LENGTH));
+    }
+
+    Ok(label)
+}
+
+impl WalletServiceImpl {
+    // Parse an address and verify it belongs to the network of the wallet
+    pub fn parse_address(&self, address: &str) -> Result<Address, ServiceError> {
+        let address = Address::from_string(address).map_err(|e| ServiceError::InvalidAddress(e.to_string()))?;
+        if address.is_mainnet() != self.network().is_mainnet() {
+            return Err(ServiceError::InvalidNetwork);
+        }
+
+        Ok(address)
+    }
+
+    // Save an address under a label, replacing the address already saved with it
+    pub async fn add_address(&self, user: &UserApplication, label: &str, address: &str) -> Result<String, ServiceError> {
+        let label = normalize_label(label)?;
+        let address = self.parse_address(address)?;
+        let addresses = self.get_addresses(user).await?;
+        if addresses.len() >= MAX_ADDRESSES && !addresses.iter().any(|saved| saved.label == label) {
+            return Err(ServiceError::AddressBookFull(MAX_ADDRESSES));
+        }
+
+        let key = AddressBookKey { user: *user, label: label.clone() };
+        let entry = AddressEntry { address: address.to_string(), added_at: get_current_time_in_seconds() };
+        let mut storage = self.wallet.get_storage().write().await;
+        storage.set_custom_data(ADDRESS_BOOK_TREE, &DataValue::Blob(key.to_bytes()), &DataValue::Blob(entry.to_bytes()).into())?;
+
+        Ok(label)
+    }
+
+    // Remove a saved address, false if there was none with this label
+    pub async fn remove_address(&self, user: &UserApplication, label: &str) -> Result<bool> {
+        let key = DataValue::Blob(AddressBookKey { user: *user, label: label.trim().to_lowercase() }.to_bytes());
+        let mut storage = self.wallet.get_storage().write().await;
+        if !storage.has_custom_data(ADDRESS_BOOK_TREE, &key)? {
+            return Ok(false);
+        }
+
+        storage.delete_custom_data(ADDRESS_BOOK_TREE, &key)?;
+
+        Ok(true)
+    }
+
+    // Labels and entries of the address book of a user
+    fn get_address_entries(&self, storage: &EncryptedStorage, user: &UserApplication) -> Result<Vec<(String, AddressEntry)>> {
+        let mut entries = Vec::new();
+        for key in storage.get_custom_tree_keys(&ADDRESS_BOOK_TREE.to_string(), &None, None, None)? {
+            let book_key: AddressBookKey = key.as_type()?;
+            if book_key.user != *user {
+                continue;
+            }
+
+            let entry = storage.get_custom_data(ADDRESS_BOOK_TREE, &key)?.to_value()?.to_type()?;
+            entries.push((book_key.label, entry));
+        }
+
+        Ok(entries)
+    }
+
+    // Get the saved addresses of a user, sorted by label
+    pub async fn get_addresses(&self, user: &UserApplication) -> Result<Vec<SavedAddress>> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut addresses = Vec::new();
+        for (label, entry) in self.get_address_entries(&storage, user)? {
+            let address = Address::from_string(&entry.address).map_err(|e| anyhow!("Invalid saved address {}: {}", entry.address, e))?;
+            addresses.push(SavedAddress {
+                label,
+                address,
+                added_at: entry.added_at
+            });
+        }
+
+        addresses.sort_by(|a, b| a.label.cmp(&b.label));
+
+        Ok(addresses)
+    }
+
+    // Remove the whole address book of a user
+    pub(super) fn delete_addresses_internal(&self, storage: &mut EncryptedStorage, user: &UserApplication) -> Result<()> {
+        let mut keys = Vec::new();
+        for key in storage.get_custom_tree_keys(&ADDRESS_BOOK_TREE.to_string(), &None, None, None)? {
+            let book_key: AddressBookKey = key.as_type()?;
+            if book_key.user == *user {
+                keys.push(key);
+            }
+        }
+
+        for key in keys {
+            storage.delete_custom_data(ADDRESS_BOOK_TREE, &key)?;
+        }
+
+        Ok(())
+    }
+
+    // When an address was first saved by a user under any label, None if it isn't saved
+    pub(super) fn get_address_added_at(&self, storage: &EncryptedStorage, user: &UserApplication, address: &Address) -> Result<Option<u64>> {
+        let address = address.to_string();
+        Ok(self.get_address_entries(storage, user)?
+            .into_iter()
+            .filter(|(_, entry)| entry.address == address)
+            .map(|(_, entry)| entry.added_at)
+            .min())
+    }
+
+    // Destination of a withdrawal, either an address or a label of the address book of the user
+    pub async fn resolve_address(&self, user: &UserApplication, destination: &str) -> Result<Address, ServiceError> {
+        if let Ok(label) = normalize_label(destination) {
+            let key = DataValue::Blob(AddressBookKey { user: *user, label }.to_bytes());
+            let storage = self.wallet.get_storage().read().await;
+            let saved = storage.get_custom_data(ADDRESS_BOOK_TREE, &key)
+                .ok()
+                .and_then(|v| v.to_value().ok())
+                .and_then(|v| v.to_type::<AddressEntry>().ok());
+
+            return match saved {
+                Some(entry) => self.parse_address(&entry.address),
+                None => Err(ServiceError::UnknownAddressLabel(destination.to_string()))
+            };
+        }
+
+        self.parse_address(destination)
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant}
+};
+
+use anyhow::Result;
+use log::{error, info, warn};
+use poise::serenity_prelude::{Colour, Http};
+use xelis_common::utils::format_xelis;
+
+use super::{ops::Alert, WalletServiceImpl};
+use crate::COLOR;
+
+// How often the wallet is checked for the alerts
+pub(super) const ALERTS_INTERVAL: Duration = Duration::from_secs(60);
+// Restarts of the event loop within the window after which the operators are alerted
+const MAX_EVENT_LOOP_RESTARTS: usize = 3;
+const EVENT_LOOP_RESTART_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+// Conditions the operators were already alerted about, so they're only told once
+#[derive(Default)]
+pub(super) struct AlertState {
+    low_balance: bool,
+    offline: bool,
+    // Restarts of the event loop within the window
+    restarts: VecDeque<Instant>,
+    restarts_alerted: bool
+}
+
+impl WalletServiceImpl {
+    // Alert the operators when the wallet balance drops below the threshold or the wallet goes offline
+    // Each alert is sent once, and the operators are told again when the condition is over
+    pub(super) async fn check_alerts(&self, http: &Http) -> Result<()> {
+        let online = self.wallet.is_online().await;
+        let was_offline = {
+            let mut state = self.alerts.lock().expect("alerts lock");
+            std::mem::replace(&mut state.offline, !online)
+        };
+
+        if !online && !was_offline {
+            error!("The wallet is offline, the daemon can't be reached");
+            self.alert_operators(http, Alert::new("Wallet Offline", "The wallet lost its connection to the daemon, deposits and withdrawals are on hold until it's back", Colour::RED)).await;
+        } else if online && was_offline {
+            info!("The wallet is back online");
+            self.alert_operators(http, Alert::new("Wallet Online", "The wallet is connected to the daemon again", COLOR)).await;
+        }
+
+        // A wallet out of sync can't tell its balance
+        let Some(threshold) = self.runtime().low_balance_alert.filter(|_| online) else {
+            return Ok(());
+        };
+
+        let balance = self.get_wallet_balance().await?;
+        let low = balance < threshold;
+        let was_low = {
+            let mut state = self.alerts.lock().expect("alerts lock");
+            std::mem::replace(&mut state.low_balance, low)
+        };
+
+        if low && !was_low {
+            warn!("The wallet only holds {} XEL, below the alert threshold of {} XEL", format_xelis(balance), format_xelis(threshold));
+            let alert = Alert::new("Low Wallet Balance", format!("The wallet only holds {} XEL, withdrawals may soon fail until it's funded", format_xelis(balance)), Colour::ORANGE)
+                .field("Threshold", format_xelis(threshold), true);
+            self.alert_operators(http, alert).await;
+        } else if !low && was_low {
+            info!("The wallet balance is back above the alert threshold with {} XEL", format_xelis(balance));
+            self.alert_operators(http, Alert::new("Wallet Balance Restored", format!("The wallet holds {} XEL again", format_xelis(balance)), COLOR)).await;
+        }
+
+        Ok(())
+    }
+
+    // Count a restart of the event loop, the operators are alerted when it keeps failing
+    pub(super) async fn record_event_loop_restart(&self, http: &Http, e: &anyhow::Error) {
+        let restarts = {
+            let mut state = self.alerts.lock().expect("alerts lock");
+            let now = Instant::now();
+            state.restarts.push_back(now);
+            while state.restarts.front().is_some_and(|restart| now.duration_since(*restart) > EVENT_LOOP_RESTART_WINDOW) {
+                state.restarts.pop_front();
+            }
+
+            if state.restarts.len() < MAX_EVENT_LOOP_RESTARTS {
+                state.restarts_alerted = false;
+                return;
+            }
+
+            if std::mem::replace(&mut state.restarts_alerted, true) {
+                return;
+            }
+
+            state.restarts.len()
+        };
+
+        error!("The event loop restarted {} times in the last {} minutes", restarts, EVENT_LOOP_RESTART_WINDOW.as_secs() / 60);
+        let alert = Alert::new("Event Loop Restarting", format!("The event loop restarted {} times in the last {} minutes, deposits may not be processed", restarts, EVENT_LOOP_RESTART_WINDOW.as_secs() / 60), Colour::RED)
+            .field("Last Error", e.to_string(), false);
+        self.alert_operators(http, alert).await;
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::Result;
+use xelis_common::{
+    api::{DataElement, DataValue},
+    crypto::{hash, Hash, Signature},
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{journal::JOURNAL_SEQUENCE_TREE, MovementKind, UserApplication, WalletServiceImpl};
+
+// Append-only log of every balance change, by sequence number
+// Unlike the journal, its records are never deleted, not even when a user is forgotten
+pub(super) const AUDIT_TREE: &str = "audit_log";
+// Key of the next sequence number in the journal sequences
+const AUDIT_SEQUENCE_KEY: &str = "next_audit";
+
+// Hash of a user id, so the log doesn't keep the id of a forgotten user in clear
+fn audit_subject(user: &UserApplication) -> Hash {
+    hash(&user.to_bytes())
+}
+
+// A balance change as appended to the audit log
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub sequence: u64,
+    // Timestamp in seconds
+    pub timestamp: u64,
+    pub subject: Hash,
+    pub asset: Hash,
+    pub kind: MovementKind,
+    pub before: u64,
+    pub after: u64,
+    // Id of the journal entry, holding the reason of a manual change
+    pub movement: u64,
+    // Digest of the previous entry, zero for the first one
+    pub previous: Hash
+}
+
+impl AuditEntry {
+    pub fn is_credit(&self) -> bool {
+        self.after >= self.before
+    }
+
+    pub fn amount(&self) -> u64 {
+        self.after.abs_diff(self.before)
+    }
+
+    // Hash signed by the wallet and linked by the next entry
+    pub fn digest(&self) -> Hash {
+        hash(&self.to_bytes())
+    }
+}
+
+impl Serializer for AuditEntry {
+    fn write(&self, writer: &mut Writer) {
+        self.sequence.write(writer);
+        self.timestamp.write(writer);
+        self.subject.write(writer);
+        self.asset.write(writer);
+        writer.write_u8(self.kind.id());
+        self.before.write(writer);
+        self.after.write(writer);
+        self.movement.write(writer);
+        self.previous.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let sequence = reader.read_u64()?;
+        let timestamp = reader.read_u64()?;
+        let subject = Hash::read(reader)?;
+        let asset = Hash::read(reader)?;
+        let kind = MovementKind::from_id(reader.read_u8()?).ok_or(ReaderError::InvalidValue)?;
+        let before = reader.read_u64()?;
+        let after = reader.read_u64()?;
+        let movement = reader.read_u64()?;
+        let previous = Hash::read(reader)?;
+
+        Ok(Self {
+            sequence,
+            timestamp,
+            subject,
+            asset,
+            kind,
+            before,
+            after,
+            movement,
+            previous
+        })
+    }
+}
+
+// An entry of the audit log with the signature of its digest by the wallet
+// Each entry holds the digest of the previous one, so an entry can't be altered,
+// removed or inserted without breaking the chain
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub entry: AuditEntry,
+    pub signature: Signature
+}
+
+impl Serializer for AuditRecord {
+    fn write(&self, writer: &mut Writer) {
+        self.entry.write(writer);
+        self.signature.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let entry = AuditEntry::read(reader)?;
+        let signature = Signature::read(reader)?;
+
+        Ok(Self {
+            entry,
+            signature
+        })
+    }
+}
+
+// Result of checking the whole audit log
+#[derive(Debug, Clone, Default)]
+pub struct AuditVerification {
+    pub records: usize,
+    // Sequence number of each broken record with the reason
+    pub broken: Vec<(u64, &'static str)>
+}
+
+impl WalletServiceImpl {
+    fn get_audit_record_internal(&self, storage: &EncryptedStorage, sequence: u64) -> Result<AuditRecord> {
+        let record = storage.get_custom_data(AUDIT_TREE, &DataValue::U64(sequence))?.to_value()?.to_type()?;
+        Ok(record)
+    }
+
+    // Append a balance change to the audit log, in the same storage write as the change
+    pub(super) fn append_audit_record(&self, storage: &mut EncryptedStorage, user: &UserApplication, asset: &Hash, kind: MovementKind, before: u64, after: u64, movement: u64) -> Result<()> {
+        let sequence = self.get_sequence_internal(storage, AUDIT_SEQUENCE_KEY);
+        let previous = match sequence.checked_sub(1) {
+            Some(last) => self.get_audit_record_internal(storage, last)?.entry.digest(),
+            None => Hash::zero()
+        };
+
+        let entry = AuditEntry {
+            sequence,
+            timestamp: get_current_time_in_seconds(),
+            subject: audit_subject(user),
+            asset: asset.clone(),
+            kind,
+            before,
+            after,
+            movement,
+            previous
+        };
+        let signature = self.wallet.sign_data(entry.digest().as_bytes());
+        let record = AuditRecord { entry, signature };
+
+        storage.set_custom_data(AUDIT_TREE, &DataValue::U64(sequence), &DataElement::Value(DataValue::Blob(record.to_bytes())))?;
+        storage.set_custom_data(JOURNAL_SEQUENCE_TREE, &DataValue::String(AUDIT_SEQUENCE_KEY.to_string()), &(sequence + 1).into())?;
+
+        Ok(())
+    }
+
+    // Get the latest records of the audit log, of a single user if set, most recent first
+    pub async fn get_audit_records(&self, user: Option<&UserApplication>, limit: usize) -> Result<Vec<AuditRecord>> {
+        let storage = self.wallet.get_storage().read().await;
+        let subject = user.map(audit_subject);
+        let mut records = Vec::new();
+        let mut sequence = self.get_sequence_internal(&storage, AUDIT_SEQUENCE_KEY);
+        while records.len() < limit && sequence > 0 {
+            sequence -= 1;
+            let record = self.get_audit_record_internal(&storage, sequence)?;
+            if subject.as_ref().is_none_or(|subject| *subject == record.entry.subject) {
+                records.push(record);
+            }
+        }
+
+        Ok(records)
+    }
+
+    // Check that the records follow each other and are signed by the wallet
+    pub async fn verify_audit_log(&self) -> Result<AuditVerification> {
+        let storage = self.wallet.get_storage().read().await;
+        let public_key = self.wallet.get_public_key();
+        let count = self.get_sequence_internal(&storage, AUDIT_SEQUENCE_KEY);
+        let mut verification = AuditVerification::default();
+        let mut previous = Hash::zero();
+        for sequence in 0..count {
+            let record = match self.get_audit_record_internal(&storage, sequence) {
+                Ok(record) => record,
+                Err(_) => {
+                    verification.broken.push((sequence, "missing or unreadable"));
+                    continue;
+                }
+            };
+
+            verification.records += 1;
+            let digest = record.entry.digest();
+            if record.entry.sequence != sequence {
+                verification.broken.push((sequence, "wrong sequence number"));
+            } else if record.entry.previous != previous {
+                verification.broken.push((sequence, "doesn't follow the previous record"));
+            } else if !record.signature.verify(digest.as_bytes(), public_key) {
+                verification.broken.push((sequence, "invalid signature"));
+            }
+            previous = digest;
+        }
+
+        // Records appended past the sequence were written behind the back of the bot
+        let stored = storage.get_custom_tree_keys(&AUDIT_TREE.to_string(), &None, None, None)?.len();
+        if stored > verification.records {
+            verification.broken.push((count, "records past the end of the log"));
+        }
+
+        Ok(verification)
+    }
+}
@@ -0,0 +1,213 @@
+use std::{future::Future, pin::Pin};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::debug;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use xelis_common::{
+    api::DataElement,
+    crypto::{Address, Hash, PublicKey, Signature},
+    network::Network,
+    transaction::{
+        builder::{FeeBuilder, TransactionTypeBuilder},
+        Transaction
+    }
+};
+use xelis_wallet::{
+    storage::EncryptedStorage,
+    wallet::{Event, Wallet}
+};
+
+// Events of the daemon buffered until the event loop handles them
+const DAEMON_EVENTS_CAPACITY: usize = 64;
+
+// Changes of a built TX to the wallet state, applied once it's broadcast
+pub type ApplyChanges = Box<dyn for<'a> FnOnce(&'a mut EncryptedStorage) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> + Send>;
+
+// Events of the daemon handled by the event loop
+#[derive(Debug, Clone)]
+pub enum DaemonEvent {
+    // The TXs at or below this topoheight can't be orphaned anymore
+    StableTopoHeight(u64),
+    // A TX was taken out of the chain by a reorg
+    TransactionOrphaned(Hash)
+}
+
+// Closed when the connection to the daemon is lost
+pub type DaemonEvents = mpsc::Receiver<Result<DaemonEvent>>;
+
+// Everything the service asks the wallet, so its logic can run without a daemon
+// The ledger of the bot is kept in the custom trees of the wallet storage
+#[async_trait]
+pub trait WalletBackend: Send + Sync {
+    fn get_storage(&self) -> &RwLock<EncryptedStorage>;
+
+    fn get_network(&self) -> &Network;
+
+    fn get_public_key(&self) -> &PublicKey;
+
+    fn get_address(&self) -> Address;
+
+    // Integrated address, the data tells which user a deposit is for
+    fn get_address_with(&self, data: DataElement) -> Address;
+
+    fn sign_data(&self, data: &[u8]) -> Signature;
+
+    // Plaintext balance of an asset, 0 if the wallet never received it
+    async fn get_balance(&self, asset: &Hash) -> u64;
+
+    async fn is_online(&self) -> bool;
+
+    // Connect to a daemon, the wallet reconnects by itself if the connection drops
+    async fn set_online_mode(&self, daemon_address: &str) -> Result<()>;
+
+    async fn set_offline_mode(&self) -> Result<()>;
+
+    // Sync the wallet again from the start of the chain
+    async fn rescan(&self) -> Result<()>;
+
+    // Topoheight of the daemon, None while the wallet is offline
+    async fn get_daemon_topoheight(&self) -> Result<Option<u64>>;
+
+    async fn estimate_fees(&self, builder: TransactionTypeBuilder) -> Result<u64>;
+
+    // Build and sign a TX with a fixed fee, its changes are applied to the wallet state once it's broadcast
+    async fn create_transaction(&self, storage: &mut EncryptedStorage, builder: TransactionTypeBuilder, fee: u64) -> Result<(Transaction, ApplyChanges)>;
+
+    async fn submit_transaction(&self, transaction: &Transaction) -> Result<()>;
+
+    // Events of the wallet, such as the new TXs
+    async fn subscribe_events(&self) -> broadcast::Receiver<Event>;
+
+    // Events of the daemon, None while the wallet is offline
+    async fn subscribe_daemon_events(&self) -> Result<Option<DaemonEvents>>;
+}
+
+#[async_trait]
+impl WalletBackend for Wallet {
+    fn get_storage(&self) -> &RwLock<EncryptedStorage> {
+        Wallet::get_storage(self)
+    }
+
+    fn get_network(&self) -> &Network {
+        Wallet::get_network(self)
+    }
+
+    fn get_public_key(&self) -> &PublicKey {
+        Wallet::get_public_key(self)
+    }
+
+    fn get_address(&self) -> Address {
+        Wallet::get_address(self)
+    }
+
+    fn get_address_with(&self, data: DataElement) -> Address {
+        Wallet::get_address_with(self, data)
+    }
+
+    fn sign_data(&self, data: &[u8]) -> Signature {
+        Wallet::sign_data(self, data)
+    }
+
+    async fn get_balance(&self, asset: &Hash) -> u64 {
+        let storage = Wallet::get_storage(self).read().await;
+        storage.get_plaintext_balance_for(asset).await.unwrap_or(0)
+    }
+
+    async fn is_online(&self) -> bool {
+        Wallet::is_online(self).await
+    }
+
+    async fn set_online_mode(&self, daemon_address: &str) -> Result<()> {
+        Wallet::set_online_mode(self, &daemon_address.to_string(), true).await?;
+        Ok(())
+    }
+
+    async fn set_offline_mode(&self) -> Result<()> {
+        Wallet::set_offline_mode(self).await?;
+        Ok(())
+    }
+
+    async fn rescan(&self) -> Result<()> {
+        Wallet::rescan(self, 0, true).await?;
+        Ok(())
+    }
+
+    async fn get_daemon_topoheight(&self) -> Result<Option<u64>> {
+        let lock = self.get_network_handler();
+        let network_handler = lock.lock().await;
+        let Some(network_handler) = network_handler.as_ref() else {
+            return Ok(None);
+        };
+
+        let info = network_handler.get_api().get_info().await?;
+        Ok(Some(info.topoheight))
+    }
+
+    async fn estimate_fees(&self, builder: TransactionTypeBuilder) -> Result<u64> {
+        Ok(Wallet::estimate_fees(self, builder, Default::default(), Default::default()).await?)
+    }
+
+    async fn create_transaction(&self, storage: &mut EncryptedStorage, builder: TransactionTypeBuilder, fee: u64) -> Result<(Transaction, ApplyChanges)> {
+        let (transaction, mut state) = self.create_transaction_with_storage(
+            storage,
+            builder,
+            FeeBuilder::Fixed(fee),
+            Default::default(),
+            None
+        ).await?;
+
+        let apply: ApplyChanges = Box::new(move |storage| Box::pin(async move {
+            state.apply_changes(storage).await?;
+            Ok(())
+        }));
+
+        Ok((transaction, apply))
+    }
+
+    async fn submit_transaction(&self, transaction: &Transaction) -> Result<()> {
+        Wallet::submit_transaction(self, transaction).await?;
+        Ok(())
+    }
+
+    async fn subscribe_events(&self) -> broadcast::Receiver<Event> {
+        Wallet::subscribe_events(self).await
+    }
+
+    // Both subscriptions are forwarded to a single channel, until one of them fails
+    async fn subscribe_daemon_events(&self) -> Result<Option<DaemonEvents>> {
+        let (mut stable_topoheight_receiver, mut orphaned_receiver) = {
+            let lock = self.get_network_handler();
+            let network_handler = lock.lock().await;
+            let Some(network_handler) = network_handler.as_ref() else {
+                return Ok(None);
+            };
+
+            let api = network_handler.get_api();
+            (api.on_stable_topoheight_changed_event().await?, api.on_transaction_orphaned_event().await?)
+        };
+
+        let (sender, receiver) = mpsc::channel(DAEMON_EVENTS_CAPACITY);
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    res = stable_topoheight_receiver.next() => res
+                        .map(|event| DaemonEvent::StableTopoHeight(event.new_stable_topoheight))
+                        .map_err(anyhow::Error::from),
+                    res = orphaned_receiver.next() => res
+                        .map(|event| DaemonEvent::TransactionOrphaned(event.data.hash.into_owned()))
+                        .map_err(anyhow::Error::from)
+                };
+
+                let failed = event.is_err();
+                // The event loop stopped listening
+                if sender.send(event).await.is_err() || failed {
+                    debug!("Stopped forwarding the daemon events");
+                    break;
+                }
+            }
+        });
+
+        Ok(Some(receiver))
+    }
+}
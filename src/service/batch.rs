@@ -0,0 +1,181 @@
+use anyhow::Result;
+use log::{error, info, warn};
+use xelis_common::{
+    api::{DataElement, DataValue},
+    crypto::Hash,
+    serializer::{Reader, ReaderError, Serializer, Writer}
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{MovementKind, UserApplication, WalletServiceImpl, HISTORY_TREE};
+
+// Balance updates being written, kept until all of them are
+// A batch still there at startup was interrupted by a crash and is completed
+pub(super) const PENDING_BATCH_TREE: &str = "pending_batch";
+// Only one batch is written at a time, under the storage lock
+const PENDING_BATCH_KEY: &str = "pending";
+
+// New balance of a user in an asset, with the one it replaces
+#[derive(Debug, Clone)]
+pub(super) struct BalanceUpdate {
+    pub user: UserApplication,
+    pub asset: Hash,
+    pub before: u64,
+    pub after: u64
+}
+
+impl Serializer for BalanceUpdate {
+    fn write(&self, writer: &mut Writer) {
+        self.user.write(writer);
+        self.asset.write(writer);
+        self.before.write(writer);
+        self.after.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let user = UserApplication::read(reader)?;
+        let asset = Hash::read(reader)?;
+        let before = reader.read_u64()?;
+        let after = reader.read_u64()?;
+
+        Ok(Self {
+            user,
+            asset,
+            before,
+            after
+        })
+    }
+}
+
+// Balance updates written together, such as both sides of a tip
+#[derive(Debug, Clone)]
+pub(super) struct BalanceBatch {
+    pub kind: MovementKind,
+    pub updates: Vec<BalanceUpdate>,
+    // Deposit TX credited by the batch with the user kept in the history,
+    // marked as processed with the balances so it can't be credited twice
+    pub history: Option<(Hash, UserApplication)>
+}
+
+impl Serializer for BalanceBatch {
+    fn write(&self, writer: &mut Writer) {
+        writer.write_u8(self.kind.id());
+        (self.updates.len() as u16).write(writer);
+        for update in self.updates.iter() {
+            update.write(writer);
+        }
+        match &self.history {
+            Some((tx_hash, owner)) => {
+                writer.write_bool(true);
+                tx_hash.write(writer);
+                owner.write(writer);
+            },
+            None => writer.write_bool(false)
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let kind = MovementKind::from_id(reader.read_u8()?).ok_or(ReaderError::InvalidValue)?;
+        let len = reader.read_u16()?;
+        let mut updates = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            updates.push(BalanceUpdate::read(reader)?);
+        }
+        let history = if reader.read_bool()? {
+            Some((Hash::read(reader)?, UserApplication::read(reader)?))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            kind,
+            updates,
+            history
+        })
+    }
+}
+
+impl WalletServiceImpl {
+    // Write the new balances of several users, none or all of them
+    pub(super) fn apply_balance_updates(&self, storage: &mut EncryptedStorage, updates: Vec<BalanceUpdate>, kind: MovementKind) -> Result<()> {
+        self.apply_balance_batch(storage, BalanceBatch { kind, updates, history: None })
+    }
+
+    // Credit the transfers of a deposit TX and mark it as processed in the history, none or all of them
+    pub(super) fn apply_deposit_updates(&self, storage: &mut EncryptedStorage, tx_hash: &Hash, owner: &UserApplication, updates: Vec<BalanceUpdate>) -> Result<()> {
+        self.apply_balance_batch(storage, BalanceBatch { kind: MovementKind::Deposit, updates, history: Some((tx_hash.clone(), *owner)) })
+    }
+
+    // The batch is saved first, so a crash in the middle is completed at the next start
+    // If one write fails, the balances already written are restored
+    fn apply_balance_batch(&self, storage: &mut EncryptedStorage, batch: BalanceBatch) -> Result<()> {
+        let key = DataValue::String(PENDING_BATCH_KEY.to_string());
+        storage.set_custom_data(PENDING_BATCH_TREE, &key, &DataElement::Value(DataValue::Blob(batch.to_bytes())))?;
+
+        for (i, update) in batch.updates.iter().enumerate() {
+            if let Err(e) = self.set_asset_balance_internal(storage, &update.user, &update.asset, update.after, batch.kind) {
+                self.restore_balances(storage, &batch.updates[..=i], batch.kind);
+                storage.delete_custom_data(PENDING_BATCH_TREE, &key)?;
+                return Err(e);
+            }
+        }
+
+        // Written before the batch is cleared, a crash in between writes it at the next start
+        if let Some((tx_hash, owner)) = batch.history.as_ref() {
+            if let Err(e) = storage.set_custom_data(HISTORY_TREE, &tx_hash.clone().into(), &owner.into()) {
+                self.restore_balances(storage, &batch.updates, batch.kind);
+                storage.delete_custom_data(PENDING_BATCH_TREE, &key)?;
+                return Err(e.into());
+            }
+        }
+
+        storage.delete_custom_data(PENDING_BATCH_TREE, &key)?;
+
+        Ok(())
+    }
+
+    // Put back the balances of a batch that couldn't be written, last first
+    fn restore_balances(&self, storage: &mut EncryptedStorage, updates: &[BalanceUpdate], kind: MovementKind) {
+        for update in updates.iter().rev() {
+            if let Err(e) = self.set_asset_balance_internal(storage, &update.user, &update.asset, update.before, kind) {
+                error!("Couldn't restore the balance of {:?} in {} to {}: {}", update.user, update.asset, update.before, e);
+            }
+        }
+    }
+
+    // Complete the batch interrupted by a crash, if any
+    // A balance that is neither the one before nor the one after was changed since, it's left as is
+    pub(super) async fn recover_balance_batch(&self) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let key = DataValue::String(PENDING_BATCH_KEY.to_string());
+        if !storage.has_custom_data(PENDING_BATCH_TREE, &key)? {
+            return Ok(());
+        }
+
+        let batch: BalanceBatch = storage.get_custom_data(PENDING_BATCH_TREE, &key)?.to_value()?.to_type()?;
+        warn!("Completing {} balance updates interrupted by a crash", batch.updates.len());
+        for update in batch.updates.iter() {
+            let balance = self.get_asset_balance_internal(&storage, &update.user, &update.asset);
+            if balance == update.after {
+                continue;
+            }
+
+            if balance != update.before {
+                error!("Balance of {:?} in {} is {}, neither {} nor {}, check it with the audit command", update.user, update.asset, balance, update.before, update.after);
+                continue;
+            }
+
+            self.set_asset_balance_internal(&mut storage, &update.user, &update.asset, update.after, batch.kind)?;
+        }
+
+        if let Some((tx_hash, owner)) = batch.history.as_ref() {
+            info!("Marking the interrupted deposit TX {} as processed", tx_hash);
+            storage.set_custom_data(HISTORY_TREE, &tx_hash.clone().into(), &owner.into())?;
+        }
+        storage.delete_custom_data(PENDING_BATCH_TREE, &key)?;
+
+        info!("Interrupted balance updates completed");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::Result;
+use xelis_common::{
+    api::DataValue,
+    serializer::{Reader, ReaderError, Serializer, Writer}
+};
+
+use super::{StatsPeriod, UserApplication, WalletServiceImpl};
+
+// Tip budgets set by the users on themselves
+pub(super) const TIP_BUDGETS_TREE: &str = "tip_budgets";
+
+// Soft limits of the XEL a user wants to tip, rains included
+// Going over a limit is still possible once the user confirms it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TipBudget {
+    // Limit per UTC day
+    pub daily: Option<u64>,
+    // Limit per week, weeks are counted from the UNIX epoch like the leaderboard
+    pub weekly: Option<u64>
+}
+
+impl TipBudget {
+    pub fn is_empty(&self) -> bool {
+        self.daily.is_none() && self.weekly.is_none()
+    }
+}
+
+impl Serializer for TipBudget {
+    fn write(&self, writer: &mut Writer) {
+        for limit in [self.daily, self.weekly] {
+            match limit {
+                Some(limit) => {
+                    writer.write_u8(1);
+                    limit.write(writer);
+                },
+                None => writer.write_u8(0)
+            }
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let mut limits = [None, None];
+        for limit in limits.iter_mut() {
+            *limit = match reader.read_u8()? {
+                0 => None,
+                1 => Some(reader.read_u64()?),
+                _ => return Err(ReaderError::InvalidValue)
+            };
+        }
+
+        Ok(Self {
+            daily: limits[0],
+            weekly: limits[1]
+        })
+    }
+}
+
+// A tip that would go over one of the budgets of its author
+#[derive(Debug, Clone, Copy)]
+pub struct BudgetOverrun {
+    // "daily" or "weekly"
+    pub period: &'static str,
+    pub limit: u64,
+    // Already tipped over the period
+    pub spent: u64
+}
+
+impl WalletServiceImpl {
+    // Get the tip budget of a user, empty if never set
+    pub async fn get_tip_budget(&self, user: &UserApplication) -> TipBudget {
+        let storage = self.wallet.get_storage().read().await;
+        storage.get_custom_data(TIP_BUDGETS_TREE, &user.into())
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_type().ok())
+            .unwrap_or_default()
+    }
+
+    // Set the tip budget of a user, an empty budget removes it
+    pub async fn set_tip_budget(&self, user: &UserApplication, budget: TipBudget) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        if budget.is_empty() {
+            storage.delete_custom_data(TIP_BUDGETS_TREE, &user.into())?;
+        } else {
+            storage.set_custom_data(TIP_BUDGETS_TREE, &user.into(), &DataValue::Blob(budget.to_bytes()).into())?;
+        }
+
+        Ok(())
+    }
+
+    // XEL already tipped by a user today and this week
+    pub async fn get_tip_budget_spending(&self, user: &UserApplication) -> (u64, u64) {
+        let storage = self.wallet.get_storage().read().await;
+        let daily = self.get_user_tip_stats(&storage, StatsPeriod::current_day(), user).tipped;
+        let weekly = self.get_user_tip_stats(&storage, StatsPeriod::current_week(), user).tipped;
+
+        (daily, weekly)
+    }
+
+    // Let the next tip of a user go over its budget, once it confirmed it
+    pub fn approve_budget_overrun(&self, user: &UserApplication) {
+        self.budget_overruns.lock().expect("budget overruns lock").insert(*user);
+    }
+
+    // Find the budget a tip of XEL would go over, if any
+    // An approved overrun is consumed by this check
+    pub async fn check_tip_budget(&self, user: &UserApplication, amount: u64) -> Option<BudgetOverrun> {
+        if self.budget_overruns.lock().expect("budget overruns lock").remove(user) {
+            return None;
+        }
+
+        let budget = self.get_tip_budget(user).await;
+        let storage = self.wallet.get_storage().read().await;
+        let limits = [
+            ("daily", budget.daily, StatsPeriod::current_day()),
+            ("weekly", budget.weekly, StatsPeriod::current_week())
+        ];
+        for (period, limit, stats_period) in limits {
+            let Some(limit) = limit else {
+                continue;
+            };
+
+            let spent = self.get_user_tip_stats(&storage, stats_period, user).tipped;
+            if spent.saturating_add(amount) > limit {
+                return Some(BudgetOverrun { period, limit, spent });
+            }
+        }
+
+        None
+    }
+}
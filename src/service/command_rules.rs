@@ -0,0 +1,149 @@
+use anyhow::Result;
+use xelis_common::{
+    api::DataValue,
+    serializer::{Reader, ReaderError, Serializer, Writer}
+};
+
+use super::{Community, ServiceError, WalletServiceImpl};
+
+// Commands disabled or restricted to some channels by each community
+pub(super) const COMMAND_RULES_TREE: &str = "command_rules";
+
+// Channels a command can be restricted to
+pub const MAX_COMMAND_CHANNELS: usize = 25;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandRule {
+    // The command can't be used in the community
+    Disabled,
+    // The command can only be used in these channels
+    Channels(Vec<u64>)
+}
+
+impl Serializer for CommandRule {
+    fn write(&self, writer: &mut Writer) {
+        match self {
+            CommandRule::Disabled => writer.write_u8(0),
+            CommandRule::Channels(channels) => {
+                writer.write_u8(1);
+                (channels.len() as u32).write(writer);
+                for channel in channels.iter() {
+                    channel.write(writer);
+                }
+            }
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        Ok(match reader.read_u8()? {
+            0 => CommandRule::Disabled,
+            1 => {
+                let len = reader.read_u32()?;
+                let mut channels = Vec::with_capacity(len.min(MAX_COMMAND_CHANNELS as u32) as usize);
+                for _ in 0..len {
+                    channels.push(reader.read_u64()?);
+                }
+                CommandRule::Channels(channels)
+            },
+            _ => return Err(ReaderError::InvalidValue)
+        })
+    }
+}
+
+// Storage key of a command rule
+pub(super) struct CommandRuleKey {
+    community: Community,
+    command: String
+}
+
+impl Serializer for CommandRuleKey {
+    fn write(&self, writer: &mut Writer) {
+        self.community.write(writer);
+        self.command.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let community = Community::read(reader)?;
+        let command = String::read(reader)?;
+
+        Ok(Self {
+            community,
+            command
+        })
+    }
+}
+
+impl WalletServiceImpl {
+    // Get the rule of a command in a community, None if it can be used anywhere
+    pub async fn get_command_rule(&self, community: &Community, command: &str) -> Option<CommandRule> {
+        let storage = self.wallet.get_storage().read().await;
+        let key = CommandRuleKey { community: *community, command: command.to_string() };
+        storage.get_custom_data(COMMAND_RULES_TREE, &DataValue::Blob(key.to_bytes()))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_type().ok())
+    }
+
+    // Set or remove the rule of a command in a community
+    pub async fn set_command_rule(&self, community: &Community, command: &str, rule: Option<CommandRule>) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let key = DataValue::Blob(CommandRuleKey { community: *community, command: command.to_string() }.to_bytes());
+        match rule {
+            Some(rule) => storage.set_custom_data(COMMAND_RULES_TREE, &key, &DataValue::Blob(rule.to_bytes()).into())?,
+            None => storage.delete_custom_data(COMMAND_RULES_TREE, &key)?
+        };
+
+        Ok(())
+    }
+
+    // Allow a command in a channel, or disallow it if it was already allowed there
+    // The command can be used anywhere again once no channel is left
+    pub async fn toggle_command_channel(&self, community: &Community, command: &str, channel: u64) -> Result<Option<CommandRule>, ServiceError> {
+        let mut channels = match self.get_command_rule(community, command).await {
+            Some(CommandRule::Channels(channels)) => channels,
+            _ => Vec::new()
+        };
+
+        if let Some(index) = channels.iter().position(|c| *c == channel) {
+            channels.remove(index);
+        } else if channels.len() >= MAX_COMMAND_CHANNELS {
+            return Err(ServiceError::TooManyCommandChannels(MAX_COMMAND_CHANNELS));
+        } else {
+            channels.push(channel);
+        }
+
+        let rule = Some(CommandRule::Channels(channels))
+            .filter(|rule| !matches!(rule, CommandRule::Channels(channels) if channels.is_empty()));
+        self.set_command_rule(community, command, rule.clone()).await?;
+
+        Ok(rule)
+    }
+
+    // Get the rules of all the commands of a community
+    pub async fn get_command_rules(&self, community: &Community) -> Result<Vec<(String, CommandRule)>> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut rules = Vec::new();
+        for key in storage.get_custom_tree_keys(&COMMAND_RULES_TREE.to_string(), &None, None, None)? {
+            let rule_key: CommandRuleKey = key.as_type()?;
+            if rule_key.community != *community {
+                continue;
+            }
+
+            let rule = storage.get_custom_data(COMMAND_RULES_TREE, &key)?.to_value()?.to_type()?;
+            rules.push((rule_key.command, rule));
+        }
+
+        rules.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Ok(rules)
+    }
+
+    // Check that a command can be used in the channel of a community
+    pub async fn check_command_rule(&self, community: &Community, command: &str, channel: u64) -> Result<(), ServiceError> {
+        match self.get_command_rule(community, command).await {
+            Some(CommandRule::Disabled) => Err(ServiceError::CommandDisabled(command.to_string())),
+            Some(CommandRule::Channels(channels)) if !channels.contains(&channel) => Err(ServiceError::CommandRestricted(command.to_string())),
+            _ => Ok(())
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::Result;
+use log::{debug, error};
+use poise::serenity_prelude::{Colour, Http};
+use teloxide::Bot;
+use xelis_common::{
+    api::{DataElement, DataValue},
+    crypto::Hash,
+    network::Network,
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{ops::Alert, unsent::Debit, UserApplication, WalletServiceImpl};
+
+// Withdrawals sent for a user and not confirmed yet, by TX hash
+// The user is told once its TX is in the stable topoheight
+pub(super) const SENT_WITHDRAWALS_TREE: &str = "sent_withdrawals";
+
+// Page of a TX in the explorer of the network, {hash} is replaced by the TX hash
+pub fn default_explorer_url(network: Network) -> Option<&'static str> {
+    match network {
+        Network::Mainnet => Some("https://explorer.xelis.io/txs/{hash}"),
+        Network::Testnet => Some("https://testnet-explorer.xelis.io/txs/{hash}"),
+        _ => None
+    }
+}
+
+// Withdrawal of a user waiting for its confirmation
+#[derive(Debug, Clone)]
+pub(super) struct SentWithdrawal {
+    pub user: UserApplication,
+    pub asset: Hash,
+    pub amount: u64,
+    pub fee: u64,
+    // Timestamp in seconds
+    pub sent_at: u64
+}
+
+impl Serializer for SentWithdrawal {
+    fn write(&self, writer: &mut Writer) {
+        self.user.write(writer);
+        self.asset.write(writer);
+        self.amount.write(writer);
+        self.fee.write(writer);
+        self.sent_at.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        Ok(Self {
+            user: UserApplication::read(reader)?,
+            asset: Hash::read(reader)?,
+            amount: reader.read_u64()?,
+            fee: reader.read_u64()?,
+            sent_at: reader.read_u64()?
+        })
+    }
+}
+
+impl WalletServiceImpl {
+    // Remember the withdrawal of a user until its TX is confirmed
+    // TXs not sent from a user balance are not followed
+    pub(super) fn record_sent_withdrawal(&self, storage: &mut EncryptedStorage, tx_hash: &Hash, debit: &Debit, fee: u64) -> Result<()> {
+        let Some(user) = debit.user else {
+            return Ok(());
+        };
+
+        let sent = SentWithdrawal {
+            user,
+            asset: debit.asset.clone(),
+            amount: debit.amount,
+            fee,
+            sent_at: get_current_time_in_seconds()
+        };
+
+        storage.set_custom_data(SENT_WITHDRAWALS_TREE, &DataValue::Blob(tx_hash.to_bytes()), &DataElement::Value(DataValue::Blob(sent.to_bytes())))?;
+        Ok(())
+    }
+
+    // Stop following a withdrawal, returns it if it was followed
+    pub(super) fn take_sent_withdrawal(&self, storage: &mut EncryptedStorage, tx_hash: &Hash) -> Result<Option<SentWithdrawal>> {
+        let key = DataValue::Blob(tx_hash.to_bytes());
+        if !storage.has_custom_data(SENT_WITHDRAWALS_TREE, &key)? {
+            return Ok(None);
+        }
+
+        let sent = storage.get_custom_data(SENT_WITHDRAWALS_TREE, &key)?.to_value()?.to_type()?;
+        storage.delete_custom_data(SENT_WITHDRAWALS_TREE, &key)?;
+
+        Ok(Some(sent))
+    }
+
+    // Page of a TX in the block explorer, if one is configured
+    pub fn get_explorer_link(&self, tx_hash: &Hash) -> Option<String> {
+        self.runtime().explorer_url.as_ref().map(|url| url.replace("{hash}", &tx_hash.to_string()))
+    }
+
+    // Tell the user its withdrawal is in the stable topoheight
+    pub(super) async fn notify_withdrawal_confirmed(&self, http: &Http, bot: &Bot, tx_hash: &Hash, sent: &SentWithdrawal) {
+        let amount = {
+            let storage = self.wallet.get_storage().read().await;
+            self.get_asset_balance_display(&storage, &sent.asset, sent.amount).await
+        };
+
+        debug!("Notifying {:?} of the confirmed withdrawal {}", sent.user, tx_hash);
+        let mut fields = vec![
+            ("Your withdrawal is confirmed on-chain".to_string(), amount.to_string()),
+            ("Transaction".to_string(), tx_hash.to_string())
+        ];
+        if let Some(link) = self.get_explorer_link(tx_hash) {
+            fields.push(("Explorer".to_string(), link));
+        }
+
+        if let Err(e) = self.notify_user(http, bot, &sent.user, "Withdrawal confirmed", fields).await {
+            error!("Couldn't queue the notification of the confirmed withdrawal {}: {:?}", tx_hash, e);
+            let alert = Alert::new("Notification Lost", format!("{:?} couldn't be told about the confirmed withdrawal {}", sent.user, tx_hash), Colour::RED)
+                .field("Error", format!("{:#}", e), false);
+            self.report_to_ops(http, &alert).await;
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{error, info, warn};
+use poise::serenity_prelude::Http;
+use teloxide::Bot;
+use xelis_common::{
+    api::DataValue,
+    config::XELIS_ASSET,
+    crypto::{Address, Hash},
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+
+use super::{OperationId, ServiceError, UserApplication, WalletServiceImpl};
+
+// Withdrawals requested while the wallet wasn't synced, by user
+pub(super) const QUEUED_WITHDRAWALS_TREE: &str = "queued_withdrawals";
+
+// Wait before the event loop restarts, doubled after each failure until the wallet is synced again
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(5);
+// Longest wait between two restarts
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+// Blocks the wallet can be behind the daemon and still send withdrawals
+const MAX_SYNC_LAG: u64 = 8;
+
+// State of the wallet connection, as seen by the event loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ConnectionState {
+    // Subscribed to the daemon and synced, withdrawals are sent right away
+    Connected,
+    // Subscribed to the daemon but catching up with the chain, withdrawals are queued
+    Syncing,
+    // The event loop stopped and restarts after a delay, withdrawals are queued
+    Reconnecting
+}
+
+pub(super) struct Connection {
+    state: ConnectionState,
+    // Restarts of the event loop since the wallet was last synced
+    failures: u32
+}
+
+impl Default for Connection {
+    // Nothing is subscribed to until the event loop starts
+    fn default() -> Self {
+        Self {
+            state: ConnectionState::Syncing,
+            failures: 0
+        }
+    }
+}
+
+impl Connection {
+    fn reconnect_delay(&self) -> Duration {
+        RECONNECT_BASE_DELAY.saturating_mul(1 << self.failures.saturating_sub(1).min(16)).min(RECONNECT_MAX_DELAY)
+    }
+}
+
+// Withdrawal requested while the wallet wasn't synced, sent once it is
+struct QueuedWithdrawal {
+    address: String,
+    asset: Hash,
+    amount: u64,
+    // Timestamp in seconds
+    queued_at: u64
+}
+
+impl Serializer for QueuedWithdrawal {
+    fn write(&self, writer: &mut Writer) {
+        self.address.write(writer);
+        self.asset.write(writer);
+        self.amount.write(writer);
+        self.queued_at.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let address = String::read(reader)?;
+        let asset = Hash::read(reader)?;
+        let amount = reader.read_u64()?;
+        let queued_at = reader.read_u64()?;
+
+        Ok(Self {
+            address,
+            asset,
+            amount,
+            queued_at
+        })
+    }
+}
+
+impl WalletServiceImpl {
+    // Whether the wallet is synced with the daemon, so a TX can be built on its state
+    pub(super) fn is_synced(&self) -> bool {
+        self.connection.lock().expect("connection lock").state == ConnectionState::Connected
+    }
+
+    // The event loop subscribed to the daemon, the wallet may still be catching up
+    pub(super) async fn connection_established(&self, daemon_topoheight: u64) -> Result<()> {
+        self.connection.lock().expect("connection lock").state = ConnectionState::Syncing;
+        self.update_sync_state(daemon_topoheight).await
+    }
+
+    // Compare the wallet with the daemon, the queued withdrawals are sent once it caught up
+    pub(super) async fn update_sync_state(&self, daemon_topoheight: u64) -> Result<()> {
+        let wallet_topoheight = self.get_wallet_topoheight().await?;
+        if wallet_topoheight + MAX_SYNC_LAG < daemon_topoheight {
+            return Ok(());
+        }
+
+        let mut connection = self.connection.lock().expect("connection lock");
+        if connection.state != ConnectionState::Connected {
+            info!("The wallet is synced at topoheight {}", wallet_topoheight);
+            connection.state = ConnectionState::Connected;
+            connection.failures = 0;
+        }
+
+        Ok(())
+    }
+
+    // The event loop stopped, switch to another daemon if the current one is lost
+    // Returns how long to wait before subscribing again, longer after each failure
+    pub(super) async fn handle_disconnect(&self, http: &Http) -> Duration {
+        let switched = match self.fail_over(http).await {
+            Ok(switched) => switched,
+            Err(e) => {
+                error!("Error while switching to another daemon: {:?}", e);
+                false
+            }
+        };
+
+        let mut connection = self.connection.lock().expect("connection lock");
+        connection.state = ConnectionState::Reconnecting;
+        // Another daemon is subscribed to without waiting longer
+        connection.failures = if switched { 0 } else { connection.failures + 1 };
+
+        let delay = connection.reconnect_delay();
+        info!("Restarting the event loop in {} seconds", delay.as_secs());
+        delay
+    }
+
+    // Keep a withdrawal until the wallet is synced, after the same checks as when it's sent
+    // The funds stay in the user balance and are checked again when it's sent
+    // A user has at most one queued withdrawal
+    pub(super) async fn queue_withdrawal(&self, user: &UserApplication, to: &Address, asset: &Hash, amount: u64, limited: bool, operation: Option<&OperationId>) -> Result<(), ServiceError> {
+        let mut storage = self.wallet.get_storage().write().await;
+        self.check_operation(&storage, operation)?;
+        self.check_not_frozen(&storage, user)?;
+
+        if limited {
+            self.check_withdraw_limit(&storage, user, amount)?;
+        }
+
+        self.check_withdraw_whitelist(&storage, user, to)?;
+
+        if amount > self.get_asset_balance_internal(&storage, user, asset) {
+            if *asset == XELIS_ASSET {
+                return Err(ServiceError::NotEnoughFunds(amount));
+            }
+
+            return Err(ServiceError::NotEnoughAssetFunds(self.get_asset_balance_display(&storage, asset, amount).await));
+        }
+
+        if storage.has_custom_data(QUEUED_WITHDRAWALS_TREE, &user.into())? {
+            return Err(ServiceError::Syncing);
+        }
+
+        let withdrawal = QueuedWithdrawal {
+            address: to.to_string(),
+            asset: asset.clone(),
+            amount,
+            queued_at: get_current_time_in_seconds()
+        };
+
+        storage.set_custom_data(QUEUED_WITHDRAWALS_TREE, &user.into(), &DataValue::Blob(withdrawal.to_bytes()).into())?;
+        // Delivered again, the same command finds it already queued
+        self.mark_operation_processed(&mut storage, operation)?;
+        info!("Queued the withdrawal of {} atomic units of {} to {} for {:?} until the wallet is synced", amount, asset, to, user);
+
+        Ok(())
+    }
+
+    // Send the withdrawals queued while the wallet wasn't synced, oldest first
+    // Each user is told in DM whether its withdrawal was sent
+    pub(super) async fn send_queued_withdrawals(&self, http: &Http, bot: &Bot) -> Result<()> {
+        if !self.is_synced() || self.is_withdraw_locked() {
+            return Ok(());
+        }
+
+        let mut queued = {
+            let storage = self.wallet.get_storage().read().await;
+            let mut queued = Vec::new();
+            for key in storage.get_custom_tree_keys(&QUEUED_WITHDRAWALS_TREE.to_string(), &None, None, None)? {
+                let user: UserApplication = key.as_type()?;
+                let withdrawal: QueuedWithdrawal = storage.get_custom_data(QUEUED_WITHDRAWALS_TREE, &key)?.to_value()?.to_type()?;
+                queued.push((user, withdrawal));
+            }
+            queued
+        };
+
+        queued.sort_by_key(|(_, withdrawal)| withdrawal.queued_at);
+        for (user, withdrawal) in queued {
+            // Lost again, the rest waits for the next sync
+            if !self.is_synced() {
+                break;
+            }
+
+            // Removed first, so a crash can't send it twice
+            {
+                let mut storage = self.wallet.get_storage().write().await;
+                storage.delete_custom_data(QUEUED_WITHDRAWALS_TREE, &(&user).into())?;
+            }
+
+            let result = match Address::from_string(&withdrawal.address) {
+                Ok(to) => {
+                    let limited = withdrawal.asset == XELIS_ASSET && self.runtime().max_daily_withdraw.is_some() && !self.is_exempt(&user, &[]).await;
+                    self.send_withdrawal(&user, to, &withdrawal.asset, withdrawal.amount, limited, None).await
+                },
+                Err(e) => Err(ServiceError::InvalidAddress(e.to_string()))
+            };
+
+            let amount = {
+                let storage = self.wallet.get_storage().read().await;
+                self.get_asset_balance_display(&storage, &withdrawal.asset, withdrawal.amount).await
+            };
+
+            let (title, fields) = match result {
+                Ok(tx_hash) => {
+                    let mut fields = vec![
+                        ("Your queued withdrawal is sent".to_string(), amount.to_string()),
+                        ("Address".to_string(), withdrawal.address),
+                        ("Transaction".to_string(), tx_hash.to_string())
+                    ];
+                    if let Some(link) = self.get_explorer_link(&tx_hash) {
+                        fields.push(("Explorer".to_string(), link));
+                    }
+
+                    ("Withdrawal sent", fields)
+                },
+                Err(e) => {
+                    warn!("Queued withdrawal of {} for {:?} failed: {}", amount, user, e);
+                    ("Withdrawal failed", vec![
+                        ("Your queued withdrawal couldn't be sent".to_string(), amount.to_string()),
+                        ("Reason".to_string(), e.to_string())
+                    ])
+                }
+            };
+
+            if let Err(e) = self.notify_user(http, bot, &user, title, fields).await {
+                error!("Couldn't notify {:?} of its queued withdrawal: {:?}", user, e);
+            }
+        }
+
+        Ok(())
+    }
+}
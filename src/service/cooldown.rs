@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant}
+};
+
+use super::{UserApplication, WalletServiceImpl};
+
+// Full buckets are dropped once this many are stored
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitConfig {
+    // Commands a user can send in a row
+    pub burst: u32,
+    // Time needed to get one more command back
+    pub refill: Duration
+}
+
+// Commands a user can still send before being limited
+pub(super) struct Bucket {
+    tokens: u32,
+    updated_at: Instant,
+    // The user was already told to slow down
+    notified: bool
+}
+
+// Bucket of each user for each command
+pub(super) type Cooldowns = HashMap<(UserApplication, String), Bucket>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimited {
+    // Seconds before the user can send the command again
+    pub retry_after: u64,
+    // The user was already told for this command, a flood shouldn't be answered
+    pub notified: bool
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "You're sending commands too fast, try again in {} seconds", self.retry_after)
+    }
+}
+
+impl RateLimitConfig {
+    // Give back the tokens earned since the last update and take one
+    fn take(&self, bucket: &mut Bucket, now: Instant) -> Result<(), Duration> {
+        let refill = self.refill.as_nanos().max(1);
+        let earned = now.duration_since(bucket.updated_at).as_nanos() / refill;
+        if earned > 0 {
+            bucket.tokens = (bucket.tokens as u128 + earned).min(self.burst as u128) as u32;
+            bucket.updated_at = if bucket.tokens == self.burst {
+                now
+            } else {
+                bucket.updated_at + self.refill * earned as u32
+            };
+        }
+
+        if bucket.tokens == 0 {
+            return Err(self.refill.saturating_sub(now.duration_since(bucket.updated_at)));
+        }
+
+        bucket.tokens -= 1;
+        bucket.notified = false;
+        Ok(())
+    }
+
+    // A full bucket is the same as no bucket at all
+    fn is_full(&self, bucket: &Bucket, now: Instant) -> bool {
+        now.duration_since(bucket.updated_at) >= self.refill * (self.burst - bucket.tokens)
+    }
+}
+
+impl WalletServiceImpl {
+    // Take a command from the bucket of the user for this command
+    // Users exempted by the operator are never limited
+    pub async fn check_rate_limit(&self, user: &UserApplication, roles: &[u64], command: &str) -> Option<RateLimited> {
+        let config = self.runtime().rate_limit?;
+        let retry_after = {
+            let mut buckets = self.cooldowns.lock().expect("cooldowns lock");
+            let now = Instant::now();
+            if buckets.len() >= MAX_BUCKETS {
+                buckets.retain(|_, bucket| !config.is_full(bucket, now));
+            }
+
+            let bucket = buckets.entry((*user, command.to_owned())).or_insert(Bucket {
+                tokens: config.burst,
+                updated_at: now,
+                notified: false
+            });
+
+            match config.take(bucket, now) {
+                Ok(()) => return None,
+                Err(retry_after) => retry_after
+            }
+        };
+
+        if self.is_exempt(user, roles).await {
+            return None;
+        }
+
+        let mut buckets = self.cooldowns.lock().expect("cooldowns lock");
+        let notified = buckets.get_mut(&(*user, command.to_owned()))
+            .map(|bucket| std::mem::replace(&mut bucket.notified, true))
+            .unwrap_or(false);
+
+        Some(RateLimited {
+            retry_after: (retry_after.as_millis() as u64).div_ceil(1000).max(1),
+            notified
+        })
+    }
+}
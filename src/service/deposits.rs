@@ -0,0 +1,229 @@
+use anyhow::Result;
+use log::{info, warn};
+use xelis_common::{
+    api::{wallet::EntryType, DataValue},
+    crypto::{Address, Hash},
+    serializer::{Reader, ReaderError, Serializer, Writer}
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{AssetBalance, MovementKind, ServiceError, UserApplication, WalletServiceImpl, HISTORY_TREE};
+
+// Deposits sent back to their sender by the operator
+pub(super) const RETURNED_DEPOSITS_TREE: &str = "returned_deposits";
+// Transfers sent back over the liability cap, false until their TX is broadcast
+// The entry is written before the broadcast, so a TX processed again never returns a transfer twice
+pub(super) const RETURNED_TRANSFERS_TREE: &str = "returned_transfers";
+
+// Transfer of a deposit TX, a TX has at most one transfer per asset
+pub(super) struct TransferKey {
+    pub tx_hash: Hash,
+    pub asset: Hash
+}
+
+impl Serializer for TransferKey {
+    fn write(&self, writer: &mut Writer) {
+        self.tx_hash.write(writer);
+        self.asset.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let tx_hash = Hash::read(reader)?;
+        let asset = Hash::read(reader)?;
+
+        Ok(Self {
+            tx_hash,
+            asset
+        })
+    }
+}
+
+// Deposit recorded for a user
+#[derive(Debug, Clone)]
+pub struct Deposit {
+    pub tx_hash: Hash,
+    pub topoheight: u64,
+    // Amounts of the TX sent to the user, one per asset
+    pub amounts: Vec<AssetBalance>,
+    // Sent back to its sender by the operator since
+    pub returned: bool
+}
+
+impl WalletServiceImpl {
+    // Some(true) if the transfer was sent back over the liability cap,
+    // Some(false) if the bot stopped while sending it back
+    pub(super) fn get_returned_transfer(&self, storage: &EncryptedStorage, tx_hash: &Hash, asset: &Hash) -> Result<Option<bool>> {
+        let key = DataValue::Blob(TransferKey { tx_hash: tx_hash.clone(), asset: asset.clone() }.to_bytes());
+        if !storage.has_custom_data(RETURNED_TRANSFERS_TREE, &key)? {
+            return Ok(None);
+        }
+
+        let sent = matches!(storage.get_custom_data(RETURNED_TRANSFERS_TREE, &key)?.to_value()?, DataValue::Bool(true));
+        Ok(Some(sent))
+    }
+
+    // Check if any transfer of a TX was sent back over the liability cap
+    pub(super) fn has_returned_transfers(&self, storage: &EncryptedStorage, tx_hash: &Hash) -> Result<bool> {
+        for key in storage.get_custom_tree_keys(&RETURNED_TRANSFERS_TREE.to_string(), &None, None, None)? {
+            let key: TransferKey = key.as_type()?;
+            if key.tx_hash == *tx_hash {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    // Send a transfer of a deposit back to its sender over the liability cap
+    // Like the debit of a withdrawal, the return is recorded before its TX is broadcast
+    // and only forgotten if nothing was sent
+    pub(super) async fn return_transfer(&self, tx_hash: &Hash, from: Address, asset: &Hash, amount: u64) -> Result<(u64, Hash), ServiceError> {
+        let key = DataValue::Blob(TransferKey { tx_hash: tx_hash.clone(), asset: asset.clone() }.to_bytes());
+        {
+            let mut storage = self.wallet.get_storage().write().await;
+            storage.set_custom_data(RETURNED_TRANSFERS_TREE, &key, &DataValue::Bool(false).into())?;
+        }
+
+        let result = self.send_back(from, asset, amount).await;
+        let mut storage = self.wallet.get_storage().write().await;
+        match result {
+            Ok(_) => storage.set_custom_data(RETURNED_TRANSFERS_TREE, &key, &DataValue::Bool(true).into())?,
+            Err(_) => storage.delete_custom_data(RETURNED_TRANSFERS_TREE, &key)?
+        }
+
+        result
+    }
+
+    // Send an incoming TX back to the address it came from
+    // If the deposit was credited to a user, it's debited from its balance first
+    // Returns each amount sent back with the hash of its TX
+    pub async fn return_deposit(&self, tx_hash: &Hash) -> Result<Vec<(AssetBalance, Hash)>, ServiceError> {
+        let key: DataValue = tx_hash.clone().into();
+        let (from, transfers, credited, refunded) = {
+            let storage = self.wallet.get_storage().read().await;
+            if storage.has_custom_data(RETURNED_DEPOSITS_TREE, &key)? {
+                return Err(ServiceError::DepositAlreadyReturned);
+            }
+
+            let entry = storage.get_transaction(tx_hash).map_err(|_| ServiceError::DepositNotFound)?;
+            let EntryType::Incoming { from, transfers } = entry.entry else {
+                return Err(ServiceError::DepositNotFound);
+            };
+
+            // The history of a forgotten user is deleted, but its deposits were credited
+            let forgotten = transfers.iter()
+                .filter_map(|transfer| transfer.extra_data.as_ref())
+                .filter_map(|data| data.data().and_then(|v| v.as_value().and_then(|v| v.as_type::<UserApplication>()).ok()))
+                .any(|user| self.is_forgotten_deposit(&storage, &user, entry.topoheight));
+            if forgotten {
+                return Err(ServiceError::DepositSpent);
+            }
+
+            (from, transfers, storage.has_custom_data(HISTORY_TREE, &key)?, self.is_unknown_deposit_refunded(&storage, tx_hash)?)
+        };
+
+        let mut returned = Vec::new();
+        for transfer in transfers {
+            let recipient = transfer.extra_data.as_ref()
+                .and_then(|data| data.data())
+                .and_then(|v| v.as_value().and_then(|v| v.as_type::<UserApplication>()).ok());
+
+            // Transfers that didn't name a user may already have been sent back by refund_deposit
+            if recipient.is_none() && refunded {
+                continue;
+            }
+
+            // Likewise for the transfers sent back over the liability cap
+            {
+                let storage = self.wallet.get_storage().read().await;
+                if self.get_returned_transfer(&storage, tx_hash, &transfer.asset)?.is_some() {
+                    continue;
+                }
+            }
+
+            let user = recipient.filter(|_| credited);
+
+            // A deposit already spent by its user can't be returned
+            if let Some(user) = user.as_ref() {
+                let mut storage = self.wallet.get_storage().write().await;
+                let balance = self.get_asset_balance_internal(&storage, user, &transfer.asset);
+                if balance < transfer.amount {
+                    return Err(ServiceError::DepositSpent);
+                }
+
+                self.set_asset_balance_internal(&mut storage, user, &transfer.asset, balance - transfer.amount, MovementKind::Refund)?;
+            }
+
+            match self.send_back(from.clone(), &transfer.asset, transfer.amount).await {
+                Ok((amount, hash)) => {
+                    let storage = self.wallet.get_storage().read().await;
+                    let amount = self.get_asset_balance_display(&storage, &transfer.asset, amount).await;
+                    info!("Returned {} of TX {} to {} in TX {}", amount, tx_hash, from, hash);
+                    returned.push((amount, hash));
+                },
+                Err(e) => {
+                    // Nothing was sent, give the deposit back to the user
+                    if let Some(user) = user.as_ref() {
+                        let mut storage = self.wallet.get_storage().write().await;
+                        let balance = self.get_asset_balance_internal(&storage, user, &transfer.asset);
+                        self.set_asset_balance_internal(&mut storage, user, &transfer.asset, balance + transfer.amount, MovementKind::Refund)?;
+                    }
+
+                    // Transfers already sent must not be sent twice
+                    if !returned.is_empty() {
+                        warn!("TX {} was only partially returned", tx_hash);
+                        let mut storage = self.wallet.get_storage().write().await;
+                        storage.set_custom_data(RETURNED_DEPOSITS_TREE, &key, &DataValue::Bool(true).into())?;
+                        self.resolve_unknown_deposit(&mut storage, tx_hash, returned.iter().map(|(_, hash)| hash.clone()).collect())?;
+                    }
+
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut storage = self.wallet.get_storage().write().await;
+        storage.set_custom_data(RETURNED_DEPOSITS_TREE, &key, &DataValue::Bool(true).into())?;
+        self.resolve_unknown_deposit(&mut storage, tx_hash, returned.iter().map(|(_, hash)| hash.clone()).collect())?;
+
+        Ok(returned)
+    }
+
+    // Get the deposits recorded for a user, oldest first
+    pub async fn get_deposits(&self, user: &UserApplication) -> Result<Vec<Deposit>> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut deposits = Vec::new();
+        for key in storage.get_custom_tree_keys(&HISTORY_TREE.to_string(), &None, None, None)? {
+            let owner: UserApplication = storage.get_custom_data(HISTORY_TREE, &key)?.to_value()?.to_type()?;
+            if owner != *user {
+                continue;
+            }
+
+            let tx_hash = key.as_hash()?;
+            let entry = storage.get_transaction(tx_hash)?;
+            let EntryType::Incoming { transfers, .. } = entry.entry else {
+                continue;
+            };
+
+            let mut amounts = Vec::new();
+            for transfer in transfers {
+                let recipient = transfer.extra_data.as_ref()
+                    .and_then(|data| data.data())
+                    .and_then(|v| v.as_value().and_then(|v| v.as_type::<UserApplication>()).ok());
+                if recipient == Some(*user) && self.get_returned_transfer(&storage, tx_hash, &transfer.asset)?.is_none() {
+                    amounts.push(self.get_asset_balance_display(&storage, &transfer.asset, transfer.amount).await);
+                }
+            }
+
+            deposits.push(Deposit {
+                tx_hash: tx_hash.clone(),
+                topoheight: entry.topoheight,
+                amounts,
+                returned: storage.has_custom_data(RETURNED_DEPOSITS_TREE, &key)?
+            });
+        }
+        deposits.sort_by_key(|deposit| deposit.topoheight);
+
+        Ok(deposits)
+    }
+}
@@ -0,0 +1,82 @@
+use tokio::sync::broadcast;
+use xelis_common::{crypto::Hash, time::get_current_time_in_seconds};
+
+use super::{UserApplication, WalletServiceImpl};
+
+// Events kept for a subscriber that reads too slowly, older ones are dropped
+pub(super) const EVENTS_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotEventKind {
+    DepositCredited,
+    Tip,
+    // Share of a rain received by one of its recipients
+    Rain,
+    WithdrawalBroadcast,
+    WithdrawalConfirmed
+}
+
+impl BotEventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DepositCredited => "deposit_credited",
+            Self::Tip => "tip",
+            Self::Rain => "rain",
+            Self::WithdrawalBroadcast => "withdrawal_broadcast",
+            Self::WithdrawalConfirmed => "withdrawal_confirmed"
+        }
+    }
+}
+
+// Activity of the bot, published to the subscribers as it happens
+#[derive(Debug, Clone)]
+pub struct BotEvent {
+    pub kind: BotEventKind,
+    // Timestamp in seconds
+    pub timestamp: u64,
+    // User depositing, tipping or withdrawing
+    pub user: UserApplication,
+    // User receiving a tip or a rain
+    pub recipient: Option<UserApplication>,
+    pub asset: Hash,
+    // Atomic units
+    pub amount: u64,
+    pub tx_hash: Option<Hash>
+}
+
+impl BotEvent {
+    pub(super) fn new(kind: BotEventKind, user: UserApplication, asset: Hash, amount: u64) -> Self {
+        Self {
+            kind,
+            timestamp: get_current_time_in_seconds(),
+            user,
+            recipient: None,
+            asset,
+            amount,
+            tx_hash: None
+        }
+    }
+
+    pub(super) fn with_recipient(mut self, recipient: UserApplication) -> Self {
+        self.recipient = Some(recipient);
+        self
+    }
+
+    pub(super) fn with_tx(mut self, tx_hash: Hash) -> Self {
+        self.tx_hash = Some(tx_hash);
+        self
+    }
+}
+
+impl WalletServiceImpl {
+    // Publish an event to the current subscribers, it's dropped if there is none
+    pub(super) fn publish_event(&self, event: BotEvent) {
+        // Fails only when nobody is subscribed
+        let _ = self.events.send(event);
+    }
+
+    // Receive the events published from now on
+    pub fn subscribe_bot_events(&self) -> broadcast::Receiver<BotEvent> {
+        self.events.subscribe()
+    }
+}
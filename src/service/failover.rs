@@ -0,0 +1,81 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{bail, Result};
+use log::{error, info, warn};
+use poise::serenity_prelude::{Colour, Http};
+use super::{ops::Alert, WalletBackend, WalletServiceImpl};
+
+// Daemons the wallet can connect to, in the order they were given
+pub(super) struct DaemonEndpoints {
+    addresses: Vec<String>,
+    // Index of the daemon the wallet is connected to
+    current: AtomicUsize
+}
+
+impl DaemonEndpoints {
+    // Connect the wallet to the first daemon that answers
+    pub(super) async fn connect(wallet: &dyn WalletBackend, addresses: Vec<String>) -> Result<Self> {
+        for (index, address) in addresses.iter().enumerate() {
+            match wallet.set_online_mode(address).await {
+                Ok(_) => {
+                    info!("Connected to the daemon at {}", address);
+                    return Ok(Self { addresses, current: AtomicUsize::new(index) })
+                },
+                Err(e) => warn!("Couldn't reach the daemon at {}: {}", address, e)
+            }
+        }
+
+        bail!("Couldn't reach any daemon at {}, check --daemon-address", addresses.join(", "))
+    }
+}
+
+impl WalletServiceImpl {
+    // Whether the daemon the wallet is connected to still answers
+    async fn is_daemon_reachable(&self) -> bool {
+        self.wallet.is_online().await && matches!(self.wallet.get_daemon_topoheight().await, Ok(Some(_)))
+    }
+
+    // Move the wallet to the next daemon when the current one can't be reached
+    // The daemons are tried in turn, the current one last, and the operators are told about the switch
+    // Returns true if the wallet switched to another daemon
+    pub(super) async fn fail_over(&self, http: &Http) -> Result<bool> {
+        let count = self.daemons.addresses.len();
+        if count < 2 || self.is_daemon_reachable().await {
+            return Ok(false);
+        }
+
+        let previous = self.daemons.current.load(Ordering::SeqCst);
+        let from = &self.daemons.addresses[previous];
+        warn!("The daemon at {} can't be reached, trying the other daemons", from);
+
+        // The wallet keeps reconnecting to the daemon it was given, it's stopped first
+        if self.wallet.is_online().await {
+            self.wallet.set_offline_mode().await?;
+        }
+
+        for index in (1..=count).map(|offset| (previous + offset) % count) {
+            let address = &self.daemons.addresses[index];
+            if let Err(e) = self.wallet.set_online_mode(address).await {
+                warn!("Couldn't reach the daemon at {}: {}", address, e);
+                continue;
+            }
+
+            self.daemons.current.store(index, Ordering::SeqCst);
+            if index == previous {
+                info!("The daemon at {} is reachable again", address);
+                return Ok(false);
+            }
+
+            info!("Switched from the daemon at {} to {}", from, address);
+            let alert = Alert::new("Daemon Failover", "The daemon couldn't be reached, the wallet switched to another one", Colour::ORANGE)
+                .field("From", from, true)
+                .field("To", address, true);
+            self.alert_operators(http, alert).await;
+            return Ok(true);
+        }
+
+        // The wallet stays offline, every daemon is tried again when the event loop restarts
+        error!("None of the {} daemons can be reached", count);
+        Ok(false)
+    }
+}
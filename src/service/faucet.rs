@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{info, warn};
+use xelis_common::{
+    api::DataValue,
+    config::XELIS_ASSET,
+    time::get_current_time_in_seconds,
+    utils::format_xelis
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{ledger::LedgerAccount, MovementKind, ServiceError, UserApplication, WalletServiceImpl, INTERNAL_BALANCES_TREE};
+
+// Last claim timestamp of each user
+pub(super) const FAUCET_TREE: &str = "faucet";
+// Key of the faucet in the internal balances
+const FAUCET_ACCOUNT: &str = "faucet";
+
+#[derive(Debug, Clone, Copy)]
+pub struct FaucetConfig {
+    // Amount given on each claim
+    pub amount: u64,
+    // Minimum time between two claims of the same user
+    pub interval: Duration
+}
+
+impl WalletServiceImpl {
+    pub(super) fn get_faucet_balance_internal(&self, storage: &EncryptedStorage) -> u64 {
+        storage.get_custom_data(INTERNAL_BALANCES_TREE, &DataValue::String(FAUCET_ACCOUNT.to_string()))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_u64().ok())
+            .unwrap_or(0)
+    }
+
+    // Get the balance available for faucet claims
+    pub async fn get_faucet_balance(&self) -> u64 {
+        let storage = self.wallet.get_storage().read().await;
+        self.get_faucet_balance_internal(&storage)
+    }
+
+    pub(super) fn add_faucet_balance_internal(&self, storage: &mut EncryptedStorage, amount: u64) -> Result<()> {
+        let balance = self.get_faucet_balance_internal(storage);
+        storage.set_custom_data(INTERNAL_BALANCES_TREE, &DataValue::String(FAUCET_ACCOUNT.to_string()), &(balance + amount).into())?;
+
+        Ok(())
+    }
+
+    // Add funds to the faucet balance, they come from the operator
+    pub async fn add_faucet_balance(&self, amount: u64) -> Result<(), ServiceError> {
+        warn!("Adding {} XEL to the faucet", format_xelis(amount));
+        let mut storage = self.wallet.get_storage().write().await;
+        self.add_faucet_balance_internal(&mut storage, amount)?;
+        self.post_ledger_entry(&mut storage, &XELIS_ASSET, MovementKind::Admin, LedgerAccount::Adjustments, LedgerAccount::Faucet, amount)?;
+
+        Ok(())
+    }
+
+    // Claim the faucet for a user
+    // Exempted users are not subject to the cooldown
+    // Returns the amount credited to the user
+    pub async fn claim_faucet(&self, user: &UserApplication, exempt: bool) -> Result<u64, ServiceError> {
+        let config = self.faucet.ok_or(ServiceError::FaucetDisabled)?;
+
+        let now = get_current_time_in_seconds();
+        let mut storage = self.wallet.get_storage().write().await;
+        let last_claim = storage.get_custom_data(FAUCET_TREE, &user.into())
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_u64().ok());
+
+        if let Some(last_claim) = last_claim.filter(|_| !exempt) {
+            let next_claim = last_claim + config.interval.as_secs();
+            if now < next_claim {
+                return Err(ServiceError::FaucetCooldown(next_claim - now));
+            }
+        }
+
+        let faucet_balance = self.get_faucet_balance_internal(&storage);
+        if faucet_balance < config.amount {
+            return Err(ServiceError::FaucetEmpty);
+        }
+
+        let balance = self.get_balance_internal(&storage, user);
+        storage.set_custom_data(INTERNAL_BALANCES_TREE, &DataValue::String(FAUCET_ACCOUNT.to_string()), &(faucet_balance - config.amount).into())?;
+        self.set_balance_internal(&mut storage, user, balance + config.amount, MovementKind::Faucet)?;
+        storage.set_custom_data(FAUCET_TREE, &user.into(), &now.into())?;
+
+        info!("{:?} claimed {} XEL from the faucet", user, format_xelis(config.amount));
+
+        Ok(config.amount)
+    }
+}
@@ -0,0 +1,262 @@
+use anyhow::Result;
+use log::info;
+use xelis_common::{
+    api::DataValue,
+    config::XELIS_ASSET,
+    crypto::{hash, Address, Hash},
+    serializer::Serializer,
+    utils::format_xelis
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{
+    acl::EXEMPTIONS_TREE,
+    budget::TIP_BUDGETS_TREE,
+    faucet::FAUCET_TREE,
+    games::GAMES_TREE,
+    goals::GOALS_TREE,
+    matrix::MATRIX_USERS_TREE,
+    pin::WITHDRAW_PINS_TREE,
+    practice::PRACTICE_TREE,
+    slack::SLACK_USERS_TREE,
+    reminders::{LAST_SEEN_TREE, REMINDERS_OPT_OUT_TREE, REMINDERS_TREE},
+    usernames::TELEGRAM_USERNAMES_TREE,
+    whitelist::WITHDRAW_WHITELIST_TREE,
+    withdrawals::WITHDRAWALS_TREE,
+    AssetBalance,
+    BalanceKey,
+    Exemption,
+    Goal,
+    MovementKind,
+    ServiceError,
+    UserApplication,
+    WalletServiceImpl,
+    BALANCES_TREE,
+    GIVEAWAYS_TREE,
+    HISTORY_TREE
+};
+
+// Users that asked to be forgotten, by hash of their id
+// Holds the topoheight of their last deposit, older deposits are never credited again
+pub(super) const FORGOTTEN_USERS_TREE: &str = "forgotten_users";
+
+// Trees with a single entry per user, keyed by the user
+const USER_TREES: [&str; 9] = [
+    TIP_BUDGETS_TREE,
+    WITHDRAWALS_TREE,
+    PRACTICE_TREE,
+    LAST_SEEN_TREE,
+    REMINDERS_TREE,
+    REMINDERS_OPT_OUT_TREE,
+    FAUCET_TREE,
+    WITHDRAW_PINS_TREE,
+    WITHDRAW_WHITELIST_TREE
+];
+
+// What happens to the balances of a user that asks to be forgotten
+#[derive(Debug, Clone)]
+pub enum Residual {
+    // Every balance is withdrawn to the address, XEL too small to be withdrawn is donated
+    Withdraw(Address),
+    // XEL goes to the faucet, other assets are left to the bot
+    Donate
+}
+
+#[derive(Debug, Clone)]
+pub struct ForgottenUser {
+    // Amounts withdrawn with the hash of their TX
+    pub withdrawn: Vec<(AssetBalance, Hash)>,
+    // XEL given to the faucet
+    pub donated: u64
+}
+
+// The tombstone doesn't keep the id of the user in clear
+fn tombstone_key(user: &UserApplication) -> DataValue {
+    DataValue::Blob(hash(&user.to_bytes()).to_bytes())
+}
+
+impl WalletServiceImpl {
+    // Check if a deposit was credited to a user that asked to be forgotten since
+    pub(super) fn is_forgotten_deposit(&self, storage: &EncryptedStorage, user: &UserApplication, topoheight: u64) -> bool {
+        storage.get_custom_data(FORGOTTEN_USERS_TREE, &tombstone_key(user))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_u64().ok())
+            .is_some_and(|last_deposit| topoheight <= last_deposit)
+    }
+
+    // Giveaways, challenges, goals and lottery rounds credit their users once they end
+    // A user can't be forgotten while one of them is running
+    fn has_running_escrow(&self, storage: &EncryptedStorage, user: &UserApplication) -> Result<bool> {
+        for key in storage.get_custom_tree_keys(&GIVEAWAYS_TREE.to_string(), &None, None, None)? {
+            let giveaway = self.get_giveaway_internal(storage, &key)?;
+            if giveaway.host == *user || giveaway.claims.contains(user) {
+                return Ok(true);
+            }
+        }
+
+        for key in storage.get_custom_tree_keys(&GAMES_TREE.to_string(), &None, None, None)? {
+            let challenge = self.get_challenge_internal(storage, &key)?;
+            if challenge.challenger == *user || challenge.opponent == Some(*user) {
+                return Ok(true);
+            }
+        }
+
+        for key in storage.get_custom_tree_keys(&GOALS_TREE.to_string(), &None, None, None)? {
+            let goal: Goal = storage.get_custom_data(GOALS_TREE, &key)?.to_value()?.to_type()?;
+            if goal.host == *user || goal.contributors.contains(user) {
+                return Ok(true);
+            }
+        }
+
+        Ok(self.get_lottery_round_internal(storage)
+            .is_some_and(|round| round.tickets.iter().any(|(holder, _)| holder == user)))
+    }
+
+    // Withdraw every balance of a user to an address, assets first as their fee is paid in XEL
+    // The XEL left is only kept for the donation if it can't be withdrawn
+    async fn withdraw_residual(&self, user: &UserApplication, to: &Address, pin: Option<&str>) -> Result<Vec<(AssetBalance, Hash)>, ServiceError> {
+        let mut withdrawn = Vec::new();
+        for balance in self.get_balances_for_user(user).await? {
+            if balance.asset == XELIS_ASSET || balance.amount == 0 {
+                continue;
+            }
+
+            let tx_hash = self.withdraw(user, to.clone(), &balance.asset, balance.amount, pin, None).await?;
+            withdrawn.push((balance, tx_hash));
+        }
+
+        let min_withdraw = self.runtime().min_withdraw;
+        let amount = match self.get_max_withdraw(user, to, &XELIS_ASSET).await {
+            Ok(amount) if amount >= min_withdraw => amount,
+            Ok(_) | Err(ServiceError::NotEnoughFundsForFee(_)) => return Ok(withdrawn),
+            Err(e) => return Err(e)
+        };
+
+        let tx_hash = self.withdraw(user, to.clone(), &XELIS_ASSET, amount, pin, None).await?;
+        withdrawn.push((AssetBalance::xelis(amount), tx_hash));
+
+        // The withdrawal limit may have kept some XEL back, it's not donated
+        match self.get_max_withdraw(user, to, &XELIS_ASSET).await {
+            Ok(amount) if amount >= min_withdraw => Err(ServiceError::ResidualBalance(amount)),
+            Ok(_) | Err(ServiceError::NotEnoughFundsForFee(_)) => Ok(withdrawn),
+            Err(e) => Err(e)
+        }
+    }
+
+    // Delete everything stored about a user, once its balances are withdrawn or donated
+    // A frozen user can't give its balances away
+    // A tombstone is kept so its past deposits are not credited again on a rescan
+    pub async fn forget_user(&self, user: &UserApplication, residual: Residual, pin: Option<&str>) -> Result<ForgottenUser, ServiceError> {
+        {
+            let storage = self.wallet.get_storage().read().await;
+            self.check_not_frozen(&storage, user)?;
+            if self.has_running_escrow(&storage, user)? {
+                return Err(ServiceError::EscrowRunning);
+            }
+        }
+
+        // Giving the balances away is as sensitive as a withdrawal
+        self.verify_withdraw_pin(user, pin).await?;
+
+        // The withdrawals can't be queued, the user is forgotten right after
+        if matches!(residual, Residual::Withdraw(_)) && !self.is_synced() {
+            return Err(ServiceError::Syncing);
+        }
+
+        let withdrawn = match &residual {
+            Residual::Withdraw(to) => self.withdraw_residual(user, to, pin).await?,
+            Residual::Donate => Vec::new()
+        };
+
+        let mut storage = self.wallet.get_storage().write().await;
+        self.check_not_frozen(&storage, user)?;
+        if self.has_running_escrow(&storage, user)? {
+            return Err(ServiceError::EscrowRunning);
+        }
+
+        let donated = self.get_balance_internal(&storage, user);
+        if donated > 0 {
+            self.add_faucet_balance_internal(&mut storage, donated)?;
+        }
+
+        let mut balances = Vec::new();
+        for key in storage.get_custom_tree_keys(&BALANCES_TREE.to_string(), &None, None, None)? {
+            let balance_key: BalanceKey = key.as_type()?;
+            if balance_key.user == *user {
+                balances.push((key, balance_key.asset));
+            }
+        }
+
+        // The deposits of the user are forgotten, the tombstone remembers the last one
+        let mut deposits = Vec::new();
+        let mut last_deposit = 0;
+        for key in storage.get_custom_tree_keys(&HISTORY_TREE.to_string(), &None, None, None)? {
+            let owner: UserApplication = storage.get_custom_data(HISTORY_TREE, &key)?.to_value()?.to_type()?;
+            if owner != *user {
+                continue;
+            }
+
+            if let Ok(entry) = storage.get_transaction(key.as_hash()?) {
+                last_deposit = last_deposit.max(entry.topoheight);
+            }
+            deposits.push(key);
+        }
+
+        // Telegram usernames point to the id of their user
+        let mut usernames = Vec::new();
+        if let UserApplication::Telegram(id) = user {
+            for key in storage.get_custom_tree_keys(&TELEGRAM_USERNAMES_TREE.to_string(), &None, None, None)? {
+                if storage.get_custom_data(TELEGRAM_USERNAMES_TREE, &key)?.to_value()?.to_u64()? == *id {
+                    usernames.push(key);
+                }
+            }
+        }
+
+        // The audit log keeps the balances going to the faucet or left to the bot
+        for (key, asset) in balances {
+            let kind = if asset == XELIS_ASSET { MovementKind::Faucet } else { MovementKind::Admin };
+            self.set_asset_balance_internal(&mut storage, user, &asset, 0, kind)?;
+            storage.delete_custom_data(BALANCES_TREE, &key)?;
+        }
+
+        for key in deposits.iter() {
+            storage.delete_custom_data(HISTORY_TREE, key)?;
+        }
+
+        for key in usernames {
+            storage.delete_custom_data(TELEGRAM_USERNAMES_TREE, &key)?;
+        }
+
+        // Matrix users are kept with their Matrix user ID
+        if let UserApplication::Matrix(id) = user {
+            storage.delete_custom_data(MATRIX_USERS_TREE, &DataValue::U64(*id))?;
+        }
+
+        // Likewise for the Slack user ID, the workspace keeps its installation
+        if let UserApplication::Slack(id) = user {
+            storage.delete_custom_data(SLACK_USERS_TREE, &DataValue::U64(*id))?;
+        }
+
+        for tree in USER_TREES {
+            storage.delete_custom_data(tree, &user.into())?;
+        }
+        storage.delete_custom_data(EXEMPTIONS_TREE, &DataValue::Blob(Exemption::User(*user).to_bytes()))?;
+
+        self.delete_addresses_internal(&mut storage, user)?;
+        self.delete_tip_stats_internal(&mut storage, user)?;
+        self.delete_movements_internal(&mut storage, user)?;
+        self.forget_ledger_account_internal(&mut storage, user)?;
+        self.delete_first_seen_internal(&mut storage, user)?;
+        self.delete_scheduled_reminders_internal(&mut storage, user)?;
+
+        storage.set_custom_data(FORGOTTEN_USERS_TREE, &tombstone_key(user), &last_deposit.into())?;
+
+        info!("A user asked to be forgotten: {} deposits and {} withdrawals, {} XEL donated to the faucet", deposits.len(), withdrawn.len(), format_xelis(donated));
+
+        Ok(ForgottenUser {
+            withdrawn,
+            donated
+        })
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::Result;
+use log::warn;
+use xelis_common::api::{DataElement, DataValue};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{ServiceError, UserApplication, WalletServiceImpl};
+
+// Users frozen by an operator, they can't transfer nor withdraw
+pub(super) const FROZEN_USERS_TREE: &str = "frozen_users";
+
+impl WalletServiceImpl {
+    // Freeze or unfreeze a user, returns false if it was already in this state
+    pub async fn set_frozen(&self, user: &UserApplication, frozen: bool) -> Result<bool> {
+        let mut storage = self.wallet.get_storage().write().await;
+        if storage.has_custom_data(FROZEN_USERS_TREE, &user.into())? == frozen {
+            return Ok(false);
+        }
+
+        if frozen {
+            warn!("Freezing {:?}", user);
+            self.freeze_internal(&mut storage, user)?;
+        } else {
+            warn!("Unfreezing {:?}", user);
+            storage.delete_custom_data(FROZEN_USERS_TREE, &user.into())?;
+        }
+
+        Ok(true)
+    }
+
+    // Freeze a user under a storage lock already held
+    pub(super) fn freeze_internal(&self, storage: &mut EncryptedStorage, user: &UserApplication) -> Result<()> {
+        storage.set_custom_data(FROZEN_USERS_TREE, &user.into(), &DataElement::Value(DataValue::Bool(true)))?;
+        Ok(())
+    }
+
+    // Check if a user is frozen
+    pub async fn is_frozen(&self, user: &UserApplication) -> bool {
+        let storage = self.wallet.get_storage().read().await;
+        storage.has_custom_data(FROZEN_USERS_TREE, &user.into()).unwrap_or(false)
+    }
+
+    // List all the frozen users
+    pub async fn get_frozen_users(&self) -> Result<Vec<UserApplication>> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut users = Vec::new();
+        for key in storage.get_custom_tree_keys(&FROZEN_USERS_TREE.to_string(), &None, None, None)? {
+            users.push(key.as_type()?);
+        }
+
+        Ok(users)
+    }
+
+    // Refuse to move the funds of a frozen user
+    // Checked under the storage lock of every debit the user asks for
+    pub(super) fn check_not_frozen(&self, storage: &EncryptedStorage, user: &UserApplication) -> Result<(), ServiceError> {
+        if storage.has_custom_data(FROZEN_USERS_TREE, &user.into())? {
+            return Err(ServiceError::AccountFrozen);
+        }
+
+        Ok(())
+    }
+}
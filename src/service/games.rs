@@ -0,0 +1,378 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use log::info;
+use rand::RngCore;
+use xelis_common::{
+    api::DataValue,
+    crypto::{hash, Hash},
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::{get_current_time_in_millis, get_current_time_in_seconds},
+    utils::format_xelis
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{MovementKind, ServiceError, UserApplication, WalletServiceImpl};
+
+pub(super) const GAMES_TREE: &str = "games";
+// Settled games, kept so results can be audited later
+pub(super) const GAMES_HISTORY_TREE: &str = "games_history";
+
+// How long a challenged user has to accept
+pub const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+// Games that can be played between two users
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Game {
+    Coinflip,
+    Dice
+}
+
+impl Game {
+    fn id(&self) -> u8 {
+        match self {
+            Game::Coinflip => 0,
+            Game::Dice => 1
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        Some(match id {
+            0 => Game::Coinflip,
+            1 => Game::Dice,
+            _ => return None
+        })
+    }
+}
+
+// A challenge escrows the stake of the challenger until the opponent
+// accepts it, declines it or it expires
+// The outcome is derived from a secret seed whose hash is published
+// when the challenge is created, so players can verify it wasn't changed
+#[derive(Debug, Clone)]
+pub struct Challenge {
+    pub id: u64,
+    pub game: Game,
+    pub challenger: UserApplication,
+    // None if anyone can accept the challenge
+    pub opponent: Option<UserApplication>,
+    // Stake of each player
+    pub amount: u64,
+    // Timestamp in seconds
+    pub expire_at: u64,
+    // Discord channel or Telegram chat where the challenge was sent
+    pub channel_id: u64,
+    seed: Hash
+}
+
+impl Challenge {
+    // Hash of the seed, published before the outcome is known
+    pub fn commitment(&self) -> Hash {
+        hash(self.seed.as_bytes())
+    }
+
+    // Seed revealed once the challenge is settled
+    pub fn seed(&self) -> &Hash {
+        &self.seed
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.expire_at
+    }
+
+    // Random value of the game: hash(seed || id)
+    fn roll(&self) -> Hash {
+        let mut bytes = self.seed.as_bytes().to_vec();
+        bytes.extend_from_slice(&self.id.to_be_bytes());
+        hash(&bytes)
+    }
+
+    // Random value of a dice round: hash(seed || id || round)
+    fn roll_round(&self, round: u32) -> Hash {
+        let mut bytes = self.seed.as_bytes().to_vec();
+        bytes.extend_from_slice(&self.id.to_be_bytes());
+        bytes.extend_from_slice(&round.to_be_bytes());
+        hash(&bytes)
+    }
+
+    // Play the game, returns if the challenger won with the outcome
+    fn play(&self) -> (bool, Outcome) {
+        match self.game {
+            Game::Coinflip => {
+                let heads = self.roll().as_bytes()[0] & 1 == 0;
+                (heads, Outcome::Coinflip { heads })
+            },
+            Game::Dice => {
+                // Both players roll a dice, rounds are replayed until there is no tie
+                let mut round = 0;
+                loop {
+                    let roll = self.roll_round(round);
+                    let bytes = roll.as_bytes();
+                    let challenger = (u64::from_be_bytes(bytes[0..8].try_into().expect("8 bytes")) % 6) as u8 + 1;
+                    let opponent = (u64::from_be_bytes(bytes[8..16].try_into().expect("8 bytes")) % 6) as u8 + 1;
+                    if challenger != opponent {
+                        return (challenger > opponent, Outcome::Dice { challenger, opponent, rerolls: round });
+                    }
+                    round += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Serializer for Challenge {
+    fn write(&self, writer: &mut Writer) {
+        self.id.write(writer);
+        writer.write_u8(self.game.id());
+        self.challenger.write(writer);
+        match &self.opponent {
+            Some(opponent) => {
+                writer.write_u8(1);
+                opponent.write(writer);
+            },
+            None => writer.write_u8(0)
+        }
+        self.amount.write(writer);
+        self.expire_at.write(writer);
+        self.channel_id.write(writer);
+        self.seed.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        Ok(Self {
+            id: reader.read_u64()?,
+            game: Game::from_id(reader.read_u8()?).ok_or(ReaderError::InvalidValue)?,
+            challenger: UserApplication::read(reader)?,
+            opponent: match reader.read_u8()? {
+                0 => None,
+                1 => Some(UserApplication::read(reader)?),
+                _ => return Err(ReaderError::InvalidValue)
+            },
+            amount: reader.read_u64()?,
+            expire_at: reader.read_u64()?,
+            channel_id: reader.read_u64()?,
+            seed: Hash::read(reader)?
+        })
+    }
+}
+
+// What happened during a game
+#[derive(Debug, Clone, Copy)]
+pub enum Outcome {
+    // The challenger is always heads
+    Coinflip { heads: bool },
+    // Final dice of each player and how many ties were replayed
+    Dice { challenger: u8, opponent: u8, rerolls: u32 }
+}
+
+// Result of a challenge accepted by the opponent
+#[derive(Debug, Clone)]
+pub struct ChallengeResult {
+    // Opponent is always set to the user who accepted
+    pub challenge: Challenge,
+    pub winner: UserApplication,
+    pub outcome: Outcome,
+    // Amount credited to the winner
+    pub prize: u64
+}
+
+impl Serializer for ChallengeResult {
+    fn write(&self, writer: &mut Writer) {
+        self.challenge.write(writer);
+        self.winner.write(writer);
+        match self.outcome {
+            Outcome::Coinflip { heads } => {
+                writer.write_u8(0);
+                writer.write_bool(heads);
+            },
+            Outcome::Dice { challenger, opponent, rerolls } => {
+                writer.write_u8(1);
+                writer.write_u8(challenger);
+                writer.write_u8(opponent);
+                rerolls.write(writer);
+            }
+        }
+        self.prize.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let challenge = Challenge::read(reader)?;
+        let winner = UserApplication::read(reader)?;
+        let outcome = match reader.read_u8()? {
+            0 => Outcome::Coinflip { heads: reader.read_bool()? },
+            1 => Outcome::Dice {
+                challenger: reader.read_u8()?,
+                opponent: reader.read_u8()?,
+                rerolls: reader.read_u32()?
+            },
+            _ => return Err(ReaderError::InvalidValue)
+        };
+
+        Ok(Self {
+            challenge,
+            winner,
+            outcome,
+            prize: reader.read_u64()?
+        })
+    }
+}
+
+impl WalletServiceImpl {
+    // Load a challenge from its storage key
+    pub(super) fn get_challenge_internal(&self, storage: &EncryptedStorage, key: &DataValue) -> Result<Challenge> {
+        let challenge = storage.get_custom_data(GAMES_TREE, key)?.to_value()?.to_type()?;
+        Ok(challenge)
+    }
+
+    // Challenge a user, the stake is escrowed from the challenger balance
+    // Without opponent, anyone can accept the challenge
+    pub async fn create_challenge(&self, game: Game, challenger: &UserApplication, opponent: Option<&UserApplication>, amount: u64, channel_id: u64) -> Result<Challenge, ServiceError> {
+        if amount == 0 {
+            return Err(ServiceError::Zero);
+        }
+
+        if opponent == Some(challenger) {
+            return Err(ServiceError::SelfChallenge);
+        }
+
+        // Prize must fit for the winner
+        amount.checked_mul(2).ok_or(ServiceError::Overflow)?;
+
+        let mut storage = self.wallet.get_storage().write().await;
+        self.check_not_frozen(&storage, challenger)?;
+        let balance = self.get_balance_internal(&storage, challenger);
+        if amount > balance {
+            return Err(ServiceError::NotEnoughFunds(amount));
+        }
+
+        // Find an unused id
+        let mut id = get_current_time_in_millis();
+        while storage.has_custom_data(GAMES_TREE, &DataValue::U64(id))? {
+            id += 1;
+        }
+
+        let mut seed = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut seed);
+
+        let challenge = Challenge {
+            id,
+            game,
+            challenger: *challenger,
+            opponent: opponent.copied(),
+            amount,
+            expire_at: get_current_time_in_seconds() + CHALLENGE_TIMEOUT.as_secs(),
+            channel_id,
+            seed: Hash::new(seed)
+        };
+
+        info!("{:?} challenged {:?} to {:?} {} for {} XEL", challenger, opponent, game, id, format_xelis(amount));
+        self.set_balance_internal(&mut storage, challenger, balance - amount, MovementKind::Game)?;
+        storage.set_custom_data(GAMES_TREE, &DataValue::U64(id), &DataValue::Blob(challenge.to_bytes()).into())?;
+
+        Ok(challenge)
+    }
+
+    // Accept a challenge: the opponent stake is escrowed and the game is played
+    pub async fn accept_challenge(&self, id: u64, user: &UserApplication) -> Result<ChallengeResult, ServiceError> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let key = DataValue::U64(id);
+        let mut challenge = match self.get_challenge_internal(&storage, &key) {
+            Ok(challenge) => challenge,
+            Err(_) => return Err(ServiceError::ChallengeNotFound)
+        };
+
+        if challenge.is_expired(get_current_time_in_seconds()) {
+            return Err(ServiceError::ChallengeNotFound);
+        }
+
+        if challenge.challenger == *user {
+            return Err(ServiceError::SelfChallenge);
+        }
+
+        if challenge.opponent.is_some_and(|opponent| opponent != *user) {
+            return Err(ServiceError::NotChallenged);
+        }
+        challenge.opponent = Some(*user);
+
+        // Losing on purpose would move the funds of a frozen account to the challenger
+        self.check_not_frozen(&storage, user)?;
+        let balance = self.get_balance_internal(&storage, user);
+        if challenge.amount > balance {
+            return Err(ServiceError::NotEnoughFunds(challenge.amount));
+        }
+
+        let (challenger_won, outcome) = challenge.play();
+        let winner = if challenger_won { challenge.challenger } else { *user };
+
+        // Nothing is written until both balances are known to fit
+        let prize = challenge.amount.checked_mul(2).ok_or(ServiceError::Overflow)?;
+        let winner_balance = if winner == *user {
+            balance - challenge.amount
+        } else {
+            self.get_balance_internal(&storage, &winner)
+        };
+        let winner_balance = winner_balance.checked_add(prize).ok_or(ServiceError::Overflow)?;
+
+        self.set_balance_internal(&mut storage, user, balance - challenge.amount, MovementKind::Game)?;
+        self.set_balance_internal(&mut storage, &winner, winner_balance, MovementKind::Game)?;
+        storage.delete_custom_data(GAMES_TREE, &key)?;
+
+        let result = ChallengeResult {
+            challenge,
+            winner,
+            outcome,
+            prize
+        };
+        storage.set_custom_data(GAMES_HISTORY_TREE, &key, &DataValue::Blob(result.to_bytes()).into())?;
+
+        info!("{:?} {} settled: {:?} won {} XEL ({:?})", result.challenge.game, id, winner, format_xelis(prize), outcome);
+
+        Ok(result)
+    }
+
+    // Decline or cancel a challenge, the stake is refunded to the challenger
+    pub async fn cancel_challenge(&self, id: u64, user: &UserApplication) -> Result<Challenge, ServiceError> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let key = DataValue::U64(id);
+        let challenge = match self.get_challenge_internal(&storage, &key) {
+            Ok(challenge) => challenge,
+            Err(_) => return Err(ServiceError::ChallengeNotFound)
+        };
+
+        if challenge.challenger != *user && challenge.opponent != Some(*user) {
+            return Err(ServiceError::NotChallenged);
+        }
+
+        let balance = self.get_balance_internal(&storage, &challenge.challenger);
+        let balance = balance.checked_add(challenge.amount).ok_or(ServiceError::Overflow)?;
+        self.set_balance_internal(&mut storage, &challenge.challenger, balance, MovementKind::Game)?;
+        storage.delete_custom_data(GAMES_TREE, &key)?;
+
+        info!("{:?} {} cancelled by {:?}", challenge.game, id, user);
+
+        Ok(challenge)
+    }
+
+    // Refund the challenges that were not accepted in time
+    pub(super) async fn expire_challenges(&self) -> Result<Vec<Challenge>> {
+        let now = get_current_time_in_seconds();
+        let mut storage = self.wallet.get_storage().write().await;
+        let mut expired = Vec::new();
+        for key in storage.get_custom_tree_keys(&GAMES_TREE.to_string(), &None, None, None)? {
+            let challenge = self.get_challenge_internal(&storage, &key)?;
+            if !challenge.is_expired(now) {
+                continue;
+            }
+
+            let balance = self.get_balance_internal(&storage, &challenge.challenger);
+            let balance = balance.checked_add(challenge.amount).ok_or(ServiceError::Overflow)?;
+            self.set_balance_internal(&mut storage, &challenge.challenger, balance, MovementKind::Game)?;
+            storage.delete_custom_data(GAMES_TREE, &key)?;
+
+            info!("{:?} {} expired, {} XEL refunded to {:?}", challenge.game, challenge.id, format_xelis(challenge.amount), challenge.challenger);
+            expired.push(challenge);
+        }
+
+        Ok(expired)
+    }
+}
@@ -0,0 +1,418 @@
+use anyhow::Result;
+use chrono::{DateTime, Datelike, NaiveDate};
+use log::{info, warn};
+use poise::serenity_prelude::{ChannelId, CreateEmbed, CreateMessage, EditMessage, Http, MessageId};
+use teloxide::Bot;
+use xelis_common::{
+    api::DataValue,
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds,
+    utils::format_xelis
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use crate::{telegram_message::{escape_html, TelegramMessage}, COLOR, ICON};
+
+use super::{discord_id, telegram_chat, Community, MovementKind, ServiceError, UserApplication, WalletServiceImpl};
+
+pub(super) const GOALS_TREE: &str = "goals";
+
+// Maximum length in bytes of the celebration message
+pub const MAX_GOAL_MESSAGE_LENGTH: usize = 200;
+// Characters of the progress bar
+const PROGRESS_BAR_LENGTH: u64 = 10;
+
+// A tipping goal of a community for the current month
+// Every XEL tip made in the community counts toward the target,
+// the optional reward is escrowed from the admin that set the goal
+// and split between the tippers once the target is reached
+#[derive(Debug, Clone)]
+pub struct Goal {
+    pub community: Community,
+    pub host: UserApplication,
+    pub target: u64,
+    pub tipped: u64,
+    pub reward: u64,
+    // Announced when the target is reached
+    pub celebration: Option<String>,
+    // Timestamp in seconds, end of the month in which the goal was set
+    pub expire_at: u64,
+    // Discord channel or Telegram chat of the progress message
+    pub channel_id: u64,
+    pub message_id: Option<u64>,
+    pub contributors: Vec<UserApplication>,
+    // Progress changed since the message was last updated
+    dirty: bool
+}
+
+// What happened to a goal since its message was last updated
+#[derive(Debug, Clone, Copy)]
+pub enum GoalUpdate {
+    Progress,
+    // Share of the reward received by each contributor
+    Reached(u64),
+    Expired
+}
+
+impl Goal {
+    pub fn is_reached(&self) -> bool {
+        self.tipped >= self.target
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.expire_at
+    }
+
+    // Percentage of the target already tipped
+    pub fn percent(&self) -> u64 {
+        (self.tipped as u128 * 100 / self.target.max(1) as u128).min(100) as u64
+    }
+
+    pub fn progress_bar(&self) -> String {
+        let filled = self.percent() * PROGRESS_BAR_LENGTH / 100;
+        format!(
+            "{}{} {}%",
+            "▓".repeat(filled as usize),
+            "░".repeat((PROGRESS_BAR_LENGTH - filled) as usize),
+            self.percent()
+        )
+    }
+
+    // Embed of the progress message on Discord
+    pub fn embed(&self, update: GoalUpdate) -> CreateEmbed {
+        let mut embed = CreateEmbed::default()
+            .title("Tipping goal")
+            .description(format!("{} XEL tipped this month", format_xelis(self.target)))
+            .field("Progress", format!("{} / {} XEL\n{}", format_xelis(self.tipped), format_xelis(self.target), self.progress_bar()), false)
+            .field("Tippers", self.contributors.len().to_string(), true)
+            .thumbnail(ICON)
+            .colour(COLOR);
+
+        if self.reward > 0 {
+            embed = embed.field("Reward", format!("{} XEL split between the tippers", format_xelis(self.reward)), true);
+        }
+
+        match update {
+            GoalUpdate::Progress => embed.field("Ends", format!("<t:{}:R>", self.expire_at), true),
+            GoalUpdate::Reached(_) => embed.field("Status", "Reached", true),
+            GoalUpdate::Expired => embed.field("Status", "Expired", true)
+        }
+    }
+
+    // Content of the progress message on Telegram
+    pub fn write_telegram_message(&self, message: &mut TelegramMessage, update: GoalUpdate) {
+        message.title("Tipping goal")
+            .line(format!("{} XEL tipped this month", format_xelis(self.target)))
+            .field("Progress", format!("{} / {} XEL\n{}", format_xelis(self.tipped), format_xelis(self.target), self.progress_bar()), false)
+            .field("Tippers", self.contributors.len().to_string(), true);
+
+        if self.reward > 0 {
+            message.field("Reward", format!("{} XEL split between the tippers", format_xelis(self.reward)), true);
+        }
+
+        match update {
+            GoalUpdate::Progress => {
+                let remaining = self.expire_at.saturating_sub(get_current_time_in_seconds());
+                message.field("Ends in", format!("{} days", remaining.div_ceil(60 * 60 * 24)), true)
+            },
+            GoalUpdate::Reached(_) => message.field("Status", "Reached", true),
+            GoalUpdate::Expired => message.field("Status", "Expired", true)
+        };
+    }
+}
+
+impl Serializer for Goal {
+    fn write(&self, writer: &mut Writer) {
+        self.community.write(writer);
+        self.host.write(writer);
+        self.target.write(writer);
+        self.tipped.write(writer);
+        self.reward.write(writer);
+        self.celebration.write(writer);
+        self.expire_at.write(writer);
+        self.channel_id.write(writer);
+        self.message_id.write(writer);
+        (self.contributors.len() as u32).write(writer);
+        for contributor in self.contributors.iter() {
+            contributor.write(writer);
+        }
+        writer.write_bool(self.dirty);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let community = Community::read(reader)?;
+        let host = UserApplication::read(reader)?;
+        let target = reader.read_u64()?;
+        let tipped = reader.read_u64()?;
+        let reward = reader.read_u64()?;
+        let celebration = Option::read(reader)?;
+        let expire_at = reader.read_u64()?;
+        let channel_id = reader.read_u64()?;
+        let message_id = Option::read(reader)?;
+        let len = reader.read_u32()?;
+        let mut contributors = Vec::with_capacity(len.min(1024) as usize);
+        for _ in 0..len {
+            contributors.push(UserApplication::read(reader)?);
+        }
+        let dirty = reader.read_bool()?;
+
+        Ok(Self {
+            community,
+            host,
+            target,
+            tipped,
+            reward,
+            celebration,
+            expire_at,
+            channel_id,
+            message_id,
+            contributors,
+            dirty
+        })
+    }
+}
+
+// Timestamp of the first second of the next month in UTC
+fn end_of_month(now: u64) -> u64 {
+    let date = DateTime::from_timestamp(now as i64, 0).unwrap_or_default().date_naive();
+    let (year, month) = match date.month() {
+        12 => (date.year() + 1, 1),
+        month => (date.year(), month + 1)
+    };
+
+    NaiveDate::from_ymd_opt(year, month, 1)
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| date.and_utc().timestamp() as u64)
+        .unwrap_or(now)
+}
+
+impl WalletServiceImpl {
+    fn get_goal_internal(&self, storage: &EncryptedStorage, community: &Community) -> Option<Goal> {
+        storage.get_custom_data(GOALS_TREE, &DataValue::Blob(community.to_bytes()))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_type().ok())
+    }
+
+    fn set_goal_internal(&self, storage: &mut EncryptedStorage, goal: &Goal) -> Result<()> {
+        storage.set_custom_data(GOALS_TREE, &DataValue::Blob(goal.community.to_bytes()), &DataValue::Blob(goal.to_bytes()).into())?;
+        Ok(())
+    }
+
+    // Set the tipping goal of a community until the end of the month
+    // The reward is escrowed from the host balance, 0 only announces the goal
+    pub async fn set_goal(&self, community: &Community, host: &UserApplication, target: u64, reward: u64, celebration: Option<String>, channel_id: u64) -> Result<Goal, ServiceError> {
+        if target == 0 {
+            return Err(ServiceError::Zero);
+        }
+
+        if celebration.as_ref().is_some_and(|message| message.len() > MAX_GOAL_MESSAGE_LENGTH) {
+            return Err(ServiceError::GoalMessageTooLong);
+        }
+
+        let mut storage = self.wallet.get_storage().write().await;
+        if self.get_goal_internal(&storage, community).is_some() {
+            return Err(ServiceError::GoalAlreadySet);
+        }
+
+        if reward > 0 {
+            self.check_not_frozen(&storage, host)?;
+            let balance = self.get_balance_internal(&storage, host);
+            if reward > balance {
+                return Err(ServiceError::NotEnoughFunds(reward));
+            }
+
+            self.set_balance_internal(&mut storage, host, balance - reward, MovementKind::Goal)?;
+        }
+
+        let goal = Goal {
+            community: *community,
+            host: *host,
+            target,
+            tipped: 0,
+            reward,
+            celebration,
+            expire_at: end_of_month(get_current_time_in_seconds()),
+            channel_id,
+            message_id: None,
+            contributors: Vec::new(),
+            dirty: false
+        };
+
+        info!("{:?} set a goal of {} XEL in {:?} with a reward of {} XEL", host, format_xelis(target), community, format_xelis(reward));
+        self.set_goal_internal(&mut storage, &goal)?;
+
+        Ok(goal)
+    }
+
+    // Remember the progress message of a goal so it can be updated
+    pub async fn set_goal_message(&self, community: &Community, message_id: u64) -> Result<(), ServiceError> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let mut goal = self.get_goal_internal(&storage, community).ok_or(ServiceError::GoalNotFound)?;
+        goal.message_id = Some(message_id);
+        self.set_goal_internal(&mut storage, &goal)?;
+
+        Ok(())
+    }
+
+    // Get the running goal of a community
+    pub async fn get_goal(&self, community: &Community) -> Option<Goal> {
+        let storage = self.wallet.get_storage().read().await;
+        self.get_goal_internal(&storage, community)
+    }
+
+    // Cancel the goal of a community, its reward goes back to the host
+    pub async fn cancel_goal(&self, community: &Community) -> Result<Goal, ServiceError> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let goal = self.get_goal_internal(&storage, community).ok_or(ServiceError::GoalNotFound)?;
+        if goal.reward > 0 {
+            let balance = self.get_balance_internal(&storage, &goal.host);
+            self.set_balance_internal(&mut storage, &goal.host, balance + goal.reward, MovementKind::Goal)?;
+        }
+
+        info!("Goal of {:?} cancelled, {} XEL refunded to {:?}", community, format_xelis(goal.reward), goal.host);
+        storage.delete_custom_data(GOALS_TREE, &DataValue::Blob(community.to_bytes()))?;
+
+        Ok(goal)
+    }
+
+    // Count a tip toward the goal of its community
+    pub(super) fn record_goal_progress(&self, storage: &mut EncryptedStorage, community: Option<Community>, from: &UserApplication, amount: u64) -> Result<()> {
+        let Some(mut goal) = community.and_then(|community| self.get_goal_internal(storage, &community)) else {
+            return Ok(());
+        };
+
+        if goal.is_reached() || goal.is_expired(get_current_time_in_seconds()) {
+            return Ok(());
+        }
+
+        goal.tipped = goal.tipped.saturating_add(amount);
+        if !goal.contributors.contains(from) {
+            goal.contributors.push(*from);
+        }
+        goal.dirty = true;
+
+        self.set_goal_internal(storage, &goal)
+    }
+
+    // Settle the goals that are reached or expired and collect the ones whose progress changed
+    async fn settle_goals(&self) -> Result<Vec<(Goal, GoalUpdate)>> {
+        let now = get_current_time_in_seconds();
+        let mut storage = self.wallet.get_storage().write().await;
+        let mut updates = Vec::new();
+        for key in storage.get_custom_tree_keys(&GOALS_TREE.to_string(), &None, None, None)? {
+            let mut goal: Goal = storage.get_custom_data(GOALS_TREE, &key)?.to_value()?.to_type()?;
+            if goal.is_reached() {
+                // Split the reward, the remaining dust goes back to the host
+                let share = goal.reward.checked_div(goal.contributors.len() as u64).unwrap_or(0);
+                for contributor in goal.contributors.iter() {
+                    let balance = self.get_balance_internal(&storage, contributor);
+                    self.set_balance_internal(&mut storage, contributor, balance + share, MovementKind::Goal)?;
+                }
+
+                let refund = goal.reward - share * goal.contributors.len() as u64;
+                if refund > 0 {
+                    let balance = self.get_balance_internal(&storage, &goal.host);
+                    self.set_balance_internal(&mut storage, &goal.host, balance + refund, MovementKind::Goal)?;
+                }
+
+                info!("Goal of {:?} reached: {} contributors received {} XEL", goal.community, goal.contributors.len(), format_xelis(share));
+                storage.delete_custom_data(GOALS_TREE, &key)?;
+                updates.push((goal, GoalUpdate::Reached(share)));
+            } else if goal.is_expired(now) {
+                if goal.reward > 0 {
+                    let balance = self.get_balance_internal(&storage, &goal.host);
+                    self.set_balance_internal(&mut storage, &goal.host, balance + goal.reward, MovementKind::Goal)?;
+                }
+
+                info!("Goal of {:?} expired at {} / {} XEL", goal.community, format_xelis(goal.tipped), format_xelis(goal.target));
+                storage.delete_custom_data(GOALS_TREE, &key)?;
+                updates.push((goal, GoalUpdate::Expired));
+            } else if goal.dirty {
+                goal.dirty = false;
+                self.set_goal_internal(&mut storage, &goal)?;
+                updates.push((goal, GoalUpdate::Progress));
+            }
+        }
+
+        Ok(updates)
+    }
+
+    // Refresh the progress messages and celebrate the reached goals
+    pub(super) async fn update_goals(&self, http: &Http, bot: &Bot) -> Result<()> {
+        // The goals are already settled, a message that can't be sent is only logged
+        for (goal, update) in self.settle_goals().await? {
+            if let Some(message_id) = goal.message_id {
+                if let Err(e) = self.edit_goal_message(http, bot, &goal, message_id, update).await {
+                    warn!("Couldn't update the goal message of {:?}: {}", goal.community, e);
+                }
+            }
+
+            if let GoalUpdate::Reached(share) = update {
+                if let Err(e) = self.announce_goal_reached(http, bot, &goal, share).await {
+                    warn!("Couldn't announce the goal of {:?}: {}", goal.community, e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn edit_goal_message(&self, http: &Http, bot: &Bot, goal: &Goal, message_id: u64, update: GoalUpdate) -> Result<()> {
+        match goal.community {
+            Community::Discord(_) => {
+                discord_id::<ChannelId>(goal.channel_id)?.edit_message(http, discord_id::<MessageId>(message_id)?, EditMessage::new().embed(goal.embed(update))).await?;
+            },
+            Community::Telegram(_) => {
+                let mut message = TelegramMessage::new(bot, telegram_chat(goal.channel_id), None);
+                goal.write_telegram_message(&mut message, update);
+                message.edit(teloxide::types::MessageId(message_id as i32)).await?;
+            },
+            // Goals are only set on Discord and Telegram
+            Community::Slack(_) | Community::Twitch(_) => {}
+        }
+
+        Ok(())
+    }
+
+    async fn announce_goal_reached(&self, http: &Http, bot: &Bot, goal: &Goal, share: u64) -> Result<()> {
+        let description = format!("{} users tipped {} XEL this month", goal.contributors.len(), format_xelis(goal.tipped));
+        match goal.community {
+            Community::Discord(_) => {
+                let mut embed = CreateEmbed::default()
+                    .title("Tipping goal reached!")
+                    .description(description)
+                    .thumbnail(ICON)
+                    .colour(COLOR);
+
+                if let Some(celebration) = goal.celebration.as_deref() {
+                    embed = embed.field("Message", celebration, false);
+                }
+
+                if share > 0 {
+                    embed = embed.field("Each tipper received", format!("{} XEL", format_xelis(share)), false);
+                }
+
+                discord_id::<ChannelId>(goal.channel_id)?.send_message(http, CreateMessage::default().embed(embed)).await?;
+            },
+            Community::Telegram(_) => {
+                let mut message = TelegramMessage::new(bot, telegram_chat(goal.channel_id), None);
+                message.title("Tipping goal reached!")
+                    .line(description);
+
+                if let Some(celebration) = goal.celebration.as_deref() {
+                    message.field("Message", escape_html(celebration), false);
+                }
+
+                if share > 0 {
+                    message.field("Each tipper received", format!("{} XEL", format_xelis(share)), false);
+                }
+
+                message.send().await?;
+            },
+            Community::Slack(_) | Community::Twitch(_) => {}
+        }
+
+        Ok(())
+    }
+}
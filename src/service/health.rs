@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use log::warn;
+
+use super::WalletServiceImpl;
+
+// State of the wallet and the event loop, for the probes and the uptime monitors
+#[derive(Debug, Clone, Copy)]
+pub struct Health {
+    pub online: bool,
+    // A standby instance doesn't run the event loop
+    pub leader: bool,
+    pub wallet_topoheight: u64,
+    // None while the daemon can't be reached
+    pub daemon_topoheight: Option<u64>,
+    // Time since the event loop handled its last event, if any
+    pub last_event: Option<Duration>
+}
+
+impl Health {
+    // Blocks the wallet is behind the daemon
+    pub fn sync_lag(&self) -> Option<u64> {
+        self.daemon_topoheight.map(|topoheight| topoheight.saturating_sub(self.wallet_topoheight))
+    }
+}
+
+impl WalletServiceImpl {
+    // Snapshot of the wallet sync and the event loop activity
+    pub async fn get_health(&self) -> Result<Health> {
+        let online = self.wallet.is_online().await;
+        // The daemon not answering is reported, not returned as an error
+        let daemon_topoheight = if online {
+            match self.wallet.get_daemon_topoheight().await {
+                Ok(topoheight) => topoheight,
+                Err(e) => {
+                    warn!("Couldn't get the topoheight of the daemon: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(Health {
+            online,
+            leader: self.is_leader(),
+            wallet_topoheight: self.get_wallet_topoheight().await?,
+            daemon_topoheight,
+            last_event: self.get_queue_status().last_event
+        })
+    }
+}
@@ -0,0 +1,101 @@
+use std::num::NonZeroU64;
+
+use anyhow::Result;
+use teloxide::types::ChatId;
+use thiserror::Error;
+use xelis_common::{api::DataValue, crypto::hash};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::WalletServiceImpl;
+
+// DM chat of each Telegram user, recorded when they /start the bot or write to it in private
+pub(super) const TELEGRAM_CHATS_TREE: &str = "telegram_chats";
+
+#[derive(Debug, Error)]
+pub enum IdError {
+    #[error("Discord ID can't be 0")]
+    DiscordZero,
+    #[error("Telegram user ID {} is out of range", .0)]
+    TelegramOutOfRange(u64),
+}
+
+// Discord IDs are snowflakes that are never 0, serenity panics on them
+pub fn discord_id<T: From<NonZeroU64>>(id: u64) -> Result<T, IdError> {
+    NonZeroU64::new(id)
+        .map(T::from)
+        .ok_or(IdError::DiscordZero)
+}
+
+// Telegram chats are stored as u64 with the same bits, group chats are negative
+// This mapping is part of the storage format and must stay as is
+pub fn telegram_channel(chat: ChatId) -> u64 {
+    chat.0 as u64
+}
+
+// Chat stored with `telegram_channel`
+pub fn telegram_chat(channel: u64) -> ChatId {
+    ChatId(channel as i64)
+}
+
+// Platforms with text IDs, such as Matrix and Slack, identify their users by the first 8 bytes of the blake3 hash of the ID
+// The ID is recorded with its hash, so two users can't share a balance
+// This mapping is part of the storage format and must stay as is
+pub(super) fn text_id(id: &str) -> u64 {
+    let digest = hash(id.as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest.as_bytes()[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+// Telegram user IDs are positive, a user ID above i64::MAX can't be a chat
+fn telegram_user_chat(user_id: u64) -> Result<ChatId, IdError> {
+    i64::try_from(user_id)
+        .map(ChatId)
+        .map_err(|_| IdError::TelegramOutOfRange(user_id))
+}
+
+impl WalletServiceImpl {
+    // Remember the DM chat of a Telegram user
+    // Returns true if the user wasn't registered yet
+    pub async fn record_telegram_chat(&self, user_id: u64, chat: ChatId) -> Result<bool> {
+        let key = DataValue::U64(user_id);
+        let value = telegram_channel(chat);
+        {
+            let storage = self.wallet.get_storage().read().await;
+            let known = storage.get_custom_data(TELEGRAM_CHATS_TREE, &key)
+                .ok()
+                .and_then(|v| v.to_value().ok())
+                .and_then(|v| v.to_u64().ok());
+
+            if known == Some(value) {
+                return Ok(false);
+            }
+        }
+
+        let mut storage = self.wallet.get_storage().write().await;
+        let registered = storage.has_custom_data(TELEGRAM_CHATS_TREE, &key)?;
+        storage.set_custom_data(TELEGRAM_CHATS_TREE, &key, &value.into())?;
+
+        Ok(!registered)
+    }
+
+    // A Telegram user who never wrote to the bot in private can't receive its DMs
+    pub(super) fn is_telegram_registered(&self, storage: &EncryptedStorage, user_id: u64) -> bool {
+        storage.has_custom_data(TELEGRAM_CHATS_TREE, &DataValue::U64(user_id)).unwrap_or(false)
+    }
+
+    // DM chat of a Telegram user, the one recorded by /start if any
+    // Otherwise Telegram uses the user ID as the ID of its private chat
+    pub async fn get_telegram_dm_chat(&self, user_id: u64) -> Result<ChatId, IdError> {
+        let storage = self.wallet.get_storage().read().await;
+        let known = storage.get_custom_data(TELEGRAM_CHATS_TREE, &DataValue::U64(user_id))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_u64().ok());
+
+        match known {
+            Some(channel) => Ok(telegram_chat(channel)),
+            None => telegram_user_chat(user_id)
+        }
+    }
+}
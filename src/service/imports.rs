@@ -0,0 +1,59 @@
+use log::warn;
+use xelis_common::{
+    api::DataValue,
+    crypto::Hash,
+    serializer::Serializer,
+    time::get_current_time_in_seconds,
+    utils::format_xelis
+};
+
+use super::{journal, MovementKind, ServiceError, UserApplication, WalletServiceImpl};
+
+// Files of balances already imported, by checksum
+// Holds the timestamp in seconds of the import
+pub(super) const IMPORTS_TREE: &str = "imports";
+
+impl WalletServiceImpl {
+    // Check if a file of balances with this checksum was already imported
+    pub async fn is_imported(&self, checksum: &Hash) -> bool {
+        let storage = self.wallet.get_storage().read().await;
+        storage.has_custom_data(IMPORTS_TREE, &DataValue::Blob(checksum.to_bytes())).unwrap_or(false)
+    }
+
+    // Credit the balances migrated from another tip bot, all at once or none
+    // The checksum of the file is remembered so the same file can't be imported twice
+    pub async fn import_balances(&self, checksum: &Hash, balances: &[(UserApplication, u64)], note: &str) -> Result<(), ServiceError> {
+        let note = journal::check_note(note)?;
+        let key = DataValue::Blob(checksum.to_bytes());
+
+        let mut storage = self.wallet.get_storage().write().await;
+        if storage.has_custom_data(IMPORTS_TREE, &key)? {
+            return Err(ServiceError::AlreadyImported);
+        }
+
+        // Check every balance before crediting the first one
+        let mut credits = Vec::with_capacity(balances.len());
+        let mut total = 0u64;
+        for (user, amount) in balances {
+            if *amount == 0 {
+                return Err(ServiceError::Zero);
+            }
+
+            let balance = self.get_balance_internal(&storage, user);
+            let new_balance = balance.checked_add(*amount).ok_or(ServiceError::Overflow)?;
+            total = total.checked_add(*amount).ok_or(ServiceError::Overflow)?;
+            credits.push((user, new_balance));
+        }
+
+        warn!("Importing {} XEL to {} users: {}", format_xelis(total), balances.len(), note);
+        for (user, new_balance) in credits {
+            let id = self.get_next_movement_id(&storage);
+            self.set_balance_internal(&mut storage, user, new_balance, MovementKind::Admin)?;
+            self.record_movement_note(&mut storage, id, note)?;
+        }
+
+        storage.set_custom_data(IMPORTS_TREE, &key, &get_current_time_in_seconds().into())?;
+
+        Ok(())
+    }
+}
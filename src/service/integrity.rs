@@ -0,0 +1,312 @@
+use anyhow::{anyhow, Result};
+use log::warn;
+use xelis_common::{
+    api::{DataElement, DataValue},
+    crypto::Hash,
+    serializer::{Reader, ReaderError, Serializer, Writer}
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{
+    acl::EXEMPTIONS_TREE,
+    address_book::{AddressBookKey, AddressEntry, ADDRESS_BOOK_TREE},
+    audit::{AuditRecord, AUDIT_TREE},
+    batch::{BalanceBatch, PENDING_BATCH_TREE},
+    budget::TIP_BUDGETS_TREE,
+    command_rules::{CommandRuleKey, COMMAND_RULES_TREE},
+    confirmations::{SentWithdrawal, SENT_WITHDRAWALS_TREE},
+    deposits::{TransferKey, RETURNED_DEPOSITS_TREE, RETURNED_TRANSFERS_TREE},
+    faucet::FAUCET_TREE,
+    forget::FORGOTTEN_USERS_TREE,
+    freeze::FROZEN_USERS_TREE,
+    games::{GAMES_HISTORY_TREE, GAMES_TREE},
+    goals::GOALS_TREE,
+    ids::TELEGRAM_CHATS_TREE,
+    imports::IMPORTS_TREE,
+    journal::{JournalKey, JOURNAL_NOTES_TREE, JOURNAL_SEQUENCE_TREE, JOURNAL_TREE},
+    ledger::{LedgerEntry, LEDGER_TREE},
+    lottery::LOTTERY_TREE,
+    matrix::MATRIX_USERS_TREE,
+    membership::{MembershipKey, FIRST_SEEN_TREE},
+    operations::PROCESSED_OPERATIONS_TREE,
+    outbox::{PendingNotification, OUTBOX_TREE},
+    pin::{PinEntry, WITHDRAW_PINS_TREE},
+    practice::PRACTICE_TREE,
+    reminders::{LAST_SEEN_TREE, REMINDERS_OPT_OUT_TREE, REMINDERS_TREE},
+    reorg::{OrphanedDeposit, ORPHANED_DEPOSITS_TREE},
+    reserves::{ReserveProof, RESERVE_PROOFS_TREE},
+    scheduler::{ScheduledReminder, SCHEDULED_REMINDERS_TREE},
+    settings::{SettingKey, SETTINGS_TREE},
+    slack::{SlackUser, SlackWorkspace, SLACK_USERS_TREE, SLACK_WORKSPACES_TREE},
+    stats::{StatsKey, STATS_TREE},
+    unconfirmed::UNCONFIRMED_TREE,
+    unknown_deposits::{UnknownDeposit, UNKNOWN_DEPOSITS_TREE},
+    unsent::{UnsentTransaction, FAILED_WITHDRAWALS_TREE, UNSENT_TRANSACTIONS_TREE},
+    usernames::TELEGRAM_USERNAMES_TREE,
+    whitelist::{WhitelistEntry, WITHDRAW_WHITELIST_TREE},
+    withdrawals::{RecentWithdrawals, WITHDRAWALS_TREE},
+    BalanceKey,
+    Challenge,
+    ChallengeResult,
+    CommandRule,
+    Community,
+    Exemption,
+    FailedWithdrawal,
+    Giveaway,
+    Goal,
+    LotteryDraw,
+    LotteryRound,
+    Movement,
+    TipBudget,
+    TipStats,
+    UserApplication,
+    WalletServiceImpl,
+    BALANCES_TREE,
+    GIVEAWAYS_TREE,
+    HISTORY_TREE,
+    INTERNAL_BALANCES_TREE
+};
+
+// Corrupt entries moved out of their tree, by tree and original key
+// The original value is kept so it can be inspected or restored by hand
+const QUARANTINE_TREE: &str = "quarantine";
+
+// Check of a key or a value of a tree
+type Check = fn(&DataValue) -> Result<()>;
+
+// Expected key and value of the entries of a tree
+struct TreeCheck {
+    tree: &'static str,
+    key: Check,
+    value: Check
+}
+
+fn blob<T: Serializer>(value: &DataValue) -> Result<()> {
+    value.as_type::<T>()?;
+    Ok(())
+}
+
+fn number(value: &DataValue) -> Result<()> {
+    match value {
+        DataValue::U64(_) => Ok(()),
+        _ => Err(anyhow!("expected a number"))
+    }
+}
+
+fn flag(value: &DataValue) -> Result<()> {
+    match value {
+        DataValue::Bool(_) => Ok(()),
+        _ => Err(anyhow!("expected a boolean"))
+    }
+}
+
+fn text(value: &DataValue) -> Result<()> {
+    match value {
+        DataValue::String(_) => Ok(()),
+        _ => Err(anyhow!("expected a string"))
+    }
+}
+
+fn hash(value: &DataValue) -> Result<()> {
+    value.as_hash()?;
+    Ok(())
+}
+
+// Settings hold values of different types
+fn any(_: &DataValue) -> Result<()> {
+    Ok(())
+}
+
+// The lottery tree holds the running round and the last draw
+fn lottery(value: &DataValue) -> Result<()> {
+    blob::<LotteryRound>(value).or_else(|_| blob::<LotteryDraw>(value))
+}
+
+// Every tree written by the bot, with the type of its keys and values
+const TREE_CHECKS: [TreeCheck; 49] = [
+    TreeCheck { tree: BALANCES_TREE, key: blob::<BalanceKey>, value: number },
+    TreeCheck { tree: HISTORY_TREE, key: hash, value: blob::<UserApplication> },
+    TreeCheck { tree: GIVEAWAYS_TREE, key: number, value: blob::<Giveaway> },
+    TreeCheck { tree: INTERNAL_BALANCES_TREE, key: text, value: number },
+    TreeCheck { tree: JOURNAL_TREE, key: blob::<JournalKey>, value: blob::<Movement> },
+    TreeCheck { tree: JOURNAL_SEQUENCE_TREE, key: text, value: number },
+    TreeCheck { tree: JOURNAL_NOTES_TREE, key: number, value: text },
+    TreeCheck { tree: AUDIT_TREE, key: number, value: blob::<AuditRecord> },
+    TreeCheck { tree: LEDGER_TREE, key: number, value: blob::<LedgerEntry> },
+    TreeCheck { tree: PENDING_BATCH_TREE, key: text, value: blob::<BalanceBatch> },
+    TreeCheck { tree: UNCONFIRMED_TREE, key: blob::<Hash>, value: number },
+    TreeCheck { tree: UNSENT_TRANSACTIONS_TREE, key: blob::<Hash>, value: blob::<UnsentTransaction> },
+    TreeCheck { tree: SENT_WITHDRAWALS_TREE, key: blob::<Hash>, value: blob::<SentWithdrawal> },
+    TreeCheck { tree: FAILED_WITHDRAWALS_TREE, key: blob::<Hash>, value: blob::<FailedWithdrawal> },
+    TreeCheck { tree: RETURNED_DEPOSITS_TREE, key: hash, value: flag },
+    TreeCheck { tree: RETURNED_TRANSFERS_TREE, key: blob::<TransferKey>, value: flag },
+    TreeCheck { tree: UNKNOWN_DEPOSITS_TREE, key: hash, value: blob::<UnknownDeposit> },
+    TreeCheck { tree: ORPHANED_DEPOSITS_TREE, key: hash, value: blob::<OrphanedDeposit> },
+    TreeCheck { tree: FORGOTTEN_USERS_TREE, key: blob::<Hash>, value: number },
+    TreeCheck { tree: IMPORTS_TREE, key: blob::<Hash>, value: number },
+    TreeCheck { tree: PROCESSED_OPERATIONS_TREE, key: blob::<Hash>, value: number },
+    TreeCheck { tree: WITHDRAWALS_TREE, key: blob::<UserApplication>, value: blob::<RecentWithdrawals> },
+    TreeCheck { tree: WITHDRAW_PINS_TREE, key: blob::<UserApplication>, value: blob::<PinEntry> },
+    TreeCheck { tree: WITHDRAW_WHITELIST_TREE, key: blob::<UserApplication>, value: blob::<WhitelistEntry> },
+    TreeCheck { tree: ADDRESS_BOOK_TREE, key: blob::<AddressBookKey>, value: blob::<AddressEntry> },
+    TreeCheck { tree: FROZEN_USERS_TREE, key: blob::<UserApplication>, value: flag },
+    TreeCheck { tree: EXEMPTIONS_TREE, key: blob::<Exemption>, value: flag },
+    TreeCheck { tree: TIP_BUDGETS_TREE, key: blob::<UserApplication>, value: blob::<TipBudget> },
+    TreeCheck { tree: STATS_TREE, key: blob::<StatsKey>, value: blob::<TipStats> },
+    TreeCheck { tree: GOALS_TREE, key: blob::<Community>, value: blob::<Goal> },
+    TreeCheck { tree: GAMES_TREE, key: number, value: blob::<Challenge> },
+    TreeCheck { tree: GAMES_HISTORY_TREE, key: number, value: blob::<ChallengeResult> },
+    TreeCheck { tree: LOTTERY_TREE, key: text, value: lottery },
+    TreeCheck { tree: FAUCET_TREE, key: blob::<UserApplication>, value: number },
+    TreeCheck { tree: PRACTICE_TREE, key: blob::<UserApplication>, value: number },
+    TreeCheck { tree: LAST_SEEN_TREE, key: blob::<UserApplication>, value: number },
+    TreeCheck { tree: REMINDERS_TREE, key: blob::<UserApplication>, value: number },
+    TreeCheck { tree: REMINDERS_OPT_OUT_TREE, key: blob::<UserApplication>, value: flag },
+    TreeCheck { tree: SCHEDULED_REMINDERS_TREE, key: number, value: blob::<ScheduledReminder> },
+    TreeCheck { tree: FIRST_SEEN_TREE, key: blob::<MembershipKey>, value: number },
+    TreeCheck { tree: SETTINGS_TREE, key: blob::<SettingKey>, value: any },
+    TreeCheck { tree: COMMAND_RULES_TREE, key: blob::<CommandRuleKey>, value: blob::<CommandRule> },
+    TreeCheck { tree: RESERVE_PROOFS_TREE, key: number, value: blob::<ReserveProof> },
+    TreeCheck { tree: OUTBOX_TREE, key: number, value: blob::<PendingNotification> },
+    TreeCheck { tree: TELEGRAM_USERNAMES_TREE, key: text, value: number },
+    TreeCheck { tree: TELEGRAM_CHATS_TREE, key: number, value: number },
+    TreeCheck { tree: MATRIX_USERS_TREE, key: number, value: text },
+    TreeCheck { tree: SLACK_WORKSPACES_TREE, key: number, value: blob::<SlackWorkspace> },
+    TreeCheck { tree: SLACK_USERS_TREE, key: number, value: blob::<SlackUser> }
+];
+
+// Storage key of a quarantined entry
+struct QuarantineKey {
+    tree: String,
+    key: DataValue
+}
+
+impl Serializer for QuarantineKey {
+    fn write(&self, writer: &mut Writer) {
+        self.tree.write(writer);
+        self.key.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let tree = String::read(reader)?;
+        let key = DataValue::read(reader)?;
+
+        Ok(Self {
+            tree,
+            key
+        })
+    }
+}
+
+// An entry that doesn't decode to the type expected in its tree
+#[derive(Debug, Clone)]
+pub struct CorruptEntry {
+    pub tree: &'static str,
+    // None if the keys of the tree can't be listed at all
+    pub key: Option<DataValue>,
+    pub reason: String,
+    // The value as stored, None if it can't be read
+    value: Option<DataElement>
+}
+
+impl CorruptEntry {
+    // Only entries read from the storage can be moved aside
+    pub fn can_be_quarantined(&self) -> bool {
+        self.key.is_some() && self.value.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StorageReport {
+    // Entries checked across all the trees
+    pub entries: usize,
+    pub corrupt: Vec<CorruptEntry>,
+    // Entries moved to the quarantine by previous checks
+    pub quarantined: usize
+}
+
+// Check a single entry, returning why it's corrupt along with its value if it can be read
+fn check_entry(storage: &EncryptedStorage, check: &TreeCheck, key: &DataValue) -> Option<(String, Option<DataElement>)> {
+    let element = storage.get_custom_data(check.tree, key);
+    if let Err(e) = (check.key)(key) {
+        return Some((format!("invalid key: {}", e), element.ok()));
+    }
+
+    let element = match element {
+        Ok(element) => element,
+        Err(e) => return Some((format!("unreadable value: {}", e), None))
+    };
+
+    let result = element.as_value()
+        .map_err(anyhow::Error::from)
+        .and_then(check.value);
+
+    result.err().map(|e| (format!("invalid value: {}", e), Some(element)))
+}
+
+impl WalletServiceImpl {
+    // Decode every entry of the bot trees with its expected type
+    fn verify_storage_internal(&self, storage: &EncryptedStorage) -> StorageReport {
+        let mut report = StorageReport::default();
+        for check in TREE_CHECKS.iter() {
+            let keys = match storage.get_custom_tree_keys(&check.tree.to_string(), &None, None, None) {
+                Ok(keys) => keys,
+                Err(e) => {
+                    report.corrupt.push(CorruptEntry {
+                        tree: check.tree,
+                        key: None,
+                        reason: format!("keys can't be listed: {}", e),
+                        value: None
+                    });
+                    continue;
+                }
+            };
+
+            for key in keys {
+                report.entries += 1;
+                if let Some((reason, value)) = check_entry(storage, check, &key) {
+                    report.corrupt.push(CorruptEntry {
+                        tree: check.tree,
+                        key: Some(key),
+                        reason,
+                        value
+                    });
+                }
+            }
+        }
+
+        report.quarantined = storage.get_custom_tree_keys(&QUARANTINE_TREE.to_string(), &None, None, None)
+            .map(|keys| keys.len())
+            .unwrap_or(0);
+
+        report
+    }
+
+    // Check that every entry of the bot trees can still be decoded
+    pub async fn verify_storage(&self) -> StorageReport {
+        let storage = self.wallet.get_storage().read().await;
+        self.verify_storage_internal(&storage)
+    }
+
+    // Move the corrupt entries to the quarantine so they can't be mistaken for valid data
+    // The storage is checked again under the write lock, returns the entries moved
+    pub async fn quarantine_corrupt_entries(&self) -> Result<Vec<CorruptEntry>> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let report = self.verify_storage_internal(&storage);
+
+        let mut quarantined = Vec::new();
+        for entry in report.corrupt {
+            let (Some(key), Some(value)) = (&entry.key, &entry.value) else {
+                continue;
+            };
+
+            warn!("Quarantining entry {:?} of tree {}: {}", key, entry.tree, entry.reason);
+            let quarantine_key = QuarantineKey { tree: entry.tree.to_string(), key: key.clone() };
+            storage.set_custom_data(QUARANTINE_TREE, &DataValue::Blob(quarantine_key.to_bytes()), value)?;
+            storage.delete_custom_data(entry.tree, key)?;
+            quarantined.push(entry);
+        }
+
+        Ok(quarantined)
+    }
+}
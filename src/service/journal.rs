@@ -0,0 +1,409 @@
+use anyhow::Result;
+use xelis_common::{
+    api::{DataElement, DataValue},
+    config::XELIS_ASSET,
+    crypto::Hash,
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{AssetBalance, ServiceError, UserApplication, WalletServiceImpl};
+
+// Every change of a user balance, used to build the statements
+// Entries of a forgotten user are deleted, the audit log keeps a trace of them
+pub(super) const JOURNAL_TREE: &str = "journal";
+// Id of the next journal entry
+pub(super) const JOURNAL_SEQUENCE_TREE: &str = "journal_sequence";
+const JOURNAL_SEQUENCE_KEY: &str = "next_id";
+// Id of the next journal entry to send to the exporter
+const JOURNAL_EXPORT_KEY: &str = "next_export";
+// Reason given by the operator for each manual balance change, by id of its journal entry
+pub(super) const JOURNAL_NOTES_TREE: &str = "journal_notes";
+
+// Characters of the reason of a manual balance change
+pub const MAX_NOTE_LENGTH: usize = 200;
+
+// Reason of a balance change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovementKind {
+    Deposit,
+    Withdraw,
+    Tip,
+    Rain,
+    Giveaway,
+    Game,
+    Lottery,
+    Faucet,
+    // Balance changed by the operator from the CLI
+    Admin,
+    // Deposit sent back to its sender by the operator
+    Refund,
+    // Reward of a community goal, escrowed until the goal ends
+    Goal,
+    // Deposit orphaned by a reorg after it was credited
+    Reorg
+}
+
+impl MovementKind {
+    pub(super) fn id(&self) -> u8 {
+        match self {
+            MovementKind::Deposit => 0,
+            MovementKind::Withdraw => 1,
+            MovementKind::Tip => 2,
+            MovementKind::Rain => 3,
+            MovementKind::Giveaway => 4,
+            MovementKind::Game => 5,
+            MovementKind::Lottery => 6,
+            MovementKind::Faucet => 7,
+            MovementKind::Admin => 8,
+            MovementKind::Refund => 9,
+            MovementKind::Goal => 10,
+            MovementKind::Reorg => 11
+        }
+    }
+
+    pub(super) fn from_id(id: u8) -> Option<Self> {
+        Some(match id {
+            0 => MovementKind::Deposit,
+            1 => MovementKind::Withdraw,
+            2 => MovementKind::Tip,
+            3 => MovementKind::Rain,
+            4 => MovementKind::Giveaway,
+            5 => MovementKind::Game,
+            6 => MovementKind::Lottery,
+            7 => MovementKind::Faucet,
+            8 => MovementKind::Admin,
+            9 => MovementKind::Refund,
+            10 => MovementKind::Goal,
+            11 => MovementKind::Reorg,
+            _ => return None
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MovementKind::Deposit => "Deposit",
+            MovementKind::Withdraw => "Withdraw",
+            MovementKind::Tip => "Tip",
+            MovementKind::Rain => "Rain",
+            MovementKind::Giveaway => "Giveaway",
+            MovementKind::Game => "Game",
+            MovementKind::Lottery => "Lottery",
+            MovementKind::Faucet => "Faucet",
+            MovementKind::Admin => "Admin",
+            MovementKind::Refund => "Refund",
+            MovementKind::Goal => "Goal",
+            MovementKind::Reorg => "Reorg"
+        }
+    }
+}
+
+// The reason of a manual change can't be empty
+pub(super) fn check_note(note: &str) -> Result<&str, ServiceError> {
+    let note = note.trim();
+    if note.is_empty() || note.chars().count() > MAX_NOTE_LENGTH {
+        return Err(ServiceError::InvalidNote(MAX_NOTE_LENGTH));
+    }
+
+    Ok(note)
+}
+
+// Storage key of a journal entry
+pub(super) struct JournalKey {
+    user: UserApplication,
+    id: u64
+}
+
+impl Serializer for JournalKey {
+    fn write(&self, writer: &mut Writer) {
+        self.user.write(writer);
+        self.id.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let user = UserApplication::read(reader)?;
+        let id = reader.read_u64()?;
+
+        Ok(Self {
+            user,
+            id
+        })
+    }
+}
+
+// A single change of a user balance
+// Both balances are kept so the journal can be verified entry by entry
+#[derive(Debug, Clone)]
+pub struct Movement {
+    // Increasing across all the users
+    pub id: u64,
+    // Timestamp in seconds
+    pub timestamp: u64,
+    pub asset: Hash,
+    pub kind: MovementKind,
+    pub before: u64,
+    pub after: u64,
+    // Reason of a manual change, stored apart from the entry
+    pub note: Option<String>
+}
+
+impl Movement {
+    pub fn is_credit(&self) -> bool {
+        self.after >= self.before
+    }
+
+    pub fn amount(&self) -> u64 {
+        self.after.abs_diff(self.before)
+    }
+}
+
+impl Serializer for Movement {
+    fn write(&self, writer: &mut Writer) {
+        self.id.write(writer);
+        self.timestamp.write(writer);
+        self.asset.write(writer);
+        writer.write_u8(self.kind.id());
+        self.before.write(writer);
+        self.after.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let id = reader.read_u64()?;
+        let timestamp = reader.read_u64()?;
+        let asset = Hash::read(reader)?;
+        let kind = MovementKind::from_id(reader.read_u8()?).ok_or(ReaderError::InvalidValue)?;
+        let before = reader.read_u64()?;
+        let after = reader.read_u64()?;
+
+        Ok(Self {
+            id,
+            timestamp,
+            asset,
+            kind,
+            before,
+            after,
+            note: None
+        })
+    }
+}
+
+// XEL movements of a user over a time range
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub opening: u64,
+    pub closing: u64,
+    pub movements: Vec<Movement>,
+    // The balances of the journal follow each other and match the current balance
+    pub verified: bool
+}
+
+impl Statement {
+    pub fn total_credits(&self) -> u64 {
+        self.movements.iter().filter(|m| m.is_credit()).map(Movement::amount).sum()
+    }
+
+    pub fn total_debits(&self) -> u64 {
+        self.movements.iter().filter(|m| !m.is_credit()).map(Movement::amount).sum()
+    }
+}
+
+impl WalletServiceImpl {
+    // Append a balance change of a user to the journal
+    pub(super) fn record_movement(&self, storage: &mut EncryptedStorage, user: &UserApplication, asset: &Hash, kind: MovementKind, before: u64, after: u64) -> Result<()> {
+        if before == after {
+            return Ok(());
+        }
+
+        let sequence_key = DataValue::String(JOURNAL_SEQUENCE_KEY.to_string());
+        let id = self.get_sequence_internal(storage, JOURNAL_SEQUENCE_KEY);
+
+        let movement = Movement {
+            id,
+            timestamp: get_current_time_in_seconds(),
+            asset: asset.clone(),
+            kind,
+            before,
+            after,
+            note: None
+        };
+
+        let key = JournalKey { user: *user, id };
+        storage.set_custom_data(JOURNAL_TREE, &DataValue::Blob(key.to_bytes()), &DataElement::Value(DataValue::Blob(movement.to_bytes())))?;
+        storage.set_custom_data(JOURNAL_SEQUENCE_TREE, &sequence_key, &(id + 1).into())?;
+
+        self.append_audit_record(storage, user, asset, kind, before, after, id)
+    }
+
+    // Id the next journal entry will get
+    pub(super) fn get_next_movement_id(&self, storage: &EncryptedStorage) -> u64 {
+        self.get_sequence_internal(storage, JOURNAL_SEQUENCE_KEY)
+    }
+
+    // Attach the reason of a manual change to its journal entry
+    pub(super) fn record_movement_note(&self, storage: &mut EncryptedStorage, id: u64, note: &str) -> Result<()> {
+        storage.set_custom_data(JOURNAL_NOTES_TREE, &id.into(), &DataElement::Value(DataValue::String(note.to_string())))?;
+        Ok(())
+    }
+
+    // Read a journal entry with its note, only manual changes have one
+    fn load_movement(&self, storage: &EncryptedStorage, key: &DataValue) -> Result<Movement> {
+        let mut movement: Movement = storage.get_custom_data(JOURNAL_TREE, key)?.to_value()?.to_type()?;
+        if movement.kind == MovementKind::Admin {
+            movement.note = storage.get_custom_data(JOURNAL_NOTES_TREE, &movement.id.into())
+                .ok()
+                .and_then(|v| v.to_value().ok())
+                .and_then(|v| match v {
+                    DataValue::String(note) => Some(note),
+                    _ => None
+                });
+        }
+
+        Ok(movement)
+    }
+
+    // Remove every journal entry of a user, the ids of the others are kept as is
+    pub(super) fn delete_movements_internal(&self, storage: &mut EncryptedStorage, user: &UserApplication) -> Result<()> {
+        let mut keys = Vec::new();
+        for key in storage.get_custom_tree_keys(&JOURNAL_TREE.to_string(), &None, None, None)? {
+            let journal_key: JournalKey = key.as_type()?;
+            if journal_key.user == *user {
+                keys.push(key);
+            }
+        }
+
+        for key in keys {
+            storage.delete_custom_data(JOURNAL_TREE, &key)?;
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn get_sequence_internal(&self, storage: &EncryptedStorage, key: &str) -> u64 {
+        storage.get_custom_data(JOURNAL_SEQUENCE_TREE, &DataValue::String(key.to_string()))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_u64().ok())
+            .unwrap_or(0)
+    }
+
+    // Get the journal entries not exported yet, in order, up to a limit
+    // The journal is only scanned when new entries exist
+    pub async fn get_unexported_movements(&self, limit: usize) -> Result<Vec<(UserApplication, Movement)>> {
+        let storage = self.wallet.get_storage().read().await;
+        let cursor = self.get_sequence_internal(&storage, JOURNAL_EXPORT_KEY);
+        if cursor >= self.get_sequence_internal(&storage, JOURNAL_SEQUENCE_KEY) {
+            return Ok(Vec::new());
+        }
+
+        let mut movements = Vec::new();
+        for key in storage.get_custom_tree_keys(&JOURNAL_TREE.to_string(), &None, None, None)? {
+            let journal_key: JournalKey = key.as_type()?;
+            if journal_key.id < cursor {
+                continue;
+            }
+
+            let movement = self.load_movement(&storage, &key)?;
+            movements.push((journal_key.user, movement));
+        }
+        movements.sort_by_key(|(_, movement)| movement.id);
+        movements.truncate(limit);
+
+        Ok(movements)
+    }
+
+    // Remember that every journal entry up to this id was exported
+    pub async fn set_movements_exported(&self, id: u64) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        storage.set_custom_data(JOURNAL_SEQUENCE_TREE, &DataValue::String(JOURNAL_EXPORT_KEY.to_string()), &(id + 1).into())?;
+
+        Ok(())
+    }
+
+    // Get the journal entries from an id, of a single user or of all of them, in order, up to a limit
+    pub async fn get_movements(&self, user: Option<&UserApplication>, from: u64, limit: usize) -> Result<Vec<(UserApplication, Movement)>> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut movements = Vec::new();
+        for key in storage.get_custom_tree_keys(&JOURNAL_TREE.to_string(), &None, None, None)? {
+            let journal_key: JournalKey = key.as_type()?;
+            if journal_key.id < from || user.is_some_and(|user| journal_key.user != *user) {
+                continue;
+            }
+
+            let movement = self.load_movement(&storage, &key)?;
+            movements.push((journal_key.user, movement));
+        }
+        movements.sort_by_key(|(_, movement)| movement.id);
+        movements.truncate(limit);
+
+        Ok(movements)
+    }
+
+    // Get the latest journal entries of a user in every asset, most recent first
+    // Each one comes with its asset, holding the balance left by the entry
+    pub async fn get_recent_movements(&self, user: &UserApplication, limit: usize) -> Result<Vec<(Movement, AssetBalance)>> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut movements = Vec::new();
+        for key in storage.get_custom_tree_keys(&JOURNAL_TREE.to_string(), &None, None, None)? {
+            let journal_key: JournalKey = key.as_type()?;
+            if journal_key.user == *user {
+                movements.push(self.load_movement(&storage, &key)?);
+            }
+        }
+        movements.sort_by_key(|movement| std::cmp::Reverse(movement.id));
+        movements.truncate(limit);
+
+        let mut recent = Vec::with_capacity(movements.len());
+        for movement in movements {
+            let balance = self.get_asset_balance_display(&storage, &movement.asset, movement.after).await;
+            recent.push((movement, balance));
+        }
+
+        Ok(recent)
+    }
+
+    // Build the XEL statement of a user between two timestamps in seconds, both included
+    pub async fn get_statement(&self, user: &UserApplication, from: u64, to: u64) -> Result<Statement> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut movements = Vec::new();
+        for key in storage.get_custom_tree_keys(&JOURNAL_TREE.to_string(), &None, None, None)? {
+            let journal_key: JournalKey = key.as_type()?;
+            if journal_key.user != *user {
+                continue;
+            }
+
+            let movement = self.load_movement(&storage, &key)?;
+            if movement.asset == XELIS_ASSET {
+                movements.push(movement);
+            }
+        }
+        movements.sort_by_key(|movement| movement.id);
+
+        let balance = self.get_balance_internal(&storage, user);
+        // Each entry must start from the balance left by the previous one,
+        // and the last one must end on the current balance
+        let verified = movements.windows(2).all(|pair| pair[0].after == pair[1].before)
+            && movements.last().is_none_or(|movement| movement.after == balance);
+
+        // Balances from before the journal existed appear as the first balance of the journal
+        let opening = movements.iter()
+            .rev()
+            .find(|movement| movement.timestamp < from)
+            .map(|movement| movement.after)
+            .or_else(|| movements.first().map(|movement| movement.before))
+            .unwrap_or(balance);
+
+        let movements: Vec<Movement> = movements.into_iter()
+            .filter(|movement| movement.timestamp >= from && movement.timestamp <= to)
+            .collect();
+
+        let closing = movements.last().map_or(opening, |movement| movement.after);
+
+        Ok(Statement {
+            opening,
+            closing,
+            movements,
+            verified
+        })
+    }
+}
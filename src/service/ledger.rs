@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use log::{info, warn};
+use xelis_common::{
+    api::{DataElement, DataValue},
+    config::XELIS_ASSET,
+    crypto::Hash,
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds,
+    utils::format_xelis
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{
+    journal::JOURNAL_SEQUENCE_TREE,
+    BalanceKey,
+    MovementKind,
+    UserApplication,
+    WalletServiceImpl,
+    BALANCES_TREE
+};
+
+// Double-entry ledger, every balance change moves an amount from an account to another, by id
+// The balances stored for the users and the internal accounts must match the ones derived from it
+pub(super) const LEDGER_TREE: &str = "ledger";
+// Key of the next entry id in the journal sequences
+const LEDGER_SEQUENCE_KEY: &str = "next_ledger";
+// Key of the topoheight of the wallet when the ledger was opened, in the journal sequences
+// Deposits confirmed before it are part of the opening balances
+const LEDGER_OPENED_KEY: &str = "ledger_opened_at";
+
+// An account of the ledger, the sum of all the accounts is always zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LedgerAccount {
+    User(UserApplication),
+    // XEL entering or leaving the bot on-chain, deposits come from it and withdrawals go to it
+    Wallet,
+    // Network fees of the withdrawals
+    Fees,
+    // Stakes of the running giveaways, goals and challenges
+    Escrow,
+    Faucet,
+    // Pot of the running lottery round
+    Lottery,
+    // Tips go through it, it's back to zero once both sides are written
+    Transfers,
+    // Balances credited or debited by the operator
+    Adjustments,
+    // Balances that existed before the ledger
+    Opening,
+    // Users that asked to be forgotten, their accounts are zeroed first
+    Forgotten
+}
+
+impl LedgerAccount {
+    // Accounts that are not held by the bot but are where the XEL came from or went to
+    pub fn is_external(&self) -> bool {
+        matches!(self, LedgerAccount::Wallet | LedgerAccount::Fees | LedgerAccount::Adjustments | LedgerAccount::Opening)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LedgerAccount::User(_) => "Users",
+            LedgerAccount::Wallet => "Wallet",
+            LedgerAccount::Fees => "Fees",
+            LedgerAccount::Escrow => "Escrow",
+            LedgerAccount::Faucet => "Faucet",
+            LedgerAccount::Lottery => "Lottery",
+            LedgerAccount::Transfers => "Transfers",
+            LedgerAccount::Adjustments => "Adjustments",
+            LedgerAccount::Opening => "Opening",
+            LedgerAccount::Forgotten => "Forgotten"
+        }
+    }
+}
+
+impl Serializer for LedgerAccount {
+    fn write(&self, writer: &mut Writer) {
+        match self {
+            LedgerAccount::User(user) => {
+                writer.write_u8(0);
+                user.write(writer);
+            },
+            LedgerAccount::Wallet => writer.write_u8(1),
+            LedgerAccount::Fees => writer.write_u8(2),
+            LedgerAccount::Escrow => writer.write_u8(3),
+            LedgerAccount::Faucet => writer.write_u8(4),
+            LedgerAccount::Lottery => writer.write_u8(5),
+            LedgerAccount::Transfers => writer.write_u8(6),
+            LedgerAccount::Adjustments => writer.write_u8(7),
+            LedgerAccount::Opening => writer.write_u8(8),
+            LedgerAccount::Forgotten => writer.write_u8(9)
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let account = match reader.read_u8()? {
+            0 => LedgerAccount::User(UserApplication::read(reader)?),
+            1 => LedgerAccount::Wallet,
+            2 => LedgerAccount::Fees,
+            3 => LedgerAccount::Escrow,
+            4 => LedgerAccount::Faucet,
+            5 => LedgerAccount::Lottery,
+            6 => LedgerAccount::Transfers,
+            7 => LedgerAccount::Adjustments,
+            8 => LedgerAccount::Opening,
+            9 => LedgerAccount::Forgotten,
+            _ => return Err(ReaderError::InvalidValue)
+        };
+
+        Ok(account)
+    }
+}
+
+// Account on the other side of a user balance change
+fn counterpart(kind: MovementKind) -> LedgerAccount {
+    match kind {
+        MovementKind::Deposit | MovementKind::Withdraw | MovementKind::Refund | MovementKind::Reorg => LedgerAccount::Wallet,
+        MovementKind::Tip | MovementKind::Rain => LedgerAccount::Transfers,
+        MovementKind::Giveaway | MovementKind::Game | MovementKind::Goal => LedgerAccount::Escrow,
+        MovementKind::Lottery => LedgerAccount::Lottery,
+        MovementKind::Faucet => LedgerAccount::Faucet,
+        MovementKind::Admin => LedgerAccount::Adjustments
+    }
+}
+
+// An amount moved from an account to another
+#[derive(Debug, Clone)]
+pub struct LedgerEntry {
+    pub id: u64,
+    // Timestamp in seconds
+    pub timestamp: u64,
+    pub asset: Hash,
+    pub kind: MovementKind,
+    pub from: LedgerAccount,
+    pub to: LedgerAccount,
+    pub amount: u64
+}
+
+impl Serializer for LedgerEntry {
+    fn write(&self, writer: &mut Writer) {
+        self.id.write(writer);
+        self.timestamp.write(writer);
+        self.asset.write(writer);
+        writer.write_u8(self.kind.id());
+        self.from.write(writer);
+        self.to.write(writer);
+        self.amount.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let id = reader.read_u64()?;
+        let timestamp = reader.read_u64()?;
+        let asset = Hash::read(reader)?;
+        let kind = MovementKind::from_id(reader.read_u8()?).ok_or(ReaderError::InvalidValue)?;
+        let from = LedgerAccount::read(reader)?;
+        let to = LedgerAccount::read(reader)?;
+        let amount = reader.read_u64()?;
+
+        Ok(Self {
+            id,
+            timestamp,
+            asset,
+            kind,
+            from,
+            to,
+            amount
+        })
+    }
+}
+
+// Balance of every account in every asset, summed from the entries
+pub(super) fn derive_balances(entries: &[LedgerEntry]) -> HashMap<(LedgerAccount, Hash), i128> {
+    let mut derived = HashMap::new();
+    for entry in entries {
+        *derived.entry((entry.from, entry.asset.clone())).or_insert(0) -= entry.amount as i128;
+        *derived.entry((entry.to, entry.asset.clone())).or_insert(0) += entry.amount as i128;
+    }
+
+    derived
+}
+
+// A stored balance that doesn't match the one derived from the ledger
+#[derive(Debug, Clone)]
+pub struct LedgerDiscrepancy {
+    pub account: LedgerAccount,
+    pub asset: Hash,
+    pub derived: i128,
+    pub stored: u64
+}
+
+// Balances derived from the whole ledger
+#[derive(Debug, Clone)]
+pub struct LedgerReport {
+    pub entries: usize,
+    // XEL of every account other than the users, whose total is apart
+    pub accounts: Vec<(LedgerAccount, i128)>,
+    pub users: i128,
+    pub discrepancies: Vec<LedgerDiscrepancy>
+}
+
+impl LedgerReport {
+    // XEL the wallet must hold on-chain for the bot to pay everything it owes
+    pub fn liabilities(&self) -> i128 {
+        self.users + self.accounts.iter()
+            .filter(|(account, _)| !account.is_external())
+            .map(|(_, balance)| balance)
+            .sum::<i128>()
+    }
+}
+
+impl WalletServiceImpl {
+    pub(super) fn get_ledger_entries_internal(&self, storage: &EncryptedStorage) -> Result<Vec<LedgerEntry>> {
+        let mut entries = Vec::new();
+        for key in storage.get_custom_tree_keys(&LEDGER_TREE.to_string(), &None, None, None)? {
+            entries.push(storage.get_custom_data(LEDGER_TREE, &key)?.to_value()?.to_type()?);
+        }
+
+        Ok(entries)
+    }
+
+    // Topoheight of the wallet when the ledger was opened
+    pub(super) fn get_ledger_opened_at(&self, storage: &EncryptedStorage) -> u64 {
+        self.get_sequence_internal(storage, LEDGER_OPENED_KEY)
+    }
+
+    // Move an amount from an account to another
+    pub(super) fn post_ledger_entry(&self, storage: &mut EncryptedStorage, asset: &Hash, kind: MovementKind, from: LedgerAccount, to: LedgerAccount, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let id = self.get_sequence_internal(storage, LEDGER_SEQUENCE_KEY);
+        let entry = LedgerEntry {
+            id,
+            timestamp: get_current_time_in_seconds(),
+            asset: asset.clone(),
+            kind,
+            from,
+            to,
+            amount
+        };
+
+        storage.set_custom_data(LEDGER_TREE, &DataValue::U64(id), &DataElement::Value(DataValue::Blob(entry.to_bytes())))?;
+        storage.set_custom_data(JOURNAL_SEQUENCE_TREE, &DataValue::String(LEDGER_SEQUENCE_KEY.to_string()), &(id + 1).into())?;
+
+        Ok(())
+    }
+
+    // Post a change of a user balance against the account matching its reason
+    pub(super) fn post_balance_change(&self, storage: &mut EncryptedStorage, user: &UserApplication, asset: &Hash, kind: MovementKind, before: u64, after: u64) -> Result<()> {
+        let account = LedgerAccount::User(*user);
+        let other = counterpart(kind);
+        if after > before {
+            self.post_ledger_entry(storage, asset, kind, other, account, after - before)
+        } else {
+            self.post_ledger_entry(storage, asset, kind, account, other, before - after)
+        }
+    }
+
+    // The fee of a withdrawal is debited from the user with its amount, only the amount stays on-chain
+    pub(super) fn post_withdraw_fee(&self, storage: &mut EncryptedStorage, fee: u64) -> Result<()> {
+        self.post_ledger_entry(storage, &XELIS_ASSET, MovementKind::Withdraw, LedgerAccount::Wallet, LedgerAccount::Fees, fee)
+    }
+
+    // The fee of a withdrawal rejected by the daemon was never paid
+    pub(super) fn post_refunded_fee(&self, storage: &mut EncryptedStorage, fee: u64) -> Result<()> {
+        self.post_ledger_entry(storage, &XELIS_ASSET, MovementKind::Refund, LedgerAccount::Fees, LedgerAccount::Wallet, fee)
+    }
+
+    // Move the entries of a forgotten user to a shared account, its balances must be zeroed first
+    pub(super) fn forget_ledger_account_internal(&self, storage: &mut EncryptedStorage, user: &UserApplication) -> Result<()> {
+        let account = LedgerAccount::User(*user);
+        for mut entry in self.get_ledger_entries_internal(storage)? {
+            if entry.from != account && entry.to != account {
+                continue;
+            }
+
+            if entry.from == account {
+                entry.from = LedgerAccount::Forgotten;
+            }
+            if entry.to == account {
+                entry.to = LedgerAccount::Forgotten;
+            }
+            storage.set_custom_data(LEDGER_TREE, &DataValue::U64(entry.id), &DataElement::Value(DataValue::Blob(entry.to_bytes())))?;
+        }
+
+        Ok(())
+    }
+
+    // Open the ledger with the balances that existed before it
+    // Nothing is done once the ledger has a single entry
+    pub(super) async fn open_ledger(&self) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        if self.get_sequence_internal(&storage, LEDGER_SEQUENCE_KEY) > 0 {
+            return Ok(());
+        }
+
+        let topoheight = storage.get_synced_topoheight().unwrap_or(0);
+        storage.set_custom_data(JOURNAL_SEQUENCE_TREE, &DataValue::String(LEDGER_OPENED_KEY.to_string()), &topoheight.into())?;
+
+        let mut opened = 0;
+        for key in storage.get_custom_tree_keys(&BALANCES_TREE.to_string(), &None, None, None)? {
+            let balance_key: BalanceKey = key.as_type()?;
+            let balance = storage.get_custom_data(BALANCES_TREE, &key)?.to_value()?.to_u64()?;
+            if balance > 0 {
+                self.post_ledger_entry(&mut storage, &balance_key.asset, MovementKind::Admin, LedgerAccount::Opening, LedgerAccount::User(balance_key.user), balance)?;
+                opened += 1;
+            }
+        }
+
+        let internal = [
+            (LedgerAccount::Faucet, self.get_faucet_balance_internal(&storage)),
+            (LedgerAccount::Lottery, self.get_lottery_pot_internal(&storage)),
+            (LedgerAccount::Escrow, self.get_escrowed_internal(&storage)?)
+        ];
+        for (account, balance) in internal {
+            self.post_ledger_entry(&mut storage, &XELIS_ASSET, MovementKind::Admin, LedgerAccount::Opening, account, balance)?;
+        }
+
+        if opened > 0 {
+            info!("Opened the ledger with the balances of {} accounts", opened);
+        }
+
+        Ok(())
+    }
+
+    // Derive every balance from the ledger and compare them with the stored ones
+    pub async fn verify_ledger(&self) -> Result<LedgerReport> {
+        let storage = self.wallet.get_storage().read().await;
+        let entries = self.get_ledger_entries_internal(&storage)?;
+        let derived = derive_balances(&entries);
+
+        // Stored balances of the accounts held by the bot, zero when not stored
+        let mut stored: HashMap<(LedgerAccount, Hash), u64> = HashMap::new();
+        for key in storage.get_custom_tree_keys(&BALANCES_TREE.to_string(), &None, None, None)? {
+            let balance_key: BalanceKey = key.as_type()?;
+            let balance = storage.get_custom_data(BALANCES_TREE, &key)?.to_value()?.to_u64()?;
+            stored.insert((LedgerAccount::User(balance_key.user), balance_key.asset), balance);
+        }
+        stored.insert((LedgerAccount::Faucet, XELIS_ASSET), self.get_faucet_balance_internal(&storage));
+        stored.insert((LedgerAccount::Lottery, XELIS_ASSET), self.get_lottery_pot_internal(&storage));
+        stored.insert((LedgerAccount::Escrow, XELIS_ASSET), self.get_escrowed_internal(&storage)?);
+
+        let mut discrepancies = Vec::new();
+        let mut checked: Vec<(LedgerAccount, Hash)> = stored.keys().cloned().collect();
+        checked.extend(derived.keys().filter(|(account, _)| !account.is_external()).cloned());
+        checked.sort_by_key(|(account, asset)| (account.to_bytes(), asset.to_bytes()));
+        checked.dedup();
+        for (account, asset) in checked {
+            let derived = derived.get(&(account, asset.clone())).copied().unwrap_or(0);
+            let stored = stored.get(&(account, asset.clone())).copied().unwrap_or(0);
+            if derived != stored as i128 {
+                warn!("{:?} holds {} of {} but the ledger gives {}", account, stored, asset, derived);
+                discrepancies.push(LedgerDiscrepancy {
+                    account,
+                    asset,
+                    derived,
+                    stored
+                });
+            }
+        }
+
+        let mut users = 0;
+        let mut accounts: HashMap<LedgerAccount, i128> = HashMap::new();
+        for ((account, asset), balance) in derived {
+            if asset != XELIS_ASSET {
+                continue;
+            }
+
+            match account {
+                LedgerAccount::User(_) => users += balance,
+                account => *accounts.entry(account).or_insert(0) += balance
+            }
+        }
+        let mut accounts: Vec<(LedgerAccount, i128)> = accounts.into_iter().collect();
+        accounts.sort_by_key(|(account, _)| account.to_bytes());
+
+        Ok(LedgerReport {
+            entries: entries.len(),
+            accounts,
+            users,
+            discrepancies
+        })
+    }
+
+    // Warn at startup if the stored balances don't follow the ledger anymore
+    pub(super) async fn check_ledger(&self) -> Result<()> {
+        self.open_ledger().await?;
+        let report = self.verify_ledger().await?;
+        if !report.discrepancies.is_empty() {
+            warn!("{} balances don't match the ledger, check them with the ledger command", report.discrepancies.len());
+        } else {
+            info!("Ledger verified: {} entries, {} XEL owed", report.entries, format_xelis(report.liabilities().max(0) as u64));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use log::info;
+use rand::RngCore;
+use xelis_common::{
+    api::{DataElement, DataValue},
+    crypto::{hash, Hash},
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds,
+    utils::format_xelis
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{MovementKind, ServiceError, UserApplication, WalletServiceImpl, INTERNAL_BALANCES_TREE};
+
+// Current round and last draw of the lottery
+pub(super) const LOTTERY_TREE: &str = "lottery";
+const ROUND_KEY: &str = "round";
+const LAST_DRAW_KEY: &str = "last_draw";
+// Key of the pot in the internal balances
+const LOTTERY_ACCOUNT: &str = "lottery";
+
+// Maximum tickets bought at once
+pub const MAX_TICKETS_PER_PURCHASE: u32 = 1000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LotteryConfig {
+    // Price of a ticket
+    pub ticket_price: u64,
+    // Duration of a round
+    pub interval: Duration
+}
+
+// A lottery round, the seed is kept secret until the draw
+// and only its hash is published
+#[derive(Debug, Clone)]
+pub struct LotteryRound {
+    pub id: u64,
+    // Price of a ticket when the round started
+    pub ticket_price: u64,
+    // Timestamp in seconds
+    pub draw_at: u64,
+    // Tickets bought by each user
+    pub tickets: Vec<(UserApplication, u32)>,
+    seed: Hash
+}
+
+impl LotteryRound {
+    // Hash of the seed, published while the round is running
+    pub fn commitment(&self) -> Hash {
+        hash(self.seed.as_bytes())
+    }
+
+    pub fn total_tickets(&self) -> u64 {
+        self.tickets.iter().map(|(_, tickets)| *tickets as u64).sum()
+    }
+
+    pub fn tickets_of(&self, user: &UserApplication) -> u32 {
+        self.tickets.iter()
+            .find(|(owner, _)| owner == user)
+            .map(|(_, tickets)| *tickets)
+            .unwrap_or(0)
+    }
+
+    // Winning ticket is hash(seed || round id) modulo the tickets sold
+    // Tickets are numbered in the order they were bought
+    fn draw(&self) -> Option<(u64, UserApplication)> {
+        let total = self.total_tickets();
+        if total == 0 {
+            return None;
+        }
+
+        let mut bytes = self.seed.as_bytes().to_vec();
+        bytes.extend_from_slice(&self.id.to_be_bytes());
+        let roll = hash(&bytes);
+        let ticket = u64::from_be_bytes(roll.as_bytes()[0..8].try_into().expect("8 bytes")) % total;
+
+        let mut next = 0;
+        for (user, tickets) in self.tickets.iter() {
+            next += *tickets as u64;
+            if ticket < next {
+                return Some((ticket, *user));
+            }
+        }
+
+        None
+    }
+}
+
+impl Serializer for LotteryRound {
+    fn write(&self, writer: &mut Writer) {
+        self.id.write(writer);
+        self.ticket_price.write(writer);
+        self.draw_at.write(writer);
+        (self.tickets.len() as u32).write(writer);
+        for (user, tickets) in self.tickets.iter() {
+            user.write(writer);
+            tickets.write(writer);
+        }
+        self.seed.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let id = reader.read_u64()?;
+        let ticket_price = reader.read_u64()?;
+        let draw_at = reader.read_u64()?;
+        let len = reader.read_u32()?;
+        let mut tickets = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            tickets.push((UserApplication::read(reader)?, reader.read_u32()?));
+        }
+        let seed = Hash::read(reader)?;
+
+        Ok(Self {
+            id,
+            ticket_price,
+            draw_at,
+            tickets,
+            seed
+        })
+    }
+}
+
+// Result of a lottery round
+#[derive(Debug, Clone)]
+pub struct LotteryDraw {
+    pub round: u64,
+    // None if no ticket was sold
+    pub winner: Option<UserApplication>,
+    pub winning_ticket: u64,
+    pub total_tickets: u64,
+    pub pot: u64,
+    // Revealed seed of the round
+    pub seed: Hash
+}
+
+impl Serializer for LotteryDraw {
+    fn write(&self, writer: &mut Writer) {
+        self.round.write(writer);
+        match &self.winner {
+            Some(winner) => {
+                writer.write_u8(1);
+                winner.write(writer);
+            },
+            None => writer.write_u8(0)
+        }
+        self.winning_ticket.write(writer);
+        self.total_tickets.write(writer);
+        self.pot.write(writer);
+        self.seed.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        Ok(Self {
+            round: reader.read_u64()?,
+            winner: match reader.read_u8()? {
+                0 => None,
+                1 => Some(UserApplication::read(reader)?),
+                _ => return Err(ReaderError::InvalidValue)
+            },
+            winning_ticket: reader.read_u64()?,
+            total_tickets: reader.read_u64()?,
+            pot: reader.read_u64()?,
+            seed: Hash::read(reader)?
+        })
+    }
+}
+
+impl WalletServiceImpl {
+    pub(super) fn get_lottery_pot_internal(&self, storage: &EncryptedStorage) -> u64 {
+        storage.get_custom_data(INTERNAL_BALANCES_TREE, &DataValue::String(LOTTERY_ACCOUNT.to_string()))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_u64().ok())
+            .unwrap_or(0)
+    }
+
+    fn set_lottery_pot_internal(&self, storage: &mut EncryptedStorage, pot: u64) -> Result<()> {
+        storage.set_custom_data(INTERNAL_BALANCES_TREE, &DataValue::String(LOTTERY_ACCOUNT.to_string()), &pot.into())?;
+        Ok(())
+    }
+
+    pub(super) fn get_lottery_round_internal(&self, storage: &EncryptedStorage) -> Option<LotteryRound> {
+        storage.get_custom_data(LOTTERY_TREE, &DataValue::String(ROUND_KEY.to_string()))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_type().ok())
+    }
+
+    fn set_lottery_round_internal(&self, storage: &mut EncryptedStorage, round: &LotteryRound) -> Result<()> {
+        storage.set_custom_data(LOTTERY_TREE, &DataValue::String(ROUND_KEY.to_string()), &DataElement::Value(DataValue::Blob(round.to_bytes())))?;
+        Ok(())
+    }
+
+    // Start a new round with a fresh seed
+    fn new_lottery_round(&self, config: &LotteryConfig, id: u64) -> LotteryRound {
+        let mut seed = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut seed);
+
+        LotteryRound {
+            id,
+            ticket_price: config.ticket_price,
+            draw_at: get_current_time_in_seconds() + config.interval.as_secs(),
+            tickets: Vec::new(),
+            seed: Hash::new(seed)
+        }
+    }
+
+    // Get the running round, a new one is started if needed
+    fn get_or_start_lottery_round(&self, storage: &mut EncryptedStorage, config: &LotteryConfig) -> Result<LotteryRound> {
+        if let Some(round) = self.get_lottery_round_internal(storage) {
+            return Ok(round);
+        }
+
+        let round = self.new_lottery_round(config, 0);
+        self.set_lottery_round_internal(storage, &round)?;
+        info!("Lottery round {} started", round.id);
+
+        Ok(round)
+    }
+
+    // Get the running round with its pot
+    pub async fn get_lottery(&self) -> Result<(LotteryRound, u64), ServiceError> {
+        let config = self.lottery.ok_or(ServiceError::LotteryDisabled)?;
+        let mut storage = self.wallet.get_storage().write().await;
+        let round = self.get_or_start_lottery_round(&mut storage, &config)?;
+        Ok((round, self.get_lottery_pot_internal(&storage)))
+    }
+
+    // Get the result of the previous round
+    pub async fn get_last_lottery_draw(&self) -> Option<LotteryDraw> {
+        let storage = self.wallet.get_storage().read().await;
+        storage.get_custom_data(LOTTERY_TREE, &DataValue::String(LAST_DRAW_KEY.to_string()))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_type().ok())
+    }
+
+    // Buy tickets for the running round, the price is added to the pot
+    // Returns the round with the tickets owned by the user
+    pub async fn buy_lottery_tickets(&self, user: &UserApplication, count: u32) -> Result<(LotteryRound, u32), ServiceError> {
+        let config = self.lottery.ok_or(ServiceError::LotteryDisabled)?;
+        if count == 0 {
+            return Err(ServiceError::Zero);
+        }
+
+        if count > MAX_TICKETS_PER_PURCHASE {
+            return Err(ServiceError::TooManyTickets(MAX_TICKETS_PER_PURCHASE));
+        }
+
+        let mut storage = self.wallet.get_storage().write().await;
+        self.check_not_frozen(&storage, user)?;
+        let mut round = self.get_or_start_lottery_round(&mut storage, &config)?;
+        let cost = round.ticket_price.checked_mul(count as u64).ok_or(ServiceError::Overflow)?;
+        let balance = self.get_balance_internal(&storage, user);
+        if cost > balance {
+            return Err(ServiceError::NotEnoughFunds(cost));
+        }
+
+        let owned = match round.tickets.iter_mut().find(|(owner, _)| owner == user) {
+            Some((_, tickets)) => {
+                *tickets = tickets.checked_add(count).ok_or(ServiceError::Overflow)?;
+                *tickets
+            },
+            None => {
+                round.tickets.push((*user, count));
+                count
+            }
+        };
+
+        let pot = self.get_lottery_pot_internal(&storage);
+        self.set_balance_internal(&mut storage, user, balance - cost, MovementKind::Lottery)?;
+        self.set_lottery_pot_internal(&mut storage, pot + cost)?;
+        self.set_lottery_round_internal(&mut storage, &round)?;
+
+        info!("{:?} bought {} lottery tickets for {} XEL in round {}", user, count, format_xelis(cost), round.id);
+
+        Ok((round, owned))
+    }
+
+    // Draw the running round if its time is over, the whole pot goes to the winner
+    // A new round is started right after
+    pub(super) async fn draw_lottery_if_due(&self) -> Result<Option<LotteryDraw>> {
+        let Some(config) = self.lottery else {
+            return Ok(None);
+        };
+
+        let mut storage = self.wallet.get_storage().write().await;
+        let round = self.get_or_start_lottery_round(&mut storage, &config)?;
+        if get_current_time_in_seconds() < round.draw_at {
+            return Ok(None);
+        }
+
+        let pot = self.get_lottery_pot_internal(&storage);
+        let draw = round.draw();
+        if let Some((_, winner)) = draw {
+            let balance = self.get_balance_internal(&storage, &winner);
+            self.set_balance_internal(&mut storage, &winner, balance + pot, MovementKind::Lottery)?;
+            self.set_lottery_pot_internal(&mut storage, 0)?;
+        }
+
+        let result = LotteryDraw {
+            round: round.id,
+            winner: draw.map(|(_, winner)| winner),
+            winning_ticket: draw.map(|(ticket, _)| ticket).unwrap_or(0),
+            total_tickets: round.total_tickets(),
+            // Without tickets, the pot stays for the next round
+            pot: if draw.is_some() { pot } else { 0 },
+            seed: round.seed.clone()
+        };
+        storage.set_custom_data(LOTTERY_TREE, &DataValue::String(LAST_DRAW_KEY.to_string()), &DataElement::Value(DataValue::Blob(result.to_bytes())))?;
+
+        let next = self.new_lottery_round(&config, round.id + 1);
+        self.set_lottery_round_internal(&mut storage, &next)?;
+
+        info!("Lottery round {} drawn: {:?} won {} XEL with ticket {}/{}", round.id, result.winner, format_xelis(result.pot), result.winning_ticket, result.total_tickets);
+
+        Ok(Some(result))
+    }
+
+    // Draw the running round at the next check, whatever its time left
+    pub async fn force_lottery_draw(&self) -> Result<(), ServiceError> {
+        let config = self.lottery.ok_or(ServiceError::LotteryDisabled)?;
+        let mut storage = self.wallet.get_storage().write().await;
+        let mut round = self.get_or_start_lottery_round(&mut storage, &config)?;
+        round.draw_at = get_current_time_in_seconds();
+        self.set_lottery_round_internal(&mut storage, &round)?;
+
+        Ok(())
+    }
+
+    // Refund every ticket of the running round and start a new one
+    // Returns the total refunded
+    pub async fn refund_lottery(&self) -> Result<u64, ServiceError> {
+        let config = self.lottery.ok_or(ServiceError::LotteryDisabled)?;
+        let mut storage = self.wallet.get_storage().write().await;
+        let round = self.get_or_start_lottery_round(&mut storage, &config)?;
+
+        let mut pot = self.get_lottery_pot_internal(&storage);
+        let mut refunded = 0;
+        for (user, tickets) in round.tickets.iter() {
+            let amount = (round.ticket_price * *tickets as u64).min(pot);
+            let balance = self.get_balance_internal(&storage, user);
+            self.set_balance_internal(&mut storage, user, balance + amount, MovementKind::Lottery)?;
+            pot -= amount;
+            refunded += amount;
+        }
+        self.set_lottery_pot_internal(&mut storage, pot)?;
+
+        let next = self.new_lottery_round(&config, round.id + 1);
+        self.set_lottery_round_internal(&mut storage, &next)?;
+
+        info!("Lottery round {} refunded: {} XEL to {} users", round.id, format_xelis(refunded), round.tickets.len());
+
+        Ok(refunded)
+    }
+}
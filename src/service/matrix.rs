@@ -0,0 +1,87 @@
+use anyhow::{anyhow, bail, Result};
+use log::{debug, info, warn};
+use matrix_sdk::{
+    ruma::{OwnedUserId, UserId},
+    Client,
+    Room
+};
+use xelis_common::api::DataValue;
+
+use super::{ids::text_id, UserApplication, WalletServiceImpl};
+
+// Matrix user ID of each Matrix user, recorded the first time they use the bot or are tipped
+pub(super) const MATRIX_USERS_TREE: &str = "matrix_users";
+
+// Matrix user IDs are text up to 255 bytes, see `text_id`
+pub fn matrix_id(user_id: &UserId) -> u64 {
+    text_id(user_id.as_str())
+}
+
+// User given by an operator, as its Matrix user ID or the ID the bot knows it by
+pub fn parse_matrix_user(id: &str) -> Option<UserApplication> {
+    let id = id.trim();
+    if id.starts_with('@') {
+        return UserId::parse(id).ok().map(|user_id| UserApplication::Matrix(matrix_id(&user_id)));
+    }
+
+    id.parse().ok().map(UserApplication::Matrix)
+}
+
+impl WalletServiceImpl {
+    // Client logged in to the homeserver, the Matrix users can't be sent DMs until it's set
+    pub fn set_matrix_client(&self, client: Client) {
+        if self.matrix.set(client).is_err() {
+            warn!("The Matrix client was already set");
+        }
+    }
+
+    fn get_matrix_client(&self) -> Result<&Client> {
+        self.matrix.get().ok_or_else(|| anyhow!("Matrix isn't configured, check --matrix-homeserver"))
+    }
+
+    // Remember the Matrix user ID behind a user, so it can be sent DMs
+    // Fails if another Matrix user already has the same hash
+    pub async fn record_matrix_user(&self, user_id: &UserId) -> Result<UserApplication> {
+        let id = matrix_id(user_id);
+        let key = DataValue::U64(id);
+        match self.get_matrix_user_id(id).await? {
+            Some(known) if known.as_str() == user_id.as_str() => return Ok(UserApplication::Matrix(id)),
+            Some(known) => bail!("The Matrix users {} and {} have the same ID {}", known, user_id, id),
+            None => {}
+        }
+
+        let mut storage = self.wallet.get_storage().write().await;
+        storage.set_custom_data(MATRIX_USERS_TREE, &key, &DataValue::String(user_id.to_string()).into())?;
+        info!("Recorded the Matrix user {} as {}", user_id, id);
+
+        Ok(UserApplication::Matrix(id))
+    }
+
+    // Matrix user ID of a user, None if it never used the bot
+    pub async fn get_matrix_user_id(&self, id: u64) -> Result<Option<OwnedUserId>> {
+        let storage = self.wallet.get_storage().read().await;
+        let user_id = storage.get_custom_data(MATRIX_USERS_TREE, &DataValue::U64(id))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| match v {
+                DataValue::String(user_id) => Some(user_id),
+                _ => None
+            });
+
+        match user_id {
+            Some(user_id) => Ok(Some(UserId::parse(user_id)?)),
+            None => Ok(None)
+        }
+    }
+
+    // Room the bot shares with a single Matrix user, created if there's none yet
+    pub async fn get_matrix_dm_room(&self, user_id: &UserId) -> Result<Room> {
+        let client = self.get_matrix_client()?;
+        if let Some(room) = client.get_dm_room(user_id) {
+            return Ok(room);
+        }
+
+        debug!("Creating a DM room with {}", user_id);
+        Ok(client.create_dm(user_id).await?)
+    }
+}
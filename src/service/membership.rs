@@ -0,0 +1,178 @@
+use anyhow::Result;
+use xelis_common::{
+    api::DataValue,
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{Community, Setting, UserApplication, WalletServiceImpl};
+
+// When each user was first seen writing in each Telegram group, timestamp in seconds
+// Telegram doesn't tell when a user joined a group nor how old its account is
+pub(super) const FIRST_SEEN_TREE: &str = "first_seen";
+
+// Storage key of the first time a user was seen in a community
+pub(super) struct MembershipKey {
+    community: Community,
+    user: UserApplication
+}
+
+impl Serializer for MembershipKey {
+    fn write(&self, writer: &mut Writer) {
+        self.community.write(writer);
+        self.user.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let community = Community::read(reader)?;
+        let user = UserApplication::read(reader)?;
+
+        Ok(Self {
+            community,
+            user
+        })
+    }
+}
+
+// How hard it is to receive a rain or claim a giveaway in a Telegram group
+// Groups have no roles, the history of the user in the group is used instead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+    Off,
+    Low,
+    Medium,
+    High
+}
+
+impl Strictness {
+    pub const ALL: [Strictness; 4] = [Strictness::Off, Strictness::Low, Strictness::Medium, Strictness::High];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strictness::Off => "off",
+            Strictness::Low => "low",
+            Strictness::Medium => "medium",
+            Strictness::High => "high"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|strictness| strictness.name() == name)
+    }
+
+    fn id(&self) -> u64 {
+        match self {
+            Strictness::Off => 0,
+            Strictness::Low => 1,
+            Strictness::Medium => 2,
+            Strictness::High => 3
+        }
+    }
+
+    fn from_id(id: u64) -> Option<Self> {
+        Some(match id {
+            0 => Strictness::Off,
+            1 => Strictness::Low,
+            2 => Strictness::Medium,
+            3 => Strictness::High,
+            _ => return None
+        })
+    }
+
+    // Days since the user was first seen writing in the group
+    pub fn min_member_days(&self) -> u64 {
+        match self {
+            Strictness::Off => 0,
+            Strictness::Low => 1,
+            Strictness::Medium => 3,
+            Strictness::High => 7
+        }
+    }
+
+    // Messages written in the group over the last 24 hours
+    pub fn min_messages(&self) -> u32 {
+        match self {
+            Strictness::Off => 0,
+            Strictness::Low => 1,
+            Strictness::Medium => 3,
+            Strictness::High => 5
+        }
+    }
+}
+
+impl WalletServiceImpl {
+    // Remember the first message of a user in a community, later ones are ignored
+    pub async fn record_first_seen(&self, community: &Community, user: &UserApplication) -> Result<()> {
+        let key = DataValue::Blob(MembershipKey { community: *community, user: *user }.to_bytes());
+        {
+            let storage = self.wallet.get_storage().read().await;
+            if storage.has_custom_data(FIRST_SEEN_TREE, &key)? {
+                return Ok(());
+            }
+        }
+
+        let mut storage = self.wallet.get_storage().write().await;
+        if !storage.has_custom_data(FIRST_SEEN_TREE, &key)? {
+            storage.set_custom_data(FIRST_SEEN_TREE, &key, &get_current_time_in_seconds().into())?;
+        }
+
+        Ok(())
+    }
+
+    // Get when a user was first seen in a community, timestamp in seconds
+    pub async fn get_first_seen(&self, community: &Community, user: &UserApplication) -> Option<u64> {
+        let storage = self.wallet.get_storage().read().await;
+        let key = DataValue::Blob(MembershipKey { community: *community, user: *user }.to_bytes());
+        storage.get_custom_data(FIRST_SEEN_TREE, &key)
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_u64().ok())
+    }
+
+    // Strictness of the rains and giveaways of a community, off if not configured
+    pub async fn get_recipient_strictness(&self, community: &Community) -> Strictness {
+        self.get_setting(community, Setting::RecipientStrictness).await
+            .and_then(|v| v.to_u64().ok())
+            .and_then(Strictness::from_id)
+            .unwrap_or(Strictness::Off)
+    }
+
+    pub async fn set_recipient_strictness(&self, community: &Community, strictness: Strictness) -> Result<()> {
+        let value = Some(DataValue::U64(strictness.id())).filter(|_| strictness != Strictness::Off);
+        self.set_setting(community, Setting::RecipientStrictness, value).await
+    }
+
+    // Check if a user can receive a rain or claim a giveaway in a community
+    // Messages are the ones written by the user in the community over the last 24 hours
+    pub async fn is_eligible_recipient(&self, community: &Community, user: &UserApplication, strictness: Strictness, messages: u32) -> bool {
+        if strictness == Strictness::Off {
+            return true;
+        }
+
+        if messages < strictness.min_messages() {
+            return false;
+        }
+
+        let min_age = strictness.min_member_days() * 24 * 60 * 60;
+        self.get_first_seen(community, user).await
+            .is_some_and(|first_seen| get_current_time_in_seconds().saturating_sub(first_seen) >= min_age)
+    }
+
+    // Forget the groups in which a user was seen
+    pub(super) fn delete_first_seen_internal(&self, storage: &mut EncryptedStorage, user: &UserApplication) -> Result<()> {
+        let mut keys = Vec::new();
+        for key in storage.get_custom_tree_keys(&FIRST_SEEN_TREE.to_string(), &None, None, None)? {
+            let membership: MembershipKey = key.as_type()?;
+            if membership.user == *user {
+                keys.push(key);
+            }
+        }
+
+        for key in keys {
+            storage.delete_custom_data(FIRST_SEEN_TREE, &key)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,464 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{atomic::{AtomicBool, Ordering}, Mutex}
+};
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use xelis_common::{
+    api::DataElement,
+    crypto::{Address, AddressType, Hash, Hashable, KeyPair, PublicKey, Signature},
+    network::Network,
+    transaction::{builder::TransactionTypeBuilder, Transaction}
+};
+use xelis_wallet::{
+    storage::EncryptedStorage,
+    wallet::Event
+};
+
+use super::backend::{ApplyChanges, DaemonEvent, DaemonEvents, WalletBackend};
+
+// Wallet without a daemon, for the unit tests of the service
+// The tests set its balances and fees, push the events it receives and queue the TXs it builds,
+// then check what it broadcast
+pub struct MockWallet {
+    storage: RwLock<EncryptedStorage>,
+    network: Network,
+    keypair: KeyPair,
+    public_key: PublicKey,
+    online: AtomicBool,
+    balances: Mutex<HashMap<Hash, u64>>,
+    fee: Mutex<u64>,
+    // TXs returned by create_transaction, in order
+    transactions: Mutex<VecDeque<Transaction>>,
+    // Reason given by submit_transaction until cleared, the TX isn't broadcast
+    rejection: Mutex<Option<String>>,
+    submitted: Mutex<Vec<Hash>>,
+    events: broadcast::Sender<Event>,
+    daemon_events: Mutex<Option<mpsc::Sender<Result<DaemonEvent>>>>,
+    topoheight: Mutex<u64>
+}
+
+impl MockWallet {
+    // The storage is opened by the test, usually in a temporary directory
+    pub fn new(storage: EncryptedStorage, network: Network) -> Self {
+        let keypair = KeyPair::new();
+        let public_key = keypair.get_public_key().compress();
+        Self {
+            storage: RwLock::new(storage),
+            network,
+            keypair,
+            public_key,
+            online: AtomicBool::new(false),
+            balances: Mutex::new(HashMap::new()),
+            fee: Mutex::new(0),
+            transactions: Mutex::new(VecDeque::new()),
+            rejection: Mutex::new(None),
+            submitted: Mutex::new(Vec::new()),
+            events: broadcast::channel(64).0,
+            daemon_events: Mutex::new(None),
+            topoheight: Mutex::new(0)
+        }
+    }
+
+    pub fn set_balance(&self, asset: Hash, balance: u64) {
+        self.balances.lock().expect("mock balances lock").insert(asset, balance);
+    }
+
+    pub fn set_fee(&self, fee: u64) {
+        *self.fee.lock().expect("mock fee lock") = fee;
+    }
+
+    pub fn set_topoheight(&self, topoheight: u64) {
+        *self.topoheight.lock().expect("mock topoheight lock") = topoheight;
+    }
+
+    // Returned by the next create_transaction
+    pub fn queue_transaction(&self, transaction: Transaction) {
+        self.transactions.lock().expect("mock transactions lock").push_back(transaction);
+    }
+
+    // Every TX broadcast is rejected with this reason until it's cleared
+    pub fn reject_transactions(&self, reason: Option<&str>) {
+        *self.rejection.lock().expect("mock rejection lock") = reason.map(str::to_string);
+    }
+
+    // Hashes of the TXs broadcast, in order
+    pub fn get_submitted(&self) -> Vec<Hash> {
+        self.submitted.lock().expect("mock submitted lock").clone()
+    }
+
+    // Received by the event loop as if the wallet sent it
+    pub fn push_event(&self, event: Event) {
+        let _ = self.events.send(event);
+    }
+
+    // Received by the event loop as if the daemon sent it, returns false while nothing is subscribed
+    pub async fn push_daemon_event(&self, event: DaemonEvent) -> bool {
+        let sender = self.daemon_events.lock().expect("mock daemon events lock").clone();
+        match sender {
+            Some(sender) => sender.send(Ok(event)).await.is_ok(),
+            None => false
+        }
+    }
+
+    // Lose the daemon, the event loop sees its events channel closed
+    pub fn disconnect(&self) {
+        self.online.store(false, Ordering::SeqCst);
+        self.daemon_events.lock().expect("mock daemon events lock").take();
+    }
+}
+
+#[async_trait]
+impl WalletBackend for MockWallet {
+    fn get_storage(&self) -> &RwLock<EncryptedStorage> {
+        &self.storage
+    }
+
+    fn get_network(&self) -> &Network {
+        &self.network
+    }
+
+    fn get_public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    fn get_address(&self) -> Address {
+        self.public_key.to_address(self.network.is_mainnet())
+    }
+
+    fn get_address_with(&self, data: DataElement) -> Address {
+        Address::new(self.network.is_mainnet(), AddressType::Data(data), self.public_key.clone())
+    }
+
+    fn sign_data(&self, data: &[u8]) -> Signature {
+        self.keypair.sign(data)
+    }
+
+    async fn get_balance(&self, asset: &Hash) -> u64 {
+        self.balances.lock().expect("mock balances lock").get(asset).copied().unwrap_or(0)
+    }
+
+    async fn is_online(&self) -> bool {
+        self.online.load(Ordering::SeqCst)
+    }
+
+    async fn set_online_mode(&self, _: &str) -> Result<()> {
+        self.online.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn set_offline_mode(&self) -> Result<()> {
+        self.disconnect();
+        Ok(())
+    }
+
+    async fn rescan(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_daemon_topoheight(&self) -> Result<Option<u64>> {
+        if !self.online.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+
+        Ok(Some(*self.topoheight.lock().expect("mock topoheight lock")))
+    }
+
+    async fn estimate_fees(&self, _: TransactionTypeBuilder) -> Result<u64> {
+        Ok(*self.fee.lock().expect("mock fee lock"))
+    }
+
+    // The builder isn't used, the test queues the TX it expects
+    async fn create_transaction(&self, _: &mut EncryptedStorage, _: TransactionTypeBuilder, _: u64) -> Result<(Transaction, ApplyChanges)> {
+        let transaction = self.transactions.lock().expect("mock transactions lock")
+            .pop_front()
+            .ok_or_else(|| anyhow!("No TX queued in the mock wallet"))?;
+
+        let apply: ApplyChanges = Box::new(|_| Box::pin(async { Ok(()) }));
+        Ok((transaction, apply))
+    }
+
+    async fn submit_transaction(&self, transaction: &Transaction) -> Result<()> {
+        if !self.online.load(Ordering::SeqCst) {
+            bail!("The mock wallet is offline");
+        }
+
+        if let Some(reason) = self.rejection.lock().expect("mock rejection lock").as_ref() {
+            bail!("{}", reason);
+        }
+
+        self.submitted.lock().expect("mock submitted lock").push(transaction.hash());
+        Ok(())
+    }
+
+    async fn subscribe_events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    async fn subscribe_daemon_events(&self) -> Result<Option<DaemonEvents>> {
+        if !self.online.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+
+        let (sender, receiver) = mpsc::channel(64);
+        *self.daemon_events.lock().expect("mock daemon events lock") = Some(sender);
+        Ok(Some(receiver))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use poise::serenity_prelude::Http;
+    use rand::random;
+    use teloxide::Bot;
+    use xelis_common::{
+        account::CiphertextCache,
+        api::{wallet::{EntryType, TransactionEntry, TransferIn}, DataValue},
+        config::XELIS_ASSET,
+        crypto::elgamal::Ciphertext,
+        serializer::Serializer,
+        time::get_current_time_in_millis,
+        transaction::{
+            builder::{AccountState, FeeBuilder, FeeHelper, TransactionBuilder, TransferBuilder},
+            extra_data::{PlaintextExtraData, PlaintextFlag},
+            Reference,
+            TxVersion
+        }
+    };
+    use xelis_wallet::{config::SALT_SIZE, storage::Storage};
+
+    use super::*;
+    use super::super::{OpsChannels, RuntimeSettings, ServiceError, UserApplication, WalletService, WalletServiceImpl, HISTORY_TREE};
+    use crate::{api_errors::ApiErrorMonitor, price::PriceFeed, telegram_notifier::TelegramNotifier};
+
+    const FEE: u64 = 1_000;
+
+    // Balance of the test account signing the queued TXs, the mock never checks it
+    struct SourceState {
+        balance: u64,
+        ciphertext: Ciphertext,
+        nonce: u64
+    }
+
+    impl FeeHelper for SourceState {
+        type Error = ();
+
+        fn account_exists(&self, _: &PublicKey) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+    }
+
+    impl AccountState for SourceState {
+        fn is_mainnet(&self) -> bool {
+            false
+        }
+
+        fn get_account_balance(&self, _: &Hash) -> Result<u64, Self::Error> {
+            Ok(self.balance)
+        }
+
+        fn get_reference(&self) -> Reference {
+            Reference { hash: Hash::zero(), topoheight: 0 }
+        }
+
+        fn get_account_ciphertext(&self, _: &Hash) -> Result<CiphertextCache, Self::Error> {
+            Ok(CiphertextCache::Decompressed(self.ciphertext.clone()))
+        }
+
+        fn update_account_balance(&mut self, _: &Hash, balance: u64, ciphertext: Ciphertext) -> Result<(), Self::Error> {
+            self.balance = balance;
+            self.ciphertext = ciphertext;
+            Ok(())
+        }
+
+        fn get_nonce(&self) -> Result<u64, Self::Error> {
+            Ok(self.nonce)
+        }
+
+        fn update_nonce(&mut self, nonce: u64) -> Result<(), Self::Error> {
+            self.nonce = nonce;
+            Ok(())
+        }
+
+        fn is_account_registered(&self, _: &PublicKey) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+    }
+
+    // Storage of a new wallet in the temporary directory, removed by the test
+    fn open_storage() -> (EncryptedStorage, String) {
+        let path = std::env::temp_dir().join(format!("xelis-tip-bot-{}", random::<u64>())).to_string_lossy().to_string();
+        let key: [u8; 32] = random();
+        let mut storage = EncryptedStorage::new(Storage::new(&path).expect("mock storage"), &key, [0; SALT_SIZE], Network::Testnet).expect("mock encrypted storage");
+        storage.set_synced_topoheight(0).expect("mock synced topoheight");
+
+        (storage, path)
+    }
+
+    async fn create_service() -> (WalletService, Arc<MockWallet>, String) {
+        let (storage, path) = open_storage();
+        let wallet = Arc::new(MockWallet::new(storage, Network::Testnet));
+        let runtime = RuntimeSettings {
+            max_liability: None,
+            solvency_buffer: 0,
+            low_balance_alert: None,
+            min_tip: 0,
+            min_withdraw: 0,
+            max_daily_withdraw: None,
+            whitelist_delay: 0,
+            rate_limit: None,
+            ops_channels: OpsChannels { discord: None, telegram: None },
+            explorer_url: None
+        };
+        let api_errors = ApiErrorMonitor::new();
+        let notifier = TelegramNotifier::new(Bot::new(""), Arc::clone(&api_errors));
+        let service = WalletServiceImpl::with_backend(wallet.clone(), vec!["mock".to_string()], None, None, None, runtime, Vec::new(), notifier, api_errors, PriceFeed::new(String::new(), Vec::new())).await
+            .expect("service on the mock wallet");
+
+        (service, wallet, path)
+    }
+
+    // Withdrawal signed by a test account, the mock broadcasts it as is
+    fn create_transaction(to: &Address, amount: u64) -> Transaction {
+        let keypair = KeyPair::new();
+        let balance = amount + FEE;
+        let mut state = SourceState {
+            balance,
+            ciphertext: keypair.get_public_key().encrypt(balance),
+            nonce: 0
+        };
+        let data = TransactionTypeBuilder::Transfers(vec![TransferBuilder {
+            amount,
+            asset: XELIS_ASSET,
+            destination: to.clone(),
+            extra_data: None,
+            encrypt_extra_data: true
+        }]);
+
+        TransactionBuilder::new(TxVersion::T0, keypair.get_public_key().compress(), None, data, FeeBuilder::Fixed(FEE))
+            .build(&mut state, &keypair)
+            .expect("test TX")
+    }
+
+    async fn get_balance(service: &WalletService, user: &UserApplication) -> u64 {
+        service.get_balances_for_user(user).await.expect("balances")[0].amount
+    }
+
+    #[tokio::test]
+    async fn tip_moves_balances() {
+        let (service, _, path) = create_service().await;
+        let from = UserApplication::Telegram(1);
+        let to = UserApplication::Discord(2);
+        service.add_balance(&from, 100, "test").await.expect("credit");
+
+        service.transfer(&from, &to, &XELIS_ASSET, 40, None, None).await.expect("tip");
+        assert_eq!(get_balance(&service, &from).await, 60);
+        assert_eq!(get_balance(&service, &to).await, 40);
+
+        // Nothing moves when the balance is too low
+        assert!(matches!(service.transfer(&from, &to, &XELIS_ASSET, 61, None, None).await, Err(ServiceError::NotEnoughFunds(_))));
+        assert_eq!(get_balance(&service, &from).await, 60);
+        assert_eq!(get_balance(&service, &to).await, 40);
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[tokio::test]
+    async fn rejected_withdrawal_refunds_the_debit() {
+        let (service, wallet, path) = create_service().await;
+        let user = UserApplication::Telegram(1);
+        service.add_balance(&user, 10_000, "test").await.expect("credit");
+        service.connection_established(0).await.expect("synced");
+        wallet.set_balance(XELIS_ASSET, 10_000);
+
+        let to = KeyPair::new().get_public_key().compress().to_address(false);
+        wallet.set_fee(FEE);
+        wallet.queue_transaction(create_transaction(&to, 5_000));
+        wallet.reject_transactions(Some("Invalid transaction"));
+
+        assert!(service.withdraw(&user, to, &XELIS_ASSET, 5_000, None, None).await.is_err());
+        assert!(wallet.get_submitted().is_empty());
+        assert_eq!(get_balance(&service, &user).await, 10_000);
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[tokio::test]
+    async fn incoming_transaction_is_credited_once() {
+        let (service, wallet, path) = create_service().await;
+        let user = UserApplication::Matrix(1);
+        let entry = TransactionEntry {
+            hash: Hash::new(random()),
+            topoheight: 1,
+            timestamp: get_current_time_in_millis(),
+            entry: EntryType::Incoming {
+                from: KeyPair::new().get_public_key().compress().to_address(false),
+                transfers: vec![TransferIn {
+                    asset: XELIS_ASSET,
+                    amount: 500,
+                    extra_data: Some(PlaintextExtraData::new(None, Some(DataElement::Value(DataValue::Blob(user.to_bytes()))), PlaintextFlag::Public))
+                }]
+            }
+        };
+
+        wallet.set_topoheight(entry.topoheight);
+        let event_loop = {
+            let service = Arc::clone(&service);
+            tokio::spawn(async move {
+                let _ = service.event_loop(&Arc::new(Http::new("")), &Bot::new("")).await;
+            })
+        };
+
+        // Wait for the event loop to subscribe
+        while !wallet.push_daemon_event(DaemonEvent::StableTopoHeight(0)).await {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Seen twice before it's stable, then again once credited
+        wallet.push_event(Event::NewTransaction(entry.clone()));
+        wallet.push_event(Event::NewTransaction(entry.clone()));
+        for _ in 0..100 {
+            wallet.push_daemon_event(DaemonEvent::StableTopoHeight(entry.topoheight)).await;
+            if get_balance(&service, &user).await > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Queued again, then dropped from the queue once stable without being credited twice
+        wallet.push_event(Event::NewTransaction(entry.clone()));
+        let mut queued = false;
+        for _ in 0..100 {
+            if service.get_queue_status().unconfirmed == 1 {
+                queued = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(queued, "the TX seen again must be queued");
+
+        let mut handled = false;
+        for _ in 0..100 {
+            wallet.push_daemon_event(DaemonEvent::StableTopoHeight(entry.topoheight)).await;
+            if service.get_queue_status().unconfirmed == 0 {
+                handled = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(handled, "the TX seen again must be handled once stable");
+
+        assert_eq!(get_balance(&service, &user).await, 500);
+        {
+            let storage = wallet.get_storage().read().await;
+            assert!(storage.has_custom_data(HISTORY_TREE, &entry.hash.clone().into()).expect("history"));
+        }
+
+        event_loop.abort();
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use log::debug;
+use teloxide::types::{ChatId, MessageId};
+use xelis_common::{
+    api::DataValue,
+    crypto::{hash, Hash},
+    serializer::Serializer,
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{telegram_channel, ServiceError, WalletServiceImpl};
+
+// Commands that moved funds, by operation ID
+// Holds the timestamp in seconds they were processed at
+pub(super) const PROCESSED_OPERATIONS_TREE: &str = "processed_operations";
+
+// How long an operation is remembered, the platforms deliver them again within a day
+const OPERATION_RETENTION: Duration = Duration::from_secs(2 * 24 * 60 * 60);
+
+// Deterministic ID of a command, the same every time its interaction or update is delivered
+// The command name is part of it, so two operations started from the same message don't collide
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationId(Hash);
+
+impl OperationId {
+    // Discord interaction, or message of a prefix command
+    pub fn discord(command: &str, interaction: u64) -> Self {
+        Self(hash(format!("discord:{}:{}", command, interaction).as_bytes()))
+    }
+
+    // Telegram message of the command, or of the buttons that confirmed it
+    pub fn telegram(command: &str, chat: ChatId, message: MessageId) -> Self {
+        Self(hash(format!("telegram:{}:{}:{}", command, telegram_channel(chat), message.0).as_bytes()))
+    }
+
+    // Matrix event of the command, its ID is unique in the homeserver
+    pub fn matrix(command: &str, event_id: &str) -> Self {
+        Self(hash(format!("matrix:{}:{}", command, event_id).as_bytes()))
+    }
+
+    // Slack trigger of the slash command, it's unique to each invocation
+    pub fn slack(command: &str, trigger_id: &str) -> Self {
+        Self(hash(format!("slack:{}:{}", command, trigger_id).as_bytes()))
+    }
+
+    // Twitch chat message of the command, its ID is unique
+    pub fn twitch(command: &str, message_id: &str) -> Self {
+        Self(hash(format!("twitch:{}:{}", command, message_id).as_bytes()))
+    }
+
+    // Key chosen by the caller of a JSON-RPC method
+    pub fn rpc(key: &str) -> Self {
+        Self(hash(format!("rpc:{}", key).as_bytes()))
+    }
+}
+
+impl WalletServiceImpl {
+    // Refuse an operation that was already processed
+    // Called under the storage write lock of the operation, so a duplicate can't run alongside it
+    pub(super) fn check_operation(&self, storage: &EncryptedStorage, operation: Option<&OperationId>) -> Result<(), ServiceError> {
+        let Some(OperationId(id)) = operation else {
+            return Ok(());
+        };
+
+        if storage.has_custom_data(PROCESSED_OPERATIONS_TREE, &DataValue::Blob(id.to_bytes()))? {
+            debug!("Operation {} was already processed", id);
+            return Err(ServiceError::AlreadyProcessed);
+        }
+
+        Ok(())
+    }
+
+    // Remember an operation, in the same storage write as the balances it changed
+    pub(super) fn mark_operation_processed(&self, storage: &mut EncryptedStorage, operation: Option<&OperationId>) -> Result<()> {
+        if let Some(OperationId(id)) = operation {
+            storage.set_custom_data(PROCESSED_OPERATIONS_TREE, &DataValue::Blob(id.to_bytes()), &get_current_time_in_seconds().into())?;
+        }
+
+        Ok(())
+    }
+
+    // Forget the operations too old to be delivered again
+    pub(super) async fn prune_operations(&self) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let expired_before = get_current_time_in_seconds().saturating_sub(OPERATION_RETENTION.as_secs());
+        for key in storage.get_custom_tree_keys(&PROCESSED_OPERATIONS_TREE.to_string(), &None, None, None)? {
+            let processed_at = storage.get_custom_data(PROCESSED_OPERATIONS_TREE, &key)?.to_value()?.to_u64()?;
+            if processed_at < expired_before {
+                storage.delete_custom_data(PROCESSED_OPERATIONS_TREE, &key)?;
+            }
+        }
+
+        Ok(())
+    }
+}
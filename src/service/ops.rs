@@ -0,0 +1,110 @@
+use log::{error, warn};
+use poise::serenity_prelude::{ChannelId, Colour, CreateEmbed, CreateMessage, Http, UserId};
+use teloxide::types::ChatId;
+
+use super::{discord_id, WalletServiceImpl};
+use crate::{telegram_message::escape_html, ICON};
+
+// Longest value of an embed field
+const MAX_FIELD_LENGTH: usize = 1024;
+
+// Channels where the errors and alerts are forwarded for the operators
+#[derive(Debug, Clone, Copy)]
+pub struct OpsChannels {
+    pub discord: Option<ChannelId>,
+    pub telegram: Option<ChatId>
+}
+
+// Alert for the operators, sent as an embed on Discord and as a message on Telegram
+// Its texts are plain, they're escaped when sent on Telegram
+#[derive(Debug, Clone)]
+pub struct Alert {
+    title: String,
+    description: String,
+    // Name, value and inline of each field
+    fields: Vec<(String, String, bool)>,
+    colour: Colour
+}
+
+impl Alert {
+    pub fn new(title: &str, description: impl Into<String>, colour: impl Into<Colour>) -> Self {
+        Self {
+            title: title.to_string(),
+            description: description.into(),
+            fields: Vec::new(),
+            colour: colour.into()
+        }
+    }
+
+    pub fn field(mut self, name: &str, value: impl Into<String>, inline: bool) -> Self {
+        let value: String = value.into();
+        self.fields.push((name.to_string(), value.chars().take(MAX_FIELD_LENGTH).collect(), inline));
+        self
+    }
+
+    fn to_embed(&self) -> CreateEmbed {
+        let mut embed = CreateEmbed::default()
+            .title(self.title.as_str())
+            .description(self.description.as_str())
+            .thumbnail(ICON)
+            .colour(self.colour);
+        for (name, value, inline) in self.fields.iter() {
+            embed = embed.field(name.as_str(), value.as_str(), *inline);
+        }
+
+        embed
+    }
+
+    // Body of the Telegram message, the title is sent apart
+    fn to_telegram_line(&self) -> String {
+        let mut line = escape_html(&self.description);
+        for (name, value, _) in self.fields.iter() {
+            line.push_str(&format!("\n<strong>{}</strong> {}", escape_html(name), escape_html(value)));
+        }
+
+        line
+    }
+}
+
+impl WalletServiceImpl {
+    // Send an alert to every Discord operator in DM and in the ops channels
+    pub(super) async fn alert_operators(&self, http: &Http, alert: Alert) {
+        let embed = alert.to_embed();
+        for operator in self.operators.iter() {
+            let operator: UserId = match discord_id(*operator) {
+                Ok(operator) => operator,
+                Err(e) => {
+                    warn!("Invalid operator {}: {}", operator, e);
+                    continue;
+                }
+            };
+
+            let result = match operator.create_dm_channel(http).await {
+                Ok(channel) => channel.send_message(http, CreateMessage::default().embed(embed.clone())).await.map(|_| ()),
+                Err(e) => Err(e)
+            };
+
+            if let Err(e) = result {
+                error!("Error while alerting operator {}: {:?}", operator, e);
+            }
+        }
+
+        self.report_to_ops(http, &alert).await;
+    }
+
+    // Forward an error or an alert to the ops channels, in addition to the logs
+    // Nothing is sent when no ops channel is configured
+    pub async fn report_to_ops(&self, http: &Http, alert: &Alert) {
+        let ops_channels = self.runtime().ops_channels;
+        if let Some(channel) = ops_channels.discord {
+            // Not logged as an error, it would be reported again
+            if let Err(e) = channel.send_message(http, CreateMessage::default().embed(alert.to_embed())).await {
+                warn!("Couldn't report to the Discord ops channel {}: {}", channel, e);
+            }
+        }
+
+        if let Some(chat) = ops_channels.telegram {
+            self.notifier.notify(chat, None, &escape_html(&alert.title), alert.to_telegram_line()).await;
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use log::{debug, info, warn};
+use poise::serenity_prelude::{CreateEmbed, CreateMessage, Http};
+use serde_json::json;
+use teloxide::Bot;
+use xelis_common::{
+    api::{DataElement, DataValue},
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{discord_id, journal::JOURNAL_SEQUENCE_TREE, UserApplication, WalletServiceImpl};
+use crate::{
+    matrix_message::MatrixMessage,
+    slack_message::SlackMessage,
+    telegram_message::{escape_html, TelegramMessage},
+    twitch_message::TwitchMessage,
+    COLOR,
+    ICON
+};
+
+// DM notifications that couldn't be delivered, by id
+pub(super) const OUTBOX_TREE: &str = "notifications_outbox";
+// Key of the next notification id in the journal sequences
+const OUTBOX_SEQUENCE_KEY: &str = "next_notification";
+
+// How often the notifications due are sent again
+pub(super) const OUTBOX_RETRY_INTERVAL: Duration = Duration::from_secs(15);
+// Delay before the first retry, doubled after each failure
+const OUTBOX_BASE_DELAY: u64 = 30;
+// Longest delay between two retries
+const OUTBOX_MAX_DELAY: u64 = 6 * 60 * 60;
+// Attempts after which a notification is undeliverable, until an operator flushes it
+pub const MAX_NOTIFICATION_ATTEMPTS: u8 = 10;
+
+// A DM notification waiting to be delivered
+#[derive(Debug, Clone)]
+pub struct PendingNotification {
+    pub id: u64,
+    pub user: UserApplication,
+    pub title: String,
+    // Name and value of each field
+    pub fields: Vec<(String, String)>,
+    pub attempts: u8,
+    // Timestamps in seconds
+    pub created_at: u64,
+    pub next_attempt: u64,
+    pub last_error: String
+}
+
+impl PendingNotification {
+    pub fn is_undeliverable(&self) -> bool {
+        self.attempts >= MAX_NOTIFICATION_ATTEMPTS
+    }
+
+    // Delay before the next attempt once this one failed
+    fn backoff(&self) -> u64 {
+        OUTBOX_BASE_DELAY.saturating_mul(1 << self.attempts.min(16)).min(OUTBOX_MAX_DELAY)
+    }
+}
+
+impl Serializer for PendingNotification {
+    fn write(&self, writer: &mut Writer) {
+        self.id.write(writer);
+        self.user.write(writer);
+        self.title.write(writer);
+        writer.write_u8(self.fields.len() as u8);
+        for (name, value) in self.fields.iter() {
+            name.write(writer);
+            value.write(writer);
+        }
+        writer.write_u8(self.attempts);
+        self.created_at.write(writer);
+        self.next_attempt.write(writer);
+        self.last_error.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let id = reader.read_u64()?;
+        let user = UserApplication::read(reader)?;
+        let title = String::read(reader)?;
+        let len = reader.read_u8()?;
+        let mut fields = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            fields.push((String::read(reader)?, String::read(reader)?));
+        }
+        let attempts = reader.read_u8()?;
+        let created_at = reader.read_u64()?;
+        let next_attempt = reader.read_u64()?;
+        let last_error = String::read(reader)?;
+
+        Ok(Self {
+            id,
+            user,
+            title,
+            fields,
+            attempts,
+            created_at,
+            next_attempt,
+            last_error
+        })
+    }
+}
+
+impl WalletServiceImpl {
+    fn save_notification(&self, storage: &mut EncryptedStorage, notification: &PendingNotification) -> Result<()> {
+        storage.set_custom_data(OUTBOX_TREE, &DataValue::U64(notification.id), &DataElement::Value(DataValue::Blob(notification.to_bytes())))?;
+        Ok(())
+    }
+
+    fn get_notifications_internal(&self, storage: &EncryptedStorage) -> Result<Vec<PendingNotification>> {
+        let mut notifications = Vec::new();
+        for key in storage.get_custom_tree_keys(&OUTBOX_TREE.to_string(), &None, None, None)? {
+            let notification: PendingNotification = storage.get_custom_data(OUTBOX_TREE, &key)?.to_value()?.to_type()?;
+            notifications.push(notification);
+        }
+
+        notifications.sort_by_key(|notification| notification.id);
+
+        Ok(notifications)
+    }
+
+    // Keep a DM notification that failed, to send it again later
+    // Its fields are plain text, escaped when sent on Telegram
+    pub(super) async fn queue_notification(&self, user: &UserApplication, title: &str, fields: Vec<(String, String)>, error: &anyhow::Error) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let id = self.get_sequence_internal(&storage, OUTBOX_SEQUENCE_KEY);
+        let now = get_current_time_in_seconds();
+        let notification = PendingNotification {
+            id,
+            user: *user,
+            title: title.to_string(),
+            fields,
+            attempts: 1,
+            created_at: now,
+            next_attempt: now + OUTBOX_BASE_DELAY,
+            last_error: error.to_string()
+        };
+
+        debug!("Queueing notification {} for {:?}", id, user);
+        self.save_notification(&mut storage, &notification)?;
+        storage.set_custom_data(JOURNAL_SEQUENCE_TREE, &DataValue::String(OUTBOX_SEQUENCE_KEY.to_string()), &(id + 1).into())?;
+
+        Ok(())
+    }
+
+    // Send a DM notification, kept in the outbox to be sent again if it fails
+    pub(super) async fn notify_user(&self, http: &Http, bot: &Bot, user: &UserApplication, title: &str, fields: Vec<(String, String)>) -> Result<()> {
+        if let Err(e) = self.send_notification(http, bot, user, title, &fields).await {
+            self.api_errors.record_error(&e);
+            warn!("Couldn't notify {:?}, the notification is queued: {}", user, e);
+            self.queue_notification(user, title, fields, &e).await?;
+        }
+
+        Ok(())
+    }
+
+    // Send a DM notification on the platform of the user, nothing is kept if it fails
+    // Every DM of the bot goes through here, its fields are plain text escaped when sent on Telegram
+    pub(super) async fn send_notification(&self, http: &Http, bot: &Bot, user: &UserApplication, title: &str, fields: &[(String, String)]) -> Result<()> {
+        match *user {
+            UserApplication::Discord(user_id) => {
+                let user = http.get_user(discord_id(user_id)?).await?;
+                let channel = user.create_dm_channel(&http).await?;
+
+                let mut embed = CreateEmbed::default()
+                    .title(title)
+                    .thumbnail(ICON)
+                    .colour(COLOR);
+                for (name, value) in fields.iter() {
+                    embed = embed.field(name.as_str(), value.as_str(), false);
+                }
+
+                channel.send_message(&http, CreateMessage::default().embed(embed)).await?;
+            },
+            UserApplication::Telegram(user_id) => self.send_telegram_notification(bot, user_id, title, fields).await?,
+            UserApplication::Matrix(user_id) => self.send_matrix_notification(user_id, title, fields).await?,
+            UserApplication::Slack(user_id) => self.send_slack_notification(user_id, title, fields).await?,
+            UserApplication::Twitch(user_id) => self.send_twitch_notification(user_id, title, fields).await?
+        }
+
+        Ok(())
+    }
+
+    async fn send_telegram_notification(&self, bot: &Bot, user_id: u64, title: &str, fields: &[(String, String)]) -> Result<()> {
+        let mut message = TelegramMessage::new(bot, self.get_telegram_dm_chat(user_id).await?, None);
+        message.title(&escape_html(title));
+        for (name, value) in fields.iter() {
+            message.field(&escape_html(name), escape_html(value), false);
+        }
+
+        message.send().await?;
+        Ok(())
+    }
+
+    // Send a DM to a Matrix user, its fields are plain text
+    async fn send_matrix_notification(&self, id: u64, title: &str, fields: &[(String, String)]) -> Result<()> {
+        let Some(user_id) = self.get_matrix_user_id(id).await? else {
+            bail!("Unknown Matrix user {}", id);
+        };
+
+        let mut message = MatrixMessage::new().title(title);
+        for (name, value) in fields {
+            message = message.field(name, value);
+        }
+
+        let room = self.get_matrix_dm_room(&user_id).await?;
+        room.send(message.into_content()).await?;
+
+        Ok(())
+    }
+
+    // Send a DM to a Slack user from the bot of its workspace, its fields are plain text
+    async fn send_slack_notification(&self, id: u64, title: &str, fields: &[(String, String)]) -> Result<()> {
+        let Some(user) = self.get_slack_user(id).await? else {
+            bail!("Unknown Slack user {}", id);
+        };
+        let Some(workspace) = self.get_slack_workspace(&user.team_id).await? else {
+            bail!("The Slack workspace {} of {} uninstalled the app", user.team_id, user.user_id);
+        };
+
+        let mut message = SlackMessage::new().title(title);
+        for (name, value) in fields {
+            message = message.field(name, value);
+        }
+
+        let api = self.get_slack_api()?;
+        let channel = api.call(&workspace.bot_token, "conversations.open", &json!({ "users": user.user_id })).await?;
+        let channel = channel["channel"]["id"].as_str().ok_or_else(|| anyhow!("Slack didn't open a DM with {}", user.user_id))?;
+        api.call(&workspace.bot_token, "chat.postMessage", &json!({ "channel": channel, "text": message.into_text() })).await?;
+
+        Ok(())
+    }
+
+    // Whisper a Twitch user from the bot account, its fields are plain text
+    // The whisper is cut at 500 characters, so the most important fields come first
+    async fn send_twitch_notification(&self, id: u64, title: &str, fields: &[(String, String)]) -> Result<()> {
+        let mut message = TwitchMessage::new().title(title);
+        for (name, value) in fields {
+            message = message.field(name, value);
+        }
+
+        self.get_twitch_api()?.whisper(id, &message.into_text()).await?;
+        Ok(())
+    }
+
+    // A Telegram user must write to the bot in private before it can be sent a DM
+    fn is_deferred(&self, storage: &EncryptedStorage, notification: &PendingNotification) -> bool {
+        match notification.user {
+            UserApplication::Telegram(user_id) => !self.is_telegram_registered(storage, user_id),
+            UserApplication::Discord(_) | UserApplication::Matrix(_) | UserApplication::Slack(_) | UserApplication::Twitch(_) => false
+        }
+    }
+
+    // Send again the notifications due, with an exponential backoff between the attempts
+    // Notifications deferred until their Telegram user writes to the bot are left as is
+    pub(super) async fn retry_notifications(&self, http: &Http, bot: &Bot) -> Result<()> {
+        let now = get_current_time_in_seconds();
+        let due: Vec<PendingNotification> = {
+            let storage = self.wallet.get_storage().read().await;
+            self.get_notifications_internal(&storage)?
+                .into_iter()
+                .filter(|notification| !notification.is_undeliverable() && notification.next_attempt <= now)
+                .filter(|notification| !self.is_deferred(&storage, notification))
+                .collect()
+        };
+
+        for mut notification in due {
+            let result = self.send_notification(http, bot, &notification.user, &notification.title, &notification.fields).await;
+            let mut storage = self.wallet.get_storage().write().await;
+            match result {
+                Ok(()) => {
+                    info!("Notification {} delivered to {:?} after {} attempts", notification.id, notification.user, notification.attempts + 1);
+                    storage.delete_custom_data(OUTBOX_TREE, &DataValue::U64(notification.id))?;
+                },
+                Err(e) => {
+                    notification.next_attempt = get_current_time_in_seconds() + notification.backoff();
+                    notification.attempts += 1;
+                    notification.last_error = e.to_string();
+                    if notification.is_undeliverable() {
+                        warn!("Notification {} for {:?} is undeliverable: {}", notification.id, notification.user, e);
+                    }
+                    self.save_notification(&mut storage, &notification)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Deliver the notifications deferred until a Telegram user wrote to the bot
+    pub async fn deliver_deferred_notifications(&self, bot: &Bot, user_id: u64) -> Result<()> {
+        let user = UserApplication::Telegram(user_id);
+        let notifications: Vec<PendingNotification> = {
+            let storage = self.wallet.get_storage().read().await;
+            self.get_notifications_internal(&storage)?
+                .into_iter()
+                .filter(|notification| notification.user == user)
+                .collect()
+        };
+
+        for notification in notifications {
+            match self.send_telegram_notification(bot, user_id, &notification.title, &notification.fields).await {
+                Ok(()) => {
+                    info!("Deferred notification {} delivered to {:?}", notification.id, user);
+                    let mut storage = self.wallet.get_storage().write().await;
+                    storage.delete_custom_data(OUTBOX_TREE, &DataValue::U64(notification.id))?;
+                },
+                Err(e) => warn!("Couldn't deliver the deferred notification {} to {:?}: {}", notification.id, user, e)
+            }
+        }
+
+        Ok(())
+    }
+
+    // Get the notifications waiting to be delivered, oldest first
+    // Each one comes with true if it waits for its Telegram user to write to the bot
+    pub async fn get_queued_notifications(&self) -> Result<Vec<(PendingNotification, bool)>> {
+        let storage = self.wallet.get_storage().read().await;
+        let notifications = self.get_notifications_internal(&storage)?
+            .into_iter()
+            .map(|notification| {
+                let deferred = self.is_deferred(&storage, &notification);
+                (notification, deferred)
+            })
+            .collect();
+
+        Ok(notifications)
+    }
+
+    // Send every queued notification again at the next retry, the undeliverable ones included
+    // Returns the number of notifications rescheduled
+    pub async fn flush_notifications(&self) -> Result<usize> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let notifications = self.get_notifications_internal(&storage)?;
+        for mut notification in notifications.iter().cloned() {
+            notification.attempts = 0;
+            notification.next_attempt = 0;
+            self.save_notification(&mut storage, &notification)?;
+        }
+
+        Ok(notifications.len())
+    }
+
+    // Delete the undeliverable notifications, returns how many were deleted
+    pub async fn drop_undeliverable_notifications(&self) -> Result<usize> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let mut dropped = 0;
+        for notification in self.get_notifications_internal(&storage)? {
+            if notification.is_undeliverable() {
+                storage.delete_custom_data(OUTBOX_TREE, &DataValue::U64(notification.id))?;
+                dropped += 1;
+            }
+        }
+
+        Ok(dropped)
+    }
+}
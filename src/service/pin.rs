@@ -0,0 +1,154 @@
+use anyhow::Result;
+use rand::RngCore;
+use xelis_common::{
+    api::DataValue,
+    crypto::{hash, Hash},
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{ServiceError, UserApplication, WalletServiceImpl};
+
+// Withdrawal PIN of each user, salted and hashed
+pub(super) const WITHDRAW_PINS_TREE: &str = "withdraw_pins";
+
+// Digits of a PIN
+pub const MIN_PIN_LENGTH: usize = 4;
+pub const MAX_PIN_LENGTH: usize = 12;
+// Wrong PINs in a row before the withdrawals of the user are locked
+const MAX_PIN_ATTEMPTS: u8 = 5;
+// Seconds the withdrawals stay locked after too many wrong PINs
+const PIN_LOCKOUT: u64 = 60 * 60;
+// Hash rounds, so a leaked storage doesn't give the PINs away at once
+const PIN_HASH_ROUNDS: u32 = 100_000;
+
+// PIN of a user, as stored
+pub(super) struct PinEntry {
+    salt: Hash,
+    hash: Hash,
+    // Wrong PINs given since the last right one
+    failures: u8,
+    // Timestamp in seconds until which the PIN is not checked anymore
+    locked_until: u64
+}
+
+impl Serializer for PinEntry {
+    fn write(&self, writer: &mut Writer) {
+        self.salt.write(writer);
+        self.hash.write(writer);
+        writer.write_u8(self.failures);
+        self.locked_until.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        Ok(Self {
+            salt: Hash::read(reader)?,
+            hash: Hash::read(reader)?,
+            failures: reader.read_u8()?,
+            locked_until: reader.read_u64()?
+        })
+    }
+}
+
+// hash(salt || pin), then hashed again with the salt for each round
+fn hash_pin(salt: &Hash, pin: &str) -> Hash {
+    let mut bytes = salt.as_bytes().to_vec();
+    bytes.extend_from_slice(pin.as_bytes());
+    let mut result = hash(&bytes);
+    for _ in 1..PIN_HASH_ROUNDS {
+        let mut bytes = salt.as_bytes().to_vec();
+        bytes.extend_from_slice(result.as_bytes());
+        result = hash(&bytes);
+    }
+
+    result
+}
+
+fn check_pin_format(pin: &str) -> Result<(), ServiceError> {
+    if pin.len() < MIN_PIN_LENGTH || pin.len() > MAX_PIN_LENGTH || !pin.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ServiceError::InvalidPin(MIN_PIN_LENGTH, MAX_PIN_LENGTH));
+    }
+
+    Ok(())
+}
+
+impl WalletServiceImpl {
+    fn get_pin_entry(&self, storage: &EncryptedStorage, user: &UserApplication) -> Option<PinEntry> {
+        storage.get_custom_data(WITHDRAW_PINS_TREE, &user.into())
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_type().ok())
+    }
+
+    // Check if the withdrawals of a user require a PIN
+    pub async fn has_withdraw_pin(&self, user: &UserApplication) -> bool {
+        let storage = self.wallet.get_storage().read().await;
+        self.get_pin_entry(&storage, user).is_some()
+    }
+
+    // Check the PIN of a user, counting the wrong ones
+    // Too many wrong PINs in a row lock its withdrawals for a while
+    pub(super) async fn verify_withdraw_pin(&self, user: &UserApplication, pin: Option<&str>) -> Result<(), ServiceError> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let Some(mut entry) = self.get_pin_entry(&storage, user) else {
+            return Ok(());
+        };
+
+        let now = get_current_time_in_seconds();
+        if entry.locked_until > now {
+            return Err(ServiceError::PinLocked(entry.locked_until - now));
+        }
+
+        let Some(pin) = pin else {
+            return Err(ServiceError::PinRequired);
+        };
+
+        let result = if hash_pin(&entry.salt, pin.trim()) == entry.hash {
+            entry.failures = 0;
+            Ok(())
+        } else {
+            entry.failures += 1;
+            if entry.failures >= MAX_PIN_ATTEMPTS {
+                entry.failures = 0;
+                entry.locked_until = now + PIN_LOCKOUT;
+                Err(ServiceError::PinLocked(PIN_LOCKOUT))
+            } else {
+                Err(ServiceError::WrongPin(MAX_PIN_ATTEMPTS - entry.failures))
+            }
+        };
+
+        storage.set_custom_data(WITHDRAW_PINS_TREE, &user.into(), &DataValue::Blob(entry.to_bytes()).into())?;
+
+        result
+    }
+
+    // Set, change or remove the withdrawal PIN of a user
+    // The current PIN is required to change or remove it
+    pub async fn set_withdraw_pin(&self, user: &UserApplication, current: Option<&str>, pin: Option<&str>) -> Result<(), ServiceError> {
+        if let Some(pin) = pin {
+            check_pin_format(pin)?;
+        }
+
+        self.verify_withdraw_pin(user, current).await?;
+
+        let mut storage = self.wallet.get_storage().write().await;
+        match pin {
+            Some(pin) => {
+                let mut salt = [0u8; 32];
+                rand::thread_rng().fill_bytes(&mut salt);
+                let salt = Hash::new(salt);
+                let entry = PinEntry {
+                    hash: hash_pin(&salt, pin),
+                    salt,
+                    failures: 0,
+                    locked_until: 0
+                };
+                storage.set_custom_data(WITHDRAW_PINS_TREE, &user.into(), &DataValue::Blob(entry.to_bytes()).into())?;
+            },
+            None => storage.delete_custom_data(WITHDRAW_PINS_TREE, &user.into())?
+        };
+
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::Result;
+use log::debug;
+use xelis_common::{
+    config::COIN_VALUE,
+    utils::format_xelis
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{ServiceError, UserApplication, WalletServiceImpl};
+
+// Sandbox balances, kept apart from the real ones
+// Nothing in this tree is backed by the wallet
+pub(super) const PRACTICE_TREE: &str = "practice_balances";
+
+// Balance given to a user the first time the practice mode is used
+pub const PRACTICE_BALANCE: u64 = 10 * COIN_VALUE;
+// Fee simulated on practice withdrawals
+pub const PRACTICE_WITHDRAW_FEE: u64 = COIN_VALUE / 10000;
+
+impl WalletServiceImpl {
+    fn get_practice_balance_internal(&self, storage: &EncryptedStorage, user: &UserApplication) -> u64 {
+        storage.get_custom_data(PRACTICE_TREE, &user.into())
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_u64().ok())
+            .unwrap_or(PRACTICE_BALANCE)
+    }
+
+    // Get the sandbox balance of a user
+    pub async fn get_practice_balance(&self, user: &UserApplication) -> u64 {
+        let storage = self.wallet.get_storage().read().await;
+        self.get_practice_balance_internal(&storage, user)
+    }
+
+    // Tip another user with the sandbox balance
+    // Returns the new sandbox balance of the author
+    pub async fn practice_tip(&self, from: &UserApplication, to: &UserApplication, amount: u64) -> Result<u64, ServiceError> {
+        if amount == 0 {
+            return Err(ServiceError::Zero);
+        }
+
+        if from == to {
+            return Err(ServiceError::SelfTip);
+        }
+
+        let mut storage = self.wallet.get_storage().write().await;
+        let from_balance = self.get_practice_balance_internal(&storage, from);
+        if amount > from_balance {
+            return Err(ServiceError::NotEnoughFunds(amount));
+        }
+
+        let to_balance = self.get_practice_balance_internal(&storage, to);
+        storage.set_custom_data(PRACTICE_TREE, &from.into(), &(from_balance - amount).into())?;
+        storage.set_custom_data(PRACTICE_TREE, &to.into(), &(to_balance + amount).into())?;
+
+        debug!("Practice tip of {} XEL from {:?} to {:?}", format_xelis(amount), from, to);
+
+        Ok(from_balance - amount)
+    }
+
+    // Simulate a withdrawal, no transaction is built
+    // Returns the new sandbox balance of the user
+    pub async fn practice_withdraw(&self, user: &UserApplication, amount: u64) -> Result<u64, ServiceError> {
+        if amount == 0 {
+            return Err(ServiceError::Zero);
+        }
+
+        let mut storage = self.wallet.get_storage().write().await;
+        let balance = self.get_practice_balance_internal(&storage, user);
+        if amount > balance {
+            return Err(ServiceError::NotEnoughFunds(amount));
+        }
+
+        if amount + PRACTICE_WITHDRAW_FEE > balance {
+            return Err(ServiceError::NotEnoughFundsForFee(PRACTICE_WITHDRAW_FEE));
+        }
+
+        let balance = balance - amount - PRACTICE_WITHDRAW_FEE;
+        storage.set_custom_data(PRACTICE_TREE, &user.into(), &balance.into())?;
+
+        debug!("Practice withdraw of {} XEL by {:?}", format_xelis(amount), user);
+
+        Ok(balance)
+    }
+
+    // Give the starting sandbox balance back to a user
+    pub async fn reset_practice(&self, user: &UserApplication) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        storage.delete_custom_data(PRACTICE_TREE, &user.into())?;
+
+        Ok(())
+    }
+}
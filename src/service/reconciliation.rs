@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use log::warn;
+use xelis_common::{
+    api::{wallet::EntryType, DataValue},
+    crypto::Hash,
+    serializer::Serializer
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{
+    ledger::{self, LedgerAccount},
+    BalanceKey,
+    MovementKind,
+    ServiceError,
+    UserApplication,
+    WalletServiceImpl,
+    BALANCES_TREE,
+    HISTORY_TREE
+};
+
+// Note of the journal entry of a stored balance set back to the ledger
+const REPAIR_NOTE: &str = "Balance repaired from the ledger";
+
+// Balance of a user in an asset, recomputed from the ledger and the deposits history
+#[derive(Debug, Clone)]
+pub struct Reconciliation {
+    pub user: UserApplication,
+    pub asset: Hash,
+    pub stored: u64,
+    // Derived from the ledger entries of the user
+    pub ledger: i128,
+    // Amounts of the deposits recorded since the ledger was opened
+    pub deposits: u64,
+    // Deposits credited in the ledger
+    pub credited: u64
+}
+
+impl Reconciliation {
+    pub fn is_balance_mismatch(&self) -> bool {
+        self.ledger != self.stored as i128
+    }
+
+    pub fn is_deposits_mismatch(&self) -> bool {
+        self.deposits != self.credited
+    }
+
+    // The stored balance can be set back to the ledger one
+    pub fn is_repairable(&self) -> bool {
+        self.is_balance_mismatch() && self.ledger >= 0
+    }
+}
+
+// Balance of a user in an asset, created empty on first use
+fn reconciliation_of(balances: &mut HashMap<(UserApplication, Hash), Reconciliation>, user: UserApplication, asset: &Hash) -> &mut Reconciliation {
+    balances.entry((user, asset.clone())).or_insert_with(|| Reconciliation {
+        user,
+        asset: asset.clone(),
+        stored: 0,
+        ledger: 0,
+        deposits: 0,
+        credited: 0
+    })
+}
+
+impl WalletServiceImpl {
+    // Recompute every user balance and list the ones that don't match
+    fn reconcile_balances_internal(&self, storage: &EncryptedStorage) -> Result<Vec<Reconciliation>> {
+        let mut balances = HashMap::new();
+
+        for key in storage.get_custom_tree_keys(&BALANCES_TREE.to_string(), &None, None, None)? {
+            let balance_key: BalanceKey = key.as_type()?;
+            reconciliation_of(&mut balances, balance_key.user, &balance_key.asset).stored = storage.get_custom_data(BALANCES_TREE, &key)?.to_value()?.to_u64()?;
+        }
+
+        let entries = self.get_ledger_entries_internal(storage)?;
+        for ((account, asset), balance) in ledger::derive_balances(&entries) {
+            if let LedgerAccount::User(user) = account {
+                reconciliation_of(&mut balances, user, &asset).ledger = balance;
+            }
+        }
+
+        for entry in entries.iter() {
+            match (entry.kind, entry.from, entry.to) {
+                (MovementKind::Deposit, LedgerAccount::Wallet, LedgerAccount::User(user)) => {
+                    reconciliation_of(&mut balances, user, &entry.asset).credited += entry.amount;
+                },
+                // Deposits orphaned by a reorg are no longer in the deposits history
+                (MovementKind::Reorg, LedgerAccount::User(user), LedgerAccount::Wallet) => {
+                    let balance = reconciliation_of(&mut balances, user, &entry.asset);
+                    balance.credited = balance.credited.saturating_sub(entry.amount);
+                },
+                _ => {}
+            }
+        }
+
+        // Deposits confirmed before the ledger are part of the opening balances
+        let opened_at = self.get_ledger_opened_at(storage);
+        for key in storage.get_custom_tree_keys(&HISTORY_TREE.to_string(), &None, None, None)? {
+            let owner: UserApplication = storage.get_custom_data(HISTORY_TREE, &key)?.to_value()?.to_type()?;
+            let tx_hash = key.as_hash()?;
+            let transaction = match storage.get_transaction(tx_hash) {
+                Ok(transaction) => transaction,
+                Err(e) => {
+                    warn!("Couldn't load the deposit TX {} of {:?}: {}", tx_hash, owner, e);
+                    continue;
+                }
+            };
+
+            if transaction.topoheight <= opened_at {
+                continue;
+            }
+
+            let EntryType::Incoming { transfers, .. } = transaction.entry else {
+                continue;
+            };
+
+            for transfer in transfers {
+                let recipient = transfer.extra_data.as_ref()
+                    .and_then(|data| data.data())
+                    .and_then(|v| v.as_value().and_then(|v| v.as_type::<UserApplication>()).ok());
+                if recipient == Some(owner) && self.get_returned_transfer(storage, tx_hash, &transfer.asset)?.is_none() {
+                    reconciliation_of(&mut balances, owner, &transfer.asset).deposits += transfer.amount;
+                }
+            }
+        }
+
+        let mut mismatches: Vec<Reconciliation> = balances.into_values()
+            .filter(|balance| balance.is_balance_mismatch() || balance.is_deposits_mismatch())
+            .collect();
+        mismatches.sort_by_key(|balance| (balance.user.to_bytes(), balance.asset.to_bytes()));
+
+        Ok(mismatches)
+    }
+
+    // Get the user balances that don't match their ledger or deposits
+    pub async fn reconcile_balances(&self) -> Result<Vec<Reconciliation>> {
+        let storage = self.wallet.get_storage().read().await;
+        self.reconcile_balances_internal(&storage)
+    }
+
+    // Set the stored balances back to the ones derived from the ledger
+    // Deposits missing from the ledger are left to the operator, as they may have been returned
+    // Returns the balances repaired
+    pub async fn repair_balances(&self) -> Result<Vec<Reconciliation>, ServiceError> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let mut repaired = Vec::new();
+        for balance in self.reconcile_balances_internal(&storage)? {
+            if !balance.is_repairable() {
+                continue;
+            }
+
+            // The ledger already holds the change, only the journal and the audit log follow it
+            let after = balance.ledger as u64;
+            let key = BalanceKey { user: balance.user, asset: balance.asset.clone() };
+            warn!("Repairing the balance of {:?} in {} from {} to {}", balance.user, balance.asset, balance.stored, after);
+            let id = self.get_next_movement_id(&storage);
+            storage.set_custom_data(BALANCES_TREE, &DataValue::Blob(key.to_bytes()), &after.into())?;
+            self.record_movement(&mut storage, &balance.user, &balance.asset, MovementKind::Admin, balance.stored, after)?;
+            self.record_movement_note(&mut storage, id, REPAIR_NOTE)?;
+            repaired.push(balance);
+        }
+
+        Ok(repaired)
+    }
+}
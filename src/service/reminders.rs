@@ -0,0 +1,171 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Result;
+use log::{debug, info, warn};
+use poise::serenity_prelude::Http;
+use teloxide::Bot;
+use xelis_common::{
+    api::DataValue,
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{AssetBalance, BalanceKey, UserApplication, WalletServiceImpl, BALANCES_TREE};
+
+// Last time each user used a command
+pub(super) const LAST_SEEN_TREE: &str = "last_seen";
+// Last reminder sent to each user
+pub(super) const REMINDERS_TREE: &str = "reminders";
+// Users that don't want to be reminded of their balance
+pub(super) const REMINDERS_OPT_OUT_TREE: &str = "reminders_opt_out";
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReminderConfig {
+    // Time without any command before a user holding a balance is reminded
+    pub inactivity: Duration,
+    // How often the dormant balances are checked
+    pub interval: Duration
+}
+
+impl WalletServiceImpl {
+    fn get_timestamp_internal(&self, storage: &EncryptedStorage, tree: &str, user: &UserApplication) -> Option<u64> {
+        storage.get_custom_data(tree, &user.into())
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_u64().ok())
+    }
+
+    // Remember that a user just used the bot
+    pub async fn record_user_activity(&self, user: &UserApplication) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        storage.set_custom_data(LAST_SEEN_TREE, &user.into(), &get_current_time_in_seconds().into())?;
+
+        Ok(())
+    }
+
+    // Enable or disable the reminders of a user
+    pub async fn set_reminders_enabled(&self, user: &UserApplication, enabled: bool) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        if enabled {
+            storage.delete_custom_data(REMINDERS_OPT_OUT_TREE, &user.into())?;
+        } else {
+            storage.set_custom_data(REMINDERS_OPT_OUT_TREE, &user.into(), &DataValue::Bool(true).into())?;
+        }
+
+        Ok(())
+    }
+
+    // Check if a user accepts the reminders
+    pub async fn are_reminders_enabled(&self, user: &UserApplication) -> bool {
+        let storage = self.wallet.get_storage().read().await;
+        !storage.has_custom_data(REMINDERS_OPT_OUT_TREE, &user.into()).unwrap_or(false)
+    }
+
+    // Every balance held by the users, with the last time each user used the bot if known
+    pub async fn get_balances_with_activity(&self) -> Result<Vec<(UserApplication, AssetBalance, Option<u64>)>> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut balances = Vec::new();
+        for key in storage.get_custom_tree_keys(&BALANCES_TREE.to_string(), &None, None, None)? {
+            let key: BalanceKey = key.as_type()?;
+            let amount = self.get_asset_balance_internal(&storage, &key.user, &key.asset);
+            if amount > 0 {
+                let balance = self.get_asset_balance_display(&storage, &key.asset, amount).await;
+                let last_seen = self.get_timestamp_internal(&storage, LAST_SEEN_TREE, &key.user);
+                balances.push((key.user, balance, last_seen));
+            }
+        }
+
+        Ok(balances)
+    }
+
+    // Find the users holding a balance that didn't use the bot for too long
+    // A user is reminded again only after another full period of inactivity
+    async fn get_dormant_users(&self, config: &ReminderConfig) -> Result<Vec<(UserApplication, Vec<AssetBalance>)>> {
+        let now = get_current_time_in_seconds();
+        let inactivity = config.inactivity.as_secs();
+        let mut storage = self.wallet.get_storage().write().await;
+
+        let mut balances: HashMap<UserApplication, Vec<AssetBalance>> = HashMap::new();
+        for key in storage.get_custom_tree_keys(&BALANCES_TREE.to_string(), &None, None, None)? {
+            let key: BalanceKey = key.as_type()?;
+            let amount = self.get_asset_balance_internal(&storage, &key.user, &key.asset);
+            if amount > 0 {
+                let balance = self.get_asset_balance_display(&storage, &key.asset, amount).await;
+                balances.entry(key.user).or_default().push(balance);
+            }
+        }
+
+        let mut dormant = Vec::new();
+        for (user, balances) in balances {
+            if storage.has_custom_data(REMINDERS_OPT_OUT_TREE, &(&user).into())? {
+                continue;
+            }
+
+            // Users from before the activity was tracked start counting from now
+            let Some(last_seen) = self.get_timestamp_internal(&storage, LAST_SEEN_TREE, &user) else {
+                storage.set_custom_data(LAST_SEEN_TREE, &(&user).into(), &now.into())?;
+                continue;
+            };
+
+            let last_reminder = self.get_timestamp_internal(&storage, REMINDERS_TREE, &user).unwrap_or(0);
+            if now.saturating_sub(last_seen.max(last_reminder)) >= inactivity {
+                dormant.push((user, balances));
+            }
+        }
+
+        Ok(dormant)
+    }
+
+    // Remind the dormant users in DM that their funds are held by the bot
+    // Users of a platform whose errors spike are reminded on a later round
+    pub(super) async fn send_dormant_reminders(&self, config: &ReminderConfig, http: &Http, bot: &Bot) -> Result<()> {
+        let dormant = self.get_dormant_users(config).await?;
+        if dormant.is_empty() {
+            return Ok(());
+        }
+
+        info!("Reminding {} dormant users of their balance", dormant.len());
+        let days = config.inactivity.as_secs() / (60 * 60 * 24);
+        for (user, balances) in dormant {
+            let platform = user.platform();
+            if self.api_errors.is_backing_off(platform) {
+                debug!("Postponing the reminder of {:?}, {} API errors are spiking", user, platform);
+                continue;
+            }
+
+            if let Err(e) = self.notify_dormant_user(http, bot, &user, days, &balances).await {
+                self.api_errors.record_error(&e);
+                warn!("Couldn't remind {:?} of its balance: {}", user, e);
+                continue;
+            }
+
+            debug!("Reminded {:?} of its balance", user);
+            let mut storage = self.wallet.get_storage().write().await;
+            storage.set_custom_data(REMINDERS_TREE, &(&user).into(), &get_current_time_in_seconds().into())?;
+        }
+
+        Ok(())
+    }
+
+    async fn notify_dormant_user(&self, http: &Http, bot: &Bot, user: &UserApplication, days: u64, balances: &[AssetBalance]) -> Result<()> {
+        let balances = balances.iter()
+            .map(|balance| balance.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        // Commands as the user types them on its platform
+        let (withdraw, stop) = match user {
+            UserApplication::Discord(_) => ("/withdraw", "/reminders enabled:False"),
+            UserApplication::Telegram(_) => ("/withdraw", "/reminders off"),
+            UserApplication::Matrix(_) | UserApplication::Twitch(_) => ("!withdraw", "!reminders off"),
+            UserApplication::Slack(_) => ("/xelis withdraw", "/xelis reminders off")
+        };
+
+        let fields = vec![
+            (format!("You haven't used the bot for this many days and still hold funds, consider withdrawing them to your own wallet with {}", withdraw), days.to_string()),
+            ("Your balance is".to_string(), balances),
+            ("Stop these reminders".to_string(), stop.to_string())
+        ];
+        self.send_notification(http, bot, user, "Balance reminder", &fields).await
+    }
+}
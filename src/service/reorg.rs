@@ -0,0 +1,281 @@
+use anyhow::Result;
+use log::{error, warn};
+use poise::serenity_prelude::{Colour, Http};
+use teloxide::Bot;
+use xelis_common::{
+    api::{wallet::EntryType, DataElement, DataValue},
+    crypto::Hash,
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+
+use super::{
+    batch::BalanceUpdate,
+    deposits::RETURNED_DEPOSITS_TREE,
+    ops::Alert,
+    MovementKind,
+    UserApplication,
+    WalletServiceImpl,
+    HISTORY_TREE
+};
+
+// Deposits credited then orphaned by a reorg, by TX hash
+pub(super) const ORPHANED_DEPOSITS_TREE: &str = "orphaned_deposits";
+
+// Credit of a deposit in an asset, taken back from the user
+#[derive(Debug, Clone)]
+pub(super) struct ReversedTransfer {
+    pub asset: Hash,
+    pub amount: u64,
+    // Part of the amount the balance couldn't cover, already spent by the user
+    pub shortfall: u64
+}
+
+impl Serializer for ReversedTransfer {
+    fn write(&self, writer: &mut Writer) {
+        self.asset.write(writer);
+        self.amount.write(writer);
+        self.shortfall.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        Ok(Self {
+            asset: Hash::read(reader)?,
+            amount: reader.read_u64()?,
+            shortfall: reader.read_u64()?
+        })
+    }
+}
+
+// A credited deposit whose TX was orphaned, with the credits taken back
+#[derive(Debug, Clone)]
+pub(super) struct DepositReversal {
+    pub tx_hash: Hash,
+    pub user: UserApplication,
+    pub transfers: Vec<ReversedTransfer>,
+    // Timestamp in seconds
+    pub reverted_at: u64
+}
+
+impl DepositReversal {
+    // The user spent part of the deposit, its account is frozen
+    pub fn has_shortfall(&self) -> bool {
+        self.transfers.iter().any(|transfer| transfer.shortfall > 0)
+    }
+}
+
+impl Serializer for DepositReversal {
+    fn write(&self, writer: &mut Writer) {
+        self.tx_hash.write(writer);
+        self.user.write(writer);
+        (self.transfers.len() as u16).write(writer);
+        for transfer in self.transfers.iter() {
+            transfer.write(writer);
+        }
+        self.reverted_at.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let tx_hash = Hash::read(reader)?;
+        let user = UserApplication::read(reader)?;
+        let len = reader.read_u16()?;
+        let mut transfers = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            transfers.push(ReversedTransfer::read(reader)?);
+        }
+        let reverted_at = reader.read_u64()?;
+
+        Ok(Self {
+            tx_hash,
+            user,
+            transfers,
+            reverted_at
+        })
+    }
+}
+
+// Reversals of the users credited by an orphaned deposit, a TX can credit several users
+#[derive(Debug, Clone)]
+pub(super) struct OrphanedDeposit {
+    pub reversals: Vec<DepositReversal>
+}
+
+impl Serializer for OrphanedDeposit {
+    fn write(&self, writer: &mut Writer) {
+        (self.reversals.len() as u16).write(writer);
+        for reversal in self.reversals.iter() {
+            reversal.write(writer);
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let len = reader.read_u16()?;
+        let mut reversals = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            reversals.push(DepositReversal::read(reader)?);
+        }
+
+        Ok(Self {
+            reversals
+        })
+    }
+}
+
+impl WalletServiceImpl {
+    // Take back the credits of a deposit whose TX was orphaned by a reorg, from every user it credited
+    // The balances are debited as far as they go, a user who already spent the deposit is frozen
+    // The TX is removed from the deposits history, so it's credited again if it's included in another block
+    // Returns the reversal of each user, none if the TX wasn't a credited deposit
+    pub(super) async fn revert_orphaned_deposit(&self, http: &Http, tx_hash: &Hash) -> Result<Vec<DepositReversal>> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let key: DataValue = tx_hash.clone().into();
+        let returned = storage.has_custom_data(RETURNED_DEPOSITS_TREE, &key)?;
+        if returned || self.has_returned_transfers(&storage, tx_hash)? {
+            error!("Deposit TX {} was returned to its sender but has been orphaned, the returned funds are lost", tx_hash);
+            drop(storage);
+            self.report_to_ops(http, &Alert::new("Returned Deposit Orphaned", format!("Deposit TX {} was returned to its sender but has been orphaned, the returned funds are lost", tx_hash), Colour::RED)).await;
+            if returned {
+                return Ok(Vec::new());
+            }
+
+            // The transfers credited along the returned ones are still taken back
+            storage = self.wallet.get_storage().write().await;
+        }
+
+        // Funds that were never credited only leave the unknown deposits
+        self.remove_unknown_deposit(&mut storage, tx_hash)?;
+
+        if !storage.has_custom_data(HISTORY_TREE, &key)? {
+            return Ok(Vec::new());
+        }
+
+        let owner: UserApplication = storage.get_custom_data(HISTORY_TREE, &key)?.to_value()?.to_type()?;
+        let transaction = match storage.get_transaction(tx_hash) {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                error!("Deposit TX {} of {:?} was orphaned but can't be loaded, check its credit with the audit command: {}", tx_hash, owner, e);
+                drop(storage);
+                let alert = Alert::new("Orphaned Deposit Unreadable", format!("Deposit TX {} was orphaned but can't be loaded, check its credit with the audit command", tx_hash), Colour::RED)
+                    .field("User", format!("{:?}", owner), false)
+                    .field("Error", e.to_string(), false);
+                self.report_to_ops(http, &alert).await;
+                return Ok(Vec::new());
+            }
+        };
+
+        let EntryType::Incoming { transfers, .. } = transaction.entry else {
+            return Ok(Vec::new());
+        };
+
+        // The history only keeps the first user credited, every transfer credited is taken back
+        let reverted_at = get_current_time_in_seconds();
+        let mut reversals: Vec<DepositReversal> = Vec::new();
+        let mut updates: Vec<BalanceUpdate> = Vec::new();
+        for transfer in transfers {
+            let recipient = transfer.extra_data.as_ref()
+                .and_then(|data| data.data())
+                .and_then(|v| v.as_value().and_then(|v| v.as_type::<UserApplication>()).ok());
+            let Some(user) = recipient else {
+                continue;
+            };
+
+            if self.is_forgotten_deposit(&storage, &user, transaction.topoheight) || self.get_returned_transfer(&storage, tx_hash, &transfer.asset)?.is_some() {
+                continue;
+            }
+
+            // Transfers of the same asset to the same user are taken back from the same balance
+            let update = match updates.iter().position(|update| update.user == user && update.asset == transfer.asset) {
+                Some(index) => &mut updates[index],
+                None => {
+                    let balance = self.get_asset_balance_internal(&storage, &user, &transfer.asset);
+                    updates.push(BalanceUpdate { user, asset: transfer.asset.clone(), before: balance, after: balance });
+                    updates.last_mut().expect("update just pushed")
+                }
+            };
+            let debited = update.after.min(transfer.amount);
+            update.after -= debited;
+
+            let reversed = ReversedTransfer {
+                asset: transfer.asset,
+                amount: transfer.amount,
+                shortfall: transfer.amount - debited
+            };
+            match reversals.iter_mut().find(|reversal| reversal.user == user) {
+                Some(reversal) => reversal.transfers.push(reversed),
+                None => reversals.push(DepositReversal {
+                    tx_hash: tx_hash.clone(),
+                    user,
+                    transfers: vec![reversed],
+                    reverted_at
+                })
+            }
+        }
+
+        updates.retain(|update| update.before != update.after);
+        if !updates.is_empty() {
+            self.apply_balance_updates(&mut storage, updates, MovementKind::Reorg)?;
+        }
+
+        for reversal in reversals.iter().filter(|reversal| reversal.has_shortfall()) {
+            warn!("{:?} already spent part of the orphaned deposit {}, freezing the account", reversal.user, tx_hash);
+            self.freeze_internal(&mut storage, &reversal.user)?;
+        }
+
+        let orphaned = OrphanedDeposit { reversals };
+        storage.delete_custom_data(HISTORY_TREE, &key)?;
+        storage.set_custom_data(ORPHANED_DEPOSITS_TREE, &key, &DataElement::Value(DataValue::Blob(orphaned.to_bytes())))?;
+        warn!("Deposit TX {} was orphaned, the credit of its {} users has been reverted", tx_hash, orphaned.reversals.len());
+
+        Ok(orphaned.reversals)
+    }
+
+    // Tell the user and the operators that a deposit was reverted
+    pub(super) async fn notify_deposit_reversal(&self, http: &Http, bot: &Bot, reversal: &DepositReversal) {
+        let mut amounts = Vec::with_capacity(reversal.transfers.len());
+        let mut shortfalls = Vec::new();
+        {
+            let storage = self.wallet.get_storage().read().await;
+            for transfer in reversal.transfers.iter() {
+                amounts.push(self.get_asset_balance_display(&storage, &transfer.asset, transfer.amount).await.to_string());
+                if transfer.shortfall > 0 {
+                    shortfalls.push(self.get_asset_balance_display(&storage, &transfer.asset, transfer.shortfall).await.to_string());
+                }
+            }
+        }
+
+        let amounts = amounts.join(", ");
+        let shortfalls = shortfalls.join(", ");
+        let mut fields = vec![
+            ("The transaction of your deposit was removed from the chain by a reorg, this amount has been taken back from your balance".to_string(), amounts.clone()),
+            ("Transaction".to_string(), reversal.tx_hash.to_string())
+        ];
+        if reversal.has_shortfall() {
+            fields.push(("Already spent, your account is frozen until an operator checks it".to_string(), shortfalls.clone()));
+        }
+
+        if let Err(e) = self.notify_user(http, bot, &reversal.user, "Deposit reverted", fields).await {
+            error!("Couldn't queue the notification of the reverted deposit {}: {:?}", reversal.tx_hash, e);
+            let alert = Alert::new("Notification Lost", format!("{:?} couldn't be told about the reverted deposit {}", reversal.user, reversal.tx_hash), Colour::RED)
+                .field("Error", format!("{:#}", e), false);
+            self.report_to_ops(http, &alert).await;
+        }
+
+        let user = match reversal.user {
+            UserApplication::Discord(id) => format!("Discord <@{}>", id),
+            UserApplication::Telegram(id) => format!("Telegram {}", id),
+            UserApplication::Matrix(id) => format!("Matrix {}", id),
+            UserApplication::Slack(id) => format!("Slack {}", id),
+            UserApplication::Twitch(id) => format!("Twitch {}", id)
+        };
+
+        let mut alert = Alert::new("Deposit Orphaned", format!("Deposit TX {} was orphaned by a reorg, its credit has been reverted", reversal.tx_hash), Colour::RED)
+            .field("User", user, false)
+            .field("Amount", amounts, false);
+
+        if reversal.has_shortfall() {
+            alert = alert.field("Already spent, account frozen", shortfalls, false);
+        }
+
+        self.alert_operators(http, alert).await;
+    }
+}
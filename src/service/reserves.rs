@@ -0,0 +1,300 @@
+use anyhow::{anyhow, Result};
+use rand::RngCore;
+use xelis_common::{
+    api::{DataElement, DataValue},
+    config::XELIS_ASSET,
+    crypto::{hash, Hash, Signature},
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{journal::JOURNAL_SEQUENCE_TREE, BalanceKey, UserApplication, WalletServiceImpl, BALANCES_TREE};
+
+// Proof of reserves snapshots, by id
+pub(super) const RESERVE_PROOFS_TREE: &str = "reserve_proofs";
+// Key of the next snapshot id in the journal sequences
+const RESERVES_SEQUENCE_KEY: &str = "next_reserve_proof";
+
+// Salt of the leaf of a user, only given to the user so the leaves can't be linked to anyone
+fn leaf_salt(secret: &Hash, user: &UserApplication) -> Hash {
+    let mut bytes = secret.to_bytes();
+    bytes.extend(user.to_bytes());
+    hash(&bytes)
+}
+
+// Leaf of a user in the Merkle tree: the hash of its salt followed by its XEL balance in big endian
+fn leaf_hash(salt: &Hash, balance: u64) -> Hash {
+    let mut bytes = salt.to_bytes();
+    bytes.extend(balance.to_be_bytes());
+    hash(&bytes)
+}
+
+// Root of the Merkle tree of the leaves, each node being the hash of its two children
+// A node without a sibling is moved up as is, the root of no leaves is zero
+fn merkle_root(leaves: &[Hash]) -> Hash {
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level.chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    let mut bytes = left.to_bytes();
+                    bytes.extend(right.to_bytes());
+                    hash(&bytes)
+                },
+                [single] => single.clone(),
+                _ => unreachable!()
+            })
+            .collect();
+    }
+
+    level.pop().unwrap_or_else(Hash::zero)
+}
+
+// Published part of a proof of reserves
+#[derive(Debug, Clone)]
+pub struct ReserveSnapshot {
+    pub id: u64,
+    // Timestamp in seconds
+    pub timestamp: u64,
+    pub topoheight: u64,
+    // Address of the wallet, its key signs the snapshot
+    pub address: String,
+    // XEL held by the wallet
+    pub assets: u64,
+    // XEL owed to the users, escrows and bot accounts included
+    pub liabilities: u64,
+    // Leaf of each user holding XEL, sorted
+    pub leaves: Vec<Hash>,
+    pub root: Hash
+}
+
+impl ReserveSnapshot {
+    // Hash signed by the wallet, the leaves are covered by the root
+    // The message is plain text, so the signature can be checked from the published JSON alone
+    pub fn digest(&self) -> Hash {
+        let message = format!(
+            "reserves:{}:{}:{}:{}:{}:{}:{}:{}",
+            self.id,
+            self.timestamp,
+            self.topoheight,
+            self.address,
+            self.assets,
+            self.liabilities,
+            self.leaves.len(),
+            self.root
+        );
+
+        hash(message.as_bytes())
+    }
+}
+
+impl Serializer for ReserveSnapshot {
+    fn write(&self, writer: &mut Writer) {
+        self.id.write(writer);
+        self.timestamp.write(writer);
+        self.topoheight.write(writer);
+        self.address.write(writer);
+        self.assets.write(writer);
+        self.liabilities.write(writer);
+        (self.leaves.len() as u32).write(writer);
+        for leaf in self.leaves.iter() {
+            leaf.write(writer);
+        }
+        self.root.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let id = reader.read_u64()?;
+        let timestamp = reader.read_u64()?;
+        let topoheight = reader.read_u64()?;
+        let address = String::read(reader)?;
+        let assets = reader.read_u64()?;
+        let liabilities = reader.read_u64()?;
+        let len = reader.read_u32()?;
+        let mut leaves = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            leaves.push(Hash::read(reader)?);
+        }
+        let root = Hash::read(reader)?;
+
+        Ok(Self {
+            id,
+            timestamp,
+            topoheight,
+            address,
+            assets,
+            liabilities,
+            leaves,
+            root
+        })
+    }
+}
+
+// Snapshot of what the wallet holds and what the bot owes, signed by the wallet
+#[derive(Debug, Clone)]
+pub struct ReserveProof {
+    pub snapshot: ReserveSnapshot,
+    pub signature: Signature,
+    // Secret deriving the salt of each leaf, never published
+    secret: Hash,
+    // Balance of each leaf, so a user can be given its own
+    balances: Vec<(UserApplication, u64)>
+}
+
+impl ReserveProof {
+    // Published snapshot as a JSON object, without the secret nor the users
+    pub fn to_json(&self) -> String {
+        let snapshot = &self.snapshot;
+        let leaves = snapshot.leaves.iter()
+            .map(|leaf| format!("\"{}\"", leaf))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        format!(
+            "{{\"id\":{},\"timestamp\":{},\"topoheight\":{},\"address\":\"{}\",\"assets\":{},\"liabilities\":{},\"root\":\"{}\",\"digest\":\"{}\",\"signature\":\"{}\",\"leaves\":[{}]}}",
+            snapshot.id,
+            snapshot.timestamp,
+            snapshot.topoheight,
+            snapshot.address,
+            snapshot.assets,
+            snapshot.liabilities,
+            snapshot.root,
+            snapshot.digest(),
+            self.signature.to_hex(),
+            leaves
+        )
+    }
+}
+
+impl Serializer for ReserveProof {
+    fn write(&self, writer: &mut Writer) {
+        self.snapshot.write(writer);
+        self.signature.write(writer);
+        self.secret.write(writer);
+        (self.balances.len() as u32).write(writer);
+        for (user, balance) in self.balances.iter() {
+            user.write(writer);
+            balance.write(writer);
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let snapshot = ReserveSnapshot::read(reader)?;
+        let signature = Signature::read(reader)?;
+        let secret = Hash::read(reader)?;
+        let len = reader.read_u32()?;
+        let mut balances = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            balances.push((UserApplication::read(reader)?, reader.read_u64()?));
+        }
+
+        Ok(Self {
+            snapshot,
+            signature,
+            secret,
+            balances
+        })
+    }
+}
+
+// What a user needs to find its leaf in a published snapshot
+#[derive(Debug, Clone)]
+pub struct ReserveLeaf {
+    pub proof: u64,
+    pub timestamp: u64,
+    pub root: Hash,
+    pub balance: u64,
+    pub salt: Hash,
+    pub leaf: Hash,
+    // Position of the leaf in the published leaves
+    pub index: usize
+}
+
+impl WalletServiceImpl {
+    // Take a signed snapshot of the reserves, with a leaf for the XEL balance of each user
+    pub async fn create_reserve_proof(&self) -> Result<ReserveProof> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let secret = Hash::new(secret);
+
+        let mut balances = Vec::new();
+        for key in storage.get_custom_tree_keys(&BALANCES_TREE.to_string(), &None, None, None)? {
+            let balance_key: BalanceKey = key.as_type()?;
+            if balance_key.asset != XELIS_ASSET {
+                continue;
+            }
+
+            let balance = storage.get_custom_data(BALANCES_TREE, &key)?.to_value()?.to_u64()?;
+            if balance > 0 {
+                balances.push((balance_key.user, balance));
+            }
+        }
+
+        let mut leaves: Vec<Hash> = balances.iter()
+            .map(|(user, balance)| leaf_hash(&leaf_salt(&secret, user), *balance))
+            .collect();
+        // Sorted, so the order of the leaves tells nothing about the users
+        leaves.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+
+        let id = self.get_sequence_internal(&storage, RESERVES_SEQUENCE_KEY);
+        let snapshot = ReserveSnapshot {
+            id,
+            timestamp: get_current_time_in_seconds(),
+            topoheight: storage.get_synced_topoheight()?,
+            address: self.wallet.get_address().to_string(),
+            assets: storage.get_plaintext_balance_for(&XELIS_ASSET).await.unwrap_or(0),
+            liabilities: self.get_total_users_balance_internal(&storage)?,
+            root: merkle_root(&leaves),
+            leaves
+        };
+        let signature = self.wallet.sign_data(snapshot.digest().as_bytes());
+        let proof = ReserveProof { snapshot, signature, secret, balances };
+
+        storage.set_custom_data(RESERVE_PROOFS_TREE, &DataValue::U64(id), &DataElement::Value(DataValue::Blob(proof.to_bytes())))?;
+        storage.set_custom_data(JOURNAL_SEQUENCE_TREE, &DataValue::String(RESERVES_SEQUENCE_KEY.to_string()), &(id + 1).into())?;
+
+        Ok(proof)
+    }
+
+    // Latest snapshot of the reserves, if one was taken
+    fn get_latest_reserve_proof_internal(&self, storage: &EncryptedStorage) -> Result<Option<ReserveProof>> {
+        let next = self.get_sequence_internal(storage, RESERVES_SEQUENCE_KEY);
+        if next == 0 {
+            return Ok(None);
+        }
+
+        let proof = storage.get_custom_data(RESERVE_PROOFS_TREE, &DataValue::U64(next - 1))?.to_value()?.to_type()?;
+        Ok(Some(proof))
+    }
+
+    // Leaf of a user in the latest snapshot, None if no snapshot was taken
+    // or if the user held no XEL at the time
+    pub async fn get_reserve_leaf(&self, user: &UserApplication) -> Result<Option<ReserveLeaf>> {
+        let storage = self.wallet.get_storage().read().await;
+        let Some(proof) = self.get_latest_reserve_proof_internal(&storage)? else {
+            return Ok(None);
+        };
+
+        let Some(balance) = proof.balances.iter().find(|(owner, _)| owner == user).map(|(_, balance)| *balance) else {
+            return Ok(None);
+        };
+
+        let salt = leaf_salt(&proof.secret, user);
+        let leaf = leaf_hash(&salt, balance);
+        let index = proof.snapshot.leaves.iter()
+            .position(|candidate| *candidate == leaf)
+            .ok_or_else(|| anyhow!("Leaf of {:?} is missing from proof {}", user, proof.snapshot.id))?;
+
+        Ok(Some(ReserveLeaf {
+            proof: proof.snapshot.id,
+            timestamp: proof.snapshot.timestamp,
+            root: proof.snapshot.root,
+            balance,
+            salt,
+            leaf,
+            index
+        }))
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use log::info;
+
+use super::{OpsChannels, RateLimitConfig, WalletServiceImpl};
+
+// Settings that can be changed while the bot runs, by reloading its configuration
+#[derive(Debug, Clone)]
+pub struct RuntimeSettings {
+    // Maximum XEL owed to the users, new deposits are returned once reached
+    pub max_liability: Option<u64>,
+    // XEL the bot can owe over its wallet balance before withdrawals are locked
+    pub solvency_buffer: u64,
+    // XEL balance of the wallet under which the operators are alerted
+    pub low_balance_alert: Option<u64>,
+    // Minimum XEL of a tip anywhere, communities can only raise it
+    pub min_tip: u64,
+    // Minimum XEL of a withdrawal, so the fee stays small compared to it
+    pub min_withdraw: u64,
+    // Maximum XEL a user can withdraw over a rolling 24 hours
+    pub max_daily_withdraw: Option<u64>,
+    // Seconds before a saved address can be used by a user with a withdrawal whitelist
+    pub whitelist_delay: u64,
+    // Commands a user can send in a row, unlimited if not set
+    pub rate_limit: Option<RateLimitConfig>,
+    // Channels where the errors and alerts are forwarded
+    pub ops_channels: OpsChannels,
+    // Page of a TX in the block explorer, {hash} is replaced by the TX hash
+    pub explorer_url: Option<String>
+}
+
+impl RuntimeSettings {
+    // Names of the settings that differ from another version
+    fn changes(&self, other: &Self) -> Vec<&'static str> {
+        let rate_limit = |settings: &Self| settings.rate_limit.map(|config| (config.burst, config.refill));
+        let ops_channels = |settings: &Self| (settings.ops_channels.discord, settings.ops_channels.telegram);
+
+        [
+            ("max_liability", self.max_liability != other.max_liability),
+            ("solvency_buffer", self.solvency_buffer != other.solvency_buffer),
+            ("low_balance_alert", self.low_balance_alert != other.low_balance_alert),
+            ("min_tip", self.min_tip != other.min_tip),
+            ("min_withdraw", self.min_withdraw != other.min_withdraw),
+            ("max_daily_withdraw", self.max_daily_withdraw != other.max_daily_withdraw),
+            ("whitelist_delay", self.whitelist_delay != other.whitelist_delay),
+            ("rate_limit", rate_limit(self) != rate_limit(other)),
+            ("ops_channels", ops_channels(self) != ops_channels(other)),
+            ("explorer_url", self.explorer_url != other.explorer_url)
+        ].into_iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
+impl WalletServiceImpl {
+    // Current settings, a reload doesn't change the ones already taken
+    pub(super) fn runtime(&self) -> Arc<RuntimeSettings> {
+        Arc::clone(&self.runtime.read().expect("runtime settings lock"))
+    }
+
+    // Replace the settings without stopping anything, the event loop included
+    // Returns the names of the settings that changed
+    pub fn reload_settings(&self, settings: RuntimeSettings) -> Vec<&'static str> {
+        let mut runtime = self.runtime.write().expect("runtime settings lock");
+        let changes = settings.changes(&runtime);
+        if !changes.is_empty() {
+            info!("Settings reloaded, changed: {}", changes.join(", "));
+            *runtime = Arc::new(settings);
+        }
+
+        changes
+    }
+}
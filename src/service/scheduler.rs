@@ -0,0 +1,303 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{debug, info, warn};
+use poise::serenity_prelude::{ButtonStyle, CreateActionRow, CreateButton, CreateEmbed, CreateMessage, Http};
+use teloxide::{
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+    Bot
+};
+use xelis_common::{
+    api::DataValue,
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::{get_current_time_in_millis, get_current_time_in_seconds},
+    utils::format_xelis
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use crate::{
+    telegram_message::{escape_html, TelegramMessage},
+    COLOR,
+    ICON,
+    REMINDER_TIP_PREFIX,
+    TIPME_BUTTON_PREFIX
+};
+
+use super::{discord_id, ServiceError, UserApplication, WalletServiceImpl};
+
+// Reminders scheduled by the users, by id
+pub(super) const SCHEDULED_REMINDERS_TREE: &str = "scheduled_reminders";
+// How often the due reminders are sent
+pub(super) const SCHEDULER_INTERVAL: Duration = Duration::from_secs(30);
+
+// Maximum length in characters of the memo of a reminder
+pub(super) const MAX_REMINDER_MEMO_LENGTH: usize = 200;
+// Reminders a user can have pending at once
+pub(super) const MAX_PENDING_REMINDERS: usize = 10;
+// How far in the future a reminder can be scheduled
+pub(super) const MAX_REMINDER_DELAY: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+// A reminder that still can't be delivered this long after its due time is dropped
+const MAX_REMINDER_LATENESS: u64 = 24 * 60 * 60;
+
+// A one-shot reminder sent in DM to its target once due
+// With an amount, the target can tip it to the creator from the reminder
+#[derive(Debug, Clone)]
+pub struct ScheduledReminder {
+    pub id: u64,
+    pub creator: UserApplication,
+    // Name of the creator when the reminder was scheduled
+    pub creator_name: String,
+    pub target: UserApplication,
+    // Timestamp in seconds
+    pub due_at: u64,
+    pub memo: String,
+    pub amount: Option<u64>
+}
+
+impl ScheduledReminder {
+    pub fn is_due(&self, now: u64) -> bool {
+        now >= self.due_at
+    }
+
+    // No button to tip yourself
+    fn tip_amount(&self) -> Option<u64> {
+        self.amount.filter(|_| self.creator != self.target)
+    }
+}
+
+impl Serializer for ScheduledReminder {
+    fn write(&self, writer: &mut Writer) {
+        self.id.write(writer);
+        self.creator.write(writer);
+        self.creator_name.write(writer);
+        self.target.write(writer);
+        self.due_at.write(writer);
+        self.memo.write(writer);
+        self.amount.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let id = reader.read_u64()?;
+        let creator = UserApplication::read(reader)?;
+        let creator_name = String::read(reader)?;
+        let target = UserApplication::read(reader)?;
+        let due_at = reader.read_u64()?;
+        let memo = String::read(reader)?;
+        let amount = Option::read(reader)?;
+
+        Ok(Self {
+            id,
+            creator,
+            creator_name,
+            target,
+            due_at,
+            memo,
+            amount
+        })
+    }
+}
+
+// Parse a delay such as 30m, 12h, 3d or 2w
+pub fn parse_delay(input: &str) -> Result<Duration, ServiceError> {
+    let input = input.trim().to_lowercase();
+    let unit = match input.chars().last() {
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        Some('w') => 7 * 24 * 60 * 60,
+        _ => return Err(ServiceError::InvalidDelay)
+    };
+
+    let value = input[..input.len() - 1].parse::<u64>()
+        .ok()
+        .filter(|value| *value > 0)
+        .ok_or(ServiceError::InvalidDelay)?;
+
+    value.checked_mul(unit)
+        .map(Duration::from_secs)
+        .ok_or(ServiceError::ReminderTooFar)
+}
+
+impl WalletServiceImpl {
+    fn get_scheduled_reminders_internal(&self, storage: &EncryptedStorage) -> Result<Vec<ScheduledReminder>> {
+        let mut reminders = Vec::new();
+        for key in storage.get_custom_tree_keys(&SCHEDULED_REMINDERS_TREE.to_string(), &None, None, None)? {
+            reminders.push(storage.get_custom_data(SCHEDULED_REMINDERS_TREE, &key)?.to_value()?.to_type()?);
+        }
+
+        Ok(reminders)
+    }
+
+    // Schedule a reminder sent in DM to the target once the delay is over
+    pub async fn schedule_reminder(&self, creator: &UserApplication, creator_name: &str, target: &UserApplication, delay: Duration, memo: &str, amount: Option<u64>) -> Result<ScheduledReminder, ServiceError> {
+        let memo = memo.trim();
+        if memo.is_empty() || memo.chars().count() > MAX_REMINDER_MEMO_LENGTH {
+            return Err(ServiceError::InvalidReminderMemo(MAX_REMINDER_MEMO_LENGTH));
+        }
+
+        if delay > MAX_REMINDER_DELAY {
+            return Err(ServiceError::ReminderTooFar);
+        }
+
+        if amount == Some(0) {
+            return Err(ServiceError::Zero);
+        }
+
+        let mut storage = self.wallet.get_storage().write().await;
+        let pending = self.get_scheduled_reminders_internal(&storage)?
+            .into_iter()
+            .filter(|reminder| reminder.creator == *creator)
+            .count();
+        if pending >= MAX_PENDING_REMINDERS {
+            return Err(ServiceError::TooManyReminders(MAX_PENDING_REMINDERS));
+        }
+
+        // Find an unused id
+        let mut id = get_current_time_in_millis();
+        while storage.has_custom_data(SCHEDULED_REMINDERS_TREE, &DataValue::U64(id))? {
+            id += 1;
+        }
+
+        let reminder = ScheduledReminder {
+            id,
+            creator: *creator,
+            creator_name: creator_name.to_string(),
+            target: *target,
+            due_at: get_current_time_in_seconds() + delay.as_secs(),
+            memo: memo.to_string(),
+            amount
+        };
+
+        debug!("{:?} scheduled reminder {} for {:?} in {} seconds", creator, id, target, delay.as_secs());
+        storage.set_custom_data(SCHEDULED_REMINDERS_TREE, &DataValue::U64(id), &DataValue::Blob(reminder.to_bytes()).into())?;
+
+        Ok(reminder)
+    }
+
+    // Send the reminders that are due in DM to their target
+    // Reminders of a platform whose errors spike are sent on a later round
+    pub(super) async fn send_due_reminders(&self, http: &Http, bot: &Bot) -> Result<()> {
+        let now = get_current_time_in_seconds();
+        let due = {
+            let storage = self.wallet.get_storage().read().await;
+            self.get_scheduled_reminders_internal(&storage)?
+                .into_iter()
+                .filter(|reminder| reminder.is_due(now))
+                .collect::<Vec<_>>()
+        };
+
+        if due.is_empty() {
+            return Ok(());
+        }
+
+        info!("Sending {} due reminders", due.len());
+        for reminder in due {
+            let platform = reminder.target.platform();
+            if self.api_errors.is_backing_off(platform) {
+                debug!("Postponing reminder {}, {} API errors are spiking", reminder.id, platform);
+                continue;
+            }
+
+            if let Err(e) = self.notify_scheduled_reminder(http, bot, &reminder).await {
+                self.api_errors.record_error(&e);
+                if now.saturating_sub(reminder.due_at) < MAX_REMINDER_LATENESS {
+                    debug!("Couldn't send reminder {} to {:?}, retrying later: {}", reminder.id, reminder.target, e);
+                    continue;
+                }
+
+                warn!("Dropping reminder {} of {:?}, it couldn't be sent: {}", reminder.id, reminder.target, e);
+            }
+
+            let mut storage = self.wallet.get_storage().write().await;
+            storage.delete_custom_data(SCHEDULED_REMINDERS_TREE, &DataValue::U64(reminder.id))?;
+        }
+
+        Ok(())
+    }
+
+    async fn notify_scheduled_reminder(&self, http: &Http, bot: &Bot, reminder: &ScheduledReminder) -> Result<()> {
+        match reminder.target {
+            UserApplication::Discord(id) => {
+                let user = http.get_user(discord_id(id)?).await?;
+                let channel = user.create_dm_channel(&http).await?;
+
+                let description = match reminder.creator {
+                    UserApplication::Discord(creator) if creator != id => format!("<@{}> asked to remind you", creator),
+                    _ => "You asked to be reminded".to_string()
+                };
+
+                let mut embed = CreateEmbed::default()
+                    .title("Reminder")
+                    .description(description)
+                    .field("About", &reminder.memo, false)
+                    .thumbnail(ICON)
+                    .colour(COLOR);
+
+                let mut message = CreateMessage::default();
+                if let Some(amount) = reminder.amount {
+                    embed = embed.field("Amount", format!("{} XEL", format_xelis(amount)), true);
+                }
+
+                // The button opens the same confirmation as a /tipme button
+                if let (Some(amount), UserApplication::Discord(creator)) = (reminder.tip_amount(), reminder.creator) {
+                    message = message.components(vec![CreateActionRow::Buttons(vec![
+                        CreateButton::new(format!("{}{}:{}", TIPME_BUTTON_PREFIX, creator, amount))
+                            .label(format!("Tip {} XEL", format_xelis(amount)))
+                            .style(ButtonStyle::Success)
+                    ])]);
+                }
+
+                channel.send_message(&http, message.embed(embed)).await?;
+            },
+            UserApplication::Telegram(id) => {
+                let mut message = TelegramMessage::new(&bot, self.get_telegram_dm_chat(id).await?, None);
+                message.title("Reminder");
+                if reminder.creator == reminder.target {
+                    message.line("You asked to be reminded");
+                } else {
+                    message.line(format!("{} asked to remind you", escape_html(&reminder.creator_name)));
+                }
+
+                message.field("About", escape_html(&reminder.memo), false);
+                if let Some(amount) = reminder.amount {
+                    message.field("Amount", format!("{} XEL", format_xelis(amount)), true);
+                }
+
+                if let (Some(amount), UserApplication::Telegram(creator)) = (reminder.tip_amount(), reminder.creator) {
+                    message.buttons(InlineKeyboardMarkup::new(vec![vec![
+                        InlineKeyboardButton::callback(format!("Tip {} XEL", format_xelis(amount)), format!("{}{}:{}", REMINDER_TIP_PREFIX, creator, amount))
+                    ]]));
+                }
+
+                message.send().await?;
+            },
+            // Reminders are scheduled from Discord and Telegram, there's no tip button on the other platforms
+            UserApplication::Matrix(_) | UserApplication::Slack(_) | UserApplication::Twitch(_) => {
+                let mut fields = vec![("About".to_string(), reminder.memo.clone())];
+                if reminder.creator != reminder.target {
+                    fields.insert(0, ("Asked by".to_string(), reminder.creator_name.clone()));
+                }
+                if let Some(amount) = reminder.amount {
+                    fields.push(("Amount".to_string(), format!("{} XEL", format_xelis(amount))));
+                }
+
+                self.send_notification(http, bot, &reminder.target, "Reminder", &fields).await?;
+            }
+        }
+
+        debug!("Sent reminder {} to {:?}", reminder.id, reminder.target);
+        Ok(())
+    }
+
+    // Forget the reminders created by or for a user
+    pub(super) fn delete_scheduled_reminders_internal(&self, storage: &mut EncryptedStorage, user: &UserApplication) -> Result<()> {
+        let reminders = self.get_scheduled_reminders_internal(storage)?;
+        for reminder in reminders {
+            if reminder.creator == *user || reminder.target == *user {
+                storage.delete_custom_data(SCHEDULED_REMINDERS_TREE, &DataValue::U64(reminder.id))?;
+            }
+        }
+
+        Ok(())
+    }
+}
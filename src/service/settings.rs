@@ -0,0 +1,282 @@
+use anyhow::Result;
+use xelis_common::{
+    api::{DataElement, DataValue},
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+
+use super::{Community, ServiceError, WalletServiceImpl};
+
+pub(super) const SETTINGS_TREE: &str = "settings";
+
+// Settings that can be configured per community
+// Each setting is stored under its own key so new ones
+// can be added without migrating the existing entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    // Discord role given to the top tipper of the previous week
+    TopTipperRole,
+    // Emoji added as a reaction on the messages of the top tipper
+    TopTipperEmoji,
+    // Current holder of the top tipper badge
+    TopTipperHolder,
+    // Week for which the badge was attributed
+    TopTipperWeek,
+    // Telegram channel where the announcements of a group are mirrored
+    AnnouncementChannel,
+    // Hide the amounts of mirrored announcements behind a spoiler
+    AnnouncementSpoiler,
+    // Bitmask of the features disabled in the community
+    DisabledFeatures,
+    // Minimum amount of a tip in the community
+    MinTip,
+    // Tips are confirmed to the tipper in DM instead of in the group
+    PrivateTips,
+    // The bot ignores the commands sent in the community
+    BotDisabled,
+    // Discord role required to receive a rain or claim a giveaway
+    RecipientRole,
+    // Minimum age in days of the account of a recipient
+    RecipientMinAccountAge,
+    // Minimum days since a recipient joined the community
+    RecipientMinMemberAge,
+    // Requirements of the recipients of a Telegram group, see Strictness
+    RecipientStrictness,
+}
+
+impl Setting {
+    fn id(&self) -> u8 {
+        match self {
+            Setting::TopTipperRole => 0,
+            Setting::TopTipperEmoji => 1,
+            Setting::TopTipperHolder => 2,
+            Setting::TopTipperWeek => 3,
+            Setting::AnnouncementChannel => 4,
+            Setting::AnnouncementSpoiler => 5,
+            Setting::DisabledFeatures => 6,
+            Setting::MinTip => 7,
+            Setting::PrivateTips => 8,
+            Setting::BotDisabled => 9,
+            Setting::RecipientRole => 10,
+            Setting::RecipientMinAccountAge => 11,
+            Setting::RecipientMinMemberAge => 12,
+            Setting::RecipientStrictness => 13,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        Some(match id {
+            0 => Setting::TopTipperRole,
+            1 => Setting::TopTipperEmoji,
+            2 => Setting::TopTipperHolder,
+            3 => Setting::TopTipperWeek,
+            4 => Setting::AnnouncementChannel,
+            5 => Setting::AnnouncementSpoiler,
+            6 => Setting::DisabledFeatures,
+            7 => Setting::MinTip,
+            8 => Setting::PrivateTips,
+            9 => Setting::BotDisabled,
+            10 => Setting::RecipientRole,
+            11 => Setting::RecipientMinAccountAge,
+            12 => Setting::RecipientMinMemberAge,
+            13 => Setting::RecipientStrictness,
+            _ => return None
+        })
+    }
+}
+
+// Features that can be disabled per community
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Rain,
+    Giveaway,
+    Games,
+    Lottery,
+    Faucet
+}
+
+impl Feature {
+    pub const ALL: [Feature; 5] = [Feature::Rain, Feature::Giveaway, Feature::Games, Feature::Lottery, Feature::Faucet];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Feature::Rain => "Rain",
+            Feature::Giveaway => "Giveaway",
+            Feature::Games => "Games",
+            Feature::Lottery => "Lottery",
+            Feature::Faucet => "Faucet"
+        }
+    }
+
+    // Bit of the feature in the DisabledFeatures setting
+    pub fn bit(&self) -> u64 {
+        1 << match self {
+            Feature::Rain => 0,
+            Feature::Giveaway => 1,
+            Feature::Games => 2,
+            Feature::Lottery => 3,
+            Feature::Faucet => 4
+        }
+    }
+}
+
+// Who can receive a rain or claim a giveaway in a community
+// Keeps fresh alt accounts from farming mass distributions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecipientFilter {
+    pub role: Option<u64>,
+    // In days
+    pub min_account_age: u64,
+    // Days since the user joined the community
+    pub min_member_age: u64
+}
+
+impl RecipientFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // The role and the join date are only needed when the filter uses them
+    pub fn needs_member(&self) -> bool {
+        self.role.is_some() || self.min_member_age > 0
+    }
+
+    // Check a user from its roles and the timestamps in seconds of its account creation and join
+    pub fn accepts(&self, roles: &[u64], created_at: u64, joined_at: Option<u64>) -> bool {
+        let now = get_current_time_in_seconds();
+        let old_enough = |since: u64, days: u64| days == 0 || now.saturating_sub(since) >= days * 24 * 60 * 60;
+
+        self.role.map_or(true, |role| roles.contains(&role))
+            && old_enough(created_at, self.min_account_age)
+            && (self.min_member_age == 0 || joined_at.is_some_and(|joined_at| old_enough(joined_at, self.min_member_age)))
+    }
+}
+
+// Storage key of a setting
+pub(super) struct SettingKey {
+    community: Community,
+    setting: Setting
+}
+
+impl Serializer for SettingKey {
+    fn write(&self, writer: &mut Writer) {
+        self.community.write(writer);
+        writer.write_u8(self.setting.id());
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let community = Community::read(reader)?;
+        let setting = Setting::from_id(reader.read_u8()?).ok_or(ReaderError::InvalidValue)?;
+
+        Ok(Self {
+            community,
+            setting
+        })
+    }
+}
+
+impl WalletServiceImpl {
+    // Get the value of a setting for a community
+    pub async fn get_setting(&self, community: &Community, setting: Setting) -> Option<DataValue> {
+        let storage = self.wallet.get_storage().read().await;
+        let key = SettingKey { community: *community, setting };
+        storage.get_custom_data(SETTINGS_TREE, &DataValue::Blob(key.to_bytes()))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+    }
+
+    // Set or remove the value of a setting for a community
+    pub async fn set_setting(&self, community: &Community, setting: Setting, value: Option<DataValue>) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let key = DataValue::Blob(SettingKey { community: *community, setting }.to_bytes());
+        match value {
+            Some(value) => storage.set_custom_data(SETTINGS_TREE, &key, &DataElement::Value(value))?,
+            None => storage.delete_custom_data(SETTINGS_TREE, &key)?
+        };
+
+        Ok(())
+    }
+
+    // Bitmask of the features disabled in a community
+    pub async fn get_disabled_features(&self, community: &Community) -> u64 {
+        self.get_setting(community, Setting::DisabledFeatures).await
+            .and_then(|v| v.to_u64().ok())
+            .unwrap_or(0)
+    }
+
+    // Minimum amount of a tip in a community, 0 if not configured
+    pub async fn get_min_tip(&self, community: &Community) -> u64 {
+        self.get_setting(community, Setting::MinTip).await
+            .and_then(|v| v.to_u64().ok())
+            .unwrap_or(0)
+    }
+
+    // Recipient filter of a community, empty if not configured
+    pub async fn get_recipient_filter(&self, community: &Community) -> RecipientFilter {
+        RecipientFilter {
+            role: self.get_setting(community, Setting::RecipientRole).await.and_then(|v| v.to_u64().ok()),
+            min_account_age: self.get_setting(community, Setting::RecipientMinAccountAge).await.and_then(|v| v.to_u64().ok()).unwrap_or(0),
+            min_member_age: self.get_setting(community, Setting::RecipientMinMemberAge).await.and_then(|v| v.to_u64().ok()).unwrap_or(0)
+        }
+    }
+
+    // Set the recipient filter of a community, unused criteria are removed
+    pub async fn set_recipient_filter(&self, community: &Community, filter: RecipientFilter) -> Result<()> {
+        self.set_setting(community, Setting::RecipientRole, filter.role.map(DataValue::U64)).await?;
+        self.set_setting(community, Setting::RecipientMinAccountAge, Some(DataValue::U64(filter.min_account_age)).filter(|_| filter.min_account_age > 0)).await?;
+        self.set_setting(community, Setting::RecipientMinMemberAge, Some(DataValue::U64(filter.min_member_age)).filter(|_| filter.min_member_age > 0)).await?;
+
+        Ok(())
+    }
+
+    // Value of a setting that is either on or off, off if not configured
+    pub async fn is_setting_enabled(&self, community: &Community, setting: Setting) -> bool {
+        matches!(self.get_setting(community, setting).await, Some(DataValue::Bool(true)))
+    }
+
+    // Check that a feature is enabled where the command is used
+    // Features are always enabled outside of a community (DM)
+    pub async fn check_feature(&self, community: Option<&Community>, feature: Feature) -> Result<(), ServiceError> {
+        let Some(community) = community else {
+            return Ok(());
+        };
+
+        if self.get_disabled_features(community).await & feature.bit() != 0 {
+            return Err(ServiceError::FeatureDisabled(feature.name()));
+        }
+
+        Ok(())
+    }
+
+    // Check that a tip reaches the minimum of the bot and the one configured by its community
+    pub async fn check_min_tip(&self, community: Option<&Community>, amount: u64) -> Result<(), ServiceError> {
+        let bot_min_tip = self.runtime().min_tip;
+        let min_tip = match community {
+            Some(community) => self.get_min_tip(community).await.max(bot_min_tip),
+            None => bot_min_tip
+        };
+
+        if amount < min_tip {
+            return Err(ServiceError::BelowMinTip(min_tip));
+        }
+
+        Ok(())
+    }
+
+    // Get all communities that configured a setting with its value
+    pub async fn get_communities_with_setting(&self, setting: Setting) -> Result<Vec<(Community, DataValue)>> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut communities = Vec::new();
+        for key in storage.get_custom_tree_keys(&SETTINGS_TREE.to_string(), &None, None, None)? {
+            let setting_key: SettingKey = key.as_type()?;
+            if setting_key.setting != setting {
+                continue;
+            }
+
+            let value = storage.get_custom_data(SETTINGS_TREE, &key)?.to_value()?;
+            communities.push((setting_key.community, value));
+        }
+
+        Ok(communities)
+    }
+}
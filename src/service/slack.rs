@@ -0,0 +1,156 @@
+use anyhow::{anyhow, bail, Result};
+use log::{info, warn};
+use xelis_common::{
+    api::DataValue,
+    serializer::{Reader, ReaderError, Serializer, Writer}
+};
+
+use super::{ids::text_id, Community, UserApplication, WalletServiceImpl};
+use crate::slack_api::SlackApi;
+
+// Workspaces that installed the app, by hash of their team ID
+pub(super) const SLACK_WORKSPACES_TREE: &str = "slack_workspaces";
+// Slack user ID of each Slack user and the workspace it was last seen in
+pub(super) const SLACK_USERS_TREE: &str = "slack_users";
+
+// Slack IDs are text, such as U024BE7LH for a user and T024BE7LH for a workspace, see `text_id`
+pub fn slack_id(id: &str) -> u64 {
+    text_id(id)
+}
+
+// Settings of a workspace are those of its community
+pub fn slack_community(team_id: &str) -> Community {
+    Community::Slack(slack_id(team_id))
+}
+
+// User given by an operator, as its Slack user ID or the ID the bot knows it by
+pub fn parse_slack_user(id: &str) -> Option<UserApplication> {
+    let id = id.trim();
+    if id.starts_with(['U', 'W']) && id.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+        return Some(UserApplication::Slack(slack_id(id)));
+    }
+
+    id.parse().ok().map(UserApplication::Slack)
+}
+
+// Installation of the app in a workspace
+#[derive(Debug, Clone)]
+pub struct SlackWorkspace {
+    pub team_id: String,
+    pub name: String,
+    // Token of the bot user of the app in the workspace
+    pub bot_token: String
+}
+
+impl Serializer for SlackWorkspace {
+    fn write(&self, writer: &mut Writer) {
+        self.team_id.write(writer);
+        self.name.write(writer);
+        self.bot_token.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        Ok(Self {
+            team_id: String::read(reader)?,
+            name: String::read(reader)?,
+            bot_token: String::read(reader)?
+        })
+    }
+}
+
+// A user is reached through the workspace it last used the bot in
+#[derive(Debug, Clone)]
+pub struct SlackUser {
+    pub user_id: String,
+    pub team_id: String
+}
+
+impl Serializer for SlackUser {
+    fn write(&self, writer: &mut Writer) {
+        self.user_id.write(writer);
+        self.team_id.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        Ok(Self {
+            user_id: String::read(reader)?,
+            team_id: String::read(reader)?
+        })
+    }
+}
+
+impl WalletServiceImpl {
+    // Client of the Web API, the Slack users can't be sent DMs until it's set
+    pub fn set_slack_api(&self, api: SlackApi) {
+        if self.slack.set(api).is_err() {
+            warn!("The Slack client was already set");
+        }
+    }
+
+    pub(super) fn get_slack_api(&self) -> Result<&SlackApi> {
+        self.slack.get().ok_or_else(|| anyhow!("Slack isn't configured, check --slack-bind-address"))
+    }
+
+    // Keep the bot token of a workspace that installed the app, replacing the previous one
+    pub async fn record_slack_workspace(&self, workspace: &SlackWorkspace) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        storage.set_custom_data(SLACK_WORKSPACES_TREE, &DataValue::U64(slack_id(&workspace.team_id)), &DataValue::Blob(workspace.to_bytes()).into())?;
+        info!("The Slack workspace {} ({}) installed the app", workspace.name, workspace.team_id);
+
+        Ok(())
+    }
+
+    // Forget the token of a workspace that uninstalled the app, its balances and settings are kept
+    pub async fn forget_slack_workspace(&self, team_id: &str) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        storage.delete_custom_data(SLACK_WORKSPACES_TREE, &DataValue::U64(slack_id(team_id)))?;
+        info!("The Slack workspace {} uninstalled the app", team_id);
+
+        Ok(())
+    }
+
+    // Installation of a workspace, None if it didn't install the app
+    pub async fn get_slack_workspace(&self, team_id: &str) -> Result<Option<SlackWorkspace>> {
+        let storage = self.wallet.get_storage().read().await;
+        let workspace = storage.get_custom_data(SLACK_WORKSPACES_TREE, &DataValue::U64(slack_id(team_id)))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| match v {
+                DataValue::Blob(bytes) => SlackWorkspace::from_bytes(&bytes).ok(),
+                _ => None
+            });
+
+        Ok(workspace)
+    }
+
+    // Remember the Slack user ID behind a user and its workspace, so it can be sent DMs
+    // Fails if another Slack user already has the same hash
+    pub async fn record_slack_user(&self, team_id: &str, user_id: &str) -> Result<UserApplication> {
+        let id = slack_id(user_id);
+        match self.get_slack_user(id).await? {
+            Some(known) if known.user_id == user_id && known.team_id == team_id => return Ok(UserApplication::Slack(id)),
+            Some(known) if known.user_id != user_id => bail!("The Slack users {} and {} have the same ID {}", known.user_id, user_id, id),
+            _ => {}
+        }
+
+        let user = SlackUser { user_id: user_id.to_string(), team_id: team_id.to_string() };
+        let mut storage = self.wallet.get_storage().write().await;
+        storage.set_custom_data(SLACK_USERS_TREE, &DataValue::U64(id), &DataValue::Blob(user.to_bytes()).into())?;
+
+        Ok(UserApplication::Slack(id))
+    }
+
+    // Slack user ID and workspace of a user, None if it never used the bot
+    pub async fn get_slack_user(&self, id: u64) -> Result<Option<SlackUser>> {
+        let storage = self.wallet.get_storage().read().await;
+        let user = storage.get_custom_data(SLACK_USERS_TREE, &DataValue::U64(id))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| match v {
+                DataValue::Blob(bytes) => SlackUser::from_bytes(&bytes).ok(),
+                _ => None
+            });
+
+        Ok(user)
+    }
+}
@@ -0,0 +1,89 @@
+use std::{fmt, sync::atomic::Ordering, time::Duration};
+
+use anyhow::Result;
+use log::{error, info};
+use poise::serenity_prelude::{Colour, Http};
+use xelis_common::{config::COIN_VALUE, utils::format_xelis};
+
+use super::{ops::Alert, WalletServiceImpl};
+use crate::COLOR;
+
+// How often the wallet balance is compared with what the bot owes
+pub(super) const SOLVENCY_INTERVAL: Duration = Duration::from_secs(60);
+// XEL the bot can owe over its wallet balance before withdrawals are locked
+// Covers the fees of the TXs not yet reflected in the wallet balance
+pub const DEFAULT_SOLVENCY_BUFFER: u64 = COIN_VALUE;
+
+// XEL held by the wallet against the XEL owed to the users
+#[derive(Debug, Clone, Copy)]
+pub struct Solvency {
+    pub assets: u64,
+    pub liabilities: u64
+}
+
+impl Solvency {
+    // Assets over liabilities, None while nothing is owed
+    pub fn ratio(&self) -> Option<f64> {
+        (self.liabilities > 0).then(|| self.assets as f64 / self.liabilities as f64)
+    }
+
+    // XEL owed that the wallet doesn't hold
+    pub fn deficit(&self) -> u64 {
+        self.liabilities.saturating_sub(self.assets)
+    }
+}
+
+impl fmt::Display for Solvency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.ratio() {
+            Some(ratio) => write!(f, "{:.2}%", ratio * 100.0),
+            None => write!(f, "Nothing owed")
+        }
+    }
+}
+
+impl WalletServiceImpl {
+    // Compare the XEL of the wallet with the XEL owed to the users
+    pub async fn get_solvency(&self) -> Result<Solvency> {
+        Ok(Solvency {
+            assets: self.get_wallet_balance().await?,
+            liabilities: self.get_total_users_balance().await?
+        })
+    }
+
+    // Lock the withdrawals once the bot owes more than its wallet holds, beyond the buffer
+    // The operators are alerted once when it happens and once when it's over,
+    // but the withdrawals stay locked until one of them unlocks them
+    pub(super) async fn check_solvency(&self, http: &Http) -> Result<()> {
+        // A wallet out of sync can't tell its balance
+        if !self.wallet.is_online().await {
+            return Ok(());
+        }
+
+        let solvency = self.get_solvency().await?;
+        let insolvent = solvency.deficit() > self.runtime().solvency_buffer;
+        if insolvent == self.insolvent.swap(insolvent, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let alert = if insolvent {
+            error!("The bot owes {} XEL but its wallet only holds {} XEL, locking the withdrawals", format_xelis(solvency.liabilities), format_xelis(solvency.assets));
+            self.locked.store(true, Ordering::SeqCst);
+
+            Alert::new("Insolvency Detected", "The users are owed more XEL than the wallet holds, withdrawals have been locked until an operator unlocks them with `/admin withdrawals`", Colour::RED)
+                .field("Deficit", format_xelis(solvency.deficit()), false)
+        } else {
+            info!("The wallet covers what the bot owes again, solvency ratio is {}", solvency);
+
+            Alert::new("Solvency Restored", "The wallet covers what the bot owes again, unlock the withdrawals with `/admin withdrawals` once the cause is understood", COLOR)
+        };
+
+        let alert = alert.field("Wallet Balance", format_xelis(solvency.assets), true)
+            .field("Total Owed", format_xelis(solvency.liabilities), true)
+            .field("Solvency Ratio", solvency.to_string(), true);
+
+        self.alert_operators(http, alert).await;
+
+        Ok(())
+    }
+}
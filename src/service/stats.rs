@@ -0,0 +1,303 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use log::{info, warn};
+use poise::serenity_prelude::{GuildId, Http, RoleId, UserId};
+use xelis_common::{
+    api::{DataElement, DataValue},
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{discord_id, Community, Setting, UserApplication, WalletServiceImpl};
+
+pub(super) const STATS_TREE: &str = "stats";
+
+const DAY: u64 = 60 * 60 * 24;
+const WEEK: u64 = DAY * 7;
+
+// Period on which the tips are aggregated
+// Day and Week contain the index of the period since the UNIX epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatsPeriod {
+    Day(u64),
+    Week(u64),
+    AllTime
+}
+
+impl StatsPeriod {
+    pub fn current_day() -> Self {
+        StatsPeriod::Day(get_current_time_in_seconds() / DAY)
+    }
+
+    pub fn current_week() -> Self {
+        StatsPeriod::Week(get_current_time_in_seconds() / WEEK)
+    }
+}
+
+impl Serializer for StatsPeriod {
+    fn write(&self, writer: &mut Writer) {
+        match self {
+            StatsPeriod::Day(day) => {
+                writer.write_u8(0);
+                day.write(writer);
+            },
+            StatsPeriod::Week(week) => {
+                writer.write_u8(1);
+                week.write(writer);
+            },
+            StatsPeriod::AllTime => {
+                writer.write_u8(2);
+            }
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let period = match reader.read_u8()? {
+            0 => StatsPeriod::Day(reader.read_u64()?),
+            1 => StatsPeriod::Week(reader.read_u64()?),
+            2 => StatsPeriod::AllTime,
+            _ => return Err(ReaderError::InvalidValue)
+        };
+
+        Ok(period)
+    }
+}
+
+// Storage key of the stats of a user
+// Community is None for the stats across all communities
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct StatsKey {
+    period: StatsPeriod,
+    community: Option<Community>,
+    user: UserApplication
+}
+
+impl Serializer for StatsKey {
+    fn write(&self, writer: &mut Writer) {
+        self.period.write(writer);
+        match &self.community {
+            Some(community) => {
+                writer.write_u8(1);
+                community.write(writer);
+            },
+            None => writer.write_u8(0)
+        }
+        self.user.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let period = StatsPeriod::read(reader)?;
+        let community = match reader.read_u8()? {
+            0 => None,
+            1 => Some(Community::read(reader)?),
+            _ => return Err(ReaderError::InvalidValue)
+        };
+        let user = UserApplication::read(reader)?;
+
+        Ok(Self {
+            period,
+            community,
+            user
+        })
+    }
+}
+
+// Aggregated tips of a user over a period
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TipStats {
+    // Total amount tipped to others
+    pub tipped: u64,
+    // Total amount received from others
+    pub received: u64,
+    // Number of tips sent
+    pub tips: u64
+}
+
+impl Serializer for TipStats {
+    fn write(&self, writer: &mut Writer) {
+        self.tipped.write(writer);
+        self.received.write(writer);
+        self.tips.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        Ok(Self {
+            tipped: reader.read_u64()?,
+            received: reader.read_u64()?,
+            tips: reader.read_u64()?
+        })
+    }
+}
+
+// Which side of the tips a leaderboard ranks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaderboardKind {
+    Tippers,
+    Receivers
+}
+
+impl WalletServiceImpl {
+    fn get_tip_stats_internal(&self, storage: &EncryptedStorage, key: &DataValue) -> TipStats {
+        storage.get_custom_data(STATS_TREE, key)
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_type().ok())
+            .unwrap_or_default()
+    }
+
+    // Stats of a user over a period, across all communities
+    pub(super) fn get_user_tip_stats(&self, storage: &EncryptedStorage, period: StatsPeriod, user: &UserApplication) -> TipStats {
+        let key = DataValue::Blob(StatsKey { period, community: None, user: *user }.to_bytes());
+        self.get_tip_stats_internal(storage, &key)
+    }
+
+    // Record a tip in the stats of every period, globally and for its community
+    pub(super) fn record_tip_stats(&self, storage: &mut EncryptedStorage, from: &UserApplication, to: &UserApplication, amount: u64, community: Option<Community>) -> Result<()> {
+        let periods = [StatsPeriod::current_day(), StatsPeriod::current_week(), StatsPeriod::AllTime];
+        let communities = match community {
+            Some(community) => vec![None, Some(community)],
+            None => vec![None]
+        };
+        for period in periods {
+            for community in communities.iter().copied() {
+                let from_key = DataValue::Blob(StatsKey { period, community, user: *from }.to_bytes());
+                let mut from_stats = self.get_tip_stats_internal(storage, &from_key);
+                from_stats.tipped += amount;
+                from_stats.tips += 1;
+                storage.set_custom_data(STATS_TREE, &from_key, &DataElement::Value(DataValue::Blob(from_stats.to_bytes())))?;
+
+                let to_key = DataValue::Blob(StatsKey { period, community, user: *to }.to_bytes());
+                let mut to_stats = self.get_tip_stats_internal(storage, &to_key);
+                to_stats.received += amount;
+                storage.set_custom_data(STATS_TREE, &to_key, &DataElement::Value(DataValue::Blob(to_stats.to_bytes())))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Remove the stats of a user for every period and community
+    pub(super) fn delete_tip_stats_internal(&self, storage: &mut EncryptedStorage, user: &UserApplication) -> Result<()> {
+        let mut keys = Vec::new();
+        for key in storage.get_custom_tree_keys(&STATS_TREE.to_string(), &None, None, None)? {
+            let stats_key: StatsKey = key.as_type()?;
+            if stats_key.user == *user {
+                keys.push(key);
+            }
+        }
+
+        for key in keys {
+            storage.delete_custom_data(STATS_TREE, &key)?;
+        }
+
+        Ok(())
+    }
+
+    // Get the stats of every user for a period
+    // Community None returns the stats across all communities
+    pub async fn get_tip_stats(&self, period: StatsPeriod, community: Option<Community>) -> Result<HashMap<UserApplication, TipStats>> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut stats = HashMap::new();
+        for key in storage.get_custom_tree_keys(&STATS_TREE.to_string(), &None, None, None)? {
+            let stats_key: StatsKey = key.as_type()?;
+            if stats_key.period != period || stats_key.community != community {
+                continue;
+            }
+
+            stats.insert(stats_key.user, self.get_tip_stats_internal(&storage, &key));
+        }
+
+        Ok(stats)
+    }
+
+    // Get the users ranked by amount tipped or received over a period
+    // Users with nothing to rank are not included
+    pub async fn get_leaderboard(&self, period: StatsPeriod, community: Option<Community>, kind: LeaderboardKind) -> Result<Vec<(UserApplication, u64)>> {
+        let stats = self.get_tip_stats(period, community).await?;
+        let mut leaderboard: Vec<(UserApplication, u64)> = stats.into_iter()
+            .map(|(user, stats)| (user, match kind {
+                LeaderboardKind::Tippers => stats.tipped,
+                LeaderboardKind::Receivers => stats.received
+            }))
+            .filter(|(_, amount)| *amount > 0)
+            .collect();
+
+        leaderboard.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        Ok(leaderboard)
+    }
+
+    // Give the configured role to the top tipper of the previous week in each guild
+    // and remove it from the previous holder
+    pub(super) async fn update_top_tippers(&self, http: &Http) -> Result<()> {
+        let week = (get_current_time_in_seconds() / WEEK).saturating_sub(1);
+
+        let mut communities = HashSet::new();
+        for setting in [Setting::TopTipperRole, Setting::TopTipperEmoji] {
+            communities.extend(self.get_communities_with_setting(setting).await?.into_iter().map(|(community, _)| community));
+        }
+
+        for community in communities {
+            let Community::Discord(guild_id) = community else {
+                continue;
+            };
+
+            let current_week = self.get_setting(&community, Setting::TopTipperWeek).await.and_then(|v| v.to_u64().ok());
+            if current_week == Some(week) {
+                continue;
+            }
+
+            let stats = self.get_tip_stats(StatsPeriod::Week(week), Some(community)).await?;
+            let top = stats.into_iter()
+                .filter_map(|(user, stats)| match user {
+                    UserApplication::Discord(id) if stats.tipped > 0 => Some((id, stats.tipped)),
+                    _ => None
+                })
+                .max_by_key(|(_, tipped)| *tipped)
+                .map(|(id, _)| id);
+
+            let previous = self.get_setting(&community, Setting::TopTipperHolder).await.and_then(|v| v.to_u64().ok());
+            let role = self.get_setting(&community, Setting::TopTipperRole).await
+                .and_then(|v| v.to_u64().ok())
+                .and_then(|role| discord_id::<RoleId>(role).ok());
+
+            if let Some(role) = role {
+                let guild: GuildId = discord_id(guild_id)?;
+                if let Some(previous) = previous.filter(|previous| Some(*previous) != top) {
+                    if let Err(e) = http.remove_member_role(guild, discord_id::<UserId>(previous)?, role, Some("No longer the top tipper of the week")).await {
+                        warn!("Couldn't remove top tipper role from {} in guild {}: {}", previous, guild_id, e);
+                    }
+                }
+
+                if let Some(top) = top.filter(|top| Some(*top) != previous) {
+                    if let Err(e) = http.add_member_role(guild, discord_id::<UserId>(top)?, role, Some("Top tipper of the week")).await {
+                        warn!("Couldn't give top tipper role to {} in guild {}: {}", top, guild_id, e);
+                    }
+                }
+            }
+
+            info!("Top tipper of week {} in guild {} is {:?}", week, guild_id, top);
+            self.set_setting(&community, Setting::TopTipperHolder, top.map(DataValue::U64)).await?;
+            self.set_setting(&community, Setting::TopTipperWeek, Some(DataValue::U64(week))).await?;
+        }
+
+        Ok(())
+    }
+
+    // Check if a Discord user is the current top tipper of a guild
+    // and returns the emoji to react with
+    pub async fn get_top_tipper_emoji(&self, guild_id: u64, user_id: u64) -> Option<String> {
+        let community = Community::Discord(guild_id);
+        let holder = self.get_setting(&community, Setting::TopTipperHolder).await?.to_u64().ok()?;
+        if holder != user_id {
+            return None;
+        }
+
+        match self.get_setting(&community, Setting::TopTipperEmoji).await? {
+            DataValue::String(emoji) => Some(emoji),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use log::{info, warn};
+use tokio::sync::RwLockWriteGuard;
+use xelis_common::{
+    crypto::{Hash, Hashable},
+    transaction::builder::TransactionTypeBuilder
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{unsent::Debit, ServiceError, WalletServiceImpl};
+
+// Broadcasts of the same TX before giving up
+const MAX_SUBMIT_ATTEMPTS: u32 = 3;
+// Wait before broadcasting the TX again, doubled after each attempt
+const SUBMIT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// The daemon already received this exact TX from a previous attempt
+pub(super) fn is_already_in_mempool(error: &str) -> bool {
+    error.to_lowercase().contains("already in mempool")
+}
+
+// Another TX already uses the nonce of this one
+pub(super) fn is_nonce_conflict(error: &str) -> bool {
+    error.to_lowercase().contains("nonce")
+}
+
+// The daemon couldn't be reached or didn't answer, it may still have received the TX
+// Any other error is an answer of the daemon rejecting it
+pub(super) fn is_transport_error(error: &str) -> bool {
+    let error = error.to_lowercase();
+    ["connection", "timed out", "timeout", "websocket", "offline", "unreachable", "no response", "broken pipe"]
+        .iter()
+        .any(|pattern| error.contains(pattern))
+}
+
+impl WalletServiceImpl {
+    // Build a TX, broadcast it and apply it to the wallet state
+    // Callers wait their turn in the submission queue, so two TXs can never be built with the same nonce
+    // The funds of `debit` are debited from its user and the signed TX is kept before the first broadcast,
+    // so a crash can neither send it without debiting them nor lose it once debited
+    // The storage lock is released while the TX is broadcast, so the other commands don't wait for the daemon,
+    // then taken again to record the result and given back to the caller
+    // On network errors the same signed TX is broadcast again, so it can't be sent twice
+    // If the daemon rejects it, the funds are given back right away
+    // If the daemon stays unreachable, the TX is broadcast again once it's back
+    pub(super) async fn broadcast_transaction<'a>(&'a self, mut storage: RwLockWriteGuard<'a, EncryptedStorage>, builder: TransactionTypeBuilder, fee: u64, debit: Debit) -> Result<(Hash, RwLockWriteGuard<'a, EncryptedStorage>), ServiceError> {
+        // An instance on standby never sends anything, the leader may send the same withdrawal
+        if !self.is_leader() {
+            return Err(ServiceError::NotLeader);
+        }
+
+        let (transaction, apply_changes) = self.wallet.create_transaction(&mut storage, builder, fee).await?;
+
+        let tx_hash = transaction.hash();
+        self.debit_withdrawal_internal(&mut storage, &debit, fee)?;
+        self.queue_unsent_transaction(&mut storage, &transaction, debit.clone(), fee)?;
+        drop(storage);
+
+        let mut attempt = 1;
+        let delivered = loop {
+            let error = match self.wallet.submit_transaction(&transaction).await {
+                Ok(_) => break true,
+                Err(e) => e
+            };
+
+            let message = error.to_string();
+            if is_already_in_mempool(&message) {
+                info!("TX {} was already received by the daemon", tx_hash);
+                break true;
+            }
+
+            // The wallet state is behind the chain, nothing was sent so nothing is applied
+            if is_nonce_conflict(&message) {
+                warn!("TX {} conflicts with another TX of the wallet: {}", tx_hash, message);
+                let mut storage = self.wallet.get_storage().write().await;
+                self.cancel_unsent_transaction(&mut storage, &tx_hash, &debit, fee)?;
+                return Err(ServiceError::TransactionConflict);
+            }
+
+            // The daemon answered and refused the TX, broadcasting it again wouldn't change its mind
+            if !is_transport_error(&message) && self.wallet.is_online().await {
+                warn!("TX {} was rejected by the daemon: {}", tx_hash, message);
+                let mut storage = self.wallet.get_storage().write().await;
+                self.cancel_unsent_transaction(&mut storage, &tx_hash, &debit, fee)?;
+                return Err(error.into());
+            }
+
+            // The TX may have reached the daemon before the connection dropped, it's kept to be broadcast again
+            if attempt >= MAX_SUBMIT_ATTEMPTS {
+                warn!("Daemon is unreachable, TX {} will be broadcast again once it's back", tx_hash);
+                break false;
+            }
+
+            warn!("Couldn't broadcast TX {} (attempt {}/{}): {}", tx_hash, attempt, MAX_SUBMIT_ATTEMPTS, message);
+            tokio::time::sleep(SUBMIT_RETRY_DELAY * 2u32.pow(attempt - 1)).await;
+            attempt += 1;
+        };
+
+        let mut storage = self.wallet.get_storage().write().await;
+        apply_changes(&mut storage).await?;
+        if delivered {
+            self.finalize_unsent_transaction(&mut storage, &tx_hash)?;
+        }
+        self.record_sent_withdrawal(&mut storage, &tx_hash, &debit, fee)?;
+        self.track_pending_withdrawal(&transaction, debit.user);
+
+        Ok((tx_hash, storage))
+    }
+}
@@ -0,0 +1,18 @@
+use anyhow::{anyhow, Result};
+use log::warn;
+
+use super::WalletServiceImpl;
+use crate::twitch_api::TwitchApi;
+
+impl WalletServiceImpl {
+    // Client of the Helix API, the Twitch users can't be sent whispers until it's set
+    pub fn set_twitch_api(&self, api: TwitchApi) {
+        if self.twitch.set(api).is_err() {
+            warn!("The Twitch client was already set");
+        }
+    }
+
+    pub(super) fn get_twitch_api(&self) -> Result<&TwitchApi> {
+        self.twitch.get().ok_or_else(|| anyhow!("Twitch isn't configured, check --twitch-client-id"))
+    }
+}
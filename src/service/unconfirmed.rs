@@ -0,0 +1,58 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use log::{info, warn};
+use xelis_common::{
+    api::{wallet::TransactionEntry, DataValue},
+    crypto::Hash,
+    serializer::Serializer
+};
+
+use super::WalletServiceImpl;
+
+// TXs seen by the event loop that aren't in the stable topoheight yet
+// Holds the topoheight of each TX, so the queue is rebuilt in order after a restart
+pub(super) const UNCONFIRMED_TREE: &str = "unconfirmed_transactions";
+
+impl WalletServiceImpl {
+    // Keep a TX waiting for the stable topoheight, so a restart can't drop it
+    pub(super) async fn persist_unconfirmed_transaction(&self, transaction: &TransactionEntry) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        storage.set_custom_data(UNCONFIRMED_TREE, &DataValue::Blob(transaction.hash.to_bytes()), &transaction.topoheight.into())?;
+
+        Ok(())
+    }
+
+    // Forget a TX once it has been handled
+    pub(super) async fn remove_unconfirmed_transaction(&self, hash: &Hash) -> Result<()> {
+        let mut storage = self.wallet.get_storage().write().await;
+        storage.delete_custom_data(UNCONFIRMED_TREE, &DataValue::Blob(hash.to_bytes()))?;
+
+        Ok(())
+    }
+
+    // Rebuild the queue of the event loop from the TXs still waiting when it stopped, by topoheight
+    // A TX the wallet no longer knows was orphaned or dropped by a rescan, it's forgotten
+    pub(super) async fn load_unconfirmed_transactions(&self) -> Result<VecDeque<TransactionEntry>> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let mut transactions = Vec::new();
+        for key in storage.get_custom_tree_keys(&UNCONFIRMED_TREE.to_string(), &None, None, None)? {
+            let hash: Hash = key.as_type()?;
+            match storage.get_transaction(&hash) {
+                Ok(transaction) => transactions.push(transaction),
+                Err(e) => {
+                    warn!("Unconfirmed TX {} is no longer in the wallet, forgetting it: {}", hash, e);
+                    storage.delete_custom_data(UNCONFIRMED_TREE, &key)?;
+                }
+            }
+        }
+
+        if !transactions.is_empty() {
+            info!("Resuming {} unconfirmed TXs", transactions.len());
+        }
+
+        transactions.sort_by_key(|transaction| transaction.topoheight);
+
+        Ok(transactions.into())
+    }
+}
@@ -0,0 +1,263 @@
+use anyhow::Result;
+use log::{info, warn};
+use poise::serenity_prelude::{Colour, Http};
+use xelis_common::{
+    api::{wallet::EntryType, DataElement, DataValue},
+    crypto::{Address, Hash},
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{deposits::RETURNED_DEPOSITS_TREE, ops::Alert, AssetBalance, ServiceError, WalletServiceImpl, HISTORY_TREE};
+
+// Incoming TXs with transfers that couldn't be credited to any user, by TX hash
+// They stay in the wallet until an operator credits or refunds them, refunded ones are kept as resolved
+pub(super) const UNKNOWN_DEPOSITS_TREE: &str = "unknown_deposits";
+
+// Transfer of an incoming TX that doesn't name a user
+#[derive(Debug, Clone)]
+pub struct UnknownTransfer {
+    pub asset: Hash,
+    pub amount: u64,
+    // Why no user could be found
+    pub reason: String
+}
+
+impl Serializer for UnknownTransfer {
+    fn write(&self, writer: &mut Writer) {
+        self.asset.write(writer);
+        self.amount.write(writer);
+        self.reason.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        Ok(Self {
+            asset: Hash::read(reader)?,
+            amount: reader.read_u64()?,
+            reason: String::read(reader)?
+        })
+    }
+}
+
+// Incoming TX stranded in the wallet
+#[derive(Debug, Clone)]
+pub struct UnknownDeposit {
+    pub tx_hash: Hash,
+    pub from: String,
+    pub topoheight: u64,
+    pub transfers: Vec<UnknownTransfer>,
+    // TXs that sent the transfers back to the sender
+    pub refunds: Vec<Hash>,
+    // Timestamps in seconds
+    pub detected_at: u64,
+    pub resolved_at: Option<u64>
+}
+
+impl UnknownDeposit {
+    pub fn is_resolved(&self) -> bool {
+        self.resolved_at.is_some()
+    }
+}
+
+impl Serializer for UnknownDeposit {
+    fn write(&self, writer: &mut Writer) {
+        self.tx_hash.write(writer);
+        self.from.write(writer);
+        self.topoheight.write(writer);
+        (self.transfers.len() as u16).write(writer);
+        for transfer in self.transfers.iter() {
+            transfer.write(writer);
+        }
+        (self.refunds.len() as u16).write(writer);
+        for refund in self.refunds.iter() {
+            refund.write(writer);
+        }
+        self.detected_at.write(writer);
+        self.resolved_at.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let tx_hash = Hash::read(reader)?;
+        let from = String::read(reader)?;
+        let topoheight = reader.read_u64()?;
+        let len = reader.read_u16()?;
+        let mut transfers = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            transfers.push(UnknownTransfer::read(reader)?);
+        }
+        let len = reader.read_u16()?;
+        let mut refunds = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            refunds.push(Hash::read(reader)?);
+        }
+        let detected_at = reader.read_u64()?;
+        let resolved_at = Option::read(reader)?;
+
+        Ok(Self {
+            tx_hash,
+            from,
+            topoheight,
+            transfers,
+            refunds,
+            detected_at,
+            resolved_at
+        })
+    }
+}
+
+impl WalletServiceImpl {
+    // Record the transfers of a confirmed TX that couldn't be credited, and alert the operators
+    // A TX seen again after a rescan is only recorded once
+    pub(super) async fn record_unknown_deposit(&self, http: &Http, tx_hash: &Hash, from: &Address, topoheight: u64, transfers: Vec<UnknownTransfer>) -> Result<()> {
+        let key: DataValue = tx_hash.clone().into();
+        let deposit = UnknownDeposit {
+            tx_hash: tx_hash.clone(),
+            from: from.to_string(),
+            topoheight,
+            transfers,
+            refunds: Vec::new(),
+            detected_at: get_current_time_in_seconds(),
+            resolved_at: None
+        };
+
+        let mut amounts = Vec::with_capacity(deposit.transfers.len());
+        let mut reasons = Vec::new();
+        {
+            let mut storage = self.wallet.get_storage().write().await;
+            if storage.has_custom_data(UNKNOWN_DEPOSITS_TREE, &key)? {
+                return Ok(());
+            }
+
+            storage.set_custom_data(UNKNOWN_DEPOSITS_TREE, &key, &DataElement::Value(DataValue::Blob(deposit.to_bytes())))?;
+            for transfer in deposit.transfers.iter() {
+                amounts.push(self.get_asset_balance_display(&storage, &transfer.asset, transfer.amount).await.to_string());
+                if !reasons.contains(&transfer.reason) {
+                    reasons.push(transfer.reason.clone());
+                }
+            }
+        }
+
+        let amounts = amounts.join(", ");
+        warn!("TX {} from {} can't be credited to any user, {} are stranded in the wallet", tx_hash, from, amounts);
+
+        let alert = Alert::new("Unknown Deposit", format!("TX {} doesn't name any user, its funds are stranded in the wallet. Credit them with the `credit` CLI command or send them back with `refund_deposit`", tx_hash), Colour::RED)
+            .field("From", deposit.from, false)
+            .field("Amount", amounts, false)
+            .field("Reason", reasons.join(", "), false);
+
+        self.alert_operators(http, alert).await;
+
+        Ok(())
+    }
+
+    fn get_unknown_deposit_internal(&self, storage: &EncryptedStorage, tx_hash: &Hash) -> Result<Option<UnknownDeposit>> {
+        let key: DataValue = tx_hash.clone().into();
+        if !storage.has_custom_data(UNKNOWN_DEPOSITS_TREE, &key)? {
+            return Ok(None);
+        }
+
+        let deposit = storage.get_custom_data(UNKNOWN_DEPOSITS_TREE, &key)?.to_value()?.to_type()?;
+        Ok(Some(deposit))
+    }
+
+    // The transfers of a TX that didn't name a user were already sent back
+    pub(super) fn is_unknown_deposit_refunded(&self, storage: &EncryptedStorage, tx_hash: &Hash) -> Result<bool> {
+        Ok(self.get_unknown_deposit_internal(storage, tx_hash)?.is_some_and(|deposit| deposit.is_resolved()))
+    }
+
+    // Mark an unknown deposit as resolved by the TXs that sent it back
+    pub(super) fn resolve_unknown_deposit(&self, storage: &mut EncryptedStorage, tx_hash: &Hash, refunds: Vec<Hash>) -> Result<()> {
+        let Some(mut deposit) = self.get_unknown_deposit_internal(storage, tx_hash)? else {
+            return Ok(());
+        };
+
+        if deposit.is_resolved() {
+            return Ok(());
+        }
+
+        deposit.refunds = refunds;
+        deposit.resolved_at = Some(get_current_time_in_seconds());
+        storage.set_custom_data(UNKNOWN_DEPOSITS_TREE, &tx_hash.clone().into(), &DataElement::Value(DataValue::Blob(deposit.to_bytes())))?;
+
+        Ok(())
+    }
+
+    // Send the transfers of an unknown deposit back to the address of its TX
+    // Transfers of the same TX credited to a user are left as is
+    // Returns each amount sent back with the hash of its TX
+    pub async fn refund_deposit(&self, tx_hash: &Hash) -> Result<Vec<(AssetBalance, Hash)>, ServiceError> {
+        let (deposit, from, credited) = {
+            let storage = self.wallet.get_storage().read().await;
+            let deposit = self.get_unknown_deposit_internal(&storage, tx_hash)?.ok_or(ServiceError::DepositNotFound)?;
+            let key: DataValue = tx_hash.clone().into();
+            if deposit.is_resolved() || storage.has_custom_data(RETURNED_DEPOSITS_TREE, &key)? {
+                return Err(ServiceError::DepositAlreadyReturned);
+            }
+
+            let entry = storage.get_transaction(tx_hash).map_err(|_| ServiceError::DepositNotFound)?;
+            let EntryType::Incoming { from, .. } = entry.entry else {
+                return Err(ServiceError::DepositNotFound);
+            };
+
+            (deposit, from, storage.has_custom_data(HISTORY_TREE, &key)?)
+        };
+
+        let mut returned = Vec::new();
+        let mut refunds = Vec::new();
+        for transfer in deposit.transfers.iter() {
+            match self.send_back(from.clone(), &transfer.asset, transfer.amount).await {
+                Ok((amount, hash)) => {
+                    let storage = self.wallet.get_storage().read().await;
+                    let amount = self.get_asset_balance_display(&storage, &transfer.asset, amount).await;
+                    info!("Refunded {} of unknown deposit {} to {} in TX {}", amount, tx_hash, from, hash);
+                    returned.push((amount, hash.clone()));
+                    refunds.push(hash);
+                },
+                Err(e) => {
+                    // Transfers already sent must not be sent twice
+                    if !refunds.is_empty() {
+                        warn!("Unknown deposit {} was only partially refunded", tx_hash);
+                        let mut storage = self.wallet.get_storage().write().await;
+                        self.resolve_unknown_deposit(&mut storage, tx_hash, refunds)?;
+                    }
+
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut storage = self.wallet.get_storage().write().await;
+        self.resolve_unknown_deposit(&mut storage, tx_hash, refunds)?;
+        // Nothing else in the TX can be returned
+        if !credited {
+            storage.set_custom_data(RETURNED_DEPOSITS_TREE, &tx_hash.clone().into(), &DataValue::Bool(true).into())?;
+        }
+
+        Ok(returned)
+    }
+
+    // Forget an unknown deposit once it was orphaned
+    pub(super) fn remove_unknown_deposit(&self, storage: &mut EncryptedStorage, tx_hash: &Hash) -> Result<()> {
+        let key: DataValue = tx_hash.clone().into();
+        if storage.has_custom_data(UNKNOWN_DEPOSITS_TREE, &key)? {
+            storage.delete_custom_data(UNKNOWN_DEPOSITS_TREE, &key)?;
+        }
+
+        Ok(())
+    }
+
+    // Get the deposits that couldn't be credited to any user, the resolved ones included, oldest first
+    pub async fn get_unknown_deposits(&self) -> Result<Vec<UnknownDeposit>> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut deposits = Vec::new();
+        for key in storage.get_custom_tree_keys(&UNKNOWN_DEPOSITS_TREE.to_string(), &None, None, None)? {
+            let deposit: UnknownDeposit = storage.get_custom_data(UNKNOWN_DEPOSITS_TREE, &key)?.to_value()?.to_type()?;
+            deposits.push(deposit);
+        }
+
+        deposits.sort_by_key(|deposit| deposit.topoheight);
+
+        Ok(deposits)
+    }
+}
@@ -0,0 +1,319 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use poise::serenity_prelude::{Colour, Http};
+use xelis_common::{
+    api::DataValue,
+    config::XELIS_ASSET,
+    crypto::{Hash, Hashable},
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds,
+    transaction::Transaction
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{
+    batch::BalanceUpdate,
+    ops::Alert,
+    submission::{is_already_in_mempool, is_nonce_conflict, is_transport_error},
+    MovementKind,
+    UserApplication,
+    WalletServiceImpl
+};
+
+// Signed TXs not known to be received by the daemon yet, with the funds debited for them
+// A TX is kept from before its first broadcast, so a crash can't lose it,
+// and broadcast again until the daemon has it
+pub(super) const UNSENT_TRANSACTIONS_TREE: &str = "unsent_transactions";
+// Withdrawals rolled back after the daemon rejected their TX, kept for the operator
+pub(super) const FAILED_WITHDRAWALS_TREE: &str = "failed_withdrawals";
+
+// How often the unsent TXs are broadcast again
+pub(super) const UNSENT_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+// Funds debited for a broadcast, given back if its TX is rejected
+#[derive(Debug, Clone)]
+pub(super) struct Debit {
+    // None if the funds didn't come from a user balance
+    pub user: Option<UserApplication>,
+    pub asset: Hash,
+    pub amount: u64
+}
+
+// A signed TX waiting for the daemon to be reachable
+pub(super) struct UnsentTransaction {
+    debit: Debit,
+    fee: u64,
+    transaction: Transaction
+}
+
+impl Serializer for UnsentTransaction {
+    fn write(&self, writer: &mut Writer) {
+        self.debit.user.write(writer);
+        self.debit.asset.write(writer);
+        self.debit.amount.write(writer);
+        self.fee.write(writer);
+        self.transaction.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let user = Option::read(reader)?;
+        let asset = Hash::read(reader)?;
+        let amount = reader.read_u64()?;
+        let fee = reader.read_u64()?;
+        let transaction = Transaction::read(reader)?;
+
+        Ok(Self {
+            debit: Debit {
+                user,
+                asset,
+                amount
+            },
+            fee,
+            transaction
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FailedWithdrawal {
+    pub tx_hash: Hash,
+    pub user: Option<UserApplication>,
+    pub asset: Hash,
+    pub amount: u64,
+    pub fee: u64,
+    // Error returned by the daemon
+    pub reason: String,
+    pub failed_at: u64
+}
+
+impl Serializer for FailedWithdrawal {
+    fn write(&self, writer: &mut Writer) {
+        self.tx_hash.write(writer);
+        self.user.write(writer);
+        self.asset.write(writer);
+        self.amount.write(writer);
+        self.fee.write(writer);
+        self.reason.write(writer);
+        self.failed_at.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        Ok(Self {
+            tx_hash: Hash::read(reader)?,
+            user: Option::read(reader)?,
+            asset: Hash::read(reader)?,
+            amount: reader.read_u64()?,
+            fee: reader.read_u64()?,
+            reason: String::read(reader)?,
+            failed_at: reader.read_u64()?
+        })
+    }
+}
+
+impl WalletServiceImpl {
+    // New balances of the user of a withdrawal, the fee is always paid in XEL
+    // Amounts are debited with the fee, or credited back when `credit` is set
+    fn withdrawal_updates(&self, storage: &EncryptedStorage, user: &UserApplication, asset: &Hash, amount: u64, fee: u64, credit: bool) -> Result<Vec<BalanceUpdate>> {
+        let apply = |balance: u64, amount: u64| if credit { balance.checked_add(amount) } else { balance.checked_sub(amount) };
+        let mut changes = vec![(XELIS_ASSET, fee)];
+        if *asset == XELIS_ASSET {
+            changes[0].1 += amount;
+        } else {
+            changes.push((asset.clone(), amount));
+        }
+
+        let mut updates = Vec::with_capacity(changes.len());
+        for (asset, amount) in changes {
+            let before = self.get_asset_balance_internal(storage, user, &asset);
+            let after = apply(before, amount).ok_or_else(|| anyhow!("Balance of {:?} in {} can't cover the withdrawal", user, asset))?;
+            updates.push(BalanceUpdate { user: *user, asset, before, after });
+        }
+
+        Ok(updates)
+    }
+
+    // Debit the funds of a withdrawal before its TX is broadcast
+    pub(super) fn debit_withdrawal_internal(&self, storage: &mut EncryptedStorage, debit: &Debit, fee: u64) -> Result<()> {
+        let Some(user) = debit.user.as_ref() else {
+            return Ok(());
+        };
+
+        let updates = self.withdrawal_updates(storage, user, &debit.asset, debit.amount, fee, false)?;
+        self.apply_balance_updates(storage, updates, MovementKind::Withdraw)?;
+        self.post_withdraw_fee(storage, fee)
+    }
+
+    // Give back the funds of a withdrawal whose TX was never sent
+    fn refund_withdrawal_internal(&self, storage: &mut EncryptedStorage, debit: &Debit, fee: u64) -> Result<()> {
+        let Some(user) = debit.user.as_ref() else {
+            return Ok(());
+        };
+
+        let updates = self.withdrawal_updates(storage, user, &debit.asset, debit.amount, fee, true)?;
+        self.apply_balance_updates(storage, updates, MovementKind::Refund)?;
+        self.post_refunded_fee(storage, fee)
+    }
+
+    // The daemon refused a TX on its first broadcast, its funds are given back
+    pub(super) fn cancel_unsent_transaction(&self, storage: &mut EncryptedStorage, tx_hash: &Hash, debit: &Debit, fee: u64) -> Result<()> {
+        self.refund_withdrawal_internal(storage, debit, fee)?;
+        storage.delete_custom_data(UNSENT_TRANSACTIONS_TREE, &DataValue::Blob(tx_hash.to_bytes()))?;
+
+        Ok(())
+    }
+
+    // The daemon received a TX, its funds stay debited
+    pub(super) fn finalize_unsent_transaction(&self, storage: &mut EncryptedStorage, tx_hash: &Hash) -> Result<()> {
+        let key = DataValue::Blob(tx_hash.to_bytes());
+        if storage.has_custom_data(UNSENT_TRANSACTIONS_TREE, &key)? {
+            storage.delete_custom_data(UNSENT_TRANSACTIONS_TREE, &key)?;
+        }
+
+        Ok(())
+    }
+
+    // Keep a TX the daemon may not have received to broadcast it again later
+    pub(super) fn queue_unsent_transaction(&self, storage: &mut EncryptedStorage, transaction: &Transaction, debit: Debit, fee: u64) -> Result<()> {
+        let key = DataValue::Blob(transaction.hash().to_bytes());
+        let unsent = UnsentTransaction {
+            debit,
+            fee,
+            transaction: transaction.clone()
+        };
+
+        storage.set_custom_data(UNSENT_TRANSACTIONS_TREE, &key, &DataValue::Blob(unsent.to_bytes()).into())?;
+        Ok(())
+    }
+
+    // Broadcast the unsent TXs again once the daemon is reachable
+    // A TX already in the history of the wallet was sent before a crash and is only forgotten
+    // A TX rejected by the daemon is rolled back and the user gets its funds back
+    pub(super) async fn retry_unsent_transactions(&self, http: &Http) -> Result<()> {
+        if !self.wallet.is_online().await {
+            return Ok(());
+        }
+
+        let _queue = self.submission_queue.lock().await;
+        let unsent = {
+            let storage = self.wallet.get_storage().read().await;
+            let mut unsent = Vec::new();
+            for key in storage.get_custom_tree_keys(&UNSENT_TRANSACTIONS_TREE.to_string(), &None, None, None)? {
+                let transaction: UnsentTransaction = storage.get_custom_data(UNSENT_TRANSACTIONS_TREE, &key)?.to_value()?.to_type()?;
+                unsent.push((key, transaction));
+            }
+            unsent
+        };
+
+        let mut rolled_back = false;
+        for (key, unsent) in unsent {
+            let tx_hash = unsent.transaction.hash();
+            let sent = {
+                let storage = self.wallet.get_storage().read().await;
+                storage.get_transaction(&tx_hash).is_ok()
+            };
+
+            if sent {
+                info!("Unsent TX {} is already in the wallet history", tx_hash);
+                let mut storage = self.wallet.get_storage().write().await;
+                storage.delete_custom_data(UNSENT_TRANSACTIONS_TREE, &key)?;
+                continue;
+            }
+
+            if let Err(e) = self.wallet.submit_transaction(&unsent.transaction).await {
+                let reason = e.to_string();
+                if !is_already_in_mempool(&reason) {
+                    // Still unreachable, the next TXs would fail the same way
+                    if is_transport_error(&reason) || !self.wallet.is_online().await {
+                        break;
+                    }
+
+                    // Its nonce may be used by the TX itself, already in a block the wallet didn't sync yet,
+                    // it's forgotten once it shows up in the history
+                    if is_nonce_conflict(&reason) {
+                        error!("Unsent TX {} conflicts with another TX, credit its user if it never shows up on-chain: {}", tx_hash, reason);
+                        let alert = Alert::new("Withdrawal Conflict", format!("Unsent TX {} conflicts with another TX, credit its user if it never shows up on-chain", tx_hash), Colour::RED)
+                            .field("Reason", reason, false);
+                        self.report_to_ops(http, &alert).await;
+                        continue;
+                    }
+
+                    self.rollback_unsent_transaction(http, &key, tx_hash, unsent, reason).await?;
+                    rolled_back = true;
+                    continue;
+                }
+            }
+
+            info!("Unsent TX {} has been broadcast", tx_hash);
+            let mut storage = self.wallet.get_storage().write().await;
+            storage.delete_custom_data(UNSENT_TRANSACTIONS_TREE, &key)?;
+        }
+
+        // The wallet state still counts the rejected TXs, it's rebuilt from the chain
+        // and the withdrawals stay locked until an operator checks the balances
+        if rolled_back {
+            self.set_withdraw_locked(true);
+            self.rescan().await?;
+        }
+
+        Ok(())
+    }
+
+    // Give the debited funds back and keep a trace of the failure
+    async fn rollback_unsent_transaction(&self, http: &Http, key: &DataValue, tx_hash: Hash, unsent: UnsentTransaction, reason: String) -> Result<()> {
+        error!("TX {} was rejected by the daemon, rolling it back: {}", tx_hash, reason);
+        let user = match unsent.debit.user {
+            Some(UserApplication::Discord(id)) => format!("Discord <@{}>", id),
+            Some(UserApplication::Telegram(id)) => format!("Telegram {}", id),
+            Some(UserApplication::Matrix(id)) => format!("Matrix {}", id),
+            Some(UserApplication::Slack(id)) => format!("Slack {}", id),
+            Some(UserApplication::Twitch(id)) => format!("Twitch {}", id),
+            None => "Bot wallet".to_string()
+        };
+        let alert = Alert::new("Withdrawal Failed", format!("TX {} was rejected by the daemon, it has been rolled back and the withdrawals are locked", tx_hash), Colour::RED)
+            .field("User", user, false)
+            .field("Reason", reason.as_str(), false);
+        self.report_to_ops(http, &alert).await;
+
+        let mut storage = self.wallet.get_storage().write().await;
+        if unsent.debit.user.is_some() {
+            self.refund_withdrawal_internal(&mut storage, &unsent.debit, unsent.fee)?;
+        } else {
+            warn!("TX {} didn't come from a user balance, nothing to refund", tx_hash);
+        }
+
+        let Debit { user, asset, amount } = unsent.debit;
+
+        let failed = FailedWithdrawal {
+            tx_hash: tx_hash.clone(),
+            user,
+            asset,
+            amount,
+            fee: unsent.fee,
+            reason,
+            failed_at: get_current_time_in_seconds()
+        };
+
+        storage.delete_custom_data(UNSENT_TRANSACTIONS_TREE, key)?;
+        self.take_sent_withdrawal(&mut storage, &tx_hash)?;
+        storage.set_custom_data(FAILED_WITHDRAWALS_TREE, &DataValue::Blob(tx_hash.to_bytes()), &DataValue::Blob(failed.to_bytes()).into())?;
+        self.pending_withdrawals.lock().expect("pending withdrawals lock").remove(&tx_hash);
+
+        Ok(())
+    }
+
+    // Withdrawals rolled back after their TX was rejected, oldest first
+    pub async fn get_failed_withdrawals(&self) -> Result<Vec<FailedWithdrawal>> {
+        let storage = self.wallet.get_storage().read().await;
+        let mut failed = Vec::new();
+        for key in storage.get_custom_tree_keys(&FAILED_WITHDRAWALS_TREE.to_string(), &None, None, None)? {
+            let withdrawal: FailedWithdrawal = storage.get_custom_data(FAILED_WITHDRAWALS_TREE, &key)?.to_value()?.to_type()?;
+            failed.push(withdrawal);
+        }
+
+        failed.sort_by_key(|withdrawal| withdrawal.failed_at);
+
+        Ok(failed)
+    }
+}
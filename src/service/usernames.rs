@@ -0,0 +1,45 @@
+use anyhow::Result;
+use xelis_common::api::DataValue;
+
+use super::WalletServiceImpl;
+
+// Last known Telegram user ID of each username
+// Telegram doesn't provide any way to resolve a username from a bot
+pub(super) const TELEGRAM_USERNAMES_TREE: &str = "telegram_usernames";
+
+// Usernames are case insensitive and may be written with their @
+fn username_key(username: &str) -> DataValue {
+    DataValue::String(username.trim_start_matches('@').to_lowercase())
+}
+
+impl WalletServiceImpl {
+    // Remember the ID behind a Telegram username
+    pub async fn record_telegram_username(&self, username: &str, id: u64) -> Result<()> {
+        let key = username_key(username);
+        {
+            let storage = self.wallet.get_storage().read().await;
+            let known = storage.get_custom_data(TELEGRAM_USERNAMES_TREE, &key)
+                .ok()
+                .and_then(|v| v.to_value().ok())
+                .and_then(|v| v.to_u64().ok());
+
+            if known == Some(id) {
+                return Ok(());
+            }
+        }
+
+        let mut storage = self.wallet.get_storage().write().await;
+        storage.set_custom_data(TELEGRAM_USERNAMES_TREE, &key, &id.into())?;
+
+        Ok(())
+    }
+
+    // Find the Telegram user ID of a username seen by the bot
+    pub async fn get_telegram_user_by_username(&self, username: &str) -> Option<u64> {
+        let storage = self.wallet.get_storage().read().await;
+        storage.get_custom_data(TELEGRAM_USERNAMES_TREE, &username_key(username))
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_u64().ok())
+    }
+}
@@ -0,0 +1,133 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use log::{debug, error, info, warn};
+use poise::serenity_prelude::{Colour, Http};
+use xelis_common::{
+    crypto::{Hash, Hashable},
+    transaction::Transaction
+};
+
+use super::{ops::Alert, submission::is_already_in_mempool, UserApplication, WalletServiceImpl};
+
+// How often the pending withdrawals are checked
+pub(super) const WATCHDOG_INTERVAL: Duration = Duration::from_secs(60);
+// Time a withdrawal has to show up on-chain before it's broadcast again
+const REBROADCAST_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+// Broadcasts of a missing withdrawal before the operators are alerted
+const MAX_REBROADCASTS: u32 = 3;
+
+// A TX sent by the wallet that isn't confirmed yet
+pub(super) struct PendingWithdrawal {
+    transaction: Transaction,
+    // None if the funds didn't come from a user balance
+    user: Option<UserApplication>,
+    sent_at: Instant,
+    // Last time the TX was broadcast
+    broadcast_at: Instant,
+    rebroadcasts: u32,
+    // The TX is in a block, it only waits for the stable topoheight
+    seen: bool,
+    // The operators were already told the TX is missing
+    alerted: bool
+}
+
+impl PendingWithdrawal {
+    fn is_missing(&self, now: Instant) -> bool {
+        !self.seen && now.duration_since(self.broadcast_at) >= REBROADCAST_TIMEOUT
+    }
+}
+
+impl WalletServiceImpl {
+    // Remember a TX sent by the wallet until it's confirmed
+    pub(super) fn track_pending_withdrawal(&self, transaction: &Transaction, user: Option<UserApplication>) {
+        let now = Instant::now();
+        self.pending_withdrawals.lock().expect("pending withdrawals lock").insert(transaction.hash(), PendingWithdrawal {
+            transaction: transaction.clone(),
+            user,
+            sent_at: now,
+            broadcast_at: now,
+            rebroadcasts: 0,
+            seen: false,
+            alerted: false
+        });
+    }
+
+    // The TX of a withdrawal is in a block, no need to broadcast it again
+    pub(super) fn mark_withdrawal_seen(&self, tx_hash: &Hash) {
+        if let Some(withdrawal) = self.pending_withdrawals.lock().expect("pending withdrawals lock").get_mut(tx_hash) {
+            debug!("Withdrawal TX {} is in a block", tx_hash);
+            withdrawal.seen = true;
+        }
+    }
+
+    // Count the withdrawals still missing after all their broadcasts
+    pub fn get_stuck_withdrawals_count(&self) -> usize {
+        self.pending_withdrawals.lock().expect("pending withdrawals lock")
+            .values()
+            .filter(|withdrawal| withdrawal.alerted)
+            .count()
+    }
+
+    // Broadcast again the withdrawals that didn't show up on-chain in time
+    // Once they were broadcast too many times, the operators are alerted instead
+    pub(super) async fn watch_pending_withdrawals(&self, http: &Http) -> Result<()> {
+        if !self.wallet.is_online().await {
+            return Ok(());
+        }
+
+        let (missing, stuck) = {
+            let mut pending = self.pending_withdrawals.lock().expect("pending withdrawals lock");
+            let now = Instant::now();
+            let mut missing = Vec::new();
+            let mut stuck = Vec::new();
+            for (tx_hash, withdrawal) in pending.iter_mut().filter(|(_, withdrawal)| !withdrawal.alerted && withdrawal.is_missing(now)) {
+                if withdrawal.rebroadcasts >= MAX_REBROADCASTS {
+                    withdrawal.alerted = true;
+                    stuck.push((tx_hash.clone(), withdrawal.user, now.duration_since(withdrawal.sent_at)));
+                } else {
+                    missing.push((tx_hash.clone(), withdrawal.transaction.clone()));
+                }
+            }
+
+            (missing, stuck)
+        };
+
+        for (tx_hash, transaction) in missing {
+            match self.wallet.submit_transaction(&transaction).await {
+                Ok(_) => info!("Withdrawal TX {} didn't show up on-chain, it has been broadcast again", tx_hash),
+                Err(e) if is_already_in_mempool(&e.to_string()) => info!("Withdrawal TX {} is still in the mempool", tx_hash),
+                Err(e) => warn!("Couldn't broadcast withdrawal TX {} again: {}", tx_hash, e)
+            }
+
+            if let Some(withdrawal) = self.pending_withdrawals.lock().expect("pending withdrawals lock").get_mut(&tx_hash) {
+                withdrawal.rebroadcasts += 1;
+                withdrawal.broadcast_at = Instant::now();
+            }
+        }
+
+        for (tx_hash, user, elapsed) in stuck {
+            error!("Withdrawal TX {} of {:?} is still not on-chain after {} minutes and {} broadcasts", tx_hash, user, elapsed.as_secs() / 60, MAX_REBROADCASTS + 1);
+            self.alert_missing_withdrawal(http, &tx_hash, user, elapsed).await;
+        }
+
+        Ok(())
+    }
+
+    // Tell the operators about a withdrawal that is debited but not on-chain
+    async fn alert_missing_withdrawal(&self, http: &Http, tx_hash: &Hash, user: Option<UserApplication>, elapsed: Duration) {
+        let user = match user {
+            Some(UserApplication::Discord(id)) => format!("Discord <@{}>", id),
+            Some(UserApplication::Telegram(id)) => format!("Telegram {}", id),
+            Some(UserApplication::Matrix(id)) => format!("Matrix {}", id),
+            Some(UserApplication::Slack(id)) => format!("Slack {}", id),
+            Some(UserApplication::Twitch(id)) => format!("Twitch {}", id),
+            None => "Bot wallet".to_string()
+        };
+
+        let alert = Alert::new("Withdrawal Missing", format!("TX {} was broadcast {} times but is still not on-chain after {} minutes", tx_hash, MAX_REBROADCASTS + 1, elapsed.as_secs() / 60), Colour::RED)
+            .field("User", user, false);
+
+        self.alert_operators(http, alert).await;
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::Result;
+use xelis_common::{
+    api::DataValue,
+    crypto::Address,
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{ServiceError, UserApplication, WalletServiceImpl};
+
+// Users that only withdraw to the addresses of their address book
+pub(super) const WITHDRAW_WHITELIST_TREE: &str = "withdraw_whitelist";
+
+// Whitelist of a user, as stored
+pub(super) struct WhitelistEntry {
+    // Timestamp in seconds when the whitelist stops, once the user asked to turn it off
+    disable_at: Option<u64>
+}
+
+impl Serializer for WhitelistEntry {
+    fn write(&self, writer: &mut Writer) {
+        self.disable_at.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        Ok(Self {
+            disable_at: Option::read(reader)?
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhitelistStatus {
+    Disabled,
+    Enabled,
+    // Turned off by the user, still enforced until this timestamp in seconds
+    Disabling(u64)
+}
+
+impl WalletServiceImpl {
+    fn get_whitelist_status_internal(&self, storage: &EncryptedStorage, user: &UserApplication) -> WhitelistStatus {
+        let entry: Option<WhitelistEntry> = storage.get_custom_data(WITHDRAW_WHITELIST_TREE, &user.into())
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_type().ok());
+
+        match entry {
+            None => WhitelistStatus::Disabled,
+            Some(WhitelistEntry { disable_at: None }) => WhitelistStatus::Enabled,
+            Some(WhitelistEntry { disable_at: Some(at) }) if at > get_current_time_in_seconds() => WhitelistStatus::Disabling(at),
+            Some(_) => WhitelistStatus::Disabled
+        }
+    }
+
+    // Get the withdrawal whitelist of a user
+    pub async fn get_whitelist_status(&self, user: &UserApplication) -> WhitelistStatus {
+        let storage = self.wallet.get_storage().read().await;
+        self.get_whitelist_status_internal(&storage, user)
+    }
+
+    // Turn the whitelist on right away, or off once the whitelist delay has passed
+    // The delay keeps someone who took over the account from turning it off to withdraw
+    pub async fn set_whitelist_enabled(&self, user: &UserApplication, enabled: bool) -> Result<WhitelistStatus> {
+        let mut storage = self.wallet.get_storage().write().await;
+        let status = match (self.get_whitelist_status_internal(&storage, user), enabled) {
+            (WhitelistStatus::Disabled, false) => return Ok(WhitelistStatus::Disabled),
+            (status @ WhitelistStatus::Disabling(_), false) => return Ok(status),
+            (_, true) => WhitelistStatus::Enabled,
+            (WhitelistStatus::Enabled, false) => WhitelistStatus::Disabling(get_current_time_in_seconds() + self.runtime().whitelist_delay)
+        };
+
+        let entry = WhitelistEntry {
+            disable_at: match status {
+                WhitelistStatus::Disabling(at) => Some(at),
+                _ => None
+            }
+        };
+        storage.set_custom_data(WITHDRAW_WHITELIST_TREE, &user.into(), &DataValue::Blob(entry.to_bytes()).into())?;
+
+        Ok(status)
+    }
+
+    // Check that a user with a whitelist withdraws to an address saved long enough ago
+    pub(super) fn check_withdraw_whitelist(&self, storage: &EncryptedStorage, user: &UserApplication, to: &Address) -> Result<(), ServiceError> {
+        if self.get_whitelist_status_internal(storage, user) == WhitelistStatus::Disabled {
+            return Ok(());
+        }
+
+        let Some(added_at) = self.get_address_added_at(storage, user, to)? else {
+            return Err(ServiceError::AddressNotWhitelisted);
+        };
+
+        let usable_at = added_at + self.runtime().whitelist_delay;
+        let now = get_current_time_in_seconds();
+        if usable_at > now {
+            return Err(ServiceError::AddressNotYetWhitelisted(usable_at - now));
+        }
+
+        Ok(())
+    }
+
+    // Delay in seconds before a saved address can be used, or the whitelist is turned off
+    pub fn get_whitelist_delay(&self) -> u64 {
+        self.runtime().whitelist_delay
+    }
+}
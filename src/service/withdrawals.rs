@@ -0,0 +1,101 @@
+use anyhow::Result;
+use xelis_common::{
+    api::DataValue,
+    serializer::{Reader, ReaderError, Serializer, Writer},
+    time::get_current_time_in_seconds
+};
+use xelis_wallet::storage::EncryptedStorage;
+
+use super::{ServiceError, UserApplication, WalletServiceImpl};
+
+// XEL withdrawn by each user over the last 24 hours
+pub(super) const WITHDRAWALS_TREE: &str = "withdrawals";
+
+// Length of the rolling window of the withdrawal limit
+const WITHDRAW_LIMIT_WINDOW: u64 = 24 * 60 * 60;
+
+// Withdrawals of a user still in the rolling window
+#[derive(Debug, Clone, Default)]
+pub(super) struct RecentWithdrawals {
+    // Timestamp in seconds and amount of each withdrawal
+    entries: Vec<(u64, u64)>
+}
+
+impl RecentWithdrawals {
+    // Drop the withdrawals that left the window
+    fn prune(&mut self, now: u64) {
+        self.entries.retain(|(timestamp, _)| timestamp + WITHDRAW_LIMIT_WINDOW > now);
+    }
+
+    fn total(&self) -> u64 {
+        self.entries.iter().map(|(_, amount)| *amount).fold(0, u64::saturating_add)
+    }
+}
+
+impl Serializer for RecentWithdrawals {
+    fn write(&self, writer: &mut Writer) {
+        (self.entries.len() as u16).write(writer);
+        for (timestamp, amount) in self.entries.iter() {
+            timestamp.write(writer);
+            amount.write(writer);
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, ReaderError> {
+        let len = reader.read_u16()?;
+        let mut entries = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            entries.push((reader.read_u64()?, reader.read_u64()?));
+        }
+
+        Ok(Self { entries })
+    }
+}
+
+impl WalletServiceImpl {
+    fn get_recent_withdrawals(&self, storage: &EncryptedStorage, user: &UserApplication) -> RecentWithdrawals {
+        let mut withdrawals: RecentWithdrawals = storage.get_custom_data(WITHDRAWALS_TREE, &user.into())
+            .ok()
+            .and_then(|v| v.to_value().ok())
+            .and_then(|v| v.to_type().ok())
+            .unwrap_or_default();
+
+        withdrawals.prune(get_current_time_in_seconds());
+        withdrawals
+    }
+
+    // XEL a user can still withdraw in the rolling window, None without any limit
+    // Users exempted by the operator are not limited
+    pub async fn get_remaining_withdraw_limit(&self, user: &UserApplication) -> Option<u64> {
+        let limit = self.runtime().max_daily_withdraw?;
+        if self.is_exempt(user, &[]).await {
+            return None;
+        }
+
+        let storage = self.wallet.get_storage().read().await;
+        Some(limit.saturating_sub(self.get_recent_withdrawals(&storage, user).total()))
+    }
+
+    // Check that a XEL withdrawal fits in the limit of the user
+    pub(super) fn check_withdraw_limit(&self, storage: &EncryptedStorage, user: &UserApplication, amount: u64) -> Result<(), ServiceError> {
+        let Some(limit) = self.runtime().max_daily_withdraw else {
+            return Ok(());
+        };
+
+        let remaining = limit.saturating_sub(self.get_recent_withdrawals(storage, user).total());
+        if amount > remaining {
+            return Err(ServiceError::WithdrawLimit(limit, remaining));
+        }
+
+        Ok(())
+    }
+
+    // Count a XEL withdrawal in the rolling window of the user
+    pub(super) fn record_withdrawal(&self, storage: &mut EncryptedStorage, user: &UserApplication, amount: u64) -> Result<()> {
+        let mut withdrawals = self.get_recent_withdrawals(storage, user);
+        withdrawals.entries.push((get_current_time_in_seconds(), amount));
+        storage.set_custom_data(WITHDRAWALS_TREE, &user.into(), &DataValue::Blob(withdrawals.to_bytes()).into())?;
+
+        Ok(())
+    }
+}
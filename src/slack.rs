@@ -0,0 +1,492 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::{bail, Error, Result};
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Json,
+    Router
+};
+use hmac::{Hmac, Mac};
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::net::TcpListener;
+use xelis_common::{api::DataValue, time::get_current_time_in_seconds, utils::format_xelis};
+
+use crate::{
+    amount::{parse_amount, Amount},
+    command_amount_args,
+    parse_asset_amount,
+    parse_withdraw_amount,
+    platform::{self, ChatPlatform, Reply, SlackPlatform},
+    service::{slack_community, Setting, SlackWorkspace, UserApplication, WalletService},
+    slack_api::SlackApi,
+    telegram_message::escape_html,
+    DEPOSITS_PAUSED_MESSAGE,
+    DEPOSITS_PAUSED_TITLE
+};
+
+// Subcommands of the /xelis slash command
+const SLACK_COMMANDS: [(&str, &str); 7] = [
+    ("/xelis help", "Show the available commands"),
+    ("/xelis balance", "Show your balance"),
+    ("/xelis deposit", "Show your deposit address"),
+    ("/xelis tip <@user> <amount> [asset]", "Tip a Slack user"),
+    ("/xelis withdraw <address|label> <amount> [asset]", "Withdraw to an address"),
+    ("/xelis reminders [on|off]", "Enable or disable the reminders of your unused balance"),
+    ("/xelis settings [tips public|private] [mintip <amount|off>] [bot on|off]", "Show or change the settings of the workspace, for its admins")
+];
+
+// Permissions asked to a workspace installing the app
+const SLACK_SCOPES: &str = "commands,chat:write,im:write,users:read";
+
+// Requests signed longer ago are refused, so they can't be replayed
+const MAX_REQUEST_AGE: u64 = 5 * 60;
+// Time given to a workspace admin to approve the installation
+const MAX_INSTALL_AGE: u64 = 10 * 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone)]
+pub struct SlackConfig {
+    pub address: SocketAddr,
+    pub client_id: String,
+    pub client_secret: String,
+    // Signs the requests sent by Slack
+    pub signing_secret: String,
+    // URL at which Slack reaches the address, such as https://tipbot.example.com
+    pub public_url: String
+}
+
+// Form posted by Slack when a user runs /xelis
+#[derive(Debug, Clone, Deserialize)]
+pub struct SlashCommand {
+    pub text: String,
+    pub user_id: String,
+    pub user_name: String,
+    pub team_id: String,
+    pub channel_id: String,
+    // Answers the command up to 5 times within 30 minutes
+    pub response_url: String,
+    pub trigger_id: String
+}
+
+#[derive(Deserialize)]
+struct OAuthParams {
+    code: Option<String>,
+    state: Option<String>,
+    // Set when the admin cancelled the installation
+    error: Option<String>
+}
+
+struct SlackState {
+    service: WalletService,
+    api: SlackApi,
+    config: SlackConfig
+}
+
+pub struct SlackServer {
+    config: SlackConfig,
+    api: SlackApi
+}
+
+impl SlackServer {
+    pub fn new(config: SlackConfig) -> Self {
+        Self {
+            config,
+            api: SlackApi::new()
+        }
+    }
+
+    // Used by the service to send the DMs
+    pub fn api(&self) -> SlackApi {
+        self.api.clone()
+    }
+
+    // Bind the address, then answer the slash commands, events and installations in the background
+    pub async fn start(self, service: WalletService) -> Result<()> {
+        let listener = TcpListener::bind(self.config.address).await?;
+        info!("Slack server listening on {}, install the app at {}/slack/install", self.config.address, self.config.public_url);
+
+        let state = Arc::new(SlackState {
+            service,
+            api: self.api,
+            config: self.config
+        });
+        let router = Router::new()
+            .route("/slack/commands", post(slash_command))
+            .route("/slack/events", post(event))
+            .route("/slack/install", get(install))
+            .route("/slack/oauth", get(oauth))
+            .with_state(state);
+
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                warn!("Slack server stopped: {}", e);
+            }
+        });
+
+        Ok(())
+    }
+}
+
+fn hmac(secret: &str) -> HmacSha256 {
+    HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size")
+}
+
+// Slack signs each request with v0=HMAC-SHA256("v0:{timestamp}:{body}") of the signing secret
+fn is_signed(signing_secret: &str, headers: &HeaderMap, body: &[u8]) -> bool {
+    let timestamp = headers.get("X-Slack-Request-Timestamp")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let signature = headers.get("X-Slack-Signature")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("v0="))
+        .and_then(|value| hex::decode(value).ok());
+
+    let (Some(timestamp), Some(signature)) = (timestamp, signature) else {
+        return false;
+    };
+
+    if get_current_time_in_seconds().abs_diff(timestamp) > MAX_REQUEST_AGE {
+        return false;
+    }
+
+    let mut mac = hmac(signing_secret);
+    mac.update(format!("v0:{}:", timestamp).as_bytes());
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+// State of an installation, so the OAuth redirect only accepts the installations started here
+fn install_state(client_secret: &str, timestamp: u64) -> String {
+    let mut mac = hmac(client_secret);
+    mac.update(format!("install:{}", timestamp).as_bytes());
+    format!("{}.{}", timestamp, hex::encode(mac.finalize().into_bytes()))
+}
+
+fn is_install_state_valid(client_secret: &str, state: &str) -> bool {
+    let Some((timestamp, signature)) = state.split_once('.') else {
+        return false;
+    };
+    let (Ok(timestamp), Ok(signature)) = (timestamp.parse::<u64>(), hex::decode(signature)) else {
+        return false;
+    };
+
+    if get_current_time_in_seconds().saturating_sub(timestamp) > MAX_INSTALL_AGE {
+        return false;
+    }
+
+    let mut mac = hmac(client_secret);
+    mac.update(format!("install:{}", timestamp).as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+// Slack expects an answer within 3 seconds, the command is answered later through its response URL
+async fn slash_command(State(state): State<Arc<SlackState>>, headers: HeaderMap, body: Bytes) -> Response {
+    if !is_signed(&state.config.signing_secret, &headers, &body) {
+        warn!("Refused a Slack command with an invalid signature");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let command: SlashCommand = match serde_urlencoded::from_bytes(&body) {
+        Ok(command) => command,
+        Err(e) => {
+            debug!("Invalid Slack command: {}", e);
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+
+    // Like Telegram, only the leader answers the commands
+    if !state.service.is_leader() {
+        return Json(json!({ "response_type": "ephemeral", "text": "The bot is restarting, please try again in a moment" })).into_response();
+    }
+
+    tokio::spawn(async move {
+        if let Err(e) = handle_command(&state, &command).await {
+            state.service.api_errors().record_error(&e);
+            error!("Error while answering the Slack command of {}: {:?}", command.user_id, e);
+        }
+    });
+
+    StatusCode::OK.into_response()
+}
+
+// Slack retries the events that weren't acknowledged, the standby lets the leader handle them
+async fn event(State(state): State<Arc<SlackState>>, headers: HeaderMap, body: Bytes) -> Response {
+    if !is_signed(&state.config.signing_secret, &headers, &body) {
+        warn!("Refused a Slack event with an invalid signature");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let Ok(payload) = serde_json::from_slice::<Value>(&body) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    // Sent once when the events URL is set in the app settings
+    if payload["type"] == "url_verification" {
+        return Json(json!({ "challenge": payload["challenge"] })).into_response();
+    }
+
+    if !state.service.is_leader() {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+
+    let (Some(team_id), Some(kind)) = (payload["team_id"].as_str(), payload["event"]["type"].as_str()) else {
+        return StatusCode::OK.into_response();
+    };
+
+    if matches!(kind, "app_uninstalled" | "tokens_revoked") {
+        if let Err(e) = state.service.forget_slack_workspace(team_id).await {
+            error!("Couldn't forget the Slack workspace {}: {:?}", team_id, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    StatusCode::OK.into_response()
+}
+
+// Link shared with the workspaces, it sends their admin to the Slack authorization page
+async fn install(State(state): State<Arc<SlackState>>) -> Response {
+    let redirect_uri = format!("{}/slack/oauth", state.config.public_url);
+    let install_state = install_state(&state.config.client_secret, get_current_time_in_seconds());
+    let params = [
+        ("client_id", state.config.client_id.as_str()),
+        ("scope", SLACK_SCOPES),
+        ("redirect_uri", redirect_uri.as_str()),
+        ("state", install_state.as_str())
+    ];
+
+    match reqwest::Url::parse_with_params("https://slack.com/oauth/v2/authorize", &params) {
+        Ok(url) => Redirect::to(url.as_str()).into_response(),
+        Err(e) => {
+            error!("Couldn't build the Slack authorization URL: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn install_page(status: StatusCode, text: &str) -> Response {
+    (status, Html(format!("<!DOCTYPE html><html><head><title>XELIS Tip Bot</title></head><body><p>{}</p></body></html>", escape_html(text)))).into_response()
+}
+
+// Slack redirects the admin here once the installation is approved, with a code for the bot token
+async fn oauth(State(state): State<Arc<SlackState>>, Query(params): Query<OAuthParams>) -> Response {
+    if let Some(error) = params.error {
+        return install_page(StatusCode::BAD_REQUEST, &format!("The installation was cancelled: {}", error));
+    }
+
+    let (Some(code), Some(install_state)) = (params.code, params.state) else {
+        return install_page(StatusCode::BAD_REQUEST, "The installation link is incomplete, please start it again");
+    };
+
+    if !is_install_state_valid(&state.config.client_secret, &install_state) {
+        return install_page(StatusCode::BAD_REQUEST, "The installation link expired, please start it again");
+    }
+
+    if !state.service.is_leader() {
+        return install_page(StatusCode::SERVICE_UNAVAILABLE, "The bot is restarting, please start the installation again in a moment");
+    }
+
+    match record_installation(&state, &code).await {
+        Ok(workspace) => install_page(StatusCode::OK, &format!("XELIS Tip Bot was added to {}, use /xelis help in Slack to get started", workspace.name)),
+        Err(e) => {
+            state.service.api_errors().record_error(&e);
+            error!("Couldn't install the Slack app: {:?}", e);
+            install_page(StatusCode::INTERNAL_SERVER_ERROR, "The installation failed, please try again later")
+        }
+    }
+}
+
+async fn record_installation(state: &SlackState, code: &str) -> Result<SlackWorkspace> {
+    let redirect_uri = format!("{}/slack/oauth", state.config.public_url);
+    let response = state.api.oauth_access(&state.config.client_id, &state.config.client_secret, code, &redirect_uri).await?;
+    let (Some(bot_token), Some(team_id)) = (response["access_token"].as_str(), response["team"]["id"].as_str()) else {
+        bail!("Slack didn't give the bot token of the installation");
+    };
+
+    let workspace = SlackWorkspace {
+        team_id: team_id.to_string(),
+        name: response["team"]["name"].as_str().unwrap_or(team_id).to_string(),
+        bot_token: bot_token.to_string()
+    };
+    state.service.record_slack_workspace(&workspace).await?;
+
+    Ok(workspace)
+}
+
+// Slack escapes the mentions of a slash command as <@U024BE7LH|name>
+fn parse_mention(text: &str) -> Option<(&str, Option<&str>)> {
+    let mention = text.strip_prefix("<@")?.strip_suffix('>')?;
+    match mention.split_once('|') {
+        Some((id, name)) => Some((id, Some(name))),
+        None => Some((mention, None))
+    }
+}
+
+async fn handle_command(state: &SlackState, command: &SlashCommand) -> Result<(), Error> {
+    let service = &state.service;
+    let mut args = command.text.split_whitespace();
+    let subcommand = args.next().map(str::to_lowercase).unwrap_or_else(|| "help".to_string());
+    let platform = SlackPlatform::new(&state.api, command);
+
+    let Some(workspace) = service.get_slack_workspace(&command.team_id).await? else {
+        return platform.send(Reply::error("XELIS Tip Bot", "This workspace must install the app again", format!("{}/slack/install", state.config.public_url))).await;
+    };
+
+    if subcommand != "settings" && service.is_setting_enabled(&slack_community(&command.team_id), Setting::BotDisabled).await {
+        return platform.send(Reply::error("XELIS Tip Bot", "The bot is disabled in this workspace", "An admin can enable it with /xelis settings bot on")).await;
+    }
+
+    // Known before the user gets a balance, so it can be sent DMs
+    let user = service.record_slack_user(&command.team_id, &command.user_id).await?;
+    if let Some(limited) = service.check_rate_limit(&user, &[], &format!("/xelis {}", subcommand)).await {
+        // Answer a flood only once
+        if !limited.notified {
+            platform.send(Reply::error("Rate limit", "Please wait", limited)).await?;
+        }
+        return Ok(());
+    }
+
+    if let Err(e) = service.record_user_activity(&user).await {
+        warn!("Couldn't record activity of {}: {}", command.user_id, e);
+    }
+
+    match subcommand.as_str() {
+        "balance" => platform::balance(service, &platform).await,
+        "deposit" => {
+            let address = service.get_address_for_user(&user);
+            let mut reply = Reply::new("Deposit")
+                .description("Please do not send any other coins than XELIS to this address")
+                .code_field("Your deposit address is", address.to_string());
+
+            if service.are_deposits_paused().await {
+                reply = reply.field(DEPOSITS_PAUSED_TITLE, DEPOSITS_PAUSED_MESSAGE, false);
+            }
+
+            platform.send(reply.private()).await
+        },
+        "tip" => {
+            let (Some(to), Some(amount)) = (args.next(), args.next()) else {
+                return platform.send(Reply::error("Tip", "Usage", "/xelis tip <@user> <amount> [asset]")).await;
+            };
+
+            let Some((to_id, to_name)) = parse_mention(to) else {
+                return platform.send(Reply::error("Tip", "An error occured while tipping", format!("{} isn't a Slack user, mention them with @", to))).await;
+            };
+            let to_name = to_name.map(|name| format!("@{}", name)).unwrap_or_else(|| to_id.to_string());
+
+            let amount = match parse_asset_amount(service, &user, args.next(), amount).await {
+                Ok(amount) if amount.rounded || amount.conversion.is_some() => {
+                    let command = format!("/xelis tip {} {}", to_name, command_amount_args(&amount.amount));
+                    return platform.send(platform::confirm_amount("Tip", &amount.amount, &command)).await;
+                },
+                Ok(amount) => amount.amount,
+                Err(e) => return platform.send(Reply::error("Tip", "An error occured while tipping", e)).await
+            };
+
+            let recipient = match service.record_slack_user(&command.team_id, to_id).await {
+                Ok(recipient) => recipient,
+                Err(e) => return platform.send(Reply::error("Tip", "An error occured while tipping", e)).await
+            };
+
+            platform::tip(service, &platform, &recipient, &to_name, &amount, None).await
+        },
+        "withdraw" => {
+            let (Some(address), Some(amount)) = (args.next(), args.next()) else {
+                return platform.send(Reply::error("Withdraw", "Usage", "/xelis withdraw <address|label> <amount> [asset]")).await;
+            };
+
+            let to = match service.resolve_address(&user, address).await {
+                Ok(to) => to,
+                Err(e) => return platform.send(Reply::error("Withdraw", "An error occured while withdrawing", e)).await
+            };
+
+            let amount = match parse_withdraw_amount(service, &user, &to, args.next(), amount).await {
+                Ok(amount) if amount.rounded || amount.conversion.is_some() => {
+                    let command = format!("/xelis withdraw {} {}", address, command_amount_args(&amount.amount));
+                    return platform.send(platform::confirm_amount("Withdraw", &amount.amount, &command)).await;
+                },
+                Ok(amount) => amount.amount,
+                Err(e) => return platform.send(Reply::error("Withdraw", "An error occured while withdrawing", e)).await
+            };
+
+            platform::withdraw(service, &platform, to, &amount, None, None).await
+        },
+        "reminders" => reminders(service, &platform, &user, args.next()).await,
+        "settings" => settings(state, &platform, &workspace, command, args.next(), args.next()).await,
+        _ => {
+            let mut reply = Reply::new("Help");
+            for (usage, description) in SLACK_COMMANDS {
+                reply = reply.field(usage, description, false);
+            }
+            platform.send(reply.private()).await
+        }
+    }
+}
+
+async fn reminders(service: &WalletService, platform: &SlackPlatform<'_>, user: &UserApplication, enabled: Option<&str>) -> Result<(), Error> {
+    match enabled.map(str::to_lowercase).as_deref() {
+        Some("on") => service.set_reminders_enabled(user, true).await?,
+        Some("off") => service.set_reminders_enabled(user, false).await?,
+        None => {},
+        Some(_) => return platform.send(Reply::error("Reminders", "Usage", "/xelis reminders [on|off]")).await
+    }
+
+    let description = if service.are_reminders_enabled(user).await {
+        "You will be reminded when your balance is left unused for a long time"
+    } else {
+        "You won't be reminded of your unused balance"
+    };
+
+    platform.send(Reply::new("Reminders").description(description).private()).await
+}
+
+// Settings of the workspace, like those of a Telegram group
+async fn settings(state: &SlackState, platform: &SlackPlatform<'_>, workspace: &SlackWorkspace, command: &SlashCommand, setting: Option<&str>, value: Option<&str>) -> Result<(), Error> {
+    let service = &state.service;
+    let community = slack_community(&command.team_id);
+    let usage = "/xelis settings [tips public|private] [mintip <amount|off>] [bot on|off]";
+
+    let info = state.api.call(&workspace.bot_token, "users.info", &json!({ "user": command.user_id })).await?;
+    let is_admin = info["user"]["is_admin"].as_bool().unwrap_or(false) || info["user"]["is_owner"].as_bool().unwrap_or(false);
+    if !is_admin {
+        return platform.send(Reply::error("Workspace settings", "Not allowed", "Only the admins of the workspace can use /xelis settings")).await;
+    }
+
+    match (setting, value) {
+        (None, _) => {},
+        (Some("tips"), Some(mode @ ("public" | "private"))) => {
+            service.set_setting(&community, Setting::PrivateTips, Some(DataValue::Bool(true)).filter(|_| mode == "private")).await?;
+        },
+        (Some("mintip"), Some(amount)) => {
+            let min_tip = match amount {
+                "off" => Ok(0),
+                amount => parse_amount(amount).and_then(Amount::exact)
+            };
+
+            match min_tip {
+                Ok(min_tip) => service.set_setting(&community, Setting::MinTip, Some(DataValue::U64(min_tip)).filter(|_| min_tip > 0)).await?,
+                Err(e) => return platform.send(Reply::error("Workspace settings", "An error occured while setting the minimum tip", e)).await
+            }
+        },
+        (Some("bot"), Some(mode @ ("on" | "off"))) => {
+            service.set_setting(&community, Setting::BotDisabled, Some(DataValue::Bool(true)).filter(|_| mode == "off")).await?;
+        },
+        _ => return platform.send(Reply::error("Workspace settings", "Usage", usage)).await
+    }
+
+    let min_tip = service.get_min_tip(&community).await;
+    let reply = Reply::new("Workspace settings")
+        .field("Tips", if service.is_setting_enabled(&community, Setting::PrivateTips).await { "Only shown to the tipper" } else { "Announced in the channel" }, true)
+        .field("Minimum tip", if min_tip > 0 { format!("{} XEL", format_xelis(min_tip)) } else { "None".to_string() }, true)
+        .field("Bot", if service.is_setting_enabled(&community, Setting::BotDisabled).await { "Disabled" } else { "Enabled" }, true)
+        .private();
+
+    platform.send(reply).await
+}
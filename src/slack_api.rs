@@ -0,0 +1,79 @@
+use reqwest::StatusCode;
+use serde_json::Value;
+use thiserror::Error;
+
+const SLACK_API_URL: &str = "https://slack.com/api";
+
+#[derive(Debug, Error)]
+pub enum SlackApiError {
+    #[error("Slack couldn't be reached: {0}")]
+    Network(#[from] reqwest::Error),
+    #[error("Slack answered with the HTTP status {0}")]
+    Status(StatusCode),
+    // Code of the error, such as invalid_auth or ratelimited
+    #[error("Slack answered {0}")]
+    Api(String)
+}
+
+// Client of the Slack Web API, each workspace has its own bot token
+#[derive(Clone)]
+pub struct SlackApi {
+    client: reqwest::Client
+}
+
+impl SlackApi {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new()
+        }
+    }
+
+    // Slack answers 200 to most errors, they're told by the ok field of the body
+    async fn read(response: reqwest::Response) -> Result<Value, SlackApiError> {
+        let status = response.status();
+        if !status.is_success() {
+            return Err(SlackApiError::Status(status));
+        }
+
+        let body: Value = response.json().await?;
+        if body["ok"].as_bool() != Some(true) {
+            let error = body["error"].as_str().unwrap_or("unknown_error");
+            return Err(SlackApiError::Api(error.to_string()));
+        }
+
+        Ok(body)
+    }
+
+    // Call a method of the Web API with the bot token of a workspace
+    pub async fn call(&self, token: &str, method: &str, params: &Value) -> Result<Value, SlackApiError> {
+        let response = self.client.post(format!("{}/{}", SLACK_API_URL, method))
+            .bearer_auth(token)
+            .json(params)
+            .send().await?;
+
+        Self::read(response).await
+    }
+
+    // Exchange the code given once a workspace installed the app for its bot token
+    pub async fn oauth_access(&self, client_id: &str, client_secret: &str, code: &str, redirect_uri: &str) -> Result<Value, SlackApiError> {
+        let response = self.client.post(format!("{}/oauth.v2.access", SLACK_API_URL))
+            .basic_auth(client_id, Some(client_secret))
+            .form(&[("code", code), ("redirect_uri", redirect_uri)])
+            .send().await?;
+
+        Self::read(response).await
+    }
+
+    // Answer a slash command through its response URL, no token is needed
+    pub async fn respond(&self, response_url: &str, message: &Value) -> Result<(), SlackApiError> {
+        let response = self.client.post(response_url)
+            .json(message)
+            .send().await?;
+
+        if !response.status().is_success() {
+            return Err(SlackApiError::Status(response.status()));
+        }
+
+        Ok(())
+    }
+}
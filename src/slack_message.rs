@@ -0,0 +1,44 @@
+// Escape a user provided text so it can't break the mrkdwn formatting or mention someone
+pub fn escape_mrkdwn(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// Message sent on Slack, formatted in mrkdwn
+// Its texts are plain, they're escaped when added
+#[derive(Default)]
+pub struct SlackMessage {
+    lines: Vec<String>
+}
+
+impl SlackMessage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, text: &str) -> Self {
+        self.lines.push(format!("*{}*", escape_mrkdwn(text)));
+        self
+    }
+
+    pub fn line(mut self, text: &str) -> Self {
+        self.lines.push(escape_mrkdwn(text));
+        self
+    }
+
+    pub fn field(mut self, name: &str, value: &str) -> Self {
+        self.lines.push(format!("*{}*\n{}", escape_mrkdwn(name), escape_mrkdwn(value)));
+        self
+    }
+
+    // Value shown in a monospace font, such as an address
+    pub fn code_field(mut self, name: &str, value: &str) -> Self {
+        self.lines.push(format!("*{}*\n`{}`", escape_mrkdwn(name), escape_mrkdwn(value)));
+        self
+    }
+
+    pub fn into_text(self) -> String {
+        self.lines.join("\n\n")
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    env,
+    io,
+    time::{Duration, Instant}
+};
+
+use log::{debug, error, info, warn};
+
+use crate::{health::is_stalled, service::WalletService};
+
+// Send a state to systemd, when it started the bot with Type=notify
+// Returns false when the bot wasn't started by systemd
+#[cfg(unix)]
+fn notify(state: &str) -> io::Result<bool> {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+
+    let socket = UnixDatagram::unbound()?;
+    // A path starting with @ is an abstract socket
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.as_encoded_bytes().strip_prefix(b"@") {
+        use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+        socket.send_to_addr(state.as_bytes(), &SocketAddr::from_abstract_name(name)?)?;
+        return Ok(true);
+    }
+
+    socket.send_to(state.as_bytes(), &path)?;
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn notify(_: &str) -> io::Result<bool> {
+    Ok(false)
+}
+
+// A notification that can't be sent isn't fatal, systemd times the bot out if it matters
+fn send(state: &str) {
+    match notify(state) {
+        Ok(true) => debug!("Sent {} to systemd", state.replace('\n', " ")),
+        Ok(false) => {},
+        Err(e) => warn!("Couldn't notify systemd: {}", e)
+    }
+}
+
+// Phase of the startup, shown by systemctl status
+pub fn notify_status(status: &str) {
+    send(&format!("STATUS={}", status));
+}
+
+// Every phase of the startup succeeded, the units ordered after the bot can start
+pub fn notify_ready() {
+    send("READY=1\nSTATUS=Running");
+}
+
+pub fn notify_stopping() {
+    send("STOPPING=1\nSTATUS=Stopping");
+}
+
+// Interval systemd expects a ping at, if WatchdogSec= is set for the bot
+fn watchdog_timeout() -> Option<Duration> {
+    // Set for the main process only, not for the ones it spawns
+    if let Some(pid) = env::var("WATCHDOG_PID").ok().and_then(|pid| pid.parse::<u32>().ok()) {
+        if pid != std::process::id() {
+            return None;
+        }
+    }
+
+    env::var("WATCHDOG_USEC").ok()
+        .and_then(|usec| usec.parse().ok())
+        .filter(|usec| *usec > 0)
+        .map(Duration::from_micros)
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchdogConfig {
+    // Time without any event after which the event loop is stalled, as for the health endpoint
+    pub max_event_age: Duration
+}
+
+// Ping the systemd watchdog while the bot is responsive, so systemd restarts it when it hangs
+// The pings run on the same runtime as the Discord and Telegram dispatchers, they stop if it's blocked
+// They also stop while the event loop is stalled
+pub struct SystemdWatchdog {
+    config: WatchdogConfig
+}
+
+impl SystemdWatchdog {
+    pub fn new(config: WatchdogConfig) -> Self {
+        Self { config }
+    }
+
+    // Ping twice per timeout in the background, does nothing without WatchdogSec=
+    pub fn start(self, service: WalletService) {
+        let Some(timeout) = watchdog_timeout() else {
+            return;
+        };
+
+        info!("systemd watchdog enabled, timeout of {} seconds", timeout.as_secs());
+        let started_at = Instant::now();
+        tokio::spawn(async move {
+            let mut stalled = false;
+            loop {
+                tokio::time::sleep(timeout / 2).await;
+                let healthy = match service.get_health().await {
+                    Ok(health) => !is_stalled(&health, self.config.max_event_age, started_at),
+                    Err(e) => {
+                        // The wallet storage answered nothing, the event loop is checked on the next ping
+                        warn!("Couldn't check the health for the systemd watchdog: {}", e);
+                        true
+                    }
+                };
+
+                if !healthy {
+                    if !std::mem::replace(&mut stalled, true) {
+                        error!("The event loop is stalled, the systemd watchdog isn't pinged anymore");
+                    }
+                    continue;
+                }
+
+                stalled = false;
+                send("WATCHDOG=1");
+            }
+        });
+    }
+}
@@ -1,16 +1,17 @@
 use teloxide::{
     Bot,
-    payloads::{SendMessage, SendMessageSetters},
+    payloads::{EditMessageText, EditMessageTextSetters, SendMessage, SendMessageSetters},
     prelude::Requester,
     requests::JsonRequest,
-    types::{ChatId, ParseMode, ThreadId}
+    types::{InlineKeyboardMarkup, MessageId, ParseMode, Recipient, ThreadId}
 };
 
 pub struct TelegramMessage<'a> {
     title: Option<String>,
     lines: Vec<String>,
+    buttons: Option<InlineKeyboardMarkup>,
     bot: &'a Bot,
-    chat_id: ChatId,
+    chat_id: Recipient,
     thread_id: Option<ThreadId>
 }
 
@@ -36,15 +37,46 @@ impl Into<String> for InlineCode<'_> {
     }
 }
 
+// Text hidden until the reader clicks on it
+pub struct Spoiler<'a> {
+    text: &'a str
+}
+
+impl<'a> Spoiler<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Spoiler { text }
+    }
+}
+
+impl ToString for Spoiler<'_> {
+    fn to_string(&self) -> String {
+        format!("<tg-spoiler>{}</tg-spoiler>", self.text)
+    }
+}
+
+impl Into<String> for Spoiler<'_> {
+    fn into(self) -> String {
+        self.to_string()
+    }
+}
+
+// Escape a user provided text so it can't break the HTML formatting
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
 const NEW_LINE: &str = "\n";
 
 impl<'a> TelegramMessage<'a> {
-    pub fn new(bot: &'a Bot, chat_id: ChatId, thread_id: Option<ThreadId>) -> Self {
+    pub fn new<C: Into<Recipient>>(bot: &'a Bot, chat_id: C, thread_id: Option<ThreadId>) -> Self {
         TelegramMessage {
             title: None,
             lines: Vec::new(),
+            buttons: None,
             bot,
-            chat_id,
+            chat_id: chat_id.into(),
             thread_id
         }
     }
@@ -59,6 +91,16 @@ impl<'a> TelegramMessage<'a> {
         self
     }
 
+    pub fn line<S: Into<String>>(&mut self, text: S) -> &mut Self {
+        self.lines.push(text.into());
+        self
+    }
+
+    pub fn buttons(&mut self, buttons: InlineKeyboardMarkup) -> &mut Self {
+        self.buttons = Some(buttons);
+        self
+    }
+
     pub fn to_string(&self) -> String {
         let mut buf = String::new();
         if let Some(title) = &self.title {
@@ -79,11 +121,25 @@ impl<'a> TelegramMessage<'a> {
     }
 
     pub fn send(&self) -> JsonRequest<SendMessage> {
-        let mut msg = self.bot.send_message(self.chat_id, self.to_string());
+        let mut msg = self.bot.send_message(self.chat_id.clone(), self.to_string());
         if let Some(thread_id) = self.thread_id {
             msg = msg.message_thread_id(thread_id);
         }
-    
+
+        if let Some(buttons) = &self.buttons {
+            msg = msg.reply_markup(buttons.clone());
+        }
+
+        msg.parse_mode(ParseMode::Html)
+    }
+
+    // Replace the content of a message previously sent in the same chat
+    pub fn edit(&self, message_id: MessageId) -> JsonRequest<EditMessageText> {
+        let mut msg = self.bot.edit_message_text(self.chat_id.clone(), message_id, self.to_string());
+        if let Some(buttons) = &self.buttons {
+            msg = msg.reply_markup(buttons.clone());
+        }
+
         msg.parse_mode(ParseMode::Html)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,199 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant}
+};
+
+use log::{debug, warn};
+use teloxide::{
+    types::{ChatId, ThreadId},
+    Bot,
+    RequestError
+};
+
+use crate::{
+    api_errors::{ApiErrorKind, ApiErrorMonitor, Platform},
+    telegram_message::TelegramMessage
+};
+
+// Minimum delay between two messages in the same chat
+// Telegram allows around 20 messages per minute in a group
+const MIN_INTERVAL: Duration = Duration::from_secs(3);
+// Maximum delay between two messages in the same chat
+const MAX_INTERVAL: Duration = Duration::from_secs(5 * 60);
+// Minimum delay between two messages in the same chat while the Telegram errors spike
+const BACKOFF_INTERVAL: Duration = Duration::from_secs(30);
+// How often the pending notifications are checked
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+// Maximum notifications merged in a single summary
+// to stay below the message size limit
+const MAX_BATCH_SIZE: usize = 25;
+
+// A notification waiting to be sent
+struct Notification {
+    title: String,
+    line: String
+}
+
+// Throttling state of a chat
+struct ChatState {
+    // Notifications waiting for the next summary
+    pending: VecDeque<Notification>,
+    // Current delay between two messages, raised when rate limited
+    interval: Duration,
+    // Nothing is sent in the chat before this instant
+    next_send: Instant
+}
+
+impl ChatState {
+    fn new() -> Self {
+        Self {
+            pending: VecDeque::new(),
+            interval: MIN_INTERVAL,
+            next_send: Instant::now()
+        }
+    }
+
+    // Message was delivered, slowly go back to the minimum delay
+    fn on_sent(&mut self, min_interval: Duration) {
+        self.interval = (self.interval / 2).max(min_interval);
+        self.next_send = Instant::now() + self.interval;
+    }
+
+    // Telegram asked us to wait, back off for this chat
+    fn on_rate_limited(&mut self, retry_after: Duration) {
+        self.interval = (self.interval * 2).max(retry_after).min(MAX_INTERVAL);
+        self.next_send = Instant::now() + retry_after.max(self.interval);
+    }
+}
+
+// Send notifications in busy Telegram groups
+// When a chat is in slow mode or the bot is rate limited,
+// notifications are merged into a periodic summary instead of failing
+// While the Telegram errors spike, every chat only gets a summary from time to time
+pub struct TelegramNotifier {
+    bot: Bot,
+    chats: Mutex<HashMap<(ChatId, Option<ThreadId>), ChatState>>,
+    api_errors: Arc<ApiErrorMonitor>
+}
+
+impl TelegramNotifier {
+    pub fn new(bot: Bot, api_errors: Arc<ApiErrorMonitor>) -> Arc<Self> {
+        let notifier = Arc::new(Self {
+            bot,
+            chats: Mutex::new(HashMap::new()),
+            api_errors
+        });
+
+        let flusher = Arc::clone(&notifier);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(FLUSH_INTERVAL).await;
+                flusher.flush().await;
+            }
+        });
+
+        notifier
+    }
+
+    // Send a notification in a chat, or queue it if the chat is throttled
+    pub async fn notify(&self, chat_id: ChatId, thread_id: Option<ThreadId>, title: &str, line: String) {
+        // While backing off, notifications only go out in the summaries
+        let backing_off = self.api_errors.is_backing_off(Platform::Telegram);
+        {
+            let mut chats = self.chats.lock().expect("notifier lock");
+            let state = chats.entry((chat_id, thread_id)).or_insert_with(ChatState::new);
+            if backing_off || !state.pending.is_empty() || Instant::now() < state.next_send {
+                debug!("Chat {} is throttled, queueing notification", chat_id);
+                state.pending.push_back(Notification { title: title.to_string(), line });
+                return;
+            }
+
+            // Reserve the slot so concurrent notifications are queued
+            state.next_send = Instant::now() + state.interval;
+        }
+
+        let res = TelegramMessage::new(&self.bot, chat_id, thread_id)
+            .title(title)
+            .line(line.clone())
+            .send().await;
+
+        let mut chats = self.chats.lock().expect("notifier lock");
+        let state = chats.entry((chat_id, thread_id)).or_insert_with(ChatState::new);
+        match res {
+            Ok(_) => state.on_sent(MIN_INTERVAL),
+            Err(e) => {
+                self.api_errors.record(Platform::Telegram, ApiErrorKind::from_telegram(&e));
+                if let RequestError::RetryAfter(seconds) = e {
+                    debug!("Rate limited in chat {} for {:?}", chat_id, seconds.duration());
+                    state.on_rate_limited(seconds.duration());
+                    state.pending.push_front(Notification { title: title.to_string(), line });
+                } else {
+                    warn!("Couldn't send notification in chat {}: {}", chat_id, e);
+                }
+            }
+        }
+    }
+
+    // Count the notifications waiting for their chat to be writable again
+    pub fn pending_count(&self) -> usize {
+        self.chats.lock().expect("notifier lock")
+            .values()
+            .map(|state| state.pending.len())
+            .sum()
+    }
+
+    // Send a summary in every chat that has pending notifications and can be written to
+    async fn flush(&self) {
+        let min_interval = if self.api_errors.is_backing_off(Platform::Telegram) {
+            BACKOFF_INTERVAL
+        } else {
+            MIN_INTERVAL
+        };
+
+        let batches: Vec<_> = {
+            let now = Instant::now();
+            let mut chats = self.chats.lock().expect("notifier lock");
+            // Forget the chats that are idle
+            chats.retain(|_, state| !state.pending.is_empty() || state.next_send > now);
+            chats.iter_mut()
+                .filter(|(_, state)| !state.pending.is_empty() && state.next_send <= now)
+                .map(|(key, state)| {
+                    state.next_send = now + state.interval.max(min_interval);
+                    let count = state.pending.len().min(MAX_BATCH_SIZE);
+                    (*key, state.pending.drain(..count).collect::<Vec<_>>())
+                })
+                .collect()
+        };
+
+        for ((chat_id, thread_id), batch) in batches {
+            let mut message = TelegramMessage::new(&self.bot, chat_id, thread_id);
+            message.title("Summary");
+            for notification in batch.iter() {
+                message.field(&notification.title, notification.line.clone(), true);
+            }
+
+            let res = message.send().await;
+            let mut chats = self.chats.lock().expect("notifier lock");
+            let state = chats.entry((chat_id, thread_id)).or_insert_with(ChatState::new);
+            match res {
+                Ok(_) => {
+                    debug!("Sent a summary of {} notifications in chat {}", batch.len(), chat_id);
+                    state.on_sent(min_interval);
+                },
+                Err(e) => {
+                    self.api_errors.record(Platform::Telegram, ApiErrorKind::from_telegram(&e));
+                    if let RequestError::RetryAfter(seconds) = e {
+                        state.on_rate_limited(seconds.duration());
+                        // Keep the original order for the next summary
+                        for notification in batch.into_iter().rev() {
+                            state.pending.push_front(notification);
+                        }
+                    } else {
+                        warn!("Couldn't send summary of {} notifications in chat {}: {}", batch.len(), chat_id, e);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context as _, Error, Result};
+use log::{debug, error, info, warn};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    sync::mpsc::{self, UnboundedReceiver},
+    task::JoinHandle
+};
+
+use crate::{
+    command_amount_args,
+    parse_asset_amount,
+    parse_withdraw_amount,
+    platform::{self, ChatPlatform, Reply, TwitchPlatform},
+    service::{UserApplication, WalletService},
+    twitch_api::TwitchApi,
+    twitch_irc::{self, ChatMessage, IrcLine, TwitchChat},
+    DEPOSITS_PAUSED_MESSAGE,
+    DEPOSITS_PAUSED_TITLE
+};
+
+// Commands answered in the chat, !help is left to the other bots of the channel
+const TWITCH_COMMANDS: [(&str, &str); 6] = [
+    ("!tiphelp", "Show the available commands"),
+    ("!balance", "Whisper your balance"),
+    ("!deposit", "Whisper your deposit address"),
+    ("!tip [@user] <amount> [asset]", "Tip a viewer, or the streamer if no one is mentioned"),
+    ("!withdraw <address|label> <amount> [asset]", "Withdraw to an address"),
+    ("!reminders [on|off]", "Enable or disable the reminders of your unused balance")
+];
+
+// Scopes the token needs to read and answer the chat, then whisper the notifications
+const TWITCH_SCOPES: [&str; 3] = ["chat:read", "chat:edit", "user:manage:whispers"];
+
+// Wait before connecting again once the chat couldn't be reached
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct TwitchConfig {
+    // Client ID of the application the token was created for
+    pub client_id: String,
+    // User access token of the bot account
+    pub token: String,
+    // Logins of the streamers whose chat the bot joins
+    pub channels: Vec<String>
+}
+
+pub struct TwitchBot {
+    api: TwitchApi,
+    login: String,
+    channels: Vec<String>
+}
+
+impl TwitchBot {
+    // Validate the token at startup, so a wrong or expired one stops the bot like a wrong Discord token
+    pub async fn login(config: TwitchConfig) -> Result<Self> {
+        let (api, account) = TwitchApi::validate(config.client_id, config.token).await
+            .context("Couldn't validate the Twitch token, check --twitch-token")?;
+
+        for scope in TWITCH_SCOPES {
+            if !account.scopes.iter().any(|granted| granted == scope) {
+                bail!("The Twitch token lacks the {} scope, check --twitch-token", scope);
+            }
+        }
+
+        info!("Logged in to Twitch as {}", account.login);
+        Ok(Self {
+            api,
+            login: account.login,
+            channels: config.channels.iter()
+                .map(|channel| channel.trim().trim_start_matches('#').to_lowercase())
+                .filter(|channel| !channel.is_empty())
+                .collect()
+        })
+    }
+
+    // Used by the service to send the whispers
+    pub fn api(&self) -> TwitchApi {
+        self.api.clone()
+    }
+
+    // Answer the commands of the joined channels, reconnecting whenever the chat drops
+    pub fn start(self, service: WalletService) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            // Like Telegram, only the leader answers the commands
+            while !service.is_leader() {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+
+            let (sender, mut outgoing) = mpsc::unbounded_channel();
+            let chat = TwitchChat::new(sender);
+            loop {
+                match self.run(&service, &chat, &mut outgoing).await {
+                    Ok(()) => info!("Twitch asked the bot to reconnect to the chat"),
+                    Err(e) => {
+                        warn!("The Twitch chat connection stopped: {:#}", e);
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        })
+    }
+
+    // Until the connection drops or Twitch asks the bot to reconnect
+    async fn run(&self, service: &WalletService, chat: &TwitchChat, outgoing: &mut UnboundedReceiver<String>) -> Result<()> {
+        let stream = twitch_irc::connect().await?;
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+
+        let channels = self.channels.iter()
+            .map(|channel| format!("#{}", channel))
+            .collect::<Vec<_>>()
+            .join(",");
+        let login = format!("CAP REQ :twitch.tv/tags twitch.tv/commands\r\nPASS oauth:{}\r\nNICK {}\r\nJOIN {}\r\n", self.api.token(), self.login, channels);
+        writer.write_all(login.as_bytes()).await?;
+        info!("Joining the Twitch channels {}", channels);
+
+        loop {
+            tokio::select! {
+                line = lines.next_line() => {
+                    let Some(line) = line? else {
+                        bail!("Twitch closed the connection");
+                    };
+                    let Some(line) = IrcLine::parse(&line) else {
+                        continue;
+                    };
+
+                    match line.command {
+                        "PING" => writer.write_all(format!("PONG :{}\r\n", line.params.last().unwrap_or(&"tmi.twitch.tv")).as_bytes()).await?,
+                        "RECONNECT" => return Ok(()),
+                        "NOTICE" if line.params.last().is_some_and(|text| text.contains("authentication failed")) => bail!("Twitch refused the token"),
+                        _ => if let Some(message) = ChatMessage::from_line(&line) {
+                            let service = service.clone();
+                            let api = self.api.clone();
+                            let chat = chat.clone();
+                            tokio::spawn(async move {
+                                if let Err(e) = handle_command(&service, &api, &chat, &message).await {
+                                    service.api_errors().record_error(&e);
+                                    error!("Error while answering the Twitch command of {}: {:?}", message.login, e);
+                                }
+                            });
+                        }
+                    }
+                },
+                Some(line) = outgoing.recv() => writer.write_all(line.as_bytes()).await?
+            }
+        }
+    }
+}
+
+async fn handle_command(service: &WalletService, api: &TwitchApi, chat: &TwitchChat, message: &ChatMessage) -> Result<(), Error> {
+    let mut args = message.text.split_whitespace();
+    let Some(command) = args.next().filter(|command| command.starts_with('!')).map(str::to_lowercase) else {
+        return Ok(());
+    };
+
+    if !TWITCH_COMMANDS.iter().any(|(usage, _)| usage.split(' ').next() == Some(command.as_str())) {
+        return Ok(());
+    }
+
+    let user = UserApplication::Twitch(message.user_id);
+    let platform = TwitchPlatform::new(api, chat, message);
+    if let Some(limited) = service.check_rate_limit(&user, &[], &command).await {
+        // Answer a flood only once, in a whisper
+        if !limited.notified {
+            platform.send(Reply::error("Rate limit", "Please wait", limited)).await?;
+        }
+        return Ok(());
+    }
+
+    if let Err(e) = service.record_user_activity(&user).await {
+        warn!("Couldn't record activity of {}: {}", message.login, e);
+    }
+
+    match command.as_str() {
+        // A single line, the chat has no formatting
+        "!tiphelp" => {
+            let usages = TWITCH_COMMANDS.iter()
+                .map(|(usage, _)| *usage)
+                .collect::<Vec<_>>()
+                .join(", ");
+            platform.send(Reply::new("XELIS Tip Bot").field("Commands", usages, false)).await
+        },
+        "!balance" => platform::balance(service, &platform).await,
+        "!deposit" => {
+            let address = service.get_address_for_user(&user);
+            let mut reply = Reply::new("Deposit")
+                .description("Please do not send any other coins than XELIS to this address")
+                .code_field("Your deposit address is", address.to_string());
+
+            if service.are_deposits_paused().await {
+                reply = reply.field(DEPOSITS_PAUSED_TITLE, DEPOSITS_PAUSED_MESSAGE, false);
+            }
+
+            platform.send(reply.private()).await
+        },
+        "!tip" => {
+            // Without a mention, the streamer of the channel is tipped
+            let (to, amount) = match args.next() {
+                Some(mention) if mention.starts_with('@') => (mention.trim_start_matches('@').to_lowercase(), args.next()),
+                amount => (message.channel.clone(), amount)
+            };
+
+            let Some(amount) = amount else {
+                return platform.send(Reply::error("Tip", "Usage", "!tip [@user] <amount> [asset]")).await;
+            };
+
+            let recipient = if to == message.channel {
+                message.room_id
+            } else {
+                match api.get_user_id(&to).await? {
+                    Some(id) => id,
+                    None => return platform.send(Reply::error("Tip", "An error occured while tipping", format!("There is no Twitch user named {}", to))).await
+                }
+            };
+
+            let amount = match parse_asset_amount(service, &user, args.next(), amount).await {
+                Ok(amount) if amount.rounded || amount.conversion.is_some() => {
+                    let command = format!("!tip @{} {}", to, command_amount_args(&amount.amount));
+                    return platform.send(platform::confirm_amount("Tip", &amount.amount, &command)).await;
+                },
+                Ok(amount) => amount.amount,
+                Err(e) => return platform.send(Reply::error("Tip", "An error occured while tipping", e)).await
+            };
+
+            debug!("{} tips {} in #{}", message.login, to, message.channel);
+            platform::tip(service, &platform, &UserApplication::Twitch(recipient), &format!("@{}", to), &amount, None).await
+        },
+        "!withdraw" => {
+            let (Some(address), Some(amount)) = (args.next(), args.next()) else {
+                return platform.send(Reply::error("Withdraw", "Usage", "!withdraw <address|label> <amount> [asset]")).await;
+            };
+
+            let to = match service.resolve_address(&user, address).await {
+                Ok(to) => to,
+                Err(e) => return platform.send(Reply::error("Withdraw", "An error occured while withdrawing", e)).await
+            };
+
+            let amount = match parse_withdraw_amount(service, &user, &to, args.next(), amount).await {
+                Ok(amount) if amount.rounded || amount.conversion.is_some() => {
+                    let command = format!("!withdraw {} {}", address, command_amount_args(&amount.amount));
+                    return platform.send(platform::confirm_amount("Withdraw", &amount.amount, &command)).await;
+                },
+                Ok(amount) => amount.amount,
+                Err(e) => return platform.send(Reply::error("Withdraw", "An error occured while withdrawing", e)).await
+            };
+
+            platform::withdraw(service, &platform, to, &amount, None, None).await
+        },
+        "!reminders" => reminders(service, &platform, &user, args.next()).await,
+        _ => Ok(())
+    }
+}
+
+async fn reminders(service: &WalletService, platform: &TwitchPlatform<'_>, user: &UserApplication, enabled: Option<&str>) -> Result<(), Error> {
+    match enabled.map(str::to_lowercase).as_deref() {
+        Some("on") => service.set_reminders_enabled(user, true).await?,
+        Some("off") => service.set_reminders_enabled(user, false).await?,
+        None => {},
+        Some(_) => return platform.send(Reply::error("Reminders", "Usage", "!reminders [on|off]")).await
+    }
+
+    let description = if service.are_reminders_enabled(user).await {
+        "You will be reminded when your balance is left unused for a long time"
+    } else {
+        "You won't be reminded of your unused balance"
+    };
+
+    platform.send(Reply::new("Reminders").description(description).private()).await
+}
@@ -0,0 +1,106 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error;
+
+const TWITCH_API_URL: &str = "https://api.twitch.tv/helix";
+const TWITCH_VALIDATE_URL: &str = "https://id.twitch.tv/oauth2/validate";
+
+#[derive(Debug, Error)]
+pub enum TwitchApiError {
+    #[error("Twitch couldn't be reached: {0}")]
+    Network(#[from] reqwest::Error),
+    // Twitch explains the error in the body, such as a recipient that blocks whispers
+    #[error("Twitch answered with the HTTP status {0}: {1}")]
+    Status(StatusCode, String)
+}
+
+// Account of the token, as told by Twitch when validating it
+#[derive(Debug, Clone, Deserialize)]
+pub struct TwitchAccount {
+    pub login: String,
+    pub user_id: String,
+    // Scopes granted to the token, chat:read, chat:edit and user:manage:whispers are needed
+    #[serde(default)]
+    pub scopes: Vec<String>
+}
+
+#[derive(Deserialize)]
+struct TwitchUser {
+    id: String
+}
+
+#[derive(Deserialize)]
+struct TwitchUsers {
+    data: Vec<TwitchUser>
+}
+
+// Client of the Helix API, authenticated as the bot account
+#[derive(Clone)]
+pub struct TwitchApi {
+    client: reqwest::Client,
+    client_id: String,
+    token: String,
+    // Twitch user ID of the bot account, the sender of the whispers
+    bot_id: String
+}
+
+impl TwitchApi {
+    // Validate the token and learn the account it belongs to
+    pub async fn validate(client_id: String, token: String) -> Result<(Self, TwitchAccount), TwitchApiError> {
+        let client = reqwest::Client::new();
+        let response = client.get(TWITCH_VALIDATE_URL)
+            .header("Authorization", format!("OAuth {}", token))
+            .send().await?;
+        let account: TwitchAccount = Self::check(response).await?.json().await?;
+
+        let api = Self {
+            client,
+            client_id,
+            token,
+            bot_id: account.user_id.clone()
+        };
+
+        Ok((api, account))
+    }
+
+    // Tokens are given to IRC as oauth:<token>
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    async fn check(response: reqwest::Response) -> Result<reqwest::Response, TwitchApiError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        Err(TwitchApiError::Status(status, body))
+    }
+
+    // Twitch user ID of a login, None if no account has it
+    pub async fn get_user_id(&self, login: &str) -> Result<Option<u64>, TwitchApiError> {
+        let response = self.client.get(format!("{}/users", TWITCH_API_URL))
+            .query(&[("login", login)])
+            .bearer_auth(&self.token)
+            .header("Client-Id", &self.client_id)
+            .send().await?;
+        let users: TwitchUsers = Self::check(response).await?.json().await?;
+
+        Ok(users.data.first().and_then(|user| user.id.parse().ok()))
+    }
+
+    // Whispers are limited to 500 characters for a user the bot never whispered
+    pub async fn whisper(&self, to: u64, message: &str) -> Result<(), TwitchApiError> {
+        let response = self.client.post(format!("{}/whispers", TWITCH_API_URL))
+            .query(&[("from_user_id", self.bot_id.clone()), ("to_user_id", to.to_string())])
+            .bearer_auth(&self.token)
+            .header("Client-Id", &self.client_id)
+            .json(&json!({ "message": message }))
+            .send().await?;
+        Self::check(response).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result};
+use log::warn;
+use tokio::{net::TcpStream, sync::mpsc::UnboundedSender};
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{pki_types::ServerName, ClientConfig, RootCertStore},
+    TlsConnector
+};
+
+const TWITCH_IRC_HOST: &str = "irc.chat.twitch.tv";
+const TWITCH_IRC_PORT: u16 = 6697;
+
+// Open a TLS connection to the chat servers, the login is sent by the caller
+pub async fn connect() -> Result<TlsStream<TcpStream>> {
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let stream = TcpStream::connect((TWITCH_IRC_HOST, TWITCH_IRC_PORT)).await
+        .context("Couldn't reach the Twitch chat")?;
+    let domain = ServerName::try_from(TWITCH_IRC_HOST)?;
+    let stream = TlsConnector::from(Arc::new(config)).connect(domain, stream).await
+        .context("Couldn't open a TLS connection to the Twitch chat")?;
+
+    Ok(stream)
+}
+
+// Line received from the chat servers, such as
+// @id=...;user-id=123 :alice!alice@alice.tmi.twitch.tv PRIVMSG #bob :!tip 1
+pub struct IrcLine<'a> {
+    tags: &'a str,
+    prefix: Option<&'a str>,
+    pub command: &'a str,
+    // The trailing parameter, after the ':', is the last one
+    pub params: Vec<&'a str>
+}
+
+impl<'a> IrcLine<'a> {
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let mut rest = line;
+        let mut tags = "";
+        if let Some(tagged) = rest.strip_prefix('@') {
+            (tags, rest) = tagged.split_once(' ')?;
+        }
+
+        let mut prefix = None;
+        if let Some(prefixed) = rest.strip_prefix(':') {
+            let (value, remaining) = prefixed.split_once(' ')?;
+            prefix = Some(value);
+            rest = remaining;
+        }
+
+        let (middle, trailing) = match rest.split_once(" :") {
+            Some((middle, trailing)) => (middle, Some(trailing)),
+            None => (rest, None)
+        };
+
+        let mut params = middle.split(' ').filter(|param| !param.is_empty());
+        let command = params.next()?;
+        let mut params = params.collect::<Vec<_>>();
+        params.extend(trailing);
+
+        Some(Self { tags, prefix, command, params })
+    }
+
+    // Value of a tag, with its escaped characters restored
+    pub fn tag(&self, name: &str) -> Option<String> {
+        self.tags.split(';')
+            .filter_map(|tag| tag.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| unescape_tag(value))
+    }
+
+    // Login of the sender, the prefix is login!login@login.tmi.twitch.tv
+    pub fn login(&self) -> Option<&'a str> {
+        self.prefix.and_then(|prefix| prefix.split('!').next())
+    }
+}
+
+fn unescape_tag(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+
+    unescaped
+}
+
+// Message of a viewer in a channel the bot joined
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub id: String,
+    // Login of the streamer, without the #
+    pub channel: String,
+    // Twitch user ID of the streamer
+    pub room_id: u64,
+    pub user_id: u64,
+    pub login: String,
+    pub text: String
+}
+
+impl ChatMessage {
+    // None if the line isn't a chat message or lacks the tags of the sender
+    pub fn from_line(line: &IrcLine) -> Option<Self> {
+        if line.command != "PRIVMSG" {
+            return None;
+        }
+
+        Some(Self {
+            id: line.tag("id")?,
+            channel: line.params.first()?.strip_prefix('#')?.to_string(),
+            room_id: line.tag("room-id")?.parse().ok()?,
+            user_id: line.tag("user-id")?.parse().ok()?,
+            login: line.login()?.to_string(),
+            text: line.params.get(1)?.to_string()
+        })
+    }
+}
+
+// Lines sent to the chat by the connection task, the replies wait there while it reconnects
+#[derive(Clone)]
+pub struct TwitchChat {
+    sender: UnboundedSender<String>
+}
+
+impl TwitchChat {
+    pub fn new(sender: UnboundedSender<String>) -> Self {
+        Self { sender }
+    }
+
+    // Answer a message in its reply thread, the text must fit on one line
+    pub fn reply(&self, message: &ChatMessage, text: &str) {
+        let line = format!("@reply-parent-msg-id={} PRIVMSG #{} :{}\r\n", message.id, message.channel, text);
+        if self.sender.send(line).is_err() {
+            warn!("Couldn't answer {} in #{}, the Twitch chat is closed", message.login, message.channel);
+        }
+    }
+}
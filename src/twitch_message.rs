@@ -0,0 +1,41 @@
+// Twitch chat lines and whispers are limited to 500 characters
+const MAX_MESSAGE_LENGTH: usize = 500;
+
+// Message sent on Twitch, on a single line as the chat has no formatting
+#[derive(Default)]
+pub struct TwitchMessage {
+    parts: Vec<String>
+}
+
+impl TwitchMessage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, text: &str) -> Self {
+        self.parts.push(text.to_string());
+        self
+    }
+
+    pub fn line(mut self, text: &str) -> Self {
+        self.parts.push(text.to_string());
+        self
+    }
+
+    pub fn field(mut self, name: &str, value: &str) -> Self {
+        self.parts.push(format!("{}: {}", name, value));
+        self
+    }
+
+    // Line breaks would end the IRC message, and a leading / or . would run a chat command
+    pub fn into_text(self) -> String {
+        let text = self.parts.join(" | ")
+            .replace(['\r', '\n'], " ");
+        let text = text.trim_start_matches(['/', '.']);
+
+        match text.char_indices().nth(MAX_MESSAGE_LENGTH) {
+            Some((end, _)) => text[..end].to_string(),
+            None => text.to_string()
+        }
+    }
+}